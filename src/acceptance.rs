@@ -0,0 +1,97 @@
+//! The decision to accept or reject a proposal
+
+use rand::Rng;
+use rand::distributions::{IndependentSample, Range};
+
+/// Decides whether a walker moves to its proposed position
+///
+/// The sampler computes the log acceptance ratio of each proposal, the change in log posterior
+/// probability plus the log factor of the move, and asks the rule whether to accept it. Install
+/// a rule with
+/// [`EnsembleSampler::set_acceptance_rule`](struct.EnsembleSampler.html#method.set_acceptance_rule)
+/// to experiment with alternatives to [`Metropolis`](struct.Metropolis.html) while keeping the
+/// moves, storage and diagnostics of the sampler.
+///
+/// A rule which accepts with probability `a(r)` for a ratio `r` leaves the posterior invariant
+/// when `a(r) = r a(1 / r)`, as for both rules provided here. Rules which break this relation,
+/// e.g. to flatten the posterior, sample a different distribution. With
+/// [approximations](trait.Prob.html#method.napproximations), every stage of delayed acceptance
+/// uses the rule.
+pub trait AcceptanceRule: Send {
+    /// Short name of the rule
+    fn name(&self) -> &str;
+
+    /// Whether to accept a proposal with log acceptance ratio `ln_ratio`
+    ///
+    /// `ln_ratio` is `-inf` for proposals outside the support, which must be rejected, and may
+    /// be `+inf` for walkers leaving a position outside it.
+    fn accept(&mut self, ln_ratio: f64, rng: &mut dyn Rng) -> bool;
+}
+
+/// The Metropolis-Hastings rule, accepting with probability `min(1, r)`
+///
+/// The default, and the most efficient rule for a given proposal (Peskun 1973).
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Metropolis;
+
+impl AcceptanceRule for Metropolis {
+    fn name(&self) -> &str {
+        "metropolis"
+    }
+
+    fn accept(&mut self, ln_ratio: f64, mut rng: &mut dyn Rng) -> bool {
+        ln_ratio > Range::new(0f64, 1f64).ind_sample(&mut rng).ln()
+    }
+}
+
+/// Barker's rule, accepting with probability `r / (1 + r)`
+///
+/// Accepts less often than [`Metropolis`](struct.Metropolis.html), at most half of the proposals
+/// which leave the log probability unchanged, but the acceptance probability is a smooth
+/// function of the ratio.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Barker;
+
+impl AcceptanceRule for Barker {
+    fn name(&self) -> &str {
+        "barker"
+    }
+
+    fn accept(&mut self, ln_ratio: f64, mut rng: &mut dyn Rng) -> bool {
+        // ln(r / (1 + r)), without overflow
+        let ln_prob = if ln_ratio >= 0.0 {
+            -(-ln_ratio).exp().ln_1p()
+        } else {
+            ln_ratio - ln_ratio.exp().ln_1p()
+        };
+        ln_prob > Range::new(0f64, 1f64).ind_sample(&mut rng).ln()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::{SeedableRng, StdRng};
+
+    fn fraction<A: AcceptanceRule>(rule: &mut A, ln_ratio: f64) -> f64 {
+        let mut rng = StdRng::from_seed(&[3]);
+        let n = 20000;
+        (0..n).filter(|_| rule.accept(ln_ratio, &mut rng)).count() as f64 / n as f64
+    }
+
+    #[test]
+    fn test_acceptance_rules() {
+        let r = 0.25f64;
+        assert!((fraction(&mut Metropolis, r.ln()) - r).abs() < 0.01);
+        assert!((fraction(&mut Barker, r.ln()) - r / (1.0 + r)).abs() < 0.01);
+        assert!((fraction(&mut Barker, 0.0) - 0.5).abs() < 0.01);
+        assert!((fraction(&mut Barker, 1000.0) - 1.0).abs() < 1e-12);
+
+        for rule in &mut [&mut Metropolis as &mut dyn AcceptanceRule, &mut Barker] {
+            let mut rng = StdRng::from_seed(&[4]);
+            assert!(!rule.accept(-f64::INFINITY, &mut rng));
+            assert!(!rule.accept(f64::NAN, &mut rng));
+            assert!(rule.accept(f64::INFINITY, &mut rng));
+        }
+    }
+}
@@ -0,0 +1,349 @@
+//! Annealed importance sampling
+
+use std::mem;
+use std::rc::Rc;
+
+use rand::distributions::{IndependentSample, Range};
+
+use errors::{EmceeError, Result};
+use guess::Guess;
+use math;
+use moves::Move;
+use tempered::Tempered;
+use {EnsembleSampler, Prob, Step};
+
+/// Estimate the marginal likelihood (evidence) of a model by annealed importance sampling
+///
+/// The walkers start from independent draws from the prior, and are moved through a sequence
+/// of distributions `prior * likelihood^beta` with `beta` increasing from 0 to 1. At each
+/// temperature the walkers accumulate an importance weight from the change in `beta` and their
+/// likelihood, and are then updated by [`nsteps`](#structfield.nsteps) iterations of the
+/// ensemble sampler at the new temperature, using the usual
+/// [moves](#method.set_moves). The mean of the weights estimates the evidence; its
+/// uncertainty is estimated by bootstrap resampling of the walkers.
+///
+/// This is much cheaper than full nested sampling for well-behaved posteriors, but needs a
+/// schedule fine enough that consecutive distributions overlap, which
+/// [`Evidence::effective_sample_size`](struct.Evidence.html#method.effective_sample_size)
+/// helps to diagnose. Because the ensemble moves couple the walkers, their weights are not
+/// strictly independent, which the bootstrap does not account for.
+///
+/// ```rust
+/// # extern crate emcee;
+/// # extern crate rand;
+/// # use emcee::{Ais, Guess, Prob};
+/// # use rand::distributions::{IndependentSample, Range};
+/// # fn main() {
+/// struct Model;
+///
+/// impl Prob for Model {
+///     fn lnlike(&self, params: &Guess) -> f64 {
+///         -0.5 * params[0] * params[0]
+///     }
+///
+///     fn lnprior(&self, params: &Guess) -> f64 {
+///         // uniform on [-5, 5]
+///         if params[0].abs() < 5.0 { -(10f64).ln() } else { -::std::f64::INFINITY }
+///     }
+/// }
+///
+/// let model = Model;
+/// let mut rng = rand::thread_rng();
+/// let prior = Range::new(-5.0, 5.0);
+/// let p0: Vec<Guess> = (0..32).map(|_| Guess::new(&[prior.ind_sample(&mut rng)])).collect();
+///
+/// let mut ais = Ais::new(32, 1, &model).unwrap();
+/// let evidence = ais.run(&p0, &Ais::<Model>::schedule(30).unwrap()).unwrap();
+/// // the exact value is ln(sqrt(2 pi) / 10)
+/// let exact = (2.0 * std::f64::consts::PI).sqrt().ln() - (10f64).ln();
+/// assert!((evidence.ln_z - exact).abs() < 0.5);
+/// # }
+/// ```
+pub struct Ais<'a, T: Prob + Sync + Send + 'a> {
+    model: &'a T,
+    nwalkers: usize,
+    dim: usize,
+    n_threads: Option<usize>,
+    seed: Option<Vec<usize>>,
+    moves: Option<Vec<(Box<dyn Move>, f64)>>,
+
+    /// The number of sampler iterations at each temperature (default 10)
+    pub nsteps: usize,
+
+    /// The number of bootstrap resamplings used to estimate the uncertainty (default 200)
+    pub nbootstrap: usize,
+}
+
+/// The result of [`Ais::run`](struct.Ais.html#method.run)
+#[derive(Debug, Clone)]
+pub struct Evidence {
+    /// The natural logarithm of the estimated evidence
+    pub ln_z: f64,
+
+    /// The bootstrap standard deviation of `ln_z`
+    pub ln_z_error: f64,
+
+    /// The final log importance weight of each walker
+    pub log_weights: Vec<f64>,
+
+    /// The final position of each walker; weighted by `log_weights` these are samples from the
+    /// posterior
+    pub pos: Vec<Guess>,
+}
+
+impl Evidence {
+    /// The effective number of walkers, `(sum w)^2 / sum w^2`
+    ///
+    /// Values much smaller than the number of walkers mean that a few walkers dominate the
+    /// estimate, and that the schedule should be made finer.
+    pub fn effective_sample_size(&self) -> f64 {
+        1.0 / math::softmax(&self.log_weights)
+            .iter()
+            .map(|w| w * w)
+            .sum::<f64>()
+    }
+}
+
+impl<'a, T: Prob + Sync + Send + 'a> Ais<'a, T> {
+    /// Create an estimator using `nwalkers` walkers
+    ///
+    /// Errors are handled by returning a [`Result`](errors/type.Result.html) which contains
+    /// [`EmceeError::InvalidInputs`](errors/enum.EmceeError.html) for the same reasons as
+    /// [`EnsembleSampler::new`](struct.EnsembleSampler.html#method.new).
+    pub fn new(nwalkers: usize, dim: usize, model: &'a T) -> Result<Self> {
+        EnsembleSampler::new(nwalkers, dim, model)?;
+        Ok(Ais {
+            model,
+            nwalkers,
+            dim,
+            n_threads: None,
+            seed: None,
+            moves: None,
+            nsteps: 10,
+            nbootstrap: 200,
+        })
+    }
+
+    /// Create an estimator whose sampler evaluates the walkers on a pool of `n_threads` threads
+    ///
+    /// Errors are handled by returning a [`Result`](errors/type.Result.html) which contains
+    /// [`EmceeError::InvalidInputs`](errors/enum.EmceeError.html) for the same reasons as
+    /// [`EnsembleSampler::new`](struct.EnsembleSampler.html#method.new).
+    pub fn threaded(nwalkers: usize, dim: usize, model: &'a T, n_threads: usize) -> Result<Self> {
+        let mut ais = Ais::new(nwalkers, dim, model)?;
+        ais.n_threads = Some(n_threads);
+        Ok(ais)
+    }
+
+    /// Seed the random number stream of the sampler and the bootstrap, see
+    /// [`EnsembleSampler::seed`](struct.EnsembleSampler.html#method.seed)
+    pub fn seed(&mut self, seed: &[usize]) {
+        self.seed = Some(seed.to_vec());
+    }
+
+    /// Replace the moves used at each temperature, see
+    /// [`EnsembleSampler::set_moves`](struct.EnsembleSampler.html#method.set_moves)
+    ///
+    /// Errors are handled by returning a [`Result`](errors/type.Result.html) which contains
+    /// [`EmceeError::InvalidInputs`](errors/enum.EmceeError.html) for the same reasons as
+    /// [`EnsembleSampler::set_moves`](struct.EnsembleSampler.html#method.set_moves).
+    pub fn set_moves(&mut self, moves: Vec<(Box<dyn Move>, f64)>) -> Result<&mut Self> {
+        let mut check = EnsembleSampler::new(self.nwalkers, self.dim, self.model)?;
+        check.set_moves(moves)?;
+        self.moves = Some(mem::take(&mut check.moves));
+        Ok(self)
+    }
+
+    /// A schedule of `ntemps` inverse temperatures from 0 to 1, `beta_k = (k / (ntemps - 1))^4`
+    ///
+    /// The power concentrates the temperatures near `beta = 0`, where the distributions change
+    /// fastest.
+    ///
+    /// Errors are handled by returning a [`Result`](errors/type.Result.html) which contains
+    /// [`EmceeError::InvalidInputs`](errors/enum.EmceeError.html) if `ntemps` is less than 2.
+    pub fn schedule(ntemps: usize) -> Result<Vec<f64>> {
+        if ntemps < 2 {
+            return Err(EmceeError::InvalidInputs(
+                "a schedule needs at least two temperatures".into(),
+            ));
+        }
+        Ok((0..ntemps)
+            .map(|k| (k as f64 / (ntemps - 1) as f64).powi(4))
+            .collect())
+    }
+
+    /// Anneal the walkers from the prior to the posterior, estimating the evidence
+    ///
+    /// `p0` must be independent draws from the prior, one per walker, and `schedule` must
+    /// increase strictly from 0 to 1, e.g. [`schedule`](#method.schedule). The likelihood and
+    /// prior of each walker are evaluated once per temperature on the calling thread, in
+    /// addition to the sampler's evaluations.
+    ///
+    /// Errors are handled by returning a [`Result`](errors/type.Result.html) which contains
+    /// [`EmceeError::InvalidInputs`](errors/enum.EmceeError.html) if `p0` does not contain one
+    /// position per walker, a position lies outside the prior, the schedule is invalid or
+    /// [`nsteps`](#structfield.nsteps) is zero, or any error from the sampler.
+    pub fn run(&mut self, p0: &[Guess], schedule: &[f64]) -> Result<Evidence> {
+        if p0.len() != self.nwalkers {
+            return Err(EmceeError::InvalidInputs(format!(
+                "expected {} initial positions, got {}",
+                self.nwalkers,
+                p0.len()
+            )));
+        }
+        if schedule.len() < 2 || schedule[0] != 0.0 || schedule[schedule.len() - 1] != 1.0
+            || schedule.windows(2).any(|pair| pair[1] <= pair[0] || pair[1].is_nan())
+        {
+            return Err(EmceeError::InvalidInputs(
+                "the schedule must increase strictly from 0 to 1".into(),
+            ));
+        }
+        if self.nsteps == 0 {
+            return Err(EmceeError::InvalidInputs(
+                "at least one step per temperature is required".into(),
+            ));
+        }
+
+        let tempered = Tempered::new(self.model);
+        let mut sampler = match self.n_threads {
+            Some(n_threads) => {
+                EnsembleSampler::threaded(self.nwalkers, self.dim, &tempered, n_threads)?
+            }
+            None => EnsembleSampler::new(self.nwalkers, self.dim, &tempered)?,
+        };
+        sampler.storechain = false;
+        if let Some(ref seed) = self.seed {
+            sampler.seed(seed);
+        }
+        let custom_moves = self.moves.take();
+        let restore = custom_moves.is_some();
+        if let Some(moves) = custom_moves {
+            sampler.set_moves(moves)?;
+        }
+
+        let result = anneal(self.model, &tempered, &mut sampler, p0, schedule, self.nsteps);
+
+        if restore {
+            sampler.reset();
+            self.moves = Some(mem::take(&mut sampler.moves));
+        }
+        let (log_weights, pos) = result?;
+
+        let ln_z = math::logmeanexp(&log_weights);
+        let range = Range::new(0, log_weights.len());
+        let estimates: Vec<f64> = (0..self.nbootstrap)
+            .map(|_| {
+                let resampled: Vec<f64> = (0..log_weights.len())
+                    .map(|_| log_weights[range.ind_sample(&mut sampler.rng)])
+                    .collect();
+                math::logmeanexp(&resampled)
+            })
+            .collect();
+        let n = estimates.len() as f64;
+        let mean = estimates.iter().sum::<f64>() / n;
+        let variance = estimates.iter().map(|x| (x - mean).powi(2)).sum::<f64>() / (n - 1.0);
+        let ln_z_error = variance.sqrt();
+
+        Ok(Evidence {
+            ln_z,
+            ln_z_error,
+            log_weights,
+            pos,
+        })
+    }
+}
+
+/// Move the walkers through the schedule, returning their log weights and final positions
+fn anneal<'a, T: Prob + Sync + Send + 'a>(
+    model: &T,
+    tempered: &Tempered<'a, T>,
+    sampler: &mut EnsembleSampler<Tempered<'a, T>>,
+    p0: &[Guess],
+    schedule: &[f64],
+    nsteps: usize,
+) -> Result<(Vec<f64>, Vec<Guess>)> {
+    let mut pos = p0.to_vec();
+    let mut log_weights = vec![0f64; pos.len()];
+    for pair in schedule.windows(2) {
+        let (previous, beta) = (pair[0], pair[1]);
+
+        let mut lnprob = Vec::with_capacity(pos.len());
+        for (guess, log_weight) in pos.iter().zip(&mut log_weights) {
+            let lnprior = model.lnprior(guess);
+            if !lnprior.is_finite() {
+                return Err(EmceeError::InvalidInputs(
+                    "the initial positions must lie within the prior".into(),
+                ));
+            }
+            let lnlike = model.lnlike(guess);
+            *log_weight += (beta - previous) * lnlike;
+            lnprob.push(lnprior + beta * lnlike);
+        }
+
+        tempered.set_beta(beta);
+        sampler.set_initial_state(Step {
+            pos: Rc::new(pos),
+            lnprob: Rc::new(lnprob),
+            iteration: 0,
+        });
+        let state = sampler.run_mcmc(&[], nsteps)?;
+        pos = state.pos.to_vec();
+    }
+    Ok((log_weights, pos))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::{SeedableRng, StdRng};
+    use testkit::BoxedGaussian;
+
+    fn prior_draws(nwalkers: usize, dim: usize, seed: usize) -> Vec<Guess> {
+        let mut rng = StdRng::from_seed(&[seed]);
+        let range = Range::new(-10.0, 10.0);
+        (0..nwalkers)
+            .map(|_| Guess {
+                values: (0..dim).map(|_| range.ind_sample(&mut rng)).collect(),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_evidence_of_gaussian() {
+        let model = BoxedGaussian::default();
+        let mut ais = Ais::new(64, 2, &model).unwrap();
+        ais.seed(&[1]);
+        let schedule = Ais::<BoxedGaussian>::schedule(40).unwrap();
+        let evidence = ais.run(&prior_draws(64, 2, 2), &schedule).unwrap();
+
+        let expected = model.ln_evidence();
+        assert!(evidence.ln_z_error > 0.0);
+        assert!(
+            (evidence.ln_z - expected).abs() < 3.0 * evidence.ln_z_error + 0.05,
+            "{} +/- {}, expected {}",
+            evidence.ln_z,
+            evidence.ln_z_error,
+            expected
+        );
+        assert!(evidence.effective_sample_size() > 16.0);
+        for guess in &evidence.pos {
+            assert!(guess.values.iter().all(|x| x.abs() < 6.0));
+        }
+    }
+
+    #[test]
+    fn test_invalid_inputs() {
+        let model = BoxedGaussian::default();
+        let mut ais = Ais::new(8, 2, &model).unwrap();
+        let p0 = prior_draws(8, 2, 3);
+        assert!(ais.run(&p0[..6], &[0.0, 1.0]).is_err());
+        assert!(ais.run(&p0, &[0.1, 1.0]).is_err());
+        assert!(ais.run(&p0, &[0.0, 0.5, 0.5, 1.0]).is_err());
+        assert!(ais.set_moves(Vec::new()).is_err());
+        assert!(Ais::<BoxedGaussian>::schedule(1).is_err());
+
+        let mut outside = p0.clone();
+        outside[0][0] = 20.0;
+        assert!(ais.run(&outside, &[0.0, 1.0]).is_err());
+    }
+}
@@ -0,0 +1,86 @@
+//! Conversions between chains and `ndarray` arrays
+//!
+//! Enabled with the `ndarray` feature. [`Chain::to_array3`](../struct.Chain.html#method.to_array3)
+//! and [`Chain::flat_array2`](../struct.Chain.html#method.flat_array2) give the samples in the
+//! shapes returned by `get_chain()` and `get_chain(flat=True)` in Python `emcee`, ready for
+//! analysis with the `ndarray` ecosystem, and [`initial_from_array`](fn.initial_from_array.html)
+//! turns a matrix of starting positions into the walkers of a run.
+//!
+//! ```rust,ignore
+//! let p0 = emcee::arrays::initial_from_array(start.view());
+//! sampler.run_mcmc(&p0, 1000)?;
+//! let samples = sampler.chain().unwrap().flat_array2();
+//! let mean = samples.mean_axis(Axis(0));
+//! ```
+
+use ndarray::{Array2, Array3, ArrayView2};
+
+use guess::Guess;
+use stores::Chain;
+
+impl Chain {
+    /// Copy the samples into an array indexed by iteration, walker and parameter
+    ///
+    /// The chain is stored in blocks of iterations rather than one contiguous buffer, so the
+    /// samples are copied rather than viewed in place.
+    pub fn to_array3(&self) -> Array3<f64> {
+        Array3::from_shape_fn(
+            (self.niterations, self.nwalkers, self.nparams),
+            |(iter, walker, param)| self.get(param, walker, iter),
+        )
+    }
+
+    /// Copy the samples into an array with one row per sample, in the order of
+    /// [`flatchain`](#method.flatchain), and one column per parameter
+    pub fn flat_array2(&self) -> Array2<f64> {
+        let nwalkers = self.nwalkers;
+        Array2::from_shape_fn(
+            (self.niterations * nwalkers, self.nparams),
+            |(sample, param)| self.get(param, sample % nwalkers, sample / nwalkers),
+        )
+    }
+}
+
+/// Create the starting positions of the walkers from a matrix with one row per walker and
+/// one column per parameter
+pub fn initial_from_array(positions: ArrayView2<f64>) -> Vec<Guess> {
+    positions
+        .outer_iter()
+        .map(|row| Guess {
+            values: row.to_vec(),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_arrays() {
+        let mut chain = Chain::new(2, 4, 300);
+        for iter in 0..300 {
+            for walker in 0..4 {
+                let base = (iter * 10 + walker) as f64;
+                chain.set_params(walker, iter, &[base, -base]);
+            }
+        }
+
+        let cube = chain.to_array3();
+        assert_eq!(cube.dim(), (300, 4, 2));
+        assert_eq!(cube[[271, 3, 1]], chain.get(1, 3, 271));
+
+        let flat = chain.flat_array2();
+        assert_eq!(flat.dim(), (1200, 2));
+        let flatchain = chain.flatchain();
+        for (i, guess) in flatchain.iter().enumerate().step_by(37) {
+            assert_eq!(flat[[i, 0]], guess[0]);
+            assert_eq!(flat[[i, 1]], guess[1]);
+        }
+
+        let start = Array2::from_shape_vec((3, 2), vec![1.0, 2.0, 3.0, 4.0, 5.0, 6.0]).unwrap();
+        let p0 = initial_from_array(start.view());
+        assert_eq!(p0.len(), 3);
+        assert_eq!(p0[1], Guess::new(&[3.0, 4.0]));
+    }
+}
@@ -0,0 +1,194 @@
+//! Arrow IPC streaming output
+//!
+//! Enabled with the `arrow` feature. [`ArrowStreamWriter`](struct.ArrowStreamWriter.html) writes
+//! samples in the Arrow IPC streaming format as they are produced, so other tools (`pyarrow`,
+//! Julia's `Arrow.jl`, DuckDB, ...) can read a run which is still in progress. Each row is one
+//! sample, with columns `iteration`, `walker`, `lnprob`, followed by one column per parameter.
+//!
+//! Batches are most efficient when large, so the writer pairs naturally with
+//! [`run_in_chunks`](../struct.EnsembleSampler.html#method.run_in_chunks):
+//!
+//! ```rust,ignore
+//! let file = std::fs::File::create("chain.arrows")?;
+//! let mut writer = ArrowStreamWriter::new(file, &["m", "c"])?;
+//! sampler.run_in_chunks(&p0, 10000, 100, |block| {
+//!     writer.write_block(&block).expect("writing block");
+//! })?;
+//! writer.finish()?;
+//! ```
+
+use std::io::Write;
+use std::sync::Arc;
+
+use arrow::array::{ArrayRef, Float64Array, UInt32Array, UInt64Array};
+use arrow::datatypes::{DataType, Field, Schema, SchemaRef};
+use arrow::error::ArrowError;
+use arrow::ipc::writer::StreamWriter;
+use arrow::record_batch::RecordBatch;
+
+use errors::{EmceeError, Result};
+use live::Block;
+use Step;
+
+impl From<ArrowError> for EmceeError {
+    fn from(e: ArrowError) -> EmceeError {
+        EmceeError::Msg(format!("arrow error: {}", e))
+    }
+}
+
+/// Writes samples as Arrow record batches to an IPC stream
+pub struct ArrowStreamWriter<W: Write> {
+    schema: SchemaRef,
+    nparams: usize,
+    writer: StreamWriter<W>,
+}
+
+impl<W: Write> ArrowStreamWriter<W> {
+    /// Start a stream, naming one column per parameter
+    ///
+    /// The schema is written immediately.
+    pub fn new(writer: W, param_names: &[&str]) -> Result<Self> {
+        let mut fields = vec![
+            Field::new("iteration", DataType::UInt64, false),
+            Field::new("walker", DataType::UInt32, false),
+            Field::new("lnprob", DataType::Float64, false),
+        ];
+        for name in param_names {
+            fields.push(Field::new(*name, DataType::Float64, false));
+        }
+
+        let schema = Arc::new(Schema::new(fields));
+        let writer = StreamWriter::try_new(writer, &schema)?;
+        Ok(ArrowStreamWriter {
+            schema,
+            nparams: param_names.len(),
+            writer,
+        })
+    }
+
+    /// Write every iteration contained in a block as a single record batch
+    pub fn write_block(&mut self, block: &Block) -> Result<()> {
+        if block.nparams() != self.nparams {
+            return Err(EmceeError::InvalidInputs(format!(
+                "expected {} parameters, the block has {}",
+                self.nparams,
+                block.nparams()
+            )));
+        }
+
+        let nrows = block.len() * block.nwalkers();
+        let mut iteration = Vec::with_capacity(nrows);
+        let mut walker = Vec::with_capacity(nrows);
+        let mut lnprob = Vec::with_capacity(nrows);
+        let mut params = vec![Vec::with_capacity(nrows); self.nparams];
+        for iter in 0..block.len() {
+            for walker_idx in 0..block.nwalkers() {
+                iteration.push((block.start + iter) as u64);
+                walker.push(walker_idx as u32);
+                lnprob.push(block.lnprob(walker_idx, iter));
+                for (param_idx, column) in params.iter_mut().enumerate() {
+                    column.push(block.get(param_idx, walker_idx, iter));
+                }
+            }
+        }
+
+        self.write_columns(iteration, walker, lnprob, params)
+    }
+
+    /// Write the walker positions of a single step as a record batch
+    pub fn write_step(&mut self, step: &Step) -> Result<()> {
+        let nwalkers = step.pos.len();
+        let mut params = vec![Vec::with_capacity(nwalkers); self.nparams];
+        for guess in step.pos.iter() {
+            if guess.values.len() != self.nparams {
+                return Err(EmceeError::InvalidInputs(format!(
+                    "expected {} parameters, the step has {}",
+                    self.nparams,
+                    guess.values.len()
+                )));
+            }
+            for (column, value) in params.iter_mut().zip(&guess.values) {
+                column.push(*value);
+            }
+        }
+
+        self.write_columns(
+            vec![step.iteration as u64; nwalkers],
+            (0..nwalkers as u32).collect(),
+            step.lnprob.to_vec(),
+            params,
+        )
+    }
+
+    /// Write the end-of-stream marker
+    pub fn finish(&mut self) -> Result<()> {
+        self.writer.finish()?;
+        Ok(())
+    }
+
+    fn write_columns(
+        &mut self,
+        iteration: Vec<u64>,
+        walker: Vec<u32>,
+        lnprob: Vec<f64>,
+        params: Vec<Vec<f64>>,
+    ) -> Result<()> {
+        let mut columns: Vec<ArrayRef> = vec![
+            Arc::new(UInt64Array::from(iteration)),
+            Arc::new(UInt32Array::from(walker)),
+            Arc::new(Float64Array::from(lnprob)),
+        ];
+        for column in params {
+            columns.push(Arc::new(Float64Array::from(column)));
+        }
+
+        let batch = RecordBatch::try_new(self.schema.clone(), columns)?;
+        self.writer.write(&batch)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use arrow::ipc::reader::StreamReader;
+    use guess::Guess;
+
+    #[test]
+    fn test_round_trip() {
+        let mut block = Block::new(5, 2, 3, 2);
+        for iter in 0..2 {
+            let pos: Vec<Guess> = (0..3)
+                .map(|walker| Guess::new(&[iter as f64, walker as f64]))
+                .collect();
+            block.push(&pos, &[-1.0, -2.0, -3.0]);
+        }
+
+        let mut buffer = Vec::new();
+        {
+            let mut writer = ArrowStreamWriter::new(&mut buffer, &["a", "b"]).unwrap();
+            writer.write_block(&block).unwrap();
+            writer.finish().unwrap();
+        }
+
+        let reader = StreamReader::try_new(buffer.as_slice(), None).unwrap();
+        let batches: Vec<RecordBatch> = reader.map(|batch| batch.unwrap()).collect();
+        assert_eq!(batches.len(), 1);
+        let batch = &batches[0];
+        assert_eq!(batch.num_rows(), 6);
+        assert_eq!(batch.num_columns(), 5);
+
+        let iteration = batch
+            .column(0)
+            .as_any()
+            .downcast_ref::<UInt64Array>()
+            .unwrap();
+        assert_eq!(iteration.value(5), 6);
+        let b = batch
+            .column(4)
+            .as_any()
+            .downcast_ref::<Float64Array>()
+            .unwrap();
+        assert_eq!(b.value(4), 1.0);
+    }
+}
@@ -0,0 +1,215 @@
+//! Autocorrelation analysis
+//!
+//! A port of the integrated autocorrelation time estimator from Python `emcee`, following
+//! [Sokal's notes](http://www.stat.unc.edu/faculty/cji/Sokal.pdf) and the automated windowing
+//! procedure with the window size chosen as the smallest `M` for which `M >= c * tau(M)`.
+
+use parallel;
+
+/// Estimate the normalised autocorrelation function of a 1D series
+///
+/// The result has the same length as `x`, and the zero-lag value is 1. If `x` has zero
+/// variance the result is all NaN.
+pub fn function_1d(x: &[f64]) -> Vec<f64> {
+    let n = x.len();
+    if n == 0 {
+        return Vec::new();
+    }
+
+    let mean = x.iter().sum::<f64>() / n as f64;
+    let size = 2 * n.next_power_of_two();
+    let mut re = vec![0f64; size];
+    let mut im = vec![0f64; size];
+    for (value, out) in x.iter().zip(re.iter_mut()) {
+        *out = value - mean;
+    }
+
+    fft(&mut re, &mut im, false);
+    for i in 0..size {
+        re[i] = re[i] * re[i] + im[i] * im[i];
+        im[i] = 0.0;
+    }
+    fft(&mut re, &mut im, true);
+
+    let norm = re[0];
+    re.truncate(n);
+    for value in &mut re {
+        *value /= norm;
+    }
+    re
+}
+
+/// Estimate the integrated autocorrelation time of one parameter
+///
+/// `walkers` contains one series per walker. The autocorrelation functions of the walkers are
+/// averaged before the automated windowing is applied with window factor `c` (Python `emcee`
+/// uses 5 by default).
+///
+/// The estimate is only reliable when the series are many times longer than the result; Python
+/// `emcee` requires at least 50 autocorrelation times.
+pub fn integrated_time<S: AsRef<[f64]>>(walkers: &[S], c: f64) -> f64 {
+    let n = walkers.first().map_or(0, |series| series.as_ref().len());
+    if n == 0 {
+        return f64::NAN;
+    }
+
+    let mut f = vec![0f64; n];
+    for series in walkers {
+        let series = series.as_ref();
+        assert_eq!(series.len(), n, "all walkers must have the same number of samples");
+        for (acc, value) in f.iter_mut().zip(function_1d(series)) {
+            *acc += value;
+        }
+    }
+    windowed_time(f, walkers.len(), c)
+}
+
+/// [`integrated_time`](fn.integrated_time.html), with the autocorrelation functions of the
+/// walkers computed on up to `nthreads` threads
+pub(crate) fn integrated_time_parallel(walkers: &[Vec<f64>], c: f64, nthreads: usize) -> f64 {
+    if nthreads <= 1 {
+        return integrated_time(walkers, c);
+    }
+    let n = walkers.first().map_or(0, |series| series.len());
+    if n == 0 {
+        return f64::NAN;
+    }
+    assert!(
+        walkers.iter().all(|series| series.len() == n),
+        "all walkers must have the same number of samples"
+    );
+
+    // Summed in walker order, as in the serial version
+    let mut f = vec![0f64; n];
+    for acf in parallel::map(walkers.len(), nthreads, |k| function_1d(&walkers[k])) {
+        for (acc, value) in f.iter_mut().zip(acf) {
+            *acc += value;
+        }
+    }
+    windowed_time(f, walkers.len(), c)
+}
+
+/// The integrated time from the sum `f` of the autocorrelation functions of `nwalkers`
+/// walkers
+fn windowed_time(f: Vec<f64>, nwalkers: usize, c: f64) -> f64 {
+    let mut taus = Vec::with_capacity(f.len());
+    let mut total = 0f64;
+    for value in f {
+        total += value / nwalkers as f64;
+        taus.push(2.0 * total - 1.0);
+    }
+
+    let window = auto_window(&taus, c);
+    taus[window]
+}
+
+fn auto_window(taus: &[f64], c: f64) -> usize {
+    taus.iter()
+        .enumerate()
+        .position(|(m, tau)| m as f64 >= c * tau)
+        .unwrap_or(taus.len() - 1)
+}
+
+/// In-place iterative radix-2 FFT; the length must be a power of two
+fn fft(re: &mut [f64], im: &mut [f64], inverse: bool) {
+    let n = re.len();
+    assert!(n.is_power_of_two());
+    assert_eq!(im.len(), n);
+
+    let mut j = 0;
+    for i in 1..n {
+        let mut bit = n >> 1;
+        while j & bit != 0 {
+            j ^= bit;
+            bit >>= 1;
+        }
+        j |= bit;
+        if i < j {
+            re.swap(i, j);
+            im.swap(i, j);
+        }
+    }
+
+    let sign = if inverse { 1.0 } else { -1.0 };
+    let mut len = 2;
+    while len <= n {
+        let angle = sign * 2.0 * ::std::f64::consts::PI / len as f64;
+        let (w_im, w_re) = angle.sin_cos();
+        for start in (0..n).step_by(len) {
+            let (mut cur_re, mut cur_im) = (1f64, 0f64);
+            for k in 0..len / 2 {
+                let a = start + k;
+                let b = a + len / 2;
+                let t_re = re[b] * cur_re - im[b] * cur_im;
+                let t_im = re[b] * cur_im + im[b] * cur_re;
+                re[b] = re[a] - t_re;
+                im[b] = im[a] - t_im;
+                re[a] += t_re;
+                im[a] += t_im;
+                let next_re = cur_re * w_re - cur_im * w_im;
+                cur_im = cur_re * w_im + cur_im * w_re;
+                cur_re = next_re;
+            }
+        }
+        len <<= 1;
+    }
+
+    if inverse {
+        for i in 0..n {
+            re[i] /= n as f64;
+            im[i] /= n as f64;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::{SeedableRng, StdRng};
+    use rand::distributions::{IndependentSample, Normal};
+
+    fn ar1(rho: f64, n: usize, rng: &mut StdRng) -> Vec<f64> {
+        let normal = Normal::new(0.0, 1.0);
+        let mut out = Vec::with_capacity(n);
+        let mut value = 0f64;
+        for _ in 0..n {
+            value = rho * value + normal.ind_sample(rng);
+            out.push(value);
+        }
+        out
+    }
+
+    #[test]
+    fn test_function_1d_matches_direct_sum() {
+        let x = [1.0, 3.0, 2.0, 5.0, 4.0, 4.5, 0.5];
+        let mean = x.iter().sum::<f64>() / x.len() as f64;
+        let direct: Vec<f64> = (0..x.len())
+            .map(|lag| {
+                (0..x.len() - lag)
+                    .map(|i| (x[i] - mean) * (x[i + lag] - mean))
+                    .sum::<f64>()
+            })
+            .collect();
+
+        let acf = function_1d(&x);
+        assert_eq!(acf.len(), x.len());
+        for (a, b) in acf.iter().zip(&direct) {
+            assert_approx_eq!(a, b / direct[0]);
+        }
+    }
+
+    #[test]
+    fn test_integrated_time_uncorrelated() {
+        let mut rng = StdRng::from_seed(&[1, 2, 3, 4]);
+        let walkers: Vec<_> = (0..8).map(|_| ar1(0.0, 2000, &mut rng)).collect();
+        assert_approx_eq!(integrated_time(&walkers, 5.0), 1.0, 0.2);
+    }
+
+    #[test]
+    fn test_integrated_time_correlated() {
+        // The exact autocorrelation time of an AR(1) process is (1 + rho) / (1 - rho)
+        let mut rng = StdRng::from_seed(&[1, 2, 3, 4]);
+        let walkers: Vec<_> = (0..16).map(|_| ar1(0.9, 5000, &mut rng)).collect();
+        assert_approx_eq!(integrated_time(&walkers, 5.0), 19.0, 3.0);
+    }
+}
@@ -0,0 +1,462 @@
+//! Models generic over the scalar type, for automatic differentiation
+//!
+//! A model which implements [`GenericProb`](trait.GenericProb.html) is written once, for any
+//! [`Scalar`](trait.Scalar.html) type. Wrapped in a [`Differentiable`](struct.Differentiable.html)
+//! it is sampled like any other [`Prob`](../trait.Prob.html), evaluated with `f64`, and its
+//! exact gradient is available from
+//! [`Differentiable::gradient`](struct.Differentiable.html#method.gradient), which evaluates the
+//! model with forward-mode [`Dual`](struct.Dual.html) numbers, so gradient-based methods need no
+//! hand-coded derivatives. Dual number types from other crates can be used by implementing
+//! [`Scalar`](trait.Scalar.html) for them.
+//!
+//! ```rust
+//! # use emcee::autodiff::{Differentiable, GenericProb, Scalar};
+//! # use emcee::{EnsembleSampler, Guess};
+//! struct Line {
+//!     x: Vec<f64>,
+//!     y: Vec<f64>,
+//! }
+//!
+//! impl GenericProb for Line {
+//!     fn lnlike<S: Scalar>(&self, params: &[S]) -> S {
+//!         let (m, c) = (params[0], params[1]);
+//!         self.x
+//!             .iter()
+//!             .zip(&self.y)
+//!             .map(|(&x, &y)| (m * x + c - y).powi(2) * -0.5)
+//!             .sum()
+//!     }
+//!
+//!     fn lnprior<S: Scalar>(&self, _params: &[S]) -> S {
+//!         S::from_f64(0.0)
+//!     }
+//! }
+//!
+//! let model = Differentiable::new(Line {
+//!     x: vec![1.0, 2.0, 3.0],
+//!     y: vec![2.0, 4.0, 6.0],
+//! });
+//! let (lnprob, gradient) = model.gradient(&Guess::new(&[2.0, 1.0]));
+//! assert_eq!(lnprob, -1.5);
+//! assert_eq!(gradient, vec![-6.0, -3.0]);
+//!
+//! let sampler = EnsembleSampler::new(8, 2, &model).unwrap();
+//! # let _ = sampler;
+//! ```
+
+use std::cmp::Ordering;
+use std::fmt::Debug;
+use std::iter::Sum;
+use std::ops::{Add, Div, Mul, Neg, Sub};
+
+use gradient::GradLnProb;
+use guess::Guess;
+use prob::Prob;
+
+/// A real number type which models can be evaluated with
+///
+/// Implemented for `f64` and [`Dual`](struct.Dual.html). Arithmetic is available between
+/// values and with `f64` on the right; use [`from_f64`](#tymethod.from_f64) for constants
+/// elsewhere.
+pub trait Scalar:
+    Copy
+    + Debug
+    + PartialOrd
+    + Send
+    + Sync
+    + Sum
+    + Add<Output = Self>
+    + Sub<Output = Self>
+    + Mul<Output = Self>
+    + Div<Output = Self>
+    + Neg<Output = Self>
+    + Add<f64, Output = Self>
+    + Sub<f64, Output = Self>
+    + Mul<f64, Output = Self>
+    + Div<f64, Output = Self>
+{
+    /// A constant
+    fn from_f64(value: f64) -> Self;
+
+    /// The real value, without any derivative information
+    fn value(&self) -> f64;
+
+    /// `e^self`
+    fn exp(self) -> Self;
+
+    /// The natural logarithm
+    fn ln(self) -> Self;
+
+    /// The square root
+    fn sqrt(self) -> Self;
+
+    /// Raise to an integer power
+    fn powi(self, n: i32) -> Self;
+
+    /// Raise to a real power
+    fn powf(self, n: f64) -> Self;
+
+    /// The sine, in radians
+    fn sin(self) -> Self;
+
+    /// The cosine, in radians
+    fn cos(self) -> Self;
+
+    /// The absolute value
+    fn abs(self) -> Self;
+}
+
+impl Scalar for f64 {
+    fn from_f64(value: f64) -> f64 {
+        value
+    }
+
+    fn value(&self) -> f64 {
+        *self
+    }
+
+    fn exp(self) -> f64 {
+        f64::exp(self)
+    }
+
+    fn ln(self) -> f64 {
+        f64::ln(self)
+    }
+
+    fn sqrt(self) -> f64 {
+        f64::sqrt(self)
+    }
+
+    fn powi(self, n: i32) -> f64 {
+        f64::powi(self, n)
+    }
+
+    fn powf(self, n: f64) -> f64 {
+        f64::powf(self, n)
+    }
+
+    fn sin(self) -> f64 {
+        f64::sin(self)
+    }
+
+    fn cos(self) -> f64 {
+        f64::cos(self)
+    }
+
+    fn abs(self) -> f64 {
+        f64::abs(self)
+    }
+}
+
+/// A forward-mode dual number `re + eps * ε` with `ε² = 0`
+///
+/// Evaluating a function with `eps = 1` in one input and 0 in the others gives the partial
+/// derivative with respect to that input in the `eps` part of the result. Comparisons only
+/// look at the real part.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Dual {
+    /// The real part
+    pub re: f64,
+
+    /// The derivative part
+    pub eps: f64,
+}
+
+impl Dual {
+    /// Create a dual number
+    pub fn new(re: f64, eps: f64) -> Dual {
+        Dual { re, eps }
+    }
+
+    /// Apply a function with value `f` and derivative `df` at the real part, by the chain rule
+    fn chain(self, f: f64, df: f64) -> Dual {
+        Dual::new(f, df * self.eps)
+    }
+}
+
+impl PartialOrd for Dual {
+    fn partial_cmp(&self, other: &Dual) -> Option<Ordering> {
+        self.re.partial_cmp(&other.re)
+    }
+}
+
+impl Add for Dual {
+    type Output = Dual;
+
+    fn add(self, other: Dual) -> Dual {
+        Dual::new(self.re + other.re, self.eps + other.eps)
+    }
+}
+
+impl Sub for Dual {
+    type Output = Dual;
+
+    fn sub(self, other: Dual) -> Dual {
+        Dual::new(self.re - other.re, self.eps - other.eps)
+    }
+}
+
+impl Mul for Dual {
+    type Output = Dual;
+
+    fn mul(self, other: Dual) -> Dual {
+        Dual::new(self.re * other.re, self.eps * other.re + self.re * other.eps)
+    }
+}
+
+impl Div for Dual {
+    type Output = Dual;
+
+    fn div(self, other: Dual) -> Dual {
+        Dual::new(
+            self.re / other.re,
+            (self.eps * other.re - self.re * other.eps) / (other.re * other.re),
+        )
+    }
+}
+
+impl Neg for Dual {
+    type Output = Dual;
+
+    fn neg(self) -> Dual {
+        Dual::new(-self.re, -self.eps)
+    }
+}
+
+impl Add<f64> for Dual {
+    type Output = Dual;
+
+    fn add(self, other: f64) -> Dual {
+        Dual::new(self.re + other, self.eps)
+    }
+}
+
+impl Sub<f64> for Dual {
+    type Output = Dual;
+
+    fn sub(self, other: f64) -> Dual {
+        Dual::new(self.re - other, self.eps)
+    }
+}
+
+impl Mul<f64> for Dual {
+    type Output = Dual;
+
+    fn mul(self, other: f64) -> Dual {
+        Dual::new(self.re * other, self.eps * other)
+    }
+}
+
+impl Div<f64> for Dual {
+    type Output = Dual;
+
+    fn div(self, other: f64) -> Dual {
+        Dual::new(self.re / other, self.eps / other)
+    }
+}
+
+impl Sum for Dual {
+    fn sum<I: Iterator<Item = Dual>>(iter: I) -> Dual {
+        iter.fold(Dual::new(0.0, 0.0), |acc, x| acc + x)
+    }
+}
+
+impl Scalar for Dual {
+    fn from_f64(value: f64) -> Dual {
+        Dual::new(value, 0.0)
+    }
+
+    fn value(&self) -> f64 {
+        self.re
+    }
+
+    fn exp(self) -> Dual {
+        let f = self.re.exp();
+        self.chain(f, f)
+    }
+
+    fn ln(self) -> Dual {
+        self.chain(self.re.ln(), 1.0 / self.re)
+    }
+
+    fn sqrt(self) -> Dual {
+        let f = self.re.sqrt();
+        self.chain(f, 0.5 / f)
+    }
+
+    fn powi(self, n: i32) -> Dual {
+        self.chain(self.re.powi(n), n as f64 * self.re.powi(n - 1))
+    }
+
+    fn powf(self, n: f64) -> Dual {
+        self.chain(self.re.powf(n), n * self.re.powf(n - 1.0))
+    }
+
+    fn sin(self) -> Dual {
+        self.chain(self.re.sin(), self.re.cos())
+    }
+
+    fn cos(self) -> Dual {
+        self.chain(self.re.cos(), -self.re.sin())
+    }
+
+    fn abs(self) -> Dual {
+        self.chain(self.re.abs(), self.re.signum())
+    }
+}
+
+/// A model written for any [`Scalar`](trait.Scalar.html) type
+///
+/// The counterpart of [`Prob`](../trait.Prob.html); wrap it in a
+/// [`Differentiable`](struct.Differentiable.html) to sample it. Invalid prior results are
+/// signalled by returning negative infinity, as for [`Prob`](../trait.Prob.html).
+pub trait GenericProb: Send + Sync {
+    /// Computes the natural logarithm of the likelihood of a position in parameter space
+    fn lnlike<S: Scalar>(&self, params: &[S]) -> S;
+
+    /// Computes the natural logarithm of the prior probability of a position in parameter space
+    fn lnprior<S: Scalar>(&self, params: &[S]) -> S;
+}
+
+/// A [`GenericProb`](trait.GenericProb.html) model, sampled with `f64` and differentiated with
+/// [`Dual`](struct.Dual.html) numbers
+///
+/// The gradient is also available through [`GradLnProb`](../trait.GradLnProb.html).
+#[derive(Debug, Clone)]
+pub struct Differentiable<M> {
+    model: M,
+}
+
+impl<M: GenericProb> Differentiable<M> {
+    /// Wrap a model
+    pub fn new(model: M) -> Differentiable<M> {
+        Differentiable { model }
+    }
+
+    /// The wrapped model
+    pub fn model(&self) -> &M {
+        &self.model
+    }
+
+    /// Recover the wrapped model
+    pub fn into_inner(self) -> M {
+        self.model
+    }
+
+    /// The log posterior probability at `params` and its gradient with respect to each
+    /// parameter
+    ///
+    /// The model is evaluated once per parameter. Outside the support of the prior the log
+    /// probability is negative infinity and the gradient is zero.
+    pub fn gradient(&self, params: &Guess) -> (f64, Vec<f64>) {
+        let lnprob = self.lnprob(params);
+        if !lnprob.is_finite() {
+            return (lnprob, vec![0.0; params.values.len()]);
+        }
+
+        let gradient = (0..params.values.len())
+            .map(|direction| {
+                let duals: Vec<Dual> = params
+                    .values
+                    .iter()
+                    .enumerate()
+                    .map(|(i, &x)| Dual::new(x, if i == direction { 1.0 } else { 0.0 }))
+                    .collect();
+                (self.model.lnprior(&duals) + self.model.lnlike(&duals)).eps
+            })
+            .collect();
+        (lnprob, gradient)
+    }
+}
+
+impl<M: GenericProb> Prob for Differentiable<M> {
+    fn lnlike(&self, params: &Guess) -> f64 {
+        self.model.lnlike(&params.values)
+    }
+
+    fn lnprior(&self, params: &Guess) -> f64 {
+        self.model.lnprior(&params.values)
+    }
+}
+
+impl<M: GenericProb> GradLnProb for Differentiable<M> {
+    fn grad_lnprob(&self, params: &Guess) -> Vec<f64> {
+        self.gradient(params).1
+    }
+
+    fn lnprob_and_grad(&self, params: &Guess) -> (f64, Vec<f64>) {
+        self.gradient(params)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use EnsembleSampler;
+
+    /// A correlated Gaussian likelihood with a prior bounding the first parameter below
+    struct Model;
+
+    impl GenericProb for Model {
+        fn lnlike<S: Scalar>(&self, params: &[S]) -> S {
+            let (x, y) = (params[0], params[1]);
+            let r = (x * x + y * y).sqrt();
+            -(x * x - x * y * 1.2 + y * y) / 2.0 + (r * 3.0).sin() * (x / 2.0).exp()
+        }
+
+        fn lnprior<S: Scalar>(&self, params: &[S]) -> S {
+            if params[0].value() > -5.0 {
+                (params[0] + 6.0).ln()
+            } else {
+                S::from_f64(-f64::INFINITY)
+            }
+        }
+    }
+
+    #[test]
+    fn test_dual_arithmetic() {
+        let x = Dual::new(2.0, 1.0);
+        assert_eq!(x * x, Dual::new(4.0, 4.0));
+        assert_eq!(x.powi(3), Dual::new(8.0, 12.0));
+        assert_eq!(Dual::from_f64(1.0) / x, Dual::new(0.5, -0.25));
+        assert_eq!((-x).abs(), Dual::new(2.0, 1.0));
+        assert_approx_eq!(x.ln().eps, 0.5);
+        assert_approx_eq!(x.powf(0.5).eps, 0.5 / 2f64.sqrt());
+        assert!(x < Dual::new(3.0, -10.0));
+    }
+
+    #[test]
+    fn test_gradient_matches_finite_differences() {
+        let model = Differentiable::new(Model);
+        let params = Guess::new(&[0.3, -1.1]);
+        let (lnprob, gradient) = model.gradient(&params);
+        assert_eq!(lnprob, Prob::lnprob(&model, &params));
+
+        let h = 1e-6;
+        for (i, derivative) in gradient.iter().enumerate() {
+            let (mut up, mut down) = (params.clone(), params.clone());
+            up.values[i] += h;
+            down.values[i] -= h;
+            let numerical = (Prob::lnprob(&model, &up) - Prob::lnprob(&model, &down)) / (2.0 * h);
+            assert_approx_eq!(*derivative, numerical, 1e-6);
+        }
+
+        let (lnprob, gradient) = model.gradient(&Guess::new(&[-7.0, 0.0]));
+        assert_eq!(lnprob, -f64::INFINITY);
+        assert_eq!(gradient, vec![0.0, 0.0]);
+    }
+
+    #[test]
+    fn test_sampling() {
+        let model = Differentiable::new(Model);
+        let mut sampler = EnsembleSampler::new(8, 2, &model).unwrap();
+        sampler.seed(&[1]);
+        let p0: Vec<Guess> = (0..8)
+            .map(|i| Guess::new(&[0.1 * i as f64, -0.05 * i as f64]))
+            .collect();
+        let state = sampler.run_mcmc(&p0, 20).unwrap();
+        for (guess, lnprob) in state.pos.iter().zip(state.lnprob.iter()) {
+            assert_eq!(*lnprob, Prob::lnprob(&model, guess));
+        }
+    }
+}
@@ -0,0 +1,288 @@
+//! Storage backends compatible with Python emcee
+//!
+//! Enabled with the `hdf5` feature. [`HdfBackend`](struct.HdfBackend.html) writes the chain into
+//! an HDF5 group laid out exactly like the one written by emcee's `emcee.backends.HDFBackend`:
+//! attributes `version`, `nwalkers`, `ndim`, `has_blobs` and `iteration`, and the datasets
+//! `chain` (iteration, walker, parameter), `log_prob` (iteration, walker) and `accepted`
+//! (walker). The file can therefore be opened from Python with
+//! `emcee.backends.HDFBackend("chain.h5", read_only=True)`, and passed on to `corner` or ArviZ
+//! without conversion. The parameter names can be stored alongside, in a `param_names`
+//! attribute which emcee ignores.
+//!
+//! Samples are appended as they are produced, so the backend pairs naturally with
+//! [`run_in_chunks`](../struct.EnsembleSampler.html#method.run_in_chunks):
+//!
+//! ```rust,ignore
+//! let mut backend = HdfBackend::create("chain.h5", "mcmc", nwalkers, ndim)?;
+//! sampler.run_in_chunks(&p0, 10000, 100, |block| {
+//!     backend.write_block(&block).expect("writing block");
+//! })?;
+//! backend.set_acceptance_fraction(&sampler.acceptance_fraction())?;
+//! ```
+
+use std::path::Path;
+
+use hdf5::types::VarLenUnicode;
+use hdf5::{Dataset, File, Group};
+use ndarray::{s, Array2, Array3};
+
+use errors::{EmceeError, Result};
+use live::Block;
+use Step;
+
+/// The version of Python emcee whose layout is reproduced
+const EMCEE_VERSION: &str = "3.1.4";
+
+/// The number of iterations stored in each HDF5 chunk
+const CHUNK_ITERATIONS: usize = 64;
+
+impl From<hdf5::Error> for EmceeError {
+    fn from(e: hdf5::Error) -> EmceeError {
+        EmceeError::Msg(format!("hdf5 error: {}", e))
+    }
+}
+
+/// Appends samples to a group of an HDF5 file in the layout of emcee's `HDFBackend`
+pub struct HdfBackend {
+    group: Group,
+    nwalkers: usize,
+    ndim: usize,
+    iteration: usize,
+}
+
+impl HdfBackend {
+    /// Create the group `name` in the file at `path`, ready for a run with `nwalkers` walkers
+    /// and `ndim` parameters
+    ///
+    /// The file is created if it does not exist. As with emcee's `HDFBackend.reset`, an existing
+    /// group of the same name is replaced, while other groups in the file are left untouched.
+    ///
+    /// Errors are handled by returning a [`Result`](../errors/type.Result.html) which contains
+    /// [`EmceeError::InvalidInputs`](../errors/enum.EmceeError.html) if either dimension is zero.
+    pub fn create<P: AsRef<Path>>(
+        path: P,
+        name: &str,
+        nwalkers: usize,
+        ndim: usize,
+    ) -> Result<HdfBackend> {
+        if nwalkers == 0 || ndim == 0 {
+            return Err(EmceeError::InvalidInputs(format!(
+                "cannot store {} walkers with {} parameters",
+                nwalkers, ndim
+            )));
+        }
+
+        let file = File::append(path)?;
+        if file.link_exists(name) {
+            file.unlink(name)?;
+        }
+        let group = file.create_group(name)?;
+
+        let version: VarLenUnicode = EMCEE_VERSION
+            .parse()
+            .map_err(|e| EmceeError::Msg(format!("hdf5 error: {}", e)))?;
+        group
+            .new_attr::<VarLenUnicode>()
+            .shape(())
+            .create("version")?
+            .write_scalar(&version)?;
+        // h5py stores Python integers as 64-bit signed integers
+        for &(attr, value) in &[("nwalkers", nwalkers), ("ndim", ndim), ("iteration", 0)] {
+            group
+                .new_attr::<i64>()
+                .shape(())
+                .create(attr)?
+                .write_scalar(&(value as i64))?;
+        }
+        group
+            .new_attr::<bool>()
+            .shape(())
+            .create("has_blobs")?
+            .write_scalar(&false)?;
+
+        group
+            .new_dataset::<f64>()
+            .shape(nwalkers)
+            .create("accepted")?
+            .write(&vec![0f64; nwalkers])?;
+        group
+            .new_dataset::<f64>()
+            .shape((0.., nwalkers, ndim))
+            .chunk((CHUNK_ITERATIONS, nwalkers, ndim))
+            .create("chain")?;
+        group
+            .new_dataset::<f64>()
+            .shape((0.., nwalkers))
+            .chunk((CHUNK_ITERATIONS, nwalkers))
+            .create("log_prob")?;
+
+        Ok(HdfBackend {
+            group,
+            nwalkers,
+            ndim,
+            iteration: 0,
+        })
+    }
+
+    /// Open the existing group `name` in the file at `path` to append further samples
+    ///
+    /// This continues a run stored by either this backend or by Python emcee, e.g. after
+    /// resuming the sampler from its last position.
+    ///
+    /// Errors are handled by returning a [`Result`](../errors/type.Result.html) which contains
+    /// [`EmceeError::InvalidInputs`](../errors/enum.EmceeError.html) if the group stores blobs,
+    /// which this backend cannot extend.
+    pub fn open<P: AsRef<Path>>(path: P, name: &str) -> Result<HdfBackend> {
+        let file = File::open_rw(path)?;
+        let group = file.group(name)?;
+        if group.attr("has_blobs")?.read_scalar::<bool>()? {
+            return Err(EmceeError::InvalidInputs(format!(
+                "the group {} stores blobs, which cannot be appended to",
+                name
+            )));
+        }
+
+        let read = |attr: &str| -> Result<usize> {
+            Ok(group.attr(attr)?.read_scalar::<i64>()? as usize)
+        };
+        let nwalkers = read("nwalkers")?;
+        let ndim = read("ndim")?;
+        let iteration = read("iteration")?;
+        Ok(HdfBackend {
+            group,
+            nwalkers,
+            ndim,
+            iteration,
+        })
+    }
+
+    /// The number of iterations stored so far
+    pub fn iteration(&self) -> usize {
+        self.iteration
+    }
+
+    /// Append every iteration contained in a block
+    pub fn write_block(&mut self, block: &Block) -> Result<()> {
+        self.check_shape(block.nwalkers(), block.nparams())?;
+
+        let n = block.len();
+        let positions = Array3::from_shape_fn((n, self.nwalkers, self.ndim), |(i, k, p)| {
+            block.get(p, k, i)
+        });
+        let lnprob = Array2::from_shape_fn((n, self.nwalkers), |(i, k)| block.lnprob(k, i));
+        self.append(&positions, &lnprob)
+    }
+
+    /// Append the walker positions of a single step
+    pub fn write_step(&mut self, step: &Step) -> Result<()> {
+        let ndim = step.pos.first().map_or(0, |guess| guess.values.len());
+        self.check_shape(step.pos.len(), ndim)?;
+        if step.pos.iter().any(|guess| guess.values.len() != ndim) {
+            return Err(EmceeError::InvalidInputs(
+                "the walkers of the step have different numbers of parameters".into(),
+            ));
+        }
+
+        let positions =
+            Array3::from_shape_fn((1, self.nwalkers, self.ndim), |(_, k, p)| step.pos[k][p]);
+        let lnprob = Array2::from_shape_fn((1, self.nwalkers), |(_, k)| step.lnprob[k]);
+        self.append(&positions, &lnprob)
+    }
+
+    /// Store the acceptance fraction of each walker
+    ///
+    /// emcee stores the number of accepted proposals rather than the fraction, and divides by
+    /// `iteration` when reading. The fractions are therefore scaled by the number of stored
+    /// iterations, so call this once the run is complete, e.g. with
+    /// [`acceptance_fraction`](../struct.EnsembleSampler.html#method.acceptance_fraction).
+    ///
+    /// Errors are handled by returning a [`Result`](../errors/type.Result.html) which contains
+    /// [`EmceeError::InvalidInputs`](../errors/enum.EmceeError.html) if there is not exactly one
+    /// fraction per walker.
+    pub fn set_acceptance_fraction(&mut self, fraction: &[f64]) -> Result<()> {
+        if fraction.len() != self.nwalkers {
+            return Err(EmceeError::InvalidInputs(format!(
+                "expected {} acceptance fractions, got {}",
+                self.nwalkers,
+                fraction.len()
+            )));
+        }
+
+        let accepted: Vec<f64> = fraction
+            .iter()
+            .map(|f| f * self.iteration as f64)
+            .collect();
+        self.group.dataset("accepted")?.write(&accepted)?;
+        Ok(())
+    }
+
+    /// Record the names of the parameters as the `param_names` attribute of the group, e.g.
+    /// from [`Chain::param_names`](../struct.Chain.html#method.param_names)
+    ///
+    /// Python emcee ignores the attribute, so the file stays readable by `HDFBackend`, while
+    /// other readers can label the columns of `chain`. Any existing names are replaced.
+    ///
+    /// Errors are handled by returning a [`Result`](../errors/type.Result.html) which contains
+    /// [`EmceeError::InvalidInputs`](../errors/enum.EmceeError.html) if there is not exactly one
+    /// name per parameter.
+    pub fn set_param_names<S: AsRef<str>>(&mut self, names: &[S]) -> Result<()> {
+        if names.len() != self.ndim {
+            return Err(EmceeError::InvalidInputs(format!(
+                "expected {} parameter names, got {}",
+                self.ndim,
+                names.len()
+            )));
+        }
+
+        let names = names
+            .iter()
+            .map(|name| name.as_ref().parse::<VarLenUnicode>())
+            .collect::<::std::result::Result<Vec<_>, _>>()
+            .map_err(|e| EmceeError::Msg(format!("hdf5 error: {}", e)))?;
+        // The attribute always has one name per parameter, so existing names are overwritten
+        let attr = match self.group.attr("param_names") {
+            Ok(attr) => attr,
+            Err(_) => self
+                .group
+                .new_attr::<VarLenUnicode>()
+                .shape(names.len())
+                .create("param_names")?,
+        };
+        attr.write(&names)?;
+        Ok(())
+    }
+
+    fn check_shape(&self, nwalkers: usize, ndim: usize) -> Result<()> {
+        if nwalkers != self.nwalkers || ndim != self.ndim {
+            return Err(EmceeError::InvalidInputs(format!(
+                "expected {} walkers with {} parameters, got {} with {}",
+                self.nwalkers, self.ndim, nwalkers, ndim
+            )));
+        }
+        Ok(())
+    }
+
+    fn append(&mut self, positions: &Array3<f64>, lnprob: &Array2<f64>) -> Result<()> {
+        let start = self.iteration;
+        let end = start + lnprob.nrows();
+        if end == start {
+            return Ok(());
+        }
+
+        let chain: Dataset = self.group.dataset("chain")?;
+        chain.resize((end, self.nwalkers, self.ndim))?;
+        chain.write_slice(positions, s![start..end, .., ..])?;
+
+        let log_prob: Dataset = self.group.dataset("log_prob")?;
+        log_prob.resize((end, self.nwalkers))?;
+        log_prob.write_slice(lnprob, s![start..end, ..])?;
+
+        // Only count the iterations once they are written, so a failed write leaves the file
+        // readable
+        self.group
+            .attr("iteration")?
+            .write_scalar(&(end as i64))?;
+        self.iteration = end;
+        Ok(())
+    }
+}
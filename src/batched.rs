@@ -0,0 +1,141 @@
+//! Models which evaluate many positions at once
+
+use guess::Guess;
+use prob::Prob;
+
+/// A model which evaluates the log posterior probability of many positions in one call
+///
+/// Models whose evaluation vectorises across positions, e.g. with SIMD or a batched BLAS
+/// call, can be much faster evaluating every walker of a half-ensemble together than one at a
+/// time. Wrap the model in [`Batched`](struct.Batched.html) to sample it this way.
+pub trait BatchedProb: Send + Sync {
+    /// Computes the natural logarithm of the posterior probability of every position of
+    /// `positions`, writing each into the same index of `out`
+    ///
+    /// `out` has the same length as `positions`. Positions outside the support should be given
+    /// `-inf`, as with [`Prob::lnprob`](trait.Prob.html#method.lnprob).
+    fn lnprob_batch(&self, positions: &[Guess], out: &mut [f64]);
+}
+
+/// Samples a [`BatchedProb`](trait.BatchedProb.html) a half-ensemble at a time
+///
+/// The sampler makes one call to
+/// [`BatchedProb::lnprob_batch`](trait.BatchedProb.html#tymethod.lnprob_batch) for the
+/// proposals of each half-ensemble, and for the initial positions, instead of one call per
+/// walker; see [`Prob::lnprob_batch`](trait.Prob.html#method.lnprob_batch) for the sampler
+/// settings which then do not apply. Single positions, e.g. for
+/// [`Prob::lnprob`](trait.Prob.html#method.lnprob), are evaluated as batches of one.
+///
+/// ```rust
+/// # use emcee::{Batched, BatchedProb, EnsembleSampler, Guess};
+/// struct Gaussian;
+///
+/// impl BatchedProb for Gaussian {
+///     fn lnprob_batch(&self, positions: &[Guess], out: &mut [f64]) {
+///         for (params, out) in positions.iter().zip(out.iter_mut()) {
+///             *out = -0.5 * params.values.iter().map(|x| x * x).sum::<f64>();
+///         }
+///     }
+/// }
+///
+/// let model = Batched::new(Gaussian);
+/// let mut sampler = EnsembleSampler::new(8, 2, &model).unwrap();
+/// let p0 = Guess::new(&[0.0, 0.0]).create_initial_guess(8);
+/// sampler.run_mcmc(&p0, 100).unwrap();
+/// ```
+#[derive(Debug, Clone)]
+pub struct Batched<M: BatchedProb> {
+    model: M,
+}
+
+impl<M: BatchedProb> Batched<M> {
+    /// Wrap `model` for sampling
+    pub fn new(model: M) -> Batched<M> {
+        Batched { model }
+    }
+
+    /// The wrapped model
+    pub fn model(&self) -> &M {
+        &self.model
+    }
+
+    /// Recover the wrapped model
+    pub fn into_inner(self) -> M {
+        self.model
+    }
+}
+
+impl<M: BatchedProb> Prob for Batched<M> {
+    fn lnlike(&self, params: &Guess) -> f64 {
+        self.lnprob(params)
+    }
+
+    fn lnprior(&self, _params: &Guess) -> f64 {
+        0.0
+    }
+
+    fn lnprob(&self, params: &Guess) -> f64 {
+        let mut out = [0f64];
+        self.model.lnprob_batch(::std::slice::from_ref(params), &mut out);
+        out[0]
+    }
+
+    fn is_batched(&self) -> bool {
+        true
+    }
+
+    fn lnprob_batch(&self, positions: &[Guess], out: &mut [f64]) {
+        self.model.lnprob_batch(positions, out)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+    use {with_data, EnsembleSampler};
+
+    /// A correlated Gaussian, recording the size of every batch
+    struct Model {
+        batches: Mutex<Vec<usize>>,
+    }
+
+    fn lnprob(params: &Guess) -> f64 {
+        let (x, y) = (params[0], params[1]);
+        -(x * x - 1.2 * x * y + y * y) / 2.0
+    }
+
+    impl BatchedProb for Model {
+        fn lnprob_batch(&self, positions: &[Guess], out: &mut [f64]) {
+            self.batches.lock().unwrap().push(positions.len());
+            for (params, out) in positions.iter().zip(out.iter_mut()) {
+                *out = lnprob(params);
+            }
+        }
+    }
+
+    #[test]
+    fn test_batched_sampling() {
+        let model = Batched::new(Model {
+            batches: Mutex::new(Vec::new()),
+        });
+        let p0 = Guess::new(&[0.5, -0.5]).create_initial_guess(10);
+        let mut sampler = EnsembleSampler::new(10, 2, &model).unwrap();
+        sampler.seed(&[3]);
+        sampler.run_mcmc(&p0, 20).unwrap();
+
+        // The initial ensemble, then both halves of each iteration
+        let batches = model.model().batches.lock().unwrap().clone();
+        assert_eq!(batches.len(), 41);
+        assert!(batches.iter().all(|&n| n == 10 || n == 5));
+        assert_eq!(sampler.run_stats().nevaluations, 210);
+
+        // The same samples as evaluating one walker at a time
+        let plain = with_data((), |params: &Guess, _: &()| lnprob(params));
+        let mut reference = EnsembleSampler::new(10, 2, &plain).unwrap();
+        reference.seed(&[3]);
+        reference.run_mcmc(&p0, 20).unwrap();
+        assert_eq!(sampler.flatchain(), reference.flatchain());
+        assert_eq!(model.lnprob(&p0[0]), lnprob(&p0[0]));
+    }
+}
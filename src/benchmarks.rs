@@ -0,0 +1,234 @@
+//! Benchmarks for choosing a move configuration
+//!
+//! Moves differ widely in how quickly they explore different kinds of posterior, and the cost
+//! of each iteration differs too, so the useful figure of merit is the number of effectively
+//! independent samples produced per second of computation. [`benchmark`](fn.benchmark.html)
+//! measures it for one configuration of moves on one [`Target`](../testkit/trait.Target.html),
+//! and [`run_suite`](fn.run_suite.html) runs a configuration on a standard set of targets
+//! representing common problem classes:
+//!
+//! * `"correlated gaussian"`: a five dimensional Gaussian with strong correlations and scales
+//!   differing by a factor of 100, which affine-invariant moves handle well
+//! * `"rosenbrock"`: the [`Rosenbrock`](../testkit/struct.Rosenbrock.html) density, a curved
+//!   ridge
+//! * `"funnel"`: Neal's [`Funnel`](../testkit/struct.Funnel.html), typical of hierarchical models
+//! * `"gaussian mixture"`: two well separated modes
+//!
+//! ```rust,no_run
+//! # use emcee::benchmarks;
+//! # use emcee::moves::{CovarianceMove, Move, StretchMove};
+//! let stretch = benchmarks::run_suite(
+//!     || vec![(Box::new(StretchMove::default()) as Box<dyn Move>, 1.0)],
+//!     32,
+//!     5000,
+//!     &[1],
+//! ).unwrap();
+//! let mixed = benchmarks::run_suite(
+//!     || vec![
+//!         (Box::new(StretchMove::default()) as Box<dyn Move>, 0.8),
+//!         (Box::new(CovarianceMove::default()) as Box<dyn Move>, 0.2),
+//!     ],
+//!     32,
+//!     5000,
+//!     &[1],
+//! ).unwrap();
+//! for result in stretch.iter().chain(&mixed) {
+//!     println!("{}", result);
+//! }
+//! ```
+//!
+//! The effective sample size comes from the integrated autocorrelation time of the second half
+//! of the run, which is unreliable when the run is shorter than about 50 autocorrelation times,
+//! and the timings depend on the machine and its load, so results are best compared between
+//! runs of the same length made together.
+
+use std::fmt;
+use std::time::Instant;
+
+use errors::{EmceeError, Result};
+use moves::Move;
+use testkit::{self, CorrelatedGaussian, Funnel, GaussianMixture, Rosenbrock, Target};
+use EnsembleSampler;
+
+/// The performance of one move configuration on one target
+#[derive(Debug, Clone)]
+pub struct Benchmark {
+    /// The name of the target
+    pub target: String,
+
+    /// The names and weights of the moves
+    pub moves: String,
+
+    /// The largest integrated autocorrelation time of any parameter, in iterations
+    pub tau: f64,
+
+    /// The effective number of independent samples in the second half of the run
+    pub neff: f64,
+
+    /// The wall-clock time of the whole run, in seconds
+    pub seconds: f64,
+
+    /// The mean acceptance fraction of the walkers
+    pub acceptance_fraction: f64,
+}
+
+impl Benchmark {
+    /// The effective number of independent samples per second of the run
+    pub fn ess_per_second(&self) -> f64 {
+        self.neff / self.seconds
+    }
+}
+
+impl fmt::Display for Benchmark {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{:<20} {:<30} tau {:>9.1}  acceptance {:>5.3}  ESS {:>9.1}  ESS/s {:>11.1}",
+            self.target,
+            self.moves,
+            self.tau,
+            self.acceptance_fraction,
+            self.neff,
+            self.ess_per_second()
+        )
+    }
+}
+
+/// Sample `target` with the weighted `moves`, and measure the effective sample size per second
+///
+/// The walkers start from a Gaussian with the target's mean and covariance, the sampler is
+/// seeded with `seed` and run for `niterations` iterations, and the second half of the run is
+/// used to estimate the effective sample size. `name` labels the result.
+///
+/// Errors are handled by returning a [`Result`](../errors/type.Result.html) which contains any
+/// error from creating or running the sampler.
+pub fn benchmark<P: Target>(
+    name: &str,
+    target: &P,
+    moves: Vec<(Box<dyn Move>, f64)>,
+    nwalkers: usize,
+    niterations: usize,
+    seed: &[usize],
+) -> Result<Benchmark> {
+    let label = moves
+        .iter()
+        .map(|(m, weight)| format!("{} ({})", m.name(), weight))
+        .collect::<Vec<_>>()
+        .join(", ");
+
+    let p0 = testkit::initial_ensemble(target, nwalkers, seed)?;
+    let mut sampler = EnsembleSampler::new(nwalkers, target.dim(), target)?;
+    sampler.seed(seed);
+    sampler.set_moves(moves)?;
+
+    let start = Instant::now();
+    sampler.run_mcmc(&p0, niterations)?;
+    let elapsed = start.elapsed();
+
+    let chain = sampler
+        .chain()
+        .ok_or_else(|| EmceeError::from("the chain was not stored"))?;
+    let (_, production) = chain.split_at(chain.niterations / 2);
+    let tau = testkit::max_integrated_time(&production);
+    Ok(Benchmark {
+        target: name.into(),
+        moves: label,
+        tau,
+        neff: production.niterations as f64 * nwalkers as f64 / tau,
+        seconds: elapsed.as_secs() as f64 + 1e-9 * f64::from(elapsed.subsec_nanos()),
+        acceptance_fraction: sampler.mean_acceptance_fraction(),
+    })
+}
+
+/// [`benchmark`](fn.benchmark.html) one move configuration on each of the standard targets
+///
+/// `configure` is called once per target to create fresh moves, so adaptive moves do not carry
+/// state between targets. See the [module documentation](index.html) for the targets.
+///
+/// Errors are handled by returning a [`Result`](../errors/type.Result.html) which contains any
+/// error from creating or running the samplers.
+pub fn run_suite<F>(
+    configure: F,
+    nwalkers: usize,
+    niterations: usize,
+    seed: &[usize],
+) -> Result<Vec<Benchmark>>
+where
+    F: Fn() -> Vec<(Box<dyn Move>, f64)>,
+{
+    let scales = [1.0, 0.3, 3.0, 0.1, 10.0];
+    let cov = (0..scales.len())
+        .map(|i| {
+            (0..scales.len())
+                .map(|j| scales[i] * scales[j] * if i == j { 1.0 } else { 0.9 })
+                .collect()
+        })
+        .collect();
+    let gaussian = CorrelatedGaussian::new(vec![0.0; scales.len()], cov)?;
+    let mixture =
+        GaussianMixture::new(vec![(1.0, vec![-4.0, 0.0], 1.0), (1.0, vec![4.0, 0.0], 1.0)])?;
+
+    Ok(vec![
+        benchmark("correlated gaussian", &gaussian, configure(), nwalkers, niterations, seed)?,
+        benchmark("rosenbrock", &Rosenbrock::default(), configure(), nwalkers, niterations, seed)?,
+        benchmark("funnel", &Funnel::default(), configure(), nwalkers, niterations, seed)?,
+        benchmark("gaussian mixture", &mixture, configure(), nwalkers, niterations, seed)?,
+    ])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use moves::{CovarianceMove, StretchMove};
+
+    #[test]
+    fn test_suite() {
+        let results = run_suite(
+            || vec![(Box::new(StretchMove::default()) as Box<dyn Move>, 1.0)],
+            32,
+            400,
+            &[1],
+        ).unwrap();
+        let names: Vec<&str> = results.iter().map(|result| result.target.as_str()).collect();
+        assert_eq!(
+            names,
+            vec!["correlated gaussian", "rosenbrock", "funnel", "gaussian mixture"]
+        );
+        for result in &results {
+            assert_eq!(result.moves, "stretch (1)");
+            assert!(result.tau >= 1.0);
+            assert!(result.neff > 0.0 && result.neff <= 32.0 * 200.0);
+            assert!(result.ess_per_second() > 0.0);
+            assert!(result.acceptance_fraction > 0.0 && result.acceptance_fraction < 1.0);
+        }
+    }
+
+    #[test]
+    fn test_independence_proposals_win_on_a_gaussian() {
+        let target = CorrelatedGaussian::new(
+            vec![0.0, 0.0, 0.0],
+            vec![vec![1.0, 0.9, 0.0], vec![0.9, 1.0, 0.0], vec![0.0, 0.0, 4.0]],
+        ).unwrap();
+        let stretch = benchmark(
+            "gaussian",
+            &target,
+            vec![(Box::new(StretchMove::default()), 1.0)],
+            16,
+            2000,
+            &[2],
+        ).unwrap();
+        let mixed = benchmark(
+            "gaussian",
+            &target,
+            vec![
+                (Box::new(StretchMove::default()), 0.5),
+                (Box::new(CovarianceMove::new(500, 10)), 0.5),
+            ],
+            16,
+            2000,
+            &[2],
+        ).unwrap();
+        assert_eq!(mixed.moves, "stretch (0.5), covariance (0.5)");
+        assert!(mixed.tau < stretch.tau, "{} >= {}", mixed.tau, stretch.tau);
+    }
+}
@@ -0,0 +1,371 @@
+//! Arbitrary metadata stored alongside each sample
+
+use std::collections::HashMap;
+#[cfg(feature = "serde")]
+use std::convert::TryFrom;
+use std::sync::Mutex;
+
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+use errors::{EmceeError, Result};
+use guess::Guess;
+use prob::Prob;
+use {EnsembleSampler, Step};
+
+/// A model which computes extra quantities, or "blobs", together with the log posterior
+///
+/// Blobs are any per-sample metadata which is computed anyway during an evaluation, e.g. the
+/// model fluxes or the individual terms of a chi-square, and which would be wasteful to compute
+/// again from the chain afterwards. Wrap the model in [`WithBlobs`](struct.WithBlobs.html) to
+/// sample it, and run the sampler with
+/// [`run_mcmc_with_blobs`](struct.EnsembleSampler.html#method.run_mcmc_with_blobs) to keep the
+/// blob of every walker at every stored iteration, as with `blobs` in Python `emcee`.
+pub trait BlobProb: Send + Sync {
+    /// The metadata computed for each position
+    type Blob: Clone + Send + 'static;
+
+    /// Computes the natural logarithm of the posterior probability of a position, together
+    /// with its blob
+    fn lnprob_blob(&self, params: &Guess) -> (f64, Self::Blob);
+}
+
+/// Samples a [`BlobProb`](trait.BlobProb.html), remembering the blobs of evaluated positions
+///
+/// The blob of each evaluation is kept until the sampler has either moved a walker to the
+/// position or rejected it, so no evaluation is repeated to recover the blobs. Only
+/// [`run_mcmc_with_blobs`](struct.EnsembleSampler.html#method.run_mcmc_with_blobs) forgets the
+/// blobs of rejected positions, so use it for every run of a wrapped model.
+///
+/// ```rust
+/// # use emcee::{BlobProb, EnsembleSampler, Guess, WithBlobs};
+/// struct Line;
+///
+/// impl BlobProb for Line {
+///     // The residual at each data point
+///     type Blob = Vec<f64>;
+///
+///     fn lnprob_blob(&self, params: &Guess) -> (f64, Vec<f64>) {
+///         let residuals: Vec<f64> = [(1.0, 3.1), (2.0, 4.9), (3.0, 7.2)]
+///             .iter()
+///             .map(|&(x, y)| y - (params[0] * x + params[1]))
+///             .collect();
+///         (-0.5 * residuals.iter().map(|r| r * r).sum::<f64>(), residuals)
+///     }
+/// }
+///
+/// let model = WithBlobs::new(Line);
+/// let mut sampler = EnsembleSampler::new(8, 2, &model).unwrap();
+/// let p0 = Guess::new(&[2.0, 1.0]).create_initial_guess(8);
+/// sampler.run_mcmc_with_blobs(&p0, 100).unwrap();
+///
+/// let blobs = sampler.get_blobs().unwrap();
+/// assert_eq!(blobs.niterations(), 100);
+/// assert_eq!(blobs.get(3, 99).len(), 3);
+/// ```
+pub struct WithBlobs<M: BlobProb> {
+    model: M,
+    cache: Mutex<HashMap<Vec<u64>, M::Blob>>,
+}
+
+impl<M: BlobProb> WithBlobs<M> {
+    /// Wrap `model` for sampling
+    pub fn new(model: M) -> WithBlobs<M> {
+        WithBlobs {
+            model,
+            cache: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// The wrapped model
+    pub fn model(&self) -> &M {
+        &self.model
+    }
+
+    /// Recover the wrapped model
+    pub fn into_inner(self) -> M {
+        self.model
+    }
+
+    /// The blob of `params`, from the cache if it was evaluated, otherwise computed afresh
+    fn blob(&self, params: &Guess) -> M::Blob {
+        let cached = self
+            .cache
+            .lock()
+            .expect("blob cache lock poisoned")
+            .get(&key(params))
+            .cloned();
+        cached.unwrap_or_else(|| self.model.lnprob_blob(params).1)
+    }
+
+    /// Forget the blobs of every position but `keep`
+    fn retain(&self, keep: &[Guess]) {
+        let keys: Vec<Vec<u64>> = keep.iter().map(key).collect();
+        self.cache
+            .lock()
+            .expect("blob cache lock poisoned")
+            .retain(|k, _| keys.contains(k));
+    }
+}
+
+impl<M: BlobProb> Prob for WithBlobs<M> {
+    fn lnlike(&self, params: &Guess) -> f64 {
+        self.lnprob(params)
+    }
+
+    fn lnprior(&self, _params: &Guess) -> f64 {
+        0.0
+    }
+
+    fn lnprob(&self, params: &Guess) -> f64 {
+        let (lnprob, blob) = self.model.lnprob_blob(params);
+        self.cache
+            .lock()
+            .expect("blob cache lock poisoned")
+            .insert(key(params), blob);
+        lnprob
+    }
+}
+
+/// The exact bit pattern of a position
+fn key(params: &Guess) -> Vec<u64> {
+    params.values.iter().map(|x| x.to_bits()).collect()
+}
+
+/// The blobs of every walker at every stored iteration
+///
+/// Returned by [`EnsembleSampler::get_blobs`](struct.EnsembleSampler.html#method.get_blobs),
+/// laid out in parallel with the [`Chain`](struct.Chain.html).
+///
+/// With the `serde` feature the store implements `Serialize` and `Deserialize` whenever the
+/// blob type does, so structured blobs can be written to disk in any serde format, e.g. JSON
+/// with `serde_json`, and read back with their walkers and iterations intact. Deserialising
+/// data whose number of blobs is not a whole number of iterations is an error.
+#[derive(Debug, Clone)]
+#[cfg_attr(
+    feature = "serde",
+    derive(Serialize, Deserialize),
+    serde(try_from = "BlobStoreData<B>")
+)]
+pub struct BlobStore<B> {
+    nwalkers: usize,
+    blobs: Vec<B>,
+}
+
+/// The serialised form of a [`BlobStore`](struct.BlobStore.html), checked before it is accepted
+#[cfg(feature = "serde")]
+#[derive(Deserialize)]
+struct BlobStoreData<B> {
+    nwalkers: usize,
+    blobs: Vec<B>,
+}
+
+#[cfg(feature = "serde")]
+impl<B> TryFrom<BlobStoreData<B>> for BlobStore<B> {
+    type Error = String;
+
+    fn try_from(data: BlobStoreData<B>) -> ::std::result::Result<BlobStore<B>, String> {
+        if data.nwalkers == 0 || data.blobs.len() % data.nwalkers != 0 {
+            return Err(format!(
+                "{} blobs do not fill whole iterations of {} walkers",
+                data.blobs.len(),
+                data.nwalkers
+            ));
+        }
+        Ok(BlobStore {
+            nwalkers: data.nwalkers,
+            blobs: data.blobs,
+        })
+    }
+}
+
+impl<B> BlobStore<B> {
+    fn new(nwalkers: usize) -> BlobStore<B> {
+        BlobStore {
+            nwalkers,
+            blobs: Vec::new(),
+        }
+    }
+
+    /// The number of walkers
+    pub fn nwalkers(&self) -> usize {
+        self.nwalkers
+    }
+
+    /// The number of stored iterations
+    pub fn niterations(&self) -> usize {
+        self.blobs.len() / self.nwalkers
+    }
+
+    /// The blob of one walker at one stored iteration
+    pub fn get(&self, walker_idx: usize, iteration_idx: usize) -> &B {
+        assert!(walker_idx < self.nwalkers);
+        assert!(iteration_idx < self.niterations());
+        &self.blobs[iteration_idx * self.nwalkers + walker_idx]
+    }
+
+    /// Every blob in iteration order, then walker order, like
+    /// [`Chain::flatchain`](struct.Chain.html#method.flatchain)
+    pub fn flatblobs(&self) -> &[B] {
+        &self.blobs
+    }
+
+    /// The blobs of each iteration after the first `discard`, keeping every `thin`th, with the
+    /// semantics of `get_blobs` in Python `emcee`
+    ///
+    /// The kept iterations are the same as for
+    /// [`Chain::get_chain`](struct.Chain.html#method.get_chain), and each holds one blob per
+    /// walker.
+    ///
+    /// Errors are handled by returning a [`Result`](errors/type.Result.html) which contains
+    /// [`EmceeError::InvalidInputs`](errors/enum.EmceeError.html) if `thin` is zero.
+    pub fn get_blobs(&self, thin: usize, discard: usize) -> Result<Vec<&[B]>> {
+        if thin == 0 {
+            return Err(EmceeError::InvalidInputs("thin must be positive".into()));
+        }
+        Ok((discard + thin - 1..self.niterations())
+            .step_by(thin)
+            .map(|iter| &self.blobs[iter * self.nwalkers..(iter + 1) * self.nwalkers])
+            .collect())
+    }
+}
+
+impl<'a, M: BlobProb + 'a> EnsembleSampler<'a, WithBlobs<M>> {
+    /// Run the sampling like [`run_mcmc`](#method.run_mcmc), also storing the blob of every
+    /// walker at every stored iteration
+    ///
+    /// The blobs are stored for the same iterations as the chain, taking
+    /// [`thin`](#structfield.thin) and [`discard`](#structfield.discard) into account, and are
+    /// available from [`get_blobs`](#method.get_blobs) until the next run or
+    /// [`reset`](#method.reset). A walker which has not moved keeps its blob.
+    ///
+    /// Errors are handled by returning a [`Result`](errors/type.Result.html) which contains
+    /// [`EmceeError::InvalidInputs`](errors/enum.EmceeError.html) if the sampler has a
+    /// [`max_stored_iterations`](#structfield.max_stored_iterations), as the stored iterations
+    /// are then only known at the end of the run, or any error from the run.
+    pub fn run_mcmc_with_blobs(&mut self, p0: &[Guess], niterations: usize) -> Result<Step> {
+        if self.max_stored_iterations.is_some() {
+            return Err(EmceeError::InvalidInputs(
+                "blobs cannot be stored with a maximum number of stored iterations".into(),
+            ));
+        }
+
+        let model = self.lnprob;
+        let (storechain, thin, discard) = (self.storechain, self.thin, self.discard);
+        let mut store = BlobStore::new(self.nwalkers);
+        self.blobs = None;
+        let result = self.sample(p0, niterations, |step| {
+            let stored = step.iteration >= discard && (step.iteration - discard) % thin == 0;
+            if storechain && stored {
+                store.blobs.extend(step.pos.iter().map(|guess| model.blob(guess)));
+            }
+            model.retain(&step.pos);
+        });
+        self.blobs = Some(Box::new(store));
+        result
+    }
+
+    /// Return the blobs stored by the last
+    /// [`run_mcmc_with_blobs`](#method.run_mcmc_with_blobs), if any
+    pub fn get_blobs(&self) -> Option<&BlobStore<M::Blob>> {
+        self.blobs
+            .as_ref()
+            .and_then(|blobs| (**blobs).downcast_ref::<BlobStore<M::Blob>>())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::{SeedableRng, StdRng};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    struct Gaussian {
+        nevaluations: AtomicUsize,
+    }
+
+    impl BlobProb for Gaussian {
+        type Blob = (f64, usize);
+
+        fn lnprob_blob(&self, params: &Guess) -> (f64, (f64, usize)) {
+            let n = self.nevaluations.fetch_add(1, Ordering::SeqCst);
+            let r2 = params.values.iter().map(|x| x * x).sum::<f64>();
+            (-0.5 * r2, (r2, n))
+        }
+    }
+
+    #[test]
+    fn test_blobs() {
+        let model = WithBlobs::new(Gaussian {
+            nevaluations: AtomicUsize::new(0),
+        });
+        let nwalkers = 10;
+        let mut rng = StdRng::from_seed(&[1, 2, 3, 4]);
+        let p0 = Guess::new(&[0.5, -0.5]).create_initial_guess_with_rng(nwalkers, &mut rng);
+
+        let mut sampler = EnsembleSampler::new(nwalkers, 2, &model).unwrap();
+        sampler.seed(&[1]);
+        sampler.thin = 2;
+        sampler.discard = 10;
+        assert!(sampler.get_blobs().is_none());
+        sampler.run_mcmc_with_blobs(&p0, 100).unwrap();
+
+        let chain = sampler.chain().unwrap();
+        let blobs = sampler.get_blobs().unwrap();
+        assert_eq!(blobs.niterations(), chain.niterations);
+        assert_eq!(blobs.nwalkers(), nwalkers);
+        for iter in 0..chain.niterations {
+            for walker in 0..nwalkers {
+                let r2 = chain.get(0, walker, iter).powi(2) + chain.get(1, walker, iter).powi(2);
+                assert_eq!(blobs.get(walker, iter).0, r2);
+            }
+        }
+
+        // Every blob came from the evaluation made by the sampler
+        assert_eq!(
+            model.model().nevaluations.load(Ordering::SeqCst),
+            sampler.run_stats().nevaluations
+        );
+        assert!(model.cache.lock().unwrap().len() <= nwalkers);
+
+        let kept = blobs.get_blobs(5, 3).unwrap();
+        assert_eq!(kept.len(), (7..chain.niterations).step_by(5).count());
+        assert_eq!(kept[1], &blobs.flatblobs()[12 * nwalkers..13 * nwalkers]);
+        assert!(blobs.get_blobs(0, 0).is_err());
+
+        sampler.reset();
+        assert!(sampler.get_blobs().is_none());
+        sampler.max_stored_iterations = Some(10);
+        assert!(sampler.run_mcmc_with_blobs(&p0, 100).is_err());
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_serde_round_trip() {
+        #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+        struct Derived {
+            flux: f64,
+            label: String,
+        }
+
+        let mut store = BlobStore::new(2);
+        store.blobs = (0..6)
+            .map(|i| Derived {
+                flux: i as f64 * 0.5,
+                label: format!("sample {}", i),
+            })
+            .collect();
+
+        let json = ::serde_json::to_string(&store).unwrap();
+        let read: BlobStore<Derived> = ::serde_json::from_str(&json).unwrap();
+        assert_eq!((read.nwalkers(), read.niterations()), (2, 3));
+        assert_eq!(read.get(1, 2), store.get(1, 2));
+        assert_eq!(read.flatblobs(), store.flatblobs());
+
+        // Blobs which do not fill whole iterations are rejected
+        let partial = r#"{"nwalkers": 4, "blobs": [{"flux": 1.0, "label": "a"}]}"#;
+        assert!(::serde_json::from_str::<BlobStore<Derived>>(partial).is_err());
+        let empty = r#"{"nwalkers": 0, "blobs": []}"#;
+        assert!(::serde_json::from_str::<BlobStore<Derived>>(empty).is_err());
+    }
+}
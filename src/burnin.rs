@@ -0,0 +1,153 @@
+//! Automatic burn-in detection
+
+use autocorr;
+use stores::Chain;
+
+/// The number of candidate burn-in lengths per chain length
+const NCANDIDATES: usize = 20;
+
+/// The minimum number of iterations in the first segment of a Geweke test
+const MIN_SEGMENT: usize = 5;
+
+/// The largest accepted Geweke z-score
+const GEWEKE_THRESHOLD: f64 = 2.0;
+
+impl Chain {
+    /// Suggest how many iterations to discard as burn-in
+    ///
+    /// The ensemble mean and the log of the ensemble spread of every parameter are traced over
+    /// the iterations. Candidate burn-in lengths are scanned in steps of a twentieth of the
+    /// chain, up to half of it, and the first one after which every trace passes a Geweke test
+    /// is returned: the mean over the first tenth of the remaining iterations must agree with
+    /// the mean over the last half within two standard errors, estimated from the variance and
+    /// autocorrelation time of the last half.
+    /// Tracing the spread as well as the mean catches a small initial ball which is still
+    /// expanding even when it was started at the right location.
+    ///
+    /// `None` means the chain is too short to test (fewer than 100 iterations) or still
+    /// drifting over its second half, in which case it should be run for longer. The result is
+    /// a guide rather than a guarantee, and is worth checking against the trace and the
+    /// [autocorrelation time](#method.integrated_time).
+    pub fn suggested_discard(&self) -> Option<usize> {
+        if self.nwalkers == 0 || self.niterations < NCANDIDATES * MIN_SEGMENT {
+            return None;
+        }
+
+        let traces = self.ensemble_traces();
+        let step = self.niterations / NCANDIDATES;
+        (0..NCANDIDATES / 2 + 1)
+            .map(|k| k * step)
+            .find(|&discard| {
+                traces
+                    .iter()
+                    .all(|trace| geweke(&trace[discard..]).abs() < GEWEKE_THRESHOLD)
+            })
+    }
+
+    /// The ensemble mean and log standard deviation of every parameter at every iteration
+    fn ensemble_traces(&self) -> Vec<Vec<f64>> {
+        let n = self.nwalkers as f64;
+        let mut traces = Vec::with_capacity(2 * self.nparams);
+        for param in 0..self.nparams {
+            let mut means = Vec::with_capacity(self.niterations);
+            let mut spreads = Vec::with_capacity(self.niterations);
+            for iter in 0..self.niterations {
+                let values: Vec<f64> = (0..self.nwalkers)
+                    .map(|walker| self.get(param, walker, iter))
+                    .collect();
+                let mean = values.iter().sum::<f64>() / n;
+                let var = values.iter().map(|x| (x - mean).powi(2)).sum::<f64>() / n;
+                means.push(mean);
+                spreads.push(0.5 * var.ln());
+            }
+            traces.push(means);
+            if self.nwalkers > 1 {
+                traces.push(spreads);
+            }
+        }
+        traces
+    }
+}
+
+/// The Geweke z-score comparing the first tenth of `series` with its last half
+///
+/// The last half is taken as the stationary reference: its variance and autocorrelation time
+/// give the standard error of both means, as the first segment is too short, and possibly too
+/// contaminated by the transient, to estimate them itself.
+fn geweke(series: &[f64]) -> f64 {
+    let n = series.len();
+    let (first, last) = (&series[..n / 10], &series[n - n / 2..]);
+    let mean_first = first.iter().sum::<f64>() / first.len() as f64;
+    let mean_last = last.iter().sum::<f64>() / last.len() as f64;
+    let diff = mean_first - mean_last;
+    if diff == 0.0 {
+        return 0.0;
+    }
+
+    let var = last.iter().map(|x| (x - mean_last).powi(2)).sum::<f64>()
+        / (last.len() - 1) as f64;
+    if var == 0.0 {
+        return f64::INFINITY;
+    }
+    let tau = autocorr::integrated_time(&[last], 5.0).max(1.0);
+    diff / (tau * var * (1.0 / first.len() as f64 + 1.0 / last.len() as f64)).sqrt()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use guess::Guess;
+    use rand::{SeedableRng, StdRng};
+    use rand::distributions::{IndependentSample, Normal};
+    use testkit::CorrelatedGaussian;
+    use EnsembleSampler;
+
+    #[test]
+    fn test_stationary_chain() {
+        let mut rng = StdRng::from_seed(&[1]);
+        let normal = Normal::new(0.0, 1.0);
+        let mut chain = Chain::new(2, 8, 1000);
+        for iter in 0..1000 {
+            for walker in 0..8 {
+                let values = [normal.ind_sample(&mut rng), 5.0 + normal.ind_sample(&mut rng)];
+                chain.set_params(walker, iter, &values);
+            }
+        }
+        assert!(chain.suggested_discard().unwrap() <= 100);
+
+        assert_eq!(chain.split_at(50).0.suggested_discard(), None);
+    }
+
+    #[test]
+    fn test_drifting_chain() {
+        let mut chain = Chain::new(1, 4, 1000);
+        for iter in 0..1000 {
+            for walker in 0..4 {
+                chain.set(0, walker, iter, iter as f64 + walker as f64);
+            }
+        }
+        assert_eq!(chain.suggested_discard(), None);
+    }
+
+    #[test]
+    fn test_burn_in_from_a_small_ball() {
+        let model = CorrelatedGaussian::standard(2);
+        let nwalkers = 16;
+        let mut sampler = EnsembleSampler::new(nwalkers, 2, &model).unwrap();
+        sampler.seed(&[2]);
+        let p0: Vec<Guess> = (0..nwalkers)
+            .map(|i| Guess::new(&[20.0 + 1e-3 * (i % 4) as f64, -20.0 + 1e-3 * (i / 4) as f64]))
+            .collect();
+        sampler.run_mcmc(&p0, 2000).unwrap();
+        let chain = sampler.chain().unwrap();
+
+        let discard = chain.suggested_discard().unwrap();
+        assert!(discard > 0);
+        assert!(discard <= 1000);
+
+        // The walkers have reached the mode by the end of the burn-in
+        for walker in 0..nwalkers {
+            assert!(chain.get(0, walker, discard) < 10.0);
+        }
+    }
+}
@@ -0,0 +1,179 @@
+//! Models with cheaper approximations
+
+use std::time::Instant;
+
+use errors::{EmceeError, Result};
+use guess::Guess;
+use prob::Prob;
+
+/// A sequence of increasingly expensive models of the same posterior
+///
+/// The last model is the full posterior; the others are cheaper
+/// [approximations](trait.Prob.html#method.napproximations) to it, ordered from the cheapest,
+/// e.g. simulations at increasing resolution. The sampler screens each proposal with the
+/// approximations in turn using delayed acceptance, so the full model only runs for proposals
+/// which the approximations consider promising, while the samples still follow the full
+/// posterior. The number of proposals rejected early is counted in
+/// [`RunStats::nscreened`](struct.RunStats.html#structfield.nscreened).
+///
+/// ```rust
+/// # use emcee::{with_data, Cascade, EnsembleSampler, Guess, Prob};
+/// let coarse = with_data(0.1, |params: &Guess, step: &f64| {
+///     // a cheap, low resolution version of the model
+///     -0.5 * (params[0] * params[0] + step * step)
+/// });
+/// let full = with_data((), |params: &Guess, _: &()| -0.5 * params[0] * params[0]);
+///
+/// let model = Cascade::new(vec![Box::new(coarse), Box::new(full)]).unwrap();
+/// assert_eq!(model.napproximations(), 1);
+/// let sampler = EnsembleSampler::new(8, 1, &model).unwrap();
+/// # let _ = sampler;
+/// ```
+pub struct Cascade {
+    levels: Vec<Box<dyn Prob>>,
+}
+
+impl Cascade {
+    /// Create a cascade from models ordered from the cheapest to the full posterior
+    ///
+    /// Errors are handled by returning a [`Result`](errors/type.Result.html) which contains
+    /// [`EmceeError::InvalidInputs`](errors/enum.EmceeError.html) if `levels` is empty.
+    pub fn new(levels: Vec<Box<dyn Prob>>) -> Result<Cascade> {
+        if levels.is_empty() {
+            return Err(EmceeError::InvalidInputs(
+                "a cascade needs at least one model".into(),
+            ));
+        }
+        Ok(Cascade { levels })
+    }
+
+    /// The full model, i.e. the last level
+    fn full(&self) -> &dyn Prob {
+        &*self.levels[self.levels.len() - 1]
+    }
+}
+
+impl Prob for Cascade {
+    fn lnlike(&self, params: &Guess) -> f64 {
+        self.full().lnlike(params)
+    }
+
+    fn lnprior(&self, params: &Guess) -> f64 {
+        self.full().lnprior(params)
+    }
+
+    fn validate(&self, params: &Guess) -> ::std::result::Result<(), String> {
+        self.full().validate(params)
+    }
+
+    fn lnprob(&self, params: &Guess) -> f64 {
+        self.full().lnprob(params)
+    }
+
+    fn lnprob_with_deadline(&self, params: &Guess, deadline: Instant) -> Option<f64> {
+        self.full().lnprob_with_deadline(params, deadline)
+    }
+
+    fn napproximations(&self) -> usize {
+        self.levels.len() - 1
+    }
+
+    fn lnprob_approximation(&self, params: &Guess, level: usize) -> f64 {
+        self.levels[level].lnprob(params)
+    }
+
+    fn stored_position(&self, params: &Guess) -> Option<Guess> {
+        self.full().stored_position(params)
+    }
+
+    fn sampled_position(&self, stored: &Guess) -> Option<Guess> {
+        self.full().sampled_position(stored)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use EnsembleSampler;
+
+    /// Gaussian with standard deviation `sigma`
+    struct Gaussian {
+        sigma: f64,
+    }
+
+    impl Gaussian {
+        fn new(sigma: f64) -> Gaussian {
+            Gaussian { sigma }
+        }
+    }
+
+    impl Prob for Gaussian {
+        fn lnlike(&self, params: &Guess) -> f64 {
+            -0.5 * params.values.iter().map(|x| (x / self.sigma).powi(2)).sum::<f64>()
+        }
+
+        fn lnprior(&self, _params: &Guess) -> f64 {
+            0.0
+        }
+    }
+
+    fn initial() -> Vec<Guess> {
+        (0..8)
+            .map(|i| Guess::new(&[0.2 * i as f64 - 0.7, 0.1 * i as f64 - 0.3]))
+            .collect()
+    }
+
+    #[test]
+    fn test_levels() {
+        assert!(Cascade::new(Vec::new()).is_err());
+        let model = Cascade::new(vec![
+            Box::new(Gaussian::new(2.0)),
+            Box::new(Gaussian::new(1.0)),
+        ]).unwrap();
+        let guess = Guess::new(&[2.0, 0.0]);
+        assert_eq!(model.napproximations(), 1);
+        assert_approx_eq!(model.lnprob_approximation(&guess, 0), -0.5);
+        assert_approx_eq!(model.lnprob(&guess), -2.0);
+    }
+
+    #[test]
+    fn test_delayed_acceptance_samples_full_model() {
+        // The approximation is too wide, so it passes many proposals the full model rejects
+        let model = Cascade::new(vec![
+            Box::new(Gaussian::new(1.5)),
+            Box::new(Gaussian::new(1.0)),
+        ]).unwrap();
+        let mut sampler = EnsembleSampler::new(8, 2, &model).unwrap();
+        sampler.seed(&[5]);
+        sampler.run_mcmc(&initial(), 5000).unwrap();
+
+        let flatchain = sampler.flatchain().unwrap();
+        let n = flatchain.len() as f64;
+        for param in 0..2 {
+            let mean = flatchain.iter().map(|guess| guess[param]).sum::<f64>() / n;
+            let var = flatchain.iter().map(|guess| (guess[param] - mean).powi(2)).sum::<f64>() / n;
+            assert!(mean.abs() < 0.1, "mean = {}", mean);
+            assert!((var - 1.0).abs() < 0.15, "var = {}", var);
+        }
+
+        let stats = sampler.run_stats();
+        assert!(stats.nscreened > 0);
+        assert_eq!(stats.nevaluations + stats.nscreened + stats.ncache_hits, 8 + 8 * 5000);
+    }
+
+    #[test]
+    fn test_approximations_spare_full_model() {
+        let model = Cascade::new(vec![
+            Box::new(Gaussian::new(1.0)),
+            Box::new(Gaussian::new(1.0)),
+        ]).unwrap();
+        let mut sampler = EnsembleSampler::new(8, 2, &model).unwrap();
+        sampler.seed(&[6]);
+        sampler.run_mcmc(&initial(), 200).unwrap();
+
+        // With an exact approximation, every proposal which survives screening is accepted
+        let stats = sampler.run_stats().clone();
+        let naccepted: f64 = sampler.acceptance_fraction().iter().sum::<f64>() * 200.0;
+        assert_eq!(stats.nevaluations - 8, naccepted.round() as usize);
+    }
+}
@@ -0,0 +1,359 @@
+//! Checkpoints of the sampler, to continue long runs after a crash
+//!
+//! A checkpoint holds the current position and log probability of every walker, the number of
+//! iterations run and accepted, the thinning, the ensemble scaling and the stored chain and log
+//! probabilities of the last run, in a text format which records every value by its bit
+//! pattern so it is restored exactly. The random state of a seeded sampler is recorded as its
+//! seed and the number of words drawn since, so continuing from the checkpoint repeats an
+//! uninterrupted run. Any other generator cannot be written out, so
+//! saving a checkpoint reseeds the sampler from its own random number stream and stores the
+//! new seed instead; continuing from the checkpoint then repeats continuing from the moment it
+//! was saved.
+
+use std::fs::{self, File};
+use std::io::{BufRead, BufReader, BufWriter, Read, Write};
+use std::path::Path;
+use std::rc::Rc;
+
+use errors::{EmceeError, Result};
+use guess::Guess;
+use rand::Rng;
+use stores::ProbStore;
+use {Chain, EnsembleSampler, Prob, Step};
+
+const MAGIC: &str = "emcee-checkpoint 2";
+
+/// Checkpoints written before the position in the random stream was recorded
+const MAGIC_V1: &str = "emcee-checkpoint 1";
+
+/// Number of words used to reseed the sampler
+const SEED_LEN: usize = 4;
+
+impl<'a, T: Prob + Sync + Send + 'a> EnsembleSampler<'a, T> {
+    /// Save a checkpoint of the sampler after its last run to `path`
+    ///
+    /// The checkpoint is written to a temporary file next to `path`, which then replaces
+    /// `path`, so a crash while saving leaves the previous checkpoint intact.
+    ///
+    /// A [`seed`](#method.seed)ed sampler saves its seed and the number of random words drawn
+    /// since, so a sampler created by [`resume`](#method.resume) draws the same random numbers
+    /// as this one: sampling on from either gives the same samples as if the run had never been
+    /// interrupted. The state of any other generator cannot be written out, so the sampler is
+    /// reseeded from its own random number stream and the new seed is saved instead. Sampling
+    /// on from here with the state returned by the last run, using
+    /// [`set_initial_state`](#method.set_initial_state), then still gives the same samples as
+    /// sampling on from the resumed sampler.
+    ///
+    /// The moves, the model and the other settings of the sampler are not saved.
+    ///
+    /// Errors are handled by returning a [`Result`](errors/type.Result.html) which contains
+    /// [`EmceeError::InvalidInputs`](errors/enum.EmceeError.html) if the sampler has not run
+    /// since it was created or [`reset`](#method.reset), or
+    /// [`EmceeError::Io`](errors/enum.EmceeError.html) if writing fails.
+    pub fn save_state<P: AsRef<Path>>(&mut self, path: P) -> Result<()> {
+        if self.last_state.is_none() {
+            return Err(EmceeError::InvalidInputs(
+                "the sampler has not run, so there is no state to save".into(),
+            ));
+        }
+
+        let (seed, draws) = match self.seed.clone() {
+            Some(seed) => (seed, self.rng.ndraws()),
+            None => {
+                let seed: Vec<usize> = (0..SEED_LEN).map(|_| self.rng.gen()).collect();
+                self.seed(&seed);
+                (seed, 0)
+            }
+        };
+
+        let path = path.as_ref();
+        let mut tmp = path.as_os_str().to_owned();
+        tmp.push(".tmp");
+        {
+            let mut writer = BufWriter::new(File::create(&tmp)?);
+            self.write_checkpoint(&mut writer, &seed, draws)?;
+            writer.flush()?;
+            writer.get_ref().sync_all()?;
+        }
+        fs::rename(&tmp, path)?;
+        Ok(())
+    }
+
+    /// Create a sampler for `lnprob` from a checkpoint written by
+    /// [`save_state`](#method.save_state)
+    ///
+    /// The saved positions become the initial state of the next run, as with
+    /// [`set_initial_state`](#method.set_initial_state), so the positions passed to the next run
+    /// are ignored, and the saved chain is available from [`chain`](#method.chain) until then.
+    /// The sampler is single threaded and uses the default moves; configure it as the saved one
+    /// was before continuing.
+    ///
+    /// Errors are handled by returning a [`Result`](errors/type.Result.html) which contains
+    /// [`EmceeError::InvalidInputs`](errors/enum.EmceeError.html) if the file is not a valid
+    /// checkpoint, or [`EmceeError::Io`](errors/enum.EmceeError.html) if reading fails.
+    pub fn resume<P: AsRef<Path>>(path: P, lnprob: &'a T) -> Result<Self> {
+        EnsembleSampler::read_checkpoint(File::open(path)?, lnprob)
+    }
+
+    fn write_checkpoint<W: Write>(&self, mut writer: W, seed: &[usize], draws: u64) -> Result<()> {
+        let state = self.last_state.as_ref().expect("the sampler has run");
+        let bits = |values: &mut dyn Iterator<Item = f64>| -> String {
+            values
+                .map(|value| format!("{:016x}", value.to_bits()))
+                .collect::<Vec<_>>()
+                .join(" ")
+        };
+
+        writeln!(writer, "{}", MAGIC)?;
+        writeln!(writer, "nwalkers {}", self.nwalkers)?;
+        writeln!(writer, "nparams {}", self.dim)?;
+        let seed: Vec<String> = seed.iter().map(|word| word.to_string()).collect();
+        writeln!(writer, "seed {}", seed.join(" "))?;
+        writeln!(writer, "draws {}", draws)?;
+        writeln!(writer, "reference {}", self.reference)?;
+        writeln!(writer, "iterations {}", self.iterations)?;
+        let accepted: Vec<String> = self.naccepted.iter().map(|n| n.to_string()).collect();
+        writeln!(writer, "accepted {}", accepted.join(" "))?;
+        writeln!(writer, "thin {} {}", self.thin, self.stored_thin)?;
+        match self.scales {
+            Some((ref center, ref scale)) => writeln!(
+                writer,
+                "scales {}",
+                bits(&mut center.iter().chain(scale).cloned())
+            )?,
+            None => writeln!(writer, "scales")?,
+        }
+        for (guess, lnprob) in state.pos.iter().zip(state.lnprob.iter()) {
+            let mut values = guess.values.iter().chain(Some(lnprob)).cloned();
+            writeln!(writer, "walker {}", bits(&mut values))?;
+        }
+
+        match (self.chain.as_ref(), self.probstore.as_ref()) {
+            (Some(chain), Some(store)) => {
+                writeln!(writer, "chain {}", chain.niterations)?;
+                for iteration in 0..chain.niterations {
+                    for walker in 0..self.nwalkers {
+                        let mut values = (0..self.dim)
+                            .map(|param| chain.get(param, walker, iteration))
+                            .chain(Some(store.get(walker, iteration)));
+                        writeln!(writer, "sample {}", bits(&mut values))?;
+                    }
+                }
+            }
+            _ => writeln!(writer, "chain")?,
+        }
+        Ok(())
+    }
+
+    fn read_checkpoint<R: Read>(reader: R, lnprob: &'a T) -> Result<Self> {
+        let mut lines = BufReader::new(reader).lines();
+        let mut next = |key: &str| -> Result<String> {
+            let line = lines.next().ok_or_else(|| {
+                EmceeError::InvalidInputs(format!("checkpoint is missing `{}`", key))
+            })??;
+            if key.is_empty() {
+                return Ok(line);
+            } else if line == key {
+                return Ok(String::new());
+            }
+            match line.strip_prefix(format!("{} ", key).as_str()) {
+                Some(rest) => Ok(rest.to_string()),
+                None => Err(EmceeError::InvalidInputs(format!(
+                    "expected `{}` in checkpoint, found `{}`",
+                    key, line
+                ))),
+            }
+        };
+        let invalid =
+            |what: &str| EmceeError::InvalidInputs(format!("invalid {} in checkpoint", what));
+        let words = |line: String, what: &str| -> Result<Vec<usize>> {
+            line.split_whitespace()
+                .map(|word| word.parse().map_err(|_| invalid(what)))
+                .collect()
+        };
+        let values = |line: String, n: usize, what: &str| -> Result<Vec<f64>> {
+            let values = line
+                .split_whitespace()
+                .map(|word| u64::from_str_radix(word, 16).map(f64::from_bits))
+                .collect::<::std::result::Result<Vec<_>, _>>()
+                .map_err(|_| invalid(what))?;
+            if values.len() != n {
+                return Err(invalid(what));
+            }
+            Ok(values)
+        };
+
+        let magic = next("")?;
+        if magic != MAGIC && magic != MAGIC_V1 {
+            return Err(EmceeError::InvalidInputs("not an emcee checkpoint".into()));
+        }
+        let nwalkers = next("nwalkers")?.parse().map_err(|_| invalid("number of walkers"))?;
+        let dim = next("nparams")?.parse().map_err(|_| invalid("number of parameters"))?;
+        let seed = words(next("seed")?, "seed")?;
+        let draws: u64 = if magic == MAGIC {
+            next("draws")?.parse().map_err(|_| invalid("random state"))?
+        } else {
+            0
+        };
+        let reference = next("reference")?.parse().map_err(|_| invalid("reference mode"))?;
+        let iterations = next("iterations")?.parse().map_err(|_| invalid("iteration count"))?;
+        let naccepted = words(next("accepted")?, "acceptance counts")?;
+        let thin = words(next("thin")?, "thinning")?;
+        let scales = next("scales")?;
+
+        let mut sampler = EnsembleSampler::new(nwalkers, dim, lnprob)?;
+        if naccepted.len() != nwalkers {
+            return Err(invalid("acceptance counts"));
+        }
+        if thin.len() != 2 || thin.contains(&0) {
+            return Err(invalid("thinning"));
+        }
+        if reference {
+            sampler.set_reference_mode(&seed);
+        } else {
+            sampler.seed(&seed);
+        }
+        sampler.rng.skip(draws);
+        sampler.iterations = iterations;
+        sampler.naccepted = naccepted;
+        sampler.thin = thin[0];
+        sampler.stored_thin = thin[1];
+        if !scales.is_empty() {
+            let scales = values(scales, 2 * dim, "scales")?;
+            let (center, scale) = scales.split_at(dim);
+            sampler.scales = Some((center.to_vec(), scale.to_vec()));
+        }
+
+        let mut pos = Vec::with_capacity(nwalkers);
+        let mut lnprobs = Vec::with_capacity(nwalkers);
+        for _ in 0..nwalkers {
+            let walker = values(next("walker")?, dim + 1, "walker position")?;
+            pos.push(Guess::new(&walker[..dim]));
+            lnprobs.push(walker[dim]);
+        }
+
+        let chain = next("chain")?;
+        if !chain.is_empty() {
+            let niterations = chain.parse().map_err(|_| invalid("chain length"))?;
+            let mut chain = Chain::new(dim, nwalkers, niterations);
+            let mut store = ProbStore::new(nwalkers, niterations);
+            for iteration in 0..niterations {
+                for walker in 0..nwalkers {
+                    let sample = values(next("sample")?, dim + 1, "sample")?;
+                    chain.set_params(walker, iteration, &sample[..dim]);
+                    store.set(walker, iteration, sample[dim]);
+                }
+            }
+            sampler.chain = Some(chain);
+            sampler.probstore = Some(store);
+        }
+
+        let (pos, lnprobs) = (Rc::new(pos), Rc::new(lnprobs));
+        sampler.last_state = Some(Step {
+            pos: pos.clone(),
+            lnprob: lnprobs.clone(),
+            iteration: 0,
+        });
+        sampler.set_initial_state(Step {
+            pos,
+            lnprob: lnprobs,
+            iteration: 0,
+        });
+        Ok(sampler)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::{SeedableRng, StdRng};
+    use std::env;
+    use testkit::CorrelatedGaussian;
+
+    fn p0() -> Vec<Guess> {
+        Guess::new(&[0.5, -0.5]).create_initial_guess(8)
+    }
+
+    #[test]
+    fn test_resume() {
+        let path = env::temp_dir().join(format!("emcee-checkpoint-{}.txt", ::std::process::id()));
+        let model = CorrelatedGaussian::standard(2);
+        let mut sampler = EnsembleSampler::new(8, 2, &model).unwrap();
+        assert!(sampler.save_state(&path).is_err());
+
+        sampler.seed(&[1, 2]);
+        sampler.autoscale = true;
+        sampler.thin = 2;
+        let p0 = p0();
+        let state = sampler.run_mcmc(&p0, 20).unwrap();
+        sampler.save_state(&path).unwrap();
+        let saved_chain = sampler.chain().unwrap().clone();
+
+        let mut resumed = EnsembleSampler::resume(&path, &model).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(resumed.iterations, 20);
+        assert_eq!(resumed.acceptance_fraction(), sampler.acceptance_fraction());
+        assert_eq!(resumed.thin, 2);
+        assert_eq!(resumed.chain().unwrap().flatchain(), saved_chain.flatchain());
+        assert_eq!(resumed.flatprob(), sampler.flatprob());
+        resumed.autoscale = true;
+
+        // Continuing either sampler gives the same samples
+        let a = sampler.set_initial_state(state).run_mcmc(&[], 20).unwrap();
+        let b = resumed.run_mcmc(&[], 20).unwrap();
+        assert_eq!(a.pos, b.pos);
+        assert_eq!(a.lnprob, b.lnprob);
+        assert_eq!(resumed.flatchain(), sampler.flatchain());
+        assert_eq!(resumed.acceptance_fraction(), sampler.acceptance_fraction());
+
+        // ...which are those of a run which was never checkpointed
+        let mut uninterrupted = EnsembleSampler::new(8, 2, &model).unwrap();
+        uninterrupted.seed(&[1, 2]);
+        uninterrupted.autoscale = true;
+        uninterrupted.thin = 2;
+        let state = uninterrupted.run_mcmc(&p0, 20).unwrap();
+        let c = uninterrupted.set_initial_state(state).run_mcmc(&[], 20).unwrap();
+        assert_eq!(c.pos, b.pos);
+        assert_eq!(uninterrupted.flatchain(), resumed.flatchain());
+    }
+
+    #[test]
+    fn test_resume_unseeded() {
+        let path = env::temp_dir().join(format!("emcee-unseeded-{}.txt", ::std::process::id()));
+        let model = CorrelatedGaussian::standard(2);
+        let rng = StdRng::from_seed(&[3, 4][..]);
+        let mut sampler = EnsembleSampler::with_rng(8, 2, &model, rng).unwrap();
+        let state = sampler.run_mcmc(&p0(), 10).unwrap();
+        sampler.save_state(&path).unwrap();
+        let mut resumed = EnsembleSampler::resume(&path, &model).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        let a = sampler.set_initial_state(state).run_mcmc(&[], 10).unwrap();
+        let b = resumed.run_mcmc(&[], 10).unwrap();
+        assert_eq!(a.pos, b.pos);
+    }
+
+    #[test]
+    fn test_invalid_checkpoint() {
+        let model = CorrelatedGaussian::standard(2);
+        let read = |text: &str| EnsembleSampler::read_checkpoint(text.as_bytes(), &model);
+        assert!(read("").is_err());
+        assert!(read("emcee-replay 1\n").is_err());
+
+        let mut sampler = EnsembleSampler::new(8, 2, &model).unwrap();
+        sampler.run_mcmc(&p0(), 5).unwrap();
+        let mut buf = Vec::new();
+        sampler.write_checkpoint(&mut buf, &[1, 2, 3, 4], 7).unwrap();
+        let text = String::from_utf8(buf).unwrap();
+        assert_eq!(read(&text).unwrap().rng.ndraws(), 7);
+
+        // Checkpoints from before the random state was saved start the stream from the seed
+        let v1: Vec<&str> = text.lines().filter(|line| !line.starts_with("draws")).collect();
+        let v1 = v1.join("\n").replacen(MAGIC, MAGIC_V1, 1);
+        assert_eq!(read(&v1).unwrap().rng.ndraws(), 0);
+
+        // Truncated in the middle of the chain
+        let truncated: Vec<&str> = text.lines().take(text.lines().count() - 3).collect();
+        assert!(read(&truncated.join("\n")).is_err());
+    }
+}
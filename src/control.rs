@@ -0,0 +1,178 @@
+//! Controllers which decide how long to run the sampler
+
+use Step;
+
+/// Settings for [`run_until_independent`][run-until-independent]
+///
+/// The run is extended `check_interval` iterations at a time. After each extension the
+/// integrated autocorrelation time `tau` of each parameter is estimated from the full run, and
+/// sampling stops once `niterations / tau >= target` for every parameter, or after
+/// `max_iterations` iterations, whichever comes first.
+///
+/// [run-until-independent]: struct.EnsembleSampler.html#method.run_until_independent
+#[derive(Debug, Clone)]
+pub struct RunLength {
+    /// Required number of independent samples per walker, i.e. the run must be at least this
+    /// many autocorrelation times long (default 50)
+    pub target: f64,
+
+    /// Number of iterations between autocorrelation time estimates (default 100)
+    pub check_interval: usize,
+
+    /// Hard limit on the total number of iterations (default 100000)
+    pub max_iterations: usize,
+
+    /// Window factor used when estimating the autocorrelation time (default 5)
+    pub window: f64,
+}
+
+impl Default for RunLength {
+    fn default() -> Self {
+        RunLength {
+            target: 50.0,
+            check_interval: 100,
+            max_iterations: 100_000,
+            window: 5.0,
+        }
+    }
+}
+
+/// Outcome of [`run_until_independent`][run-until-independent]
+///
+/// [run-until-independent]: struct.EnsembleSampler.html#method.run_until_independent
+#[derive(Debug)]
+pub struct RunLengthReport {
+    /// The state of the walkers at the end of the run
+    pub state: Step,
+
+    /// Total number of iterations performed
+    pub niterations: usize,
+
+    /// Final estimate of the integrated autocorrelation time, one value per parameter
+    pub tau: Vec<f64>,
+
+    /// Estimated number of independent samples per walker, using the largest `tau`
+    pub nindependent: f64,
+
+    /// Whether the target was reached before the iteration limit
+    pub converged: bool,
+}
+
+/// Number of independent samples per walker given per-parameter autocorrelation times
+pub(crate) fn nindependent(niterations: usize, tau: &[f64]) -> f64 {
+    let max_tau = tau.iter().cloned().fold(0f64, f64::max);
+    if tau.iter().any(|value| !value.is_finite()) || max_tau <= 0.0 {
+        0.0
+    } else {
+        niterations as f64 / max_tau
+    }
+}
+
+/// Stopping criterion for [`run_mcmc_until_converged`][run-until-converged]
+///
+/// The chain is considered converged once it is longer than `ntau` times the integrated
+/// autocorrelation time `tau` of every parameter, and every `tau` has changed by less than a
+/// fraction `rtol` since the previous check, following the recommendation of the Python
+/// `emcee` documentation.
+///
+/// [run-until-converged]: struct.EnsembleSampler.html#method.run_mcmc_until_converged
+#[derive(Debug, Clone)]
+pub struct Convergence {
+    /// Minimum length of the chain in autocorrelation times (default 50)
+    pub ntau: f64,
+
+    /// Largest relative change of the autocorrelation times between checks (default 0.01)
+    pub rtol: f64,
+
+    /// Window factor used when estimating the autocorrelation time (default 5)
+    pub window: f64,
+}
+
+impl Default for Convergence {
+    fn default() -> Self {
+        Convergence {
+            ntau: 50.0,
+            rtol: 0.01,
+            window: 5.0,
+        }
+    }
+}
+
+impl Convergence {
+    /// Whether a chain of `niterations` iterations with autocorrelation times `tau`, which
+    /// were `previous` at the last check, has converged
+    pub(crate) fn is_met(&self, niterations: usize, tau: &[f64], previous: &[f64]) -> bool {
+        tau.iter().zip(previous).all(|(&tau, &previous)| {
+            tau.is_finite()
+                && tau > 0.0
+                && niterations as f64 > self.ntau * tau
+                && (tau - previous).abs() < self.rtol * tau
+        })
+    }
+}
+
+/// Settings for [`run_warmup`][run-warmup]
+///
+/// Every `interval` iterations, the tuning parameter of each adaptable move is updated with a
+/// Robbins-Monro step in log space,
+///
+/// ```text
+/// ln(theta_k+1) = ln(theta_k) + step / (k + 1)^decay * (acceptance_k - target)
+/// ```
+///
+/// where `acceptance_k` is the fraction of that move's proposals accepted during the last
+/// interval. The decaying step size means the tuning parameters settle down as warmup proceeds.
+///
+/// [run-warmup]: struct.EnsembleSampler.html#method.run_warmup
+#[derive(Debug, Clone)]
+pub struct AcceptanceTarget {
+    /// The acceptance fraction to aim for, applied to each move separately (default 0.3)
+    pub target: f64,
+
+    /// Number of iterations between adjustments (default 10)
+    pub interval: usize,
+
+    /// Initial size of the adaptation steps (default 1)
+    pub step: f64,
+
+    /// Exponent of the step size decay, between 0.5 and 1 (default 0.6)
+    pub decay: f64,
+}
+
+impl Default for AcceptanceTarget {
+    fn default() -> Self {
+        AcceptanceTarget {
+            target: 0.3,
+            interval: 10,
+            step: 1.0,
+            decay: 0.6,
+        }
+    }
+}
+
+/// One adjustment made during [`run_warmup`][run-warmup]
+///
+/// [run-warmup]: struct.EnsembleSampler.html#method.run_warmup
+#[derive(Debug, Clone, PartialEq)]
+pub struct AdaptationRecord {
+    /// The number of warmup iterations completed when the adjustment was made
+    pub iteration: usize,
+
+    /// Index of the adjusted move, in the order given to
+    /// [`set_moves`](struct.EnsembleSampler.html#method.set_moves)
+    pub move_idx: usize,
+
+    /// Acceptance fraction of the move during the last interval
+    pub acceptance: f64,
+
+    /// The tuning parameter after the adjustment
+    pub tuning: f64,
+}
+
+impl AcceptanceTarget {
+    /// The updated tuning parameter after the `k`th adjustment
+    pub(crate) fn adjust(&self, k: usize, tuning: f64, acceptance: f64) -> f64 {
+        let gamma = self.step / ((k + 1) as f64).powf(self.decay);
+        (tuning.ln() + gamma * (acceptance - self.target)).exp()
+    }
+}
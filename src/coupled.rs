@@ -0,0 +1,348 @@
+//! Two samplers exchanging states
+
+use rand::distributions::{IndependentSample, Range};
+use std::rc::Rc;
+use std::slice;
+
+use errors::{EmceeError, Result};
+use guess::Guess;
+use stores::{Chain, ProbStore};
+use running::RunningStats;
+use {locate_walker, stored_positions, EnsembleSampler, Prob, Step};
+
+/// Two independently configured samplers which occasionally propose to exchange walkers
+///
+/// The samplers may target different models with the same parameters, e.g. the posteriors of
+/// two subsets of the data, or one model at two temperatures, and may use different moves and
+/// numbers of walkers. They are run side by side, and every
+/// [`swap_interval`](#structfield.swap_interval) iterations [`nswaps`](#structfield.nswaps)
+/// pairs of walkers, one from each sampler, are chosen at random and proposed to swap
+/// positions. A swap of `x` in the first sampler with `y` in the second is accepted with
+/// probability
+///
+/// ```text
+/// min(1, p1(y) p2(x) / (p1(x) p2(y)))
+/// ```
+///
+/// which leaves the product of the two posteriors invariant, so each sampler still samples its
+/// own posterior while the exchanges let a well-mixing sampler carry the other between modes.
+/// This allows exchange schemes beyond standard parallel tempering, at the cost of two extra
+/// model evaluations per proposed swap.
+///
+/// The samplers are available through [`first`](#method.first) and [`second`](#method.second)
+/// and their mutable counterparts, e.g. to read their chains.
+pub struct CoupledSamplers<'a, T: Prob + Sync + Send + 'a, U: Prob + Sync + Send + 'a> {
+    first: EnsembleSampler<'a, T>,
+    second: EnsembleSampler<'a, U>,
+    nproposed: usize,
+    naccepted: usize,
+
+    /// The number of iterations between exchanges (default 10)
+    pub swap_interval: usize,
+
+    /// The number of swaps proposed at each exchange (default 1)
+    pub nswaps: usize,
+}
+
+impl<'a, T: Prob + Sync + Send + 'a, U: Prob + Sync + Send + 'a> CoupledSamplers<'a, T, U> {
+    /// Couple two configured samplers
+    ///
+    /// Errors are handled by returning a [`Result`](errors/type.Result.html) which contains
+    /// [`EmceeError::InvalidInputs`](errors/enum.EmceeError.html) if the samplers do not have
+    /// the same number of parameters.
+    pub fn new(first: EnsembleSampler<'a, T>, second: EnsembleSampler<'a, U>) -> Result<Self> {
+        if first.dim != second.dim {
+            return Err(EmceeError::InvalidInputs(format!(
+                "the samplers have {} and {} parameters",
+                first.dim, second.dim
+            )));
+        }
+
+        Ok(CoupledSamplers {
+            first,
+            second,
+            nproposed: 0,
+            naccepted: 0,
+            swap_interval: 10,
+            nswaps: 1,
+        })
+    }
+
+    /// Seed both samplers reproducibly
+    ///
+    /// The first sampler is seeded with `seed` followed by 0 and the second with `seed`
+    /// followed by 1. The swaps draw from the stream of the first sampler.
+    pub fn seed(&mut self, seed: &[usize]) {
+        let mut stream = seed.to_vec();
+        stream.push(0);
+        self.first.seed(&stream);
+        *stream.last_mut().expect("the stream is not empty") = 1;
+        self.second.seed(&stream);
+    }
+
+    /// The first sampler
+    pub fn first(&self) -> &EnsembleSampler<'a, T> {
+        &self.first
+    }
+
+    /// Mutable access to the first sampler
+    pub fn first_mut(&mut self) -> &mut EnsembleSampler<'a, T> {
+        &mut self.first
+    }
+
+    /// The second sampler
+    pub fn second(&self) -> &EnsembleSampler<'a, U> {
+        &self.second
+    }
+
+    /// Mutable access to the second sampler
+    pub fn second_mut(&mut self) -> &mut EnsembleSampler<'a, U> {
+        &mut self.second
+    }
+
+    /// Separate the samplers again
+    pub fn into_inner(self) -> (EnsembleSampler<'a, T>, EnsembleSampler<'a, U>) {
+        (self.first, self.second)
+    }
+
+    /// The fraction of proposed swaps which were accepted, or NaN if none were proposed
+    pub fn swap_acceptance_fraction(&self) -> f64 {
+        self.naccepted as f64 / self.nproposed as f64
+    }
+
+    /// Run both samplers for `niterations` iterations, exchanging walkers between them
+    ///
+    /// Each sampler stores its own chain if its
+    /// [`storechain`](struct.EnsembleSampler.html#structfield.storechain) is set. Every
+    /// iteration is stored, regardless of [`thin`](struct.EnsembleSampler.html#structfield.thin),
+    /// and an iteration followed by an exchange is stored as it was before the swaps. The final
+    /// states of both samplers are returned.
+    ///
+    /// Errors are handled by returning a [`Result`](errors/type.Result.html) which contains
+    /// [`EmceeError::InvalidInputs`](errors/enum.EmceeError.html) if `niterations` or the swap
+    /// interval is zero, or any error from running the samplers.
+    pub fn run_mcmc(
+        &mut self,
+        p0_first: &[Guess],
+        p0_second: &[Guess],
+        niterations: usize,
+    ) -> Result<(Step, Step)> {
+        if niterations == 0 || self.swap_interval == 0 {
+            return Err(EmceeError::InvalidInputs(
+                "the number of iterations and the swap interval must be positive".into(),
+            ));
+        }
+
+        let mut first = Record::new(&self.first, niterations);
+        let mut second = Record::new(&self.second, niterations);
+        let mut done = 0;
+        while done < niterations {
+            let n = self.swap_interval.min(niterations - done);
+            let mut result = first
+                .advance(&mut self.first, p0_first, done, n)
+                .and_then(|_| second.advance(&mut self.second, p0_second, done, n));
+            done += n;
+            if result.is_ok() && done < niterations {
+                result = self.exchange(&mut first, &mut second);
+            }
+            if let Err(e) = result {
+                self.first.initial_state = None;
+                self.second.initial_state = None;
+                return Err(e);
+            }
+        }
+
+        self.first.initial_state = None;
+        self.second.initial_state = None;
+        Ok((first.finish(&mut self.first), second.finish(&mut self.second)))
+    }
+
+    /// Propose [`nswaps`](#structfield.nswaps) swaps between the current states
+    fn exchange(&mut self, first: &mut Record, second: &mut Record) -> Result<()> {
+        let first_walkers = Range::new(0, self.first.nwalkers);
+        let second_walkers = Range::new(0, self.second.nwalkers);
+        let unit = Range::new(0f64, 1f64);
+        for _ in 0..self.nswaps {
+            let i = first_walkers.ind_sample(&mut self.first.rng);
+            let j = second_walkers.ind_sample(&mut self.first.rng);
+            let x = first.position(i);
+            let y = second.position(j);
+
+            let first_at_y = self
+                .first
+                .get_lnprob(slice::from_ref(&y))
+                .map_err(|err| locate_walker(err, |_| i))?[0];
+            let second_at_x = self
+                .second
+                .get_lnprob(slice::from_ref(&x))
+                .map_err(|err| locate_walker(err, |_| j))?[0];
+            let ln_ratio = first_at_y + second_at_x - first.lnprob(i) - second.lnprob(j);
+            self.nproposed += 1;
+            if unit.ind_sample(&mut self.first.rng).ln() < ln_ratio {
+                first.replace(i, y, first_at_y);
+                second.replace(j, x, second_at_x);
+                self.naccepted += 1;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// The state and stored chain of one sampler while it is coupled
+struct Record {
+    state: Option<Step>,
+    storage: Option<(Chain, ProbStore, RunningStats)>,
+}
+
+impl Record {
+    fn new<T: Prob + Sync + Send>(sampler: &EnsembleSampler<T>, niterations: usize) -> Record {
+        let storage = if sampler.storechain {
+            Some((
+                sampler.new_chain(niterations),
+                ProbStore::new(sampler.nwalkers, niterations),
+                RunningStats::new(sampler.dim, sampler.nwalkers),
+            ))
+        } else {
+            None
+        };
+        Record {
+            state: None,
+            storage,
+        }
+    }
+
+    fn advance<T: Prob + Sync + Send>(
+        &mut self,
+        sampler: &mut EnsembleSampler<T>,
+        p0: &[Guess],
+        start: usize,
+        niterations: usize,
+    ) -> Result<()> {
+        let storage = &mut self.storage;
+        let model = sampler.lnprob;
+        sampler.advance(p0, niterations, &mut self.state, |step| {
+            if let Some((ref mut chain, ref mut probstore, ref mut running)) = *storage {
+                for (walker_idx, guess) in stored_positions(model, &step.pos).iter().enumerate() {
+                    chain.set_params(walker_idx, start + step.iteration, &guess.values);
+                }
+                probstore.set_probs(start + step.iteration, &step.lnprob);
+                running.push(chain, start + step.iteration);
+            }
+        })
+    }
+
+    fn state(&self) -> &Step {
+        self.state.as_ref().expect("the sampler has run")
+    }
+
+    fn position(&self, walker: usize) -> Guess {
+        self.state().pos[walker].clone()
+    }
+
+    fn lnprob(&self, walker: usize) -> f64 {
+        self.state().lnprob[walker]
+    }
+
+    fn replace(&mut self, walker: usize, position: Guess, lnprob: f64) {
+        let state = self.state.as_mut().expect("the sampler has run");
+        Rc::make_mut(&mut state.pos)[walker] = position;
+        Rc::make_mut(&mut state.lnprob)[walker] = lnprob;
+    }
+
+    fn finish<T: Prob + Sync + Send>(self, sampler: &mut EnsembleSampler<T>) -> Step {
+        if let Some((chain, probstore, running)) = self.storage {
+            sampler.chain = Some(chain);
+            sampler.probstore = Some(probstore);
+            sampler.running = Some(running);
+        }
+        self.state.expect("the sampler has run")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A unit Gaussian centred on `centre` in every parameter
+    struct Gaussian {
+        centre: f64,
+    }
+
+    impl Prob for Gaussian {
+        fn lnlike(&self, params: &Guess) -> f64 {
+            -0.5 * params
+                .values
+                .iter()
+                .map(|x| (x - self.centre).powi(2))
+                .sum::<f64>()
+        }
+
+        fn lnprior(&self, _params: &Guess) -> f64 {
+            0.0
+        }
+    }
+
+    fn initial(nwalkers: usize, offset: f64) -> Vec<Guess> {
+        (0..nwalkers)
+            .map(|i| Guess::new(&[offset + 0.1 * i as f64 - 0.4, offset + 0.05 * i as f64]))
+            .collect()
+    }
+
+    #[test]
+    fn test_swaps_preserve_both_posteriors() {
+        let (a, b) = (Gaussian { centre: 0.0 }, Gaussian { centre: 1.0 });
+        let first = EnsembleSampler::new(16, 2, &a).unwrap();
+        let second = EnsembleSampler::new(12, 2, &b).unwrap();
+        let mut coupled = CoupledSamplers::new(first, second).unwrap();
+        coupled.seed(&[5]);
+        coupled.swap_interval = 2;
+        coupled.nswaps = 4;
+        assert!(coupled.swap_acceptance_fraction().is_nan());
+
+        let niterations = 3000;
+        let (state_a, state_b) = coupled
+            .run_mcmc(&initial(16, 0.0), &initial(12, 1.0), niterations)
+            .unwrap();
+        assert_eq!(state_a.pos.len(), 16);
+        assert_eq!(state_b.pos.len(), 12);
+
+        // Overlapping posteriors, so a good fraction of the swaps are accepted
+        let fraction = coupled.swap_acceptance_fraction();
+        assert!(fraction > 0.2 && fraction < 0.9, "fraction = {}", fraction);
+
+        for (sampler_mean, centre) in [
+            (coupled.first().online_stats().mean(), 0.0),
+            (coupled.second().online_stats().mean(), 1.0),
+        ]
+            .iter()
+        {
+            for value in sampler_mean {
+                assert_approx_eq!(*value, *centre, 0.15);
+            }
+        }
+
+        // The stored probabilities match the stored positions
+        let chain = coupled.first().chain().unwrap();
+        assert_eq!(chain.niterations, niterations);
+        let lnprob = coupled.first().flatprob().unwrap();
+        let flatchain = coupled.first().flatchain().unwrap();
+        for (guess, lnprob) in flatchain.iter().zip(&lnprob).skip(16 * 100).take(16 * 10) {
+            assert_approx_eq!(*lnprob, a.lnprob(guess), 1e-10);
+        }
+    }
+
+    #[test]
+    fn test_invalid_inputs() {
+        let (a, b) = (Gaussian { centre: 0.0 }, Gaussian { centre: 1.0 });
+        let first = EnsembleSampler::new(8, 2, &a).unwrap();
+        let wrong = EnsembleSampler::new(8, 3, &b).unwrap();
+        assert!(CoupledSamplers::new(first, wrong).is_err());
+
+        let first = EnsembleSampler::new(8, 2, &a).unwrap();
+        let second = EnsembleSampler::new(8, 2, &b).unwrap();
+        let mut coupled = CoupledSamplers::new(first, second).unwrap();
+        assert!(coupled.run_mcmc(&initial(8, 0.0), &initial(8, 1.0), 0).is_err());
+        coupled.swap_interval = 0;
+        assert!(coupled.run_mcmc(&initial(8, 0.0), &initial(8, 1.0), 10).is_err());
+    }
+}
@@ -0,0 +1,298 @@
+//! Export of chains as comma-separated values
+
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+
+use errors::{EmceeError, Result};
+use stores::{self, Chain, ProbStore};
+use {EnsembleSampler, Prob};
+
+impl Chain {
+    /// Write the samples after the first `discard` iterations, keeping every `thin`th
+    /// iteration, to a CSV file at `path`
+    ///
+    /// The file is a wide table with one row per sample: the columns are `iteration`, `walker`
+    /// and then the [parameter names](#method.set_param_names), or `param0`, `param1` and so on
+    /// if the chain has none, so it can be read straight into e.g. a pandas or polars data
+    /// frame; see [`write_csv_long`](#method.write_csv_long) for one row per value. The
+    /// iterations kept are those of [`get_chain`](#method.get_chain), and are
+    /// numbered as in the full chain. Names containing commas, quotes or line breaks are
+    /// quoted. Use [`EnsembleSampler::write_csv`](struct.EnsembleSampler.html#method.write_csv)
+    /// to include the log probability of each sample.
+    ///
+    /// Errors are handled by returning a [`Result`](errors/type.Result.html) which contains
+    /// [`EmceeError::InvalidInputs`](errors/enum.EmceeError.html) if `thin` is zero, or
+    /// [`EmceeError::Io`](errors/enum.EmceeError.html) if writing fails.
+    pub fn write_csv<P: AsRef<Path>>(&self, path: P, discard: usize, thin: usize) -> Result<()> {
+        self.write_csv_to(BufWriter::new(File::create(path)?), discard, thin)
+    }
+
+    /// Write the samples as CSV to `writer`, as in [`write_csv`](#method.write_csv)
+    ///
+    /// Errors are handled by returning a [`Result`](errors/type.Result.html) which contains
+    /// [`EmceeError::InvalidInputs`](errors/enum.EmceeError.html) if `thin` is zero, or
+    /// [`EmceeError::Io`](errors/enum.EmceeError.html) if writing fails.
+    pub fn write_csv_to<W: Write>(&self, writer: W, discard: usize, thin: usize) -> Result<()> {
+        write_table(writer, self, None, discard, thin)
+    }
+
+    /// Write the samples after the first `discard` iterations, keeping every `thin`th
+    /// iteration, to a long-format CSV file at `path`
+    ///
+    /// The table has one row per value, with the columns `iteration`, `walker`, `parameter`
+    /// and `value`, where the parameter is named as in the columns of
+    /// [`write_csv`](#method.write_csv). This is the layout plotting libraries facet on, e.g.
+    /// `facet_wrap(~parameter)` in ggplot2 or `sns.relplot(col="parameter")` in seaborn, so
+    /// large chains need not be reshaped after loading. The file is `nparams` times as long as
+    /// that of `write_csv`, and the iterations are selected and numbered in the same way.
+    ///
+    /// Errors are handled by returning a [`Result`](errors/type.Result.html) which contains
+    /// [`EmceeError::InvalidInputs`](errors/enum.EmceeError.html) if `thin` is zero, or
+    /// [`EmceeError::Io`](errors/enum.EmceeError.html) if writing fails.
+    pub fn write_csv_long<P: AsRef<Path>>(
+        &self,
+        path: P,
+        discard: usize,
+        thin: usize,
+    ) -> Result<()> {
+        self.write_csv_long_to(BufWriter::new(File::create(path)?), discard, thin)
+    }
+
+    /// Write the samples as long-format CSV to `writer`, as in
+    /// [`write_csv_long`](#method.write_csv_long)
+    ///
+    /// Errors are handled by returning a [`Result`](errors/type.Result.html) which contains
+    /// [`EmceeError::InvalidInputs`](errors/enum.EmceeError.html) if `thin` is zero, or
+    /// [`EmceeError::Io`](errors/enum.EmceeError.html) if writing fails.
+    pub fn write_csv_long_to<W: Write>(
+        &self,
+        writer: W,
+        discard: usize,
+        thin: usize,
+    ) -> Result<()> {
+        write_long_table(writer, self, None, discard, thin)
+    }
+}
+
+impl<'a, T: Prob + 'a> EnsembleSampler<'a, T> {
+    /// Write the stored samples and their log probabilities to a CSV file at `path`
+    ///
+    /// The table is that of [`Chain::write_csv`](struct.Chain.html#method.write_csv) with an
+    /// extra `lnprob` column after the parameters.
+    ///
+    /// Errors are handled by returning a [`Result`](errors/type.Result.html) which contains
+    /// [`EmceeError::InvalidInputs`](errors/enum.EmceeError.html) if no chain is stored or
+    /// `thin` is zero, or [`EmceeError::Io`](errors/enum.EmceeError.html) if writing fails.
+    pub fn write_csv<P: AsRef<Path>>(&self, path: P, discard: usize, thin: usize) -> Result<()> {
+        self.write_csv_to(BufWriter::new(File::create(path)?), discard, thin)
+    }
+
+    /// Write the stored samples and their log probabilities as CSV to `writer`, as in
+    /// [`write_csv`](#method.write_csv)
+    ///
+    /// Errors are handled by returning a [`Result`](errors/type.Result.html) which contains
+    /// [`EmceeError::InvalidInputs`](errors/enum.EmceeError.html) if no chain is stored or
+    /// `thin` is zero, or [`EmceeError::Io`](errors/enum.EmceeError.html) if writing fails.
+    pub fn write_csv_to<W: Write>(&self, writer: W, discard: usize, thin: usize) -> Result<()> {
+        let (chain, probstore) = self.stored()?;
+        write_table(writer, chain, Some(probstore), discard, thin)
+    }
+
+    /// Write the stored samples and their log probabilities to a long-format CSV file at `path`
+    ///
+    /// The table is that of [`Chain::write_csv_long`](struct.Chain.html#method.write_csv_long)
+    /// with the log probability of each sample as one more row, whose parameter is `lnprob`.
+    ///
+    /// Errors are handled by returning a [`Result`](errors/type.Result.html) which contains
+    /// [`EmceeError::InvalidInputs`](errors/enum.EmceeError.html) if no chain is stored or
+    /// `thin` is zero, or [`EmceeError::Io`](errors/enum.EmceeError.html) if writing fails.
+    pub fn write_csv_long<P: AsRef<Path>>(
+        &self,
+        path: P,
+        discard: usize,
+        thin: usize,
+    ) -> Result<()> {
+        self.write_csv_long_to(BufWriter::new(File::create(path)?), discard, thin)
+    }
+
+    /// Write the stored samples and their log probabilities as long-format CSV to `writer`, as
+    /// in [`write_csv_long`](#method.write_csv_long)
+    ///
+    /// Errors are handled by returning a [`Result`](errors/type.Result.html) which contains
+    /// [`EmceeError::InvalidInputs`](errors/enum.EmceeError.html) if no chain is stored or
+    /// `thin` is zero, or [`EmceeError::Io`](errors/enum.EmceeError.html) if writing fails.
+    pub fn write_csv_long_to<W: Write>(
+        &self,
+        writer: W,
+        discard: usize,
+        thin: usize,
+    ) -> Result<()> {
+        let (chain, probstore) = self.stored()?;
+        write_long_table(writer, chain, Some(probstore), discard, thin)
+    }
+
+    /// The stored chain and log probabilities, for the exports
+    pub(crate) fn stored(&self) -> Result<(&Chain, &ProbStore)> {
+        match (self.chain.as_ref(), self.probstore.as_ref()) {
+            (Some(chain), Some(probstore)) => Ok((chain, probstore)),
+            _ => Err(EmceeError::InvalidInputs(
+                "the sampler is not storing the chain".into(),
+            )),
+        }
+    }
+}
+
+/// The names of the parameter columns of an exported table
+pub(crate) fn param_columns(chain: &Chain) -> Vec<String> {
+    match chain.param_names() {
+        Some(names) => names.to_vec(),
+        None => (0..chain.nparams).map(|param| format!("param{}", param)).collect(),
+    }
+}
+
+fn write_table<W: Write>(
+    mut writer: W,
+    chain: &Chain,
+    probstore: Option<&ProbStore>,
+    discard: usize,
+    thin: usize,
+) -> Result<()> {
+    let iterations = stores::kept_iterations(chain.niterations, discard, thin)?;
+
+    let mut header = vec!["iteration".to_string(), "walker".to_string()];
+    header.extend(param_columns(chain).iter().map(|name| quote(name)));
+    if probstore.is_some() {
+        header.push("lnprob".to_string());
+    }
+    writeln!(writer, "{}", header.join(","))?;
+
+    let mut row = String::new();
+    for iter in iterations {
+        for walker in 0..chain.nwalkers {
+            row.clear();
+            row.push_str(&format!("{},{}", iter, walker));
+            for param in 0..chain.nparams {
+                row.push_str(&format!(",{}", chain.get(param, walker, iter)));
+            }
+            if let Some(probstore) = probstore {
+                row.push_str(&format!(",{}", probstore.get(walker, iter)));
+            }
+            writeln!(writer, "{}", row)?;
+        }
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+fn write_long_table<W: Write>(
+    mut writer: W,
+    chain: &Chain,
+    probstore: Option<&ProbStore>,
+    discard: usize,
+    thin: usize,
+) -> Result<()> {
+    let iterations = stores::kept_iterations(chain.niterations, discard, thin)?;
+    let names: Vec<String> = param_columns(chain).iter().map(|name| quote(name)).collect();
+
+    writeln!(writer, "iteration,walker,parameter,value")?;
+    for iter in iterations {
+        for walker in 0..chain.nwalkers {
+            for (param, name) in names.iter().enumerate() {
+                let value = chain.get(param, walker, iter);
+                writeln!(writer, "{},{},{},{}", iter, walker, name, value)?;
+            }
+            if let Some(probstore) = probstore {
+                writeln!(writer, "{},{},lnprob,{}", iter, walker, probstore.get(walker, iter))?;
+            }
+        }
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+/// Quote a header field if it contains a character special to CSV
+fn quote(field: &str) -> String {
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_write_csv() {
+        let mut chain = Chain::new(2, 2, 2);
+        for iter in 0..2 {
+            for walker in 0..2 {
+                chain.set_params(walker, iter, &[iter as f64 + 0.5, 2.0 * walker as f64]);
+            }
+        }
+
+        let mut out = Vec::new();
+        chain.write_csv_to(&mut out, 0, 1).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "iteration,walker,param0,param1\n0,0,0.5,0\n0,1,0.5,2\n1,0,1.5,0\n1,1,1.5,2\n"
+        );
+
+        chain.set_param_names(&["mass", "radius, km"]).unwrap();
+        let mut out = Vec::new();
+        chain.write_csv_to(&mut out, 0, 1).unwrap();
+        let text = String::from_utf8(out).unwrap();
+        assert!(text.starts_with("iteration,walker,mass,\"radius, km\"\n0,0,0.5,0\n"));
+
+        assert!(chain.write_csv_to(Vec::new(), 0, 0).is_err());
+    }
+
+    #[test]
+    fn test_write_csv_thinned() {
+        let mut chain = Chain::new(1, 2, 9);
+        let mut probstore = ProbStore::new(2, 9);
+        for iter in 0..9 {
+            for walker in 0..2 {
+                chain.set(0, walker, iter, (10 * iter + walker) as f64);
+                probstore.set(walker, iter, -(iter as f64));
+            }
+        }
+
+        // Iterations 5 and 8, as chain[discard + thin - 1::thin] in emcee
+        let mut out = Vec::new();
+        write_table(&mut out, &chain, Some(&probstore), 3, 3).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "iteration,walker,param0,lnprob\n5,0,50,-5\n5,1,51,-5\n8,0,80,-8\n8,1,81,-8\n"
+        );
+    }
+
+    #[test]
+    fn test_write_csv_long() {
+        let mut chain = Chain::new(2, 2, 3);
+        let mut probstore = ProbStore::new(2, 3);
+        for iter in 0..3 {
+            for walker in 0..2 {
+                chain.set_params(walker, iter, &[iter as f64 + 0.5, 2.0 * walker as f64]);
+                probstore.set(walker, iter, -(iter as f64));
+            }
+        }
+        chain.set_param_names(&["mass", "radius, km"]).unwrap();
+
+        let mut out = Vec::new();
+        write_long_table(&mut out, &chain, Some(&probstore), 1, 2).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "iteration,walker,parameter,value\n\
+             2,0,mass,2.5\n2,0,\"radius, km\",0\n2,0,lnprob,-2\n\
+             2,1,mass,2.5\n2,1,\"radius, km\",2\n2,1,lnprob,-2\n"
+        );
+
+        let mut out = Vec::new();
+        chain.write_csv_long_to(&mut out, 0, 1).unwrap();
+        assert_eq!(String::from_utf8(out).unwrap().lines().count(), 1 + 3 * 2 * 2);
+        assert!(chain.write_csv_long_to(Vec::new(), 0, 0).is_err());
+    }
+}
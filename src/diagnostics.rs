@@ -0,0 +1,325 @@
+//! Convergence diagnostics
+//!
+//! The split Gelman-Rubin statistic, R̂, compares the spread of the samples between walkers
+//! with the spread within each walker. Each walker's samples are split into a first and second
+//! half, so a walker still drifting towards the typical set also shows up as disagreement
+//! between its two halves. Once every sequence samples the same distribution the two estimates
+//! of the variance agree and R̂ approaches 1; a common rule of thumb is to keep running until
+//! every parameter is below 1.01 (Vehtari et al. 2021).
+//!
+//! [`EnsembleSampler::diagnostics`](../struct.EnsembleSampler.html#method.diagnostics) gathers
+//! R̂ together with the other standard checks into a
+//! [`DiagnosticReport`](struct.DiagnosticReport.html), which can be written as JSON for
+//! automated pipelines to decide whether a run is usable.
+
+use errors::{EmceeError, Result};
+use parallel;
+use running::{self, Welford};
+use stores::Chain;
+use {EnsembleSampler, Prob};
+
+/// The window factor used to estimate the autocorrelation times of a report
+const WINDOW: f64 = 5.0;
+
+/// A walker is reported as stuck if its acceptance fraction is below this fraction of the
+/// median of the ensemble
+pub const STUCK_ACCEPTANCE_RATIO: f64 = 0.25;
+
+/// The split Gelman-Rubin statistic of each parameter of `chain`
+///
+/// Each walker's stored samples are split in half, giving `2 * nwalkers` sequences, as in
+/// [`RunningStats::split_rhat`](../struct.RunningStats.html#method.split_rhat), which gives the
+/// same values for the chain as it is stored. When the number of iterations is odd the second
+/// half holds the extra sample. At least four stored iterations are required, otherwise the
+/// results are NaN.
+pub fn rhat(chain: &Chain) -> Vec<f64> {
+    let half = chain.niterations / 2;
+    let nthreads = parallel::nthreads(chain.nparams * chain.nwalkers * chain.niterations);
+    parallel::map(chain.nparams, nthreads, |param| {
+        let halves: Vec<Welford> = (0..chain.nwalkers)
+            .flat_map(|walker| {
+                vec![(0, half), (half, chain.niterations)]
+                    .into_iter()
+                    .map(move |(start, end)| {
+                        let mut acc = Welford::default();
+                        for iter in start..end {
+                            acc.add(chain.get(param, walker, iter));
+                        }
+                        acc
+                    })
+            })
+            .collect();
+        let refs: Vec<&Welford> = halves.iter().collect();
+        running::rhat(&refs)
+    })
+}
+
+/// The indices of the walkers whose acceptance fraction is below
+/// [`STUCK_ACCEPTANCE_RATIO`](constant.STUCK_ACCEPTANCE_RATIO.html) times the median, which
+/// are usually stuck in a local mode
+pub fn stuck_walkers(acceptance_fraction: &[f64]) -> Vec<usize> {
+    let mut sorted: Vec<f64> = acceptance_fraction
+        .iter()
+        .cloned()
+        .filter(|value| !value.is_nan())
+        .collect();
+    if sorted.is_empty() {
+        return Vec::new();
+    }
+    sorted.sort_by(|a, b| a.partial_cmp(b).unwrap());
+    let n = sorted.len();
+    let median = (sorted[(n - 1) / 2] + sorted[n / 2]) / 2.0;
+
+    acceptance_fraction
+        .iter()
+        .enumerate()
+        .filter(|&(_, &value)| value < STUCK_ACCEPTANCE_RATIO * median)
+        .map(|(walker, _)| walker)
+        .collect()
+}
+
+/// The standard convergence diagnostics of a run, from
+/// [`EnsembleSampler::diagnostics`](../struct.EnsembleSampler.html#method.diagnostics)
+///
+/// Per-parameter values are in the order of the parameters, and are NaN where the chain is too
+/// short to estimate them.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DiagnosticReport {
+    /// The number of stored iterations the diagnostics were computed from
+    pub niterations: usize,
+
+    /// The names of the parameters, if the chain has them
+    pub param_names: Option<Vec<String>>,
+
+    /// The acceptance fraction of each walker
+    pub acceptance_fraction: Vec<f64>,
+
+    /// The mean acceptance fraction of the walkers
+    pub mean_acceptance_fraction: f64,
+
+    /// The integrated autocorrelation time of each parameter, in stored iterations
+    pub tau: Vec<f64>,
+
+    /// The effective sample size of each parameter, the number of stored samples divided by
+    /// its autocorrelation time
+    pub ess: Vec<f64>,
+
+    /// The split Gelman-Rubin statistic of each parameter, from [`rhat`](fn.rhat.html)
+    pub rhat: Vec<f64>,
+
+    /// The walkers which appear to be stuck, from [`stuck_walkers`](fn.stuck_walkers.html)
+    pub stuck_walkers: Vec<usize>,
+
+    /// The Monte Carlo standard error of the mean of each parameter, from
+    /// [`Chain::mcse_mean`](../struct.Chain.html#method.mcse_mean)
+    pub mcse_mean: Vec<f64>,
+}
+
+impl DiagnosticReport {
+    /// Write the report as a JSON object with the same field names
+    ///
+    /// Values which are not finite, such as the NaN of a parameter whose autocorrelation time
+    /// could not be estimated, are written as `null`, and the parameter names are `null` if
+    /// the chain has none.
+    pub fn to_json(&self) -> String {
+        let names = match self.param_names {
+            Some(ref names) => {
+                let quoted: Vec<String> = names.iter().map(|name| json_string(name)).collect();
+                format!("[{}]", quoted.join(", "))
+            }
+            None => "null".to_string(),
+        };
+        let stuck: Vec<String> = self.stuck_walkers.iter().map(|w| w.to_string()).collect();
+
+        let fields = [
+            ("niterations", self.niterations.to_string()),
+            ("param_names", names),
+            ("acceptance_fraction", json_array(&self.acceptance_fraction)),
+            ("mean_acceptance_fraction", json_number(self.mean_acceptance_fraction)),
+            ("tau", json_array(&self.tau)),
+            ("ess", json_array(&self.ess)),
+            ("rhat", json_array(&self.rhat)),
+            ("stuck_walkers", format!("[{}]", stuck.join(", "))),
+            ("mcse_mean", json_array(&self.mcse_mean)),
+        ];
+        let body: Vec<String> = fields
+            .iter()
+            .map(|&(key, ref value)| format!("  \"{}\": {}", key, value))
+            .collect();
+        format!("{{\n{}\n}}", body.join(",\n"))
+    }
+}
+
+fn json_number(value: f64) -> String {
+    if value.is_finite() {
+        format!("{:?}", value)
+    } else {
+        "null".to_string()
+    }
+}
+
+fn json_array(values: &[f64]) -> String {
+    let values: Vec<String> = values.iter().map(|&value| json_number(value)).collect();
+    format!("[{}]", values.join(", "))
+}
+
+fn json_string(value: &str) -> String {
+    let mut out = String::with_capacity(value.len() + 2);
+    out.push('"');
+    for c in value.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            c if (c as u32) < 0x20 => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+impl<'a, T: Prob + Sync + Send + 'a> EnsembleSampler<'a, T> {
+    /// Compute the standard convergence diagnostics of the stored chain
+    ///
+    /// The report holds the acceptance fractions, autocorrelation times, effective sample
+    /// sizes, split R̂, stuck walkers and Monte Carlo standard errors, and can be written as
+    /// JSON with [`to_json`](diagnostics/struct.DiagnosticReport.html#method.to_json).
+    ///
+    /// Errors are handled by returning a [`Result`](errors/type.Result.html) which contains
+    /// [`EmceeError::InvalidInputs`](errors/enum.EmceeError.html) if no chain is stored.
+    pub fn diagnostics(&self) -> Result<DiagnosticReport> {
+        let chain = self.chain.as_ref().ok_or_else(|| {
+            EmceeError::InvalidInputs("the sampler is not storing the chain".into())
+        })?;
+
+        let nsamples = (chain.niterations * chain.nwalkers) as f64;
+        let tau = chain.integrated_time(WINDOW);
+        let acceptance_fraction = self.acceptance_fraction();
+        Ok(DiagnosticReport {
+            niterations: chain.niterations,
+            param_names: chain.param_names().map(|names| names.to_vec()),
+            mean_acceptance_fraction: self.mean_acceptance_fraction(),
+            stuck_walkers: stuck_walkers(&acceptance_fraction),
+            acceptance_fraction,
+            ess: tau.iter().map(|tau| nsamples / tau).collect(),
+            tau,
+            rhat: rhat(chain),
+            mcse_mean: chain
+                .mcse_mean()
+                .unwrap_or_else(|_| vec![f64::NAN; chain.nparams]),
+        })
+    }
+}
+
+impl Chain {
+    /// Whether the split R̂ of every parameter, from
+    /// [`diagnostics::rhat`](diagnostics/fn.rhat.html), is below `threshold`
+    ///
+    /// A chain too short to compute R̂ is not considered converged.
+    pub fn converged(&self, threshold: f64) -> bool {
+        rhat(self).iter().all(|&value| value < threshold)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use guess::Guess;
+    use running::RunningStats;
+    use testkit::CorrelatedGaussian;
+
+    #[test]
+    fn test_rhat() {
+        let (nparams, nwalkers, niterations) = (2, 4, 101);
+        let mut chain = Chain::new(nparams, nwalkers, niterations);
+        let mut stats = RunningStats::new(nparams, nwalkers);
+        for iter in 0..niterations {
+            for walker in 0..nwalkers {
+                let x = ((iter * 7 + walker * 3) % 5) as f64;
+                // The second parameter of the last walker sits far from the others
+                let offset = if walker == nwalkers - 1 { 10.0 } else { 0.0 };
+                chain.set_params(walker, iter, &[x, x + offset]);
+            }
+            stats.push(&chain, iter);
+        }
+
+        let values = rhat(&chain);
+        for (value, expected) in values.iter().zip(stats.split_rhat()) {
+            assert_approx_eq!(*value, expected);
+        }
+        assert!(values[0] < 1.01);
+        assert!(values[1] > 2.0);
+        assert!(!chain.converged(1.01));
+        assert!(chain.converged(values[1] + 0.1));
+
+        let short = Chain::new(nparams, nwalkers, 3);
+        assert!(rhat(&short).iter().all(|value| value.is_nan()));
+        assert!(!short.converged(1.01));
+    }
+
+    #[test]
+    fn test_stuck_walkers() {
+        assert_eq!(stuck_walkers(&[0.3, 0.05, 0.35, 0.0, 0.28]), vec![1, 3]);
+        assert_eq!(stuck_walkers(&[0.3, 0.2, 0.4, 0.25]), Vec::<usize>::new());
+        assert!(stuck_walkers(&[f64::NAN; 4]).is_empty());
+    }
+
+    #[test]
+    fn test_diagnostics() {
+        let model = CorrelatedGaussian::standard(2);
+        let mut sampler = EnsembleSampler::new(16, 2, &model).unwrap();
+        sampler.storechain = false;
+        assert!(sampler.diagnostics().is_err());
+
+        sampler.storechain = true;
+        sampler.seed(&[5]);
+        let p0: Vec<Guess> = (0..16)
+            .map(|i| Guess::new(&[0.1 * i as f64 - 0.8, 0.05 * i as f64]))
+            .collect();
+        sampler.run_mcmc(&p0, 2000).unwrap();
+        let report = sampler.diagnostics().unwrap();
+
+        let chain = sampler.chain().unwrap();
+        assert_eq!(report.niterations, 2000);
+        assert_eq!(report.param_names, None);
+        assert_eq!(report.acceptance_fraction, sampler.acceptance_fraction());
+        assert_eq!(report.tau, chain.integrated_time(5.0));
+        assert_approx_eq!(report.ess[0], 32000.0 / report.tau[0]);
+        assert_eq!(report.rhat, rhat(chain));
+        assert!(report.rhat.iter().all(|&value| value < 1.05));
+        assert!(report.stuck_walkers.is_empty());
+        assert_eq!(report.mcse_mean, chain.mcse_mean().unwrap());
+
+        let json = report.to_json();
+        assert!(json.starts_with("{\n  \"niterations\": 2000,\n  \"param_names\": null,\n"));
+        assert!(json.contains("\"stuck_walkers\": [],\n"));
+        assert!(json.ends_with("]\n}"));
+    }
+
+    #[test]
+    fn test_report_json() {
+        let report = DiagnosticReport {
+            niterations: 3,
+            param_names: Some(vec!["a".into(), "say \"b\"\\".into()]),
+            acceptance_fraction: vec![0.5, 0.25],
+            mean_acceptance_fraction: 0.375,
+            tau: vec![f64::NAN, 1.0],
+            ess: vec![f64::NAN, 6.0],
+            rhat: vec![f64::INFINITY, 1e-20],
+            stuck_walkers: vec![1],
+            mcse_mean: vec![0.1, 0.2],
+        };
+        let expected = r#"{
+  "niterations": 3,
+  "param_names": ["a", "say \"b\"\\"],
+  "acceptance_fraction": [0.5, 0.25],
+  "mean_acceptance_fraction": 0.375,
+  "tau": [null, 1.0],
+  "ess": [null, 6.0],
+  "rhat": [null, 1e-20],
+  "stuck_walkers": [1],
+  "mcse_mean": [0.1, 0.2]
+}"#;
+        assert_eq!(report.to_json(), expected);
+    }
+}
@@ -0,0 +1,127 @@
+//! Detection of duplicated walkers
+
+use stores::{Chain, ChainView};
+
+/// The walkers of a chain which duplicate another walker
+///
+/// Returned by [`Chain::find_duplicate_walkers`](struct.Chain.html#method.find_duplicate_walkers)
+/// and [`Chain::prune_duplicate_walkers`](struct.Chain.html#method.prune_duplicate_walkers).
+#[derive(Debug, Clone, PartialEq)]
+pub struct DuplicateWalkers {
+    /// The walkers kept for analysis, in increasing order: the first of each group of
+    /// identical walkers and every walker which duplicates no other
+    pub kept: Vec<usize>,
+
+    /// Each removed walker, paired with the kept walker whose trajectory it repeats
+    pub removed: Vec<(usize, usize)>,
+}
+
+impl DuplicateWalkers {
+    /// Whether any walker was found to be a duplicate
+    pub fn found(&self) -> bool {
+        !self.removed.is_empty()
+    }
+}
+
+impl Chain {
+    /// Find walkers whose trajectories are identical to that of an earlier walker
+    ///
+    /// Two walkers are duplicates when every parameter agrees within `tol` times the larger of
+    /// its magnitude and one at every iteration after the first `discard`. This happens when
+    /// walkers are started from the same position with the same random numbers, e.g. through
+    /// a seeding bug, or when one walker was copied into another; independent walkers of a
+    /// continuous posterior never agree for more than a few iterations. Duplicates carry no
+    /// extra information, but count towards the number of samples and shrink the apparent
+    /// spread between walkers, so statistics computed over them are overconfident.
+    ///
+    /// Use `tol = 0.0` to only match bit-identical trajectories. Nothing is found when no
+    /// iterations remain after discarding.
+    pub fn find_duplicate_walkers(&self, discard: usize, tol: f64) -> DuplicateWalkers {
+        let iterations = discard.min(self.niterations)..self.niterations;
+        let mut report = DuplicateWalkers {
+            kept: Vec::with_capacity(self.nwalkers),
+            removed: Vec::new(),
+        };
+        if iterations.start == iterations.end {
+            report.kept = (0..self.nwalkers).collect();
+            return report;
+        }
+
+        for walker in 0..self.nwalkers {
+            // Compare with the kept walkers only, so each duplicate points at the first of its
+            // group; most pairs differ at the first compared value
+            let original = report.kept.iter().cloned().find(|&other| {
+                iterations.clone().all(|iter| {
+                    (0..self.nparams).all(|param| {
+                        let (a, b) = (self.get(param, walker, iter), self.get(param, other, iter));
+                        a == b || (a - b).abs() <= tol * a.abs().max(b.abs()).max(1.0)
+                    })
+                })
+            });
+            match original {
+                Some(original) => report.removed.push((walker, original)),
+                None => report.kept.push(walker),
+            }
+        }
+        report
+    }
+
+    /// Restrict the chain to walkers which duplicate no other, for flattening and statistics
+    ///
+    /// The duplicates are found by
+    /// [`find_duplicate_walkers`](#method.find_duplicate_walkers) and excluded from the
+    /// returned [`ChainView`](struct.ChainView.html), whose
+    /// [`flatchain`](struct.ChainView.html#method.flatchain) and
+    /// [`integrated_time`](struct.ChainView.html#method.integrated_time) then count each
+    /// trajectory once. The report lists what was removed.
+    pub fn prune_duplicate_walkers(
+        &self,
+        discard: usize,
+        tol: f64,
+    ) -> (ChainView<'_>, DuplicateWalkers) {
+        let report = self.find_duplicate_walkers(discard, tol);
+        (self.select_walkers(&report.kept), report)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_duplicate_walkers() {
+        let mut chain = Chain::new(2, 5, 20);
+        for iter in 0..20 {
+            for walker in 0..5 {
+                let x = (iter as f64 * 0.7 + walker as f64 * 1.3).sin();
+                chain.set_params(walker, iter, &[x, x * walker as f64]);
+            }
+            // Walker 3 repeats walker 1 to rounding, and walker 4 repeats walker 0 after
+            // iteration 5
+            let (a, b) = (chain.get(0, 1, iter), chain.get(1, 1, iter));
+            chain.set_params(3, iter, &[a, b * (1.0 + 1e-14)]);
+            if iter >= 5 {
+                let (a, b) = (chain.get(0, 0, iter), chain.get(1, 0, iter));
+                chain.set_params(4, iter, &[a, b]);
+            }
+        }
+
+        let report = chain.find_duplicate_walkers(0, 1e-12);
+        assert_eq!(report.kept, vec![0, 1, 2, 4]);
+        assert_eq!(report.removed, vec![(3, 1)]);
+        assert!(report.found());
+
+        // Only bit-identical trajectories with no tolerance
+        assert!(!chain.find_duplicate_walkers(0, 0.0).found());
+
+        let (view, report) = chain.prune_duplicate_walkers(5, 1e-12);
+        assert_eq!(report.removed, vec![(3, 1), (4, 0)]);
+        assert_eq!(view.walkers(), &[0, 1, 2]);
+        assert_eq!(view.flatchain().len(), 60);
+
+        // Nothing to compare after discarding every iteration
+        let report = chain.find_duplicate_walkers(20, 0.0);
+        assert_eq!(report.kept, vec![0, 1, 2, 3, 4]);
+        assert!(!report.found());
+    }
+}
@@ -8,6 +8,24 @@ pub enum EmceeError {
 
     /// General message type for ad-hoc messages
     Msg(String),
+
+    /// Reading or writing a file failed
+    Io(::std::io::Error),
+
+    /// The model returned a log probability which is NaN or positive infinity, with the
+    /// sampler's [`nan_policy`](../struct.EnsembleSampler.html#structfield.nan_policy) set to
+    /// [`NanPolicy::Error`](../enum.NanPolicy.html)
+    NonFiniteProbability {
+        /// The walker whose position, or proposal, was evaluated
+        walker: usize,
+
+        /// The number of steps the sampler had taken, counted since it was created or
+        /// [`reset`](../struct.EnsembleSampler.html#method.reset)
+        iteration: usize,
+
+        /// The position at which the model was evaluated
+        position: Vec<f64>,
+    },
 }
 
 impl ::std::fmt::Display for EmceeError {
@@ -22,6 +40,18 @@ impl<'a> ::std::convert::From<&'a str> for EmceeError {
     }
 }
 
+impl ::std::convert::From<String> for EmceeError {
+    fn from(msg: String) -> EmceeError {
+        EmceeError::Msg(msg)
+    }
+}
+
+impl ::std::convert::From<::std::io::Error> for EmceeError {
+    fn from(e: ::std::io::Error) -> EmceeError {
+        EmceeError::Io(e)
+    }
+}
+
 /// Result alias which wraps [`EmceeError`][emcee-error]
 ///
 /// [emcee-error]: https://example.com
@@ -31,16 +61,19 @@ impl ::std::error::Error for EmceeError {
     fn description(&self) -> &str {
         use EmceeError::*;
 
-        let details = match *self {
-            InvalidInputs(ref msg) | Msg(ref msg) => msg,
-        };
-
-        details.as_str()
+        match *self {
+            InvalidInputs(ref msg) | Msg(ref msg) => msg.as_str(),
+            Io(_) => "I/O error",
+            NonFiniteProbability { .. } => "non-finite log probability",
+        }
     }
 
     fn cause(&self) -> Option<&dyn (::std::error::Error)> {
-        // We are not wrapping other error types, and our types do not have an
-        // underlying cause beyond the description passed via the creation
-        None
+        // Apart from I/O errors, our types do not have an underlying cause beyond the
+        // description passed via the creation
+        match *self {
+            EmceeError::Io(ref e) => Some(e),
+            _ => None,
+        }
     }
 }
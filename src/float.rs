@@ -0,0 +1,41 @@
+//! Floating point types for stored samples
+
+use std::fmt::Debug;
+
+/// A floating point type in which positions and samples can be held
+///
+/// Implemented for `f64`, the default everywhere, and `f32`. A
+/// [`Chain<f32>`](struct.Chain.html) or [`ProbStore<f32>`](struct.ProbStore.html) takes half
+/// the memory of the default, which helps with very long runs or many walkers, and copying a
+/// chain into the smaller type with [`to_precision`](struct.Chain.html#method.to_precision)
+/// keeps about seven significant figures, far finer than the Monte Carlo error of the
+/// samples. The sampler itself always computes in `f64`, but a sampler created with
+/// [`EnsembleSampler::with_precision`](struct.EnsembleSampler.html#method.with_precision) stores
+/// its chain in the smaller type as it runs.
+pub trait Float: Copy + Default + PartialEq + PartialOrd + Debug + Send + Sync + 'static {
+    /// Convert from `f64`, rounding to the nearest value of this type
+    fn from_f64(value: f64) -> Self;
+
+    /// Convert to `f64`, which is exact for both implementations
+    fn to_f64(self) -> f64;
+}
+
+impl Float for f64 {
+    fn from_f64(value: f64) -> Self {
+        value
+    }
+
+    fn to_f64(self) -> f64 {
+        self
+    }
+}
+
+impl Float for f32 {
+    fn from_f64(value: f64) -> Self {
+        value as f32
+    }
+
+    fn to_f64(self) -> f64 {
+        f64::from(self)
+    }
+}
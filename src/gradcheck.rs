@@ -0,0 +1,273 @@
+//! Verification of hand-coded gradients against finite differences
+//!
+//! Gradient-based methods such as HMC or MALA sample the wrong distribution, without any error,
+//! when the gradient they are given does not match the log probability. Before such a run,
+//! [`check_gradient`](fn.check_gradient.html) evaluates the gradient at walkers drawn at random
+//! from the initial ensemble and compares each component with a central finite difference of
+//! [`Prob::lnprob`](../trait.Prob.html#method.lnprob), reporting every component which
+//! disagrees.
+//!
+//! ```rust,ignore
+//! let report = gradcheck::check_gradient(
+//!     &model,
+//!     |params| model.grad_lnprob(params),
+//!     &p0,
+//!     &GradientCheck::default(),
+//!     &mut rng,
+//! )?;
+//! assert!(report.passed(), "{:?}", report.mismatches);
+//! ```
+
+use rand::Rng;
+
+use errors::{EmceeError, Result};
+use guess::Guess;
+use Prob;
+
+/// Settings of [`check_gradient`](fn.check_gradient.html)
+#[derive(Debug, Clone, PartialEq)]
+pub struct GradientCheck {
+    /// The number of walkers of the ensemble at which to check the gradient (default 5)
+    pub npoints: usize,
+
+    /// The finite difference step, relative to the magnitude of each parameter or absolute
+    /// for parameters smaller than one (default 1e-5)
+    pub step: f64,
+
+    /// The relative tolerance of each component (default 1e-4)
+    pub rtol: f64,
+
+    /// The absolute tolerance of each component (default 1e-6)
+    pub atol: f64,
+}
+
+impl Default for GradientCheck {
+    fn default() -> Self {
+        GradientCheck {
+            npoints: 5,
+            step: 1e-5,
+            rtol: 1e-4,
+            atol: 1e-6,
+        }
+    }
+}
+
+/// A gradient component which disagrees with its finite difference estimate
+#[derive(Debug, Clone, PartialEq)]
+pub struct GradientMismatch {
+    /// The index of the walker in the ensemble
+    pub walker: usize,
+
+    /// The index of the parameter
+    pub param: usize,
+
+    /// The component returned by the gradient
+    pub analytic: f64,
+
+    /// The central finite difference estimate
+    pub numerical: f64,
+}
+
+impl GradientMismatch {
+    /// The difference between the two values relative to the larger of them
+    pub fn relative_error(&self) -> f64 {
+        let scale = self.analytic.abs().max(self.numerical.abs());
+        (self.analytic - self.numerical).abs() / scale
+    }
+}
+
+/// The result of [`check_gradient`](fn.check_gradient.html)
+#[derive(Debug, Clone, PartialEq)]
+pub struct GradientReport {
+    /// The indices of the walkers at which the gradient was checked
+    pub walkers: Vec<usize>,
+
+    /// The number of components compared with their finite difference estimate
+    pub nchecked: usize,
+
+    /// The number of components which could not be compared, because the log probability is
+    /// not finite at the walker or within a step of it
+    pub nskipped: usize,
+
+    /// Every component outside the tolerance
+    pub mismatches: Vec<GradientMismatch>,
+}
+
+impl GradientReport {
+    /// Whether at least one component was compared and every compared component agreed
+    pub fn passed(&self) -> bool {
+        self.nchecked > 0 && self.mismatches.is_empty()
+    }
+
+    /// The mismatch with the largest relative error
+    pub fn worst(&self) -> Option<&GradientMismatch> {
+        self.mismatches
+            .iter()
+            .max_by(|a, b| a.relative_error().total_cmp(&b.relative_error()))
+    }
+}
+
+/// Compare a gradient of the log probability of `model` with central finite differences at
+/// walkers drawn without replacement from `ensemble`
+///
+/// `gradient` returns the derivative of [`lnprob`](../trait.Prob.html#method.lnprob) with
+/// respect to each parameter. A component agrees when it is within `atol + rtol * |value|` of
+/// the finite difference, widened by the rounding error of the difference itself, so large
+/// log probabilities do not raise false alarms. Walkers outside the support of the model are
+/// skipped, as are components whose finite difference crosses its boundary.
+///
+/// Errors are handled by returning a [`Result`](../errors/type.Result.html) which contains
+/// [`EmceeError::InvalidInputs`](../errors/enum.EmceeError.html) if the ensemble is empty,
+/// `npoints` is zero, the step is not positive, or the gradient has the wrong length.
+pub fn check_gradient<T, F, R>(
+    model: &T,
+    gradient: F,
+    ensemble: &[Guess],
+    settings: &GradientCheck,
+    rng: &mut R,
+) -> Result<GradientReport>
+where
+    T: Prob,
+    F: Fn(&Guess) -> Vec<f64>,
+    R: Rng,
+{
+    if ensemble.is_empty() || settings.npoints == 0 {
+        return Err(EmceeError::InvalidInputs(
+            "at least one point is required to check the gradient".into(),
+        ));
+    }
+    if settings.step.is_nan() || settings.step <= 0.0 {
+        return Err(EmceeError::InvalidInputs(
+            "the finite difference step must be positive".into(),
+        ));
+    }
+
+    // Partial Fisher-Yates shuffle of the walker indices
+    let npoints = settings.npoints.min(ensemble.len());
+    let mut indices: Vec<usize> = (0..ensemble.len()).collect();
+    for i in 0..npoints {
+        let j = rng.gen_range(i, ensemble.len());
+        indices.swap(i, j);
+    }
+    indices.truncate(npoints);
+
+    let mut report = GradientReport {
+        walkers: indices.clone(),
+        nchecked: 0,
+        nskipped: 0,
+        mismatches: Vec::new(),
+    };
+    for walker in indices {
+        let point = &ensemble[walker];
+        let ndim = point.values.len();
+        if !model.lnprob(point).is_finite() {
+            report.nskipped += ndim;
+            continue;
+        }
+
+        let analytic = gradient(point);
+        if analytic.len() != ndim {
+            return Err(EmceeError::InvalidInputs(format!(
+                "the gradient has {} components for {} parameters",
+                analytic.len(),
+                ndim
+            )));
+        }
+
+        for (param, &analytic) in analytic.iter().enumerate() {
+            let h = settings.step * point.values[param].abs().max(1.0);
+            let mut shifted = point.clone();
+            shifted.values[param] += h;
+            let above = model.lnprob(&shifted);
+            shifted.values[param] -= 2.0 * h;
+            let below = model.lnprob(&shifted);
+            if !above.is_finite() || !below.is_finite() {
+                report.nskipped += 1;
+                continue;
+            }
+
+            let numerical = (above - below) / (2.0 * h);
+            let roundoff = 100.0 * f64::EPSILON * (above.abs() + below.abs()) / h;
+            let tolerance =
+                settings.atol + settings.rtol * analytic.abs().max(numerical.abs()) + roundoff;
+            let error = (analytic - numerical).abs();
+            report.nchecked += 1;
+            if error.is_nan() || error > tolerance {
+                report.mismatches.push(GradientMismatch {
+                    walker,
+                    param,
+                    analytic,
+                    numerical,
+                });
+            }
+        }
+    }
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use autodiff::{Differentiable, GenericProb, Scalar};
+    use rand::{SeedableRng, StdRng};
+
+    /// A correlated Gaussian with a prior bounding the first parameter below
+    struct Model;
+
+    impl GenericProb for Model {
+        fn lnlike<S: Scalar>(&self, params: &[S]) -> S {
+            let (x, y) = (params[0], params[1]);
+            -(x * x - x * y * 1.2 + y * y) / 2.0 + (x * 3.0).sin()
+        }
+
+        fn lnprior<S: Scalar>(&self, params: &[S]) -> S {
+            if params[0].value() > -1.0 {
+                S::from_f64(0.0)
+            } else {
+                S::from_f64(-f64::INFINITY)
+            }
+        }
+    }
+
+    #[test]
+    fn test_check_gradient() {
+        let model = Differentiable::new(Model);
+        let ensemble: Vec<Guess> = (0..8)
+            .map(|i| Guess::new(&[0.3 * i as f64 - 0.9, 1.5 - 0.4 * i as f64]))
+            .collect();
+        let settings = GradientCheck {
+            npoints: 8,
+            ..GradientCheck::default()
+        };
+        let mut rng = StdRng::from_seed(&[1, 2, 3, 4]);
+
+        let report =
+            check_gradient(&model, |p| model.gradient(p).1, &ensemble, &settings, &mut rng)
+                .unwrap();
+        assert!(report.passed(), "{:?}", report.mismatches);
+        assert_eq!(report.nchecked, 16);
+
+        // A sign error in the second component is found at every walker
+        let wrong = |p: &Guess| {
+            let mut gradient = model.gradient(p).1;
+            gradient[1] = -gradient[1];
+            gradient
+        };
+        let report = check_gradient(&model, wrong, &ensemble, &settings, &mut rng).unwrap();
+        assert!(!report.passed());
+        assert!(report.mismatches.iter().all(|mismatch| mismatch.param == 1));
+        assert_eq!(report.mismatches.len(), 8);
+        assert!(report.worst().unwrap().relative_error() > 1.0);
+
+        // Walkers outside the prior are skipped
+        let outside = vec![Guess::new(&[-2.0, 0.0])];
+        let report =
+            check_gradient(&model, |p| model.gradient(p).1, &outside, &settings, &mut rng)
+                .unwrap();
+        assert_eq!(report.nskipped, 2);
+        assert!(!report.passed());
+
+        assert!(check_gradient(&model, |_| vec![0.0], &ensemble, &settings, &mut rng).is_err());
+        assert!(check_gradient(&model, |_| vec![], &[], &settings, &mut rng).is_err());
+    }
+}
@@ -0,0 +1,203 @@
+//! Gradients of the log posterior probability
+
+use errors::{EmceeError, Result};
+use guess::Guess;
+use parallel;
+use prob::Prob;
+
+/// A model which provides the gradient of its log posterior probability
+///
+/// Gradient-based methods need the derivative of
+/// [`lnprob`](trait.Prob.html#method.lnprob) with respect to each parameter. Models written
+/// with [`autodiff`](autodiff/index.html) provide exact gradients through
+/// [`Differentiable`](autodiff/struct.Differentiable.html), hand-coded gradients can be
+/// verified with [`gradcheck`](gradcheck/index.html), and any other model can be wrapped in
+/// [`FiniteDifference`](struct.FiniteDifference.html).
+pub trait GradLnProb: Prob {
+    /// Computes the gradient of the log posterior probability with respect to each parameter
+    fn grad_lnprob(&self, params: &Guess) -> Vec<f64>;
+
+    /// Computes the log posterior probability and its gradient together
+    ///
+    /// Override this when the two share work. The default calls
+    /// [`lnprob`](trait.Prob.html#method.lnprob) and
+    /// [`grad_lnprob`](#tymethod.grad_lnprob) separately.
+    fn lnprob_and_grad(&self, params: &Guess) -> (f64, Vec<f64>) {
+        (self.lnprob(params), self.grad_lnprob(params))
+    }
+}
+
+/// Provides the gradient of a plain model by central finite differences
+///
+/// Each component costs two evaluations of the model, at the position shifted by `±h` along
+/// the parameter, where `h` is the step times the magnitude of the parameter, or the step
+/// itself for parameters smaller than one. Where only one of the two shifted positions is
+/// inside the support of the model, the one-sided difference is used instead, and outside the
+/// support the gradient is zero. A
+/// [`threaded`](struct.FiniteDifference.html#method.threaded) wrapper evaluates the shifted
+/// positions in parallel, which pays off for expensive models with several parameters.
+///
+/// ```rust
+/// # use emcee::{with_data, FiniteDifference, GradLnProb, Guess};
+/// let model = with_data(vec![1.0, 2.0, 3.0], |params: &Guess, data: &Vec<f64>| {
+///     -0.5 * data.iter().map(|y| (y - params[0]).powi(2)).sum::<f64>()
+/// });
+/// let model = FiniteDifference::new(model, 1e-6).unwrap();
+/// let gradient = model.grad_lnprob(&Guess::new(&[1.0]));
+/// assert!((gradient[0] - 3.0).abs() < 1e-6);
+/// ```
+#[derive(Debug, Clone)]
+pub struct FiniteDifference<T: Prob> {
+    model: T,
+    step: f64,
+    n_threads: usize,
+}
+
+impl<T: Prob> FiniteDifference<T> {
+    /// Wrap `model`, differencing with the relative `step`
+    ///
+    /// Errors are handled by returning a [`Result`](errors/type.Result.html) which contains
+    /// [`EmceeError::InvalidInputs`](errors/enum.EmceeError.html) if `step` is not positive
+    /// and finite.
+    pub fn new(model: T, step: f64) -> Result<Self> {
+        FiniteDifference::threaded(model, step, 1)
+    }
+
+    /// Wrap `model`, differencing with the relative `step` and evaluating the shifted positions
+    /// on up to `n_threads` threads
+    ///
+    /// The gradient does not depend on the number of threads.
+    ///
+    /// Errors are handled by returning a [`Result`](errors/type.Result.html) which contains
+    /// [`EmceeError::InvalidInputs`](errors/enum.EmceeError.html) if `step` is not positive
+    /// and finite.
+    pub fn threaded(model: T, step: f64, n_threads: usize) -> Result<Self> {
+        if !step.is_finite() || step <= 0.0 {
+            return Err(EmceeError::InvalidInputs(format!(
+                "the finite difference step must be positive, not {}",
+                step
+            )));
+        }
+        Ok(FiniteDifference {
+            model,
+            step,
+            n_threads: n_threads.max(1),
+        })
+    }
+
+    /// The wrapped model
+    pub fn model(&self) -> &T {
+        &self.model
+    }
+
+    /// Recover the wrapped model
+    pub fn into_inner(self) -> T {
+        self.model
+    }
+
+    /// The relative finite difference step
+    pub fn step(&self) -> f64 {
+        self.step
+    }
+}
+
+impl<T: Prob> Prob for FiniteDifference<T> {
+    fn lnlike(&self, params: &Guess) -> f64 {
+        self.model.lnlike(params)
+    }
+
+    fn lnprior(&self, params: &Guess) -> f64 {
+        self.model.lnprior(params)
+    }
+
+    fn validate(&self, params: &Guess) -> ::std::result::Result<(), String> {
+        self.model.validate(params)
+    }
+
+    fn lnprob(&self, params: &Guess) -> f64 {
+        self.model.lnprob(params)
+    }
+}
+
+impl<T: Prob> GradLnProb for FiniteDifference<T> {
+    fn grad_lnprob(&self, params: &Guess) -> Vec<f64> {
+        self.lnprob_and_grad(params).1
+    }
+
+    fn lnprob_and_grad(&self, params: &Guess) -> (f64, Vec<f64>) {
+        let ndim = params.values.len();
+        let lnprob = self.model.lnprob(params);
+        if !lnprob.is_finite() {
+            return (lnprob, vec![0.0; ndim]);
+        }
+
+        let steps: Vec<f64> = params
+            .values
+            .iter()
+            .map(|x| self.step * x.abs().max(1.0))
+            .collect();
+        // Shifted positions 2i and 2i + 1 are above and below the position along parameter i
+        let shifted = parallel::map(2 * ndim, self.n_threads, |k| {
+            let mut position = params.clone();
+            let sign = if k % 2 == 0 { 1.0 } else { -1.0 };
+            position.values[k / 2] += sign * steps[k / 2];
+            self.model.lnprob(&position)
+        });
+
+        let gradient = steps
+            .iter()
+            .zip(shifted.chunks(2))
+            .map(|(h, pair)| match (pair[0].is_finite(), pair[1].is_finite()) {
+                (true, true) => (pair[0] - pair[1]) / (2.0 * h),
+                (true, false) => (pair[0] - lnprob) / h,
+                (false, true) => (lnprob - pair[1]) / h,
+                (false, false) => 0.0,
+            })
+            .collect();
+        (lnprob, gradient)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A correlated Gaussian with a prior bounding the first parameter below
+    struct Model;
+
+    impl Prob for Model {
+        fn lnlike(&self, params: &Guess) -> f64 {
+            let (x, y) = (params[0], params[1]);
+            -(x * x - 1.2 * x * y + y * y) / 2.0
+        }
+
+        fn lnprior(&self, params: &Guess) -> f64 {
+            if params[0] >= -1.0 {
+                0.0
+            } else {
+                -f64::INFINITY
+            }
+        }
+    }
+
+    #[test]
+    fn test_finite_difference() {
+        let model = FiniteDifference::new(Model, 1e-6).unwrap();
+        let (lnprob, gradient) = model.lnprob_and_grad(&Guess::new(&[0.5, 2.0]));
+        assert_eq!(lnprob, Model.lnprob(&Guess::new(&[0.5, 2.0])));
+        assert_approx_eq!(gradient[0], -0.5 + 1.2, 1e-6);
+        assert_approx_eq!(gradient[1], 0.3 - 2.0, 1e-6);
+
+        // The evaluations do not depend on the number of threads
+        let threaded = FiniteDifference::threaded(Model, 1e-6, 4).unwrap();
+        assert_eq!(threaded.grad_lnprob(&Guess::new(&[0.5, 2.0])), gradient);
+
+        // One-sided at the edge of the support, zero outside it
+        let edge = model.grad_lnprob(&Guess::new(&[-1.0, 0.0]));
+        assert_approx_eq!(edge[0], 1.0, 1e-5);
+        assert_eq!(model.grad_lnprob(&Guess::new(&[-2.0, 0.0])), vec![0.0, 0.0]);
+
+        assert!(FiniteDifference::new(Model, 0.0).is_err());
+        assert!(FiniteDifference::new(Model, f64::NAN).is_err());
+    }
+}
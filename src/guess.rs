@@ -1,30 +1,44 @@
 use rand::Rng;
-use rand::distributions::{IndependentSample, Normal};
+use rand::distributions::{IndependentSample, Normal, Range};
+
+use errors::{EmceeError, Result};
+use float::Float;
 
 /// Represents an initial guess
 ///
-/// This is the starting position for the sampling. All values are 64-bit floating point
-/// numbers, and are contained in a [`Vec`](https://doc.rust-lang.org/std/vec/struct.Vec.html).
-#[derive(Debug, Clone)]
-pub struct Guess {
+/// This is the starting position for the sampling. The values are 64-bit floating point
+/// numbers unless another [`Float`](trait.Float.html) type is given, and are contained in a
+/// [`Vec`](https://doc.rust-lang.org/std/vec/struct.Vec.html).
+#[derive(Debug, Clone, PartialEq)]
+pub struct Guess<F: Float = f64> {
     /// A position in parameter space
-    pub values: Vec<f64>,
+    pub values: Vec<F>,
 }
 
-impl ::std::ops::Index<usize> for Guess {
-    type Output = f64;
+impl<F: Float> ::std::ops::Index<usize> for Guess<F> {
+    type Output = F;
 
     fn index(&self, idx: usize) -> &Self::Output {
         &self.values[idx]
     }
 }
 
-impl ::std::ops::IndexMut<usize> for Guess {
-    fn index_mut(&mut self, idx: usize) -> &mut f64 {
+impl<F: Float> ::std::ops::IndexMut<usize> for Guess<F> {
+    fn index_mut(&mut self, idx: usize) -> &mut F {
         &mut self.values[idx]
     }
 }
 
+impl<F: Float> Guess<F> {
+    /// Copy the guess into another floating point type, rounding each value to the nearest
+    /// value of the new type
+    pub fn to_precision<G: Float>(&self) -> Guess<G> {
+        Guess {
+            values: self.values.iter().map(|value| G::from_f64(value.to_f64())).collect(),
+        }
+    }
+}
+
 impl Guess {
     /// Create a guess from a slice
     pub fn new(values: &[f64]) -> Self {
@@ -33,6 +47,16 @@ impl Guess {
         }
     }
 
+    /// The value of the parameter called `name` given the parameter `names`, e.g. those of
+    /// the [chain](struct.Chain.html#method.param_names) the guess was drawn from, or `None`
+    /// if there is no parameter of that name
+    pub fn get<S: AsRef<str>>(&self, names: &[S], name: &str) -> Option<f64> {
+        names
+            .iter()
+            .position(|candidate| candidate.as_ref() == name)
+            .map(|idx| self.values[idx])
+    }
+
     /// Create a guess vector, perturbed from the starting position
     ///
     /// Use this to generate the starting guess for the sampling, where there is one guess
@@ -56,6 +80,123 @@ impl Guess {
             .collect()
     }
 
+    /// Draw `nwalkers` positions from a Gaussian ball around `center`
+    ///
+    /// Parameter `i` of each walker is drawn from a normal distribution with mean `center[i]`
+    /// and standard deviation `radius[i]`, which should be small compared with the width of the
+    /// posterior in that parameter, as in the usual `p0 + 1e-4 * randn(nwalkers, ndim)` of
+    /// Python `emcee`.
+    ///
+    /// Errors are handled by returning a [`Result`](errors/type.Result.html) which contains
+    /// [`EmceeError::InvalidInputs`](errors/enum.EmceeError.html) if `center` and `radius` have
+    /// different lengths, any value is not finite, a radius is negative, or two walkers are
+    /// identical, e.g. because every radius is zero.
+    pub fn ball<R: Rng>(
+        center: &[f64],
+        radius: &[f64],
+        nwalkers: usize,
+        mut rng: &mut R,
+    ) -> Result<Vec<Guess>> {
+        if center.len() != radius.len() {
+            return Err(EmceeError::InvalidInputs(format!(
+                "{} radii were given for {} parameters",
+                radius.len(),
+                center.len()
+            )));
+        }
+        if center.iter().chain(radius).any(|x| !x.is_finite()) || radius.iter().any(|&r| r < 0.0)
+        {
+            return Err(EmceeError::InvalidInputs(
+                "the centre must be finite and the radii finite and non-negative".into(),
+            ));
+        }
+
+        let normal = Normal::new(0.0, 1.0);
+        let walkers: Vec<Guess> = (0..nwalkers)
+            .map(|_| {
+                let values = center
+                    .iter()
+                    .zip(radius)
+                    .map(|(c, r)| c + r * normal.ind_sample(&mut rng))
+                    .collect();
+                Guess { values }
+            })
+            .collect();
+        Guess::check_distinct(&walkers)?;
+        Ok(walkers)
+    }
+
+    /// Draw `nwalkers` positions uniformly from the box between `lo` and `hi`
+    ///
+    /// Suits priors with hard bounds and no better starting point, at the cost of a longer
+    /// burn-in than a small [`ball`](#method.ball) around a good fit.
+    ///
+    /// Errors are handled by returning a [`Result`](errors/type.Result.html) which contains
+    /// [`EmceeError::InvalidInputs`](errors/enum.EmceeError.html) if `lo` and `hi` have
+    /// different lengths, any bound is not finite, or any lower bound is not below its upper
+    /// bound.
+    pub fn uniform_in_bounds<R: Rng>(
+        lo: &[f64],
+        hi: &[f64],
+        nwalkers: usize,
+        mut rng: &mut R,
+    ) -> Result<Vec<Guess>> {
+        if lo.len() != hi.len() {
+            return Err(EmceeError::InvalidInputs(format!(
+                "{} lower and {} upper bounds were given",
+                lo.len(),
+                hi.len()
+            )));
+        }
+        if lo.iter().zip(hi).any(|(lo, hi)| !(lo.is_finite() && hi.is_finite() && lo < hi)) {
+            return Err(EmceeError::InvalidInputs(
+                "each bound must be finite, with the lower below the upper".into(),
+            ));
+        }
+
+        let ranges: Vec<Range<f64>> =
+            lo.iter().zip(hi).map(|(&lo, &hi)| Range::new(lo, hi)).collect();
+        let walkers: Vec<Guess> = (0..nwalkers)
+            .map(|_| Guess {
+                values: ranges.iter().map(|range| range.ind_sample(&mut rng)).collect(),
+            })
+            .collect();
+        Guess::check_distinct(&walkers)?;
+        Ok(walkers)
+    }
+
+    /// Check that no two walkers of an ensemble are at the same position
+    ///
+    /// Identical walkers can never separate under the stretch move, or any other move which
+    /// proposes along the line between two walkers, so the ensemble effectively loses a
+    /// walker. Starting positions built by hand are worth checking before a run.
+    ///
+    /// Errors are handled by returning a [`Result`](errors/type.Result.html) which contains
+    /// [`EmceeError::InvalidInputs`](errors/enum.EmceeError.html) naming the first pair of
+    /// identical walkers.
+    pub fn check_distinct(walkers: &[Guess]) -> Result<()> {
+        let mut order: Vec<usize> = (0..walkers.len()).collect();
+        // Adding zero turns -0 into +0, which compare equal but sort apart
+        order.sort_by(|&a, &b| {
+            let (a, b) = (&walkers[a].values, &walkers[b].values);
+            a.iter()
+                .zip(b)
+                .map(|(x, y)| (x + 0.0).total_cmp(&(y + 0.0)))
+                .find(|ordering| ordering.is_ne())
+                .unwrap_or_else(|| a.len().cmp(&b.len()))
+        });
+        for pair in order.windows(2) {
+            if walkers[pair[0]].values == walkers[pair[1]].values {
+                let (first, second) = (pair[0].min(pair[1]), pair[0].max(pair[1]));
+                return Err(EmceeError::InvalidInputs(format!(
+                    "walkers {} and {} are at the same position",
+                    first, second
+                )));
+            }
+        }
+        Ok(())
+    }
+
     /// Returns if the guess vector contains infinite values
     pub fn contains_infs(&self) -> bool {
         self.values.iter().any(|val| val.is_infinite())
@@ -110,6 +251,35 @@ mod tests {
         assert_eq!(initial.len(), 10);
     }
 
+    #[test]
+    fn test_initial_ensembles() {
+        let mut rng = StdRng::from_seed(&[1, 2, 3, 4]);
+        let ball = Guess::ball(&[1.0, -2.0], &[0.1, 0.0], 50, &mut rng).unwrap();
+        assert_eq!(ball.len(), 50);
+        assert!(ball.iter().all(|guess| (guess[0] - 1.0).abs() < 0.5 && guess[1] == -2.0));
+        assert!(Guess::ball(&[1.0, -2.0], &[0.0, 0.0], 2, &mut rng).is_err());
+        assert!(Guess::ball(&[1.0, -2.0], &[0.1], 2, &mut rng).is_err());
+        assert!(Guess::ball(&[1.0], &[-0.1], 2, &mut rng).is_err());
+
+        let uniform = Guess::uniform_in_bounds(&[0.0, -1.0], &[1.0, 1.0], 50, &mut rng).unwrap();
+        assert!(uniform
+            .iter()
+            .all(|guess| (0.0..1.0).contains(&guess[0]) && (-1.0..1.0).contains(&guess[1])));
+        assert!(Guess::uniform_in_bounds(&[0.0], &[0.0], 2, &mut rng).is_err());
+        assert!(Guess::uniform_in_bounds(&[0.0], &[f64::INFINITY], 2, &mut rng).is_err());
+
+        let walkers = vec![
+            Guess::new(&[1.0, 0.0]),
+            Guess::new(&[0.5, 2.0]),
+            Guess::new(&[1.0, -0.0]),
+        ];
+        match Guess::check_distinct(&walkers) {
+            Err(EmceeError::InvalidInputs(msg)) => assert!(msg.contains("0 and 2")),
+            other => panic!("expected an error, got {:?}", other),
+        }
+        assert!(Guess::check_distinct(&walkers[..2]).is_ok());
+    }
+
     #[test]
     fn test_contains_infinites() {
         let guess = Guess::new(&[::std::f64::INFINITY]);
@@ -136,4 +306,12 @@ mod tests {
         guess[2] = 15.0;
         assert_eq!(guess[2], 15.0);
     }
+
+    #[test]
+    fn test_get() {
+        let guess = Guess::new(&[1.5, 2.0]);
+        assert_eq!(guess.get(&["mass", "radius"], "radius"), Some(2.0));
+        assert_eq!(guess.get(&["mass", "radius"], "density"), None);
+        assert_eq!(guess.get::<&str>(&[], "mass"), None);
+    }
 }
@@ -0,0 +1,249 @@
+//! Information gained from the data
+//!
+//! The Kullback-Leibler divergence from the prior to the posterior,
+//! `D = E_post[ln p(x | d) - ln p(x)]`, measures in nats how much the data constrained the
+//! parameters, e.g. to compare experimental designs with a single number. It is estimated from
+//! posterior samples as `D = -H - E_post[ln p(x)]`, where the entropy `H` of the posterior comes
+//! from the distances between nearest neighbours (Kozachenko & Leonenko 1987) and the prior term
+//! from the prior density at each sample. The prior density must therefore be normalised.
+//!
+//! The samples are whitened with their covariance before the neighbour search, which keeps the
+//! estimator accurate for strongly correlated or badly scaled posteriors. The estimator assumes
+//! approximately independent samples; repeated positions, which MCMC chains contain whenever a
+//! proposal is rejected, are removed.
+
+use errors::{EmceeError, Result};
+use guess::Guess;
+use linalg;
+use {EnsembleSampler, Prob};
+
+/// The number of neighbours used by
+/// [`EnsembleSampler::information_gain`](../struct.EnsembleSampler.html#method.information_gain)
+pub const DEFAULT_NEIGHBOURS: usize = 5;
+
+/// Estimate the differential entropy, in nats, of the distribution the samples were drawn from
+///
+/// `k` is the neighbour whose distance is used; larger values reduce the variance of the
+/// estimate at the cost of some bias.
+///
+/// Errors are handled by returning a [`Result`](../errors/type.Result.html) which contains
+/// [`EmceeError::InvalidInputs`](../errors/enum.EmceeError.html) if `k` is zero, there are not
+/// more than `k` distinct samples, or the samples are degenerate in some direction.
+pub fn entropy(samples: &[Guess], k: usize) -> Result<f64> {
+    if k == 0 {
+        return Err(EmceeError::InvalidInputs(
+            "at least one neighbour is required".into(),
+        ));
+    }
+
+    let mut distinct: Vec<&Guess> = samples.iter().collect();
+    distinct.sort_by(|a, b| {
+        a.values
+            .partial_cmp(&b.values)
+            .unwrap_or(::std::cmp::Ordering::Equal)
+    });
+    distinct.dedup_by(|a, b| a.values == b.values);
+    if distinct.len() <= k {
+        return Err(EmceeError::InvalidInputs(format!(
+            "at least {} distinct samples are required",
+            k + 1
+        )));
+    }
+
+    let owned: Vec<Guess> = distinct.iter().map(|&guess| guess.clone()).collect();
+    let (mean, cov) = linalg::mean_covariance(&owned);
+    let l = linalg::cholesky(&cov).ok_or_else(|| {
+        EmceeError::InvalidInputs("the samples do not span every parameter".into())
+    })?;
+    let whitened: Vec<Vec<f64>> = owned
+        .iter()
+        .map(|guess| {
+            let delta: Vec<f64> = guess.values.iter().zip(&mean).map(|(x, m)| x - m).collect();
+            linalg::lower_solve(&l, &delta)
+        })
+        .collect();
+
+    let n = whitened.len();
+    let dim = mean.len();
+    let mut sum_ln_distance = 0f64;
+    let mut nearest = Vec::with_capacity(n);
+    for (i, x) in whitened.iter().enumerate() {
+        nearest.clear();
+        for (j, y) in whitened.iter().enumerate() {
+            if i != j {
+                nearest.push(x.iter().zip(y).map(|(a, b)| (a - b).powi(2)).sum::<f64>());
+            }
+        }
+        let (_, kth, _) = nearest.select_nth_unstable_by(k - 1, |a, b| {
+            a.partial_cmp(b).unwrap_or(::std::cmp::Ordering::Equal)
+        });
+        sum_ln_distance += 0.5 * kth.ln();
+    }
+
+    // psi(n) - psi(k) for integer arguments
+    let digamma_difference: f64 = (k..n).map(|j| 1.0 / j as f64).sum();
+    let ln_det: f64 = (0..dim).map(|i| l[i][i].ln()).sum();
+    Ok(digamma_difference + ln_unit_ball_volume(dim) + dim as f64 * sum_ln_distance / n as f64
+        + ln_det)
+}
+
+/// Estimate the Kullback-Leibler divergence from the prior to the posterior, in nats
+///
+/// `samples` are draws from the posterior and `lnprior` the normalised log prior density. See
+/// [`entropy`](fn.entropy.html) for `k`.
+///
+/// Errors are handled by returning a [`Result`](../errors/type.Result.html) which contains
+/// [`EmceeError::InvalidInputs`](../errors/enum.EmceeError.html) for the same reasons as
+/// [`entropy`](fn.entropy.html), or if the prior density is zero at a sample.
+pub fn information_gain<F>(samples: &[Guess], lnprior: F, k: usize) -> Result<f64>
+where
+    F: Fn(&Guess) -> f64,
+{
+    let entropy = entropy(samples, k)?;
+    let mut mean_lnprior = 0f64;
+    for guess in samples {
+        let value = lnprior(guess);
+        if !value.is_finite() {
+            return Err(EmceeError::InvalidInputs(
+                "the prior density must be positive at every sample".into(),
+            ));
+        }
+        mean_lnprior += value;
+    }
+    mean_lnprior /= samples.len() as f64;
+    Ok(-entropy - mean_lnprior)
+}
+
+/// The log volume of the unit ball in `dim` dimensions
+fn ln_unit_ball_volume(dim: usize) -> f64 {
+    // V_d = V_(d - 2) * 2 pi / d, starting from V_0 = 1 and V_1 = 2
+    let (mut ln_volume, mut d) = match dim % 2 {
+        0 => (0.0, 2),
+        _ => (2f64.ln(), 3),
+    };
+    while d <= dim {
+        ln_volume += (2.0 * ::std::f64::consts::PI / d as f64).ln();
+        d += 2;
+    }
+    ln_volume
+}
+
+impl<'a, T: Prob + Sync + Send + 'a> EnsembleSampler<'a, T> {
+    /// Estimate how much the data constrained the parameters, as the Kullback-Leibler
+    /// divergence in nats from the prior to the posterior
+    ///
+    /// The stored chain after the first `discard` iterations is thinned by its integrated
+    /// autocorrelation time, and the divergence is estimated from those samples and
+    /// [`Prob::lnprior`](trait.Prob.html#tymethod.lnprior), which must be a normalised density,
+    /// using [`DEFAULT_NEIGHBOURS`](information/constant.DEFAULT_NEIGHBOURS.html) neighbours.
+    /// The prior is evaluated in the sampled coordinates, so the stored samples are first mapped
+    /// back with [`Prob::sampled_position`](trait.Prob.html#method.sampled_position). See the
+    /// [`information`](information/index.html) module for details.
+    ///
+    /// Errors are handled by returning a [`Result`](errors/type.Result.html) which contains
+    /// [`EmceeError::InvalidInputs`](errors/enum.EmceeError.html) if no chain is stored,
+    /// `discard` is not less than the number of stored iterations, or for the same reasons as
+    /// [`information_gain`](information/fn.information_gain.html).
+    pub fn information_gain(&self, discard: usize) -> Result<f64> {
+        let chain = self.chain.as_ref().ok_or_else(|| {
+            EmceeError::InvalidInputs("the sampler is not storing the chain".into())
+        })?;
+        if discard >= chain.niterations {
+            return Err(EmceeError::InvalidInputs(format!(
+                "cannot discard {} of {} iterations",
+                discard, chain.niterations
+            )));
+        }
+
+        let (_, production) = chain.split_at(discard);
+        let tau = production
+            .integrated_time(5.0)
+            .iter()
+            .cloned()
+            .fold(1f64, f64::max);
+        let thin = tau.ceil() as usize;
+        let lnprob = self.lnprob;
+        let samples: Vec<Guess> = production
+            .flatchain()
+            .into_iter()
+            .enumerate()
+            .filter(|&(idx, _)| (idx / production.nwalkers) % thin == 0)
+            .map(|(_, guess)| lnprob.sampled_position(&guess).unwrap_or(guess))
+            .collect();
+
+        information_gain(&samples, |guess| lnprob.lnprior(guess), DEFAULT_NEIGHBOURS)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::{SeedableRng, StdRng};
+    use rand::distributions::{IndependentSample, Normal};
+    use std::f64::consts::{E, PI};
+    use testkit::BoxedGaussian;
+
+    fn normal_samples(n: usize, sigmas: &[f64], seed: usize) -> Vec<Guess> {
+        let mut rng = StdRng::from_seed(&[seed]);
+        let normal = Normal::new(0.0, 1.0);
+        (0..n)
+            .map(|_| Guess {
+                values: sigmas
+                    .iter()
+                    .map(|sigma| sigma * normal.ind_sample(&mut rng))
+                    .collect(),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_unit_ball_volume() {
+        assert_approx_eq!(ln_unit_ball_volume(1), 2f64.ln());
+        assert_approx_eq!(ln_unit_ball_volume(2), PI.ln());
+        assert_approx_eq!(ln_unit_ball_volume(3), (4.0 * PI / 3.0).ln());
+    }
+
+    #[test]
+    fn test_entropy_of_gaussian() {
+        let sigmas = [0.5, 20.0];
+        let samples = normal_samples(2000, &sigmas, 1);
+        let expected: f64 = sigmas
+            .iter()
+            .map(|sigma| 0.5 * (2.0 * PI * E * sigma * sigma).ln())
+            .sum();
+        assert_approx_eq!(entropy(&samples, 5).unwrap(), expected, 0.1);
+
+        // Duplicates are ignored
+        let mut repeated = samples.clone();
+        repeated.extend(samples.iter().take(500).cloned());
+        assert_approx_eq!(
+            entropy(&repeated, 5).unwrap(),
+            entropy(&samples, 5).unwrap(),
+            1e-10
+        );
+
+        assert!(entropy(&samples[..3], 5).is_err());
+        assert!(entropy(&samples, 0).is_err());
+    }
+
+    #[test]
+    fn test_information_gain() {
+        // Unit Gaussian posterior in a box of width 20, per parameter
+        let expected = 2.0 * (20f64.ln() - 0.5 * (2.0 * PI * E).ln());
+
+        let model = BoxedGaussian::default();
+        let samples = normal_samples(2000, &[1.0, 1.0], 2);
+        let gain = information_gain(&samples, |guess| model.lnprior(guess), 5).unwrap();
+        assert_approx_eq!(gain, expected, 0.1);
+
+        let mut sampler = EnsembleSampler::new(16, 2, &model).unwrap();
+        assert!(sampler.information_gain(0).is_err());
+        sampler.seed(&[3]);
+        let p0: Vec<Guess> = (0..16)
+            .map(|i| Guess::new(&[0.1 * i as f64 - 0.8, 0.05 * i as f64]))
+            .collect();
+        sampler.run_mcmc(&p0, 3000).unwrap();
+        assert!(sampler.information_gain(3000).is_err());
+        assert_approx_eq!(sampler.information_gain(500).unwrap(), expected, 0.2);
+    }
+}
@@ -324,29 +324,139 @@
 
 extern crate rand;
 extern crate scoped_threadpool;
-use scoped_threadpool::Pool;
+#[cfg(feature = "r")]
+extern crate extendr_api;
+#[cfg(feature = "arrow")]
+extern crate arrow;
+#[cfg(feature = "hdf5")]
+extern crate hdf5;
+#[cfg(feature = "ndarray")]
+extern crate ndarray;
+#[cfg(feature = "parquet")]
+extern crate parquet;
+#[cfg(feature = "serde")]
+extern crate serde;
+pub use scoped_threadpool::Pool;
 
 #[cfg(test)]
 #[macro_use]
 extern crate assert_approx_eq;
-
+#[cfg(all(test, feature = "serde"))]
+extern crate serde_json;
+
+mod acceptance;
+mod ais;
+#[cfg(feature = "ndarray")]
+pub mod arrays;
+mod blobs;
+pub mod autodiff;
+#[cfg(feature = "arrow")]
+pub mod arrow_ipc;
+pub mod autocorr;
+#[cfg(feature = "hdf5")]
+pub mod backends;
+mod batched;
+pub mod benchmarks;
+mod burnin;
+mod cascade;
+mod checkpoint;
+mod control;
+mod coupled;
+mod csv;
+pub mod diagnostics;
+mod duplicates;
 pub mod errors;
+mod float;
+pub mod gradcheck;
+mod gradient;
 mod guess;
+pub mod information;
+pub mod likelihood;
+mod linalg;
+mod live;
+pub mod math;
+pub mod mcse;
+#[cfg(feature = "matlab")]
+pub mod matlab;
+pub mod moves;
+mod npz;
+mod observer;
+mod parallel;
+#[cfg(feature = "parquet")]
+pub mod parquet_file;
+pub mod prior;
 mod prob;
-mod stretch;
+mod progress;
+mod quantised;
+mod reparam;
+pub mod replay;
+mod retry;
+mod rng;
+#[cfg(feature = "r")]
+pub mod r;
+mod run_stats;
+mod running;
+mod sketch;
+mod special;
+mod spec;
+mod stepping_stone;
 mod stores;
-
+mod stretch;
+mod super_ensemble;
+#[cfg(feature = "surrogate")]
+pub mod surrogate;
+mod tempered;
+pub mod testkit;
+mod transform;
+mod whiten;
+
+use std::any::Any;
+use std::borrow::Cow;
+use std::collections::BTreeMap;
+use std::ops::ControlFlow;
 use std::rc::Rc;
+use std::sync::Arc;
 use std::sync::mpsc::channel;
-use rand::{Rng, SeedableRng, StdRng};
+use std::time::{Duration, Instant};
+use rand::{Isaac64Rng, Rng, SeedableRng, StdRng};
 use rand::distributions::{IndependentSample, Range};
 
 use errors::*;
+pub use acceptance::{AcceptanceRule, Barker, Metropolis};
+pub use ais::{Ais, Evidence};
+pub use batched::{Batched, BatchedProb};
+pub use blobs::{BlobProb, BlobStore, WithBlobs};
+pub use cascade::Cascade;
+pub use coupled::CoupledSamplers;
+pub use control::{AcceptanceTarget, AdaptationRecord, Convergence, RunLength, RunLengthReport};
+pub use duplicates::DuplicateWalkers;
+pub use float::Float;
+pub use gradient::{FiniteDifference, GradLnProb};
 pub use guess::Guess;
-pub use prob::Prob;
-
+pub use live::{Block, ChainMonitor, ChainSnapshot};
+pub use prob::{with_data, Prob, WithData};
+pub use observer::{ProposalEvent, ProposalObserver};
+pub use progress::{Progress, ProgressReporter, TextProgress};
+pub use quantised::QuantisedChain;
+pub use reparam::{NonCentred, Scale};
+pub use retry::RetryPolicy;
+pub use run_stats::RunStats;
+pub use running::{OnlineStats, RunningStats};
+pub use sketch::TDigest;
+pub use spec::{BlockLevel, ParamBlock, ParamSpec};
+pub use stepping_stone::{SteppingStone, SteppingStoneEvidence};
+pub use stores::{
+    initial_from_chain, Chain, ChainSamples, ChainView, PrincipalComponents, ProbStore,
+    Projection, TemperedChains,
+};
+pub use super_ensemble::SuperEnsemble;
+pub use transform::{LogTransform, LogitTransform, Transform, Transformed};
+
+use live::LiveChain;
+use moves::{AncestryRecord, Move, MoveAcceptance, StretchMove, SubspaceMove};
 use stretch::Stretch;
-use stores::{Chain, ProbStore};
+use rng::SamplerRng;
+use whiten::Whitening;
 
 /// Struct representing the current iteration evaluation
 ///
@@ -371,26 +481,186 @@ pub struct Step {
     pub iteration: usize,
 }
 
+/// How a [`threaded`](struct.EnsembleSampler.html#method.threaded) sampler uses its threads
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Parallelism {
+    /// Evaluate the walkers of each half-ensemble in parallel, one walker per task
+    Walkers,
+
+    /// Evaluate the walkers one at a time, handing each evaluation the thread pool through
+    /// [`Prob::lnprob_in_pool`](trait.Prob.html#method.lnprob_in_pool)
+    ///
+    /// Approximations and evaluations with a
+    /// [`timeout`](struct.EnsembleSampler.html#structfield.timeout) are made serially without
+    /// the pool.
+    Evaluation,
+}
+
+/// What the sampler does with a log probability which is NaN or positive infinity
+///
+/// Set in [`EnsembleSampler::nan_policy`](struct.EnsembleSampler.html#structfield.nan_policy).
+/// Negative infinity is never affected, as it is the usual way for a model to reject a position.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum NanPolicy {
+    /// Stop the run with
+    /// [`EmceeError::NonFiniteProbability`](errors/enum.EmceeError.html), reporting the walker,
+    /// the iteration and the offending position
+    #[default]
+    Error,
+
+    /// Treat the value as `-inf`, so the proposal is rejected, and count it in
+    /// [`RunStats::nnonfinite`](struct.RunStats.html#structfield.nnonfinite)
+    TreatAsNegInf,
+}
+
 /// Affine-invariant Markov-chain Monte Carlo sampler
-pub struct EnsembleSampler<'a, T: Prob + Sync + Send + 'a> {
+///
+/// The sampler computes in `f64`, and stores its chain and log probabilities as `S`, `f64` by
+/// default; see [`with_precision`](#method.with_precision) to store them as `f32`.
+pub struct EnsembleSampler<'a, T: Prob + Sync + Send + 'a, S: Float = f64> {
     nwalkers: usize,
     lnprob: &'a T,
     dim: usize,
-    proposal_scale: f64,
+    moves: Vec<(Box<dyn Move>, f64)>,
+    acceptance: Box<dyn AcceptanceRule>,
+    configured_tuning: Vec<Option<f64>>,
+    grouped: bool,
+    move_acceptance: Vec<MoveAcceptance>,
+    adaptation_trace: Vec<AdaptationRecord>,
+    ancestry: Vec<AncestryRecord>,
+    validation_failures: BTreeMap<String, usize>,
+    run_stats: RunStats,
+    blobs: Option<Box<dyn Any>>,
+    progress: Option<Box<dyn ProgressReporter>>,
+    observer: Option<Box<dyn ProposalObserver>>,
+    param_names: Option<Arc<[String]>>,
+    approx_lnprob: Vec<Vec<f64>>,
     pool: Option<Pool>,
+    parallelism: Parallelism,
+    reference: bool,
 
-    rng: Box<dyn Rng>,
+    rng: SamplerRng,
+    seed: Option<Vec<usize>>,
+    scales: Option<(Vec<f64>, Vec<f64>)>,
+    whitening: Option<Whitening>,
     naccepted: Vec<usize>,
     iterations: usize,
-    chain: Option<Chain>,
-    probstore: Option<ProbStore>,
+    chain: Option<Chain<S>>,
+    probstore: Option<ProbStore<S>>,
+    running: Option<RunningStats>,
+    online: OnlineStats,
+    sketches: Vec<TDigest>,
+    live: Option<LiveChain>,
     initial_state: Option<Step>,
+    last_state: Option<Step>,
+    stored_thin: usize,
 
     /// Determines whether the sampler stores the chain; can be disabled in order to run large models (default true)
     pub storechain: bool,
 
     /// Thin the stored chains by this much, i.e. only every `thin`th step is stored (default 1)
     pub thin: usize,
+
+    /// Store nothing from the first `discard` iterations of each run (default 0)
+    ///
+    /// This drops the burn-in as the sampler runs, so it never takes up memory in the chain and
+    /// does not need to be cut off afterwards. The discarded iterations are also left out of
+    /// the [online statistics](#method.online_stats), quantile sketches and
+    /// [chain monitors](#method.monitor), and [`thin`](#structfield.thin) counts from the first
+    /// iteration which is kept.
+    pub discard: usize,
+
+    /// Take this many steps of the ensemble in every iteration of a run (default 1)
+    ///
+    /// Only the last step of each iteration is stored, published to
+    /// [monitors](#method.monitor) and passed to callbacks, as with `thin_by` in Python
+    /// `emcee`, so a run of `n` iterations makes `n * steps_per_store` steps but stores and
+    /// reports only `n`. This thins the chain inside the sampler, cutting the traffic to
+    /// [`run_in_chunks`](#method.run_in_chunks) or a storage backend as well as the size of the
+    /// chain, while the sampled distribution is unchanged. [`thin`](#structfield.thin) and
+    /// [`discard`](#structfield.discard) count iterations, not steps, while the
+    /// [acceptance fractions](#method.acceptance_fraction) count every step.
+    pub steps_per_store: usize,
+
+    /// Cap the stored chain at this many iterations (default `None`)
+    ///
+    /// When the stored chain is full, every other stored iteration is discarded and the
+    /// thinning factor is doubled, so the chain always holds iterations evenly spread over the
+    /// whole run and its memory use is fixed in advance, however long the run. The thinning
+    /// factor in effect is available from [`stored_thin`](#method.stored_thin). Only the stored
+    /// chain is affected; the [online statistics](#method.online_stats) still see every
+    /// `thin`th step. Must be at least 2.
+    pub max_stored_iterations: Option<usize>,
+
+    /// Let the moves operate on parameters rescaled by the spread of the initial ensemble
+    /// (default false)
+    ///
+    /// When a run starts from new initial positions, the mean and standard deviation of each
+    /// parameter over the walkers are recorded, and every proposal is made in terms of the
+    /// standardised values `(x - mean) / std`. This keeps moves with absolute step sizes, and
+    /// the arithmetic of the affine-invariant moves, well behaved when parameters differ by many
+    /// orders of magnitude. The map is linear, so no acceptance correction is required. The
+    /// scales are kept until [`reset`](#method.reset) and are available from
+    /// [`parameter_scales`](#method.parameter_scales).
+    pub autoscale: bool,
+
+    /// Give up on any single evaluation of the model which takes longer than this (default
+    /// `None`)
+    ///
+    /// Evaluations which exceed the timeout are treated as if they returned `-inf`, so the
+    /// proposal is rejected, and are counted by [`ntimeouts`](#method.ntimeouts). The model is
+    /// given the deadline through
+    /// [`Prob::lnprob_with_deadline`](trait.Prob.html#method.lnprob_with_deadline), which it
+    /// should implement to stop waiting on external processes or network services, as the
+    /// sampler cannot interrupt an evaluation itself.
+    pub timeout: Option<Duration>,
+
+    /// How to retry evaluations which fail transiently (default no retries)
+    ///
+    /// Failures are reported by the model through
+    /// [`Prob::try_lnprob`](trait.Prob.html#method.try_lnprob). An evaluation which still fails
+    /// after every retry is treated as if it returned `-inf`, so the proposal is rejected rather
+    /// than the run aborted; retries and failures are counted in the
+    /// [`run_stats`](#method.run_stats).
+    pub retry: RetryPolicy,
+
+    /// What to do when the model returns a log probability which is NaN or positive infinity
+    /// (default [`NanPolicy::Error`](enum.NanPolicy.html))
+    ///
+    /// Either is a bug in the model, e.g. a division by zero or the log of a negative number,
+    /// which would otherwise corrupt the acceptance test of every later step.
+    pub nan_policy: NanPolicy,
+
+    /// Reassign the walkers to the two halves of the ensemble at random every iteration
+    /// (default false)
+    ///
+    /// By default the first half of the walkers is always updated using the second half and
+    /// vice versa, so the same walkers always serve as each other's complementary ensemble.
+    /// Within a half every walker is updated at once, so what shuffling changes is which walkers
+    /// are grouped together, as in Python `emcee` 3. This removes correlations between fixed
+    /// groups of walkers which can show up in short chains. The permutation is drawn from the
+    /// sampler's random number stream, so seeded runs stay reproducible, and walkers keep their
+    /// indices in the chain and all statistics.
+    pub shuffle_walkers: bool,
+
+    /// Record the origin of every accepted step (default false)
+    ///
+    /// Each accepted step adds an [`AncestryRecord`](moves/struct.AncestryRecord.html) to
+    /// [`ancestry`](#method.ancestry), holding the move which generated it and, for moves built
+    /// on a complementary walker such as the stretch move, that walker. The records accumulate
+    /// over runs until [`reset`](#method.reset), and grow with the number of accepted steps.
+    pub track_ancestry: bool,
+
+    /// Keep a [`TDigest`](struct.TDigest.html) of every parameter with this compression
+    /// (default `None`)
+    ///
+    /// The digests are updated with every stored iteration alongside the
+    /// [online statistics](#method.online_stats), whether or not the chain is stored, and give
+    /// approximate medians and credible intervals from
+    /// [`quantile_sketches`](#method.quantile_sketches) in memory which does not grow with the
+    /// run. This makes quantiles available for runs far too long to keep the flatchain. A
+    /// compression of 100 is a reasonable default.
+    pub quantile_sketch: Option<f64>,
 }
 
 impl<'a, T: Prob + Sync + Send + 'a> EnsembleSampler<'a, T>
@@ -405,33 +675,7 @@ impl<'a, T: Prob + Sync + Send + 'a> EnsembleSampler<'a, T>
     /// * the number of walkers must be even * the number of walkers must be at least twice the
     /// number of parameters
     pub fn new(nwalkers: usize, dim: usize, lnprob: &'a T) -> Result<Self> {
-        if nwalkers % 2 != 0 {
-            return Err(EmceeError::InvalidInputs(
-                "the number of walkers must be even".into(),
-            ));
-        }
-
-        if nwalkers <= 2 * dim {
-            let msg = "the number of walkers should be more than \
-                       twice the dimension of your parameter space";
-            return Err(EmceeError::InvalidInputs(msg.into()));
-        }
-
-        Ok(EnsembleSampler {
-            nwalkers: nwalkers,
-            iterations: 0,
-            lnprob: lnprob,
-            dim: dim,
-            pool: None,
-            naccepted: vec![0; nwalkers],
-            rng: Box::new(rand::thread_rng()),
-            proposal_scale: 2.0,
-            chain: None,
-            probstore: None,
-            storechain: true,
-            thin: 1,
-            initial_state: None,
-        })
+        EnsembleSampler::with_precision(nwalkers, dim, lnprob)
     }
 
     /// Create a new `EnsembleSampler`
@@ -443,6 +687,41 @@ impl<'a, T: Prob + Sync + Send + 'a> EnsembleSampler<'a, T>
     /// * the number of walkers must be even * the number of walkers must be at least twice the
     /// number of parameters
     pub fn threaded(nwalkers: usize, dim: usize, lnprob: &'a T, n_threads: usize) -> Result<Self> {
+        let mut sampler = EnsembleSampler::new(nwalkers, dim, lnprob)?;
+        if n_threads > nwalkers {
+            println!("WARNING: You asked for more threads ({}) than walkers ({}). Is this a mistake?", n_threads, nwalkers);
+        }
+        if nwalkers % n_threads != 0 {
+            println!("WARNING: Your number of threads ({}) does not divide the number of walkers ({}). Is this a mistake?", n_threads, nwalkers);
+        }
+        sampler.pool = Some(Pool::new(n_threads as u32));
+        Ok(sampler)
+    }
+
+    /// Create a new `EnsembleSampler` which draws its random numbers from `rng`
+    ///
+    /// See [`set_rng`](#method.set_rng); errors are as for [`new`](#method.new).
+    pub fn with_rng<R: Rng + 'static>(
+        nwalkers: usize,
+        dim: usize,
+        lnprob: &'a T,
+        rng: R,
+    ) -> Result<Self> {
+        let mut sampler = EnsembleSampler::new(nwalkers, dim, lnprob)?;
+        sampler.set_rng(rng);
+        Ok(sampler)
+    }
+}
+
+impl<'a, T: Prob + Sync + Send + 'a, S: Float> EnsembleSampler<'a, T, S> {
+    /// Create a new `EnsembleSampler` which stores its chain and log probabilities as `S`
+    ///
+    /// The sampler computes in `f64` whatever `S` is, and rounds the values it stores, so e.g.
+    /// `EnsembleSampler::<_, f32>::with_precision(nwalkers, dim, &model)` halves the memory of
+    /// the stored chain while running, not only after converting it. [`new`](#method.new) and
+    /// the other constructors create the usual `f64` sampler. Errors are as for
+    /// [`new`](#method.new).
+    pub fn with_precision(nwalkers: usize, dim: usize, lnprob: &'a T) -> Result<Self> {
         if nwalkers % 2 != 0 {
             return Err(EmceeError::InvalidInputs(
                 "the number of walkers must be even".into(),
@@ -454,27 +733,55 @@ impl<'a, T: Prob + Sync + Send + 'a> EnsembleSampler<'a, T>
                        twice the dimension of your parameter space";
             return Err(EmceeError::InvalidInputs(msg.into()));
         }
-        if n_threads > nwalkers {
-            println!("WARNING: You asked for more threads ({}) than walkers ({}). Is this a mistake?", n_threads, nwalkers);
-        }
-        if nwalkers % n_threads != 0 {
-            println!("WARNING: Your number of threads ({}) does not divide the number of walkers ({}). Is this a mistake?", n_threads, nwalkers);
-        }
 
         Ok(EnsembleSampler {
             nwalkers: nwalkers,
             iterations: 0,
             lnprob: lnprob,
             dim: dim,
-            pool: Some(Pool::new(n_threads as u32)),
+            pool: None,
+            parallelism: Parallelism::Walkers,
+            reference: false,
             naccepted: vec![0; nwalkers],
-            rng: Box::new(rand::thread_rng()),
-            proposal_scale: 2.0,
+            rng: SamplerRng::new(Box::new(rand::thread_rng())),
+            seed: None,
+            scales: None,
+            whitening: None,
+            moves: vec![(Box::new(StretchMove::default()), 1.0)],
+            acceptance: Box::new(Metropolis),
+            configured_tuning: vec![StretchMove::default().tuning()],
+            grouped: false,
+            move_acceptance: vec![MoveAcceptance::new("stretch")],
+            adaptation_trace: Vec::new(),
+            ancestry: Vec::new(),
+            validation_failures: BTreeMap::new(),
+            blobs: None,
+            progress: None,
+            observer: None,
+            param_names: None,
+            run_stats: RunStats::default(),
+            approx_lnprob: Vec::new(),
             chain: None,
             probstore: None,
+            running: None,
+            online: OnlineStats::new(dim),
+            sketches: Vec::new(),
+            live: None,
             storechain: true,
             thin: 1,
+            discard: 0,
+            steps_per_store: 1,
+            max_stored_iterations: None,
+            stored_thin: 1,
+            autoscale: false,
+            timeout: None,
+            retry: RetryPolicy::default(),
+            nan_policy: NanPolicy::default(),
+            shuffle_walkers: false,
+            track_ancestry: false,
+            quantile_sketch: None,
             initial_state: None,
+            last_state: None,
         })
     }
 
@@ -484,8 +791,315 @@ impl<'a, T: Prob + Sync + Send + 'a> EnsembleSampler<'a, T>
     /// [`SeedableRng.from_seed`]
     /// (https://docs.rs/rand/0.3.15/rand/trait.SeedableRng.html#tymethod.from_seed)
     /// accepts.
+    ///
+    /// A seeded sampler repeats its runs bit for bit, given the same model, settings and
+    /// number of threads, and [`save_state`](#method.save_state) records its position in the
+    /// random stream, so a run resumed from a checkpoint continues exactly as if it had never
+    /// stopped.
     pub fn seed(&mut self, seed: &[usize]) {
-        self.rng = Box::new(StdRng::from_seed(seed));
+        let rng: Box<dyn Rng> = if self.reference {
+            let seed: Vec<u64> = seed.iter().map(|&value| value as u64).collect();
+            Box::new(Isaac64Rng::from_seed(&seed[..]))
+        } else {
+            Box::new(StdRng::from_seed(seed))
+        };
+        self.rng = SamplerRng::new(rng);
+        self.seed = Some(seed.to_vec());
+    }
+
+    /// Draw the random numbers from `rng` instead of the built in generator
+    ///
+    /// Any generator can be used, e.g. a seeded `XorShiftRng` for speed. The sampler cannot
+    /// see its seed, so [`reset`](#method.reset) does not rewind it, and
+    /// [`save_state`](#method.save_state) reseeds the sampler from it rather than recording
+    /// its state; use [`seed`](#method.seed) for runs which must resume exactly. A later call
+    /// to [`seed`](#method.seed) replaces `rng` with the built in generator.
+    pub fn set_rng<R: Rng + 'static>(&mut self, rng: R) -> &mut Self {
+        self.rng = SamplerRng::new(Box::new(rng));
+        self.seed = None;
+        self
+    }
+
+    /// Switch to the reference mode, seeded with `seed`, for reproducible tests
+    ///
+    /// In the reference mode the chain depends only on the seed, the model and the settings,
+    /// not on the machine: random numbers come from the 64-bit ISAAC generator whatever the
+    /// word size of the platform, and the model is evaluated serially on the calling thread, in
+    /// walker order, even on a [`threaded`](#method.threaded) sampler. Timeouts depend on the
+    /// wall clock, so running with a [`timeout`](#structfield.timeout) is an error. Tests of a
+    /// model can then compare chains exactly with values recorded on another machine, provided
+    /// the model itself avoids platform-dependent results; note that the precision of
+    /// transcendental functions such as `exp` and `ln` is not specified by the standard
+    /// library, and may differ in the last bit between platforms.
+    ///
+    /// The reference mode stays on through [`reset`](#method.reset), and later calls to
+    /// [`seed`](#method.seed) seed the reference generator.
+    pub fn set_reference_mode(&mut self, seed: &[usize]) -> &mut Self {
+        self.reference = true;
+        self.seed(seed);
+        self
+    }
+
+    /// Whether the sampler is in the [reference mode](#method.set_reference_mode)
+    pub fn reference_mode(&self) -> bool {
+        self.reference
+    }
+
+    /// Evaluate the model on `n_threads` threads, or serially if `n_threads` is 1
+    ///
+    /// This is equivalent to creating the sampler with [`threaded`](#method.threaded), and can
+    /// be changed between runs. The proposals of a whole half-ensemble are evaluated in
+    /// parallel, while every random number is drawn on the calling thread before and after, so
+    /// the chain is identical to a serial run with the same [`seed`](#method.seed) whatever the
+    /// number of threads. Parallel evaluation pays off once a model evaluation takes much
+    /// longer than starting a task, roughly a millisecond or more.
+    ///
+    /// Errors are handled by returning a [`Result`](errors/type.Result.html) which contains
+    /// [`EmceeError::InvalidInputs`](errors/enum.EmceeError.html) if `n_threads` is zero.
+    pub fn set_threads(&mut self, n_threads: usize) -> Result<&mut Self> {
+        if n_threads == 0 {
+            return Err(EmceeError::InvalidInputs(
+                "at least one thread is required".into(),
+            ));
+        }
+        self.pool = if n_threads == 1 {
+            None
+        } else {
+            Some(Pool::new(n_threads as u32))
+        };
+        Ok(self)
+    }
+
+    /// The number of threads the model is evaluated on
+    pub fn threads(&self) -> usize {
+        self.pool
+            .as_ref()
+            .map_or(1, |pool| pool.thread_count() as usize)
+    }
+
+    /// Choose how the threads of a [`threaded`](#method.threaded) sampler are used
+    ///
+    /// With [`Parallelism::Evaluation`](enum.Parallelism.html) the walkers are evaluated one at
+    /// a time, and each evaluation is handed the whole thread pool through
+    /// [`Prob::lnprob_in_pool`](trait.Prob.html#method.lnprob_in_pool). This suits models which
+    /// are parallel internally, e.g. built on a threaded linear algebra library, whose threads
+    /// would otherwise compete with the sampler's. The default is
+    /// [`Parallelism::Walkers`](enum.Parallelism.html).
+    pub fn set_parallelism(&mut self, parallelism: Parallelism) -> &mut Self {
+        self.parallelism = parallelism;
+        self
+    }
+
+    /// How the threads of the sampler are used, see
+    /// [`set_parallelism`](#method.set_parallelism)
+    pub fn parallelism(&self) -> Parallelism {
+        self.parallelism
+    }
+
+    /// Report the progress of every run to `reporter`
+    ///
+    /// The reporter is given the iteration, the mean acceptance fraction and the elapsed time
+    /// after every iteration of every run, from which it can estimate the time remaining; see
+    /// [`TextProgress`](struct.TextProgress.html) for one which prints to standard error.
+    /// It is kept by [`reset`](#method.reset).
+    ///
+    /// ```rust,no_run
+    /// # use emcee::{EnsembleSampler, Guess, Prob, TextProgress};
+    /// # struct Model;
+    /// # impl Prob for Model {
+    /// #     fn lnlike(&self, params: &Guess) -> f64 { -params[0] * params[0] }
+    /// #     fn lnprior(&self, _params: &Guess) -> f64 { 0.0 }
+    /// # }
+    /// # let model = Model;
+    /// let mut sampler = EnsembleSampler::new(8, 1, &model).unwrap();
+    /// sampler.set_progress(TextProgress::default());
+    /// # let p0 = Guess::new(&[0.0]).create_initial_guess(8);
+    /// sampler.run_mcmc(&p0, 100000).unwrap();
+    /// ```
+    pub fn set_progress<R: ProgressReporter + 'static>(&mut self, reporter: R) -> &mut Self {
+        self.progress = Some(Box::new(reporter));
+        self
+    }
+
+    /// Stop reporting progress
+    pub fn clear_progress(&mut self) -> &mut Self {
+        self.progress = None;
+        self
+    }
+
+    /// Pass every proposal, accepted or rejected, to `observer`
+    ///
+    /// The observer is given the walker, the old and proposed positions and their log
+    /// probabilities; see [`ProposalObserver`](trait.ProposalObserver.html). It is kept by
+    /// [`reset`](#method.reset).
+    pub fn set_observer<O: ProposalObserver + 'static>(&mut self, observer: O) -> &mut Self {
+        self.observer = Some(Box::new(observer));
+        self
+    }
+
+    /// Stop passing proposals to the observer
+    pub fn clear_observer(&mut self) -> &mut Self {
+        self.observer = None;
+        self
+    }
+
+    /// Name the parameters, e.g. `&["mass", "radius"]`, so the stored chain carries the names
+    ///
+    /// The names are given to the current stored chain and to those of later runs, see
+    /// [`Chain::set_param_names`](struct.Chain.html#method.set_param_names). They are kept by
+    /// [`reset`](#method.reset).
+    ///
+    /// Errors are handled by returning a [`Result`](errors/type.Result.html) which contains
+    /// [`EmceeError::InvalidInputs`](errors/enum.EmceeError.html) if the number of names is not
+    /// the number of parameters.
+    pub fn set_param_names(&mut self, names: &[&str]) -> Result<&mut Self> {
+        if names.len() != self.dim {
+            return Err(EmceeError::InvalidInputs(format!(
+                "{} names were given for {} parameters",
+                names.len(),
+                self.dim
+            )));
+        }
+        let names: Arc<[String]> = names.iter().map(|name| name.to_string()).collect();
+        if let Some(chain) = self.chain.as_mut() {
+            chain.names = Some(names.clone());
+        }
+        self.param_names = Some(names);
+        Ok(self)
+    }
+
+    /// Replace the moves used to generate proposals
+    ///
+    /// Each entry is a move and its relative weight. Every iteration, one move is chosen at
+    /// random with probability proportional to its weight and used to update both halves of the
+    /// ensemble. The acceptance statistics returned by
+    /// [`move_acceptance`](#method.move_acceptance) are reset.
+    ///
+    /// Errors are handled by returning a [`Result`](errors/type.Result.html) which contains
+    /// [`EmceeError::InvalidInputs`](errors/enum.EmceeError.html) if no moves are given, or any
+    /// weight is not positive and finite.
+    pub fn set_moves(&mut self, moves: Vec<(Box<dyn Move>, f64)>) -> Result<&mut Self> {
+        if moves.is_empty() {
+            return Err(EmceeError::InvalidInputs(
+                "at least one move must be given".into(),
+            ));
+        }
+
+        if moves
+            .iter()
+            .any(|&(_, weight)| !(weight.is_finite() && weight > 0.0))
+        {
+            return Err(EmceeError::InvalidInputs(
+                "move weights must be positive and finite".into(),
+            ));
+        }
+
+        self.move_acceptance = moves
+            .iter()
+            .map(|(m, _)| MoveAcceptance::new(m.name()))
+            .collect();
+        self.configured_tuning = moves.iter().map(|(m, _)| m.tuning()).collect();
+        self.moves = moves;
+        self.grouped = false;
+        Ok(self)
+    }
+
+    /// Replace the rule deciding whether each proposal is accepted (default
+    /// [`Metropolis`](struct.Metropolis.html))
+    ///
+    /// The rule applies to every move, and is kept by [`reset`](#method.reset).
+    ///
+    /// ```rust
+    /// # use emcee::{Barker, EnsembleSampler, Guess, Prob};
+    /// # struct Model;
+    /// # impl Prob for Model {
+    /// #     fn lnlike(&self, params: &Guess) -> f64 { -params[0] * params[0] }
+    /// #     fn lnprior(&self, _params: &Guess) -> f64 { 0.0 }
+    /// # }
+    /// # let model = Model;
+    /// let mut sampler = EnsembleSampler::new(8, 1, &model).unwrap();
+    /// sampler.set_acceptance_rule(Barker);
+    /// assert_eq!(sampler.acceptance_rule(), "barker");
+    /// ```
+    pub fn set_acceptance_rule<A: AcceptanceRule + 'static>(&mut self, rule: A) -> &mut Self {
+        self.acceptance = Box::new(rule);
+        self
+    }
+
+    /// The name of the rule deciding whether each proposal is accepted
+    pub fn acceptance_rule(&self) -> &str {
+        self.acceptance.name()
+    }
+
+    /// Split the parameters into groups, each updated by its own move
+    ///
+    /// Each entry gives the indices of the parameters in a group and the move used to update
+    /// them, e.g. a stretch move for the shape parameters and another move for nuisance
+    /// calibration offsets. Every iteration performs one sub-step per group, in the order given,
+    /// where the group's move proposes new values for that group's parameters only (see
+    /// [`SubspaceMove`](moves/struct.SubspaceMove.html)) and both halves of the ensemble are
+    /// updated. Each group has its own entry in [`move_acceptance`](#method.move_acceptance) and
+    /// its tuning is adapted separately by [`run_warmup`](#method.run_warmup). Calling
+    /// [`set_moves`](#method.set_moves) removes the groups again.
+    ///
+    /// Errors are handled by returning a [`Result`](errors/type.Result.html) which contains
+    /// [`EmceeError::InvalidInputs`](errors/enum.EmceeError.html) unless every parameter belongs
+    /// to exactly one group.
+    pub fn set_groups(&mut self, groups: Vec<(Vec<usize>, Box<dyn Move>)>) -> Result<&mut Self> {
+        let mut seen = vec![false; self.dim];
+        for (indices, _) in &groups {
+            if indices.is_empty() {
+                return Err(EmceeError::InvalidInputs("parameter groups must not be empty".into()));
+            }
+            for &idx in indices {
+                if idx >= self.dim || seen[idx] {
+                    return Err(EmceeError::InvalidInputs(format!(
+                        "parameter {} is out of range or belongs to more than one group",
+                        idx
+                    )));
+                }
+                seen[idx] = true;
+            }
+        }
+        if let Some(idx) = seen.iter().position(|seen| !seen) {
+            return Err(EmceeError::InvalidInputs(format!(
+                "parameter {} does not belong to any group",
+                idx
+            )));
+        }
+
+        let moves = groups
+            .into_iter()
+            .map(|(indices, m)| {
+                (Box::new(SubspaceMove::new(indices, m)) as Box<dyn Move>, 1.0)
+            })
+            .collect();
+        self.set_moves(moves)?;
+        self.grouped = true;
+        Ok(self)
+    }
+
+    /// Update each block of a [`ParamSpec`](struct.ParamSpec.html) in turn, with a move created
+    /// by `make` for each block
+    ///
+    /// This is [`set_groups`](#method.set_groups) with the groups taken from the specification,
+    /// see [`ParamSpec::block_moves`](struct.ParamSpec.html#method.block_moves).
+    ///
+    /// Errors are handled by returning a [`Result`](errors/type.Result.html) which contains
+    /// [`EmceeError::InvalidInputs`](errors/enum.EmceeError.html) if the specification does not
+    /// have one parameter per dimension.
+    pub fn set_block_moves<F>(&mut self, spec: &ParamSpec, make: F) -> Result<&mut Self>
+    where
+        F: FnMut(&ParamBlock) -> Box<dyn Move>,
+    {
+        if spec.nparams() != self.dim {
+            return Err(EmceeError::InvalidInputs(format!(
+                "the specification has {} parameters but the sampler has {}",
+                spec.nparams(),
+                self.dim
+            )));
+        }
+        self.set_groups(spec.block_moves(make))
     }
 
     /// Run the sampler with a callback called on each iteration
@@ -504,6 +1118,75 @@ impl<'a, T: Prob + Sync + Send + 'a> EnsembleSampler<'a, T>
     where
         F: FnMut(Step),
     {
+        self.sample_until(params, iterations, |step| {
+            callback(step);
+            ControlFlow::Continue(())
+        })
+    }
+
+    /// Run the sampler for up to `niterations` iterations, calling `callback` after each one
+    ///
+    /// The callback sees the iteration index, walker positions and log probabilities of every
+    /// iteration, e.g. to report live diagnostics, and the run stops cleanly after the current
+    /// iteration if it returns `ControlFlow::Break(())`, e.g. for a custom convergence test.
+    /// Everything up to and including that iteration is stored as in a complete run, and the
+    /// returned state is the last iteration which was run, so sampling can be continued with
+    /// [`set_initial_state`](#method.set_initial_state).
+    ///
+    /// ```rust
+    /// # use emcee::{EnsembleSampler, Guess, Prob};
+    /// # use std::ops::ControlFlow;
+    /// # struct Gaussian;
+    /// # impl Prob for Gaussian {
+    /// #     fn lnlike(&self, params: &Guess) -> f64 { -0.5 * params[0] * params[0] }
+    /// #     fn lnprior(&self, _params: &Guess) -> f64 { 0.0 }
+    /// # }
+    /// # let model = Gaussian;
+    /// let mut sampler = EnsembleSampler::new(8, 1, &model).unwrap();
+    /// let p0 = Guess::new(&[0.0]).create_initial_guess(8);
+    /// let last = sampler
+    ///     .run_mcmc_with_callback(&p0, 1000, |step| {
+    ///         let best = step.lnprob.iter().cloned().fold(f64::NAN, f64::max);
+    ///         if step.iteration >= 10 && best > -0.01 {
+    ///             ControlFlow::Break(())
+    ///         } else {
+    ///             ControlFlow::Continue(())
+    ///         }
+    ///     })
+    ///     .unwrap();
+    /// assert_eq!(sampler.chain().unwrap().niterations, last.iteration + 1);
+    /// ```
+    ///
+    /// Errors are handled by returning a [`Result`](errors/type.Result.html) which contains
+    /// any error from the run, as for [`run_mcmc`](#method.run_mcmc).
+    pub fn run_mcmc_with_callback<F>(
+        &mut self,
+        p0: &[Guess],
+        niterations: usize,
+        mut callback: F,
+    ) -> Result<Step>
+    where
+        F: FnMut(&Step) -> ControlFlow<()>,
+    {
+        self.sample_until(p0, niterations, |step| callback(&step))
+    }
+
+    /// Run the sampler, calling `callback` on each iteration until it asks to stop
+    fn sample_until<F>(
+        &mut self,
+        params: &[Guess],
+        iterations: usize,
+        mut callback: F,
+    ) -> Result<Step>
+    where
+        F: FnMut(Step) -> ControlFlow<()>,
+    {
+        if self.reference && self.timeout.is_some() {
+            return Err(EmceeError::InvalidInputs(
+                "timeouts depend on the wall clock and cannot be used in the reference mode".into(),
+            ));
+        }
+
         let mut p = match self.initial_state {
             None => Rc::new(params.to_vec()),
             Some(ref state) => state.pos.clone(),
@@ -514,70 +1197,124 @@ impl<'a, T: Prob + Sync + Send + 'a> EnsembleSampler<'a, T>
             Some(ref state) => state.lnprob.clone(),
         };
 
-        let halfk = self.nwalkers / 2;
-
         if lnprob.iter().any(|val| val.is_nan()) {
             return Err("The initial lnprob was NaN.".into());
         }
 
+        if self.autoscale && (self.initial_state.is_none() || self.scales.is_none()) {
+            self.scales = Some(ensemble_scales(&p));
+        }
+
+        self.approx_lnprob = vec![Vec::new(); self.nwalkers];
+        for level in 0..self.lnprob.napproximations() {
+            for (walker, value) in self.get_lnprob_at(&p, Some(level))?.into_iter().enumerate() {
+                self.approx_lnprob[walker].push(value);
+            }
+        }
+
+        if self.steps_per_store == 0 {
+            return Err(EmceeError::InvalidInputs(
+                "each iteration must take at least one step".into(),
+            ));
+        }
+
+        if let Some(max) = self.max_stored_iterations {
+            if max < 2 {
+                return Err(EmceeError::InvalidInputs(
+                    "at least two iterations must be stored".into(),
+                ));
+            }
+        }
+
+        let nkept = iterations.saturating_sub(self.discard);
+        let capacity = self.max_stored_iterations.map_or(nkept, |max| max.min(nkept));
+        let mut nstored = 0;
+        self.stored_thin = self.thin;
         if self.storechain {
-            self.chain = Some(Chain::new(self.dim, self.nwalkers, iterations));
-            self.probstore = Some(ProbStore::new(self.nwalkers, iterations));
+            // The stores grow as iterations are stored, so a run which is stopped early never
+            // allocated its full length
+            self.chain = Some(self.new_chain(0));
+            self.probstore = Some(ProbStore::zeros(self.nwalkers, 0));
+            self.running = Some(RunningStats::new(self.dim, self.nwalkers));
+        }
+
+        if let Some(live) = self.live.as_mut() {
+            live.clear();
         }
 
         self.naccepted.resize(self.nwalkers, 0);
 
+        let started = Instant::now();
+        let mut last_iteration = iterations - 1;
         for iteration in 0..iterations {
-            for ensemble_idx in 0..2 {
-                let (first, second) = if ensemble_idx == 0 {
-                    Rc::make_mut(&mut p).split_at_mut(halfk)
-                } else {
-                    let (second, first) = Rc::make_mut(&mut p).split_at_mut(halfk);
-                    (first, second)
-                };
+            // Only the last of the steps making up an iteration is stored and reported
+            for _ in 0..self.steps_per_store {
+                self.take_step(&mut p, &mut lnprob)?;
+                self.iterations += 1;
+            }
 
-                let (lnprob_slice, _) = if ensemble_idx == 0 {
-                    Rc::make_mut(&mut lnprob).split_at_mut(halfk)
-                } else {
-                    let (second, first) = Rc::make_mut(&mut lnprob).split_at_mut(halfk);
-                    (first, second)
-                };
+            /* Iterations are only stored once the burn-in has been discarded */
+            let kept = iteration.checked_sub(self.discard);
 
-                assert_eq!(first.len(), halfk);
-                assert_eq!(second.len(), halfk);
-                assert_eq!(lnprob_slice.len(), halfk);
+            /* Halve the stored chain when it is full, which may leave this iteration out */
+            if let Some(kept) = kept {
+                if self.storechain
+                    && kept % self.stored_thin == 0
+                    && kept / self.stored_thin == capacity
+                {
+                    nstored = self.compact_storage(nstored);
+                }
+            }
 
-                let stretch = self.propose_stretch(first, second, lnprob_slice)?;
+            /* Update the store variables with the new parameter values */
 
-                if stretch.accept.iter().any(|val| *val) {
-                    /* Some walkers have accepted new positions, so update the store variables */
-                    for walker_idx in 0..halfk {
-                        if !stretch.accept[walker_idx] {
-                            continue;
-                        }
+            let stored_thin = self.stored_thin;
+            let positions = match kept {
+                Some(_) => stored_positions(self.lnprob, &p),
+                None => Cow::Borrowed(&p[..]),
+            };
+            let stored = kept.filter(|kept| self.storechain && kept % stored_thin == 0);
+            if let Some(kept) = stored {
+                let iteration = kept / self.stored_thin;
+                if let Some(chain) = self.chain.as_mut() {
+                    if iteration == chain.niterations {
+                        chain.extend(1);
+                    }
+                    for (walker_idx, p_value) in positions.iter().enumerate() {
+                        let values: Vec<S> =
+                            p_value.values.iter().map(|&x| S::from_f64(x)).collect();
+                        chain.set_params(walker_idx, iteration, &values);
+                    }
+                }
+                if let Some(store) = self.probstore.as_mut() {
+                    if iteration == store.niterations() {
+                        store.extend(1);
+                    }
+                    let lnprob: Vec<S> = lnprob.iter().map(|&x| S::from_f64(x)).collect();
+                    store.set_probs(iteration, &lnprob);
+                }
+                nstored = iteration + 1;
 
-                        lnprob_slice[walker_idx] = stretch.newlnprob[walker_idx];
-                        /* Update the param vector values */
-                        for (param_idx, param) in stretch.q[walker_idx].values.iter().enumerate() {
-                            first[walker_idx][param_idx] = *param;
+                if let (Some(chain), Some(running)) = (self.chain.as_ref(), self.running.as_mut()) {
+                    running.push(chain, iteration);
+                }
+            }
+
+            if kept.is_some_and(|kept| kept % self.thin == 0) {
+                self.online.push(&positions, &lnprob);
+                if let Some(compression) = self.quantile_sketch {
+                    if self.sketches.is_empty() {
+                        self.sketches = (0..self.dim).map(|_| TDigest::new(compression)).collect();
+                    }
+                    for guess in positions.iter() {
+                        for (sketch, value) in self.sketches.iter_mut().zip(&guess.values) {
+                            sketch.add(*value);
                         }
-                        let real_walker_idx = walker_idx + ensemble_idx * halfk;
-                        self.naccepted[real_walker_idx] += 1;
                     }
                 }
-            }
 
-            /* Update the store variables with the new parameter values */
-            if iteration % self.thin == 0 {
-                let iteration = iteration / self.thin;
-                for (walker_idx, p_value) in p.iter().enumerate() {
-                    self.chain
-                        .as_mut()
-                        .map(|chain| chain.set_params(walker_idx, iteration, &p_value.values));
-
-                    self.probstore
-                        .as_mut()
-                        .map(|store| store.set_probs(iteration, &lnprob));
+                if let Some(live) = self.live.as_mut() {
+                    live.push(&positions, &lnprob);
                 }
             }
 
@@ -587,16 +1324,37 @@ impl<'a, T: Prob + Sync + Send + 'a> EnsembleSampler<'a, T>
                 iteration: iteration,
             };
 
-            callback(step);
+            self.report_progress(iteration + 1, iterations, started, false);
+            if callback(step).is_break() {
+                last_iteration = iteration;
+                break;
+            }
+        }
+        self.report_progress(last_iteration + 1, iterations, started, true);
+
+        if let Some(live) = self.live.as_mut() {
+            live.flush();
+        }
 
-            self.iterations += 1;
+        if self.max_stored_iterations.is_some() {
+            if let Some(chain) = self.chain.as_mut() {
+                chain.truncate(nstored);
+            }
+            if let Some(store) = self.probstore.as_mut() {
+                store.truncate(nstored);
+            }
         }
 
         let step = Step {
             pos: p.clone(),
             lnprob: lnprob.clone(),
-            iteration: iterations - 1,
+            iteration: last_iteration,
         };
+        self.last_state = Some(Step {
+            pos: p,
+            lnprob,
+            iteration: last_iteration,
+        });
 
         Ok(step)
     }
@@ -609,10 +1367,191 @@ impl<'a, T: Prob + Sync + Send + 'a> EnsembleSampler<'a, T>
         self.sample(p0, niterations, |_step| {})
     }
 
-    /// Set the initial state of the sampler
-    pub fn set_initial_state(&mut self, state0: Step) -> &mut Self {
-        self.initial_state = Some(state0);
-        self
+    /// Run the sampling, handing each completed block of iterations to the caller
+    ///
+    /// The sampler does not keep its own copy of the chain during this run; instead, every
+    /// `chunk_size` stored iterations (taking [`thin`](#structfield.thin) into account) are
+    /// passed to `callback` as an owned [`Block`](struct.Block.html). This bounds the peak memory
+    /// use to a single block and lets the caller stream the samples to their own storage. The
+    /// final block may be shorter than `chunk_size`. Like the stored chain, the blocks hold the
+    /// positions given by [`Prob::stored_position`](trait.Prob.html#method.stored_position).
+    ///
+    /// Errors are handled by returning a [`Result`](errors/type.Result.html) which contains
+    /// [`EmceeError::InvalidInputs`](errors/enum.EmceeError.html) if `chunk_size` is zero.
+    pub fn run_in_chunks<F>(
+        &mut self,
+        p0: &[Guess],
+        niterations: usize,
+        chunk_size: usize,
+        mut callback: F,
+    ) -> Result<Step>
+    where
+        F: FnMut(Block),
+    {
+        if chunk_size == 0 {
+            return Err(EmceeError::InvalidInputs(
+                "the chunk size must be positive".into(),
+            ));
+        }
+
+        let (dim, nwalkers, thin, discard) = (self.dim, self.nwalkers, self.thin, self.discard);
+        let lnprob = self.lnprob;
+        let mut block = Block::new(0, dim, nwalkers, chunk_size);
+
+        let storechain = self.storechain;
+        self.storechain = false;
+        self.chain.take();
+        self.probstore.take();
+        self.running.take();
+
+        let result = self.sample(p0, niterations, |step| {
+            if step.iteration < discard || (step.iteration - discard) % thin != 0 {
+                return;
+            }
+
+            block.push(&stored_positions(lnprob, &step.pos), &step.lnprob);
+            if block.len() == chunk_size {
+                let next = Block::new(block.start + chunk_size, dim, nwalkers, chunk_size);
+                callback(::std::mem::replace(&mut block, next));
+            }
+        });
+        self.storechain = storechain;
+
+        let step = result?;
+        if !block.is_empty() {
+            callback(block);
+        }
+        Ok(step)
+    }
+}
+
+impl<'a, T: Prob + Sync + Send + 'a> EnsembleSampler<'a, T> {
+    /// Run the sampling until the chain contains enough independent samples
+    ///
+    /// The run is extended in steps of [`check_interval`](struct.RunLength.html) iterations
+    /// until the estimated number of independent samples per walker (the number of iterations
+    /// divided by the largest integrated autocorrelation time) reaches the requested target, or
+    /// the iteration limit is hit. Every iteration is stored, regardless of
+    /// [`thin`](#structfield.thin), as the autocorrelation time is estimated from the full run.
+    ///
+    /// Errors are handled by returning a [`Result`](errors/type.Result.html) which contains
+    /// [`EmceeError::InvalidInputs`](errors/enum.EmceeError.html) if the check interval or
+    /// iteration limit is zero.
+    pub fn run_until_independent(
+        &mut self,
+        p0: &[Guess],
+        control: &RunLength,
+    ) -> Result<RunLengthReport> {
+        let mut tau = Vec::new();
+        let (state, niterations, converged) =
+            self.run_with_checks(p0, control.check_interval, control.max_iterations, |chain| {
+                tau = chain.integrated_time(control.window);
+                control::nindependent(chain.niterations, &tau) >= control.target
+            })?;
+
+        Ok(RunLengthReport {
+            state,
+            niterations,
+            nindependent: control::nindependent(niterations, &tau),
+            tau,
+            converged,
+        })
+    }
+
+    /// Run the sampling until the chain has converged, returning the number of iterations run
+    ///
+    /// Every `check_every` iterations the integrated autocorrelation time `tau` of each
+    /// parameter is estimated from the full run, and sampling stops once the run is longer than
+    /// [`ntau`](struct.Convergence.html#structfield.ntau) times every `tau` and no estimate has
+    /// changed by more than a fraction [`rtol`](struct.Convergence.html#structfield.rtol) since
+    /// the previous check, or after `max_iters` iterations. As with
+    /// [`run_until_independent`](#method.run_until_independent) every iteration is stored,
+    /// regardless of [`thin`](#structfield.thin).
+    ///
+    /// Errors are handled by returning a [`Result`](errors/type.Result.html) which contains
+    /// [`EmceeError::InvalidInputs`](errors/enum.EmceeError.html) if `check_every` or
+    /// `max_iters` is zero.
+    pub fn run_mcmc_until_converged(
+        &mut self,
+        p0: &[Guess],
+        max_iters: usize,
+        check_every: usize,
+        criterion: &Convergence,
+    ) -> Result<usize> {
+        let mut previous: Option<Vec<f64>> = None;
+        let (_, niterations, _) = self.run_with_checks(p0, check_every, max_iters, |chain| {
+            let tau = chain.integrated_time(criterion.window);
+            let met = previous
+                .as_ref()
+                .is_some_and(|previous| criterion.is_met(chain.niterations, &tau, previous));
+            previous = Some(tau);
+            met
+        })?;
+        Ok(niterations)
+    }
+
+    /// Store every iteration, calling `stop` with the chain every `check_interval` iterations
+    /// until it returns true or `max_iterations` iterations have run
+    ///
+    /// Returns the final state, the number of iterations and whether `stop` ended the run.
+    fn run_with_checks<F>(
+        &mut self,
+        p0: &[Guess],
+        check_interval: usize,
+        max_iterations: usize,
+        mut stop: F,
+    ) -> Result<(Step, usize, bool)>
+    where
+        F: FnMut(&Chain) -> bool,
+    {
+        if check_interval == 0 || max_iterations == 0 {
+            return Err(EmceeError::InvalidInputs(
+                "the check interval and iteration limit must be positive".into(),
+            ));
+        }
+
+        let mut chain = self.new_chain(0);
+        let mut probstore = ProbStore::new(self.nwalkers, 0);
+        let mut running = RunningStats::new(self.dim, self.nwalkers);
+        let model = self.lnprob;
+
+        let mut state = None;
+        let mut stopped = false;
+        while chain.niterations < max_iterations {
+            let start = chain.niterations;
+            let n = check_interval.min(max_iterations - start);
+            chain.extend(n);
+            probstore.extend(n);
+
+            let result = self.advance(p0, n, &mut state, |step| {
+                for (walker_idx, guess) in stored_positions(model, &step.pos).iter().enumerate() {
+                    chain.set_params(walker_idx, start + step.iteration, &guess.values);
+                }
+                probstore.set_probs(start + step.iteration, &step.lnprob);
+                running.push(&chain, start + step.iteration);
+            });
+            if let Err(e) = result {
+                self.initial_state = None;
+                return Err(e);
+            }
+
+            if stop(&chain) {
+                stopped = true;
+                break;
+            }
+        }
+
+        self.initial_state = None;
+
+        let niterations = chain.niterations;
+        if self.storechain {
+            self.chain = Some(chain);
+            self.probstore = Some(probstore);
+            self.running = Some(running);
+        }
+
+        let state = state.expect("at least one block of iterations has run");
+        Ok((state, niterations, stopped))
     }
 
     /// Return the samples as computed by the sampler
@@ -624,226 +1563,1026 @@ impl<'a, T: Prob + Sync + Send + 'a> EnsembleSampler<'a, T>
     pub fn flatprob(&self) -> Option<Vec<f64>> {
         self.probstore.as_ref().map(|probstore| probstore.flatprob())
     }
+}
+
+impl<'a, T: Prob + Sync + Send + 'a, S: Float> EnsembleSampler<'a, T, S> {
+
+    /// Run a warmup phase which adapts the moves towards a target acceptance fraction
+    ///
+    /// The sampler is run for `niterations` iterations, and every
+    /// [`interval`](struct.AcceptanceTarget.html) iterations the tuning parameter of each move
+    /// which has one (see [`Move::tuning`](moves/trait.Move.html#method.tuning)) is adjusted
+    /// towards the target acceptance fraction using a Robbins-Monro update. Each adjustment is
+    /// recorded, and can be inspected afterwards with
+    /// [`adaptation_trace`](#method.adaptation_trace).
+    ///
+    /// Warmup iterations are not stored in the chain. The returned state can be passed to
+    /// [`set_initial_state`](#method.set_initial_state) to continue sampling with the adapted
+    /// moves.
+    ///
+    /// Errors are handled by returning a [`Result`](errors/type.Result.html) which contains
+    /// [`EmceeError::InvalidInputs`](errors/enum.EmceeError.html) if the interval is zero or the
+    /// target is not between 0 and 1.
+    pub fn run_warmup(
+        &mut self,
+        p0: &[Guess],
+        niterations: usize,
+        control: &AcceptanceTarget,
+    ) -> Result<Step> {
+        if control.interval == 0 || niterations == 0 {
+            return Err(EmceeError::InvalidInputs(
+                "the number of iterations and adaptation interval must be positive".into(),
+            ));
+        }
+
+        if !(control.target > 0.0 && control.target < 1.0) {
+            return Err(EmceeError::InvalidInputs(
+                "the target acceptance fraction must be between 0 and 1".into(),
+            ));
+        }
+
+        self.chain.take();
+        self.probstore.take();
+        self.running.take();
+        self.adaptation_trace.clear();
+
+        let mut state = None;
+        let mut done = 0;
+        let mut k = 0;
+        while done < niterations {
+            let n = control.interval.min(niterations - done);
+            let before: Vec<_> = self.move_acceptance
+                .iter()
+                .map(|stats| (stats.nproposed, stats.naccepted))
+                .collect();
+
+            if let Err(e) = self.advance(p0, n, &mut state, |_step| {}) {
+                self.initial_state = None;
+                return Err(e);
+            }
+            done += n;
+
+            for (move_idx, &(nproposed, naccepted)) in before.iter().enumerate() {
+                let stats = &self.move_acceptance[move_idx];
+                let nproposed = stats.nproposed - nproposed;
+                let tuning = match self.moves[move_idx].0.tuning() {
+                    Some(tuning) if nproposed > 0 => tuning,
+                    _ => continue,
+                };
+
+                let acceptance = (stats.naccepted - naccepted) as f64 / nproposed as f64;
+                let tuning = control.adjust(k, tuning, acceptance);
+                self.moves[move_idx].0.set_tuning(tuning);
+                self.adaptation_trace.push(AdaptationRecord {
+                    iteration: done,
+                    move_idx,
+                    acceptance,
+                    tuning,
+                });
+            }
+            k += 1;
+        }
+
+        self.initial_state = None;
+        Ok(state.expect("at least one block of iterations has run"))
+    }
+
+    /// Return the adjustments made to the moves by the last call to
+    /// [`run_warmup`](#method.run_warmup)
+    pub fn adaptation_trace(&self) -> &[AdaptationRecord] {
+        &self.adaptation_trace
+    }
 
-    /// Return the number of iterations accepted, one value per walker
+    /// Set the initial state of the sampler
+    pub fn set_initial_state(&mut self, state0: Step) -> &mut Self {
+        self.initial_state = Some(state0);
+        self
+    }
+
+    /// Publish the stored iterations for reading from other threads
+    ///
+    /// Every iteration which would be stored in the chain (taking [`thin`](#structfield.thin)
+    /// into account) is gathered into blocks of `block_size` iterations. Each completed block is
+    /// published to the returned [`ChainMonitor`](struct.ChainMonitor.html), which can be moved
+    /// to a monitoring thread to compute diagnostics without stopping the run. Any partially
+    /// filled block is published when the run finishes, and starting a new run discards the
+    /// previously published blocks.
+    ///
+    /// Calling this again returns a handle to the same published blocks.
+    pub fn monitor(&mut self, block_size: usize) -> ChainMonitor {
+        let (dim, nwalkers) = (self.dim, self.nwalkers);
+        self.live
+            .get_or_insert_with(|| LiveChain::new(dim, nwalkers, block_size))
+            .monitor()
+    }
+
+    /// Return the stored chain, if the sampler is storing it
+    pub fn chain(&self) -> Option<&Chain<S>> {
+        self.chain.as_ref()
+    }
+
+    /// Return the stored log probabilities, if the sampler is storing the chain
+    ///
+    /// Unlike [`flatprob`](#method.flatprob) this does not copy the values, so monitoring code
+    /// can cheaply track e.g. the best or mean log probability over recent iterations with
+    /// [`ProbStore::max_over`](struct.ProbStore.html#method.max_over) and
+    /// [`ProbStore::mean_over`](struct.ProbStore.html#method.mean_over).
+    pub fn probstore(&self) -> Option<&ProbStore<S>> {
+        self.probstore.as_ref()
+    }
+
+    /// Return the fraction of proposals accepted, one value per walker
+    ///
+    /// The fractions cover every iteration since the sampler was created or
+    /// [`reset`](#method.reset), and are NaN before the first iteration. A walker whose fraction
+    /// is far below the others' is usually stuck, e.g. in a local mode, and fractions between
+    /// about 0.2 and 0.5 are typical of a well mixing ensemble. When
+    /// [parameter groups](#method.set_groups) are used each group makes one proposal per
+    /// iteration.
     pub fn acceptance_fraction(&self) -> Vec<f64> {
+        let nproposals = (self.iterations * self.nsubsteps()) as f64;
         self.naccepted
             .iter()
-            .map(|naccepted| *naccepted as f64 / self.iterations as f64)
+            .map(|naccepted| *naccepted as f64 / nproposals)
             .collect()
     }
 
-    /// Return the sampler to its default state
-    pub fn reset(&mut self) {
-        self.iterations = 0;
-        self.naccepted.resize(0, 0);
-        self.chain.take();
-        self.probstore.take();
+    /// Return the mean of the [acceptance fractions](#method.acceptance_fraction) of the
+    /// walkers, or NaN before the first iteration
+    pub fn mean_acceptance_fraction(&self) -> f64 {
+        let fractions = self.acceptance_fraction();
+        fractions.iter().sum::<f64>() / fractions.len() as f64
     }
 
-    // Internal functions
+    /// Return the running means and variances of the parameters and log probability, which are
+    /// kept whether or not the chain is stored
+    pub fn online_stats(&self) -> &OnlineStats {
+        &self.online
+    }
 
-    fn propose_stretch(&mut self, p0: &[Guess], p1: &[Guess], lnprob0: &[f64]) -> Result<Stretch> {
-        assert_eq!(p0.len() + p1.len(), self.nwalkers);
-        let s = p0;
-        let c = p1;
-        let ns = s.len();
-        let nc = c.len();
-
-        // let z_range = Range::new(1.0f64, 2.0f64);
-        let rint_range = Range::new(0usize, nc);
-        let unit_range = Range::new(0f64, 1f64);
-
-        let mut q = Vec::with_capacity(ns);
-        let mut all_zz = Vec::with_capacity(ns);
-        for sval in s {
-            let zz = ((self.proposal_scale - 1.0) * unit_range.ind_sample(&mut self.rng) + 1.0f64)
-                .powf(2.0f64) / self.proposal_scale;
-            let rint = rint_range.ind_sample(&mut self.rng);
-
-            let mut values = Vec::with_capacity(self.dim);
-            for (param_i, s_param) in sval.values.iter().enumerate() {
-                let random_c = c[rint][param_i];
-                let guess_diff = random_c - s_param;
-                let new_value = random_c - zz * guess_diff;
-                values.push(new_value);
-            }
-            q.push(Guess { values });
-            all_zz.push(zz);
-        }
-        assert_eq!(q.len(), all_zz.len());
+    /// Start the [online statistics](#method.online_stats) afresh, e.g. after burn-in
+    pub fn clear_online_stats(&mut self) {
+        self.online = OnlineStats::new(self.dim);
+        self.sketches.clear();
+    }
 
-        let mut out = Stretch::preallocated_accept(ns);
-        out.newlnprob = self.get_lnprob(&q)?;
-        out.q = q;
+    /// Return the streaming quantile estimate of each parameter, which is empty unless
+    /// [`quantile_sketch`](#structfield.quantile_sketch) is set
+    ///
+    /// Like the [online statistics](#method.online_stats) they accumulate over runs until
+    /// [`clear_online_stats`](#method.clear_online_stats) or [`reset`](#method.reset).
+    pub fn quantile_sketches(&self) -> &[TDigest] {
+        &self.sketches
+    }
 
-        assert_eq!(out.newlnprob.len(), ns);
+    /// Return the running summary statistics of the stored chain
+    ///
+    /// These are updated every time an iteration is stored, so the split Gelman-Rubin statistic
+    /// and the posterior means and variances can be checked cheaply at any point without
+    /// re-scanning the chain, e.g. from the callback given to [`sample`](#method.sample). They
+    /// are only available while the sampler is storing the chain.
+    pub fn running_stats(&self) -> Option<&RunningStats> {
+        self.running.as_ref()
+    }
 
-        for i in 0..ns {
-            assert!(all_zz[i] > 0.);
-            let lnpdiff = (self.dim as f64 - 1.0) * all_zz[i].ln() + out.newlnprob[i] - lnprob0[i];
-            let test_value = unit_range.ind_sample(&mut self.rng).ln();
+    /// Return the acceptance statistics of each move, in the order the moves were given
+    ///
+    /// When several moves are mixed, the aggregate
+    /// [`acceptance_fraction`](#method.acceptance_fraction) can hide a move which is rarely
+    /// accepted; this breaks the numbers down per move.
+    pub fn move_acceptance(&self) -> &[MoveAcceptance] {
+        &self.move_acceptance
+    }
 
-            if lnpdiff > test_value {
-                out.accept[i] = true;
-            }
-        }
-        Ok(out)
+    /// Return the number of model evaluations which exceeded the [`timeout`](#structfield.timeout)
+    pub fn ntimeouts(&self) -> usize {
+        self.run_stats.ntimeouts
     }
 
-    fn get_lnprob(&mut self, p: &[Guess]) -> Result<Vec<f64>> {
-        let mut lnprobs = Vec::with_capacity(p.len());
-        let lnprob = self.lnprob;
-        for guess in p {
-            if guess.contains_infs() {
-                return Err("At least one parameter value was infinite".into());
-            } else if guess.contains_nans() {
-                return Err("At least one parameter value was NaN".into());
-            }
-        }
-        match &mut self.pool {
-            Some(pool) => {
-                for _ in 0..p.len() {
-                    lnprobs.push(0.);
-                }
-                let (tx, rx) = channel();
-                pool.scoped(|scoped| {
-                    for (i, guess) in p.iter().enumerate() {
-                        let tx = tx.clone();
-                        scoped.execute(move || {
-                            tx.send((i, lnprob.lnprob(&guess))).expect("Error sending job results to from the thread pool");
-                        });
-                    }
-                });
+    /// Return the number of model evaluations and the time spent in them
+    pub fn run_stats(&self) -> &RunStats {
+        &self.run_stats
+    }
 
-                for (i, result) in rx.try_iter() {
-                    if result.is_nan() {
-                        return Err("NaN value of lnprob".into());
-                    }
-                    lnprobs[i] = result;
-                }
-            },
-            None => {
-                for guess in p {
-                    let result = self.lnprob.lnprob(guess);
-                    if result.is_nan() {
-                        return Err("NaN value of lnprob".into());
-                    }
-                    lnprobs.push(result);
-                }
-            }
-        }
-        Ok(lnprobs)
+    /// Return the mean and standard deviation of each parameter used to rescale the proposals
+    /// when [`autoscale`](#structfield.autoscale) is enabled
+    pub fn parameter_scales(&self) -> Option<(&[f64], &[f64])> {
+        self.scales
+            .as_ref()
+            .map(|(center, scale)| (center.as_slice(), scale.as_slice()))
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use rand::distributions::Normal;
-    use super::*;
+    /// Return the number of iterations between consecutive iterations of the stored chain
+    ///
+    /// This is [`thin`](#structfield.thin), multiplied by two each time the chain was halved to
+    /// stay within [`max_stored_iterations`](#structfield.max_stored_iterations).
+    pub fn stored_thin(&self) -> usize {
+        self.stored_thin
+    }
 
-    const REAL_M: f64 = 2.0f64;
-    const REAL_C: f64 = 5.0f64;
+    /// Return the origin of every accepted step since the sampler was created or
+    /// [`reset`](#method.reset), if [`track_ancestry`](#structfield.track_ancestry) is set
+    pub fn ancestry(&self) -> &[AncestryRecord] {
+        &self.ancestry
+    }
 
-    struct LinearModel<'a> {
-        x: &'a [f64],
-        y: &'a [f64],
+    /// Return the number of proposals rejected by [`Prob::validate`](trait.Prob.html#method.validate),
+    /// keyed by the failure message
+    pub fn validation_failures(&self) -> &BTreeMap<String, usize> {
+        &self.validation_failures
     }
 
-    impl<'a> LinearModel<'a> {
-        fn new(x: &'a [f64], y: &'a [f64]) -> Self {
-            LinearModel { x, y }
+    /// Clear the results of previous runs, keeping the configuration
+    ///
+    /// The stored chain, acceptance counters, move statistics, validation failures, run and
+    /// online statistics, quantile sketches, initial state, parameter scales, ancestry and
+    /// adaptation history are cleared, any tuning adapted by [`run_warmup`](#method.run_warmup)
+    /// is returned to the value the moves were configured with, and moves forget anything
+    /// learned while sampling.
+    /// The moves themselves, [`storechain`](#structfield.storechain), [`thin`](#structfield.thin), any
+    /// [`monitor`](#method.monitor), any [whitening](#method.set_whitening), the
+    /// [parameter names](#method.set_param_names) and the thread pool are kept. If the sampler
+    /// was [`seed`](#method.seed)ed it is reseeded with the same seed, so a reset sampler
+    /// reproduces the runs of a freshly configured one; this makes it cheap to reuse one sampler
+    /// for many datasets.
+    pub fn reset(&mut self) {
+        self.iterations = 0;
+        self.naccepted = vec![0; self.nwalkers];
+        self.chain.take();
+        self.probstore.take();
+        self.blobs.take();
+        self.running.take();
+        self.online = OnlineStats::new(self.dim);
+        self.sketches.clear();
+        self.initial_state.take();
+        self.last_state.take();
+        self.scales.take();
+        if let Some(live) = self.live.as_mut() {
+            live.clear();
+        }
+        for (&mut (ref mut m, _), tuning) in self.moves.iter_mut().zip(&self.configured_tuning) {
+            m.reset();
+            if let Some(tuning) = *tuning {
+                m.set_tuning(tuning);
+            }
+        }
+        for stats in &mut self.move_acceptance {
+            stats.nproposed = 0;
+            stats.naccepted = 0;
+        }
+        self.adaptation_trace.clear();
+        self.ancestry.clear();
+        self.validation_failures.clear();
+        self.run_stats = RunStats::default();
+        self.approx_lnprob.clear();
+        if let Some(seed) = self.seed.clone() {
+            self.seed(&seed);
         }
     }
 
-    impl<'a> Prob for LinearModel<'a> {
-        fn lnprior(&self, _params: &Guess) -> f64 {
-            0.0f64
-        }
+    // Internal functions
 
-        fn lnlike(&self, params: &Guess) -> f64 {
-            let m = params[0];
-            let c = params[1];
-            let sum = self.x.iter().zip(self.y).fold(0.0f64, |acc, (x, y)| {
-                let model_value = m * x + c;
-                let residual = y - model_value;
-                acc + residual.powf(2.0)
-            });
-            -sum
+    /// Run `niterations` iterations without storing the chain, continuing from `state` if it is
+    /// set, and leave the final state in `state`
+    fn advance<F>(
+        &mut self,
+        p0: &[Guess],
+        niterations: usize,
+        state: &mut Option<Step>,
+        callback: F,
+    ) -> Result<()>
+    where
+        F: FnMut(Step),
+    {
+        if let Some(previous) = state.take() {
+            self.initial_state = Some(previous);
         }
-    }
 
-    struct MultivariateProb<'a> {
-        icov: &'a [[f64; 5]; 5],
+        let storechain = self.storechain;
+        self.storechain = false;
+        let result = self.sample(p0, niterations, callback);
+        self.storechain = storechain;
+
+        *state = Some(result?);
+        Ok(())
     }
 
-    impl<'a> Prob for MultivariateProb<'a> {
-        // Stub methods as they are not used
-        fn lnlike(&self, _params: &Guess) -> f64 {
-            0.0f64
+    /// Discard every other stored iteration and double the thinning of the stored chain,
+    /// returning the number of iterations kept
+    fn compact_storage(&mut self, nstored: usize) -> usize {
+        let mut nkept = nstored;
+        if let Some(chain) = self.chain.as_mut() {
+            nkept = chain.keep_even_iterations(nstored);
         }
-        fn lnprior(&self, _params: &Guess) -> f64 {
-            0.0f64
+        if let Some(store) = self.probstore.as_mut() {
+            store.keep_even_iterations(nstored);
         }
+        self.stored_thin *= 2;
 
-        fn lnprob(&self, params: &Guess) -> f64 {
-            let mut values = [0f64; 5];
-            for (i, value) in params.values.iter().enumerate() {
-                values[i] = *value;
+        if let (Some(chain), Some(running)) = (self.chain.as_ref(), self.running.as_mut()) {
+            *running = RunningStats::new(self.dim, self.nwalkers);
+            for iteration in 0..nkept {
+                running.push(chain, iteration);
             }
-            let inv_prod = mat_vec_mul(&self.icov, &values);
-            -vec_vec_mul(&values, &inv_prod) / 2.0
         }
+        nkept
     }
 
-    #[test]
-    fn test_single_sample() {
-        let (real_x, observed_y) = generate_dataset(20);
-        let foo = LinearModel::new(&real_x, &observed_y);
-        let p0 = create_guess();
-
-        let nwalkers = 10;
-        let mut sampler = EnsembleSampler::new(nwalkers, 2, &foo).unwrap();
-
-        let params = p0.create_initial_guess(nwalkers);
-        sampler.run_mcmc(&params, 1).unwrap();
-        assert_eq!(sampler.iterations, 1);
+    /// A uniformly random permutation of the walkers (Fisher-Yates)
+    fn random_permutation(&mut self) -> Vec<usize> {
+        let mut order: Vec<usize> = (0..self.nwalkers).collect();
+        for i in (1..self.nwalkers).rev() {
+            let j = Range::new(0, i + 1).ind_sample(&mut self.rng);
+            order.swap(i, j);
+        }
+        order
     }
 
-    #[test]
-    fn test_sample_with_callback() {
-        let (real_x, observed_y) = generate_dataset(20);
-        let foo = LinearModel::new(&real_x, &observed_y);
-        let p0 = create_guess();
+    /// Pass the progress of the current run to the reporter, if any
+    fn report_progress(&mut self, done: usize, niterations: usize, started: Instant, last: bool) {
+        if self.progress.is_none() {
+            return;
+        }
+        let progress = Progress {
+            iteration: done,
+            niterations,
+            acceptance_fraction: self.mean_acceptance_fraction(),
+            elapsed: started.elapsed(),
+        };
+        let reporter = self.progress.as_mut().expect("a reporter is set");
+        if last {
+            reporter.finish(&progress);
+        } else {
+            reporter.report(&progress);
+        }
+    }
 
-        let nwalkers = 10;
-        let mut sampler = EnsembleSampler::new(nwalkers, 2, &foo).unwrap();
+    /// Update every walker once with each move in turn, or with one move chosen at random
+    fn take_step(&mut self, p: &mut Rc<Vec<Guess>>, lnprob: &mut Rc<Vec<f64>>) -> Result<()> {
+        let halfk = self.nwalkers / 2;
 
-        let params = p0.create_initial_guess(nwalkers);
+        // Position `i` of the ensemble holds walker `order[i]` during the update
+        let order = if self.shuffle_walkers {
+            let order = self.random_permutation();
+            permute(Rc::make_mut(p), &order);
+            permute(Rc::make_mut(lnprob), &order);
+            permute(&mut self.approx_lnprob, &order);
+            Some(order)
+        } else {
+            None
+        };
+        let walker_at =
+            |position: usize| order.as_ref().map_or(position, |order| order[position]);
+
+        for substep in 0..self.nsubsteps() {
+            let move_idx = if self.grouped {
+                substep
+            } else {
+                self.choose_move()
+            };
+            for ensemble_idx in 0..2 {
+                let (first, second) = if ensemble_idx == 0 {
+                    Rc::make_mut(p).split_at_mut(halfk)
+                } else {
+                    let (second, first) = Rc::make_mut(p).split_at_mut(halfk);
+                    (first, second)
+                };
 
-        let mut counter = 0;
+                let (lnprob_slice, _) = if ensemble_idx == 0 {
+                    Rc::make_mut(lnprob).split_at_mut(halfk)
+                } else {
+                    let (second, first) = Rc::make_mut(lnprob).split_at_mut(halfk);
+                    (first, second)
+                };
 
-        sampler.sample(&params, 2, |_step| counter += 1).unwrap();
-        assert_eq!(counter, 2);
-        assert_eq!(sampler.iterations, 2);
-    }
+                assert_eq!(first.len(), halfk);
+                assert_eq!(second.len(), halfk);
+                assert_eq!(lnprob_slice.len(), halfk);
 
-    #[test]
-    fn test_run_mcmc() {
-        let (real_x, observed_y) = generate_dataset(20);
-        let foo = LinearModel::new(&real_x, &observed_y);
-        let p0 = create_guess();
+                let offset = ensemble_idx * halfk;
+                let stretch = self
+                    .propose(move_idx, first, second, lnprob_slice, offset)
+                    .map_err(|err| locate_walker(err, |i| walker_at(offset + i)))?;
 
-        let nwalkers = 10;
-        let niters = 100;
+                if let Some(observer) = self.observer.as_mut() {
+                    for walker_idx in 0..halfk {
+                        let event = ProposalEvent {
+                            walker: walker_at(offset + walker_idx),
+                            iteration: self.iterations,
+                            move_idx,
+                            old: &first[walker_idx],
+                            new: &stretch.q[walker_idx],
+                            old_lnprob: lnprob_slice[walker_idx],
+                            new_lnprob: stretch.newlnprob[walker_idx],
+                        };
+                        if stretch.accept[walker_idx] {
+                            observer.on_accept(&event);
+                        } else {
+                            observer.on_reject(&event);
+                        }
+                    }
+                }
+
+                if stretch.accept.iter().any(|val| *val) {
+                    /* Some walkers have accepted new positions, so update the store variables */
+                    for walker_idx in 0..halfk {
+                        if !stretch.accept[walker_idx] {
+                            continue;
+                        }
+
+                        lnprob_slice[walker_idx] = stretch.newlnprob[walker_idx];
+                        /* Update the param vector values */
+                        for (param_idx, param) in stretch.q[walker_idx].values.iter().enumerate() {
+                            first[walker_idx][param_idx] = *param;
+                        }
+                        let real_walker_idx = walker_idx + ensemble_idx * halfk;
+                        self.naccepted[walker_at(real_walker_idx)] += 1;
+                        if self.track_ancestry {
+                            let parent = stretch.sources.get(walker_idx).map(|&idx| {
+                                walker_at(if ensemble_idx == 0 { idx + halfk } else { idx })
+                            });
+                            self.ancestry.push(AncestryRecord {
+                                iteration: self.iterations,
+                                walker: walker_at(real_walker_idx),
+                                parent,
+                                move_idx,
+                            });
+                        }
+                        if !stretch.newapprox[walker_idx].is_empty() {
+                            self.approx_lnprob[real_walker_idx]
+                                .clone_from(&stretch.newapprox[walker_idx]);
+                        }
+                    }
+                }
+
+                let stats = &mut self.move_acceptance[move_idx];
+                stats.nproposed += halfk;
+                stats.naccepted += stretch.accept.iter().filter(|val| **val).count();
+            }
+        }
+
+        if let Some(order) = order {
+            unpermute(Rc::make_mut(p), &order);
+            unpermute(Rc::make_mut(lnprob), &order);
+            unpermute(&mut self.approx_lnprob, &order);
+        }
+        Ok(())
+    }
+
+    /// The number of proposals made for each walker per iteration
+    fn nsubsteps(&self) -> usize {
+        if self.grouped {
+            self.moves.len()
+        } else {
+            1
+        }
+    }
+
+    fn choose_move(&mut self) -> usize {
+        if self.moves.len() == 1 {
+            return 0;
+        }
+
+        let total: f64 = self.moves.iter().map(|&(_, weight)| weight).sum();
+        let mut target = Range::new(0f64, total).ind_sample(&mut self.rng);
+        for (idx, &(_, weight)) in self.moves.iter().enumerate() {
+            if target < weight {
+                return idx;
+            }
+            target -= weight;
+        }
+        self.moves.len() - 1
+    }
+
+    fn propose(
+        &mut self,
+        move_idx: usize,
+        p0: &[Guess],
+        p1: &[Guess],
+        lnprob0: &[f64],
+        offset: usize,
+    ) -> Result<Stretch> {
+        assert_eq!(p0.len() + p1.len(), self.nwalkers);
+        let ns = p0.len();
+
+        let proposal = {
+            let whitening = self.whitening.as_ref();
+            let scales = match self.scales {
+                Some((ref center, ref scale)) if self.autoscale && whitening.is_none() => {
+                    Some((center, scale))
+                }
+                _ => None,
+            };
+            let standardise = |positions: &[Guess]| -> Vec<Guess> {
+                if let Some(whitening) = whitening {
+                    return positions
+                        .iter()
+                        .map(|guess| Guess {
+                            values: whitening.whiten(&guess.values),
+                        })
+                        .collect();
+                }
+                let (center, scale) = scales.expect("the ensemble is standardised");
+                positions
+                    .iter()
+                    .map(|guess| Guess {
+                        values: guess.values
+                            .iter()
+                            .zip(center.iter().zip(scale))
+                            .map(|(x, (c, s))| (x - c) / s)
+                            .collect(),
+                    })
+                    .collect()
+            };
+            let unstandardise = |positions: &mut [Guess]| {
+                if let Some(whitening) = whitening {
+                    for q in positions {
+                        q.values = whitening.unwhiten(&q.values);
+                    }
+                } else if let Some((center, scale)) = scales {
+                    for q in positions {
+                        for (x, (c, s)) in q.values.iter_mut().zip(center.iter().zip(scale)) {
+                            *x = *x * s + c;
+                        }
+                    }
+                }
+            };
+
+            // Positions requested by the move are evaluated with the full model, except those
+            // which the model rejects
+            let lnprob = self.lnprob;
+            let options = self.evaluation_options();
+            let run_stats = &mut self.run_stats;
+            let mut pool = if self.reference { None } else { self.pool.as_mut() };
+            let mut evaluate = |positions: &[Guess]| -> Result<Vec<f64>> {
+                let mut positions = positions.to_vec();
+                unstandardise(&mut positions);
+                let valid: Vec<usize> = (0..positions.len())
+                    .filter(|&i| {
+                        let guess = &positions[i];
+                        !guess.contains_infs()
+                            && !guess.contains_nans()
+                            && lnprob.validate(guess).is_ok()
+                    })
+                    .collect();
+                let q: Vec<Guess> = valid.iter().map(|&i| positions[i].clone()).collect();
+                let pool = pool.as_deref_mut();
+                // Moves request their positions in walker order, the same number per walker
+                let per_walker = (positions.len() / ns).max(1);
+                let values = evaluate_all(lnprob, pool, options, run_stats, &q, None)
+                    .map_err(|err| locate_walker(err, |i| valid[i] / per_walker))?;
+                let mut out = vec![-f64::INFINITY; positions.len()];
+                for (&i, value) in valid.iter().zip(values) {
+                    out[i] = value;
+                }
+                Ok(out)
+            };
+
+            let m = &mut self.moves[move_idx].0;
+            let rng = &mut self.rng;
+            if scales.is_some() || whitening.is_some() {
+                let (s, c) = (standardise(p0), standardise(p1));
+                let mut proposal = m.propose_evaluated(&s, lnprob0, &c, rng, &mut evaluate)?;
+                unstandardise(&mut proposal.q);
+                proposal
+            } else {
+                m.propose_evaluated(p0, lnprob0, p1, rng, &mut evaluate)?
+            }
+        };
+        assert_eq!(proposal.q.len(), ns);
+        assert_eq!(proposal.factors.len(), ns);
+
+        // Invalid proposals are never evaluated and are always rejected, and proposals which
+        // have not moved keep their current log probability
+        let mut newlnprob = vec![-f64::INFINITY; ns];
+        let mut pending = Vec::with_capacity(ns);
+        for (i, q) in proposal.q.iter().enumerate() {
+            if let Err(msg) = self.lnprob.validate(q) {
+                *self.validation_failures.entry(msg).or_insert(0) += 1;
+                self.run_stats.nrejected_bounds += 1;
+            } else if q.values == p0[i].values {
+                newlnprob[i] = lnprob0[i];
+                self.run_stats.ncache_hits += 1;
+            } else {
+                pending.push(i);
+            }
+        }
+
+        // Delayed acceptance: each approximation in turn screens the remaining proposals, with
+        // an acceptance test correcting for the previous stage, so only the survivors reach the
+        // full model, whose test then corrects for the last approximation
+        let mut correction = proposal.factors.clone();
+        let mut newapprox = vec![Vec::new(); ns];
+        for level in 0..self.lnprob.napproximations() {
+            let q: Vec<Guess> = pending.iter().map(|&i| proposal.q[i].clone()).collect();
+            let values = self
+                .get_lnprob_at(&q, Some(level))
+                .map_err(|err| locate_walker(err, |i| pending[i]))?;
+            let mut survivors = Vec::with_capacity(pending.len());
+            for (&i, value) in pending.iter().zip(values) {
+                let lndiff = value - self.approx_lnprob[offset + i][level];
+                if self.acceptance.accept(correction[i] + lndiff, &mut self.rng) {
+                    survivors.push(i);
+                } else {
+                    self.run_stats.nscreened += 1;
+                }
+                correction[i] = -lndiff;
+                newapprox[i].push(value);
+            }
+            pending = survivors;
+        }
+
+        let values = if pending.len() == ns {
+            self.get_lnprob(&proposal.q)?
+        } else {
+            let q: Vec<Guess> = pending.iter().map(|&i| proposal.q[i].clone()).collect();
+            self.get_lnprob(&q)
+                .map_err(|err| locate_walker(err, |i| pending[i]))?
+        };
+        for (&i, value) in pending.iter().zip(values) {
+            if value == -f64::INFINITY {
+                self.run_stats.nrejected_bounds += 1;
+            }
+            newlnprob[i] = value;
+        }
+
+        let mut out = Stretch::preallocated_accept(ns);
+        out.newlnprob = newlnprob;
+        out.newapprox = newapprox;
+        out.sources = proposal.sources;
+        out.q = proposal.q;
+
+        assert_eq!(out.newlnprob.len(), ns);
+
+        for (i, accept) in out.accept.iter_mut().enumerate() {
+            let lnpdiff = correction[i] + out.newlnprob[i] - lnprob0[i];
+            *accept = self.acceptance.accept(lnpdiff, &mut self.rng);
+        }
+        Ok(out)
+    }
+
+    /// An empty chain for this sampler's parameters and walkers, with any parameter names
+    fn new_chain(&self, niterations: usize) -> Chain<S> {
+        let mut chain = Chain::zeros(self.dim, self.nwalkers, niterations);
+        chain.names = self.param_names.clone();
+        chain
+    }
+
+    fn get_lnprob(&mut self, p: &[Guess]) -> Result<Vec<f64>> {
+        self.get_lnprob_at(p, None)
+    }
+
+    /// Evaluate the full model, or approximation `level` of it; only evaluations of the full
+    /// model are counted in the run statistics
+    fn get_lnprob_at(&mut self, p: &[Guess], level: Option<usize>) -> Result<Vec<f64>> {
+        let options = self.evaluation_options();
+        let pool = if self.reference { None } else { self.pool.as_mut() };
+        evaluate_all(self.lnprob, pool, options, &mut self.run_stats, p, level)
+    }
+
+    fn evaluation_options(&self) -> EvaluationOptions {
+        EvaluationOptions {
+            parallelism: self.parallelism,
+            timeout: self.timeout,
+            retry: self.retry,
+            nan_policy: self.nan_policy,
+            iteration: self.iterations,
+        }
+    }
+}
+
+/// How each evaluation of the model is made
+#[derive(Clone, Copy)]
+struct EvaluationOptions {
+    parallelism: Parallelism,
+    timeout: Option<Duration>,
+    retry: RetryPolicy,
+    nan_policy: NanPolicy,
+    iteration: usize,
+}
+
+/// The result of evaluating the model at one position
+enum Outcome {
+    Value(f64),
+    TimedOut,
+    Failed,
+}
+
+/// Evaluate every position in `p` with the full model, or approximation `level` of it, in
+/// parallel if a thread pool is given; only evaluations of the full model are counted in
+/// `run_stats`
+fn evaluate_all<T: Prob + Sync + Send>(
+    lnprob: &T,
+    pool: Option<&mut Pool>,
+    options: EvaluationOptions,
+    run_stats: &mut RunStats,
+    p: &[Guess],
+    level: Option<usize>,
+) -> Result<Vec<f64>> {
+    let mut lnprobs = Vec::with_capacity(p.len());
+    for guess in p {
+        if guess.contains_infs() {
+            return Err("At least one parameter value was infinite".into());
+        } else if guess.contains_nans() {
+            return Err("At least one parameter value was NaN".into());
+        }
+    }
+    if level.is_none() && lnprob.is_batched() {
+        return evaluate_batch(lnprob, options, run_stats, p);
+    }
+    match pool {
+        Some(pool) if options.parallelism == Parallelism::Walkers => {
+            for _ in 0..p.len() {
+                lnprobs.push(0.);
+            }
+            let (tx, rx) = channel();
+            pool.scoped(|scoped| {
+                for (i, guess) in p.iter().enumerate() {
+                    let tx = tx.clone();
+                    scoped.execute(move || {
+                        tx.send((i, evaluate(lnprob, &guess, level, options, None))).expect("Error sending job results to from the thread pool");
+                    });
+                }
+            });
+
+            for (i, (outcome, elapsed, retries)) in rx.try_iter() {
+                lnprobs[i] = record_outcome(run_stats, level, outcome, elapsed, retries);
+            }
+        },
+        mut pool => {
+            for guess in p {
+                let (outcome, elapsed, retries) =
+                    evaluate(lnprob, guess, level, options, pool.as_deref_mut());
+                lnprobs.push(record_outcome(run_stats, level, outcome, elapsed, retries));
+            }
+        }
+    }
+    check_finite(&mut lnprobs, p, options, run_stats)?;
+    Ok(lnprobs)
+}
+
+/// Evaluate every position in `p` with a single call to the full model, counting each
+/// position as one evaluation in `run_stats`
+fn evaluate_batch<T: Prob>(
+    lnprob: &T,
+    options: EvaluationOptions,
+    run_stats: &mut RunStats,
+    p: &[Guess],
+) -> Result<Vec<f64>> {
+    let mut lnprobs = vec![0f64; p.len()];
+    if p.is_empty() {
+        return Ok(lnprobs);
+    }
+    let start = Instant::now();
+    lnprob.lnprob_batch(p, &mut lnprobs);
+    run_stats.nevaluations += p.len();
+    run_stats.evaluation_time += start.elapsed();
+    check_finite(&mut lnprobs, p, options, run_stats)?;
+    Ok(lnprobs)
+}
+
+/// Evaluate one position with the full model, or approximation `level` of it, returning the
+/// outcome, the time taken and the number of retries; the full model is handed `pool` if one is
+/// given
+fn evaluate<T: Prob>(
+    lnprob: &T,
+    guess: &Guess,
+    level: Option<usize>,
+    options: EvaluationOptions,
+    pool: Option<&mut Pool>,
+) -> (Outcome, Duration, usize) {
+    let start = Instant::now();
+    let mut retries = 0;
+    let outcome = match (level, options.timeout, pool) {
+        (Some(level), _, _) => Outcome::Value(lnprob.lnprob_approximation(guess, level)),
+        (None, None, Some(pool)) => Outcome::Value(lnprob.lnprob_in_pool(guess, pool)),
+        (None, None, None) => {
+            let (result, nretries) = options.retry.run(|| lnprob.try_lnprob(guess));
+            retries = nretries;
+            result.map_or(Outcome::Failed, Outcome::Value)
+        }
+        (None, Some(timeout), _) => {
+            let deadline = start + timeout;
+            lnprob
+                .lnprob_with_deadline(guess, deadline)
+                .filter(|_| Instant::now() <= deadline)
+                .map_or(Outcome::TimedOut, Outcome::Value)
+        }
+    };
+    (outcome, start.elapsed(), retries)
+}
+
+/// Count an evaluation in `run_stats`, if it was of the full model, and convert its outcome to
+/// a log probability
+fn record_outcome(
+    run_stats: &mut RunStats,
+    level: Option<usize>,
+    outcome: Outcome,
+    elapsed: Duration,
+    retries: usize,
+) -> f64 {
+    if level.is_none() {
+        run_stats.nevaluations += 1;
+        run_stats.evaluation_time += elapsed;
+    }
+    run_stats.nretries += retries;
+    match outcome {
+        Outcome::Value(value) => value,
+        Outcome::TimedOut => {
+            run_stats.ntimeouts += 1;
+            -f64::INFINITY
+        }
+        Outcome::Failed => {
+            run_stats.nfailures += 1;
+            -f64::INFINITY
+        }
+    }
+}
+
+/// Apply the [`NanPolicy`](enum.NanPolicy.html) of `options` to the log probabilities of the
+/// positions `p`; an error names the index of the position in `p` as its walker
+fn check_finite(
+    lnprobs: &mut [f64],
+    p: &[Guess],
+    options: EvaluationOptions,
+    run_stats: &mut RunStats,
+) -> Result<()> {
+    for (walker, (value, guess)) in lnprobs.iter_mut().zip(p).enumerate() {
+        if value.is_nan() || *value == f64::INFINITY {
+            match options.nan_policy {
+                NanPolicy::Error => {
+                    return Err(EmceeError::NonFiniteProbability {
+                        walker,
+                        iteration: options.iteration,
+                        position: guess.values.clone(),
+                    });
+                }
+                NanPolicy::TreatAsNegInf => {
+                    run_stats.nnonfinite += 1;
+                    *value = -f64::INFINITY;
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+/// The positions to store for the sampled ensemble `p`, as given by
+/// [`Prob::stored_position`](trait.Prob.html#method.stored_position)
+pub(crate) fn stored_positions<'p, T: Prob>(lnprob: &T, p: &'p [Guess]) -> Cow<'p, [Guess]> {
+    match p.iter().map(|guess| lnprob.stored_position(guess)).collect() {
+        Some(stored) => Cow::Owned(stored),
+        None => Cow::Borrowed(p),
+    }
+}
+
+/// Renumber the walker of a [`NonFiniteProbability`](errors/enum.EmceeError.html) error with
+/// `walker_at`, leaving other errors unchanged
+pub(crate) fn locate_walker<F: Fn(usize) -> usize>(err: EmceeError, walker_at: F) -> EmceeError {
+    match err {
+        EmceeError::NonFiniteProbability {
+            walker,
+            iteration,
+            position,
+        } => EmceeError::NonFiniteProbability {
+            walker: walker_at(walker),
+            iteration,
+            position,
+        },
+        err => err,
+    }
+}
+
+/// Reorder `values` so that position `i` holds the value previously at `order[i]`
+fn permute<V: Clone>(values: &mut Vec<V>, order: &[usize]) {
+    if values.len() == order.len() {
+        *values = order.iter().map(|&i| values[i].clone()).collect();
+    }
+}
+
+/// Undo [`permute`](fn.permute.html)
+fn unpermute<V: Clone>(values: &mut Vec<V>, order: &[usize]) {
+    if values.len() == order.len() {
+        let mut restored = values.clone();
+        for (value, &i) in values.iter().zip(order) {
+            restored[i] = value.clone();
+        }
+        *values = restored;
+    }
+}
+
+/// Mean and standard deviation of each parameter over the walkers, with degenerate or
+/// non-finite scales replaced by 1
+fn ensemble_scales(p: &[Guess]) -> (Vec<f64>, Vec<f64>) {
+    let (mean, cov) = linalg::mean_covariance(p);
+    let scale = cov
+        .iter()
+        .enumerate()
+        .map(|(i, row)| {
+            let std = row[i].sqrt();
+            if std.is_finite() && std > 0.0 {
+                std
+            } else {
+                1.0
+            }
+        })
+        .collect();
+    (mean, scale)
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::distributions::Normal;
+    use super::*;
+
+    const REAL_M: f64 = 2.0f64;
+    const REAL_C: f64 = 5.0f64;
+
+    struct LinearModel<'a> {
+        x: &'a [f64],
+        y: &'a [f64],
+    }
+
+    impl<'a> LinearModel<'a> {
+        fn new(x: &'a [f64], y: &'a [f64]) -> Self {
+            LinearModel { x, y }
+        }
+    }
+
+    impl<'a> Prob for LinearModel<'a> {
+        fn lnprior(&self, _params: &Guess) -> f64 {
+            0.0f64
+        }
+
+        fn lnlike(&self, params: &Guess) -> f64 {
+            let m = params[0];
+            let c = params[1];
+            let sum = self.x.iter().zip(self.y).fold(0.0f64, |acc, (x, y)| {
+                let model_value = m * x + c;
+                let residual = y - model_value;
+                acc + residual.powf(2.0)
+            });
+            -sum
+        }
+    }
+
+    struct MultivariateProb<'a> {
+        icov: &'a [[f64; 5]; 5],
+    }
+
+    impl<'a> Prob for MultivariateProb<'a> {
+        // Stub methods as they are not used
+        fn lnlike(&self, _params: &Guess) -> f64 {
+            0.0f64
+        }
+        fn lnprior(&self, _params: &Guess) -> f64 {
+            0.0f64
+        }
+
+        fn lnprob(&self, params: &Guess) -> f64 {
+            let mut values = [0f64; 5];
+            for (i, value) in params.values.iter().enumerate() {
+                values[i] = *value;
+            }
+            let inv_prod = mat_vec_mul(&self.icov, &values);
+            -vec_vec_mul(&values, &inv_prod) / 2.0
+        }
+    }
+
+    #[test]
+    fn test_single_sample() {
+        let (real_x, observed_y) = generate_dataset(20);
+        let foo = LinearModel::new(&real_x, &observed_y);
+        let p0 = create_guess();
+
+        let nwalkers = 10;
         let mut sampler = EnsembleSampler::new(nwalkers, 2, &foo).unwrap();
 
         let params = p0.create_initial_guess(nwalkers);
-        sampler.run_mcmc(&params, niters).unwrap();
+        sampler.run_mcmc(&params, 1).unwrap();
+        assert_eq!(sampler.iterations, 1);
     }
 
     #[test]
-    fn test_with_initial_state() {
+    fn test_sample_with_callback() {
+        let (real_x, observed_y) = generate_dataset(20);
+        let foo = LinearModel::new(&real_x, &observed_y);
+        let p0 = create_guess();
+
+        let nwalkers = 10;
+        let mut sampler = EnsembleSampler::new(nwalkers, 2, &foo).unwrap();
+
+        let params = p0.create_initial_guess(nwalkers);
+
+        let mut counter = 0;
+
+        sampler.sample(&params, 2, |_step| counter += 1).unwrap();
+        assert_eq!(counter, 2);
+        assert_eq!(sampler.iterations, 2);
+    }
+
+    #[test]
+    fn test_run_mcmc() {
         let (real_x, observed_y) = generate_dataset(20);
         let foo = LinearModel::new(&real_x, &observed_y);
         let p0 = create_guess();
@@ -853,257 +2592,1801 @@ mod tests {
         let mut sampler = EnsembleSampler::new(nwalkers, 2, &foo).unwrap();
 
         let params = p0.create_initial_guess(nwalkers);
-        let lnprob0: Vec<_> = params.iter().map(|val| foo.lnprob(val)).collect();
+        sampler.run_mcmc(&params, niters).unwrap();
+    }
 
-        let initial_state = Step {
-            pos: Rc::new(params.clone()),
-            lnprob: Rc::new(lnprob0.clone()),
-            iteration: 0,
-        };
+    #[test]
+    fn test_with_data() {
+        let (real_x, observed_y) = generate_dataset(20);
+        let foo = LinearModel::new(&real_x, &observed_y);
+        let model = with_data((real_x.clone(), observed_y.clone()), |params: &Guess, data: &(Vec<f64>, Vec<f64>)| {
+            LinearModel::new(&data.0, &data.1).lnprob(params)
+        });
+        let p0 = create_guess();
+        assert_eq!(model.lnprob(&p0), foo.lnprob(&p0));
+
+        let nwalkers = 10;
+        let mut sampler = EnsembleSampler::threaded(nwalkers, 2, &model, 4).unwrap();
+        let params = p0.create_initial_guess(nwalkers);
+        sampler.run_mcmc(&params, 10).unwrap();
+        assert_eq!(sampler.iterations, 10);
+        assert_eq!(model.data().0, real_x);
+    }
+
+    #[test]
+    fn test_set_threads() {
+        let (real_x, observed_y) = generate_dataset(20);
+        let foo = LinearModel::new(&real_x, &observed_y);
+        let nwalkers = 10;
+        let params = create_guess().create_initial_guess(nwalkers);
+
+        let mut serial = EnsembleSampler::new(nwalkers, 2, &foo).unwrap();
+        assert_eq!(serial.threads(), 1);
+        serial.seed(&[1]);
+        serial.run_mcmc(&params, 50).unwrap();
+
+        let mut parallel = EnsembleSampler::new(nwalkers, 2, &foo).unwrap();
+        parallel.set_threads(3).unwrap();
+        assert_eq!(parallel.threads(), 3);
+        parallel.seed(&[1]);
+        parallel.run_mcmc(&params, 50).unwrap();
+        assert_eq!(parallel.flatchain(), serial.flatchain());
+        assert_eq!(parallel.flatprob(), serial.flatprob());
+
+        parallel.set_threads(1).unwrap();
+        assert_eq!(parallel.threads(), 1);
+        assert!(parallel.set_threads(0).is_err());
+    }
+
+    #[test]
+    fn test_parallelism_within_evaluation() {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+
+        struct Pooled<'a> {
+            inner: LinearModel<'a>,
+            npooled: AtomicUsize,
+        }
+
+        impl<'a> Prob for Pooled<'a> {
+            fn lnlike(&self, params: &Guess) -> f64 {
+                self.inner.lnlike(params)
+            }
+
+            fn lnprior(&self, params: &Guess) -> f64 {
+                self.inner.lnprior(params)
+            }
+
+            fn lnprob_in_pool(&self, params: &Guess, pool: &mut Pool) -> f64 {
+                self.npooled.fetch_add(1, Ordering::SeqCst);
+                let mut result = 0.0;
+                pool.scoped(|scoped| scoped.execute(|| result = self.lnprob(params)));
+                result
+            }
+        }
+
+        let (real_x, observed_y) = generate_dataset(20);
+        let model = Pooled {
+            inner: LinearModel::new(&real_x, &observed_y),
+            npooled: AtomicUsize::new(0),
+        };
+        let nwalkers = 10;
+        let params = create_guess().create_initial_guess(nwalkers);
+
+        let mut walkers = EnsembleSampler::threaded(nwalkers, 2, &model, 2).unwrap();
+        assert_eq!(walkers.parallelism(), Parallelism::Walkers);
+        walkers.seed(&[1]);
+        walkers.run_mcmc(&params, 20).unwrap();
+        assert_eq!(model.npooled.load(Ordering::SeqCst), 0);
+
+        let mut pooled = EnsembleSampler::threaded(nwalkers, 2, &model, 2).unwrap();
+        pooled.set_parallelism(Parallelism::Evaluation).seed(&[1]);
+        pooled.run_mcmc(&params, 20).unwrap();
+        assert_eq!(pooled.flatchain(), walkers.flatchain());
+        assert_eq!(
+            model.npooled.load(Ordering::SeqCst),
+            pooled.run_stats().nevaluations
+        );
+    }
+
+    #[test]
+    fn test_shuffle_walkers() {
+        let order = [2, 0, 3, 1];
+        let mut values = vec!['a', 'b', 'c', 'd'];
+        permute(&mut values, &order);
+        assert_eq!(values, vec!['c', 'a', 'd', 'b']);
+        unpermute(&mut values, &order);
+        assert_eq!(values, vec!['a', 'b', 'c', 'd']);
+
+        let (real_x, observed_y) = load_baked_dataset();
+        let foo = LinearModel::new(&real_x, &observed_y);
+        let nwalkers = 10;
+        let mut rng = StdRng::from_seed(&[1, 2, 3, 4]);
+        let pos = create_guess().create_initial_guess_with_rng(nwalkers, &mut rng);
+
+        let run = |shuffle: bool| {
+            let mut sampler = EnsembleSampler::new(nwalkers, 2, &foo).unwrap();
+            sampler.seed(&[5]);
+            sampler.shuffle_walkers = shuffle;
+            sampler.track_ancestry = true;
+            sampler.run_mcmc(&pos, 2000).unwrap();
+            sampler
+        };
+        let fixed = run(false);
+        let shuffled = run(true);
+        assert_eq!(shuffled.flatchain(), run(true).flatchain());
+        assert!(shuffled.flatchain() != fixed.flatchain());
+
+        // Walkers keep their identity: every move of a walker in the chain after the first
+        // iteration is recorded against it
+        let chain = shuffled.chain().unwrap();
+        for walker in 0..nwalkers {
+            let nmoves = (1..chain.niterations)
+                .filter(|&iter| chain.get(0, walker, iter) != chain.get(0, walker, iter - 1))
+                .count();
+            let records = shuffled
+                .ancestry()
+                .iter()
+                .filter(|record| record.walker == walker)
+                .collect::<Vec<_>>();
+            assert_eq!(records.len(), shuffled.naccepted[walker]);
+            assert_eq!(records.iter().filter(|record| record.iteration > 0).count(), nmoves);
+        }
+
+        let mean = |sampler: &EnsembleSampler<LinearModel>| {
+            let (_, production) = sampler.chain().unwrap().split_at(500);
+            let flat = production.flatchain();
+            flat.iter().map(|guess| guess[0]).sum::<f64>() / flat.len() as f64
+        };
+        assert_approx_eq!(mean(&shuffled), mean(&fixed), 0.02);
+    }
+
+    #[test]
+    fn test_reference_mode() {
+        let (real_x, observed_y) = generate_dataset(20);
+        let foo = LinearModel::new(&real_x, &observed_y);
+        let nwalkers = 10;
+        let params = create_guess().create_initial_guess(nwalkers);
+
+        let mut serial = EnsembleSampler::new(nwalkers, 2, &foo).unwrap();
+        assert!(!serial.reference_mode());
+        serial.set_reference_mode(&[1, 2]);
+        assert!(serial.reference_mode());
+        serial.run_mcmc(&params, 50).unwrap();
+
+        let mut threaded = EnsembleSampler::threaded(nwalkers, 2, &foo, 4).unwrap();
+        threaded.set_reference_mode(&[1, 2]);
+        threaded.run_mcmc(&params, 50).unwrap();
+        assert_eq!(threaded.flatchain(), serial.flatchain());
+
+        // Resetting restores the reference seed
+        threaded.reset();
+        assert!(threaded.reference_mode());
+        threaded.run_mcmc(&params, 50).unwrap();
+        assert_eq!(threaded.flatchain(), serial.flatchain());
+
+        threaded.reset();
+        threaded.timeout = Some(Duration::from_secs(1));
+        assert!(threaded.run_mcmc(&params, 50).is_err());
+    }
+
+    #[test]
+    fn test_with_initial_state() {
+        let (real_x, observed_y) = generate_dataset(20);
+        let foo = LinearModel::new(&real_x, &observed_y);
+        let p0 = create_guess();
+
+        let nwalkers = 10;
+        let niters = 100;
+        let mut sampler = EnsembleSampler::new(nwalkers, 2, &foo).unwrap();
+
+        let params = p0.create_initial_guess(nwalkers);
+        let lnprob0: Vec<_> = params.iter().map(|val| foo.lnprob(val)).collect();
+
+        let initial_state = Step {
+            pos: Rc::new(params.clone()),
+            lnprob: Rc::new(lnprob0.clone()),
+            iteration: 0,
+        };
+
+        sampler
+            .set_initial_state(initial_state)
+            .run_mcmc(&params, niters)
+            .unwrap();
+    }
+
+    #[test]
+    fn test_restart_sampler() {
+        let (real_x, observed_y) = generate_dataset(20);
+        let foo = LinearModel::new(&real_x, &observed_y);
+        let p0 = create_guess();
+
+        let nwalkers = 10;
+        let niters = 100;
+        let mut sampler = EnsembleSampler::new(nwalkers, 2, &foo).unwrap();
+
+        let params = p0.create_initial_guess(nwalkers);
+
+        let state = sampler.run_mcmc(&params, niters).unwrap();
+
+        sampler.reset();
+        sampler
+            .set_initial_state(state)
+            .run_mcmc(&params, niters)
+            .unwrap();
+    }
+
+    #[test]
+    fn test_lnprob() {
+        /* PYTHON OUTPUT
+         *  x: [ 0.20584494  0.58083612  1.5599452   1.5601864   1.81824967  1.8340451
+         *   2.12339111  2.9122914   3.04242243  3.74540119  4.31945019  5.24756432
+         *   5.98658484  6.01115012  7.08072578  7.31993942  8.32442641  8.66176146
+         *   9.50714306  9.69909852]
+         *
+         * y: [  4.39885877   6.47591958   7.21186633   6.70806911  10.10214811
+         *    8.4423139    9.31431042   9.39983462  10.54046213  12.60172497
+         *   12.4879068   15.87082665  16.37253099  16.73060649  18.55974494
+         *   21.49215702  21.63535559  21.26581199  24.83683104  23.17735339]
+         *
+         *
+         * pos: [[  2.08863595e-06   2.08863595e-06]
+         * [ -1.95967012e-05  -1.95967012e-05]
+         * [ -1.32818605e-05  -1.32818605e-05]
+         * [  1.96861236e-06   1.96861236e-06]]
+
+         * result: array([-4613.19497084, -4613.277985  , -4613.25381092, -4613.1954303 ])
+         */
+        let (real_x, observed_y) = load_baked_dataset();
+        let mut pos = Vec::new();
+        pos.push(Guess {
+            values: vec![2.08863595e-06, 2.08863595e-06],
+        });
+        pos.push(Guess {
+            values: vec![-1.95967012e-05, -1.95967012e-05],
+        });
+        pos.push(Guess {
+            values: vec![-1.32818605e-05, -1.32818605e-05],
+        });
+        pos.push(Guess {
+            values: vec![1.96861236e-06, 1.96861236e-06],
+        });
+        let foo = LinearModel::new(&real_x, &observed_y);
+
+        let nwalkers = 8;
+        let mut sampler = EnsembleSampler::new(nwalkers, 2, &foo).unwrap();
+        let lnprob = sampler.get_lnprob(&pos).unwrap();
+        let expected: Vec<f64> = vec![-4613.19497084, -4613.277985, -4613.25381092, -4613.1954303];
+        for (a, b) in lnprob.iter().zip(expected) {
+            assert_approx_eq!(a, b);
+        }
+    }
+
+    #[test]
+    fn test_lnprob_implementations() {
+        let p0 = create_guess();
+        let (real_x, observed_y) = load_baked_dataset();
+        let foo = LinearModel::new(&real_x, &observed_y);
+
+        let expected = -4613.202966359966f64;
+        assert_approx_eq!(foo.lnprob(&p0), expected);
+    }
+
+    #[test]
+    fn test_propose_stretch() {
+        let nwalkers = 100;
+        let p0 = Guess {
+            values: vec![2.0f64, 5.0f64],
+        };
+
+        let pos = p0.create_initial_guess(nwalkers);
+        let (real_x, observed_y) = load_baked_dataset();
+        let foo = LinearModel::new(&real_x, &observed_y);
+
+        let mut sampler = EnsembleSampler::new(nwalkers, p0.values.len(), &foo).unwrap();
+        let (a, b) = pos.split_at(nwalkers / 2);
+
+        assert_eq!(a.len(), nwalkers / 2);
+        assert_eq!(b.len(), nwalkers / 2);
+
+        let lnprob = sampler.get_lnprob(&pos).unwrap();
+        let _stretch = sampler.propose(0, &a, &b, &lnprob, 0).unwrap();
+    }
+
+    #[test]
+    fn test_mcmc_run() {
+        let nwalkers = 20;
+        let p0 = Guess {
+            values: vec![0f64, 0f64],
+        };
+        let mut rng = StdRng::from_seed(&[1, 2, 3, 4]);
+        let pos = p0.create_initial_guess_with_rng(nwalkers, &mut rng);
+        let (real_x, observed_y) = load_baked_dataset();
+        let foo = LinearModel::new(&real_x, &observed_y);
+
+        let niters = 1000;
+        let mut sampler = EnsembleSampler::new(nwalkers, p0.values.len(), &foo).unwrap();
+        sampler.seed(&[0]);
+        let _ = sampler.run_mcmc(&pos, niters).unwrap();
+
+        if let Some(ref chain) = sampler.chain {
+            /* Wide margins due to random numbers :( */
+            assert_approx_eq!(chain.get(0, 0, niters - 2), 2.0f64, 1.0f64);
+            assert_approx_eq!(chain.get(1, 0, niters - 2), 5.0f64, 1.0f64);
+        }
+    }
+
+    #[test]
+    fn test_nwalkers_even() {
+        let (real_x, observed_y) = load_baked_dataset();
+        let foo = LinearModel::new(&real_x, &observed_y);
+        match EnsembleSampler::new(3, 3, &foo) {
+            Err(EmceeError::InvalidInputs(msg)) => {
+                assert!(msg.contains("number of walkers must be even"));
+            }
+            _ => panic!("incorrect"),
+        }
+    }
+
+    #[test]
+    fn test_enough_walkers() {
+        let (real_x, observed_y) = load_baked_dataset();
+        let foo = LinearModel::new(&real_x, &observed_y);
+        match EnsembleSampler::new(4, 3, &foo) {
+            Err(EmceeError::InvalidInputs(msg)) => {
+                assert!(msg.contains("should be more than twice"));
+            }
+            _ => panic!("incorrect"),
+        }
+    }
+
+    #[test]
+    fn test_lnprob_gaussian() {
+        // Testing the probability model for the multivariate test
+        let icov = [
+            [
+                318.92634269,
+                531.39511426,
+                -136.10315845,
+                154.17685545,
+                552.308813,
+            ],
+            [
+                531.39511426,
+                899.91793286,
+                -224.74333441,
+                258.98686842,
+                938.32014715,
+            ],
+            [
+                -136.10315845,
+                -224.74333441,
+                60.61145495,
+                -66.68898448,
+                -232.52035701,
+            ],
+            [
+                154.17685545,
+                258.98686842,
+                -66.68898448,
+                83.9979827,
+                266.44429402,
+            ],
+            [
+                552.308813,
+                938.32014715,
+                -232.52035701,
+                266.44429402,
+                983.33032073,
+            ],
+        ];
+
+        let guesses = &[
+            Guess::new(&[5., 5., 5., 5., 5.]),
+            Guess::new(&[5., 5., 5., 5., 9.]),
+            Guess::new(&[5., 120., 5., 5., 9.]),
+        ];
+        let expecteds = &[-80374.2068729, -138398.513797, -7902962.23125];
+
+        for (guess, expected) in guesses.iter().zip(expecteds) {
+            let p = MultivariateProb { icov: (&icov) };
+            assert_approx_eq!(p.lnprob(&guess), expected, 1E-4);
+        }
+    }
+
+    #[test]
+    fn test_not_storing_chain() {
+        let nwalkers = 20;
+        let p0 = Guess {
+            values: vec![0f64, 0f64],
+        };
+        let mut rng = StdRng::from_seed(&[1, 2, 3, 4]);
+        let pos = p0.create_initial_guess_with_rng(nwalkers, &mut rng);
+        let (real_x, observed_y) = load_baked_dataset();
+        let foo = LinearModel::new(&real_x, &observed_y);
+
+        let niters = 1000;
+        let mut sampler = EnsembleSampler::new(nwalkers, p0.values.len(), &foo).unwrap();
+        sampler.seed(&[0]);
+        sampler.storechain = false;
+        let _ = sampler.run_mcmc(&pos, niters).unwrap();
+        assert!(sampler.chain.is_none());
+    }
+
+    #[test]
+    fn test_discard() {
+        let nwalkers = 20;
+        let p0 = Guess {
+            values: vec![0f64, 0f64],
+        };
+        let mut rng = StdRng::from_seed(&[1, 2, 3, 4]);
+        let pos = p0.create_initial_guess_with_rng(nwalkers, &mut rng);
+        let (real_x, observed_y) = load_baked_dataset();
+        let foo = LinearModel::new(&real_x, &observed_y);
+
+        let mut full = EnsembleSampler::new(nwalkers, p0.values.len(), &foo).unwrap();
+        full.seed(&[0]);
+        full.run_mcmc(&pos, 100).unwrap();
+
+        let mut discarded = EnsembleSampler::new(nwalkers, p0.values.len(), &foo).unwrap();
+        discarded.seed(&[0]);
+        discarded.discard = 40;
+        discarded.thin = 3;
+        discarded.run_mcmc(&pos, 100).unwrap();
+
+        // Iterations 40, 43, ..., 97 are kept
+        let chain = discarded.chain().unwrap();
+        assert_eq!(chain.niterations, 20);
+        let expected: Vec<Guess> = (40..100)
+            .step_by(3)
+            .flat_map(|iter| {
+                let full = full.chain().unwrap();
+                (0..nwalkers)
+                    .map(move |walker| {
+                        Guess::new(&[full.get(0, walker, iter), full.get(1, walker, iter)])
+                    })
+                    .collect::<Vec<_>>()
+            })
+            .collect();
+        assert_eq!(discarded.flatchain().unwrap(), expected);
+        assert_eq!(discarded.online_stats().count(), 20 * nwalkers);
+        assert_eq!(discarded.flatprob().unwrap().len(), 20 * nwalkers);
+
+        let mut blocks = Vec::new();
+        discarded.reset();
+        discarded.seed(&[0]);
+        discarded
+            .run_in_chunks(&pos, 100, 8, |block| blocks.push(block))
+            .unwrap();
+        assert_eq!(blocks.iter().map(|block| block.len()).sum::<usize>(), 20);
+        assert_eq!(blocks[0].position(3, 0), expected[3]);
+    }
+
+    #[test]
+    fn test_steps_per_store() {
+        let nwalkers = 20;
+        let p0 = Guess {
+            values: vec![0f64, 0f64],
+        };
+        let mut rng = StdRng::from_seed(&[1, 2, 3, 4]);
+        let pos = p0.create_initial_guess_with_rng(nwalkers, &mut rng);
+        let (real_x, observed_y) = load_baked_dataset();
+        let foo = LinearModel::new(&real_x, &observed_y);
+
+        let mut full = EnsembleSampler::new(nwalkers, p0.values.len(), &foo).unwrap();
+        full.seed(&[0]);
+        full.run_mcmc(&pos, 60).unwrap();
+
+        let mut repeated = EnsembleSampler::new(nwalkers, p0.values.len(), &foo).unwrap();
+        repeated.seed(&[0]);
+        repeated.steps_per_store = 3;
+        let mut ncallbacks = 0;
+        let last = repeated
+            .sample(&pos, 20, |_step| ncallbacks += 1)
+            .unwrap();
+        assert_eq!(ncallbacks, 20);
+        assert_eq!(last.iteration, 19);
+
+        // Iterations 2, 5, ..., 59 of the plain run are stored
+        let chain = repeated.chain().unwrap();
+        assert_eq!(chain.niterations, 20);
+        for iter in 0..20 {
+            for walker in 0..nwalkers {
+                let full = full.chain().unwrap();
+                assert_eq!(chain.get(1, walker, iter), full.get(1, walker, 3 * iter + 2));
+            }
+        }
+        assert_eq!(repeated.acceptance_fraction(), full.acceptance_fraction());
+
+        repeated.steps_per_store = 0;
+        assert!(repeated.run_mcmc(&pos, 20).is_err());
+    }
+
+    #[test]
+    fn test_single_precision_storage() {
+        let nwalkers = 10;
+        let mut rng = StdRng::from_seed(&[1, 2, 3, 4]);
+        let pos = create_guess().create_initial_guess_with_rng(nwalkers, &mut rng);
+        let (real_x, observed_y) = load_baked_dataset();
+        let foo = LinearModel::new(&real_x, &observed_y);
+
+        let mut double = EnsembleSampler::new(nwalkers, 2, &foo).unwrap();
+        double.seed(&[6]);
+        double.run_mcmc(&pos, 50).unwrap();
+
+        // The same run, with only the stored values rounded
+        let mut single = EnsembleSampler::<_, f32>::with_precision(nwalkers, 2, &foo).unwrap();
+        single.seed(&[6]);
+        single.run_mcmc(&pos, 50).unwrap();
+        let chain: &Chain<f32> = single.chain().unwrap();
+        assert_eq!(chain.niterations, 50);
+        let rounded = double.chain().unwrap().to_precision::<f32>();
+        assert_eq!(chain.positions_at(49), rounded.positions_at(49));
+        assert_eq!(
+            single.probstore().unwrap().get(3, 20),
+            double.probstore().unwrap().get(3, 20) as f32
+        );
+        assert_eq!(single.running_stats().unwrap().niterations(), 50);
+        assert_eq!(single.acceptance_fraction(), double.acceptance_fraction());
+    }
+
+    #[test]
+    fn test_thinning() {
+        let nwalkers = 20;
+        let p0 = Guess {
+            values: vec![0f64, 0f64],
+        };
+        let mut rng = StdRng::from_seed(&[1, 2, 3, 4]);
+        let pos = p0.create_initial_guess_with_rng(nwalkers, &mut rng);
+        let (real_x, observed_y) = load_baked_dataset();
+        let foo = LinearModel::new(&real_x, &observed_y);
+
+        let niters = 1000;
+        let mut sampler = EnsembleSampler::new(nwalkers, p0.values.len(), &foo).unwrap();
+        sampler.seed(&[0]);
+        sampler.thin = 500;
+        let _ = sampler.run_mcmc(&pos, niters).unwrap();
+        match sampler.chain {
+            Some(ref chain) => assert_eq!(chain.niterations, niters / 500),
+            None => panic!("chain should not be `None`"),
+        }
+    }
+
+    #[test]
+    fn test_run_in_chunks() {
+        let nwalkers = 20;
+        let p0 = create_guess();
+        let mut rng = StdRng::from_seed(&[1, 2, 3, 4]);
+        let pos = p0.create_initial_guess_with_rng(nwalkers, &mut rng);
+        let (real_x, observed_y) = load_baked_dataset();
+        let foo = LinearModel::new(&real_x, &observed_y);
+
+        let mut sampler = EnsembleSampler::new(nwalkers, p0.values.len(), &foo).unwrap();
+        sampler.seed(&[0]);
+
+        let mut blocks = Vec::new();
+        let state = sampler
+            .run_in_chunks(&pos, 25, 10, |block| blocks.push(block))
+            .unwrap();
+
+        assert!(sampler.chain.is_none());
+        assert!(sampler.storechain);
+        assert_eq!(blocks.iter().map(|block| block.len()).collect::<Vec<_>>(), vec![10, 10, 5]);
+        assert_eq!(blocks.iter().map(|block| block.start).collect::<Vec<_>>(), vec![0, 10, 20]);
+
+        let last = &blocks[2];
+        for walker in 0..nwalkers {
+            assert_eq!(last.get(0, walker, 4), state.pos[walker][0]);
+            assert_eq!(last.lnprob(walker, 4), state.lnprob[walker]);
+        }
+    }
+
+    #[test]
+    fn test_run_in_chunks_invalid_size() {
+        let (real_x, observed_y) = load_baked_dataset();
+        let foo = LinearModel::new(&real_x, &observed_y);
+        let mut sampler = EnsembleSampler::new(10, 2, &foo).unwrap();
+        let pos = create_guess().create_initial_guess(10);
+        match sampler.run_in_chunks(&pos, 10, 0, |_block| {}) {
+            Err(EmceeError::InvalidInputs(msg)) => assert!(msg.contains("chunk size")),
+            _ => panic!("incorrect"),
+        }
+    }
+
+    #[test]
+    fn test_run_until_independent() {
+        let nwalkers = 20;
+        let p0 = create_guess();
+        let mut rng = StdRng::from_seed(&[1, 2, 3, 4]);
+        let pos = p0.create_initial_guess_with_rng(nwalkers, &mut rng);
+        let (real_x, observed_y) = load_baked_dataset();
+        let foo = LinearModel::new(&real_x, &observed_y);
+
+        let mut sampler = EnsembleSampler::new(nwalkers, p0.values.len(), &foo).unwrap();
+        sampler.seed(&[0]);
+
+        let control = RunLength {
+            target: 10.0,
+            check_interval: 200,
+            max_iterations: 5000,
+            ..RunLength::default()
+        };
+        let report = sampler.run_until_independent(&pos, &control).unwrap();
+
+        assert!(report.converged);
+        assert!(report.nindependent >= 10.0);
+        assert_eq!(report.niterations % 200, 0);
+        assert_eq!(report.tau.len(), 2);
+        assert_eq!(sampler.chain.as_ref().unwrap().niterations, report.niterations);
+        assert_eq!(sampler.iterations, report.niterations);
+    }
+
+    #[test]
+    fn test_run_until_independent_iteration_cap() {
+        let nwalkers = 20;
+        let p0 = create_guess();
+        let (real_x, observed_y) = load_baked_dataset();
+        let foo = LinearModel::new(&real_x, &observed_y);
+        let pos = p0.create_initial_guess(nwalkers);
+
+        let mut sampler = EnsembleSampler::new(nwalkers, p0.values.len(), &foo).unwrap();
+        let control = RunLength {
+            target: 1e6,
+            check_interval: 30,
+            max_iterations: 100,
+            ..RunLength::default()
+        };
+        let report = sampler.run_until_independent(&pos, &control).unwrap();
+        assert!(!report.converged);
+        assert_eq!(report.niterations, 100);
+    }
+
+    #[test]
+    fn test_run_mcmc_until_converged() {
+        let nwalkers = 20;
+        let p0 = create_guess();
+        let mut rng = StdRng::from_seed(&[1, 2, 3, 4]);
+        let pos = p0.create_initial_guess_with_rng(nwalkers, &mut rng);
+        let (real_x, observed_y) = load_baked_dataset();
+        let foo = LinearModel::new(&real_x, &observed_y);
+
+        let mut sampler = EnsembleSampler::new(nwalkers, p0.values.len(), &foo).unwrap();
+        sampler.seed(&[0]);
+        let criterion = Convergence {
+            ntau: 10.0,
+            rtol: 0.2,
+            ..Convergence::default()
+        };
+        let niterations = sampler
+            .run_mcmc_until_converged(&pos, 10000, 200, &criterion)
+            .unwrap();
+
+        assert!(niterations < 10000);
+        assert_eq!(niterations % 200, 0);
+        let chain = sampler.chain().unwrap();
+        assert_eq!(chain.niterations, niterations);
+        let tau = chain.integrated_time(criterion.window);
+        assert!(tau.iter().all(|&tau| niterations as f64 > 10.0 * tau));
+
+        // An impossible tolerance runs to the iteration limit
+        let criterion = Convergence {
+            rtol: 0.0,
+            ..Convergence::default()
+        };
+        let niterations = sampler
+            .run_mcmc_until_converged(&pos, 250, 100, &criterion)
+            .unwrap();
+        assert_eq!(niterations, 250);
+        assert_eq!(sampler.chain().unwrap().niterations, 250);
+
+        match sampler.run_mcmc_until_converged(&pos, 250, 0, &criterion) {
+            Err(EmceeError::InvalidInputs(msg)) => assert!(msg.contains("check interval")),
+            _ => panic!("incorrect"),
+        }
+    }
+
+    #[test]
+    fn test_set_param_names() {
+        let nwalkers = 10;
+        let p0 = create_guess();
+        let (real_x, observed_y) = load_baked_dataset();
+        let foo = LinearModel::new(&real_x, &observed_y);
+        let pos = p0.create_initial_guess(nwalkers);
+
+        let mut sampler = EnsembleSampler::new(nwalkers, 2, &foo).unwrap();
+        assert!(sampler.set_param_names(&["m"]).is_err());
+        sampler.set_param_names(&["m", "c"]).unwrap();
+        sampler.run_mcmc(&pos, 20).unwrap();
+
+        let chain = sampler.chain().unwrap();
+        assert_eq!(chain.param_names().unwrap(), &["m", "c"]);
+        let flat = sampler.flatchain().unwrap();
+        assert_eq!(flat[0].get(chain.param_names().unwrap(), "c"), Some(flat[0][1]));
+
+        // The names are kept by a reset and given to the chains of later runs
+        sampler.reset();
+        sampler.run_mcmc(&pos, 5).unwrap();
+        assert_eq!(sampler.chain().unwrap().param_index("m"), Some(0));
+    }
+
+    #[test]
+    fn test_write_csv() {
+        use std::env;
+        use std::fs;
+
+        let nwalkers = 10;
+        let p0 = create_guess();
+        let (real_x, observed_y) = load_baked_dataset();
+        let foo = LinearModel::new(&real_x, &observed_y);
+        let pos = p0.create_initial_guess(nwalkers);
+
+        let mut sampler = EnsembleSampler::new(nwalkers, 2, &foo).unwrap();
+        let path = env::temp_dir().join(format!("emcee-chain-{}.csv", ::std::process::id()));
+        assert!(sampler.write_csv(&path, 0, 1).is_err());
+
+        sampler.set_param_names(&["m", "c"]).unwrap();
+        sampler.run_mcmc(&pos, 20).unwrap();
+        sampler.write_csv(&path, 10, 2).unwrap();
+        let text = fs::read_to_string(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        let lines: Vec<&str> = text.lines().collect();
+        assert_eq!(lines[0], "iteration,walker,m,c,lnprob");
+        assert_eq!(lines.len(), 1 + 5 * nwalkers);
+        let first: Vec<&str> = lines[1].split(',').collect();
+        assert_eq!(first[..2], ["11", "0"]);
+        let lnprob: f64 = first[4].parse().unwrap();
+        assert_eq!(lnprob, sampler.probstore().unwrap().get(0, 11));
+    }
+
+    #[test]
+    fn test_progress_reports() {
+        use std::sync::{Arc, Mutex};
+
+        struct Recorder(Arc<Mutex<(Vec<Progress>, Option<Progress>)>>);
+
+        impl ProgressReporter for Recorder {
+            fn report(&mut self, progress: &Progress) {
+                self.0.lock().unwrap().0.push(*progress);
+            }
+
+            fn finish(&mut self, progress: &Progress) {
+                self.0.lock().unwrap().1 = Some(*progress);
+            }
+        }
+
+        let nwalkers = 20;
+        let p0 = create_guess();
+        let mut rng = StdRng::from_seed(&[1, 2, 3, 4]);
+        let pos = p0.create_initial_guess_with_rng(nwalkers, &mut rng);
+        let (real_x, observed_y) = load_baked_dataset();
+        let foo = LinearModel::new(&real_x, &observed_y);
+
+        let reports = Arc::new(Mutex::new((Vec::new(), None)));
+        let mut sampler = EnsembleSampler::new(nwalkers, p0.values.len(), &foo).unwrap();
+        sampler.seed(&[0]);
+        sampler.set_progress(Recorder(reports.clone()));
+        sampler
+            .run_mcmc_with_callback(&pos, 50, |step| {
+                if step.iteration == 39 {
+                    ControlFlow::Break(())
+                } else {
+                    ControlFlow::Continue(())
+                }
+            })
+            .unwrap();
+
+        {
+            let reports = reports.lock().unwrap();
+            let iterations: Vec<usize> = reports.0.iter().map(|p| p.iteration).collect();
+            assert_eq!(iterations, (1..41).collect::<Vec<_>>());
+            assert!(reports.0.iter().all(|p| p.niterations == 50));
+            assert!(reports.0.windows(2).all(|w| w[0].elapsed <= w[1].elapsed));
+            let finished = reports.1.unwrap();
+            assert_eq!(finished.iteration, 40);
+            assert_eq!(finished.acceptance_fraction, sampler.mean_acceptance_fraction());
+        }
+
+        sampler.clear_progress();
+        sampler.run_mcmc(&pos, 10).unwrap();
+        assert_eq!(reports.lock().unwrap().0.len(), 40);
+    }
+
+    #[test]
+    fn test_proposal_observer() {
+        use std::sync::{Arc, Mutex};
+
+        /// Per-walker acceptances, and rejections at the boundary of the prior
+        #[derive(Default)]
+        struct Counts {
+            accepted: Vec<usize>,
+            nrejected: usize,
+            nboundary: usize,
+        }
+
+        struct Counter(Arc<Mutex<Counts>>);
+
+        impl ProposalObserver for Counter {
+            fn on_accept(&mut self, event: &ProposalEvent) {
+                assert!(event.new_lnprob > -f64::INFINITY);
+                self.0.lock().unwrap().accepted[event.walker] += 1;
+            }
+
+            fn on_reject(&mut self, event: &ProposalEvent) {
+                let mut counts = self.0.lock().unwrap();
+                counts.nrejected += 1;
+                if event.new[0] < 0.0 {
+                    assert_eq!(event.new_lnprob, -f64::INFINITY);
+                    counts.nboundary += 1;
+                }
+            }
+        }
+
+        struct HalfGaussian;
+
+        impl Prob for HalfGaussian {
+            fn lnlike(&self, params: &Guess) -> f64 {
+                -0.5 * params.values.iter().map(|x| x * x).sum::<f64>()
+            }
+
+            fn lnprior(&self, params: &Guess) -> f64 {
+                if params[0] >= 0.0 {
+                    0.0
+                } else {
+                    -f64::INFINITY
+                }
+            }
+        }
+
+        let nwalkers = 10;
+        let mut rng = StdRng::from_seed(&[1, 2, 3, 4]);
+        let pos = Guess::new(&[0.5, 0.0]).create_initial_guess_with_rng(nwalkers, &mut rng);
+        let counts = Arc::new(Mutex::new(Counts {
+            accepted: vec![0; nwalkers],
+            ..Counts::default()
+        }));
+
+        let mut sampler = EnsembleSampler::new(nwalkers, 2, &HalfGaussian).unwrap();
+        sampler.seed(&[1]);
+        sampler.shuffle_walkers = true;
+        sampler.set_observer(Counter(counts.clone()));
+        sampler.run_mcmc(&pos, 100).unwrap();
+
+        {
+            let counts = counts.lock().unwrap();
+            let expected: Vec<usize> = sampler
+                .acceptance_fraction()
+                .iter()
+                .map(|f| (f * 100.0).round() as usize)
+                .collect();
+            assert_eq!(counts.accepted, expected);
+            let naccepted: usize = counts.accepted.iter().sum();
+            assert_eq!(naccepted + counts.nrejected, nwalkers * 100);
+            assert!(counts.nboundary > 0);
+        }
+
+        sampler.clear_observer();
+        sampler.run_mcmc(&pos, 10).unwrap();
+        let counts = counts.lock().unwrap();
+        assert_eq!(counts.nrejected + counts.accepted.iter().sum::<usize>(), nwalkers * 100);
+    }
+
+    #[test]
+    fn test_callback_stops_run() {
+        let nwalkers = 20;
+        let p0 = create_guess();
+        let mut rng = StdRng::from_seed(&[1, 2, 3, 4]);
+        let pos = p0.create_initial_guess_with_rng(nwalkers, &mut rng);
+        let (real_x, observed_y) = load_baked_dataset();
+        let foo = LinearModel::new(&real_x, &observed_y);
+
+        let mut full = EnsembleSampler::new(nwalkers, p0.values.len(), &foo).unwrap();
+        full.seed(&[0]);
+        full.run_mcmc(&pos, 100).unwrap();
+
+        let mut sampler = EnsembleSampler::new(nwalkers, p0.values.len(), &foo).unwrap();
+        sampler.seed(&[0]);
+        let mut seen = Vec::new();
+        let last = sampler
+            .run_mcmc_with_callback(&pos, 100, |step| {
+                seen.push(step.iteration);
+                assert_eq!(step.pos.len(), nwalkers);
+                assert_eq!(step.lnprob.len(), nwalkers);
+                if step.iteration == 29 {
+                    ControlFlow::Break(())
+                } else {
+                    ControlFlow::Continue(())
+                }
+            })
+            .unwrap();
+
+        assert_eq!(seen, (0..30).collect::<Vec<_>>());
+        assert_eq!(last.iteration, 29);
+        let chain = sampler.chain().unwrap();
+        assert_eq!(chain.niterations, 30);
+        assert_eq!(chain.get(1, 3, 29), full.chain().unwrap().get(1, 3, 29));
+        assert_eq!(sampler.flatprob().unwrap().len(), 30 * nwalkers);
+        for (fraction, naccepted) in sampler.acceptance_fraction().iter().zip(&sampler.naccepted) {
+            assert_approx_eq!(*fraction, *naccepted as f64 / 30.0);
+        }
+
+        // The run can be continued from where it stopped
+        sampler.set_initial_state(last);
+        sampler.run_mcmc(&pos, 70).unwrap();
+        assert_eq!(
+            sampler.chain().unwrap().get(0, 5, 69),
+            full.chain().unwrap().get(0, 5, 99)
+        );
+    }
+
+    #[test]
+    fn test_acceptance_fraction() {
+        let nwalkers = 20;
+        let p0 = create_guess();
+        let mut rng = StdRng::from_seed(&[1, 2, 3, 4]);
+        let pos = p0.create_initial_guess_with_rng(nwalkers, &mut rng);
+        let (real_x, observed_y) = load_baked_dataset();
+        let foo = LinearModel::new(&real_x, &observed_y);
+
+        let mut sampler = EnsembleSampler::new(nwalkers, p0.values.len(), &foo).unwrap();
+        sampler.seed(&[0]);
+        assert!(sampler.mean_acceptance_fraction().is_nan());
+
+        sampler.run_mcmc(&pos, 100).unwrap();
+        let fractions = sampler.acceptance_fraction();
+        assert_eq!(fractions.len(), nwalkers);
+        for (fraction, naccepted) in fractions.iter().zip(&sampler.naccepted) {
+            assert_approx_eq!(*fraction, *naccepted as f64 / 100.0);
+        }
+        let mean = sampler.mean_acceptance_fraction();
+        assert_approx_eq!(mean, fractions.iter().sum::<f64>() / nwalkers as f64);
+        assert!(mean > 0.0 && mean < 1.0);
+
+        sampler.reset();
+        assert!(sampler.mean_acceptance_fraction().is_nan());
+    }
+
+    #[test]
+    fn test_move_acceptance() {
+        let nwalkers = 20;
+        let p0 = create_guess();
+        let mut rng = StdRng::from_seed(&[1, 2, 3, 4]);
+        let pos = p0.create_initial_guess_with_rng(nwalkers, &mut rng);
+        let (real_x, observed_y) = load_baked_dataset();
+        let foo = LinearModel::new(&real_x, &observed_y);
+
+        let niters = 200;
+        let mut sampler = EnsembleSampler::new(nwalkers, p0.values.len(), &foo).unwrap();
+        sampler.seed(&[0]);
+        sampler
+            .set_moves(vec![
+                (Box::new(StretchMove::new(1.5)), 0.5),
+                (Box::new(StretchMove::new(20.0)), 0.5),
+            ])
+            .unwrap();
+        sampler.run_mcmc(&pos, niters).unwrap();
+
+        let stats = sampler.move_acceptance();
+        assert_eq!(stats.len(), 2);
+        assert_eq!(stats[0].nproposed + stats[1].nproposed, niters * nwalkers);
+        assert!(stats[0].nproposed > 0 && stats[1].nproposed > 0);
+        assert_eq!(
+            stats[0].naccepted + stats[1].naccepted,
+            sampler.naccepted.iter().sum::<usize>()
+        );
+        // Larger stretches are accepted less often
+        assert!(stats[0].fraction() > stats[1].fraction());
+
+        sampler.reset();
+        assert_eq!(sampler.move_acceptance()[0].nproposed, 0);
+    }
+
+    #[test]
+    fn test_covariance_move() {
+        use moves::CovarianceMove;
+
+        let nwalkers = 20;
+        let p0 = Guess::new(&[2.0, 5.0]);
+        let mut rng = StdRng::from_seed(&[1, 2, 3, 4]);
+        let pos = p0.create_initial_guess_with_rng(nwalkers, &mut rng);
+        let (real_x, observed_y) = load_baked_dataset();
+        let foo = LinearModel::new(&real_x, &observed_y);
+
+        let mut sampler = EnsembleSampler::new(nwalkers, p0.values.len(), &foo).unwrap();
+        sampler.seed(&[0]);
+        let state = sampler.run_mcmc(&pos, 200).unwrap();
+
+        sampler
+            .set_moves(vec![
+                (Box::new(StretchMove::default()), 0.5),
+                (Box::new(CovarianceMove::new(2000, 10)), 0.5),
+            ])
+            .unwrap();
+        sampler.set_initial_state(state).run_mcmc(&pos, 500).unwrap();
+
+        // The posterior of the linear model is Gaussian, so once fitted the independence
+        // proposals are accepted far more often than local moves
+        let stats = sampler.move_acceptance();
+        assert!(stats[1].fraction() > stats[0].fraction());
+    }
+
+    #[test]
+    fn test_de_move_mixture() {
+        use moves::{DEMove, DESnookerMove};
+
+        let nwalkers = 20;
+        let p0 = Guess::new(&[2.0, 5.0]);
+        let mut rng = StdRng::from_seed(&[1, 2, 3, 4]);
+        let pos = p0.create_initial_guess_with_rng(nwalkers, &mut rng);
+        let (real_x, observed_y) = load_baked_dataset();
+        let foo = LinearModel::new(&real_x, &observed_y);
+
+        let mut stretch = EnsembleSampler::new(nwalkers, p0.values.len(), &foo).unwrap();
+        stretch.seed(&[0]);
+        stretch.run_mcmc(&pos, 1000).unwrap();
+
+        let mut sampler = EnsembleSampler::new(nwalkers, p0.values.len(), &foo).unwrap();
+        sampler.seed(&[0]);
+        sampler
+            .set_moves(vec![
+                (Box::new(DEMove::default()), 0.8),
+                (Box::new(DESnookerMove::default()), 0.2),
+            ])
+            .unwrap();
+        sampler.run_mcmc(&pos, 1000).unwrap();
+
+        let stats = sampler.move_acceptance();
+        assert_eq!(stats[0].name, "de");
+        assert_eq!(stats[1].name, "de-snooker");
+        assert!(stats[0].nproposed > 3 * stats[1].nproposed);
+        assert!(stats[1].naccepted > 0);
+
+        // Both sets of moves sample the same posterior
+        let mean = |s: &EnsembleSampler<LinearModel>, i: usize| {
+            let flat = s.flatchain().unwrap();
+            flat.iter().map(|g| g[i]).sum::<f64>() / flat.len() as f64
+        };
+        for i in 0..2 {
+            assert_approx_eq!(mean(&sampler, i), mean(&stretch, i), 0.05);
+        }
+    }
+
+    #[test]
+    fn test_independence_phase() {
+        use moves::StudentTMove;
+
+        let nwalkers = 20;
+        let p0 = Guess::new(&[2.0, 5.0]);
+        let mut rng = StdRng::from_seed(&[1, 2, 3, 4]);
+        let pos = p0.create_initial_guess_with_rng(nwalkers, &mut rng);
+        let (real_x, observed_y) = load_baked_dataset();
+        let foo = LinearModel::new(&real_x, &observed_y);
+
+        let mut sampler = EnsembleSampler::new(nwalkers, p0.values.len(), &foo).unwrap();
+        sampler.seed(&[0]);
+        let state = sampler.run_mcmc(&pos, 500).unwrap();
+
+        let flatchain = sampler.flatchain().unwrap();
+        let independence = StudentTMove::fit(&flatchain[100 * nwalkers..], 10.0).unwrap();
+        sampler
+            .set_moves(vec![(Box::new(independence), 1.0)])
+            .unwrap();
+        sampler.set_initial_state(state).run_mcmc(&pos, 200).unwrap();
+
+        assert!(sampler.move_acceptance()[0].fraction() > 0.5);
+    }
+
+    #[test]
+    fn test_set_moves_validation() {
+        let (real_x, observed_y) = load_baked_dataset();
+        let foo = LinearModel::new(&real_x, &observed_y);
+        let mut sampler = EnsembleSampler::new(10, 2, &foo).unwrap();
+        assert!(sampler.set_moves(Vec::new()).is_err());
+        assert!(
+            sampler
+                .set_moves(vec![(Box::new(StretchMove::default()), 0.0)])
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn test_run_warmup() {
+        let nwalkers = 20;
+        let p0 = create_guess();
+        let mut rng = StdRng::from_seed(&[1, 2, 3, 4]);
+        let pos = p0.create_initial_guess_with_rng(nwalkers, &mut rng);
+        let (real_x, observed_y) = load_baked_dataset();
+        let foo = LinearModel::new(&real_x, &observed_y);
+
+        let mut sampler = EnsembleSampler::new(nwalkers, p0.values.len(), &foo).unwrap();
+        sampler.seed(&[0]);
+        sampler
+            .set_moves(vec![(Box::new(StretchMove::new(50.0)), 1.0)])
+            .unwrap();
+
+        let control = AcceptanceTarget {
+            target: 0.4,
+            ..AcceptanceTarget::default()
+        };
+        let state = sampler.run_warmup(&pos, 1000, &control).unwrap();
+        assert!(sampler.chain.is_none());
+
+        let trace = sampler.adaptation_trace();
+        assert_eq!(trace.len(), 100);
+        assert_eq!(trace[0].iteration, 10);
+        assert_eq!(trace[99].iteration, 1000);
+        // The very bold initial move has to be toned down to reach the target
+        let tuning = sampler.moves[0].0.tuning().unwrap();
+        assert!(tuning < 49.0);
+        assert_eq!(trace[99].tuning, tuning);
+
+        let before = sampler.move_acceptance()[0].clone();
+        sampler.set_initial_state(state).run_mcmc(&pos, 500).unwrap();
+        let after = &sampler.move_acceptance()[0];
+        let acceptance = (after.naccepted - before.naccepted) as f64
+            / (after.nproposed - before.nproposed) as f64;
+        assert_approx_eq!(acceptance, 0.4, 0.1);
+    }
+
+    #[test]
+    fn test_reset_preserves_configuration() {
+        let nwalkers = 20;
+        let p0 = create_guess();
+        let mut rng = StdRng::from_seed(&[1, 2, 3, 4]);
+        let pos = p0.create_initial_guess_with_rng(nwalkers, &mut rng);
+        let (real_x, observed_y) = load_baked_dataset();
+        let foo = LinearModel::new(&real_x, &observed_y);
+
+        let mut sampler = EnsembleSampler::new(nwalkers, p0.values.len(), &foo).unwrap();
+        sampler.seed(&[3]);
+        sampler.thin = 2;
+        sampler
+            .set_moves(vec![(Box::new(StretchMove::new(5.0)), 1.0)])
+            .unwrap();
+        let first = sampler.run_mcmc(&pos, 50).unwrap();
+
+        let state = sampler
+            .run_warmup(&pos, 100, &AcceptanceTarget::default())
+            .unwrap();
+        sampler.set_initial_state(state);
+        assert_ne!(sampler.moves[0].0.tuning(), Some(4.0));
+
+        sampler.reset();
+        assert!(sampler.chain().is_none());
+        assert!(sampler.initial_state.is_none());
+        assert!(sampler.adaptation_trace().is_empty());
+        assert_eq!(sampler.acceptance_fraction().len(), nwalkers);
+        assert_eq!(sampler.moves[0].0.tuning(), Some(4.0));
+        assert_eq!(sampler.thin, 2);
+
+        // The same seed is used again, so the first run is reproduced
+        let second = sampler.run_mcmc(&pos, 50).unwrap();
+        assert_eq!(*first.lnprob, *second.lnprob);
+    }
+
+    #[test]
+    fn test_validation_failures() {
+        struct HalfNormal;
+
+        impl Prob for HalfNormal {
+            fn lnlike(&self, params: &Guess) -> f64 {
+                assert!(params[0] >= 0.0, "invalid proposals must not be evaluated");
+                -0.5 * params.values.iter().map(|x| x * x).sum::<f64>()
+            }
+
+            fn lnprior(&self, _params: &Guess) -> f64 {
+                0.0
+            }
+
+            fn validate(&self, params: &Guess) -> ::std::result::Result<(), String> {
+                if params[0] < 0.0 {
+                    Err("negative scale".into())
+                } else {
+                    Ok(())
+                }
+            }
+        }
+
+        let nwalkers = 10;
+        let model = HalfNormal;
+        let mut rng = StdRng::from_seed(&[1, 2, 3, 4]);
+        let pos = Guess::new(&[1.0, 0.0]).create_initial_guess_with_rng(nwalkers, &mut rng);
+        let mut sampler = EnsembleSampler::new(nwalkers, 2, &model).unwrap();
+        sampler.seed(&[5]);
+        sampler.run_mcmc(&pos, 200).unwrap();
+
+        let failures = sampler.validation_failures();
+        assert_eq!(failures.len(), 1);
+        assert!(failures["negative scale"] > 0);
+        assert_eq!(sampler.run_stats().nrejected_bounds, failures["negative scale"]);
+        assert!(sampler.flatchain().unwrap().iter().all(|guess| guess[0] >= 0.0));
+
+        sampler.reset();
+        assert!(sampler.validation_failures().is_empty());
+    }
+
+    #[test]
+    fn test_parameter_groups() {
+        let icov = [
+            [1.0, 0.0, 0.0, 0.0, 0.0],
+            [0.0, 1.0, 0.0, 0.0, 0.0],
+            [0.0, 0.0, 1.0, 0.0, 0.0],
+            [0.0, 0.0, 0.0, 1.0, 0.0],
+            [0.0, 0.0, 0.0, 0.0, 1.0],
+        ];
+        let model = MultivariateProb { icov: &icov };
+        let ndim = 3;
+        let nwalkers = 20;
+        let niters = 2000;
+        let mut rng = StdRng::from_seed(&[1, 2, 3, 4]);
+        let pos = Guess::new(&[0.5, -0.5, 1.0]).create_initial_guess_with_rng(nwalkers, &mut rng);
+
+        let mut sampler = EnsembleSampler::new(nwalkers, ndim, &model).unwrap();
+        sampler.seed(&[9]);
+        assert!(sampler
+            .set_groups(vec![(vec![0, 1], Box::new(StretchMove::default()))])
+            .is_err());
+        assert!(sampler
+            .set_groups(vec![
+                (vec![0, 1], Box::new(StretchMove::default())),
+                (vec![1, 2], Box::new(StretchMove::default())),
+            ])
+            .is_err());
+        sampler
+            .set_groups(vec![
+                (vec![0, 1], Box::new(StretchMove::default())),
+                (vec![2], Box::new(StretchMove::new(3.0))),
+            ])
+            .unwrap();
+        sampler.run_mcmc(&pos, niters).unwrap();
+
+        let stats = sampler.move_acceptance();
+        assert_eq!(stats.len(), 2);
+        assert_eq!(stats[0].name, "stretch[0,1]");
+        for stats in stats {
+            assert_eq!(stats.nproposed, niters * nwalkers);
+        }
+        assert!(sampler.acceptance_fraction().iter().all(|f| *f > 0.0 && *f <= 1.0));
+
+        let flatchain = sampler.flatchain().unwrap();
+        for param in 0..ndim {
+            let values: Vec<f64> = flatchain[flatchain.len() / 2..]
+                .iter()
+                .map(|guess| guess[param])
+                .collect();
+            let mean = values.iter().sum::<f64>() / values.len() as f64;
+            let var = values.iter().map(|x| (x - mean).powi(2)).sum::<f64>() / values.len() as f64;
+            assert_approx_eq!(mean, 0.0, 0.2);
+            assert_approx_eq!(var, 1.0, 0.25);
+        }
+    }
+
+    #[test]
+    fn test_autoscale() {
+        struct Stretched;
+
+        impl Prob for Stretched {
+            fn lnlike(&self, params: &Guess) -> f64 {
+                let a = (params[0] - 1e4) / 1e-3;
+                let b = params[1] / 1e3;
+                -0.5 * (a * a + b * b)
+            }
+
+            fn lnprior(&self, _params: &Guess) -> f64 {
+                0.0
+            }
+        }
+
+        let model = Stretched;
+        let nwalkers = 10;
+        let niters = 2000;
+        let mut rng = StdRng::from_seed(&[1, 2, 3, 4]);
+        let normal = Normal::new(0.0, 1.0);
+        let pos: Vec<Guess> = (0..nwalkers)
+            .map(|_| {
+                Guess::new(&[
+                    1e4 + 1e-3 * normal.ind_sample(&mut rng),
+                    1e3 * normal.ind_sample(&mut rng),
+                ])
+            })
+            .collect();
+
+        let mut sampler = EnsembleSampler::new(nwalkers, 2, &model).unwrap();
+        sampler.seed(&[4]);
+        sampler.autoscale = true;
+        assert!(sampler.parameter_scales().is_none());
+        sampler.run_mcmc(&pos, niters).unwrap();
+
+        {
+            let (center, scale) = sampler.parameter_scales().unwrap();
+            assert_approx_eq!(center[0], 1e4, 1e-2);
+            assert!(scale[0] > 1e-4 && scale[0] < 1e-2);
+            assert!(scale[1] > 1e2 && scale[1] < 1e4);
+        }
+
+        let flatchain = sampler.flatchain().unwrap();
+        let tail = &flatchain[flatchain.len() / 2..];
+        let std = |param: usize, loc: f64| {
+            (tail.iter().map(|guess| (guess[param] - loc).powi(2)).sum::<f64>()
+                / tail.len() as f64)
+                .sqrt()
+        };
+        assert_approx_eq!(std(0, 1e4), 1e-3, 3e-4);
+        assert_approx_eq!(std(1, 0.0), 1e3, 3e2);
+
+        sampler.reset();
+        assert!(sampler.parameter_scales().is_none());
+    }
+
+    #[test]
+    fn test_timeouts() {
+        use std::thread;
+
+        struct Hangs;
+
+        impl Prob for Hangs {
+            fn lnlike(&self, params: &Guess) -> f64 {
+                if params[0] > 1.0 {
+                    // A simulation which does not respect the deadline
+                    thread::sleep(Duration::from_millis(20));
+                }
+                -0.5 * params.values.iter().map(|x| x * x).sum::<f64>()
+            }
+
+            fn lnprior(&self, _params: &Guess) -> f64 {
+                0.0
+            }
+
+            fn lnprob_with_deadline(&self, params: &Guess, deadline: Instant) -> Option<f64> {
+                if params[0] < -1.0 {
+                    // A simulation which gives up at the deadline
+                    while Instant::now() <= deadline {
+                        thread::yield_now();
+                    }
+                    None
+                } else {
+                    Some(self.lnprob(params))
+                }
+            }
+        }
+
+        let model = Hangs;
+        let nwalkers = 10;
+        let mut rng = StdRng::from_seed(&[1, 2, 3, 4]);
+        let pos = Guess::new(&[0.0, 0.0]).create_initial_guess_with_rng(nwalkers, &mut rng);
+
+        for threads in &[None, Some(2)] {
+            let mut sampler = match *threads {
+                None => EnsembleSampler::new(nwalkers, 2, &model).unwrap(),
+                Some(n) => EnsembleSampler::threaded(nwalkers, 2, &model, n).unwrap(),
+            };
+            sampler.seed(&[2]);
+            sampler.timeout = Some(Duration::from_millis(5));
+            sampler.run_mcmc(&pos, 100).unwrap();
+
+            assert!(sampler.ntimeouts() > 0);
+            let flatchain = sampler.flatchain().unwrap();
+            assert!(flatchain.iter().all(|guess| guess[0] <= 1.0 && guess[0] >= -1.0));
 
-        sampler
-            .set_initial_state(initial_state)
-            .run_mcmc(&params, niters)
-            .unwrap();
+            sampler.reset();
+            assert_eq!(sampler.ntimeouts(), 0);
+        }
     }
 
     #[test]
-    fn test_restart_sampler() {
-        let (real_x, observed_y) = generate_dataset(20);
-        let foo = LinearModel::new(&real_x, &observed_y);
-        let p0 = create_guess();
+    fn test_retries() {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+
+        struct Flaky {
+            ncalls: AtomicUsize,
+        }
+
+        impl Prob for Flaky {
+            fn lnlike(&self, params: &Guess) -> f64 {
+                -0.5 * params.values.iter().map(|x| x * x).sum::<f64>()
+            }
+
+            fn lnprior(&self, _params: &Guess) -> f64 {
+                0.0
+            }
+
+            fn try_lnprob(&self, params: &Guess) -> ::std::result::Result<f64, String> {
+                if params[0] > 1.0 {
+                    // A region where the likelihood server is permanently down
+                    return Err("connection refused".into());
+                }
+                // Elsewhere two out of three requests are dropped
+                if self.ncalls.fetch_add(1, Ordering::SeqCst) % 3 != 2 {
+                    Err("connection reset".into())
+                } else {
+                    Ok(self.lnprob(params))
+                }
+            }
+        }
 
+        let model = Flaky {
+            ncalls: AtomicUsize::new(0),
+        };
         let nwalkers = 10;
-        let niters = 100;
-        let mut sampler = EnsembleSampler::new(nwalkers, 2, &foo).unwrap();
+        let mut rng = StdRng::from_seed(&[1, 2, 3, 4]);
+        let pos = Guess::new(&[0.0, 0.0]).create_initial_guess_with_rng(nwalkers, &mut rng);
 
-        let params = p0.create_initial_guess(nwalkers);
+        let mut sampler = EnsembleSampler::new(nwalkers, 2, &model).unwrap();
+        sampler.seed(&[2]);
+        sampler.retry = RetryPolicy::new(2, Duration::from_millis(0));
+        sampler.run_mcmc(&pos, 100).unwrap();
 
-        let state = sampler.run_mcmc(&params, niters).unwrap();
+        let stats = sampler.run_stats();
+        assert!(stats.nfailures > 0);
+        assert!(stats.nretries >= 2 * (stats.nevaluations - stats.nfailures));
+        let flatchain = sampler.flatchain().unwrap();
+        assert!(flatchain.iter().all(|guess| guess[0] <= 1.0));
 
+        // Without retries most evaluations fail, but the run still completes
         sampler.reset();
-        sampler
-            .set_initial_state(state)
-            .run_mcmc(&params, niters)
-            .unwrap();
+        sampler.retry = RetryPolicy::default();
+        sampler.run_mcmc(&pos, 10).unwrap();
+        let stats = sampler.run_stats();
+        assert_eq!(stats.nretries, 0);
+        assert!(stats.nfailures > stats.nevaluations / 2);
     }
 
     #[test]
-    fn test_lnprob() {
-        /* PYTHON OUTPUT
-         *  x: [ 0.20584494  0.58083612  1.5599452   1.5601864   1.81824967  1.8340451
-         *   2.12339111  2.9122914   3.04242243  3.74540119  4.31945019  5.24756432
-         *   5.98658484  6.01115012  7.08072578  7.31993942  8.32442641  8.66176146
-         *   9.50714306  9.69909852]
-         *
-         * y: [  4.39885877   6.47591958   7.21186633   6.70806911  10.10214811
-         *    8.4423139    9.31431042   9.39983462  10.54046213  12.60172497
-         *   12.4879068   15.87082665  16.37253099  16.73060649  18.55974494
-         *   21.49215702  21.63535559  21.26581199  24.83683104  23.17735339]
-         *
-         *
-         * pos: [[  2.08863595e-06   2.08863595e-06]
-         * [ -1.95967012e-05  -1.95967012e-05]
-         * [ -1.32818605e-05  -1.32818605e-05]
-         * [  1.96861236e-06   1.96861236e-06]]
+    fn test_nan_policy() {
+        struct Buggy;
 
-         * result: array([-4613.19497084, -4613.277985  , -4613.25381092, -4613.1954303 ])
-         */
-        let (real_x, observed_y) = load_baked_dataset();
-        let mut pos = Vec::new();
-        pos.push(Guess {
-            values: vec![2.08863595e-06, 2.08863595e-06],
-        });
-        pos.push(Guess {
-            values: vec![-1.95967012e-05, -1.95967012e-05],
-        });
-        pos.push(Guess {
-            values: vec![-1.32818605e-05, -1.32818605e-05],
-        });
-        pos.push(Guess {
-            values: vec![1.96861236e-06, 1.96861236e-06],
-        });
-        let foo = LinearModel::new(&real_x, &observed_y);
+        impl Prob for Buggy {
+            fn lnlike(&self, params: &Guess) -> f64 {
+                // NaN for x > 1
+                -0.5 * params[1] * params[1] + (1.0 - params[0]).sqrt()
+            }
 
-        let nwalkers = 8;
-        let mut sampler = EnsembleSampler::new(nwalkers, 2, &foo).unwrap();
-        let lnprob = sampler.get_lnprob(&pos).unwrap();
-        let expected: Vec<f64> = vec![-4613.19497084, -4613.277985, -4613.25381092, -4613.1954303];
-        for (a, b) in lnprob.iter().zip(expected) {
-            assert_approx_eq!(a, b);
+            fn lnprior(&self, _params: &Guess) -> f64 {
+                0.0
+            }
         }
-    }
 
-    #[test]
-    fn test_lnprob_implementations() {
-        let p0 = create_guess();
-        let (real_x, observed_y) = load_baked_dataset();
-        let foo = LinearModel::new(&real_x, &observed_y);
+        let nwalkers = 10;
+        let mut rng = StdRng::from_seed(&[1, 2, 3, 4]);
+        let mut pos = Guess::new(&[0.0, 0.0]).create_initial_guess_with_rng(nwalkers, &mut rng);
+        let mut sampler = EnsembleSampler::new(nwalkers, 2, &Buggy).unwrap();
+        sampler.seed(&[5]);
+
+        // A walker starting in the broken region is caught before the first step
+        pos[7].values[0] = 2.0;
+        match sampler.run_mcmc(&pos, 10) {
+            Err(EmceeError::NonFiniteProbability {
+                walker,
+                iteration,
+                position,
+            }) => {
+                assert_eq!(walker, 7);
+                assert_eq!(iteration, 0);
+                assert_eq!(position, pos[7].values);
+            }
+            other => panic!("expected a non-finite probability error, got {:?}", other),
+        }
 
-        let expected = -4613.202966359966f64;
-        assert_approx_eq!(foo.lnprob(&p0), expected);
+        // A proposal into it is caught at the step it is made
+        pos[7].values[0] = 0.5;
+        sampler.reset();
+        match sampler.run_mcmc(&pos, 500) {
+            Err(EmceeError::NonFiniteProbability {
+                walker, position, ..
+            }) => {
+                assert!(walker < nwalkers);
+                assert!(position[0] > 1.0);
+            }
+            other => panic!("expected a non-finite probability error, got {:?}", other),
+        }
+
+        // Or rejected and counted
+        sampler.reset();
+        sampler.nan_policy = NanPolicy::TreatAsNegInf;
+        sampler.run_mcmc(&pos, 500).unwrap();
+        assert!(sampler.run_stats().nnonfinite > 0);
+        let flatchain = sampler.flatchain().unwrap();
+        assert!(flatchain.iter().all(|guess| guess[0] <= 1.0));
     }
 
     #[test]
-    fn test_propose_stretch() {
-        let nwalkers = 100;
-        let p0 = Guess {
-            values: vec![2.0f64, 5.0f64],
-        };
+    fn test_run_stats() {
+        use moves::Proposal;
+
+        struct StayMove;
+
+        impl Move for StayMove {
+            fn name(&self) -> &str {
+                "stay"
+            }
+
+            fn propose(&mut self, s: &[Guess], _c: &[Guess], _rng: &mut dyn Rng) -> Proposal {
+                Proposal {
+                    q: s.to_vec(),
+                    factors: vec![0.0; s.len()],
+                    sources: Vec::new(),
+                }
+            }
+        }
 
-        let pos = p0.create_initial_guess(nwalkers);
         let (real_x, observed_y) = load_baked_dataset();
         let foo = LinearModel::new(&real_x, &observed_y);
+        let nwalkers = 10;
+        let niters = 100;
+        let mut rng = StdRng::from_seed(&[1, 2, 3, 4]);
+        let pos = create_guess().create_initial_guess_with_rng(nwalkers, &mut rng);
 
-        let mut sampler = EnsembleSampler::new(nwalkers, p0.values.len(), &foo).unwrap();
-        let (a, b) = pos.split_at(nwalkers / 2);
-
-        assert_eq!(a.len(), nwalkers / 2);
-        assert_eq!(b.len(), nwalkers / 2);
+        let mut sampler = EnsembleSampler::new(nwalkers, 2, &foo).unwrap();
+        sampler.seed(&[1]);
+        sampler
+            .set_moves(vec![
+                (Box::new(StayMove), 1.0),
+                (Box::new(StretchMove::default()), 1.0),
+            ])
+            .unwrap();
+        sampler.run_mcmc(&pos, niters).unwrap();
+
+        let stats = sampler.run_stats().clone();
+        assert_eq!(stats.ncache_hits, sampler.move_acceptance()[0].nproposed);
+        assert!(stats.ncache_hits > 0);
+        assert_eq!(
+            stats.nevaluations + stats.ncache_hits,
+            nwalkers + niters * nwalkers
+        );
+        assert_eq!(stats.nrejected_bounds, 0);
+        assert!(stats.mean_evaluation_time().is_some());
 
-        let lnprob = sampler.get_lnprob(&pos).unwrap();
-        let _stretch = sampler.propose_stretch(&a, &b, &lnprob).unwrap();
+        sampler.reset();
+        assert_eq!(*sampler.run_stats(), RunStats::default());
     }
 
     #[test]
-    fn test_mcmc_run() {
-        let nwalkers = 20;
-        let p0 = Guess {
-            values: vec![0f64, 0f64],
-        };
-        let mut rng = StdRng::from_seed(&[1, 2, 3, 4]);
-        let pos = p0.create_initial_guess_with_rng(nwalkers, &mut rng);
+    fn test_ancestry() {
+        use moves::CovarianceMove;
+
         let (real_x, observed_y) = load_baked_dataset();
         let foo = LinearModel::new(&real_x, &observed_y);
+        let nwalkers = 10;
+        let mut rng = StdRng::from_seed(&[1, 2, 3, 4]);
+        let pos = create_guess().create_initial_guess_with_rng(nwalkers, &mut rng);
 
-        let niters = 1000;
-        let mut sampler = EnsembleSampler::new(nwalkers, p0.values.len(), &foo).unwrap();
-        sampler.seed(&[0]);
-        let _ = sampler.run_mcmc(&pos, niters).unwrap();
+        let mut sampler = EnsembleSampler::new(nwalkers, 2, &foo).unwrap();
+        sampler.seed(&[1]);
+        sampler
+            .set_moves(vec![
+                (Box::new(StretchMove::default()), 1.0),
+                (Box::new(CovarianceMove::default()), 1.0),
+            ])
+            .unwrap();
+        sampler.run_mcmc(&pos, 50).unwrap();
+        assert!(sampler.ancestry().is_empty());
 
-        if let Some(ref chain) = sampler.chain {
-            /* Wide margins due to random numbers :( */
-            assert_approx_eq!(chain.get(0, 0, niters - 2), 2.0f64, 1.0f64);
-            assert_approx_eq!(chain.get(1, 0, niters - 2), 5.0f64, 1.0f64);
+        sampler.reset();
+        sampler.track_ancestry = true;
+        sampler.run_mcmc(&pos, 50).unwrap();
+
+        let records = sampler.ancestry();
+        let naccepted: usize = sampler.move_acceptance().iter().map(|m| m.naccepted).sum();
+        assert_eq!(records.len(), naccepted);
+        for record in records {
+            assert!(record.iteration < 50);
+            match record.move_idx {
+                0 => {
+                    // The parent comes from the other half of the ensemble
+                    let parent = record.parent.unwrap();
+                    assert_ne!(parent < nwalkers / 2, record.walker < nwalkers / 2);
+                }
+                _ => assert_eq!(record.parent, None),
+            }
         }
+
+        sampler.reset();
+        assert!(sampler.ancestry().is_empty());
     }
 
     #[test]
-    fn test_nwalkers_even() {
+    fn test_max_stored_iterations() {
         let (real_x, observed_y) = load_baked_dataset();
         let foo = LinearModel::new(&real_x, &observed_y);
-        match EnsembleSampler::new(3, 3, &foo) {
-            Err(EmceeError::InvalidInputs(msg)) => {
-                assert!(msg.contains("number of walkers must be even"));
+        let nwalkers = 10;
+        let niters = 1000;
+        let mut rng = StdRng::from_seed(&[1, 2, 3, 4]);
+        let pos = create_guess().create_initial_guess_with_rng(nwalkers, &mut rng);
+
+        let mut full = EnsembleSampler::new(nwalkers, 2, &foo).unwrap();
+        full.seed(&[5]);
+        full.run_mcmc(&pos, niters).unwrap();
+        assert_eq!(full.stored_thin(), 1);
+
+        let mut capped = EnsembleSampler::new(nwalkers, 2, &foo).unwrap();
+        capped.seed(&[5]);
+        capped.max_stored_iterations = Some(100);
+        capped.run_mcmc(&pos, niters).unwrap();
+
+        // Halved at iterations 100, 200, 400 and 800
+        assert_eq!(capped.stored_thin(), 16);
+        let (full_chain, chain) = (full.chain().unwrap(), capped.chain().unwrap());
+        assert_eq!(chain.niterations, 63);
+        for k in 0..chain.niterations {
+            for walker in 0..nwalkers {
+                assert_eq!(chain.get(1, walker, k), full_chain.get(1, walker, 16 * k));
             }
-            _ => panic!("incorrect"),
         }
+        let full_prob = full.flatprob().unwrap();
+        let prob = capped.flatprob().unwrap();
+        assert_eq!(prob.len(), 63 * nwalkers);
+        assert_eq!(prob[nwalkers * 62], full_prob[nwalkers * 16 * 62]);
+        assert_eq!(
+            capped.running_stats().unwrap().niterations(),
+            chain.niterations
+        );
+
+        // Short runs are unaffected
+        capped.run_mcmc(&pos, 50).unwrap();
+        assert_eq!(capped.stored_thin(), 1);
+        assert_eq!(capped.chain().unwrap().niterations, 50);
+
+        capped.max_stored_iterations = Some(1);
+        assert!(capped.run_mcmc(&pos, 50).is_err());
     }
 
     #[test]
-    fn test_enough_walkers() {
+    fn test_online_stats() {
         let (real_x, observed_y) = load_baked_dataset();
         let foo = LinearModel::new(&real_x, &observed_y);
-        match EnsembleSampler::new(4, 3, &foo) {
-            Err(EmceeError::InvalidInputs(msg)) => {
-                assert!(msg.contains("should be more than twice"));
-            }
-            _ => panic!("incorrect"),
+        let nwalkers = 10;
+        let niters = 100;
+        let mut rng = StdRng::from_seed(&[1, 2, 3, 4]);
+        let pos = create_guess().create_initial_guess_with_rng(nwalkers, &mut rng);
+
+        let mut sampler = EnsembleSampler::new(nwalkers, 2, &foo).unwrap();
+        assert_eq!(sampler.online_stats().count(), 0);
+        assert!(sampler.online_stats().mean_lnprob().is_nan());
+        sampler.run_mcmc(&pos, niters).unwrap();
+
+        let flatchain = sampler.flatchain().unwrap();
+        let flatprob = sampler.flatprob().unwrap();
+        let stats = sampler.online_stats();
+        assert_eq!(stats.count(), nwalkers * niters);
+        let n = flatchain.len() as f64;
+        for param in 0..2 {
+            let mean = flatchain.iter().map(|guess| guess[param]).sum::<f64>() / n;
+            let var = flatchain
+                .iter()
+                .map(|guess| (guess[param] - mean).powi(2))
+                .sum::<f64>() / (n - 1.0);
+            assert_approx_eq!(stats.mean()[param], mean, 1e-10);
+            assert_approx_eq!(stats.variance()[param], var, 1e-10);
         }
+        assert_approx_eq!(
+            stats.mean_lnprob(),
+            flatprob.iter().sum::<f64>() / n,
+            1e-10
+        );
+
+        // Kept without storing the chain, and accumulated over runs
+        sampler.storechain = false;
+        sampler.run_mcmc(&pos, niters).unwrap();
+        assert_eq!(sampler.online_stats().count(), 2 * nwalkers * niters);
+        sampler.clear_online_stats();
+        assert_eq!(sampler.online_stats().count(), 0);
     }
 
     #[test]
-    fn test_lnprob_gaussian() {
-        // Testing the probability model for the multivariate test
-        let icov = [
-            [
-                318.92634269,
-                531.39511426,
-                -136.10315845,
-                154.17685545,
-                552.308813,
-            ],
-            [
-                531.39511426,
-                899.91793286,
-                -224.74333441,
-                258.98686842,
-                938.32014715,
-            ],
-            [
-                -136.10315845,
-                -224.74333441,
-                60.61145495,
-                -66.68898448,
-                -232.52035701,
-            ],
-            [
-                154.17685545,
-                258.98686842,
-                -66.68898448,
-                83.9979827,
-                266.44429402,
-            ],
-            [
-                552.308813,
-                938.32014715,
-                -232.52035701,
-                266.44429402,
-                983.33032073,
-            ],
-        ];
+    fn test_quantile_sketches() {
+        let (real_x, observed_y) = load_baked_dataset();
+        let foo = LinearModel::new(&real_x, &observed_y);
+        let nwalkers = 10;
+        let niters = 500;
+        let mut rng = StdRng::from_seed(&[1, 2, 3, 4]);
+        let pos = create_guess().create_initial_guess_with_rng(nwalkers, &mut rng);
 
-        let guesses = &[
-            Guess::new(&[5., 5., 5., 5., 5.]),
-            Guess::new(&[5., 5., 5., 5., 9.]),
-            Guess::new(&[5., 120., 5., 5., 9.]),
-        ];
-        let expecteds = &[-80374.2068729, -138398.513797, -7902962.23125];
+        let mut sampler = EnsembleSampler::new(nwalkers, 2, &foo).unwrap();
+        sampler.run_mcmc(&pos, 10).unwrap();
+        assert!(sampler.quantile_sketches().is_empty());
 
-        for (guess, expected) in guesses.iter().zip(expecteds) {
-            let p = MultivariateProb { icov: (&icov) };
-            assert_approx_eq!(p.lnprob(&guess), expected, 1E-4);
+        sampler.reset();
+        sampler.seed(&[5]);
+        sampler.quantile_sketch = Some(100.0);
+        sampler.run_mcmc(&pos, niters).unwrap();
+        let flatchain = sampler.flatchain().unwrap();
+        let sketches = sampler.quantile_sketches();
+        assert_eq!(sketches.len(), 2);
+        for (param, sketch) in sketches.iter().enumerate() {
+            assert_eq!(sketch.count(), nwalkers * niters);
+            let mut values: Vec<f64> = flatchain.iter().map(|guess| guess[param]).collect();
+            values.sort_by(|a, b| a.partial_cmp(b).unwrap());
+            let spread = values[values.len() - 1] - values[0];
+            for &q in &[0.025, 0.5, 0.975] {
+                let exact = values[(q * values.len() as f64) as usize];
+                assert_approx_eq!(sketch.quantile(q), exact, 0.01 * spread);
+            }
         }
+
+        sampler.clear_online_stats();
+        assert!(sampler.quantile_sketches().is_empty());
     }
 
     #[test]
-    fn test_not_storing_chain() {
+    fn test_running_stats() {
         let nwalkers = 20;
-        let p0 = Guess {
-            values: vec![0f64, 0f64],
-        };
+        let p0 = create_guess();
         let mut rng = StdRng::from_seed(&[1, 2, 3, 4]);
         let pos = p0.create_initial_guess_with_rng(nwalkers, &mut rng);
         let (real_x, observed_y) = load_baked_dataset();
         let foo = LinearModel::new(&real_x, &observed_y);
 
-        let niters = 1000;
+        let niters = 2000;
         let mut sampler = EnsembleSampler::new(nwalkers, p0.values.len(), &foo).unwrap();
         sampler.seed(&[0]);
+        assert!(sampler.running_stats().is_none());
+        sampler.run_mcmc(&pos, niters).unwrap();
+
+        let stats = sampler.running_stats().unwrap();
+        assert_eq!(stats.niterations(), niters);
+
+        let flatchain = sampler.flatchain().unwrap();
+        for param in 0..2 {
+            let mean = flatchain.iter().map(|guess| guess[param]).sum::<f64>()
+                / flatchain.len() as f64;
+            assert_approx_eq!(stats.mean()[param], mean, 1e-8);
+        }
+
+        assert!(stats.split_rhat().iter().all(|rhat| rhat.is_finite()));
+
         sampler.storechain = false;
-        let _ = sampler.run_mcmc(&pos, niters).unwrap();
-        assert!(sampler.chain.is_none());
+        sampler.run_mcmc(&pos, 10).unwrap();
+        assert_eq!(sampler.running_stats().unwrap().niterations(), niters);
+        sampler.reset();
+        assert!(sampler.running_stats().is_none());
     }
 
     #[test]
-    fn test_thinning() {
+    fn test_monitor_from_another_thread() {
+        use std::sync::atomic::{AtomicBool, Ordering};
+        use std::sync::Arc;
+        use std::thread;
+
         let nwalkers = 20;
-        let p0 = Guess {
-            values: vec![0f64, 0f64],
-        };
+        let p0 = create_guess();
         let mut rng = StdRng::from_seed(&[1, 2, 3, 4]);
         let pos = p0.create_initial_guess_with_rng(nwalkers, &mut rng);
         let (real_x, observed_y) = load_baked_dataset();
         let foo = LinearModel::new(&real_x, &observed_y);
 
-        let niters = 1000;
+        let niters = 500;
         let mut sampler = EnsembleSampler::new(nwalkers, p0.values.len(), &foo).unwrap();
         sampler.seed(&[0]);
-        sampler.thin = 500;
-        let _ = sampler.run_mcmc(&pos, niters).unwrap();
-        match sampler.chain {
-            Some(ref chain) => assert_eq!(chain.niterations, niters),
-            None => panic!("chain should not be `None`"),
+        let monitor = sampler.monitor(10);
+
+        let done = Arc::new(AtomicBool::new(false));
+        let reader = {
+            let done = done.clone();
+            let monitor = monitor.clone();
+            thread::spawn(move || {
+                let mut last = 0;
+                while !done.load(Ordering::SeqCst) {
+                    let snapshot = monitor.snapshot();
+                    let n = snapshot.niterations();
+                    assert!(n >= last);
+                    assert_eq!(n % 10, 0);
+                    assert_eq!(snapshot.flatchain().len(), n * nwalkers);
+                    last = n;
+                }
+            })
+        };
+
+        sampler.run_mcmc(&pos, niters).unwrap();
+        done.store(true, Ordering::SeqCst);
+        reader.join().unwrap();
+
+        let snapshot = monitor.snapshot();
+        assert_eq!(snapshot.niterations(), niters);
+        let chain = sampler.chain.as_ref().unwrap();
+        for iteration in &[0, 123, niters - 1] {
+            for walker in 0..nwalkers {
+                assert_eq!(snapshot.get(0, walker, *iteration), chain.get(0, walker, *iteration));
+                assert_eq!(snapshot.get(1, walker, *iteration), chain.get(1, walker, *iteration));
+            }
         }
     }
 
@@ -0,0 +1,798 @@
+//! Ready-made likelihoods for fitting a model curve to data
+//!
+//! Each likelihood compares the data with the predictions of a closure which maps the
+//! parameters to the expected value of every data point, and implements
+//! [`Prob`](../trait.Prob.html) with a flat prior, so it can be sampled directly or used inside
+//! a model which adds its own prior. Likelihoods of separate datasets which share parameters
+//! are combined with a [`Joint`](struct.Joint.html).
+//!
+//! ```rust
+//! # use emcee::likelihood::Gaussian;
+//! # use emcee::{EnsembleSampler, Guess};
+//! let x = vec![1.0, 2.0, 3.0, 4.0];
+//! let y = vec![3.1, 4.9, 7.2, 8.8];
+//! let sigma = vec![0.2; 4];
+//!
+//! let model = Gaussian::new(y, sigma, move |params: &Guess| {
+//!     x.iter().map(|x| params[0] * x + params[1]).collect()
+//! }).unwrap();
+//!
+//! let sampler = EnsembleSampler::new(8, 2, &model).unwrap();
+//! # let _ = sampler;
+//! ```
+
+use std::f64::consts::PI;
+
+use errors::{EmceeError, Result};
+use guess::Guess;
+use linalg::{self, Matrix};
+use math;
+use prob::Prob;
+use special::{ln_gamma, ln_normal_cdf, ln_normal_interval};
+
+#[derive(Debug, Clone)]
+enum Noise {
+    /// Independent errors with these standard deviations
+    Diagonal(Vec<f64>),
+
+    /// Correlated errors, with the lower triangular Cholesky factor of their covariance
+    Full(Matrix),
+}
+
+/// Gaussian errors around a model prediction: the usual chi-square fit
+///
+/// The log likelihood is `-chi2 / 2 - ln det(2 pi C) / 2`, where `C` is the covariance of the
+/// errors. With correlated errors, `chi2 = r^T C^-1 r` for the residuals `r` is evaluated with
+/// the Cholesky factor of `C`, which is computed once when the likelihood is created.
+#[derive(Debug, Clone)]
+pub struct Gaussian<F> {
+    y: Vec<f64>,
+    noise: Noise,
+    norm: f64,
+    predict: F,
+}
+
+impl<F> Gaussian<F>
+where
+    F: Fn(&Guess) -> Vec<f64> + Send + Sync,
+{
+    /// Independent Gaussian errors with standard deviation `sigma[i]` on the data point `y[i]`
+    ///
+    /// `predict` returns the model prediction for every data point.
+    ///
+    /// Errors are handled by returning a [`Result`](../errors/type.Result.html) which contains
+    /// [`EmceeError::InvalidInputs`](../errors/enum.EmceeError.html) if `sigma` and `y` have
+    /// different lengths, or a standard deviation is not positive and finite.
+    pub fn new(y: Vec<f64>, sigma: Vec<f64>, predict: F) -> Result<Gaussian<F>> {
+        check_sigma(&sigma, y.len())?;
+        let norm = sigma.iter().map(|s| (2.0 * PI * s * s).ln()).sum::<f64>();
+        Ok(Gaussian {
+            y,
+            noise: Noise::Diagonal(sigma),
+            norm,
+            predict,
+        })
+    }
+
+    /// Correlated Gaussian errors on the data points `y` with the covariance matrix `cov`
+    ///
+    /// `predict` returns the model prediction for every data point.
+    ///
+    /// Errors are handled by returning a [`Result`](../errors/type.Result.html) which contains
+    /// [`EmceeError::InvalidInputs`](../errors/enum.EmceeError.html) if the covariance is not
+    /// square, does not match the data, or is not positive definite.
+    pub fn with_covariance(y: Vec<f64>, cov: Vec<Vec<f64>>, predict: F) -> Result<Gaussian<F>> {
+        if cov.len() != y.len() || cov.iter().any(|row| row.len() != y.len()) {
+            return Err(EmceeError::InvalidInputs(
+                "the covariance must be square and match the data".into(),
+            ));
+        }
+        let l = linalg::cholesky(&cov).ok_or_else(|| {
+            EmceeError::InvalidInputs("the covariance must be positive definite".into())
+        })?;
+
+        // ln det(2 pi C) = n ln(2 pi) + 2 sum ln L_ii
+        let norm = (0..y.len())
+            .map(|i| (2.0 * PI).ln() + 2.0 * l[i][i].ln())
+            .sum::<f64>();
+        Ok(Gaussian {
+            y,
+            noise: Noise::Full(l),
+            norm,
+            predict,
+        })
+    }
+
+    /// The data points
+    pub fn data(&self) -> &[f64] {
+        &self.y
+    }
+
+    /// The residuals `y - prediction` at a position in parameter space
+    ///
+    /// # Panics
+    ///
+    /// Panics if the prediction does not have one value per data point.
+    pub fn residuals(&self, params: &Guess) -> Vec<f64> {
+        let prediction = predictions(&self.predict, params, self.y.len());
+        self.y.iter().zip(&prediction).map(|(y, m)| y - m).collect()
+    }
+
+    /// The chi-square of the fit at a position in parameter space
+    ///
+    /// # Panics
+    ///
+    /// Panics if the prediction does not have one value per data point.
+    pub fn chi2(&self, params: &Guess) -> f64 {
+        let residuals = self.residuals(params);
+        match self.noise {
+            Noise::Diagonal(ref sigma) => residuals
+                .iter()
+                .zip(sigma)
+                .map(|(r, s)| (r / s).powi(2))
+                .sum(),
+            Noise::Full(ref l) => linalg::lower_solve(l, &residuals)
+                .iter()
+                .map(|z| z * z)
+                .sum(),
+        }
+    }
+}
+
+impl<F> Prob for Gaussian<F>
+where
+    F: Fn(&Guess) -> Vec<f64> + Send + Sync,
+{
+    fn lnlike(&self, params: &Guess) -> f64 {
+        -0.5 * (self.chi2(params) + self.norm)
+    }
+
+    fn lnprior(&self, _params: &Guess) -> f64 {
+        0.0
+    }
+}
+
+/// The log density of a Gaussian with standard deviation `sigma` at a distance `r` from its mean
+fn ln_normal(r: f64, sigma: f64) -> f64 {
+    -0.5 * ((r / sigma).powi(2) + (2.0 * PI * sigma * sigma).ln())
+}
+
+/// Evaluate `predict` and check it gives one value per data point
+fn predictions<F: Fn(&Guess) -> Vec<f64>>(predict: &F, params: &Guess, ndata: usize) -> Vec<f64> {
+    let prediction = predict(params);
+    assert_eq!(
+        prediction.len(),
+        ndata,
+        "the model predicted {} values for {} data points",
+        prediction.len(),
+        ndata
+    );
+    prediction
+}
+
+fn check_sigma(sigma: &[f64], ndata: usize) -> Result<()> {
+    if sigma.len() != ndata {
+        return Err(EmceeError::InvalidInputs(format!(
+            "{} uncertainties given for {} data points",
+            sigma.len(),
+            ndata
+        )));
+    }
+    if !sigma.iter().all(|s| s.is_finite() && *s > 0.0) {
+        return Err(EmceeError::InvalidInputs(
+            "the uncertainties must be positive and finite".into(),
+        ));
+    }
+    Ok(())
+}
+
+/// The product of several likelihoods of the same parameters
+///
+/// The log likelihoods and log priors of the components are summed, e.g. to fit the
+/// detections of a survey with a [`Gaussian`](struct.Gaussian.html) and its upper limits with
+/// a [`Censored`](struct.Censored.html) likelihood, or to add a prior written as a separate
+/// [`Prob`](../trait.Prob.html).
+pub struct Joint {
+    components: Vec<Box<dyn Prob>>,
+}
+
+impl Joint {
+    /// Combine the given likelihoods
+    pub fn new(components: Vec<Box<dyn Prob>>) -> Joint {
+        Joint { components }
+    }
+}
+
+impl Prob for Joint {
+    fn lnlike(&self, params: &Guess) -> f64 {
+        self.components
+            .iter()
+            .map(|component| component.lnlike(params))
+            .sum()
+    }
+
+    fn lnprior(&self, params: &Guess) -> f64 {
+        self.components
+            .iter()
+            .map(|component| component.lnprior(params))
+            .sum()
+    }
+
+    fn validate(&self, params: &Guess) -> ::std::result::Result<(), String> {
+        self.components
+            .iter()
+            .try_for_each(|component| component.validate(params))
+    }
+}
+
+/// One data point of a [`Censored`](struct.Censored.html) likelihood
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Observation {
+    /// A measured value with a Gaussian uncertainty
+    Value {
+        /// The measured value
+        value: f64,
+
+        /// The standard deviation of the measurement error
+        sigma: f64,
+    },
+
+    /// Only known to lie below `limit`, e.g. a flux too faint to be detected
+    UpperLimit {
+        /// The limit
+        limit: f64,
+
+        /// The standard deviation of the measurement error, which blurs the limit
+        sigma: f64,
+    },
+
+    /// Only known to lie above `limit`
+    LowerLimit {
+        /// The limit
+        limit: f64,
+
+        /// The standard deviation of the measurement error, which blurs the limit
+        sigma: f64,
+    },
+}
+
+impl Observation {
+    fn sigma(&self) -> f64 {
+        match *self {
+            Observation::Value { sigma, .. }
+            | Observation::UpperLimit { sigma, .. }
+            | Observation::LowerLimit { sigma, .. } => sigma,
+        }
+    }
+}
+
+/// Gaussian errors around a model prediction, with some data points only known as limits
+///
+/// A measured value contributes its Gaussian density, as in a
+/// [`Gaussian`](struct.Gaussian.html) likelihood. An upper limit contributes the probability
+/// `Phi((limit - prediction) / sigma)` that the measurement would have fallen below the limit,
+/// and a lower limit the probability that it would have fallen above. The log probabilities are
+/// computed directly, so limits far from the prediction do not underflow.
+#[derive(Debug, Clone)]
+pub struct Censored<F> {
+    observations: Vec<Observation>,
+    predict: F,
+}
+
+impl<F> Censored<F>
+where
+    F: Fn(&Guess) -> Vec<f64> + Send + Sync,
+{
+    /// Create a likelihood of the `observations`, where `predict` returns the model prediction
+    /// for each one
+    ///
+    /// Errors are handled by returning a [`Result`](../errors/type.Result.html) which contains
+    /// [`EmceeError::InvalidInputs`](../errors/enum.EmceeError.html) if an uncertainty is not
+    /// positive and finite.
+    pub fn new(observations: Vec<Observation>, predict: F) -> Result<Censored<F>> {
+        let sigma: Vec<f64> = observations.iter().map(Observation::sigma).collect();
+        check_sigma(&sigma, observations.len())?;
+        Ok(Censored {
+            observations,
+            predict,
+        })
+    }
+
+    /// The observations
+    pub fn observations(&self) -> &[Observation] {
+        &self.observations
+    }
+}
+
+impl<F> Prob for Censored<F>
+where
+    F: Fn(&Guess) -> Vec<f64> + Send + Sync,
+{
+    fn lnlike(&self, params: &Guess) -> f64 {
+        let prediction = predictions(&self.predict, params, self.observations.len());
+        self.observations
+            .iter()
+            .zip(&prediction)
+            .map(|(observation, m)| match *observation {
+                Observation::Value { value, sigma } => ln_normal(value - m, sigma),
+                Observation::UpperLimit { limit, sigma } => ln_normal_cdf((limit - m) / sigma),
+                Observation::LowerLimit { limit, sigma } => ln_normal_cdf((m - limit) / sigma),
+            })
+            .sum()
+    }
+
+    fn lnprior(&self, _params: &Guess) -> f64 {
+        0.0
+    }
+}
+
+/// Gaussian errors around a model prediction, for data which could only be observed within
+/// fixed bounds
+///
+/// When a survey can only record values between `lower` and `upper`, e.g. above a flux limit,
+/// the density of each measured value is renormalised by the probability that it fell within
+/// the bounds: `N(y | m, sigma) / (Phi((upper - m) / sigma) - Phi((lower - m) / sigma))`.
+/// Without this the fit is biased towards models which put the data near the bounds.
+#[derive(Debug, Clone)]
+pub struct Truncated<F> {
+    y: Vec<f64>,
+    sigma: Vec<f64>,
+    lower: f64,
+    upper: f64,
+    predict: F,
+}
+
+impl<F> Truncated<F>
+where
+    F: Fn(&Guess) -> Vec<f64> + Send + Sync,
+{
+    /// Independent Gaussian errors with standard deviation `sigma[i]` on the data point `y[i]`,
+    /// which can only be observed between `lower` and `upper` (unbounded if `None`)
+    ///
+    /// Errors are handled by returning a [`Result`](../errors/type.Result.html) which contains
+    /// [`EmceeError::InvalidInputs`](../errors/enum.EmceeError.html) if `sigma` and `y` have
+    /// different lengths, a standard deviation is not positive and finite, or a data point lies
+    /// outside the bounds.
+    pub fn new(
+        y: Vec<f64>,
+        sigma: Vec<f64>,
+        lower: Option<f64>,
+        upper: Option<f64>,
+        predict: F,
+    ) -> Result<Truncated<F>> {
+        check_sigma(&sigma, y.len())?;
+        let lower = lower.unwrap_or(-f64::INFINITY);
+        let upper = upper.unwrap_or(f64::INFINITY);
+        if let Some(value) = y.iter().find(|&&value| !(value >= lower && value <= upper)) {
+            return Err(EmceeError::InvalidInputs(format!(
+                "the data point {} lies outside the bounds {} to {}",
+                value, lower, upper
+            )));
+        }
+        Ok(Truncated {
+            y,
+            sigma,
+            lower,
+            upper,
+            predict,
+        })
+    }
+}
+
+impl<F> Prob for Truncated<F>
+where
+    F: Fn(&Guess) -> Vec<f64> + Send + Sync,
+{
+    fn lnlike(&self, params: &Guess) -> f64 {
+        let prediction = predictions(&self.predict, params, self.y.len());
+        self.y
+            .iter()
+            .zip(&self.sigma)
+            .zip(&prediction)
+            .map(|((y, sigma), m)| {
+                let a = (self.lower - m) / sigma;
+                let b = (self.upper - m) / sigma;
+                ln_normal(y - m, *sigma) - ln_normal_interval(a, b)
+            })
+            .sum()
+    }
+
+    fn lnprior(&self, _params: &Guess) -> f64 {
+        0.0
+    }
+}
+
+/// The degrees of freedom of a [`StudentT`](struct.StudentT.html) likelihood
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Dof {
+    /// A fixed value, e.g. 4 for a moderately robust fit
+    Fixed(f64),
+
+    /// Sampled as the parameter at this index, so the data decide how heavy the tails are
+    Param(usize),
+}
+
+/// Student-t errors around a model prediction, for fits which are robust to outliers
+///
+/// The residual `r` of a data point with scale `sigma` has the log density
+/// `ln Gamma((nu + 1) / 2) - ln Gamma(nu / 2) - ln(nu pi sigma^2) / 2
+/// - (nu + 1) / 2 ln(1 + r^2 / (nu sigma^2))`. The heavy tails mean a few outliers cannot pull
+/// the fit far from the bulk of the data, and as `nu` grows the likelihood approaches a
+/// [`Gaussian`](struct.Gaussian.html) with standard deviations `sigma`. When `nu` is sampled,
+/// positions where it is not positive are outside the prior; a prior favouring small values,
+/// e.g. flat in `ln nu`, is usually worth adding.
+#[derive(Debug, Clone)]
+pub struct StudentT<F> {
+    y: Vec<f64>,
+    sigma: Vec<f64>,
+    nu: Dof,
+    predict: F,
+}
+
+impl<F> StudentT<F>
+where
+    F: Fn(&Guess) -> Vec<f64> + Send + Sync,
+{
+    /// Independent Student-t errors with scale `sigma[i]` on the data point `y[i]`, and `nu`
+    /// degrees of freedom
+    ///
+    /// Errors are handled by returning a [`Result`](../errors/type.Result.html) which contains
+    /// [`EmceeError::InvalidInputs`](../errors/enum.EmceeError.html) if `sigma` and `y` have
+    /// different lengths, a scale is not positive and finite, or a fixed `nu` is not positive.
+    pub fn new(y: Vec<f64>, sigma: Vec<f64>, nu: Dof, predict: F) -> Result<StudentT<F>> {
+        check_sigma(&sigma, y.len())?;
+        if let Dof::Fixed(nu) = nu {
+            if nu.is_nan() || nu <= 0.0 {
+                return Err(EmceeError::InvalidInputs(
+                    "the degrees of freedom must be positive".into(),
+                ));
+            }
+        }
+        Ok(StudentT {
+            y,
+            sigma,
+            nu,
+            predict,
+        })
+    }
+
+    fn nu(&self, params: &Guess) -> f64 {
+        match self.nu {
+            Dof::Fixed(nu) => nu,
+            Dof::Param(idx) => params[idx],
+        }
+    }
+}
+
+impl<F> Prob for StudentT<F>
+where
+    F: Fn(&Guess) -> Vec<f64> + Send + Sync,
+{
+    fn lnlike(&self, params: &Guess) -> f64 {
+        let nu = self.nu(params);
+        let prediction = predictions(&self.predict, params, self.y.len());
+        let norm = ln_gamma((nu + 1.0) / 2.0) - ln_gamma(nu / 2.0) - 0.5 * (nu * PI).ln();
+        self.y
+            .iter()
+            .zip(&self.sigma)
+            .zip(&prediction)
+            .map(|((y, sigma), m)| {
+                let z2 = ((y - m) / sigma).powi(2);
+                norm - sigma.ln() - 0.5 * (nu + 1.0) * (z2 / nu).ln_1p()
+            })
+            .sum()
+    }
+
+    fn lnprior(&self, params: &Guess) -> f64 {
+        let nu = self.nu(params);
+        if nu > 0.0 && nu.is_finite() {
+            0.0
+        } else {
+            -f64::INFINITY
+        }
+    }
+}
+
+/// The indices of the parameters describing the outliers of an
+/// [`OutlierMixture`](struct.OutlierMixture.html)
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Outliers {
+    /// The index of the fraction of outliers, between 0 and 1
+    pub fraction: usize,
+
+    /// The index of the mean of the outlier distribution
+    pub mean: usize,
+
+    /// The index of the natural log of the variance of the outlier distribution
+    pub ln_variance: usize,
+}
+
+/// Gaussian errors around a model prediction, with a fraction of the data points drawn from a
+/// broad outlier distribution instead
+///
+/// Each data point is good with probability `1 - P` and follows `N(y | m, sigma^2)`, or is an
+/// outlier with probability `P` and follows `N(y | Y, sigma^2 + V)`, where `P`, `Y` and
+/// `ln V` are sampled parameters given by [`Outliers`](struct.Outliers.html) (Hogg, Bovy & Lang
+/// 2010, section 5). The membership of each point is marginalised analytically, with the
+/// mixture summed in log space so that points far from both components do not underflow, and
+/// can be recovered afterwards with
+/// [`outlier_probabilities`](#method.outlier_probabilities). Positions with a fraction outside
+/// 0 to 1 are outside the prior.
+#[derive(Debug, Clone)]
+pub struct OutlierMixture<F> {
+    y: Vec<f64>,
+    sigma: Vec<f64>,
+    outliers: Outliers,
+    predict: F,
+}
+
+impl<F> OutlierMixture<F>
+where
+    F: Fn(&Guess) -> Vec<f64> + Send + Sync,
+{
+    /// Independent Gaussian errors with standard deviation `sigma[i]` on the data point `y[i]`,
+    /// contaminated by outliers described by the parameters in `outliers`
+    ///
+    /// Errors are handled by returning a [`Result`](../errors/type.Result.html) which contains
+    /// [`EmceeError::InvalidInputs`](../errors/enum.EmceeError.html) if `sigma` and `y` have
+    /// different lengths, or a standard deviation is not positive and finite.
+    pub fn new(
+        y: Vec<f64>,
+        sigma: Vec<f64>,
+        outliers: Outliers,
+        predict: F,
+    ) -> Result<OutlierMixture<F>> {
+        check_sigma(&sigma, y.len())?;
+        Ok(OutlierMixture {
+            y,
+            sigma,
+            outliers,
+            predict,
+        })
+    }
+
+    /// The probability that each data point is an outlier, given the parameters
+    ///
+    /// Averaging over the samples of a chain gives the posterior probability that each point
+    /// is an outlier.
+    pub fn outlier_probabilities(&self, params: &Guess) -> Vec<f64> {
+        self.components(params)
+            .into_iter()
+            .map(|(good, bad)| 1.0 / (1.0 + (good - bad).exp()))
+            .collect()
+    }
+
+    /// The log probability of each data point under the good and the outlier components,
+    /// including the mixture weights
+    fn components(&self, params: &Guess) -> Vec<(f64, f64)> {
+        let fraction = params[self.outliers.fraction];
+        let mean = params[self.outliers.mean];
+        let variance = params[self.outliers.ln_variance].exp();
+        let prediction = predictions(&self.predict, params, self.y.len());
+        self.y
+            .iter()
+            .zip(&self.sigma)
+            .zip(&prediction)
+            .map(|((y, sigma), m)| {
+                let good = (-fraction).ln_1p() + ln_normal(y - m, *sigma);
+                let bad = fraction.ln() + ln_normal(y - mean, (sigma * sigma + variance).sqrt());
+                (good, bad)
+            })
+            .collect()
+    }
+}
+
+impl<F> Prob for OutlierMixture<F>
+where
+    F: Fn(&Guess) -> Vec<f64> + Send + Sync,
+{
+    fn lnlike(&self, params: &Guess) -> f64 {
+        self.components(params)
+            .into_iter()
+            .map(|(good, bad)| math::logaddexp(good, bad))
+            .sum()
+    }
+
+    fn lnprior(&self, params: &Guess) -> f64 {
+        let fraction = params[self.outliers.fraction];
+        if (0.0..=1.0).contains(&fraction) && params[self.outliers.ln_variance].is_finite() {
+            0.0
+        } else {
+            -f64::INFINITY
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn line(x: Vec<f64>) -> impl Fn(&Guess) -> Vec<f64> + Send + Sync {
+        move |params: &Guess| x.iter().map(|x| params[0] * x + params[1]).collect()
+    }
+
+    #[test]
+    fn test_independent_errors() {
+        let x = vec![0.0, 1.0, 2.0];
+        let model = Gaussian::new(vec![1.0, 3.0, 4.0], vec![1.0, 2.0, 0.5], line(x)).unwrap();
+        let params = Guess::new(&[2.0, 1.0]);
+        assert_eq!(model.residuals(&params), vec![0.0, 0.0, -1.0]);
+        assert_approx_eq!(model.chi2(&params), 4.0);
+        let norm = (2.0 * PI).ln() + (8.0 * PI).ln() + (0.5 * PI).ln();
+        assert_approx_eq!(model.lnprob(&params), -0.5 * (4.0 + norm));
+
+        assert!(Gaussian::new(vec![1.0], vec![1.0, 1.0], line(vec![0.0])).is_err());
+        assert!(Gaussian::new(vec![1.0], vec![0.0], line(vec![0.0])).is_err());
+    }
+
+    #[test]
+    fn test_correlated_errors() {
+        // A diagonal covariance agrees with independent errors
+        let diagonal = vec![vec![1.0, 0.0, 0.0], vec![0.0, 4.0, 0.0], vec![0.0, 0.0, 0.25]];
+        let x = vec![0.0, 1.0, 2.0];
+        let y = vec![1.0, 3.0, 4.0];
+        let full = Gaussian::with_covariance(y.clone(), diagonal, line(x.clone())).unwrap();
+        let independent = Gaussian::new(y.clone(), vec![1.0, 2.0, 0.5], line(x.clone())).unwrap();
+        let params = Guess::new(&[1.5, 0.5]);
+        assert_approx_eq!(full.lnprob(&params), independent.lnprob(&params));
+
+        // Two data points with correlation rho: chi2 = (r1^2 - 2 rho r1 r2 + r2^2) / (1 - rho^2)
+        let rho = 0.6;
+        let cov = vec![vec![1.0, rho], vec![rho, 1.0]];
+        let model =
+            Gaussian::with_covariance(vec![1.0, -1.0], cov, |_: &Guess| vec![0.0, 0.0]).unwrap();
+        let params = Guess::new(&[0.0]);
+        assert_approx_eq!(model.chi2(&params), (2.0 + 2.0 * rho) / (1.0 - rho * rho));
+        let ln_det = (1.0 - rho * rho).ln();
+        assert_approx_eq!(
+            model.lnprob(&params),
+            -0.5 * (model.chi2(&params) + 2.0 * (2.0 * PI).ln() + ln_det)
+        );
+
+        let indefinite = vec![vec![1.0, 2.0], vec![2.0, 1.0]];
+        assert!(Gaussian::with_covariance(vec![0.0, 0.0], indefinite, line(x)).is_err());
+    }
+
+    #[test]
+    fn test_censored() {
+        let observations = vec![
+            Observation::Value {
+                value: 1.0,
+                sigma: 0.5,
+            },
+            Observation::UpperLimit {
+                limit: 2.0,
+                sigma: 1.0,
+            },
+            Observation::LowerLimit {
+                limit: -1.0,
+                sigma: 2.0,
+            },
+        ];
+        let model = Censored::new(observations, |params: &Guess| vec![params[0]; 3]).unwrap();
+        let params = Guess::new(&[1.0]);
+        // N(0 | 0, 0.5), Phi(1) and Phi(1)
+        let expected = -(2.0 * PI * 0.25f64).ln() / 2.0 + 2.0 * 0.841_344_746f64.ln();
+        assert_approx_eq!(model.lnprob(&params), expected, 1e-6);
+
+        // A limit far below the prediction is extremely unlikely, but finite
+        assert!(model.lnprob(&Guess::new(&[50.0])).is_finite());
+
+        let joint = Joint::new(vec![
+            Box::new(model),
+            Box::new(Gaussian::new(vec![0.0], vec![1.0], |params: &Guess| vec![params[0]])
+                .unwrap()),
+        ]);
+        assert_approx_eq!(joint.lnprob(&params), expected - 0.5 * (1.0 + (2.0 * PI).ln()), 1e-6);
+
+        let zero_sigma = vec![Observation::UpperLimit {
+            limit: 0.0,
+            sigma: 0.0,
+        }];
+        assert!(Censored::new(zero_sigma, |_: &Guess| vec![0.0]).is_err());
+    }
+
+    #[test]
+    fn test_truncated() {
+        let predict = |params: &Guess| vec![params[0]; 2];
+        let model =
+            Truncated::new(vec![0.5, 1.5], vec![1.0, 1.0], Some(0.0), None, predict).unwrap();
+        let untruncated = Gaussian::new(vec![0.5, 1.5], vec![1.0, 1.0], predict).unwrap();
+
+        // Half of the distribution lies above a bound at the prediction
+        let params = Guess::new(&[0.0]);
+        assert_approx_eq!(model.lnprob(&params), untruncated.lnprob(&params) + 2.0 * 2f64.ln());
+        // Far above the bound the truncation makes no difference
+        let params = Guess::new(&[40.0]);
+        assert_approx_eq!(model.lnprob(&params), untruncated.lnprob(&params));
+        // Far below it the normalisation does not underflow
+        assert!(model.lnprob(&Guess::new(&[-30.0])).is_finite());
+
+        let both = Truncated::new(vec![0.5], vec![1.0], Some(-1.0), Some(1.0), |_: &Guess| {
+            vec![0.0]
+        }).unwrap();
+        let expected = -0.5 * (0.25 + (2.0 * PI).ln()) - 0.682_689_492f64.ln();
+        assert_approx_eq!(both.lnprob(&Guess::new(&[0.0])), expected, 1e-6);
+
+        assert!(Truncated::new(vec![-0.5], vec![1.0], Some(0.0), None, predict).is_err());
+    }
+
+    #[test]
+    fn test_student_t() {
+        let predict = |params: &Guess| vec![params[0]; 2];
+        let y = vec![1.0, -2.0];
+        let sigma = vec![1.0, 2.0];
+
+        // One degree of freedom gives the Cauchy distribution
+        let cauchy = StudentT::new(y.clone(), sigma.clone(), Dof::Fixed(1.0), predict).unwrap();
+        let expected = -(PI * 2.0).ln() - (PI * 4.0).ln();
+        assert_approx_eq!(cauchy.lnprob(&Guess::new(&[0.0])), expected, 1e-12);
+
+        // Many degrees of freedom approach a Gaussian
+        let wide = StudentT::new(y.clone(), sigma.clone(), Dof::Fixed(1e7), predict).unwrap();
+        let gaussian = Gaussian::new(y.clone(), sigma.clone(), predict).unwrap();
+        let params = Guess::new(&[0.5]);
+        assert_approx_eq!(wide.lnprob(&params), gaussian.lnprob(&params), 1e-5);
+
+        // A sampled nu
+        let sampled = StudentT::new(y.clone(), sigma.clone(), Dof::Param(1), predict).unwrap();
+        assert_approx_eq!(
+            sampled.lnprob(&Guess::new(&[0.0, 1.0])),
+            cauchy.lnprob(&Guess::new(&[0.0]))
+        );
+        assert_eq!(sampled.lnprob(&Guess::new(&[0.0, -1.0])), -f64::INFINITY);
+
+        // An outlier moves the fit far less than with Gaussian errors
+        let y = vec![0.0, 0.1, -0.1, 0.05, 20.0];
+        let robust = StudentT::new(y.clone(), vec![1.0; 5], Dof::Fixed(2.0), |params: &Guess| {
+            vec![params[0]; 5]
+        }).unwrap();
+        let best = (-100..500)
+            .map(|i| 0.01 * i as f64)
+            .max_by(|a, b| {
+                let (a, b) = (Guess::new(&[*a]), Guess::new(&[*b]));
+                robust.lnprob(&a).partial_cmp(&robust.lnprob(&b)).unwrap()
+            })
+            .unwrap();
+        assert!(best.abs() < 0.2, "{}", best);
+
+        assert!(StudentT::new(y, vec![1.0; 5], Dof::Fixed(0.0), predict).is_err());
+    }
+
+    #[test]
+    fn test_outlier_mixture() {
+        let outliers = Outliers {
+            fraction: 2,
+            mean: 3,
+            ln_variance: 4,
+        };
+        let x = vec![0.0, 1.0, 2.0, 3.0, 4.0];
+        let y = vec![1.0, 3.1, 4.9, 30.0, 9.0];
+        let model = OutlierMixture::new(y.clone(), vec![0.2; 5], outliers, line(x.clone()))
+            .unwrap();
+
+        // Without outliers the mixture is the plain Gaussian likelihood
+        let gaussian = Gaussian::new(y.clone(), vec![0.2; 5], line(x.clone())).unwrap();
+        let params = Guess::new(&[2.0, 1.0, 0.0, 0.0, 0.0]);
+        assert_approx_eq!(model.lnprob(&params), gaussian.lnprob(&params));
+
+        // A single point, mixed by hand
+        let single = OutlierMixture::new(vec![2.0], vec![1.0], outliers, line(vec![0.0])).unwrap();
+        let params = Guess::new(&[0.0, 0.0, 0.25, 1.0, 3f64.ln()]);
+        let expected = 0.75 * (-2.0f64).exp() / (2.0 * PI).sqrt()
+            + 0.25 * (-1.0 / 8.0f64).exp() / (8.0 * PI).sqrt();
+        assert_approx_eq!(single.lnprob(&params), expected.ln());
+
+        // The fourth point is recognised as the outlier
+        let params = Guess::new(&[2.0, 1.0, 0.2, 10.0, 100f64.ln()]);
+        let probabilities = model.outlier_probabilities(&params);
+        assert!(probabilities[3] > 0.99);
+        assert!(probabilities.iter().enumerate().all(|(i, p)| i == 3 || *p < 0.01));
+
+        // Points far from both components do not underflow
+        let params = Guess::new(&[200.0, 1.0, 0.2, -1e3, 0.0]);
+        assert!(model.lnprob(&params).is_finite());
+
+        assert_eq!(model.lnprob(&Guess::new(&[2.0, 1.0, 1.5, 0.0, 0.0])), -f64::INFINITY);
+    }
+}
@@ -0,0 +1,230 @@
+//! Small dense linear algebra helpers
+//!
+//! Matrices are stored as a `Vec` of rows. The sizes involved are the number of parameters, so
+//! no attempt is made at blocking or vectorisation.
+
+use std::cmp::Ordering;
+
+use guess::Guess;
+use parallel;
+
+pub(crate) type Matrix = Vec<Vec<f64>>;
+
+/// Mean and unbiased covariance of a set of positions
+///
+/// The parameters of a large set are shared out between every available core.
+pub(crate) fn mean_covariance(samples: &[Guess]) -> (Vec<f64>, Matrix) {
+    assert!(!samples.is_empty());
+    let n = samples.len() as f64;
+    let dim = samples[0].values.len();
+    let nthreads = parallel::nthreads(samples.len() * dim * dim);
+
+    let mean = parallel::map(dim, nthreads, |i| {
+        samples.iter().fold(0f64, |acc, sample| acc + sample[i] / n)
+    });
+
+    let norm = if samples.len() > 1 { n - 1.0 } else { 1.0 };
+    let cov = parallel::map(dim, nthreads, |i| {
+        (0..dim)
+            .map(|j| {
+                samples
+                    .iter()
+                    .map(|sample| (sample[i] - mean[i]) * (sample[j] - mean[j]))
+                    .sum::<f64>() / norm
+            })
+            .collect()
+    });
+    (mean, cov)
+}
+
+/// Lower-triangular Cholesky factor `L` with `L L^T = a`, or `None` if `a` is not positive
+/// definite
+pub(crate) fn cholesky(a: &[Vec<f64>]) -> Option<Matrix> {
+    let n = a.len();
+    let mut l = vec![vec![0f64; n]; n];
+    for i in 0..n {
+        assert_eq!(a[i].len(), n, "matrix must be square");
+        for j in 0..=i {
+            let sum: f64 = (0..j).map(|k| l[i][k] * l[j][k]).sum();
+            if i == j {
+                let diag = a[i][i] - sum;
+                if !(diag > 0.0 && diag.is_finite()) {
+                    return None;
+                }
+                l[i][j] = diag.sqrt();
+            } else {
+                l[i][j] = (a[i][j] - sum) / l[j][j];
+            }
+        }
+    }
+    Some(l)
+}
+
+/// Returns true if `l` is lower triangular with a positive, finite diagonal
+pub(crate) fn is_valid_cholesky(l: &[Vec<f64>]) -> bool {
+    let n = l.len();
+    l.iter().enumerate().all(|(i, row)| {
+        row.len() == n && row[i] > 0.0 && row.iter().all(|value| value.is_finite())
+            && row[i + 1..].iter().all(|value| *value == 0.0)
+    })
+}
+
+/// Compute `L x` for lower-triangular `L`
+pub(crate) fn lower_mul(l: &[Vec<f64>], x: &[f64]) -> Vec<f64> {
+    (0..l.len())
+        .map(|i| (0..=i).map(|j| l[i][j] * x[j]).sum())
+        .collect()
+}
+
+/// Solve `L y = b` for lower-triangular `L`
+pub(crate) fn lower_solve(l: &[Vec<f64>], b: &[f64]) -> Vec<f64> {
+    let n = l.len();
+    let mut y = vec![0f64; n];
+    for i in 0..n {
+        let sum: f64 = (0..i).map(|j| l[i][j] * y[j]).sum();
+        y[i] = (b[i] - sum) / l[i][i];
+    }
+    y
+}
+
+/// Eigenvalues and unit eigenvectors of a symmetric matrix, by cyclic Jacobi rotations
+///
+/// The eigenvalues are in decreasing order, and the eigenvectors are the rows of the returned
+/// matrix, each with its largest component positive.
+pub(crate) fn symmetric_eigen(a: &[Vec<f64>]) -> (Vec<f64>, Matrix) {
+    let n = a.len();
+    let mut a = a.to_vec();
+    let mut v: Matrix = (0..n)
+        .map(|i| (0..n).map(|j| if i == j { 1.0 } else { 0.0 }).collect())
+        .collect();
+
+    let total: f64 = a.iter().flat_map(|row| row.iter()).map(|x| x * x).sum();
+    for _ in 0..100 {
+        let off: f64 = (0..n)
+            .flat_map(|p| (p + 1..n).map(move |q| (p, q)))
+            .map(|(p, q)| a[p][q] * a[p][q])
+            .sum();
+        if off <= 1e-30 * total {
+            break;
+        }
+
+        for p in 0..n {
+            for q in p + 1..n {
+                if a[p][q] == 0.0 {
+                    continue;
+                }
+                // The rotation which zeroes a[p][q] (Numerical Recipes, section 11.1)
+                let theta = (a[q][q] - a[p][p]) / (2.0 * a[p][q]);
+                let t = if theta.abs() > 1e150 {
+                    0.5 / theta
+                } else {
+                    theta.signum() / (theta.abs() + (theta * theta + 1.0).sqrt())
+                };
+                let c = 1.0 / (t * t + 1.0).sqrt();
+                let s = t * c;
+                for row in a.iter_mut().chain(v.iter_mut()) {
+                    let (x, y) = (row[p], row[q]);
+                    row[p] = c * x - s * y;
+                    row[q] = s * x + c * y;
+                }
+                let (upper, lower) = a.split_at_mut(q);
+                for (x, y) in upper[p].iter_mut().zip(lower[0].iter_mut()) {
+                    let (xp, yq) = (*x, *y);
+                    *x = c * xp - s * yq;
+                    *y = s * xp + c * yq;
+                }
+            }
+        }
+    }
+
+    let mut order: Vec<usize> = (0..n).collect();
+    order.sort_by(|&i, &j| a[j][j].partial_cmp(&a[i][i]).unwrap_or(Ordering::Equal));
+    let values = order.iter().map(|&i| a[i][i]).collect();
+    let vectors = order
+        .iter()
+        .map(|&i| {
+            let vector: Vec<f64> = v.iter().map(|row| row[i]).collect();
+            let largest = vector
+                .iter()
+                .cloned()
+                .fold(0f64, |m, x| if x.abs() > m.abs() { x } else { m });
+            vector.iter().map(|x| x * largest.signum()).collect()
+        })
+        .collect();
+    (values, vectors)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cholesky_round_trip() {
+        let a = vec![
+            vec![4.0, 2.0, 0.4],
+            vec![2.0, 5.0, 1.0],
+            vec![0.4, 1.0, 3.0],
+        ];
+        let l = cholesky(&a).unwrap();
+        assert!(is_valid_cholesky(&l));
+        for i in 0..3 {
+            for j in 0..3 {
+                let value: f64 = (0..3).map(|k| l[i][k] * l[j][k]).sum();
+                assert_approx_eq!(value, a[i][j]);
+            }
+        }
+
+        let x = [1.0, -2.0, 0.5];
+        let y = lower_solve(&l, &lower_mul(&l, &x));
+        for (a, b) in x.iter().zip(&y) {
+            assert_approx_eq!(a, b);
+        }
+    }
+
+    #[test]
+    fn test_cholesky_not_positive_definite() {
+        assert!(cholesky(&[vec![1.0, 2.0], vec![2.0, 1.0]]).is_none());
+        assert!(!is_valid_cholesky(&[vec![1.0, 1.0], vec![0.0, 1.0]]));
+    }
+
+    #[test]
+    fn test_symmetric_eigen() {
+        let a = vec![
+            vec![4.0, 2.0, 0.4],
+            vec![2.0, 5.0, 1.0],
+            vec![0.4, 1.0, 3.0],
+        ];
+        let (values, vectors) = symmetric_eigen(&a);
+        assert!(values[0] >= values[1] && values[1] >= values[2]);
+        assert_approx_eq!(values.iter().sum::<f64>(), 12.0);
+        for (value, vector) in values.iter().zip(&vectors) {
+            assert_approx_eq!(vector.iter().map(|x| x * x).sum::<f64>(), 1.0);
+            for i in 0..3 {
+                let av: f64 = (0..3).map(|j| a[i][j] * vector[j]).sum();
+                assert_approx_eq!(av, value * vector[i]);
+            }
+        }
+        let dot: f64 = vectors[0].iter().zip(&vectors[1]).map(|(x, y)| x * y).sum();
+        assert_approx_eq!(dot, 0.0);
+
+        // Already diagonal, with a repeated eigenvalue
+        let (values, vectors) = symmetric_eigen(&[vec![1.0, 0.0], vec![0.0, 1.0]]);
+        assert_eq!(values, vec![1.0, 1.0]);
+        assert_eq!(vectors, vec![vec![1.0, 0.0], vec![0.0, 1.0]]);
+    }
+
+    #[test]
+    fn test_mean_covariance() {
+        let samples = vec![
+            Guess::new(&[1.0, 2.0]),
+            Guess::new(&[3.0, 2.0]),
+            Guess::new(&[2.0, 5.0]),
+        ];
+        let (mean, cov) = mean_covariance(&samples);
+        assert_approx_eq!(mean[0], 2.0);
+        assert_approx_eq!(mean[1], 3.0);
+        assert_approx_eq!(cov[0][0], 1.0);
+        assert_approx_eq!(cov[1][1], 3.0);
+        assert_approx_eq!(cov[0][1], 0.0);
+    }
+}
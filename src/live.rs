@@ -0,0 +1,347 @@
+//! Read access to the chain while the sampler is running
+//!
+//! Stored iterations are gathered into fixed-size [`Block`](struct.Block.html)s. Once a block is
+//! complete it is never modified again, so it is shared with any number of
+//! [`ChainMonitor`](struct.ChainMonitor.html)s through an `Arc`. Publishing a block swaps the
+//! list of blocks in a single write, so a [`ChainSnapshot`](struct.ChainSnapshot.html) always
+//! sees a consistent prefix of the run.
+
+use std::sync::{Arc, RwLock};
+use guess::Guess;
+use stores::thumbnail_indices;
+
+/// A run of consecutive stored iterations
+///
+/// Values are laid out in the same order as the sampler's chain: iteration major, then walker,
+/// then parameter.
+#[derive(Debug, Clone)]
+pub struct Block {
+    /// Index of the first stored iteration contained in this block
+    pub start: usize,
+
+    nparams: usize,
+    nwalkers: usize,
+    niterations: usize,
+    positions: Vec<f64>,
+    lnprob: Vec<f64>,
+}
+
+impl Block {
+    pub(crate) fn new(start: usize, nparams: usize, nwalkers: usize, capacity: usize) -> Block {
+        Block {
+            start,
+            nparams,
+            nwalkers,
+            niterations: 0,
+            positions: Vec::with_capacity(capacity * nwalkers * nparams),
+            lnprob: Vec::with_capacity(capacity * nwalkers),
+        }
+    }
+
+    pub(crate) fn push(&mut self, pos: &[Guess], lnprob: &[f64]) {
+        assert_eq!(pos.len(), self.nwalkers);
+        assert_eq!(lnprob.len(), self.nwalkers);
+        for guess in pos {
+            assert_eq!(guess.values.len(), self.nparams);
+            self.positions.extend_from_slice(&guess.values);
+        }
+        self.lnprob.extend_from_slice(lnprob);
+        self.niterations += 1;
+    }
+
+    /// The number of iterations contained in this block
+    pub fn len(&self) -> usize {
+        self.niterations
+    }
+
+    /// Returns true if the block does not contain any iterations
+    pub fn is_empty(&self) -> bool {
+        self.niterations == 0
+    }
+
+    /// The number of walkers stored per iteration
+    pub fn nwalkers(&self) -> usize {
+        self.nwalkers
+    }
+
+    /// The number of parameters stored per walker
+    pub fn nparams(&self) -> usize {
+        self.nparams
+    }
+
+    /// Get a single parameter value, indexed relative to the start of the block
+    pub fn get(&self, param_idx: usize, walker_idx: usize, iteration_idx: usize) -> f64 {
+        assert!(param_idx < self.nparams);
+        assert!(walker_idx < self.nwalkers);
+        assert!(iteration_idx < self.niterations);
+
+        self.positions[(iteration_idx * self.nwalkers + walker_idx) * self.nparams + param_idx]
+    }
+
+    /// Get a log posterior probability, indexed relative to the start of the block
+    pub fn lnprob(&self, walker_idx: usize, iteration_idx: usize) -> f64 {
+        assert!(walker_idx < self.nwalkers);
+        assert!(iteration_idx < self.niterations);
+
+        self.lnprob[iteration_idx * self.nwalkers + walker_idx]
+    }
+
+    /// Get the position of a walker, indexed relative to the start of the block
+    pub fn position(&self, walker_idx: usize, iteration_idx: usize) -> Guess {
+        assert!(walker_idx < self.nwalkers);
+        assert!(iteration_idx < self.niterations);
+
+        let offset = (iteration_idx * self.nwalkers + walker_idx) * self.nparams;
+        Guess::new(&self.positions[offset..offset + self.nparams])
+    }
+}
+
+/// Consistent, immutable view of the chain at the time it was taken
+#[derive(Debug, Clone, Default)]
+pub struct ChainSnapshot {
+    blocks: Vec<Arc<Block>>,
+}
+
+impl ChainSnapshot {
+    /// The completed blocks, in iteration order
+    pub fn blocks(&self) -> &[Arc<Block>] {
+        &self.blocks
+    }
+
+    /// The number of stored iterations contained in the snapshot
+    pub fn niterations(&self) -> usize {
+        self.blocks.iter().map(|block| block.len()).sum()
+    }
+
+    /// Get a single parameter value
+    pub fn get(&self, param_idx: usize, walker_idx: usize, iteration_idx: usize) -> f64 {
+        let (block, offset) = self.locate(iteration_idx);
+        block.get(param_idx, walker_idx, offset)
+    }
+
+    /// Get a single log posterior probability
+    pub fn lnprob(&self, walker_idx: usize, iteration_idx: usize) -> f64 {
+        let (block, offset) = self.locate(iteration_idx);
+        block.lnprob(walker_idx, offset)
+    }
+
+    /// Return the samples contained in the snapshot, in the same order as
+    /// [`EnsembleSampler::flatchain`](struct.EnsembleSampler.html#method.flatchain)
+    pub fn flatchain(&self) -> Vec<Guess> {
+        let mut out = Vec::with_capacity(self.niterations() * self.nwalkers());
+        for block in &self.blocks {
+            for iter in 0..block.len() {
+                for walker in 0..block.nwalkers() {
+                    out.push(block.position(walker, iter));
+                }
+            }
+        }
+        out
+    }
+
+    /// Return the log posterior probabilities contained in the snapshot
+    pub fn flatprob(&self) -> Vec<f64> {
+        self.blocks
+            .iter()
+            .flat_map(|block| block.lnprob.iter().cloned())
+            .collect()
+    }
+
+    /// Return at most `max_samples` samples spread uniformly over the snapshot, chosen as by
+    /// [`Chain::thumbnail`](struct.Chain.html#method.thumbnail)
+    pub fn thumbnail(&self, max_samples: usize) -> Vec<Guess> {
+        let (iterations, walkers) =
+            thumbnail_indices(self.niterations(), self.nwalkers(), max_samples);
+        let mut out = Vec::with_capacity(iterations.len() * walkers.len());
+        for &iter in &iterations {
+            let (block, offset) = self.locate(iter);
+            for &walker in &walkers {
+                out.push(block.position(walker, offset));
+            }
+        }
+        out
+    }
+
+    fn nwalkers(&self) -> usize {
+        self.blocks.first().map_or(0, |block| block.nwalkers())
+    }
+
+    fn locate(&self, iteration_idx: usize) -> (&Block, usize) {
+        let block_idx = match self.blocks
+            .binary_search_by(|block| block.start.cmp(&iteration_idx))
+        {
+            Ok(idx) => idx,
+            Err(idx) => {
+                assert!(idx > 0, "iteration index {} is not in the snapshot", iteration_idx);
+                idx - 1
+            }
+        };
+        let block = &self.blocks[block_idx];
+        let offset = iteration_idx - block.start;
+        assert!(
+            offset < block.len(),
+            "iteration index {} is not in the snapshot",
+            iteration_idx
+        );
+        (block, offset)
+    }
+}
+
+/// Handle for reading the chain from another thread while sampling proceeds
+///
+/// Created by [`EnsembleSampler::monitor`](struct.EnsembleSampler.html#method.monitor). The
+/// handle is cheap to clone and can be sent to other threads.
+#[derive(Debug, Clone, Default)]
+pub struct ChainMonitor {
+    published: Arc<RwLock<Arc<Vec<Arc<Block>>>>>,
+}
+
+impl ChainMonitor {
+    /// Take a snapshot of all blocks published so far
+    ///
+    /// The read lock is only held long enough to clone a reference counted pointer, so taking a
+    /// snapshot never stalls the sampler for longer than that.
+    pub fn snapshot(&self) -> ChainSnapshot {
+        let blocks = self.published
+            .read()
+            .expect("chain monitor lock poisoned")
+            .clone();
+        ChainSnapshot {
+            blocks: blocks.to_vec(),
+        }
+    }
+
+    fn publish(&self, block: Block) {
+        let mut published = self.published.write().expect("chain monitor lock poisoned");
+        let mut blocks = published.to_vec();
+        blocks.push(Arc::new(block));
+        *published = Arc::new(blocks);
+    }
+
+    fn clear(&self) {
+        *self.published.write().expect("chain monitor lock poisoned") = Arc::new(Vec::new());
+    }
+}
+
+/// Sampler side of a [`ChainMonitor`](struct.ChainMonitor.html)
+#[derive(Debug)]
+pub(crate) struct LiveChain {
+    monitor: ChainMonitor,
+    block_size: usize,
+    current: Block,
+}
+
+impl LiveChain {
+    pub(crate) fn new(nparams: usize, nwalkers: usize, block_size: usize) -> LiveChain {
+        assert!(block_size > 0, "block size must be positive");
+        LiveChain {
+            monitor: ChainMonitor::default(),
+            block_size,
+            current: Block::new(0, nparams, nwalkers, block_size),
+        }
+    }
+
+    pub(crate) fn monitor(&self) -> ChainMonitor {
+        self.monitor.clone()
+    }
+
+    /// Discard everything published so far, ready for a new run
+    pub(crate) fn clear(&mut self) {
+        self.monitor.clear();
+        self.current = Block::new(0, self.current.nparams, self.current.nwalkers, self.block_size);
+    }
+
+    /// Add the next stored iteration, publishing the current block if it is full
+    pub(crate) fn push(&mut self, pos: &[Guess], lnprob: &[f64]) {
+        self.current.push(pos, lnprob);
+        if self.current.len() == self.block_size {
+            self.flush();
+        }
+    }
+
+    /// Publish the current block, even if it is not full
+    pub(crate) fn flush(&mut self) {
+        if self.current.is_empty() {
+            return;
+        }
+
+        let start = self.current.start + self.current.len();
+        let next = Block::new(start, self.current.nparams, self.current.nwalkers, self.block_size);
+        let block = ::std::mem::replace(&mut self.current, next);
+        self.monitor.publish(block);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn positions(iteration: usize) -> Vec<Guess> {
+        (0..3)
+            .map(|walker| Guess::new(&[iteration as f64, walker as f64]))
+            .collect()
+    }
+
+    #[test]
+    fn test_blocks_are_published_when_full() {
+        let mut live = LiveChain::new(2, 3, 4);
+        let monitor = live.monitor();
+
+        for iteration in 0..6 {
+            live.push(&positions(iteration), &[0.0, 1.0, 2.0]);
+        }
+
+        let snapshot = monitor.snapshot();
+        assert_eq!(snapshot.blocks().len(), 1);
+        assert_eq!(snapshot.niterations(), 4);
+
+        live.flush();
+        let snapshot = monitor.snapshot();
+        assert_eq!(snapshot.blocks().len(), 2);
+        assert_eq!(snapshot.niterations(), 6);
+        assert_eq!(snapshot.blocks()[1].start, 4);
+    }
+
+    #[test]
+    fn test_snapshot_indexing() {
+        let mut live = LiveChain::new(2, 3, 4);
+        let monitor = live.monitor();
+        for iteration in 0..10 {
+            live.push(&positions(iteration), &[0.0, 1.0, iteration as f64]);
+        }
+        live.flush();
+
+        let snapshot = monitor.snapshot();
+        assert_eq!(snapshot.get(0, 1, 7), 7.0);
+        assert_eq!(snapshot.get(1, 2, 7), 2.0);
+        assert_eq!(snapshot.lnprob(2, 9), 9.0);
+
+        let flatchain = snapshot.flatchain();
+        assert_eq!(flatchain.len(), 30);
+        assert_eq!(flatchain[4][0], 1.0);
+        assert_eq!(flatchain[4][1], 1.0);
+        assert_eq!(snapshot.flatprob().len(), 30);
+
+        let thumbnail = snapshot.thumbnail(12);
+        assert_eq!(thumbnail.len(), 12);
+        assert_eq!(thumbnail[0].values, vec![0.0, 0.0]);
+        assert_eq!(thumbnail[3].values, vec![3.0, 0.0]);
+        assert_eq!(thumbnail[11].values, vec![9.0, 2.0]);
+    }
+
+    #[test]
+    fn test_snapshots_are_unaffected_by_later_blocks() {
+        let mut live = LiveChain::new(2, 3, 1);
+        let monitor = live.monitor();
+        live.push(&positions(0), &[0.0, 0.0, 0.0]);
+        let before = monitor.snapshot();
+
+        live.push(&positions(1), &[0.0, 0.0, 0.0]);
+        assert_eq!(before.niterations(), 1);
+        assert_eq!(monitor.snapshot().niterations(), 2);
+
+        live.clear();
+        assert_eq!(before.niterations(), 1);
+        assert_eq!(monitor.snapshot().niterations(), 0);
+    }
+}
@@ -0,0 +1,223 @@
+//! Numerically stable arithmetic in log space
+//!
+//! Log probabilities are often so large in magnitude that their exponentials overflow or
+//! underflow, e.g. `exp(-800)` is zero as an `f64`. These functions combine values through
+//! their logs without ever forming the exponentials directly, as needed e.g. to marginalise
+//! over a discrete latent variable in a likelihood:
+//!
+//! ```rust
+//! # use emcee::math;
+//! // ln(0.3 exp(-1000) + 0.7 exp(-1001)), which is -inf if computed naively
+//! let lnlike = math::logaddexp(0.3f64.ln() - 1000.0, 0.7f64.ln() - 1001.0);
+//! assert!((lnlike - (-1000.0 + (0.3 + 0.7 * (-1f64).exp()).ln())).abs() < 1e-12);
+//! ```
+
+use std::iter::FromIterator;
+
+/// `ln(exp(a) + exp(b))`
+pub fn logaddexp(a: f64, b: f64) -> f64 {
+    let max = a.max(b);
+    if max.is_infinite() {
+        return max;
+    }
+    max + (-(a - b).abs()).exp().ln_1p()
+}
+
+/// `ln(sum(exp(values)))`, or negative infinity if `values` is empty
+pub fn logsumexp(values: &[f64]) -> f64 {
+    let max = values.iter().cloned().fold(-f64::INFINITY, f64::max);
+    if max.is_infinite() {
+        return max;
+    }
+    max + values.iter().map(|x| (x - max).exp()).sum::<f64>().ln()
+}
+
+/// `ln(mean(exp(values)))`, or NaN if `values` is empty
+pub fn logmeanexp(values: &[f64]) -> f64 {
+    if values.is_empty() {
+        return f64::NAN;
+    }
+    logsumexp(values) - (values.len() as f64).ln()
+}
+
+/// `ln(1 - exp(x))` for `x <= 0`, accurate both near zero and for large negative `x`
+///
+/// Returns NaN for positive `x` (Mächler 2012).
+pub fn log1mexp(x: f64) -> f64 {
+    if x > 0.0 {
+        f64::NAN
+    } else if x > -::std::f64::consts::LN_2 {
+        (-x.exp_m1()).ln()
+    } else {
+        (-x.exp()).ln_1p()
+    }
+}
+
+/// `ln(exp(a) - exp(b))` for `a >= b`
+///
+/// Returns NaN if `a < b`.
+pub fn logsubexp(a: f64, b: f64) -> f64 {
+    if b == -f64::INFINITY {
+        a
+    } else {
+        a + log1mexp(b - a)
+    }
+}
+
+/// Normalised weights `exp(ln_weights) / sum(exp(ln_weights))`, e.g. importance weights
+///
+/// The result is all NaN if every weight is zero.
+pub fn softmax(ln_weights: &[f64]) -> Vec<f64> {
+    let total = logsumexp(ln_weights);
+    ln_weights.iter().map(|x| (x - total).exp()).collect()
+}
+
+/// A running sum of values which are only known through their logs
+///
+/// Values are added one at a time with [`add`](#method.add), or collected from an iterator,
+/// and the log of their sum or mean is available at any point. This is useful when the values
+/// are produced one by one, e.g. importance weights computed during a run, and storing them all
+/// for [`logsumexp`](fn.logsumexp.html) is not wanted.
+///
+/// ```rust
+/// # use emcee::math::LogSum;
+/// let sum: LogSum = vec![-1000.0, -1000.0].into_iter().collect();
+/// assert!((sum.ln_sum() - (-1000.0 + 2f64.ln())).abs() < 1e-12);
+/// assert_eq!(sum.ln_mean(), -1000.0);
+/// ```
+#[derive(Debug, Clone, Copy)]
+pub struct LogSum {
+    max: f64,
+    scaled: f64,
+    count: usize,
+}
+
+impl LogSum {
+    /// An empty sum
+    pub fn new() -> LogSum {
+        LogSum {
+            max: -f64::INFINITY,
+            scaled: 0.0,
+            count: 0,
+        }
+    }
+
+    /// Add a value given by its natural log
+    pub fn add(&mut self, ln_value: f64) {
+        self.count += 1;
+        if ln_value > self.max {
+            self.scaled = self.scaled * (self.max - ln_value).exp() + 1.0;
+            self.max = ln_value;
+        } else if ln_value == self.max {
+            self.scaled += 1.0;
+        } else {
+            self.scaled += (ln_value - self.max).exp();
+        }
+    }
+
+    /// The number of values added
+    pub fn count(&self) -> usize {
+        self.count
+    }
+
+    /// The log of the sum of the values, or negative infinity if none were added
+    pub fn ln_sum(&self) -> f64 {
+        if self.max.is_infinite() {
+            return self.max;
+        }
+        self.max + self.scaled.ln()
+    }
+
+    /// The log of the mean of the values, or NaN if none were added
+    pub fn ln_mean(&self) -> f64 {
+        if self.count == 0 {
+            return f64::NAN;
+        }
+        self.ln_sum() - (self.count as f64).ln()
+    }
+}
+
+impl Default for LogSum {
+    fn default() -> Self {
+        LogSum::new()
+    }
+}
+
+impl Extend<f64> for LogSum {
+    fn extend<I: IntoIterator<Item = f64>>(&mut self, iter: I) {
+        for ln_value in iter {
+            self.add(ln_value);
+        }
+    }
+}
+
+impl FromIterator<f64> for LogSum {
+    fn from_iter<I: IntoIterator<Item = f64>>(iter: I) -> Self {
+        let mut sum = LogSum::new();
+        sum.extend(iter);
+        sum
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sums() {
+        let values = [0.5f64, 2.0, 0.25];
+        let ln_values: Vec<f64> = values.iter().map(|x| x.ln()).collect();
+        assert_approx_eq!(logsumexp(&ln_values), 2.75f64.ln());
+        assert_approx_eq!(logmeanexp(&ln_values), (2.75f64 / 3.0).ln());
+        assert_approx_eq!(logaddexp(0.5f64.ln(), 2f64.ln()), 2.5f64.ln());
+
+        // Far outside the range of exp
+        let shifted: Vec<f64> = ln_values.iter().map(|x| x - 1000.0).collect();
+        assert_approx_eq!(logsumexp(&shifted), 2.75f64.ln() - 1000.0);
+        assert_approx_eq!(logaddexp(shifted[0], shifted[1]), 2.5f64.ln() - 1000.0);
+        let sum: LogSum = shifted.iter().cloned().collect();
+        assert_approx_eq!(sum.ln_sum(), 2.75f64.ln() - 1000.0);
+        assert_approx_eq!(sum.ln_mean(), (2.75f64 / 3.0).ln() - 1000.0);
+        assert_eq!(sum.count(), 3);
+
+        // Zeros and infinities
+        let inf = f64::INFINITY;
+        assert_eq!(logsumexp(&[]), -inf);
+        assert_eq!(logsumexp(&[-inf, -inf]), -inf);
+        assert_eq!(logsumexp(&[-inf, 1.0]), 1.0);
+        assert_eq!(logsumexp(&[inf, 1.0]), inf);
+        assert_eq!(logaddexp(-inf, -inf), -inf);
+        assert_eq!(logaddexp(-inf, 2.0), 2.0);
+        assert!(logmeanexp(&[]).is_nan());
+        let mut sum = LogSum::new();
+        assert_eq!(sum.ln_sum(), -inf);
+        sum.extend(vec![-inf, -inf]);
+        assert_eq!(sum.ln_sum(), -inf);
+        sum.add(0.0);
+        assert_eq!(sum.ln_sum(), 0.0);
+        assert_approx_eq!(sum.ln_mean(), -(3f64.ln()));
+    }
+
+    #[test]
+    fn test_differences() {
+        assert_approx_eq!(log1mexp(0.5f64.ln()), 0.5f64.ln());
+        // Near zero 1 - exp(x) ~ -x
+        assert_approx_eq!(log1mexp(-1e-20), (1e-20f64).ln());
+        assert_eq!(log1mexp(-1e3), 0.0);
+        assert_eq!(log1mexp(0.0), -f64::INFINITY);
+        assert!(log1mexp(0.1).is_nan());
+
+        assert_approx_eq!(logsubexp(3f64.ln(), 1f64.ln()), 2f64.ln());
+        assert_approx_eq!(logsubexp(3f64.ln() - 900.0, -900.0), 2f64.ln() - 900.0);
+        assert_eq!(logsubexp(1.0, -f64::INFINITY), 1.0);
+        assert!(logsubexp(0.0, 1.0).is_nan());
+    }
+
+    #[test]
+    fn test_softmax() {
+        let weights = softmax(&[-1000.0, -1000.0 + 3f64.ln()]);
+        assert_approx_eq!(weights[0], 0.25);
+        assert_approx_eq!(weights[1], 0.75);
+        assert_eq!(softmax(&[0.0, -f64::INFINITY]), vec![1.0, 0.0]);
+    }
+}
@@ -0,0 +1,199 @@
+//! MATLAB `.mat` export
+//!
+//! Enabled with the `matlab` feature. Files are written in the Level 5 MAT-file format, which
+//! every MATLAB release since 5.0 (and GNU Octave, and `scipy.io.loadmat`) can read. Only real
+//! double precision arrays are supported, which is all the sampler produces.
+
+use std::io::Write;
+
+use errors::{EmceeError, Result};
+use {EnsembleSampler, Prob};
+
+const MI_INT8: u32 = 1;
+const MI_INT32: u32 = 5;
+const MI_UINT32: u32 = 6;
+const MI_DOUBLE: u32 = 9;
+const MI_MATRIX: u32 = 14;
+const MX_DOUBLE_CLASS: u32 = 6;
+
+/// Writes named arrays to a Level 5 MAT-file
+pub struct MatWriter<W: Write> {
+    writer: W,
+}
+
+impl<W: Write> MatWriter<W> {
+    /// Start a MAT-file by writing its header
+    pub fn new(mut writer: W) -> Result<Self> {
+        let mut header = [b' '; 128];
+        let text = b"MATLAB 5.0 MAT-file, Platform: rust-emcee";
+        header[..text.len()].copy_from_slice(text);
+        // No subsystem data
+        for byte in &mut header[116..124] {
+            *byte = 0;
+        }
+        header[124..126].copy_from_slice(&0x0100u16.to_le_bytes());
+        header[126..128].copy_from_slice(b"IM");
+        writer.write_all(&header)?;
+        Ok(MatWriter { writer })
+    }
+
+    /// Write a real double precision array
+    ///
+    /// `data` must be in column-major (Fortran) order, as used by MATLAB, and contain the
+    /// product of `dims` values.
+    pub fn write_array(&mut self, name: &str, dims: &[usize], data: &[f64]) -> Result<()> {
+        if dims.iter().product::<usize>() != data.len() {
+            return Err(EmceeError::InvalidInputs(format!(
+                "array `{}` has dimensions {:?} but {} values",
+                name,
+                dims,
+                data.len()
+            )));
+        }
+
+        if name.is_empty() || !name.is_ascii() {
+            return Err(EmceeError::InvalidInputs(format!(
+                "invalid MATLAB variable name `{}`",
+                name
+            )));
+        }
+
+        let mut flags = Vec::with_capacity(8);
+        flags.extend_from_slice(&MX_DOUBLE_CLASS.to_le_bytes());
+        flags.extend_from_slice(&0u32.to_le_bytes());
+
+        let mut dimensions = Vec::with_capacity(4 * dims.len().max(2));
+        for dim in dims {
+            dimensions.extend_from_slice(&(*dim as i32).to_le_bytes());
+        }
+        if dims.len() < 2 {
+            // MATLAB arrays always have at least two dimensions
+            for _ in dims.len()..2 {
+                dimensions.extend_from_slice(&1i32.to_le_bytes());
+            }
+        }
+
+        let mut values = Vec::with_capacity(8 * data.len());
+        for value in data {
+            values.extend_from_slice(&value.to_le_bytes());
+        }
+
+        let mut body = Vec::new();
+        element(&mut body, MI_UINT32, &flags);
+        element(&mut body, MI_INT32, &dimensions);
+        element(&mut body, MI_INT8, name.as_bytes());
+        element(&mut body, MI_DOUBLE, &values);
+
+        let mut out = Vec::with_capacity(body.len() + 8);
+        element(&mut out, MI_MATRIX, &body);
+        self.writer.write_all(&out)?;
+        Ok(())
+    }
+
+    /// Return the underlying writer
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+}
+
+/// Append a data element (tag, data and padding to a multiple of 8 bytes)
+fn element(out: &mut Vec<u8>, data_type: u32, data: &[u8]) {
+    out.extend_from_slice(&data_type.to_le_bytes());
+    out.extend_from_slice(&(data.len() as u32).to_le_bytes());
+    out.extend_from_slice(data);
+    let padding = (8 - data.len() % 8) % 8;
+    let len = out.len();
+    out.resize(len + padding, 0);
+}
+
+impl<'a, T: Prob + Sync + Send + 'a> EnsembleSampler<'a, T> {
+    /// Write the stored chain to a MATLAB `.mat` file
+    ///
+    /// The file contains `chain`, an iterations x walkers x parameters array, `lnprob`, an
+    /// iterations x walkers array, and `acceptance_fraction` with one value per walker.
+    ///
+    /// Errors are handled by returning a [`Result`](errors/type.Result.html) which contains
+    /// [`EmceeError::Msg`](errors/enum.EmceeError.html) if the chain has not been stored, or
+    /// [`EmceeError::Io`](errors/enum.EmceeError.html) if writing fails.
+    pub fn write_mat<W: Write>(&self, writer: W) -> Result<()> {
+        let (chain, probstore) = match (self.chain.as_ref(), self.probstore.as_ref()) {
+            (Some(chain), Some(probstore)) => (chain, probstore),
+            _ => return Err("the chain has not been stored".into()),
+        };
+
+        let (niterations, nwalkers, nparams) = (chain.niterations, chain.nwalkers, chain.nparams);
+        let mut positions = vec![0f64; niterations * nwalkers * nparams];
+        let mut lnprob = vec![0f64; niterations * nwalkers];
+        for iter in 0..niterations {
+            for walker in 0..nwalkers {
+                for param in 0..nparams {
+                    positions[iter + niterations * (walker + nwalkers * param)] =
+                        chain.get(param, walker, iter);
+                }
+                lnprob[iter + niterations * walker] = probstore.get(walker, iter);
+            }
+        }
+
+        let mut writer = MatWriter::new(writer)?;
+        writer.write_array("chain", &[niterations, nwalkers, nparams], &positions)?;
+        writer.write_array("lnprob", &[niterations, nwalkers], &lnprob)?;
+        let acceptance = self.acceptance_fraction();
+        writer.write_array("acceptance_fraction", &[acceptance.len(), 1], &acceptance)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn u32_at(bytes: &[u8], offset: usize) -> u32 {
+        let mut buf = [0u8; 4];
+        buf.copy_from_slice(&bytes[offset..offset + 4]);
+        u32::from_le_bytes(buf)
+    }
+
+    #[test]
+    fn test_header() {
+        let bytes = MatWriter::new(Vec::new()).unwrap().into_inner();
+        assert_eq!(bytes.len(), 128);
+        assert!(bytes.starts_with(b"MATLAB 5.0 MAT-file"));
+        assert_eq!(&bytes[124..128], &[0x00, 0x01, b'I', b'M']);
+    }
+
+    #[test]
+    fn test_array_layout() {
+        let mut writer = MatWriter::new(Vec::new()).unwrap();
+        writer
+            .write_array("x", &[2, 3], &[1.0, 2.0, 3.0, 4.0, 5.0, 6.0])
+            .unwrap();
+        let bytes = writer.into_inner();
+        let body = &bytes[128..];
+
+        assert_eq!(u32_at(body, 0), MI_MATRIX);
+        assert_eq!(u32_at(body, 4) as usize, body.len() - 8);
+
+        // array flags, dimensions, name, then the real part
+        assert_eq!(u32_at(body, 8), MI_UINT32);
+        assert_eq!(u32_at(body, 16), MX_DOUBLE_CLASS);
+        assert_eq!(u32_at(body, 24), MI_INT32);
+        assert_eq!(u32_at(body, 32), 2);
+        assert_eq!(u32_at(body, 36), 3);
+        assert_eq!(u32_at(body, 40), MI_INT8);
+        assert_eq!(u32_at(body, 44), 1);
+        assert_eq!(body[48], b'x');
+        assert_eq!(u32_at(body, 56), MI_DOUBLE);
+        assert_eq!(u32_at(body, 60), 48);
+        let mut buf = [0u8; 8];
+        buf.copy_from_slice(&body[64 + 8 * 5..64 + 8 * 6]);
+        assert_eq!(f64::from_le_bytes(buf), 6.0);
+        assert_eq!(body.len(), 64 + 48);
+    }
+
+    #[test]
+    fn test_invalid_arrays() {
+        let mut writer = MatWriter::new(Vec::new()).unwrap();
+        assert!(writer.write_array("x", &[2, 2], &[1.0]).is_err());
+        assert!(writer.write_array("", &[1], &[1.0]).is_err());
+    }
+}
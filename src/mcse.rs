@@ -0,0 +1,248 @@
+//! Monte Carlo standard errors
+//!
+//! Posterior summaries estimated from a chain are themselves uncertain, because the chain is
+//! finite and its samples are correlated. The batch means estimators split a series into
+//! batches long compared to its autocorrelation time, so the batch means are nearly
+//! independent and their spread gives the variance of the overall mean (Flegal & Jones 2010).
+//! Overlapping batch means use every batch start rather than only multiples of the batch size,
+//! which gives a less noisy estimate for the same series.
+//!
+//! For a chain, the ensemble mean at each iteration is used as the series, which accounts for
+//! any correlations between the walkers. The error of a quantile is found by estimating the
+//! error of the fraction of samples below it, and mapping that interval back through the
+//! empirical quantile function, so no density estimate is needed. The parameters of a large
+//! chain are shared out between every available core.
+
+use errors::{EmceeError, Result};
+use parallel;
+use stores::Chain;
+
+/// The batch size used by the [`Chain`](../struct.Chain.html) methods for a series of `n`
+/// values, `floor(sqrt(n))`
+pub fn default_batch_size(n: usize) -> usize {
+    (n as f64).sqrt() as usize
+}
+
+/// Estimate the Monte Carlo standard error of the mean of `series` using non-overlapping
+/// batches of `batch_size` values
+///
+/// Any values which do not fill a batch are dropped from the start of the series.
+///
+/// Errors are handled by returning a [`Result`](../errors/type.Result.html) which contains
+/// [`EmceeError::InvalidInputs`](../errors/enum.EmceeError.html) if `batch_size` is zero or the
+/// series does not contain at least two batches.
+pub fn batch_means(series: &[f64], batch_size: usize) -> Result<f64> {
+    check_batches(series.len(), batch_size)?;
+
+    let nbatches = series.len() / batch_size;
+    let used = &series[series.len() - nbatches * batch_size..];
+    let mean = used.iter().sum::<f64>() / used.len() as f64;
+    let sum_squares: f64 = used
+        .chunks(batch_size)
+        .map(|batch| (batch.iter().sum::<f64>() / batch_size as f64 - mean).powi(2))
+        .sum();
+    let variance = batch_size as f64 * sum_squares / (nbatches - 1) as f64;
+    Ok((variance / used.len() as f64).sqrt())
+}
+
+/// Estimate the Monte Carlo standard error of the mean of `series` using overlapping batches
+/// of `batch_size` values
+///
+/// Errors are handled by returning a [`Result`](../errors/type.Result.html) which contains
+/// [`EmceeError::InvalidInputs`](../errors/enum.EmceeError.html) if `batch_size` is zero or the
+/// series does not contain at least two batches.
+pub fn overlapping_batch_means(series: &[f64], batch_size: usize) -> Result<f64> {
+    check_batches(series.len(), batch_size)?;
+
+    let n = series.len();
+    let mean = series.iter().sum::<f64>() / n as f64;
+    let mut window: f64 = series[..batch_size].iter().sum();
+    let mut sum_squares = (window / batch_size as f64 - mean).powi(2);
+    for start in 1..(n - batch_size + 1) {
+        window += series[start + batch_size - 1] - series[start - 1];
+        sum_squares += (window / batch_size as f64 - mean).powi(2);
+    }
+    let variance = (n * batch_size) as f64 * sum_squares
+        / ((n - batch_size) * (n - batch_size + 1)) as f64;
+    Ok((variance / n as f64).sqrt())
+}
+
+fn check_batches(n: usize, batch_size: usize) -> Result<()> {
+    if batch_size == 0 {
+        return Err(EmceeError::InvalidInputs(
+            "the batch size must be positive".into(),
+        ));
+    }
+    if n < 2 * batch_size {
+        return Err(EmceeError::InvalidInputs(format!(
+            "{} values do not fill two batches of {}",
+            n, batch_size
+        )));
+    }
+    Ok(())
+}
+
+impl Chain {
+    /// Estimate the Monte Carlo standard error of the posterior mean of each parameter
+    ///
+    /// Overlapping batch means are applied to the ensemble mean of each parameter, with
+    /// [`default_batch_size`](mcse/fn.default_batch_size.html) iterations per batch. See the
+    /// [`mcse`](mcse/index.html) module for details.
+    ///
+    /// Errors are handled by returning a [`Result`](errors/type.Result.html) which contains
+    /// [`EmceeError::InvalidInputs`](errors/enum.EmceeError.html) if the chain has fewer than
+    /// two iterations.
+    pub fn mcse_mean(&self) -> Result<Vec<f64>> {
+        let batch_size = default_batch_size(self.niterations);
+        parallel::map(self.nparams, self.nthreads(), |param| {
+            let series: Vec<f64> = (0..self.niterations)
+                .map(|iter| self.fraction_or_mean(param, iter, None))
+                .collect();
+            overlapping_batch_means(&series, batch_size)
+        })
+        .into_iter()
+        .collect()
+    }
+
+    /// Estimate the Monte Carlo standard error of the `q` quantile of each parameter, for `q`
+    /// between 0 and 1
+    ///
+    /// The error of the fraction of samples below the estimated quantile is found with
+    /// overlapping batch means, and half the width of the corresponding interval of empirical
+    /// quantiles is returned. See the [`mcse`](mcse/index.html) module for details.
+    ///
+    /// Errors are handled by returning a [`Result`](errors/type.Result.html) which contains
+    /// [`EmceeError::InvalidInputs`](errors/enum.EmceeError.html) if `q` is not between 0 and
+    /// 1, or the chain has fewer than two iterations.
+    pub fn mcse_quantile(&self, q: f64) -> Result<Vec<f64>> {
+        if !(0.0..=1.0).contains(&q) {
+            return Err(EmceeError::InvalidInputs(format!(
+                "the quantile {} is not between 0 and 1",
+                q
+            )));
+        }
+
+        let batch_size = default_batch_size(self.niterations);
+        parallel::map(self.nparams, self.nthreads(), |param| {
+            let mut sorted: Vec<f64> = (0..self.niterations)
+                .flat_map(|iter| (0..self.nwalkers).map(move |walker| (iter, walker)))
+                .map(|(iter, walker)| self.get(param, walker, iter))
+                .collect();
+            sorted.sort_by(|a, b| a.partial_cmp(b).unwrap_or(::std::cmp::Ordering::Equal));
+            let quantile = |q: f64| {
+                let idx = (q.clamp(0.0, 1.0) * (sorted.len() - 1) as f64).round() as usize;
+                sorted[idx]
+            };
+
+            let threshold = Some(quantile(q));
+            let series: Vec<f64> = (0..self.niterations)
+                .map(|iter| self.fraction_or_mean(param, iter, threshold))
+                .collect();
+            let error = overlapping_batch_means(&series, batch_size)?;
+            Ok((quantile(q + error) - quantile(q - error)) / 2.0)
+        })
+        .into_iter()
+        .collect()
+    }
+
+    /// The number of threads sharing out the parameters
+    fn nthreads(&self) -> usize {
+        parallel::nthreads(self.nparams * self.nwalkers * self.niterations)
+    }
+
+    /// The ensemble mean of a parameter at one iteration, or the fraction of walkers at or
+    /// below `threshold`
+    fn fraction_or_mean(&self, param: usize, iter: usize, threshold: Option<f64>) -> f64 {
+        let total: f64 = (0..self.nwalkers)
+            .map(|walker| {
+                let value = self.get(param, walker, iter);
+                match threshold {
+                    Some(threshold) if value <= threshold => 1.0,
+                    Some(_) => 0.0,
+                    None => value,
+                }
+            })
+            .sum();
+        total / self.nwalkers as f64
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::{SeedableRng, StdRng};
+    use rand::distributions::{IndependentSample, Normal};
+
+    /// An AR(1) series with unit marginal variance
+    fn ar1(n: usize, rho: f64, seed: usize) -> Vec<f64> {
+        let mut rng = StdRng::from_seed(&[seed]);
+        let normal = Normal::new(0.0, (1.0 - rho * rho).sqrt());
+        let mut x = 0f64;
+        (0..n)
+            .map(|_| {
+                x = rho * x + normal.ind_sample(&mut rng);
+                x
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_batch_means() {
+        // The asymptotic standard error of the mean of an AR(1) series is
+        // sqrt((1 + rho) / (1 - rho) / n)
+        let n = 100_000;
+        let rho = 0.9;
+        let expected = ((1.0 + rho) / (1.0 - rho) / n as f64).sqrt();
+        let series = ar1(n, rho, 1);
+        let batch_size = default_batch_size(n);
+        assert_approx_eq!(batch_means(&series, batch_size).unwrap(), expected, 0.2 * expected);
+        assert_approx_eq!(
+            overlapping_batch_means(&series, batch_size).unwrap(),
+            expected,
+            0.15 * expected
+        );
+
+        // Independent values
+        let series = ar1(n, 0.0, 2);
+        let expected = (1.0 / n as f64).sqrt();
+        assert_approx_eq!(
+            overlapping_batch_means(&series, batch_size).unwrap(),
+            expected,
+            0.15 * expected
+        );
+
+        assert!(batch_means(&series, 0).is_err());
+        assert!(overlapping_batch_means(&series[..10], 6).is_err());
+        assert_eq!(overlapping_batch_means(&[1.0; 10], 3).unwrap(), 0.0);
+    }
+
+    fn ar1_chain(nwalkers: usize, niterations: usize, rho: f64) -> Chain {
+        let mut chain = Chain::new(1, nwalkers, niterations);
+        for walker in 0..nwalkers {
+            for (iter, value) in ar1(niterations, rho, 10 + walker).into_iter().enumerate() {
+                chain.set(0, walker, iter, value);
+            }
+        }
+        chain
+    }
+
+    #[test]
+    fn test_chain_errors() {
+        // Independent walkers, so the error falls with the total number of samples
+        let (nwalkers, niterations) = (4, 20_000);
+        let nsamples = (nwalkers * niterations) as f64;
+        let rho = 0.8;
+        let chain = ar1_chain(nwalkers, niterations, rho);
+        let expected = ((1.0 + rho) / (1.0 - rho) / nsamples).sqrt();
+        assert_approx_eq!(chain.mcse_mean().unwrap()[0], expected, 0.2 * expected);
+
+        // For independent unit Gaussian samples the error of the median is sqrt(pi / 2) times
+        // that of the mean
+        let chain = ar1_chain(nwalkers, niterations, 0.0);
+        let expected = (::std::f64::consts::PI / 2.0 / nsamples).sqrt();
+        assert_approx_eq!(chain.mcse_quantile(0.5).unwrap()[0], expected, 0.25 * expected);
+
+        assert!(chain.mcse_quantile(1.5).is_err());
+        assert!(Chain::new(1, nwalkers, 1).mcse_mean().is_err());
+    }
+}
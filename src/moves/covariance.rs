@@ -0,0 +1,238 @@
+use std::collections::VecDeque;
+
+use rand::Rng;
+use rand::distributions::{IndependentSample, Normal};
+
+use guess::Guess;
+use linalg::{self, Matrix};
+use super::{Move, Proposal};
+
+/// Independence proposals from a Gaussian fitted to the recent ensemble history
+///
+/// Every time a proposal is made, the positions of the complementary ensemble are added to a
+/// rolling history of the most recent `history_len` positions. Every `refit_interval` proposals
+/// a multivariate Gaussian is fitted to this history, and new positions are drawn from it
+/// independently of the walkers' current positions, with the acceptance factor
+/// `ln N(x | mu, cov) - ln N(x' | mu, cov)`.
+///
+/// The move is adaptive: each step leaves the posterior invariant for the current fit, but
+/// while the fit follows the history the chain is only approximately stationary. For
+/// production runs, stop the adaptation after a warm-up with
+/// [`set_freeze_after`](#method.set_freeze_after), after which the move is an exact
+/// Metropolis-Hastings independence sampler.
+///
+/// Once the ensemble has roughly located a near-Gaussian posterior, this makes large jumps
+/// across it and can mix much faster than local moves. It is best mixed with a local move such
+/// as the [`StretchMove`](struct.StretchMove.html) using
+/// [`set_moves`](../struct.EnsembleSampler.html#method.set_moves), since the proposals can never
+/// reach regions the fitted Gaussian does not cover.
+#[derive(Debug, Clone)]
+pub struct CovarianceMove {
+    history_len: usize,
+    refit_interval: usize,
+    history: VecDeque<Guess>,
+    nproposals: usize,
+    freeze_after: Option<usize>,
+    fit: Option<(Vec<f64>, Matrix)>,
+}
+
+impl CovarianceMove {
+    /// Create a move which keeps the last `history_len` positions, and refits the Gaussian
+    /// every `refit_interval` proposals until it is [frozen](#method.set_freeze_after)
+    pub fn new(history_len: usize, refit_interval: usize) -> CovarianceMove {
+        assert!(history_len > 1, "the history must contain more than one position");
+        assert!(refit_interval > 0, "the refit interval must be positive");
+        CovarianceMove {
+            history_len,
+            refit_interval,
+            history: VecDeque::with_capacity(history_len),
+            nproposals: 0,
+            freeze_after: None,
+            fit: None,
+        }
+    }
+
+    /// Stop adapting once `nproposals` proposals have been made
+    ///
+    /// The history is then no longer updated nor the Gaussian refitted, so all later proposals
+    /// come from the same fit. As the only move, it makes two proposals per iteration, one for
+    /// each half of the ensemble, so freeze it after twice the number of warm-up iterations.
+    /// The count starts again when the move is reset.
+    pub fn set_freeze_after(&mut self, nproposals: usize) -> &mut Self {
+        self.freeze_after = Some(nproposals);
+        self
+    }
+
+    /// Whether the fit is no longer updated
+    pub fn is_frozen(&self) -> bool {
+        self.freeze_after
+            .is_some_and(|nproposals| self.nproposals >= nproposals)
+    }
+
+    /// The mean and lower-triangular Cholesky factor of the covariance of the current fit
+    pub fn fit(&self) -> Option<(&[f64], &[Vec<f64>])> {
+        self.fit
+            .as_ref()
+            .map(|(mean, l)| (mean.as_slice(), l.as_slice()))
+    }
+
+    fn refit(&mut self) {
+        let history: Vec<Guess> = self.history.iter().cloned().collect();
+        let (mean, cov) = linalg::mean_covariance(&history);
+        if let Some(l) = linalg::cholesky(&cov) {
+            self.fit = Some((mean, l));
+        }
+    }
+}
+
+impl Default for CovarianceMove {
+    fn default() -> Self {
+        CovarianceMove::new(1000, 10)
+    }
+}
+
+impl Move for CovarianceMove {
+    fn name(&self) -> &str {
+        "covariance"
+    }
+
+    fn propose(&mut self, s: &[Guess], c: &[Guess], mut rng: &mut dyn Rng) -> Proposal {
+        if !self.is_frozen() {
+            for guess in c {
+                if self.history.len() == self.history_len {
+                    self.history.pop_front();
+                }
+                self.history.push_back(guess.clone());
+            }
+
+            if self.fit.is_none() || self.nproposals.is_multiple_of(self.refit_interval) {
+                self.refit();
+            }
+        }
+        self.nproposals += 1;
+
+        let (mean, l) = match self.fit {
+            Some((ref mean, ref l)) => (mean, l),
+            None => {
+                // The history is degenerate, so stay put
+                return Proposal {
+                    q: s.to_vec(),
+                    factors: vec![0f64; s.len()],
+                    sources: Vec::new(),
+                };
+            }
+        };
+
+        let normal = Normal::new(0.0, 1.0);
+        let mut q = Vec::with_capacity(s.len());
+        let mut factors = Vec::with_capacity(s.len());
+        for sval in s {
+            let z: Vec<f64> = (0..mean.len()).map(|_| normal.ind_sample(&mut rng)).collect();
+            let offset = linalg::lower_mul(l, &z);
+            let values = mean.iter().zip(&offset).map(|(m, o)| m + o).collect();
+
+            let diff: Vec<f64> = sval.values.iter().zip(mean).map(|(x, m)| x - m).collect();
+            let w = linalg::lower_solve(l, &diff);
+            let lnq_current = -0.5 * w.iter().map(|x| x * x).sum::<f64>();
+            let lnq_proposed = -0.5 * z.iter().map(|x| x * x).sum::<f64>();
+
+            q.push(Guess { values });
+            factors.push(lnq_current - lnq_proposed);
+        }
+
+        Proposal {
+            q,
+            factors,
+            sources: Vec::new(),
+        }
+    }
+
+    fn reset(&mut self) {
+        self.history.clear();
+        self.nproposals = 0;
+        self.fit = None;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::{SeedableRng, StdRng};
+
+    #[test]
+    fn test_proposals_are_independent_of_current_position() {
+        let c = vec![
+            Guess::new(&[0.0, 1.0]),
+            Guess::new(&[2.0, 0.0]),
+            Guess::new(&[1.0, 3.0]),
+            Guess::new(&[-1.0, 1.0]),
+        ];
+        let s1 = vec![Guess::new(&[0.5, 0.5])];
+        let s2 = vec![Guess::new(&[10.0, -4.0])];
+
+        let mut a = CovarianceMove::new(10, 1);
+        let mut b = a.clone();
+        let pa = a.propose(&s1, &c, &mut StdRng::from_seed(&[3]));
+        let pb = b.propose(&s2, &c, &mut StdRng::from_seed(&[3]));
+        assert_eq!(pa.q[0].values, pb.q[0].values);
+
+        // Points further from the fitted mean are more eager to jump
+        assert!(pb.factors[0] < pa.factors[0]);
+    }
+
+    #[test]
+    fn test_history_is_bounded() {
+        let c: Vec<Guess> = (0..6)
+            .map(|i| Guess::new(&[i as f64, (i * i) as f64]))
+            .collect();
+        let s = vec![Guess::new(&[0.0, 0.0])];
+        let mut m = CovarianceMove::new(4, 1);
+        m.propose(&s, &c, &mut StdRng::from_seed(&[3]));
+        assert_eq!(m.history.len(), 4);
+        assert_eq!(m.history[0][0], 2.0);
+
+        let (mean, _) = m.fit().unwrap();
+        assert_approx_eq!(mean[0], 3.5);
+
+        m.reset();
+        assert!(m.history.is_empty());
+        assert!(m.fit().is_none());
+    }
+
+    #[test]
+    fn test_freeze() {
+        let c: Vec<Guess> = (0..6)
+            .map(|i| Guess::new(&[i as f64, (i * i) as f64]))
+            .collect();
+        let s = vec![Guess::new(&[0.0, 0.0])];
+        let mut m = CovarianceMove::new(100, 1);
+        m.set_freeze_after(2);
+        let mut rng = StdRng::from_seed(&[3]);
+        m.propose(&s, &c, &mut rng);
+        assert!(!m.is_frozen());
+        m.propose(&s, &c, &mut rng);
+        assert!(m.is_frozen());
+
+        let fit = m.fit.clone();
+        let shifted: Vec<Guess> = c
+            .iter()
+            .map(|guess| Guess::new(&[guess[0] + 5.0, guess[1]]))
+            .collect();
+        m.propose(&s, &shifted, &mut rng);
+        assert_eq!(m.history.len(), 12);
+        assert_eq!(m.fit, fit);
+
+        m.reset();
+        assert!(!m.is_frozen());
+    }
+
+    #[test]
+    fn test_degenerate_history() {
+        let c = vec![Guess::new(&[1.0, 1.0]), Guess::new(&[1.0, 1.0])];
+        let s = vec![Guess::new(&[0.0, 0.0])];
+        let mut m = CovarianceMove::default();
+        let proposal = m.propose(&s, &c, &mut StdRng::from_seed(&[3]));
+        assert!(m.fit().is_none());
+        assert_eq!(proposal.q[0].values, s[0].values);
+    }
+}
@@ -0,0 +1,286 @@
+use rand::Rng;
+use rand::distributions::{IndependentSample, Normal, Range};
+
+use guess::Guess;
+use super::{Move, Proposal};
+
+/// The differential evolution move of ter Braak (2006) and Nelson et al. (2014)
+///
+/// Each walker is moved by `gamma` times the difference between two distinct, randomly chosen
+/// walkers of the complementary ensemble, plus a small Gaussian jitter of width `sigma` in every
+/// coordinate so that the proposals are not confined to the lattice spanned by the differences.
+/// The proposal is symmetric, so no acceptance factor is needed.
+///
+/// With the default `gamma` of `2.38 / sqrt(2 ndim)`, which is optimal for Gaussian targets,
+/// this often mixes faster than the [`StretchMove`](struct.StretchMove.html) in moderately many
+/// dimensions. It is commonly combined with an occasional
+/// [`DESnookerMove`](struct.DESnookerMove.html) using
+/// [`set_moves`](../struct.EnsembleSampler.html#method.set_moves), e.g. with weights 0.8 and 0.2.
+#[derive(Debug, Clone)]
+pub struct DEMove {
+    /// The scale of the difference vectors, or `None` to use `2.38 / sqrt(2 ndim)`
+    pub gamma: Option<f64>,
+
+    /// The standard deviation of the Gaussian jitter added to each coordinate (default 1e-5)
+    pub sigma: f64,
+}
+
+impl DEMove {
+    /// Create a move which scales the difference vectors by `gamma` and adds jitter of width
+    /// `sigma`
+    pub fn new(gamma: f64, sigma: f64) -> DEMove {
+        assert!(gamma > 0.0, "the differential evolution scale must be positive");
+        assert!(sigma >= 0.0, "the jitter width must not be negative");
+        DEMove {
+            gamma: Some(gamma),
+            sigma,
+        }
+    }
+}
+
+impl Default for DEMove {
+    fn default() -> Self {
+        DEMove {
+            gamma: None,
+            sigma: 1e-5,
+        }
+    }
+}
+
+impl Move for DEMove {
+    fn name(&self) -> &str {
+        "de"
+    }
+
+    fn propose(&mut self, s: &[Guess], c: &[Guess], mut rng: &mut dyn Rng) -> Proposal {
+        assert!(
+            c.len() >= 2,
+            "the differential evolution move needs at least two complementary walkers"
+        );
+
+        let ndim = s.first().map_or(0, |sval| sval.values.len());
+        let gamma = self
+            .gamma
+            .unwrap_or_else(|| 2.38 / (2.0 * ndim as f64).sqrt());
+        let jitter = Normal::new(0.0, self.sigma);
+
+        let mut q = Vec::with_capacity(s.len());
+        for sval in s {
+            let (i, j) = distinct_pair(c.len(), &mut rng);
+            let values = sval
+                .values
+                .iter()
+                .enumerate()
+                .map(|(k, x)| x + gamma * (c[i][k] - c[j][k]) + jitter.ind_sample(&mut rng))
+                .collect();
+            q.push(Guess { values });
+        }
+
+        Proposal {
+            factors: vec![0.0; q.len()],
+            q,
+            sources: Vec::new(),
+        }
+    }
+
+    /// The tuning parameter is `gamma`, if it was given explicitly
+    fn tuning(&self) -> Option<f64> {
+        self.gamma
+    }
+
+    fn set_tuning(&mut self, value: f64) {
+        assert!(value > 0.0);
+        self.gamma = Some(value);
+    }
+}
+
+/// The differential evolution snooker move of ter Braak & Vrugt (2008)
+///
+/// For each walker a third walker `z` of the complementary ensemble is chosen as an anchor, and
+/// the walker moves along the line joining it to `z`. The step is `gamma` times the difference
+/// between the projections onto that line of two further complementary walkers. Unlike the
+/// [`DEMove`](struct.DEMove.html) the step direction is not limited to differences between
+/// walkers, which helps when the posterior has several modes or is strongly non-linear.
+///
+/// The proposal is not symmetric, and the acceptance factor is
+/// `(ndim - 1) ln(|q - z| / |x - z|)`.
+#[derive(Debug, Clone)]
+pub struct DESnookerMove {
+    /// The scale of the projected difference (default 1.7)
+    pub gamma: f64,
+}
+
+impl DESnookerMove {
+    /// Create a snooker move with scale `gamma`
+    pub fn new(gamma: f64) -> DESnookerMove {
+        assert!(gamma > 0.0, "the snooker scale must be positive");
+        DESnookerMove { gamma }
+    }
+}
+
+impl Default for DESnookerMove {
+    fn default() -> Self {
+        DESnookerMove::new(1.7)
+    }
+}
+
+impl Move for DESnookerMove {
+    fn name(&self) -> &str {
+        "de-snooker"
+    }
+
+    fn propose(&mut self, s: &[Guess], c: &[Guess], mut rng: &mut dyn Rng) -> Proposal {
+        assert!(
+            c.len() >= 3,
+            "the snooker move needs at least three complementary walkers"
+        );
+
+        let mut q = Vec::with_capacity(s.len());
+        let mut factors = Vec::with_capacity(s.len());
+        let mut sources = Vec::with_capacity(s.len());
+        for sval in s {
+            let (z, (i, j)) = loop {
+                let z = Range::new(0, c.len()).ind_sample(&mut rng);
+                let (i, j) = distinct_pair(c.len(), &mut rng);
+                if z != i && z != j {
+                    break (z, (i, j));
+                }
+            };
+
+            let delta: Vec<f64> = sval
+                .values
+                .iter()
+                .zip(&c[z].values)
+                .map(|(x, z)| x - z)
+                .collect();
+            let norm = delta.iter().map(|d| d * d).sum::<f64>().sqrt();
+            sources.push(z);
+            if norm == 0.0 {
+                // The walker sits on the anchor, so there is no line to move along
+                q.push(sval.clone());
+                factors.push(0.0);
+                continue;
+            }
+
+            let u: Vec<f64> = delta.iter().map(|d| d / norm).collect();
+            let projection = |g: &Guess| g.values.iter().zip(&u).map(|(x, u)| x * u).sum::<f64>();
+            let step = self.gamma * (projection(&c[i]) - projection(&c[j]));
+            let values: Vec<f64> = sval.values.iter().zip(&u).map(|(x, u)| x + step * u).collect();
+
+            let new_norm = values
+                .iter()
+                .zip(&c[z].values)
+                .map(|(x, z)| (x - z) * (x - z))
+                .sum::<f64>()
+                .sqrt();
+            factors.push((values.len() as f64 - 1.0) * (new_norm.ln() - norm.ln()));
+            q.push(Guess { values });
+        }
+
+        Proposal {
+            q,
+            factors,
+            sources,
+        }
+    }
+
+    fn tuning(&self) -> Option<f64> {
+        Some(self.gamma)
+    }
+
+    fn set_tuning(&mut self, value: f64) {
+        assert!(value > 0.0);
+        self.gamma = value;
+    }
+}
+
+/// Two distinct indices below `n`, in random order
+fn distinct_pair(n: usize, mut rng: &mut dyn Rng) -> (usize, usize) {
+    let range = Range::new(0, n);
+    let i = range.ind_sample(&mut rng);
+    let j = (i + Range::new(1, n).ind_sample(&mut rng)) % n;
+    (i, j)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::{SeedableRng, StdRng};
+    use testkit::{self, CorrelatedGaussian};
+
+    fn target() -> CorrelatedGaussian {
+        CorrelatedGaussian::new(
+            vec![1.0, -2.0, 0.5],
+            vec![
+                vec![1.0, 0.8, 0.0],
+                vec![0.8, 2.0, -0.3],
+                vec![0.0, -0.3, 0.5],
+            ],
+        ).unwrap()
+    }
+
+    #[test]
+    fn test_de_proposals() {
+        let s = vec![Guess::new(&[0.0, 0.0])];
+        let c = vec![Guess::new(&[1.0, 2.0]), Guess::new(&[3.0, 3.0])];
+        let mut m = DEMove::new(0.5, 0.0);
+        let proposal = m.propose(&s, &c, &mut StdRng::from_seed(&[1]));
+
+        // Without jitter the step is half the difference between the two complementary walkers
+        let q = &proposal.q[0];
+        assert_approx_eq!(q[0].abs(), 1.0);
+        assert_approx_eq!(q[1].abs(), 0.5);
+        assert_eq!(q[0].signum(), q[1].signum());
+        assert_eq!(proposal.factors, vec![0.0]);
+        assert_eq!(DEMove::default().tuning(), None);
+    }
+
+    #[test]
+    fn test_snooker_proposals() {
+        let s = vec![Guess::new(&[1.0, 1.0, 0.0]), Guess::new(&[0.0, 0.0, 0.0])];
+        let c = vec![
+            Guess::new(&[0.0, 0.0, 0.0]),
+            Guess::new(&[2.0, 0.0, 1.0]),
+            Guess::new(&[0.0, 3.0, -1.0]),
+        ];
+        let mut m = DESnookerMove::default();
+        let proposal = m.propose(&s, &c, &mut StdRng::from_seed(&[2]));
+
+        for ((sval, q), (factor, &z)) in s
+            .iter()
+            .zip(&proposal.q)
+            .zip(proposal.factors.iter().zip(&proposal.sources))
+        {
+            let anchor = &c[z].values;
+            let dist = |x: &[f64]| {
+                x.iter()
+                    .zip(anchor)
+                    .map(|(x, z)| (x - z) * (x - z))
+                    .sum::<f64>()
+                    .sqrt()
+            };
+            if dist(&sval.values) == 0.0 {
+                assert_eq!(q, sval);
+                continue;
+            }
+            // The proposal lies on the line through the walker and the anchor
+            let cross = (sval[0] - anchor[0]) * (q[1] - anchor[1])
+                - (sval[1] - anchor[1]) * (q[0] - anchor[0]);
+            assert_approx_eq!(cross, 0.0);
+            assert_approx_eq!(*factor, 2.0 * (dist(&q.values) / dist(&sval.values)).ln());
+        }
+    }
+
+    #[test]
+    fn test_de_moves_sample_target() {
+        let target = target();
+        let run = testkit::run_move(&target, Box::new(DEMove::default()), 16, 4000, &[1]).unwrap();
+        testkit::assert_moments(&run, &target, 4.0);
+        testkit::assert_marginals(&run, &target, 10, 4.0);
+
+        let snooker = Box::new(DESnookerMove::default());
+        let run = testkit::run_move(&target, snooker, 16, 4000, &[2]).unwrap();
+        testkit::assert_moments(&run, &target, 4.0);
+        testkit::assert_marginals(&run, &target, 10, 4.0);
+    }
+}
@@ -0,0 +1,210 @@
+use rand::Rng;
+use rand::distributions::{IndependentSample, Normal};
+
+use errors::{EmceeError, Result};
+use guess::Guess;
+use linalg::{self, Matrix};
+use super::{Move, Proposal};
+
+/// The shape of the proposal distribution of a [`GaussianMove`](struct.GaussianMove.html)
+#[derive(Debug, Clone, PartialEq)]
+enum Shape {
+    /// The same standard deviation for every parameter
+    Isotropic(f64),
+
+    /// The standard deviation of each parameter
+    Diagonal(Vec<f64>),
+
+    /// Lower-triangular Cholesky factor of the covariance
+    Cholesky(Matrix),
+}
+
+/// Gaussian random-walk Metropolis proposals, as `GaussianMove` in Python `emcee`
+///
+/// Each walker is moved independently of the rest of the ensemble, by a step drawn from a
+/// multivariate normal distribution with zero mean and a fixed covariance: isotropic, diagonal
+/// or full. The proposal is symmetric, so the acceptance factor is zero.
+///
+/// The covariance is multiplied by the square of a scale factor, 1 to begin with, which is the
+/// [tuning parameter](trait.Move.html#method.tuning) of the move, so
+/// [`run_warmup`](../struct.EnsembleSampler.html#method.run_warmup) adapts the step size
+/// towards the target acceptance fraction during burn-in; the covariance only needs to have
+/// roughly the right shape.
+#[derive(Debug, Clone)]
+pub struct GaussianMove {
+    shape: Shape,
+    scale: f64,
+}
+
+impl GaussianMove {
+    /// Steps with the same `variance` in every parameter
+    ///
+    /// Errors are handled by returning a [`Result`](../errors/type.Result.html) which contains
+    /// [`EmceeError::InvalidInputs`](../errors/enum.EmceeError.html) if the variance is not
+    /// positive and finite.
+    pub fn isotropic(variance: f64) -> Result<GaussianMove> {
+        check_variances(&[variance])?;
+        Ok(GaussianMove {
+            shape: Shape::Isotropic(variance.sqrt()),
+            scale: 1.0,
+        })
+    }
+
+    /// Independent steps with the given variance in each parameter
+    ///
+    /// Errors are handled by returning a [`Result`](../errors/type.Result.html) which contains
+    /// [`EmceeError::InvalidInputs`](../errors/enum.EmceeError.html) if there are no variances
+    /// or any is not positive and finite.
+    pub fn diagonal(variances: &[f64]) -> Result<GaussianMove> {
+        check_variances(variances)?;
+        Ok(GaussianMove {
+            shape: Shape::Diagonal(variances.iter().map(|v| v.sqrt()).collect()),
+            scale: 1.0,
+        })
+    }
+
+    /// Correlated steps with covariance matrix `cov`
+    ///
+    /// Errors are handled by returning a [`Result`](../errors/type.Result.html) which contains
+    /// [`EmceeError::InvalidInputs`](../errors/enum.EmceeError.html) if `cov` is not square or
+    /// not positive definite.
+    pub fn full(cov: &[Vec<f64>]) -> Result<GaussianMove> {
+        if cov.is_empty() || cov.iter().any(|row| row.len() != cov.len()) {
+            return Err(EmceeError::InvalidInputs(
+                "the covariance must be a non-empty square matrix".into(),
+            ));
+        }
+        let l = linalg::cholesky(cov).ok_or_else(|| {
+            EmceeError::InvalidInputs("the covariance is not positive definite".into())
+        })?;
+        Ok(GaussianMove {
+            shape: Shape::Cholesky(l),
+            scale: 1.0,
+        })
+    }
+
+    /// The factor multiplying the standard deviation of every step
+    pub fn scale(&self) -> f64 {
+        self.scale
+    }
+
+    /// The number of parameters the move was configured for, or `None` for an isotropic move
+    fn ndim(&self) -> Option<usize> {
+        match self.shape {
+            Shape::Isotropic(_) => None,
+            Shape::Diagonal(ref sigma) => Some(sigma.len()),
+            Shape::Cholesky(ref l) => Some(l.len()),
+        }
+    }
+}
+
+impl Move for GaussianMove {
+    fn name(&self) -> &str {
+        "gaussian"
+    }
+
+    fn propose(&mut self, s: &[Guess], _c: &[Guess], mut rng: &mut dyn Rng) -> Proposal {
+        let normal = Normal::new(0.0, 1.0);
+        let q = s
+            .iter()
+            .map(|sval| {
+                let ndim = sval.values.len();
+                if let Some(expected) = self.ndim() {
+                    assert_eq!(
+                        ndim, expected,
+                        "the move was configured for {} parameters, the walker has {}",
+                        expected, ndim
+                    );
+                }
+                let z: Vec<f64> = (0..ndim).map(|_| normal.ind_sample(&mut rng)).collect();
+                let step = match self.shape {
+                    Shape::Isotropic(sigma) => z.iter().map(|z| sigma * z).collect(),
+                    Shape::Diagonal(ref sigma) => {
+                        z.iter().zip(sigma).map(|(z, sigma)| sigma * z).collect()
+                    }
+                    Shape::Cholesky(ref l) => linalg::lower_mul(l, &z),
+                };
+                let values = sval
+                    .values
+                    .iter()
+                    .zip(step)
+                    .map(|(x, dx): (&f64, f64)| x + self.scale * dx)
+                    .collect();
+                Guess { values }
+            })
+            .collect();
+
+        Proposal {
+            q,
+            factors: vec![0.0; s.len()],
+            sources: Vec::new(),
+        }
+    }
+
+    fn tuning(&self) -> Option<f64> {
+        Some(self.scale)
+    }
+
+    fn set_tuning(&mut self, value: f64) {
+        assert!(value > 0.0);
+        self.scale = value;
+    }
+}
+
+fn check_variances(variances: &[f64]) -> Result<()> {
+    if variances.is_empty() || !variances.iter().all(|v| v.is_finite() && *v > 0.0) {
+        return Err(EmceeError::InvalidInputs(
+            "variances must be positive and finite".into(),
+        ));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::{SeedableRng, StdRng};
+    use testkit::{self, CorrelatedGaussian};
+
+    #[test]
+    fn test_gaussian_proposals() {
+        let s: Vec<Guess> = (0..4000).map(|_| Guess::new(&[1.0, -1.0])).collect();
+        let mut m = GaussianMove::diagonal(&[4.0, 0.25]).unwrap();
+        m.set_tuning(0.5);
+        let proposal = m.propose(&s, &[], &mut StdRng::from_seed(&[4]));
+        assert!(proposal.factors.iter().all(|&f| f == 0.0));
+
+        let var = |param: usize, centre: f64| {
+            proposal
+                .q
+                .iter()
+                .map(|q| (q[param] - centre).powi(2))
+                .sum::<f64>()
+                / s.len() as f64
+        };
+        // Standard deviations 2 and 0.5, halved by the scale
+        assert!((var(0, 1.0) - 1.0).abs() < 0.1);
+        assert!((var(1, -1.0) - 0.0625).abs() < 0.006);
+        assert_eq!(m.tuning(), Some(0.5));
+
+        assert!(GaussianMove::isotropic(0.0).is_err());
+        assert!(GaussianMove::diagonal(&[1.0, f64::NAN]).is_err());
+        assert!(GaussianMove::full(&[vec![1.0, 2.0], vec![2.0, 1.0]]).is_err());
+        assert!(GaussianMove::full(&[vec![1.0, 0.0]]).is_err());
+    }
+
+    #[test]
+    fn test_gaussian_move_samples_target() {
+        let target = CorrelatedGaussian::new(
+            vec![1.0, -2.0],
+            vec![vec![1.0, 0.8], vec![0.8, 2.0]],
+        ).unwrap();
+        let m = GaussianMove::full(&[vec![1.0, 0.8], vec![0.8, 2.0]]).unwrap();
+        let run = testkit::run_move(&target, Box::new(m), 16, 4000, &[5]).unwrap();
+        testkit::assert_moments(&run, &target, 4.0);
+
+        let m = GaussianMove::isotropic(0.5).unwrap();
+        let run = testkit::run_move(&target, Box::new(m), 16, 4000, &[6]).unwrap();
+        testkit::assert_moments(&run, &target, 4.0);
+    }
+}
@@ -0,0 +1,172 @@
+use rand::Rng;
+use rand::distributions::{IndependentSample, Normal, Range};
+
+use guess::Guess;
+use linalg::{self, Matrix};
+use math;
+use super::{Move, Proposal};
+
+/// Bandwidth of the kernel density estimate of a [`KDEMove`](struct.KDEMove.html), as a
+/// factor multiplying the standard deviation of the complementary ensemble
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum Bandwidth {
+    /// Scott's rule, `n^(-1 / (d + 4))` for `n` walkers in `d` dimensions
+    #[default]
+    Scott,
+
+    /// Silverman's rule, `(n (d + 2) / 4)^(-1 / (d + 4))`
+    Silverman,
+
+    /// A fixed factor
+    Factor(f64),
+}
+
+impl Bandwidth {
+    fn factor(&self, n: usize, ndim: usize) -> f64 {
+        let (n, d) = (n as f64, ndim as f64);
+        match *self {
+            Bandwidth::Scott => n.powf(-1.0 / (d + 4.0)),
+            Bandwidth::Silverman => (n * (d + 2.0) / 4.0).powf(-1.0 / (d + 4.0)),
+            Bandwidth::Factor(factor) => factor,
+        }
+    }
+}
+
+/// Proposals drawn from a kernel density estimate of the complementary ensemble, as `KDEMove`
+/// in Python `emcee`
+///
+/// The complementary walkers are smoothed with Gaussian kernels whose covariance is that of
+/// the walkers scaled by the square of the [`Bandwidth`](enum.Bandwidth.html) factor, as in
+/// `scipy.stats.gaussian_kde`, and each proposal is an independent draw from the estimate. The
+/// acceptance factor is the log ratio of the density estimate at the current and proposed
+/// positions.
+///
+/// When the ensemble has spread over the posterior this makes large, well-aimed jumps, e.g.
+/// between well-separated modes, but the estimate is poor with few walkers per dimension. The
+/// complementary ensemble needs more walkers than parameters; otherwise the walkers stay put.
+#[derive(Debug, Clone, Default)]
+pub struct KDEMove {
+    /// The bandwidth of the kernels (default Scott's rule)
+    pub bandwidth: Bandwidth,
+}
+
+impl KDEMove {
+    /// Create a move with the given kernel bandwidth
+    pub fn new(bandwidth: Bandwidth) -> KDEMove {
+        if let Bandwidth::Factor(factor) = bandwidth {
+            assert!(factor > 0.0, "the bandwidth factor must be positive");
+        }
+        KDEMove { bandwidth }
+    }
+}
+
+/// Log of the density estimate at `x`, up to a constant
+fn ln_density(l: &[Vec<f64>], c: &[Guess], x: &[f64]) -> f64 {
+    let terms: Vec<f64> = c
+        .iter()
+        .map(|centre| {
+            let diff: Vec<f64> = x.iter().zip(&centre.values).map(|(x, c)| x - c).collect();
+            let w = linalg::lower_solve(l, &diff);
+            -0.5 * w.iter().map(|w| w * w).sum::<f64>()
+        })
+        .collect();
+    math::logsumexp(&terms)
+}
+
+impl Move for KDEMove {
+    fn name(&self) -> &str {
+        "kde"
+    }
+
+    fn propose(&mut self, s: &[Guess], c: &[Guess], mut rng: &mut dyn Rng) -> Proposal {
+        let ndim = c.first().map_or(0, |guess| guess.values.len());
+        let kernel: Option<Matrix> = if c.len() > ndim {
+            let (_, mut cov) = linalg::mean_covariance(c);
+            let factor = self.bandwidth.factor(c.len(), ndim);
+            for value in cov.iter_mut().flat_map(|row| row.iter_mut()) {
+                *value *= factor * factor;
+            }
+            linalg::cholesky(&cov)
+        } else {
+            None
+        };
+        let l = match kernel {
+            Some(l) => l,
+            None => {
+                // The ensemble is degenerate, so stay put
+                return Proposal {
+                    q: s.to_vec(),
+                    factors: vec![0f64; s.len()],
+                    sources: Vec::new(),
+                };
+            }
+        };
+
+        let normal = Normal::new(0.0, 1.0);
+        let pick = Range::new(0usize, c.len());
+        let mut q = Vec::with_capacity(s.len());
+        let mut factors = Vec::with_capacity(s.len());
+        let mut sources = Vec::with_capacity(s.len());
+        for sval in s {
+            let centre = pick.ind_sample(&mut rng);
+            let z: Vec<f64> = (0..ndim).map(|_| normal.ind_sample(&mut rng)).collect();
+            let values: Vec<f64> = c[centre]
+                .values
+                .iter()
+                .zip(linalg::lower_mul(&l, &z))
+                .map(|(x, dx)| x + dx)
+                .collect();
+
+            factors.push(ln_density(&l, c, &sval.values) - ln_density(&l, c, &values));
+            q.push(Guess { values });
+            sources.push(centre);
+        }
+
+        Proposal {
+            q,
+            factors,
+            sources,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::{SeedableRng, StdRng};
+    use testkit::{self, GaussianMixture};
+
+    #[test]
+    fn test_kde_proposals() {
+        let c = vec![
+            Guess::new(&[0.0, 1.0]),
+            Guess::new(&[2.0, 0.0]),
+            Guess::new(&[1.0, 3.0]),
+            Guess::new(&[-1.0, 1.0]),
+        ];
+        let s = vec![Guess::new(&[0.5, 1.0]), Guess::new(&[10.0, -4.0])];
+        let mut m = KDEMove::default();
+        let proposal = m.propose(&s, &c, &mut StdRng::from_seed(&[7]));
+
+        // Leaving the far tail of the estimate, which is rarely proposed, is penalised
+        assert!(proposal.factors[1] < proposal.factors[0] - 10.0);
+        assert_eq!(proposal.sources.len(), 2);
+
+        // Scott's factor for 4 walkers in 2 dimensions
+        assert_approx_eq!(Bandwidth::Scott.factor(4, 2), 4f64.powf(-1.0 / 6.0));
+
+        // Too few complementary walkers to estimate a density in 2 dimensions
+        let proposal = m.propose(&s, &c[..2], &mut StdRng::from_seed(&[7]));
+        assert_eq!(proposal.q, s);
+    }
+
+    #[test]
+    fn test_kde_move_samples_target() {
+        // Two well-separated modes, which the move jumps between
+        let target =
+            GaussianMixture::new(vec![(1.0, vec![-3.0, 0.0], 1.0), (1.0, vec![3.0, 0.0], 1.0)])
+                .unwrap();
+        let run = testkit::run_move(&target, Box::new(KDEMove::default()), 32, 3000, &[8]);
+        testkit::assert_moments(&run.unwrap(), &target, 4.0);
+    }
+}
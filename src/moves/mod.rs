@@ -0,0 +1,183 @@
+//! Proposal moves
+//!
+//! Each iteration the ensemble is split into two halves, and the walkers in one half are updated
+//! using proposals generated with the help of the other (complementary) half. A
+//! [`Move`](trait.Move.html) generates those proposals; the sampler then evaluates them and
+//! performs the Metropolis-Hastings acceptance step.
+//!
+//! The default move is the [`StretchMove`](struct.StretchMove.html) of Goodman & Weare, as used
+//! by Python `emcee`. Several moves can be combined using
+//! [`EnsembleSampler::set_moves`](../struct.EnsembleSampler.html#method.set_moves), in which case
+//! one of them is chosen at random each iteration according to its weight, e.g. the mixture of
+//! differential evolution moves recommended by Python `emcee`:
+//!
+//! ```rust,no_run
+//! # use emcee::{EnsembleSampler, Guess, Prob};
+//! # use emcee::moves::{DEMove, DESnookerMove};
+//! # struct Model;
+//! # impl Prob for Model {
+//! #     fn lnlike(&self, _params: &Guess) -> f64 { 0.0 }
+//! #     fn lnprior(&self, _params: &Guess) -> f64 { 0.0 }
+//! # }
+//! # let model = Model;
+//! let mut sampler = EnsembleSampler::new(32, 3, &model).unwrap();
+//! sampler.set_moves(vec![
+//!     (Box::new(DEMove::default()), 0.8),
+//!     (Box::new(DESnookerMove::default()), 0.2),
+//! ]).unwrap();
+//! ```
+
+use rand::Rng;
+
+use errors::Result;
+use guess::Guess;
+
+mod covariance;
+mod de;
+mod gaussian;
+mod kde;
+mod multiple_try;
+mod scaled;
+mod stretch;
+mod student_t;
+mod subspace;
+
+pub use self::covariance::CovarianceMove;
+pub use self::de::{DEMove, DESnookerMove};
+pub use self::gaussian::GaussianMove;
+pub use self::kde::{Bandwidth, KDEMove};
+pub use self::multiple_try::MultipleTryMove;
+pub use self::scaled::{ScaledStretchMove, Scaling};
+pub use self::stretch::StretchMove;
+pub use self::student_t::StudentTMove;
+pub use self::subspace::SubspaceMove;
+
+/// New positions proposed for one half of the ensemble
+#[derive(Debug, Default, Clone)]
+pub struct Proposal {
+    /// The proposed positions, one for each walker being updated
+    pub q: Vec<Guess>,
+
+    /// Natural log of the extra factor to include in the acceptance probability of each
+    /// proposal, e.g. the `(ndim - 1) ln z` term of the stretch move, or the log ratio of the
+    /// reverse and forward proposal densities for asymmetric proposals
+    pub factors: Vec<f64>,
+
+    /// For each walker, the index in the complementary ensemble of the walker its proposal was
+    /// generated from, recorded when the sampler
+    /// [tracks ancestry](../struct.EnsembleSampler.html#structfield.track_ancestry); left empty
+    /// by moves which do not build proposals from a single complementary walker
+    pub sources: Vec<usize>,
+}
+
+/// Evaluates the log posterior at a set of positions for
+/// [`Move::propose_evaluated`](trait.Move.html#method.propose_evaluated)
+pub type Evaluate<'a> = dyn FnMut(&[Guess]) -> Result<Vec<f64>> + 'a;
+
+/// Generates proposals for the sampler
+pub trait Move {
+    /// Short name used to label the statistics of this move
+    fn name(&self) -> &str;
+
+    /// Propose new positions for the walkers in `s`
+    ///
+    /// `c` contains the current positions of the complementary half of the ensemble, which must
+    /// not be modified by the proposal. One position and one log factor must be returned per
+    /// walker in `s`.
+    fn propose(&mut self, s: &[Guess], c: &[Guess], rng: &mut dyn Rng) -> Proposal;
+
+    /// Propose new positions for the walkers in `s`, with access to the log posterior
+    ///
+    /// Moves which need the posterior at intermediate positions, e.g. to choose between several
+    /// candidates, override this method. `lnprob_s` holds the current log posterior of each
+    /// walker in `s`, and `evaluate` returns the log posterior at any set of positions, with
+    /// negative infinity for positions the model rejects. The sampler still evaluates the
+    /// returned proposals and performs the acceptance step as usual.
+    ///
+    /// The default ignores the posterior and calls [`propose`](#tymethod.propose). The
+    /// [`EnsembleSampler`](../struct.EnsembleSampler.html) always calls this method, while other
+    /// samplers may call `propose` directly.
+    fn propose_evaluated(
+        &mut self,
+        s: &[Guess],
+        _lnprob_s: &[f64],
+        c: &[Guess],
+        rng: &mut dyn Rng,
+        _evaluate: &mut Evaluate,
+    ) -> Result<Proposal> {
+        Ok(self.propose(s, c, rng))
+    }
+
+    /// The positive tuning parameter of this move, if it has one
+    ///
+    /// By convention, larger values give bolder proposals which are accepted less often. This is
+    /// used by [`run_warmup`](../struct.EnsembleSampler.html#method.run_warmup) to adapt the
+    /// move towards a target acceptance fraction.
+    fn tuning(&self) -> Option<f64> {
+        None
+    }
+
+    /// Update the tuning parameter returned by [`tuning`](#method.tuning)
+    fn set_tuning(&mut self, _value: f64) {}
+
+    /// Discard anything learned while sampling, e.g. a history of positions, keeping the
+    /// configuration the move was created with
+    ///
+    /// Called by [`EnsembleSampler::reset`](../struct.EnsembleSampler.html#method.reset).
+    fn reset(&mut self) {}
+}
+
+/// The origin of one accepted step, recorded when the sampler
+/// [tracks ancestry](../struct.EnsembleSampler.html#structfield.track_ancestry)
+///
+/// Following the parents back through the records shows how walkers reached a mode, and
+/// counting records by move shows which moves actually drive the mixing.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AncestryRecord {
+    /// The iteration of the step, counted since the sampler was created or
+    /// [`reset`](../struct.EnsembleSampler.html#method.reset)
+    pub iteration: usize,
+
+    /// The walker which moved
+    pub walker: usize,
+
+    /// The walker of the complementary ensemble the proposal was generated from, if the move
+    /// reports one
+    pub parent: Option<usize>,
+
+    /// Index of the move which generated the proposal, in the order given to
+    /// [`set_moves`](../struct.EnsembleSampler.html#method.set_moves)
+    pub move_idx: usize,
+}
+
+/// Acceptance statistics for one move of the sampler
+#[derive(Debug, Clone, PartialEq)]
+pub struct MoveAcceptance {
+    /// The [name](trait.Move.html#tymethod.name) of the move
+    pub name: String,
+
+    /// The number of individual walker proposals made by this move
+    pub nproposed: usize,
+
+    /// The number of those proposals which were accepted
+    pub naccepted: usize,
+}
+
+impl MoveAcceptance {
+    pub(crate) fn new(name: &str) -> MoveAcceptance {
+        MoveAcceptance {
+            name: name.to_string(),
+            nproposed: 0,
+            naccepted: 0,
+        }
+    }
+
+    /// The fraction of proposals which were accepted, or NaN if this move has not been used
+    pub fn fraction(&self) -> f64 {
+        if self.nproposed == 0 {
+            f64::NAN
+        } else {
+            self.naccepted as f64 / self.nproposed as f64
+        }
+    }
+}
@@ -0,0 +1,248 @@
+use rand::Rng;
+use rand::distributions::{IndependentSample, Range};
+
+use errors::Result;
+use guess::Guess;
+use math;
+use super::{Evaluate, Move, Proposal};
+
+/// Multiple-try Metropolis (Liu, Liang & Wong 2000) around any other move
+///
+/// For each walker the inner move generates `ntries` candidates, and one is selected with
+/// probability proportional to its weight `p(y) exp(f / 2)`, where `p` is the posterior and `f`
+/// the inner move's log acceptance factor. Another `ntries - 1` reference points are then drawn
+/// from the selected candidate, and the acceptance factor compares the total weight of the
+/// candidates with that of the reference points and the current position. This leaves the
+/// posterior invariant for any inner move whose factor for the reverse step is the negative of
+/// the factor for the forward step, as for all moves in this module.
+///
+/// Each proposal costs `2 ntries - 1` evaluations of the posterior, plus one by the sampler for
+/// the selected candidate, but is accepted far more often than a single try when the proposals
+/// of the inner move are poorly matched to the posterior, e.g. on flat plateaus where most
+/// candidates are rejected, or when evaluations are cheap and run in parallel.
+///
+/// The candidates are only evaluated when the move is used directly by the
+/// [`EnsembleSampler`](../struct.EnsembleSampler.html); e.g. inside a
+/// [`SubspaceMove`](struct.SubspaceMove.html) it makes a single try with the inner move.
+pub struct MultipleTryMove {
+    name: String,
+    ntries: usize,
+    inner: Box<dyn Move>,
+}
+
+impl MultipleTryMove {
+    /// Create a move which makes `ntries` tries with `inner`
+    pub fn new(inner: Box<dyn Move>, ntries: usize) -> MultipleTryMove {
+        assert!(ntries > 0, "at least one try is needed");
+        MultipleTryMove {
+            name: format!("multiple-try({})", inner.name()),
+            ntries,
+            inner,
+        }
+    }
+
+    /// The number of candidates generated for each walker
+    pub fn ntries(&self) -> usize {
+        self.ntries
+    }
+}
+
+impl Move for MultipleTryMove {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn propose(&mut self, s: &[Guess], c: &[Guess], rng: &mut dyn Rng) -> Proposal {
+        self.inner.propose(s, c, rng)
+    }
+
+    fn propose_evaluated(
+        &mut self,
+        s: &[Guess],
+        lnprob_s: &[f64],
+        c: &[Guess],
+        mut rng: &mut dyn Rng,
+        evaluate: &mut Evaluate,
+    ) -> Result<Proposal> {
+        let k = self.ntries;
+        let repeat = |positions: &[Guess], n: usize| -> Vec<Guess> {
+            positions
+                .iter()
+                .flat_map(|guess| (0..n).map(move |_| guess.clone()))
+                .collect()
+        };
+
+        // Candidates, k for each walker, and their weights
+        let forward = self.inner.propose(&repeat(s, k), c, rng);
+        let weights: Vec<f64> = evaluate(&forward.q)?
+            .iter()
+            .zip(&forward.factors)
+            .map(|(lnprob, factor)| lnprob + 0.5 * factor)
+            .collect();
+
+        let unit_range = Range::new(0f64, 1f64);
+        let mut selected = Vec::with_capacity(s.len());
+        for candidates in weights.chunks(k) {
+            let u = unit_range.ind_sample(&mut rng);
+            let mut total = 0f64;
+            let choice = math::softmax(candidates)
+                .iter()
+                .position(|p| {
+                    total += p;
+                    u < total
+                })
+                .unwrap_or(k - 1);
+            selected.push(choice);
+        }
+
+        // Reference points, k - 1 drawn from each selected candidate
+        let y: Vec<Guess> = selected
+            .iter()
+            .enumerate()
+            .map(|(i, j)| forward.q[i * k + j].clone())
+            .collect();
+        let (backward, lnprob_backward) = if k > 1 {
+            let backward = self.inner.propose(&repeat(&y, k - 1), c, rng);
+            let lnprob = evaluate(&backward.q)?;
+            (backward, lnprob)
+        } else {
+            (Proposal::default(), Vec::new())
+        };
+
+        let mut factors = Vec::with_capacity(s.len());
+        let mut sources = Vec::with_capacity(s.len());
+        for (i, &j) in selected.iter().enumerate() {
+            let idx = i * k + j;
+            let candidates = &weights[i * k..(i + 1) * k];
+            let forward_weight = math::logsumexp(candidates);
+            if forward_weight == -f64::INFINITY {
+                // Every candidate was rejected by the posterior
+                factors.push(-f64::INFINITY);
+            } else {
+                let lnprob_y = weights[idx] - 0.5 * forward.factors[idx];
+                let mut reference: Vec<f64> = (i * (k - 1)..(i + 1) * (k - 1))
+                    .map(|r| lnprob_backward[r] + 0.5 * backward.factors[r])
+                    .collect();
+                reference.push(lnprob_s[i] - 0.5 * forward.factors[idx]);
+                let reverse_weight = math::logsumexp(&reference);
+                factors.push(forward_weight - reverse_weight - lnprob_y + lnprob_s[i]);
+            }
+            if let Some(&source) = forward.sources.get(idx) {
+                sources.push(source);
+            }
+        }
+
+        Ok(Proposal {
+            q: y,
+            factors,
+            sources,
+        })
+    }
+
+    fn tuning(&self) -> Option<f64> {
+        self.inner.tuning()
+    }
+
+    fn set_tuning(&mut self, value: f64) {
+        self.inner.set_tuning(value)
+    }
+
+    fn reset(&mut self) {
+        self.inner.reset()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use moves::{DEMove, StretchMove};
+    use rand::{SeedableRng, StdRng};
+    use testkit::{self, CorrelatedGaussian, Target};
+    use EnsembleSampler;
+
+    fn target() -> CorrelatedGaussian {
+        CorrelatedGaussian::new(
+            vec![1.0, -2.0, 0.5],
+            vec![
+                vec![1.0, 0.8, 0.0],
+                vec![0.8, 2.0, -0.3],
+                vec![0.0, -0.3, 0.5],
+            ],
+        ).unwrap()
+    }
+
+    #[test]
+    fn test_single_try_without_evaluations() {
+        let s = vec![Guess::new(&[0.0, 0.0]), Guess::new(&[1.0, 1.0])];
+        let c = vec![Guess::new(&[2.0, 4.0]), Guess::new(&[-1.0, 3.0])];
+
+        let mut m = MultipleTryMove::new(Box::new(StretchMove::default()), 5);
+        assert_eq!(m.name(), "multiple-try(stretch)");
+        assert_eq!(m.ntries(), 5);
+        let proposal = m.propose(&s, &c, &mut StdRng::from_seed(&[1]));
+        let single = StretchMove::default().propose(&s, &c, &mut StdRng::from_seed(&[1]));
+        assert_eq!(proposal.q, single.q);
+        assert_eq!(proposal.factors, single.factors);
+    }
+
+    #[test]
+    fn test_rejected_candidates() {
+        let s = vec![Guess::new(&[0.0, 0.0])];
+        let c = vec![Guess::new(&[2.0, 4.0]), Guess::new(&[-1.0, 3.0])];
+        let mut m = MultipleTryMove::new(Box::new(StretchMove::default()), 3);
+        let mut nevaluated = 0;
+        let proposal = m
+            .propose_evaluated(&s, &[0.0], &c, &mut StdRng::from_seed(&[1]), &mut |q| {
+                nevaluated += q.len();
+                Ok(vec![-f64::INFINITY; q.len()])
+            })
+            .unwrap();
+        assert_eq!(proposal.factors, vec![-f64::INFINITY]);
+        assert_eq!(nevaluated, 5);
+    }
+
+    #[test]
+    fn test_multiple_try_samples_target() {
+        let target = target();
+        let stretch = MultipleTryMove::new(Box::new(StretchMove::default()), 4);
+        let run = testkit::run_move(&target, Box::new(stretch), 16, 4000, &[1]).unwrap();
+        testkit::assert_moments(&run, &target, 4.0);
+        testkit::assert_marginals(&run, &target, 10, 4.0);
+
+        let de = MultipleTryMove::new(Box::new(DEMove::new(1.5, 1e-5)), 4);
+        let run = testkit::run_move(&target, Box::new(de), 16, 4000, &[2]).unwrap();
+        testkit::assert_moments(&run, &target, 4.0);
+        testkit::assert_marginals(&run, &target, 10, 4.0);
+    }
+
+    #[test]
+    fn test_multiple_tries_are_accepted_more_often() {
+        let target = target();
+        let p0: Vec<Guess> = (0..16)
+            .map(|i| {
+                Guess::new(&target
+                    .mean()
+                    .iter()
+                    .enumerate()
+                    .map(|(j, x)| x + 0.1 * ((i * (j + 1)) % 7) as f64 - 0.3)
+                    .collect::<Vec<f64>>())
+            })
+            .collect();
+
+        let fraction = |m: Box<dyn Move>| {
+            let mut sampler = EnsembleSampler::new(16, 3, &target).unwrap();
+            sampler.seed(&[3]);
+            sampler.set_moves(vec![(m, 1.0)]).unwrap();
+            sampler.run_mcmc(&p0, 500).unwrap();
+            sampler.move_acceptance()[0].fraction()
+        };
+
+        // A deliberately bold stretch move which is rarely accepted with one try
+        let single = fraction(Box::new(StretchMove::new(8.0)));
+        let multiple = fraction(Box::new(MultipleTryMove::new(
+            Box::new(StretchMove::new(8.0)),
+            8,
+        )));
+        assert!(multiple > 1.5 * single, "{} vs {}", multiple, single);
+    }
+}
@@ -0,0 +1,199 @@
+use rand::Rng;
+
+use errors::{EmceeError, Result};
+use guess::Guess;
+use linalg;
+use super::{Move, Proposal, StretchMove};
+
+/// Linear scaling which defines the whitened space of a
+/// [`ScaledStretchMove`](struct.ScaledStretchMove.html)
+#[derive(Debug, Clone, PartialEq)]
+pub enum Scaling {
+    /// The characteristic scale of each parameter; whitened coordinates are `x_i / scale_i`
+    Diagonal(Vec<f64>),
+
+    /// Lower-triangular Cholesky factor `L` of a covariance matrix; whitened coordinates are
+    /// `L^-1 x`
+    Cholesky(Vec<Vec<f64>>),
+
+    /// Estimate the scale of each parameter from the standard deviation of the complementary
+    /// ensemble, every time a proposal is made
+    EnsembleDiagonal,
+
+    /// Estimate the Cholesky factor from the covariance of the complementary ensemble, every
+    /// time a proposal is made
+    EnsembleCholesky,
+}
+
+/// Stretch move performed in a whitened parameter space
+///
+/// Positions are mapped into whitened coordinates using a [`Scaling`](enum.Scaling.html), a
+/// regular [`StretchMove`](struct.StretchMove.html) is made there, and the proposals are mapped
+/// back. This is aimed at problems whose parameters differ by many orders of magnitude. As the
+/// map is linear, the acceptance factor is the same as for the plain stretch move.
+///
+/// Note the stretch move is affine invariant, so in exact arithmetic a fixed scaling does not
+/// change the distribution of proposals; what changes is the scale of the quantities the
+/// proposal arithmetic works with. Ensemble-estimated scalings are re-computed from the
+/// complementary walkers on every proposal, falling back to the diagonal and then to the
+/// identity if the ensemble is degenerate.
+#[derive(Debug, Clone)]
+pub struct ScaledStretchMove {
+    stretch: StretchMove,
+    scaling: Scaling,
+}
+
+impl ScaledStretchMove {
+    /// Create a scaled stretch move with stretch scale parameter `a`
+    ///
+    /// Errors are handled by returning a [`Result`](../errors/type.Result.html) which contains
+    /// [`EmceeError::InvalidInputs`](../errors/enum.EmceeError.html) if a diagonal scale is not
+    /// positive and finite, or a Cholesky factor is not lower triangular with a positive
+    /// diagonal.
+    pub fn new(a: f64, scaling: Scaling) -> Result<ScaledStretchMove> {
+        match scaling {
+            Scaling::Diagonal(ref scale) => {
+                if !scale.iter().all(|value| value.is_finite() && *value > 0.0) {
+                    return Err(EmceeError::InvalidInputs(
+                        "diagonal scales must be positive and finite".into(),
+                    ));
+                }
+            }
+            Scaling::Cholesky(ref l) => {
+                if !linalg::is_valid_cholesky(l) {
+                    return Err(EmceeError::InvalidInputs(
+                        "the Cholesky factor must be lower triangular with a positive diagonal"
+                            .into(),
+                    ));
+                }
+            }
+            Scaling::EnsembleDiagonal | Scaling::EnsembleCholesky => {}
+        }
+
+        Ok(ScaledStretchMove {
+            stretch: StretchMove::new(a),
+            scaling,
+        })
+    }
+
+    /// The scaling defining the whitened space
+    pub fn scaling(&self) -> &Scaling {
+        &self.scaling
+    }
+
+    /// Resolve the scaling to use for this proposal as a lower-triangular matrix
+    fn transform(&self, c: &[Guess], dim: usize) -> Vec<Vec<f64>> {
+        let diagonal = |scale: &[f64]| {
+            (0..dim)
+                .map(|i| {
+                    let mut row = vec![0f64; dim];
+                    row[i] = scale[i];
+                    row
+                })
+                .collect::<Vec<_>>()
+        };
+
+        match self.scaling {
+            Scaling::Diagonal(ref scale) => diagonal(scale),
+            Scaling::Cholesky(ref l) => l.clone(),
+            Scaling::EnsembleDiagonal | Scaling::EnsembleCholesky => {
+                let (_, cov) = linalg::mean_covariance(c);
+                if self.scaling == Scaling::EnsembleCholesky {
+                    if let Some(l) = linalg::cholesky(&cov) {
+                        return l;
+                    }
+                }
+
+                let scale: Vec<f64> = (0..dim)
+                    .map(|i| {
+                        let sd = cov[i][i].sqrt();
+                        if sd.is_finite() && sd > 0.0 {
+                            sd
+                        } else {
+                            1.0
+                        }
+                    })
+                    .collect();
+                diagonal(&scale)
+            }
+        }
+    }
+}
+
+impl Move for ScaledStretchMove {
+    fn name(&self) -> &str {
+        "scaled-stretch"
+    }
+
+    fn propose(&mut self, s: &[Guess], c: &[Guess], rng: &mut dyn Rng) -> Proposal {
+        let dim = s.first().map_or(0, |guess| guess.values.len());
+        let l = self.transform(c, dim);
+        let whiten = |guess: &Guess| Guess {
+            values: linalg::lower_solve(&l, &guess.values),
+        };
+
+        let ws: Vec<Guess> = s.iter().map(&whiten).collect();
+        let wc: Vec<Guess> = c.iter().map(&whiten).collect();
+        let mut proposal = self.stretch.propose(&ws, &wc, rng);
+        for q in &mut proposal.q {
+            q.values = linalg::lower_mul(&l, &q.values);
+        }
+        proposal
+    }
+
+    fn tuning(&self) -> Option<f64> {
+        self.stretch.tuning()
+    }
+
+    fn set_tuning(&mut self, value: f64) {
+        self.stretch.set_tuning(value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::{SeedableRng, StdRng};
+
+    fn ensembles() -> (Vec<Guess>, Vec<Guess>) {
+        let s = vec![Guess::new(&[1e6, 1e-6]), Guess::new(&[2e6, 3e-6])];
+        let c = vec![
+            Guess::new(&[1.5e6, 2e-6]),
+            Guess::new(&[0.5e6, 4e-6]),
+            Guess::new(&[3e6, 1e-6]),
+        ];
+        (s, c)
+    }
+
+    #[test]
+    fn test_matches_plain_stretch() {
+        // Affine invariance: the same random draws give the same proposals in any linear frame
+        let (s, c) = ensembles();
+        let scalings = [
+            Scaling::Diagonal(vec![1e6, 1e-6]),
+            Scaling::Cholesky(vec![vec![2e6, 0.0], vec![1e-7, 3e-6]]),
+            Scaling::EnsembleDiagonal,
+            Scaling::EnsembleCholesky,
+        ];
+        for scaling in &scalings {
+            let mut scaled = ScaledStretchMove::new(2.0, scaling.clone()).unwrap();
+            let mut plain = StretchMove::new(2.0);
+            let a = scaled.propose(&s, &c, &mut StdRng::from_seed(&[5]));
+            let b = plain.propose(&s, &c, &mut StdRng::from_seed(&[5]));
+            assert_eq!(a.factors, b.factors);
+            for (qa, qb) in a.q.iter().zip(&b.q) {
+                assert_approx_eq!(qa[0] / qb[0], 1.0, 1e-9);
+                assert_approx_eq!(qa[1] / qb[1], 1.0, 1e-9);
+            }
+        }
+    }
+
+    #[test]
+    fn test_invalid_scaling() {
+        assert!(ScaledStretchMove::new(2.0, Scaling::Diagonal(vec![1.0, 0.0])).is_err());
+        assert!(
+            ScaledStretchMove::new(2.0, Scaling::Cholesky(vec![vec![1.0, 1.0], vec![0.0, 1.0]]))
+                .is_err()
+        );
+    }
+}
@@ -0,0 +1,105 @@
+use rand::Rng;
+use rand::distributions::{IndependentSample, Range};
+
+use guess::Guess;
+use super::{Move, Proposal};
+
+/// The affine-invariant stretch move of Goodman & Weare (2010)
+///
+/// Each walker is moved along the line joining it to a randomly chosen walker of the
+/// complementary ensemble, by a factor `z` drawn from `g(z) ∝ 1 / sqrt(z)` on `[1/a, a]`.
+#[derive(Debug, Clone)]
+pub struct StretchMove {
+    /// The scale parameter `a` of the proposal distribution (default 2)
+    pub a: f64,
+}
+
+impl StretchMove {
+    /// Create a stretch move with scale parameter `a`
+    pub fn new(a: f64) -> StretchMove {
+        assert!(a > 1.0, "the stretch scale parameter must be greater than 1");
+        StretchMove { a }
+    }
+}
+
+impl Default for StretchMove {
+    fn default() -> Self {
+        StretchMove::new(2.0)
+    }
+}
+
+impl Move for StretchMove {
+    fn name(&self) -> &str {
+        "stretch"
+    }
+
+    fn propose(&mut self, s: &[Guess], c: &[Guess], mut rng: &mut dyn Rng) -> Proposal {
+        let ns = s.len();
+        let nc = c.len();
+
+        let rint_range = Range::new(0usize, nc);
+        let unit_range = Range::new(0f64, 1f64);
+
+        let mut q = Vec::with_capacity(ns);
+        let mut factors = Vec::with_capacity(ns);
+        let mut sources = Vec::with_capacity(ns);
+        for sval in s {
+            let zz = ((self.a - 1.0) * unit_range.ind_sample(&mut rng) + 1.0f64).powf(2.0f64)
+                / self.a;
+            let rint = rint_range.ind_sample(&mut rng);
+
+            let mut values = Vec::with_capacity(sval.values.len());
+            for (param_i, s_param) in sval.values.iter().enumerate() {
+                let random_c = c[rint][param_i];
+                let guess_diff = random_c - s_param;
+                let new_value = random_c - zz * guess_diff;
+                values.push(new_value);
+            }
+            assert!(zz > 0.);
+            factors.push((sval.values.len() as f64 - 1.0) * zz.ln());
+            q.push(Guess { values });
+            sources.push(rint);
+        }
+
+        Proposal {
+            q,
+            factors,
+            sources,
+        }
+    }
+
+    /// The tuning parameter of the stretch move is `a - 1`
+    fn tuning(&self) -> Option<f64> {
+        Some(self.a - 1.0)
+    }
+
+    fn set_tuning(&mut self, value: f64) {
+        assert!(value > 0.0);
+        self.a = 1.0 + value;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::{SeedableRng, StdRng};
+
+    #[test]
+    fn test_stretch_proposals_lie_on_line() {
+        let s = vec![Guess::new(&[0.0, 0.0]), Guess::new(&[1.0, 1.0])];
+        let c = vec![Guess::new(&[2.0, 4.0])];
+        let mut rng = StdRng::from_seed(&[1, 2, 3, 4]);
+
+        let proposal = StretchMove::default().propose(&s, &c, &mut rng);
+        assert_eq!(proposal.q.len(), 2);
+        assert_eq!(proposal.factors.len(), 2);
+        for ((sval, qval), factor) in s.iter().zip(&proposal.q).zip(&proposal.factors) {
+            // z = (c - q) / (c - s) in every dimension, and (ndim - 1) ln z is the log factor
+            let z0 = (c[0][0] - qval[0]) / (c[0][0] - sval[0]);
+            let z1 = (c[0][1] - qval[1]) / (c[0][1] - sval[1]);
+            assert_approx_eq!(z0, z1);
+            assert!((0.5..=2.0).contains(&z0));
+            assert_approx_eq!(*factor, z0.ln());
+        }
+    }
+}
@@ -0,0 +1,156 @@
+use rand::Rng;
+use rand::distributions::{ChiSquared, IndependentSample, Normal};
+
+use errors::{EmceeError, Result};
+use guess::Guess;
+use linalg::{self, Matrix};
+use super::{Move, Proposal};
+
+/// Independence proposals from a multivariate Student-t fitted to a previous run
+///
+/// This is intended for a final "independence phase" after warmup: the mean and covariance of
+/// the warmup samples define a Student-t distribution with `dof` degrees of freedom, and every
+/// proposal is an independent draw from it. For nearly Gaussian posteriors almost every proposal
+/// is accepted, so the samples are close to independent and far fewer iterations are needed for
+/// precise quantile estimates. The heavy tails keep the sampler valid when the posterior is
+/// somewhat wider than the fit.
+#[derive(Debug, Clone)]
+pub struct StudentTMove {
+    dof: f64,
+    mean: Vec<f64>,
+    scale: Matrix,
+}
+
+impl StudentTMove {
+    /// Fit the proposal distribution to a set of samples, e.g. the
+    /// [`flatchain`](../struct.EnsembleSampler.html#method.flatchain) of a warmup run
+    ///
+    /// The scale matrix is chosen so the covariance of the proposal distribution matches the
+    /// sample covariance.
+    ///
+    /// Errors are handled by returning a [`Result`](../errors/type.Result.html) which contains
+    /// [`EmceeError::InvalidInputs`](../errors/enum.EmceeError.html) if `dof` is not greater than
+    /// 2, or the sample covariance is not positive definite.
+    pub fn fit(samples: &[Guess], dof: f64) -> Result<StudentTMove> {
+        if dof.is_nan() || dof <= 2.0 {
+            return Err(EmceeError::InvalidInputs(
+                "the number of degrees of freedom must be greater than 2".into(),
+            ));
+        }
+
+        if samples.len() < 2 {
+            return Err(EmceeError::InvalidInputs(
+                "at least two samples are required".into(),
+            ));
+        }
+
+        let (mean, mut cov) = linalg::mean_covariance(samples);
+        for row in &mut cov {
+            for value in row.iter_mut() {
+                *value *= (dof - 2.0) / dof;
+            }
+        }
+
+        let scale = linalg::cholesky(&cov).ok_or_else(|| {
+            EmceeError::InvalidInputs("the sample covariance is not positive definite".into())
+        })?;
+
+        Ok(StudentTMove { dof, mean, scale })
+    }
+
+    /// The location of the proposal distribution
+    pub fn mean(&self) -> &[f64] {
+        &self.mean
+    }
+
+    /// The number of degrees of freedom of the proposal distribution
+    pub fn dof(&self) -> f64 {
+        self.dof
+    }
+
+    /// Log density of the proposal distribution, up to a constant
+    fn lnq(&self, x: &[f64]) -> f64 {
+        let diff: Vec<f64> = x.iter().zip(&self.mean).map(|(x, m)| x - m).collect();
+        let w = linalg::lower_solve(&self.scale, &diff);
+        let r2 = w.iter().map(|x| x * x).sum::<f64>();
+        -0.5 * (self.dof + self.mean.len() as f64) * (r2 / self.dof).ln_1p()
+    }
+}
+
+impl Move for StudentTMove {
+    fn name(&self) -> &str {
+        "student-t"
+    }
+
+    fn propose(&mut self, s: &[Guess], _c: &[Guess], mut rng: &mut dyn Rng) -> Proposal {
+        let normal = Normal::new(0.0, 1.0);
+        let chi2 = ChiSquared::new(self.dof);
+
+        let mut q = Vec::with_capacity(s.len());
+        let mut factors = Vec::with_capacity(s.len());
+        for sval in s {
+            let z: Vec<f64> = (0..self.mean.len())
+                .map(|_| normal.ind_sample(&mut rng))
+                .collect();
+            let u = chi2.ind_sample(&mut rng);
+            let stretch = (self.dof / u).sqrt();
+            let offset = linalg::lower_mul(&self.scale, &z);
+            let values: Vec<f64> = self.mean
+                .iter()
+                .zip(&offset)
+                .map(|(m, o)| m + o * stretch)
+                .collect();
+
+            factors.push(self.lnq(&sval.values) - self.lnq(&values));
+            q.push(Guess { values });
+        }
+
+        Proposal {
+            q,
+            factors,
+            sources: Vec::new(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::{SeedableRng, StdRng};
+
+    fn samples() -> Vec<Guess> {
+        vec![
+            Guess::new(&[0.0, 1.0]),
+            Guess::new(&[2.0, 0.0]),
+            Guess::new(&[1.0, 3.0]),
+            Guess::new(&[-1.0, 1.0]),
+        ]
+    }
+
+    #[test]
+    fn test_fit() {
+        let m = StudentTMove::fit(&samples(), 5.0).unwrap();
+        assert_approx_eq!(m.mean()[0], 0.5);
+        assert_approx_eq!(m.mean()[1], 1.25);
+
+        assert!(StudentTMove::fit(&samples(), 2.0).is_err());
+        assert!(StudentTMove::fit(&samples()[..1], 5.0).is_err());
+        let degenerate = vec![Guess::new(&[1.0, 1.0]), Guess::new(&[2.0, 2.0])];
+        assert!(StudentTMove::fit(&degenerate, 5.0).is_err());
+    }
+
+    #[test]
+    fn test_acceptance_factor() {
+        let mut m = StudentTMove::fit(&samples(), 5.0).unwrap();
+        let s = vec![Guess::new(&[0.5, 1.25]), Guess::new(&[30.0, -20.0])];
+        let proposal = m.propose(&s, &[], &mut StdRng::from_seed(&[3]));
+
+        // The factor corrects for the proposal density, so leaving its mode is favoured and
+        // leaving the far tails (which the proposal rarely revisits) is penalised
+        assert!(proposal.factors[0] >= 0.0);
+        assert!(proposal.factors[1] < 0.0);
+        for ((sval, q), factor) in s.iter().zip(&proposal.q).zip(&proposal.factors) {
+            assert_approx_eq!(*factor, m.lnq(&sval.values) - m.lnq(&q.values));
+        }
+    }
+}
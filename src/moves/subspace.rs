@@ -0,0 +1,120 @@
+use rand::Rng;
+
+use guess::Guess;
+use super::{Move, Proposal};
+
+/// Apply a move to a subset of the parameters, leaving the others unchanged
+///
+/// The positions are projected onto the parameters in `indices`, the inner move makes its
+/// proposal in that subspace, and the result is embedded back into the full space. The
+/// acceptance factors of the inner move are used unchanged, as they only depend on the
+/// dimension of the subspace the move operates in.
+///
+/// This is the building block of parameter groups, see
+/// [`EnsembleSampler::set_groups`](../struct.EnsembleSampler.html#method.set_groups).
+pub struct SubspaceMove {
+    name: String,
+    indices: Vec<usize>,
+    inner: Box<dyn Move>,
+}
+
+impl SubspaceMove {
+    /// Create a move which applies `inner` to the parameters in `indices`
+    pub fn new(indices: Vec<usize>, inner: Box<dyn Move>) -> SubspaceMove {
+        assert!(!indices.is_empty(), "a subspace needs at least one parameter");
+        let name = format!(
+            "{}[{}]",
+            inner.name(),
+            indices
+                .iter()
+                .map(|idx| idx.to_string())
+                .collect::<Vec<_>>()
+                .join(",")
+        );
+        SubspaceMove {
+            name,
+            indices,
+            inner,
+        }
+    }
+
+    /// The indices of the parameters this move updates
+    pub fn indices(&self) -> &[usize] {
+        &self.indices
+    }
+
+    fn project(&self, positions: &[Guess]) -> Vec<Guess> {
+        positions
+            .iter()
+            .map(|guess| Guess {
+                values: self.indices.iter().map(|&idx| guess[idx]).collect(),
+            })
+            .collect()
+    }
+}
+
+impl Move for SubspaceMove {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn propose(&mut self, s: &[Guess], c: &[Guess], rng: &mut dyn Rng) -> Proposal {
+        let sub = self.inner.propose(&self.project(s), &self.project(c), rng);
+        let q = s.iter()
+            .zip(&sub.q)
+            .map(|(current, proposed)| {
+                let mut q = current.clone();
+                for (&idx, value) in self.indices.iter().zip(&proposed.values) {
+                    q[idx] = *value;
+                }
+                q
+            })
+            .collect();
+        Proposal {
+            q,
+            factors: sub.factors,
+            sources: sub.sources,
+        }
+    }
+
+    fn tuning(&self) -> Option<f64> {
+        self.inner.tuning()
+    }
+
+    fn set_tuning(&mut self, value: f64) {
+        self.inner.set_tuning(value)
+    }
+
+    fn reset(&mut self) {
+        self.inner.reset()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use moves::StretchMove;
+    use rand::{SeedableRng, StdRng};
+
+    #[test]
+    fn test_only_selected_parameters_change() {
+        let s: Vec<Guess> = (0..4)
+            .map(|i| Guess::new(&[i as f64, 10.0 + i as f64, -(i as f64)]))
+            .collect();
+        let c: Vec<Guess> = (0..4)
+            .map(|i| Guess::new(&[2.0 * i as f64, 5.0 * i as f64, i as f64]))
+            .collect();
+
+        let mut m = SubspaceMove::new(vec![0, 2], Box::new(StretchMove::default()));
+        assert_eq!(m.name(), "stretch[0,2]");
+        let proposal = m.propose(&s, &c, &mut StdRng::from_seed(&[1]));
+        assert_eq!(proposal.q.len(), 4);
+        for (current, proposed) in s.iter().zip(&proposal.q) {
+            assert_eq!(current[1], proposed[1]);
+            assert!(current[0] != proposed[0] || current[2] != proposed[2]);
+        }
+
+        m.set_tuning(3.0);
+        assert_eq!(m.tuning(), Some(3.0));
+    }
+}
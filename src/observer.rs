@@ -0,0 +1,48 @@
+//! Notification of every accepted and rejected proposal
+
+use guess::Guess;
+
+/// A single proposal and its outcome, passed to a
+/// [`ProposalObserver`](trait.ProposalObserver.html)
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ProposalEvent<'a> {
+    /// The walker the proposal was made for
+    pub walker: usize,
+
+    /// The number of steps the sampler had taken before this one, counted since it was
+    /// created or [`reset`](struct.EnsembleSampler.html#method.reset)
+    pub iteration: usize,
+
+    /// Index of the move which generated the proposal, in the order given to
+    /// [`set_moves`](struct.EnsembleSampler.html#method.set_moves)
+    pub move_idx: usize,
+
+    /// The position of the walker before the step
+    pub old: &'a Guess,
+
+    /// The proposed position
+    pub new: &'a Guess,
+
+    /// The log posterior probability at the old position
+    pub old_lnprob: f64,
+
+    /// The log posterior probability at the proposed position; `-inf` for proposals which
+    /// failed [`Prob::validate`](trait.Prob.html#method.validate), were rejected by an
+    /// approximation before the full model was evaluated, or lie outside the support
+    pub new_lnprob: f64,
+}
+
+/// Receives every proposal made by a sampler, with its outcome
+///
+/// Install an observer with
+/// [`EnsembleSampler::set_observer`](struct.EnsembleSampler.html#method.set_observer) for
+/// bookkeeping the sampler does not do itself, e.g. counting the proposals which leave the
+/// prior through each boundary. Either method is called once per walker per step, after the
+/// acceptance test and before the walker is moved, so implementations should be cheap.
+pub trait ProposalObserver: Send {
+    /// Called for each proposal which was accepted
+    fn on_accept(&mut self, _event: &ProposalEvent) {}
+
+    /// Called for each proposal which was rejected
+    fn on_reject(&mut self, _event: &ProposalEvent) {}
+}
@@ -0,0 +1,64 @@
+//! Data-parallel helpers for post-processing large chains
+//!
+//! The work is split into contiguous ranges of items, one per thread, and the results are
+//! gathered in item order. Each item is computed exactly as it would be serially, so the
+//! results do not depend on the number of threads.
+
+use std::thread;
+
+/// Below this many values the work is done on the calling thread, as spawning threads would
+/// cost more than it saves
+const MIN_PARALLEL_VALUES: usize = 1 << 18;
+
+/// The number of threads to use for work touching `nvalues` values
+pub(crate) fn nthreads(nvalues: usize) -> usize {
+    if nvalues < MIN_PARALLEL_VALUES {
+        1
+    } else {
+        thread::available_parallelism().map_or(1, |n| n.get())
+    }
+}
+
+/// Compute `f(i)` for every `i` in `0..n` on up to `nthreads` threads, in order of `i`
+pub(crate) fn map<T, F>(n: usize, nthreads: usize, f: F) -> Vec<T>
+where
+    T: Send,
+    F: Fn(usize) -> T + Sync,
+{
+    let nthreads = nthreads.min(n);
+    if nthreads <= 1 {
+        return (0..n).map(f).collect();
+    }
+
+    let per_thread = n.div_ceil(nthreads);
+    let f = &f;
+    thread::scope(|scope| {
+        let handles: Vec<_> = (0..n)
+            .step_by(per_thread)
+            .map(|start| {
+                let end = (start + per_thread).min(n);
+                scope.spawn(move || (start..end).map(f).collect::<Vec<T>>())
+            })
+            .collect();
+        handles
+            .into_iter()
+            .flat_map(|handle| handle.join().expect("post-processing thread panicked"))
+            .collect()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_map() {
+        let expected: Vec<usize> = (0..1000).map(|i| i * i).collect();
+        for &nthreads in &[0, 1, 3, 8, 2000] {
+            assert_eq!(map(1000, nthreads, |i| i * i), expected);
+        }
+        assert!(map(0, 4, |i| i).is_empty());
+        assert_eq!(nthreads(10), 1);
+        assert!(nthreads(MIN_PARALLEL_VALUES) >= 1);
+    }
+}
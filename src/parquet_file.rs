@@ -0,0 +1,216 @@
+//! Parquet export of chains
+//!
+//! Enabled with the `parquet` feature.
+//! [`Chain::write_parquet`](../struct.Chain.html#method.write_parquet) and
+//! [`EnsembleSampler::write_parquet`](../struct.EnsembleSampler.html#method.write_parquet)
+//! write the same tidy table as the CSV exports, with one row per sample and columns
+//! `iteration`, `walker`, one per parameter and, from the sampler, `lnprob`. The file is
+//! typed and compressed, and loads directly with `pandas.read_parquet` or
+//! `polars.read_parquet`, e.g. for corner plots:
+//!
+//! ```rust,ignore
+//! sampler.set_param_names(&["m", "c"])?;
+//! sampler.run_mcmc(&p0, 5000)?;
+//! sampler.write_parquet("chain.parquet", 1000, 10)?;
+//! ```
+
+use std::fs::File;
+use std::io::Write;
+use std::path::Path;
+use std::sync::Arc;
+
+use arrow::array::{ArrayRef, Float64Array, UInt32Array, UInt64Array};
+use arrow::datatypes::{DataType, Field, Schema};
+use arrow::record_batch::RecordBatch;
+use parquet::arrow::ArrowWriter;
+use parquet::errors::ParquetError;
+
+use csv;
+use errors::{EmceeError, Result};
+use stores::{self, Chain, ProbStore};
+use {EnsembleSampler, Prob};
+
+impl From<ParquetError> for EmceeError {
+    fn from(e: ParquetError) -> EmceeError {
+        EmceeError::Msg(format!("parquet error: {}", e))
+    }
+}
+
+impl Chain {
+    /// Write the samples after the first `discard` iterations, keeping every `thin`th
+    /// iteration, to a Parquet file at `path`
+    ///
+    /// The table has the columns of [`write_csv`](#method.write_csv).
+    ///
+    /// Errors are handled by returning a [`Result`](errors/type.Result.html) which contains
+    /// [`EmceeError::InvalidInputs`](errors/enum.EmceeError.html) if `thin` is zero, or
+    /// [`EmceeError::Io`](errors/enum.EmceeError.html) if writing fails.
+    pub fn write_parquet<P: AsRef<Path>>(
+        &self,
+        path: P,
+        discard: usize,
+        thin: usize,
+    ) -> Result<()> {
+        self.write_parquet_to(File::create(path)?, discard, thin)
+    }
+
+    /// Write the samples as Parquet to `writer`, as in [`write_parquet`](#method.write_parquet)
+    ///
+    /// Errors are handled by returning a [`Result`](errors/type.Result.html) which contains
+    /// [`EmceeError::InvalidInputs`](errors/enum.EmceeError.html) if `thin` is zero, or
+    /// [`EmceeError::Io`](errors/enum.EmceeError.html) if writing fails.
+    pub fn write_parquet_to<W: Write + Send>(
+        &self,
+        writer: W,
+        discard: usize,
+        thin: usize,
+    ) -> Result<()> {
+        write_table(writer, self, None, discard, thin)
+    }
+}
+
+impl<'a, T: Prob + 'a> EnsembleSampler<'a, T> {
+    /// Write the stored samples and their log probabilities to a Parquet file at `path`
+    ///
+    /// The table has the columns of [`write_csv`](#method.write_csv).
+    ///
+    /// Errors are handled by returning a [`Result`](errors/type.Result.html) which contains
+    /// [`EmceeError::InvalidInputs`](errors/enum.EmceeError.html) if no chain is stored or
+    /// `thin` is zero, or [`EmceeError::Io`](errors/enum.EmceeError.html) if writing fails.
+    pub fn write_parquet<P: AsRef<Path>>(
+        &self,
+        path: P,
+        discard: usize,
+        thin: usize,
+    ) -> Result<()> {
+        self.write_parquet_to(File::create(path)?, discard, thin)
+    }
+
+    /// Write the stored samples and their log probabilities as Parquet to `writer`, as in
+    /// [`write_parquet`](#method.write_parquet)
+    ///
+    /// Errors are handled by returning a [`Result`](errors/type.Result.html) which contains
+    /// [`EmceeError::InvalidInputs`](errors/enum.EmceeError.html) if no chain is stored or
+    /// `thin` is zero, or [`EmceeError::Io`](errors/enum.EmceeError.html) if writing fails.
+    pub fn write_parquet_to<W: Write + Send>(
+        &self,
+        writer: W,
+        discard: usize,
+        thin: usize,
+    ) -> Result<()> {
+        let (chain, probstore) = self.stored()?;
+        write_table(writer, chain, Some(probstore), discard, thin)
+    }
+}
+
+fn write_table<W: Write + Send>(
+    writer: W,
+    chain: &Chain,
+    probstore: Option<&ProbStore>,
+    discard: usize,
+    thin: usize,
+) -> Result<()> {
+    let iterations: Vec<usize> =
+        stores::kept_iterations(chain.niterations, discard, thin)?.collect();
+
+    let mut fields = vec![
+        Field::new("iteration", DataType::UInt64, false),
+        Field::new("walker", DataType::UInt32, false),
+    ];
+    for name in csv::param_columns(chain) {
+        fields.push(Field::new(name, DataType::Float64, false));
+    }
+    if probstore.is_some() {
+        fields.push(Field::new("lnprob", DataType::Float64, false));
+    }
+
+    let nrows = iterations.len() * chain.nwalkers;
+    let mut iteration = Vec::with_capacity(nrows);
+    let mut walker = Vec::with_capacity(nrows);
+    let mut params = vec![Vec::with_capacity(nrows); chain.nparams];
+    let mut lnprob = Vec::with_capacity(nrows);
+    for &iter in &iterations {
+        for walker_idx in 0..chain.nwalkers {
+            iteration.push(iter as u64);
+            walker.push(walker_idx as u32);
+            for (param_idx, column) in params.iter_mut().enumerate() {
+                column.push(chain.get(param_idx, walker_idx, iter));
+            }
+            if let Some(probstore) = probstore {
+                lnprob.push(probstore.get(walker_idx, iter));
+            }
+        }
+    }
+
+    let mut columns: Vec<ArrayRef> = vec![
+        Arc::new(UInt64Array::from(iteration)),
+        Arc::new(UInt32Array::from(walker)),
+    ];
+    for column in params {
+        columns.push(Arc::new(Float64Array::from(column)));
+    }
+    if probstore.is_some() {
+        columns.push(Arc::new(Float64Array::from(lnprob)));
+    }
+
+    let schema = Arc::new(Schema::new(fields));
+    let batch = RecordBatch::try_new(schema.clone(), columns)?;
+    let mut writer = ArrowWriter::try_new(writer, schema, None)?;
+    writer.write(&batch)?;
+    writer.close()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder;
+    use std::env;
+
+    #[test]
+    fn test_write_parquet() {
+        let mut chain = Chain::new(2, 2, 9);
+        let mut probstore = ProbStore::new(2, 9);
+        for iter in 0..9 {
+            for walker in 0..2 {
+                chain.set_params(walker, iter, &[iter as f64, walker as f64]);
+                probstore.set(walker, iter, -(iter as f64));
+            }
+        }
+        chain.set_param_names(&["m", "c"]).unwrap();
+
+        let path = env::temp_dir().join(format!("emcee-chain-{}.parquet", ::std::process::id()));
+        write_table(File::create(&path).unwrap(), &chain, Some(&probstore), 3, 3).unwrap();
+
+        let reader = ParquetRecordBatchReaderBuilder::try_new(File::open(&path).unwrap())
+            .unwrap()
+            .build()
+            .unwrap();
+        let batches: Vec<RecordBatch> = reader.map(|batch| batch.unwrap()).collect();
+        ::std::fs::remove_file(&path).unwrap();
+
+        let batch = &batches[0];
+        assert_eq!(batch.num_rows(), 4);
+        let schema = batch.schema();
+        let names: Vec<&str> = schema
+            .fields()
+            .iter()
+            .map(|field| field.name().as_str())
+            .collect();
+        assert_eq!(names, ["iteration", "walker", "m", "c", "lnprob"]);
+
+        // Iterations 5 and 8, as chain[discard + thin - 1::thin] in emcee
+        let iteration = batch
+            .column(0)
+            .as_any()
+            .downcast_ref::<UInt64Array>()
+            .unwrap();
+        assert_eq!(iteration.value(2), 8);
+        let lnprob = batch
+            .column(4)
+            .as_any()
+            .downcast_ref::<Float64Array>()
+            .unwrap();
+        assert_eq!(lnprob.value(1), -5.0);
+    }
+}
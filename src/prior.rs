@@ -0,0 +1,443 @@
+//! Ready-made priors, and bounded parameters without boilerplate
+//!
+//! A [`Prior`](trait.Prior.html) gives the log density of one or more parameters. The common
+//! one-parameter priors are [`Uniform`](struct.Uniform.html), [`Gaussian`](struct.Gaussian.html)
+//! and [`LogUniform`](struct.LogUniform.html), and [`Independent`](struct.Independent.html)
+//! puts one prior on each parameter of a model. Wrapping a likelihood in a
+//! [`PriorWrappedProb`](struct.PriorWrappedProb.html) adds the prior to it, and rejects
+//! positions outside the bounds of the prior without evaluating the likelihood.
+//!
+//! ```rust
+//! # use emcee::prior::{Gaussian, Independent, LogUniform, PriorWrappedProb, Uniform};
+//! # use emcee::{with_data, EnsembleSampler, Guess, Prob};
+//! let x = vec![1.0, 2.0, 3.0, 4.0];
+//! let y = vec![3.1, 4.9, 7.2, 8.8];
+//!
+//! // A slope, an intercept and the scatter about the line
+//! let prior = Independent::new(vec![
+//!     Box::new(Uniform::new(-5.0, 5.0).unwrap()),
+//!     Box::new(Gaussian::new(0.0, 10.0).unwrap()),
+//!     Box::new(LogUniform::new(1e-3, 10.0).unwrap()),
+//! ]);
+//! let likelihood = with_data((x, y), |params: &Guess, data: &(Vec<f64>, Vec<f64>)| {
+//!     let (m, c, s) = (params[0], params[1], params[2]);
+//!     let (ref x, ref y) = *data;
+//!     x.iter()
+//!         .zip(y)
+//!         .map(|(x, y)| -0.5 * ((y - (m * x + c)) / s).powi(2) - s.ln())
+//!         .sum::<f64>()
+//! });
+//!
+//! let model = PriorWrappedProb::new(prior, likelihood);
+//! assert_eq!(model.lnprob(&Guess::new(&[2.0, 1.0, -1.0])), -std::f64::INFINITY);
+//! let sampler = EnsembleSampler::new(8, 3, &model).unwrap();
+//! # let _ = sampler;
+//! ```
+
+use std::f64::consts::PI;
+use std::time::Instant;
+
+use errors::{EmceeError, Result};
+use guess::Guess;
+use prob::Prob;
+use Pool;
+
+/// The prior probability of one or more parameters
+pub trait Prior: Send + Sync {
+    /// The number of parameters the prior is over
+    fn ndim(&self) -> usize {
+        1
+    }
+
+    /// The natural logarithm of the prior density at `values`, which has
+    /// [`ndim`](#method.ndim) elements, or `-inf` outside the support
+    fn ln_density(&self, values: &[f64]) -> f64;
+}
+
+/// A flat prior between `lo` and `hi`, inclusive
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Uniform {
+    lo: f64,
+    hi: f64,
+}
+
+impl Uniform {
+    /// A flat prior between `lo` and `hi`
+    ///
+    /// Errors are handled by returning a [`Result`](../errors/type.Result.html) which contains
+    /// [`EmceeError::InvalidInputs`](../errors/enum.EmceeError.html) if either bound is not
+    /// finite or `lo` is not below `hi`.
+    pub fn new(lo: f64, hi: f64) -> Result<Uniform> {
+        if !(lo.is_finite() && hi.is_finite() && lo < hi) {
+            return Err(EmceeError::InvalidInputs(format!(
+                "a uniform prior needs finite bounds with lo < hi, not [{}, {}]",
+                lo, hi
+            )));
+        }
+        Ok(Uniform { lo, hi })
+    }
+}
+
+impl Prior for Uniform {
+    fn ln_density(&self, values: &[f64]) -> f64 {
+        if values[0] >= self.lo && values[0] <= self.hi {
+            -(self.hi - self.lo).ln()
+        } else {
+            -f64::INFINITY
+        }
+    }
+}
+
+/// A normal prior with mean `mean` and standard deviation `sigma`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Gaussian {
+    mean: f64,
+    sigma: f64,
+}
+
+impl Gaussian {
+    /// A normal prior with mean `mean` and standard deviation `sigma`
+    ///
+    /// Errors are handled by returning a [`Result`](../errors/type.Result.html) which contains
+    /// [`EmceeError::InvalidInputs`](../errors/enum.EmceeError.html) if the mean is not finite
+    /// or `sigma` is not positive and finite.
+    pub fn new(mean: f64, sigma: f64) -> Result<Gaussian> {
+        if !(mean.is_finite() && sigma.is_finite() && sigma > 0.0) {
+            return Err(EmceeError::InvalidInputs(format!(
+                "a Gaussian prior needs a finite mean and positive sigma, not {} and {}",
+                mean, sigma
+            )));
+        }
+        Ok(Gaussian { mean, sigma })
+    }
+}
+
+impl Prior for Gaussian {
+    fn ln_density(&self, values: &[f64]) -> f64 {
+        let z = (values[0] - self.mean) / self.sigma;
+        -0.5 * (z * z + (2.0 * PI * self.sigma * self.sigma).ln())
+    }
+}
+
+/// A prior flat in the logarithm of the parameter between `lo` and `hi`, inclusive
+///
+/// The usual uninformative prior for a positive scale, e.g. a noise level, whose order of
+/// magnitude is unknown. The density is `1 / (x ln(hi / lo))`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LogUniform {
+    lo: f64,
+    hi: f64,
+}
+
+impl LogUniform {
+    /// A prior flat in `ln x` between `lo` and `hi`
+    ///
+    /// Errors are handled by returning a [`Result`](../errors/type.Result.html) which contains
+    /// [`EmceeError::InvalidInputs`](../errors/enum.EmceeError.html) unless
+    /// `0 < lo < hi < inf`.
+    pub fn new(lo: f64, hi: f64) -> Result<LogUniform> {
+        if !(lo > 0.0 && hi.is_finite() && lo < hi) {
+            return Err(EmceeError::InvalidInputs(format!(
+                "a log-uniform prior needs bounds with 0 < lo < hi, not [{}, {}]",
+                lo, hi
+            )));
+        }
+        Ok(LogUniform { lo, hi })
+    }
+}
+
+impl Prior for LogUniform {
+    fn ln_density(&self, values: &[f64]) -> f64 {
+        let x = values[0];
+        if x >= self.lo && x <= self.hi {
+            -x.ln() - (self.hi / self.lo).ln().ln()
+        } else {
+            -f64::INFINITY
+        }
+    }
+}
+
+/// Independent priors on consecutive parameters
+///
+/// The first prior covers the first [`ndim`](trait.Prior.html#method.ndim) parameters, the
+/// next the following ones, and so on, and the log densities are summed.
+pub struct Independent {
+    priors: Vec<Box<dyn Prior>>,
+}
+
+impl Independent {
+    /// Combine the given priors, in the order of the parameters
+    pub fn new(priors: Vec<Box<dyn Prior>>) -> Independent {
+        Independent { priors }
+    }
+}
+
+impl Prior for Independent {
+    fn ndim(&self) -> usize {
+        self.priors.iter().map(|prior| prior.ndim()).sum()
+    }
+
+    fn ln_density(&self, values: &[f64]) -> f64 {
+        let mut start = 0;
+        let mut total = 0.0;
+        for prior in &self.priors {
+            let end = start + prior.ndim();
+            total += prior.ln_density(&values[start..end]);
+            if total == -f64::INFINITY {
+                break;
+            }
+            start = end;
+        }
+        total
+    }
+}
+
+/// A likelihood together with a prior on its parameters
+///
+/// The log prior is that of the [`Prior`](trait.Prior.html), added to any prior of the wrapped
+/// model, and positions where it is `-inf`, e.g. outside the bounds of a
+/// [`Uniform`](struct.Uniform.html) prior, are rejected without evaluating the likelihood.
+/// Approximations, batching, retries, timeouts and the thread pool of the wrapped model are
+/// kept, each with the prior added.
+/// The wrapped model is typically a likelihood from [`likelihood`](../likelihood/index.html)
+/// or a function packaged with [`with_data`](../fn.with_data.html).
+///
+/// # Panics
+///
+/// Evaluating the prior panics if the number of parameters is not the
+/// [`ndim`](trait.Prior.html#method.ndim) of the prior.
+pub struct PriorWrappedProb<P, F> {
+    prior: P,
+    model: F,
+}
+
+impl<P: Prior, F: Prob> PriorWrappedProb<P, F> {
+    /// Add `prior` to the likelihood `model`
+    pub fn new(prior: P, model: F) -> PriorWrappedProb<P, F> {
+        PriorWrappedProb { prior, model }
+    }
+
+    /// The prior
+    pub fn prior(&self) -> &P {
+        &self.prior
+    }
+
+    /// The wrapped model
+    pub fn model(&self) -> &F {
+        &self.model
+    }
+
+    /// The log density of the prior alone
+    fn ln_density(&self, params: &Guess) -> f64 {
+        assert_eq!(
+            params.values.len(),
+            self.prior.ndim(),
+            "the prior is over {} parameters, the position has {}",
+            self.prior.ndim(),
+            params.values.len()
+        );
+        self.prior.ln_density(&params.values)
+    }
+}
+
+impl<P: Prior, F: Prob> Prob for PriorWrappedProb<P, F> {
+    fn lnlike(&self, params: &Guess) -> f64 {
+        self.model.lnlike(params)
+    }
+
+    fn lnprior(&self, params: &Guess) -> f64 {
+        let lnprior = self.ln_density(params);
+        if lnprior == -f64::INFINITY {
+            lnprior
+        } else {
+            lnprior + self.model.lnprior(params)
+        }
+    }
+
+    fn validate(&self, params: &Guess) -> ::std::result::Result<(), String> {
+        self.model.validate(params)
+    }
+
+    fn lnprob(&self, params: &Guess) -> f64 {
+        let lnprior = self.ln_density(params);
+        if lnprior == -f64::INFINITY {
+            lnprior
+        } else {
+            lnprior + self.model.lnprob(params)
+        }
+    }
+
+    fn try_lnprob(&self, params: &Guess) -> ::std::result::Result<f64, String> {
+        let lnprior = self.ln_density(params);
+        if lnprior == -f64::INFINITY {
+            Ok(lnprior)
+        } else {
+            self.model.try_lnprob(params).map(|lnprob| lnprior + lnprob)
+        }
+    }
+
+    fn lnprob_with_deadline(&self, params: &Guess, deadline: Instant) -> Option<f64> {
+        let lnprior = self.ln_density(params);
+        if lnprior == -f64::INFINITY {
+            Some(lnprior)
+        } else {
+            self.model
+                .lnprob_with_deadline(params, deadline)
+                .map(|lnprob| lnprior + lnprob)
+        }
+    }
+
+    fn lnprob_in_pool(&self, params: &Guess, pool: &mut Pool) -> f64 {
+        let lnprior = self.ln_density(params);
+        if lnprior == -f64::INFINITY {
+            lnprior
+        } else {
+            lnprior + self.model.lnprob_in_pool(params, pool)
+        }
+    }
+
+    fn is_batched(&self) -> bool {
+        self.model.is_batched()
+    }
+
+    fn lnprob_batch(&self, positions: &[Guess], out: &mut [f64]) {
+        // Only the positions inside the prior are handed to the model
+        let lnpriors: Vec<f64> = positions.iter().map(|params| self.ln_density(params)).collect();
+        let inside: Vec<Guess> = positions
+            .iter()
+            .zip(&lnpriors)
+            .filter(|&(_, &lnprior)| lnprior != -f64::INFINITY)
+            .map(|(params, _)| params.clone())
+            .collect();
+        let mut lnprobs = vec![0.0; inside.len()];
+        if !inside.is_empty() {
+            self.model.lnprob_batch(&inside, &mut lnprobs);
+        }
+
+        let mut lnprobs = lnprobs.into_iter();
+        for (out, lnprior) in out.iter_mut().zip(lnpriors) {
+            *out = if lnprior == -f64::INFINITY {
+                lnprior
+            } else {
+                lnprior + lnprobs.next().expect("one evaluation per position inside the prior")
+            };
+        }
+    }
+
+    fn napproximations(&self) -> usize {
+        self.model.napproximations()
+    }
+
+    fn lnprob_approximation(&self, params: &Guess, level: usize) -> f64 {
+        let lnprior = self.ln_density(params);
+        if lnprior == -f64::INFINITY {
+            lnprior
+        } else {
+            lnprior + self.model.lnprob_approximation(params, level)
+        }
+    }
+
+    fn stored_position(&self, params: &Guess) -> Option<Guess> {
+        self.model.stored_position(params)
+    }
+
+    fn sampled_position(&self, stored: &Guess) -> Option<Guess> {
+        self.model.sampled_position(stored)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Mutex;
+    use {with_data, Batched, BatchedProb, Cascade};
+
+    /// A flat likelihood counting its evaluations
+    struct Counting(AtomicUsize);
+
+    impl Prob for Counting {
+        fn lnlike(&self, _params: &Guess) -> f64 {
+            self.0.fetch_add(1, Ordering::SeqCst);
+            0.0
+        }
+
+        fn lnprior(&self, _params: &Guess) -> f64 {
+            0.0
+        }
+    }
+
+    #[test]
+    fn test_priors() {
+        assert_approx_eq!(Uniform::new(-1.0, 3.0).unwrap().ln_density(&[0.0]), -4f64.ln());
+        assert_approx_eq!(
+            Gaussian::new(1.0, 2.0).unwrap().ln_density(&[3.0]),
+            -0.5 - (8.0 * PI).sqrt().ln()
+        );
+        let log_uniform = LogUniform::new(1.0, 100.0).unwrap();
+        assert_approx_eq!(log_uniform.ln_density(&[10.0]), -(10f64 * 100f64.ln()).ln());
+        assert_eq!(log_uniform.ln_density(&[0.5]), -f64::INFINITY);
+
+        assert!(Uniform::new(1.0, 1.0).is_err());
+        assert!(Gaussian::new(0.0, 0.0).is_err());
+        assert!(LogUniform::new(0.0, 1.0).is_err());
+
+        let prior = Independent::new(vec![
+            Box::new(Uniform::new(0.0, 2.0).unwrap()),
+            Box::new(Gaussian::new(0.0, 1.0).unwrap()),
+        ]);
+        assert_eq!(prior.ndim(), 2);
+        let model = PriorWrappedProb::new(prior, Counting(AtomicUsize::new(0)));
+        assert_approx_eq!(
+            model.lnprob(&Guess::new(&[1.0, 0.0])),
+            -2f64.ln() - 0.5 * (2.0 * PI).ln()
+        );
+
+        // Outside the bounds the likelihood is never evaluated
+        assert_eq!(model.lnprob(&Guess::new(&[3.0, 0.0])), -f64::INFINITY);
+        assert_eq!(model.model().0.load(Ordering::SeqCst), 1);
+    }
+
+    /// A flat batched likelihood recording the size of every batch
+    struct Sizes(Mutex<Vec<usize>>);
+
+    impl BatchedProb for Sizes {
+        fn lnprob_batch(&self, positions: &[Guess], out: &mut [f64]) {
+            self.0.lock().unwrap().push(positions.len());
+            for out in out.iter_mut() {
+                *out = 1.0;
+            }
+        }
+    }
+
+    #[test]
+    fn test_forwarding() {
+        let coarse = with_data((), |params: &Guess, _: &()| -params[0]);
+        let full = with_data((), |params: &Guess, _: &()| -2.0 * params[0]);
+        let cascade = Cascade::new(vec![Box::new(coarse), Box::new(full)]).unwrap();
+        let model = PriorWrappedProb::new(Uniform::new(0.0, 2.0).unwrap(), cascade);
+        assert_eq!(model.napproximations(), 1);
+        let params = Guess::new(&[1.5]);
+        assert_approx_eq!(model.lnprob_approximation(&params, 0), -1.5 - 2f64.ln());
+        assert_approx_eq!(model.lnprob(&params), -3.0 - 2f64.ln());
+        assert_approx_eq!(model.try_lnprob(&params).unwrap(), -3.0 - 2f64.ln());
+        assert_eq!(model.lnprob_approximation(&Guess::new(&[3.0]), 0), -f64::INFINITY);
+
+        let sizes = Batched::new(Sizes(Mutex::new(Vec::new())));
+        let model = PriorWrappedProb::new(Uniform::new(0.0, 2.0).unwrap(), sizes);
+        assert!(model.is_batched());
+        let positions = [Guess::new(&[1.0]), Guess::new(&[3.0]), Guess::new(&[0.5])];
+        let mut out = [0.0; 3];
+        model.lnprob_batch(&positions, &mut out);
+        assert_eq!(out, [1.0 - 2f64.ln(), -f64::INFINITY, 1.0 - 2f64.ln()]);
+        assert_eq!(*model.model().model().0.lock().unwrap(), [2]);
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_prior_dimension() {
+        let prior = Uniform::new(0.0, 1.0).unwrap();
+        let model = PriorWrappedProb::new(prior, Counting(AtomicUsize::new(0)));
+        model.lnprior(&Guess::new(&[0.5, 0.5]));
+    }
+}
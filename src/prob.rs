@@ -1,4 +1,7 @@
+use std::time::Instant;
+
 use guess::Guess;
+use scoped_threadpool::Pool;
 
 /// Encapsulate the model evaluation
 ///
@@ -58,6 +61,19 @@ pub trait Prob : Send + Sync {
     /// Computes the natural logarithm of the prior probability of a position in parameter space
     fn lnprior(&self, params: &Guess) -> f64;
 
+    /// Checks that a proposed position is meaningful before it is evaluated
+    ///
+    /// Called on every proposal before [`lnprob`](trait.Prob.html#method.lnprob). A proposal
+    /// which fails validation is rejected without evaluating the model, and the returned message
+    /// is counted in
+    /// [`EnsembleSampler::validation_failures`](struct.EnsembleSampler.html#method.validation_failures),
+    /// so that e.g. bounds or transforms which produce physically meaningless parameters show up
+    /// in the diagnostics rather than as silently rejected proposals. The default accepts every
+    /// position.
+    fn validate(&self, _params: &Guess) -> ::std::result::Result<(), String> {
+        Ok(())
+    }
+
     /// Computes the natural logarithm of the log posterior probabilities
     fn lnprob(&self, params: &Guess) -> f64 {
         let lnp = self.lnprior(params);
@@ -67,4 +83,190 @@ pub trait Prob : Send + Sync {
             -::std::f64::INFINITY
         }
     }
+
+    /// Computes the log posterior probability, reporting transient failures
+    ///
+    /// Models which call out to network services or subprocesses should override this to return
+    /// an error when an evaluation fails for reasons unrelated to the position, e.g. a dropped
+    /// connection. The evaluation is then repeated according to the sampler's
+    /// [`retry`](struct.EnsembleSampler.html#structfield.retry) policy, and only if every attempt
+    /// fails is the proposal rejected, and counted in
+    /// [`RunStats::nfailures`](struct.RunStats.html#structfield.nfailures). This is the
+    /// evaluation used unless the sampler has a
+    /// [`timeout`](struct.EnsembleSampler.html#structfield.timeout) or hands its thread pool to
+    /// the model. The default calls [`lnprob`](trait.Prob.html#method.lnprob), which never
+    /// fails.
+    fn try_lnprob(&self, params: &Guess) -> ::std::result::Result<f64, String> {
+        Ok(self.lnprob(params))
+    }
+
+    /// Computes the log posterior probability, giving up at `deadline`
+    ///
+    /// Only called when the sampler has a [`timeout`](struct.EnsembleSampler.html#structfield.timeout).
+    /// Returning `None` signals that the evaluation was abandoned, and the proposal is rejected.
+    /// Models which wait on external processes or network services should override this to stop
+    /// waiting (e.g. kill the child process) once the deadline has passed; the default simply
+    /// calls [`lnprob`](trait.Prob.html#method.lnprob), so a slow evaluation is still waited for,
+    /// although its result is discarded.
+    fn lnprob_with_deadline(&self, params: &Guess, _deadline: Instant) -> Option<f64> {
+        Some(self.lnprob(params))
+    }
+
+    /// Computes the log posterior probability with the sampler's thread pool available
+    ///
+    /// Only called when the sampler evaluates walkers one at a time with
+    /// [`Parallelism::Evaluation`](enum.Parallelism.html), so the model can spread the work of a
+    /// single evaluation over every thread of the pool, e.g. with
+    /// [`Pool::scoped`](struct.Pool.html#method.scoped). The default ignores the pool and calls
+    /// [`lnprob`](trait.Prob.html#method.lnprob).
+    fn lnprob_in_pool(&self, params: &Guess, _pool: &mut Pool) -> f64 {
+        self.lnprob(params)
+    }
+
+    /// Whether the sampler evaluates the model a batch of positions at a time
+    ///
+    /// When `true`, the positions of each half-ensemble are handed to
+    /// [`lnprob_batch`](trait.Prob.html#method.lnprob_batch) in a single call instead of
+    /// evaluating [`lnprob`](trait.Prob.html#method.lnprob) once per walker. The default is
+    /// `false`; wrap a [`BatchedProb`](trait.BatchedProb.html) in
+    /// [`Batched`](struct.Batched.html) to sample it in batches.
+    fn is_batched(&self) -> bool {
+        false
+    }
+
+    /// Computes the log posterior probability of every position of `positions` at once,
+    /// writing each into the same index of `out`
+    ///
+    /// Only called when [`is_batched`](trait.Prob.html#method.is_batched) is `true`, on the
+    /// sampler's own thread, and never for approximations. The sampler's
+    /// [`timeout`](struct.EnsembleSampler.html#structfield.timeout),
+    /// [`retry`](struct.EnsembleSampler.html#structfield.retry) policy and thread pool do not
+    /// apply to batches. The default calls [`lnprob`](trait.Prob.html#method.lnprob) for each
+    /// position.
+    fn lnprob_batch(&self, positions: &[Guess], out: &mut [f64]) {
+        for (params, out) in positions.iter().zip(out.iter_mut()) {
+            *out = self.lnprob(params);
+        }
+    }
+
+    /// The number of cheaper approximations to the log posterior probability
+    ///
+    /// Models with approximations are sampled with delayed acceptance: each proposal is first
+    /// tested against approximation 0, then each surviving proposal against the next, more
+    /// expensive, approximation, and only proposals which survive every approximation are
+    /// evaluated with [`lnprob`](trait.Prob.html#method.lnprob). The acceptance test at each
+    /// stage corrects for the previous one, so the samples still follow the full posterior,
+    /// while most poor proposals are rejected cheaply. The default has no approximations. See
+    /// [`Cascade`](struct.Cascade.html) to combine separate models.
+    fn napproximations(&self) -> usize {
+        0
+    }
+
+    /// Computes approximation `level` to the log posterior probability
+    ///
+    /// Levels are ordered from the cheapest to the most expensive, and `level` is less than
+    /// [`napproximations`](trait.Prob.html#method.napproximations). The approximations should
+    /// be finite wherever the full posterior is.
+    fn lnprob_approximation(&self, params: &Guess, _level: usize) -> f64 {
+        self.lnprob(params)
+    }
+
+    /// The position to store for the sampled position `params`, or `None` to store `params`
+    /// itself
+    ///
+    /// Models which sample reparameterised coordinates return the position in their own
+    /// parameters, which the sampler then stores in its chain and uses for its running
+    /// statistics, quantile sketches and live snapshots. The positions and log probabilities of
+    /// each [`Step`](struct.Step.html), and the state the sampler continues from, stay in the
+    /// sampled coordinates. The default returns `None`; see
+    /// [`Transformed`](struct.Transformed.html).
+    fn stored_position(&self, _params: &Guess) -> Option<Guess> {
+        None
+    }
+
+    /// The sampled position for the stored position `stored`, the inverse of
+    /// [`stored_position`](trait.Prob.html#method.stored_position), or `None` if they are the
+    /// same
+    ///
+    /// The sampler uses this where densities of the model are compared with the stored chain,
+    /// such as for the [information gain](struct.EnsembleSampler.html#method.information_gain)
+    /// or a [whitening](struct.EnsembleSampler.html#method.set_whitening_from_chain) fitted to
+    /// the chain, so models which override `stored_position` should override this as well. The
+    /// default returns `None`.
+    fn sampled_position(&self, _stored: &Guess) -> Option<Guess> {
+        None
+    }
+}
+
+/// Package a dataset with a plain log probability function
+///
+/// The returned [`WithData`](struct.WithData.html) owns `data` and implements
+/// [`Prob`](trait.Prob.html) by calling `lnprob(params, &data)`, so the same dataset is shared
+/// by reference between all threads of a
+/// [`threaded`](struct.EnsembleSampler.html#method.threaded) sampler without wrapping it in an
+/// `Arc` or writing a model struct. The function returns the full log posterior probability,
+/// i.e. including any prior term.
+///
+/// ```rust
+/// # use emcee::{with_data, EnsembleSampler, Guess};
+/// let x = vec![1.0, 2.0, 3.0, 4.0];
+/// let y = vec![3.1, 4.9, 7.2, 8.8];
+///
+/// let model = with_data((x, y), |params: &Guess, data: &(Vec<f64>, Vec<f64>)| {
+///     let (m, c) = (params[0], params[1]);
+///     let (ref x, ref y) = *data;
+///     -0.5 * x.iter()
+///         .zip(y)
+///         .map(|(x, y)| (y - (m * x + c)).powi(2))
+///         .sum::<f64>()
+/// });
+///
+/// let sampler = EnsembleSampler::threaded(8, 2, &model, 4).unwrap();
+/// # let _ = sampler;
+/// ```
+pub fn with_data<D, F>(data: D, lnprob: F) -> WithData<D, F>
+where
+    D: Send + Sync,
+    F: Fn(&Guess, &D) -> f64 + Send + Sync,
+{
+    WithData { data, lnprob }
+}
+
+/// A log probability function together with the data it needs
+///
+/// Created by [`with_data`](fn.with_data.html).
+#[derive(Debug, Clone)]
+pub struct WithData<D, F> {
+    data: D,
+    lnprob: F,
+}
+
+impl<D, F> WithData<D, F> {
+    /// The packaged data
+    pub fn data(&self) -> &D {
+        &self.data
+    }
+
+    /// Recover the packaged data
+    pub fn into_data(self) -> D {
+        self.data
+    }
+}
+
+impl<D, F> Prob for WithData<D, F>
+where
+    D: Send + Sync,
+    F: Fn(&Guess, &D) -> f64 + Send + Sync,
+{
+    fn lnlike(&self, params: &Guess) -> f64 {
+        (self.lnprob)(params, &self.data)
+    }
+
+    fn lnprior(&self, _params: &Guess) -> f64 {
+        0.0
+    }
+
+    fn lnprob(&self, params: &Guess) -> f64 {
+        (self.lnprob)(params, &self.data)
+    }
 }
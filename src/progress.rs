@@ -0,0 +1,169 @@
+//! Progress reports from long runs
+
+use std::time::{Duration, Instant};
+
+/// The state of a run, passed to a [`ProgressReporter`](trait.ProgressReporter.html) after
+/// every iteration
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Progress {
+    /// The number of iterations completed in this run
+    pub iteration: usize,
+
+    /// The number of iterations requested for this run
+    pub niterations: usize,
+
+    /// The mean acceptance fraction of the walkers so far, as from
+    /// [`mean_acceptance_fraction`](struct.EnsembleSampler.html#method.mean_acceptance_fraction)
+    pub acceptance_fraction: f64,
+
+    /// The time since the run started
+    pub elapsed: Duration,
+}
+
+impl Progress {
+    /// The fraction of the run completed
+    pub fn fraction(&self) -> f64 {
+        if self.niterations == 0 {
+            1.0
+        } else {
+            self.iteration as f64 / self.niterations as f64
+        }
+    }
+
+    /// The estimated time until the run completes, assuming the remaining iterations take as
+    /// long as those so far, or `None` before the first iteration
+    pub fn remaining(&self) -> Option<Duration> {
+        if self.iteration == 0 {
+            return None;
+        }
+        let per_iteration = self.elapsed.as_secs_f64() / self.iteration as f64;
+        let left = self.niterations.saturating_sub(self.iteration) as f64;
+        Some(Duration::from_secs_f64(per_iteration * left))
+    }
+}
+
+/// Receives the progress of every run of a sampler
+///
+/// Install a reporter with
+/// [`EnsembleSampler::set_progress`](struct.EnsembleSampler.html#method.set_progress), e.g.
+/// [`TextProgress`](struct.TextProgress.html), or implement this trait to drive a progress bar
+/// or a log. [`report`](#tymethod.report) is called after every iteration, so implementations
+/// should limit how often they produce output.
+pub trait ProgressReporter: Send {
+    /// Called after every iteration of a run
+    fn report(&mut self, progress: &Progress);
+
+    /// Called once when a run ends, whether it completed or was stopped early
+    fn finish(&mut self, _progress: &Progress) {}
+}
+
+/// Prints the progress of each run to standard error at a fixed interval
+///
+/// Each line gives the iteration, the mean acceptance fraction and the estimated time
+/// remaining, e.g.
+///
+/// ```text
+/// iteration 1200/5000 (24.0%), acceptance 0.312, 1m 20s remaining
+/// ```
+#[derive(Debug, Clone)]
+pub struct TextProgress {
+    interval: Duration,
+    last: Option<Instant>,
+}
+
+impl TextProgress {
+    /// Print at most once every `interval`
+    pub fn new(interval: Duration) -> TextProgress {
+        TextProgress {
+            interval,
+            last: None,
+        }
+    }
+}
+
+impl Default for TextProgress {
+    /// Print at most once a second
+    fn default() -> Self {
+        TextProgress::new(Duration::from_secs(1))
+    }
+}
+
+impl ProgressReporter for TextProgress {
+    fn report(&mut self, progress: &Progress) {
+        let now = Instant::now();
+        let due = self.last.is_none_or(|last| now.duration_since(last) >= self.interval);
+        if due {
+            self.last = Some(now);
+            eprintln!("{}", describe(progress));
+        }
+    }
+
+    fn finish(&mut self, progress: &Progress) {
+        self.last = None;
+        eprintln!(
+            "finished {} iterations in {}, acceptance {:.3}",
+            progress.iteration,
+            format_duration(progress.elapsed),
+            progress.acceptance_fraction
+        );
+    }
+}
+
+/// One line of [`TextProgress`](struct.TextProgress.html) output
+fn describe(progress: &Progress) -> String {
+    let remaining = progress
+        .remaining()
+        .map_or_else(|| "unknown".to_string(), format_duration);
+    format!(
+        "iteration {}/{} ({:.1}%), acceptance {:.3}, {} remaining",
+        progress.iteration,
+        progress.niterations,
+        100.0 * progress.fraction(),
+        progress.acceptance_fraction,
+        remaining
+    )
+}
+
+/// Format a duration to the nearest second, e.g. `1h 02m 03s`
+fn format_duration(duration: Duration) -> String {
+    let seconds = duration.as_secs_f64().round() as u64;
+    let (hours, minutes, seconds) = (seconds / 3600, seconds / 60 % 60, seconds % 60);
+    if hours > 0 {
+        format!("{}h {:02}m {:02}s", hours, minutes, seconds)
+    } else if minutes > 0 {
+        format!("{}m {:02}s", minutes, seconds)
+    } else {
+        format!("{}s", seconds)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_progress() {
+        let progress = Progress {
+            iteration: 1200,
+            niterations: 5000,
+            acceptance_fraction: 0.3124,
+            elapsed: Duration::from_secs(24),
+        };
+        assert_eq!(progress.fraction(), 0.24);
+        assert_eq!(progress.remaining(), Some(Duration::from_secs(76)));
+        assert_eq!(
+            describe(&progress),
+            "iteration 1200/5000 (24.0%), acceptance 0.312, 1m 16s remaining"
+        );
+
+        let start = Progress {
+            iteration: 0,
+            ..progress
+        };
+        assert_eq!(start.remaining(), None);
+        assert!(describe(&start).ends_with("unknown remaining"));
+
+        assert_eq!(format_duration(Duration::from_secs(3723)), "1h 02m 03s");
+        assert_eq!(format_duration(Duration::from_millis(4600)), "5s");
+    }
+}
@@ -0,0 +1,327 @@
+//! Compact storage of chains as 16-bit integers
+
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Read, Write};
+use std::path::Path;
+use std::sync::Arc;
+
+use errors::{EmceeError, Result};
+use stores::Chain;
+
+const MAGIC: &[u8] = b"emcee-quantised 1\n";
+
+/// The largest quantised value
+const LEVELS: f64 = u16::MAX as f64;
+
+/// A chain with every value rounded to one of 65536 levels spanning the range of its parameter
+///
+/// Created by [`Chain::quantise`](struct.Chain.html#method.quantise). Each value takes two
+/// bytes instead of eight, for archiving enormous chains whose full precision is not needed:
+/// each parameter is stored as an offset, its minimum over the chain, a scale, and the
+/// number of scale steps of every value above the offset. Values are read back as `f64`
+/// transparently, within half a step, its [`resolution`](#method.resolution), of the
+/// original, which for any posterior wider than a few thousandths of the range of the chain is
+/// far below the Monte Carlo error.
+///
+/// [`save`](#method.save) and [`load`](#method.load) write and read a compact binary file.
+#[derive(Debug, Clone, PartialEq)]
+pub struct QuantisedChain {
+    nparams: usize,
+    nwalkers: usize,
+    niterations: usize,
+    offsets: Vec<f64>,
+    scales: Vec<f64>,
+    values: Vec<u16>,
+    names: Option<Arc<[String]>>,
+}
+
+impl Chain {
+    /// Round the chain to 16 bits per value, see [`QuantisedChain`](struct.QuantisedChain.html)
+    ///
+    /// Errors are handled by returning a [`Result`](errors/type.Result.html) which contains
+    /// [`EmceeError::InvalidInputs`](errors/enum.EmceeError.html) if any value is not finite.
+    pub fn quantise(&self) -> Result<QuantisedChain> {
+        let mut offsets = vec![f64::INFINITY; self.nparams];
+        let mut maxima = vec![-f64::INFINITY; self.nparams];
+        for iter in 0..self.niterations {
+            for walker in 0..self.nwalkers {
+                for param in 0..self.nparams {
+                    let value = self.get(param, walker, iter);
+                    if !value.is_finite() {
+                        return Err(EmceeError::InvalidInputs(format!(
+                            "parameter {} of walker {} is {} at iteration {}",
+                            param, walker, value, iter
+                        )));
+                    }
+                    offsets[param] = offsets[param].min(value);
+                    maxima[param] = maxima[param].max(value);
+                }
+            }
+        }
+        let scales: Vec<f64> = offsets
+            .iter()
+            .zip(&maxima)
+            .map(|(lo, hi)| if hi > lo { (hi - lo) / LEVELS } else { 0.0 })
+            .collect();
+
+        let mut values = Vec::with_capacity(self.niterations * self.nwalkers * self.nparams);
+        for iter in 0..self.niterations {
+            for walker in 0..self.nwalkers {
+                for param in 0..self.nparams {
+                    let step = if scales[param] > 0.0 {
+                        (self.get(param, walker, iter) - offsets[param]) / scales[param]
+                    } else {
+                        0.0
+                    };
+                    values.push(step.round().clamp(0.0, LEVELS) as u16);
+                }
+            }
+        }
+
+        Ok(QuantisedChain {
+            nparams: self.nparams,
+            nwalkers: self.nwalkers,
+            niterations: self.niterations,
+            offsets,
+            scales,
+            values,
+            names: self.names.clone(),
+        })
+    }
+}
+
+impl QuantisedChain {
+    /// The number of parameters per walker
+    pub fn nparams(&self) -> usize {
+        self.nparams
+    }
+
+    /// The number of walkers
+    pub fn nwalkers(&self) -> usize {
+        self.nwalkers
+    }
+
+    /// The number of stored iterations
+    pub fn niterations(&self) -> usize {
+        self.niterations
+    }
+
+    /// The names of the parameters, if the chain had them
+    pub fn param_names(&self) -> Option<&[String]> {
+        self.names.as_deref()
+    }
+
+    /// Get a single parameter value, restored to `f64`
+    pub fn get(&self, param_idx: usize, walker_idx: usize, iteration_idx: usize) -> f64 {
+        assert!(param_idx < self.nparams && walker_idx < self.nwalkers);
+        let index = (iteration_idx * self.nwalkers + walker_idx) * self.nparams + param_idx;
+        self.offsets[param_idx] + self.scales[param_idx] * f64::from(self.values[index])
+    }
+
+    /// The spacing between the levels of parameter `param_idx`; every value is restored within
+    /// half of it
+    pub fn resolution(&self, param_idx: usize) -> f64 {
+        self.scales[param_idx]
+    }
+
+    /// Restore the full chain, for the analysis methods of [`Chain`](struct.Chain.html)
+    pub fn to_chain(&self) -> Chain {
+        let mut chain = Chain::new(self.nparams, self.nwalkers, self.niterations);
+        chain.names = self.names.clone();
+        for iter in 0..self.niterations {
+            for walker in 0..self.nwalkers {
+                for param in 0..self.nparams {
+                    chain.set(param, walker, iter, self.get(param, walker, iter));
+                }
+            }
+        }
+        chain
+    }
+
+    /// Write the chain to a file at `path`, see [`write_to`](#method.write_to)
+    ///
+    /// Errors are handled by returning a [`Result`](errors/type.Result.html) which contains
+    /// [`EmceeError::Io`](errors/enum.EmceeError.html) if writing fails.
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        self.write_to(BufWriter::new(File::create(path)?))
+    }
+
+    /// Write the chain to `writer` in a compact binary format
+    ///
+    /// The format is a header line, the dimensions of the chain, any parameter names, the
+    /// offset and scale of each parameter and then the values, all little-endian, so a chain
+    /// of `n` values takes little more than `2n` bytes.
+    ///
+    /// Errors are handled by returning a [`Result`](errors/type.Result.html) which contains
+    /// [`EmceeError::Io`](errors/enum.EmceeError.html) if writing fails.
+    pub fn write_to<W: Write>(&self, mut writer: W) -> Result<()> {
+        writer.write_all(MAGIC)?;
+        for &n in &[self.nparams, self.nwalkers, self.niterations] {
+            writer.write_all(&(n as u64).to_le_bytes())?;
+        }
+        match self.names {
+            Some(ref names) => {
+                writer.write_all(&[1])?;
+                for name in names.iter() {
+                    writer.write_all(&(name.len() as u64).to_le_bytes())?;
+                    writer.write_all(name.as_bytes())?;
+                }
+            }
+            None => writer.write_all(&[0])?,
+        }
+        for (offset, scale) in self.offsets.iter().zip(&self.scales) {
+            writer.write_all(&offset.to_le_bytes())?;
+            writer.write_all(&scale.to_le_bytes())?;
+        }
+        let mut bytes = Vec::with_capacity(2 * self.values.len());
+        for value in &self.values {
+            bytes.extend_from_slice(&value.to_le_bytes());
+        }
+        writer.write_all(&bytes)?;
+        writer.flush()?;
+        Ok(())
+    }
+
+    /// Read a chain written by [`save`](#method.save) from the file at `path`
+    ///
+    /// Errors are handled by returning a [`Result`](errors/type.Result.html) which contains
+    /// [`EmceeError::InvalidInputs`](errors/enum.EmceeError.html) if the file is not a
+    /// quantised chain, or [`EmceeError::Io`](errors/enum.EmceeError.html) if reading fails.
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<QuantisedChain> {
+        QuantisedChain::read_from(BufReader::new(File::open(path)?))
+    }
+
+    /// Read a chain written by [`write_to`](#method.write_to) from `reader`
+    ///
+    /// Errors are handled by returning a [`Result`](errors/type.Result.html) which contains
+    /// [`EmceeError::InvalidInputs`](errors/enum.EmceeError.html) if the data are not a
+    /// quantised chain, or [`EmceeError::Io`](errors/enum.EmceeError.html) if reading fails.
+    pub fn read_from<R: Read>(mut reader: R) -> Result<QuantisedChain> {
+        let invalid = || EmceeError::InvalidInputs("not a quantised emcee chain".into());
+        let mut magic = vec![0u8; MAGIC.len()];
+        reader.read_exact(&mut magic)?;
+        if magic != MAGIC {
+            return Err(invalid());
+        }
+
+        let mut word = [0u8; 8];
+        let mut read_u64 = |reader: &mut R| -> Result<u64> {
+            reader.read_exact(&mut word)?;
+            Ok(u64::from_le_bytes(word))
+        };
+        let nparams = read_u64(&mut reader)? as usize;
+        let nwalkers = read_u64(&mut reader)? as usize;
+        let niterations = read_u64(&mut reader)? as usize;
+        let nvalues = nparams
+            .checked_mul(nwalkers)
+            .and_then(|n| n.checked_mul(niterations))
+            .ok_or_else(invalid)?;
+
+        let mut flag = [0u8];
+        reader.read_exact(&mut flag)?;
+        let names = match flag[0] {
+            0 => None,
+            1 => {
+                let mut names = Vec::new();
+                for _ in 0..nparams {
+                    let len = read_u64(&mut reader)?;
+                    let mut name = Vec::new();
+                    reader.by_ref().take(len).read_to_end(&mut name)?;
+                    if name.len() as u64 != len {
+                        return Err(invalid());
+                    }
+                    names.push(String::from_utf8(name).map_err(|_| invalid())?);
+                }
+                Some(names.into())
+            }
+            _ => return Err(invalid()),
+        };
+
+        let mut offsets = Vec::new();
+        let mut scales = Vec::new();
+        for _ in 0..nparams {
+            offsets.push(f64::from_bits(read_u64(&mut reader)?));
+            scales.push(f64::from_bits(read_u64(&mut reader)?));
+        }
+
+        // Read what is there rather than trusting the header with the allocation
+        let mut bytes = Vec::new();
+        let nbytes = nvalues.checked_mul(2).ok_or_else(invalid)?;
+        reader.take(nbytes as u64).read_to_end(&mut bytes)?;
+        if bytes.len() != nbytes {
+            return Err(invalid());
+        }
+        let values = bytes
+            .chunks(2)
+            .map(|pair| u16::from_le_bytes([pair[0], pair[1]]))
+            .collect();
+
+        Ok(QuantisedChain {
+            nparams,
+            nwalkers,
+            niterations,
+            offsets,
+            scales,
+            values,
+            names,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_quantise() {
+        let mut chain = Chain::new(3, 4, 50);
+        for iter in 0..50 {
+            for walker in 0..4 {
+                let x = (iter * 4 + walker) as f64;
+                chain.set_params(walker, iter, &[1e3 * x.sin(), 1e-6 * x, 2.5]);
+            }
+        }
+        chain.set_param_names(&["a", "b", "constant"]).unwrap();
+
+        let quantised = chain.quantise().unwrap();
+        let samples = chain.flatchain();
+        let (lo, hi) = samples
+            .iter()
+            .fold((f64::INFINITY, -f64::INFINITY), |(lo, hi), guess| {
+                (lo.min(guess[0]), hi.max(guess[0]))
+            });
+        let expected = (hi - lo) / 65535.0;
+        assert_approx_eq!(quantised.resolution(0), expected, 1e-12 * expected);
+        assert_eq!(quantised.resolution(2), 0.0);
+        for iter in 0..50 {
+            for walker in 0..4 {
+                for param in 0..3 {
+                    let error = quantised.get(param, walker, iter) - chain.get(param, walker, iter);
+                    assert!(error.abs() <= 0.5 * quantised.resolution(param) * (1.0 + 1e-9));
+                }
+            }
+        }
+        assert_eq!(quantised.get(2, 3, 49), 2.5);
+
+        let mut bytes = Vec::new();
+        quantised.write_to(&mut bytes).unwrap();
+        assert!(bytes.len() < 2 * 600 + 200);
+        let restored = QuantisedChain::read_from(&bytes[..]).unwrap();
+        assert_eq!(restored, quantised);
+        let full = restored.to_chain();
+        assert_eq!(full.param_names(), chain.param_names());
+        assert_eq!(full.get(1, 2, 30), quantised.get(1, 2, 30));
+
+        assert!(QuantisedChain::read_from(&bytes[..bytes.len() - 1]).is_err());
+        assert!(QuantisedChain::read_from(&b"not a chain at all"[..]).is_err());
+        let mut huge = MAGIC.to_vec();
+        for &n in &[1u64, 1 << 63, 1] {
+            huge.extend_from_slice(&n.to_le_bytes());
+        }
+        huge.push(0);
+        huge.extend_from_slice(&[0; 16]);
+        assert!(QuantisedChain::read_from(&huge[..]).is_err());
+        chain.set(0, 0, 0, f64::NAN);
+        assert!(chain.quantise().is_err());
+    }
+}
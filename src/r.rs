@@ -0,0 +1,142 @@
+//! R bindings
+//!
+//! Enabled with the `r` feature, which builds this module on top of
+//! [extendr](https://extendr.github.io). An R package embeds the crate as a static library with
+//! the feature enabled and calls the registration routine generated by `extendr_module!` from its
+//! `R_init_emcee` entry point; R code can then run the sampler with an ordinary R closure:
+//!
+//! ```r
+//! lnprob <- function(theta) -0.5 * sum(theta^2)
+//! p0 <- matrix(rnorm(20 * 2, sd = 1e-3), nrow = 20)  # one row per walker
+//! result <- emcee_run(lnprob, p0, 1000L, 42L)
+//! dim(result$chain)                       # iterations x walkers x parameters
+//! coda::gelman.diag(result$mcmc)          # `mcmc` is a coda `mcmc.list`, one chain per walker
+//! ```
+//!
+//! R is single threaded, so the sampler is always run without a thread pool.
+
+use extendr_api::prelude::*;
+
+use {EnsembleSampler, Guess, Prob};
+
+/// Adapter calling an R closure as the log posterior probability
+///
+/// Evaluations which raise an R error, or do not return a single number, give NaN so the
+/// sampler stops with an error.
+struct RProb {
+    lnprob: Function,
+}
+
+// R objects must only be touched from the R main thread. The sampler driving this model is
+// created by `emcee_run` without a thread pool, so every evaluation happens on the thread R
+// called into Rust from; these impls only exist to satisfy the bounds of `Prob`.
+unsafe impl Send for RProb {}
+unsafe impl Sync for RProb {}
+
+impl Prob for RProb {
+    fn lnlike(&self, _params: &Guess) -> f64 {
+        0.0
+    }
+
+    fn lnprior(&self, _params: &Guess) -> f64 {
+        0.0
+    }
+
+    fn lnprob(&self, params: &Guess) -> f64 {
+        match self.lnprob.call(pairlist!(params.values.clone())) {
+            Ok(value) => value
+                .as_real()
+                .or_else(|| value.as_integer().map(f64::from))
+                .unwrap_or(f64::NAN),
+            Err(_) => f64::NAN,
+        }
+    }
+}
+
+/// Run the affine-invariant ensemble sampler
+///
+/// `lnprob` is called with a numeric vector of parameters and must return the log posterior
+/// probability. `p0` holds the initial position of each walker, one row per walker. `seed` may be
+/// `NULL`, or an integer seed for reproducible runs.
+///
+/// Returns a list with the `chain` as an iterations x walkers x parameters array, the `lnprob`
+/// values as an iterations x walkers matrix, the per-walker `acceptance` fraction, and the chain
+/// as a coda `mcmc.list` named `mcmc`.
+/// @export
+#[extendr]
+fn emcee_run(lnprob: Function, p0: RMatrix<f64>, niterations: i32, seed: Robj) -> Result<Robj> {
+    if niterations <= 0 {
+        return Err(Error::Other("niterations must be positive".into()));
+    }
+
+    let nwalkers = p0.nrows();
+    let dim = p0.ncols();
+    let niterations = niterations as usize;
+
+    // R matrices are stored column major
+    let data = p0.data();
+    let guesses: Vec<Guess> = (0..nwalkers)
+        .map(|walker| Guess {
+            values: (0..dim).map(|param| data[param * nwalkers + walker]).collect(),
+        })
+        .collect();
+
+    let model = RProb { lnprob };
+    let mut sampler = EnsembleSampler::new(nwalkers, dim, &model)
+        .map_err(|e| Error::Other(format!("{:?}", e)))?;
+    if let Some(seed) = seed.as_integer() {
+        sampler.seed(&[seed as usize]);
+    }
+    sampler
+        .run_mcmc(&guesses, niterations)
+        .map_err(|e| Error::Other(format!("{:?}", e)))?;
+
+    let flatchain = sampler.flatchain().expect("the chain is stored by default");
+    let flatprob = sampler.flatprob().expect("the chain is stored by default");
+
+    let mut chain = vec![0f64; niterations * nwalkers * dim];
+    let mut lnprobs = vec![0f64; niterations * nwalkers];
+    for iter in 0..niterations {
+        for walker in 0..nwalkers {
+            let idx = iter * nwalkers + walker;
+            for param in 0..dim {
+                chain[iter + niterations * (walker + nwalkers * param)] = flatchain[idx][param];
+            }
+            lnprobs[iter + niterations * walker] = flatprob[idx];
+        }
+    }
+
+    let mut walkers = Vec::with_capacity(nwalkers);
+    for walker in 0..nwalkers {
+        let mut values = vec![0f64; niterations * dim];
+        for iter in 0..niterations {
+            for param in 0..dim {
+                values[iter + niterations * param] = flatchain[iter * nwalkers + walker][param];
+            }
+        }
+        let mut mcmc = Robj::from(values);
+        mcmc.set_attrib("dim", vec![niterations as i32, dim as i32])?;
+        mcmc.set_attrib("mcpar", vec![1f64, niterations as f64, 1f64])?;
+        mcmc.set_class(&["mcmc"])?;
+        walkers.push(mcmc);
+    }
+    let mut mcmc_list: Robj = List::from_values(walkers).into();
+    mcmc_list.set_class(&["mcmc.list"])?;
+
+    let mut chain = Robj::from(chain);
+    chain.set_attrib("dim", vec![niterations as i32, nwalkers as i32, dim as i32])?;
+    let mut lnprobs = Robj::from(lnprobs);
+    lnprobs.set_attrib("dim", vec![niterations as i32, nwalkers as i32])?;
+
+    Ok(list!(
+        chain = chain,
+        lnprob = lnprobs,
+        acceptance = sampler.acceptance_fraction(),
+        mcmc = mcmc_list
+    ).into())
+}
+
+extendr_module! {
+    mod emcee;
+    fn emcee_run;
+}
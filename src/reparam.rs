@@ -0,0 +1,292 @@
+//! Non-centred reparameterisation of hierarchical models
+
+use errors::{EmceeError, Result};
+use guess::Guess;
+use prob::Prob;
+use stores::Chain;
+
+/// How the scale of a group is stored in the model's parameters
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Scale {
+    /// The parameter at this index is the standard deviation itself
+    Sd(usize),
+
+    /// The parameter at this index is the natural log of the standard deviation
+    LogSd(usize),
+
+    /// The parameter at this index is the natural log of the variance, as in Neal's funnel
+    LogVariance(usize),
+}
+
+impl Scale {
+    fn index(&self) -> usize {
+        match *self {
+            Scale::Sd(idx) | Scale::LogSd(idx) | Scale::LogVariance(idx) => idx,
+        }
+    }
+
+    /// The standard deviation and its natural log, or `None` if it is not positive
+    fn sd(&self, params: &[f64]) -> Option<(f64, f64)> {
+        let value = params[self.index()];
+        let ln_sd = match *self {
+            Scale::Sd(_) => {
+                if value > 0.0 {
+                    value.ln()
+                } else {
+                    return None;
+                }
+            }
+            Scale::LogSd(_) => value,
+            Scale::LogVariance(_) => value / 2.0,
+        };
+        if ln_sd.is_finite() {
+            Some((ln_sd.exp(), ln_sd))
+        } else {
+            None
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+struct Group {
+    location: Option<usize>,
+    scale: Scale,
+    members: Vec<usize>,
+}
+
+/// Sample a hierarchical model in non-centred coordinates
+///
+/// In a hierarchical model the group-level parameters `x_i ~ N(mu, sigma)` are strongly
+/// coupled to the population scale `sigma`: their spread shrinks with `sigma`, giving a funnel
+/// which no affine-invariant move can follow into its neck. Declaring each such group with
+/// [`add_group`](#method.add_group) makes the sampler work with `z_i = (x_i - mu) / sigma`
+/// instead, which are roughly independent of `mu` and `sigma`. The model itself is unchanged:
+/// it is always evaluated at the centred parameters `x_i = mu + sigma z_i`, and the log
+/// Jacobian `n ln(sigma)` of a group of `n` members is added to the prior, so the sampled
+/// distribution is exactly the model's posterior in the new coordinates.
+///
+/// The sampled positions are non-centred: start the walkers from
+/// [`non_centred`](#method.non_centred) positions, and convert the results back with
+/// [`centred`](#method.centred) or [`centred_chain`](#method.centred_chain).
+///
+/// ```rust
+/// # use emcee::{EnsembleSampler, NonCentred, Scale};
+/// # use emcee::testkit::Funnel;
+/// // The first parameter of the funnel is the log variance of the other nine
+/// let mut model = NonCentred::new(Funnel::default());
+/// model.add_group(None, Scale::LogVariance(0), &(1..10).collect::<Vec<_>>()).unwrap();
+///
+/// let mut sampler = EnsembleSampler::new(32, 10, &model).unwrap();
+/// # let _ = sampler;
+/// ```
+#[derive(Debug, Clone)]
+pub struct NonCentred<T> {
+    model: T,
+    groups: Vec<Group>,
+}
+
+impl<T: Prob> NonCentred<T> {
+    /// Wrap a model, initially without any groups
+    pub fn new(model: T) -> NonCentred<T> {
+        NonCentred {
+            model,
+            groups: Vec::new(),
+        }
+    }
+
+    /// Declare the parameters at the indices `members` as drawn from a Gaussian with the
+    /// location parameter at index `location` (or zero if `None`) and the standard deviation
+    /// given by `scale`
+    ///
+    /// Errors are handled by returning a [`Result`](errors/type.Result.html) which contains
+    /// [`EmceeError::InvalidInputs`](errors/enum.EmceeError.html) if `members` is empty, an
+    /// index is repeated, or a parameter already belongs to a group, or is the location or
+    /// scale of one.
+    pub fn add_group(
+        &mut self,
+        location: Option<usize>,
+        scale: Scale,
+        members: &[usize],
+    ) -> Result<&mut Self> {
+        if members.is_empty() {
+            return Err(EmceeError::InvalidInputs(
+                "a group needs at least one member".into(),
+            ));
+        }
+
+        let hypers: Vec<usize> = location.into_iter().chain(Some(scale.index())).collect();
+        let is_member = |idx: usize| self.groups.iter().any(|group| group.members.contains(&idx));
+        let is_hyper = |idx: usize| {
+            self.groups
+                .iter()
+                .any(|group| group.location == Some(idx) || group.scale.index() == idx)
+        };
+        for (i, &idx) in members.iter().enumerate() {
+            if is_member(idx) || is_hyper(idx) || hypers.contains(&idx)
+                || members[..i].contains(&idx)
+            {
+                return Err(EmceeError::InvalidInputs(format!(
+                    "parameter {} is used twice in the hierarchy",
+                    idx
+                )));
+            }
+        }
+        if let Some(&idx) = hypers.iter().find(|&&idx| is_member(idx)) {
+            return Err(EmceeError::InvalidInputs(format!(
+                "parameter {} is used twice in the hierarchy",
+                idx
+            )));
+        }
+
+        self.groups.push(Group {
+            location,
+            scale,
+            members: members.to_vec(),
+        });
+        Ok(self)
+    }
+
+    /// The wrapped model
+    pub fn model(&self) -> &T {
+        &self.model
+    }
+
+    /// Convert a non-centred position, as sampled, to the model's parameters
+    ///
+    /// Members of a group with an invalid scale are set to NaN.
+    pub fn centred(&self, params: &Guess) -> Guess {
+        let mut values = params.values.clone();
+        for group in &self.groups {
+            let mu = group.location.map_or(0.0, |idx| params[idx]);
+            let sd = group.scale.sd(&params.values).map_or(f64::NAN, |s| s.0);
+            for &idx in &group.members {
+                values[idx] = mu + sd * params[idx];
+            }
+        }
+        Guess { values }
+    }
+
+    /// Convert a position in the model's parameters to the non-centred coordinates which are
+    /// sampled, e.g. to build the initial positions of the walkers
+    ///
+    /// Members of a group with an invalid scale are set to NaN.
+    pub fn non_centred(&self, params: &Guess) -> Guess {
+        let mut values = params.values.clone();
+        for group in &self.groups {
+            let mu = group.location.map_or(0.0, |idx| params[idx]);
+            let sd = group.scale.sd(&params.values).map_or(f64::NAN, |s| s.0);
+            for &idx in &group.members {
+                values[idx] = (params[idx] - mu) / sd;
+            }
+        }
+        Guess { values }
+    }
+
+    /// Convert every position of a sampled chain to the model's parameters
+    pub fn centred_chain(&self, chain: &Chain) -> Chain {
+        let mut out = Chain::new(chain.nparams, chain.nwalkers, chain.niterations);
+        for iteration in 0..chain.niterations {
+            for walker in 0..chain.nwalkers {
+                let values: Vec<f64> = (0..chain.nparams)
+                    .map(|param| chain.get(param, walker, iteration))
+                    .collect();
+                let centred = self.centred(&Guess { values });
+                out.set_params(walker, iteration, &centred.values);
+            }
+        }
+        out
+    }
+
+    /// The log Jacobian of the map from non-centred to centred parameters, or `None` if a scale
+    /// is invalid
+    fn ln_jacobian(&self, params: &Guess) -> Option<f64> {
+        self.groups.iter().try_fold(0.0, |total, group| {
+            let (_, ln_sd) = group.scale.sd(&params.values)?;
+            Some(total + group.members.len() as f64 * ln_sd)
+        })
+    }
+}
+
+impl<T: Prob> Prob for NonCentred<T> {
+    fn lnlike(&self, params: &Guess) -> f64 {
+        self.model.lnlike(&self.centred(params))
+    }
+
+    fn lnprior(&self, params: &Guess) -> f64 {
+        match self.ln_jacobian(params) {
+            Some(ln_jacobian) => self.model.lnprior(&self.centred(params)) + ln_jacobian,
+            None => -f64::INFINITY,
+        }
+    }
+
+    fn validate(&self, params: &Guess) -> ::std::result::Result<(), String> {
+        self.model.validate(&self.centred(params))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use testkit::{self, Funnel};
+    use EnsembleSampler;
+
+    fn funnel() -> NonCentred<Funnel> {
+        let mut model = NonCentred::new(Funnel::default());
+        model
+            .add_group(None, Scale::LogVariance(0), &(1..10).collect::<Vec<_>>())
+            .unwrap();
+        model
+    }
+
+    #[test]
+    fn test_transforms() {
+        let mut model = NonCentred::new(Funnel { dim: 5, scale: 3.0 });
+        model.add_group(Some(4), Scale::Sd(0), &[1, 2]).unwrap();
+        let centred = Guess::new(&[2.0, 5.0, -1.0, 7.0, 1.0]);
+        let sampled = model.non_centred(&centred);
+        assert_eq!(sampled.values, vec![2.0, 2.0, -1.0, 7.0, 1.0]);
+        assert_eq!(model.centred(&sampled).values, centred.values);
+
+        // The log Jacobian of two members with sd 2
+        assert_approx_eq!(
+            model.lnprob(&sampled),
+            model.model().lnprob(&centred) + 2.0 * 2f64.ln()
+        );
+        assert_eq!(model.lnprob(&Guess::new(&[-1.0, 0.0, 0.0, 0.0, 0.0])), -f64::INFINITY);
+
+        // Scales and locations may be shared between groups, members may not
+        assert!(model.add_group(Some(4), Scale::Sd(0), &[3]).is_ok());
+        assert!(model.add_group(None, Scale::LogSd(1), &[3]).is_err());
+        let mut model = NonCentred::new(Funnel { dim: 5, scale: 3.0 });
+        assert!(model.add_group(None, Scale::Sd(0), &[]).is_err());
+        assert!(model.add_group(None, Scale::Sd(0), &[0, 1]).is_err());
+        assert!(model.add_group(None, Scale::Sd(0), &[1, 1]).is_err());
+    }
+
+    #[test]
+    fn test_funnel_neck() {
+        let model = funnel();
+        let target = Funnel::default();
+        let nwalkers = 32;
+        let p0: Vec<Guess> = testkit::initial_ensemble(&target, nwalkers, &[1])
+            .unwrap()
+            .iter()
+            .map(|guess| model.non_centred(guess))
+            .collect();
+
+        let mut sampler = EnsembleSampler::new(nwalkers, 10, &model).unwrap();
+        sampler.seed(&[1]);
+        sampler.run_mcmc(&p0, 4000).unwrap();
+        let chain = model.centred_chain(&sampler.chain().unwrap().split_at(1000).1);
+
+        // In the non-centred coordinates the target is a standard Gaussian, so the log
+        // variance is sampled well into the neck of the funnel
+        let v: Vec<f64> = chain.flatchain().iter().map(|guess| guess[0]).collect();
+        let n = v.len() as f64;
+        let mean = v.iter().sum::<f64>() / n;
+        let var = v.iter().map(|x| (x - mean).powi(2)).sum::<f64>() / n;
+        assert_approx_eq!(mean, 0.0, 0.5);
+        assert_approx_eq!(var, 9.0, 1.5);
+        assert!(v.iter().any(|&x| x < -6.0));
+    }
+}
@@ -0,0 +1,259 @@
+//! Exact reproduction of runs
+//!
+//! A [`Replay`](struct.Replay.html) records everything needed to repeat a run bit for bit: the
+//! seed, the initial positions, the number of iterations and a hash of the sampler
+//! configuration. The sampler draws all its random numbers from a single stream, so the seed
+//! fixes every proposal and acceptance decision; there are no per-walker streams to record.
+//!
+//! Runs which hit the sampler's [`timeout`](../struct.EnsembleSampler.html#structfield.timeout)
+//! depend on timing, and cannot be reproduced exactly.
+
+use std::fs::File;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::path::Path;
+
+use errors::{EmceeError, Result};
+use guess::Guess;
+use {EnsembleSampler, Prob, Step};
+
+const MAGIC: &str = "emcee-replay 1";
+
+/// A record of a run, created by
+/// [`EnsembleSampler::run_recorded`](../struct.EnsembleSampler.html#method.run_recorded)
+#[derive(Debug, Clone, PartialEq)]
+pub struct Replay {
+    /// The seed of the sampler's random number stream
+    pub seed: Vec<usize>,
+
+    /// Hash of the sampler configuration the run was made with
+    pub config_hash: u64,
+
+    /// The number of iterations
+    pub niterations: usize,
+
+    /// The initial position of each walker
+    pub p0: Vec<Guess>,
+}
+
+impl Replay {
+    /// Write the replay in its text format
+    ///
+    /// Positions are written as the bit patterns of the values, so they are restored exactly.
+    pub fn write<W: Write>(&self, mut writer: W) -> Result<()> {
+        writeln!(writer, "{}", MAGIC)?;
+        let seed: Vec<String> = self.seed.iter().map(|word| word.to_string()).collect();
+        writeln!(writer, "seed {}", seed.join(" "))?;
+        writeln!(writer, "config {:016x}", self.config_hash)?;
+        writeln!(writer, "niterations {}", self.niterations)?;
+        for guess in &self.p0 {
+            let values: Vec<String> = guess
+                .values
+                .iter()
+                .map(|value| format!("{:016x}", value.to_bits()))
+                .collect();
+            writeln!(writer, "walker {}", values.join(" "))?;
+        }
+        Ok(())
+    }
+
+    /// Read a replay written by [`write`](#method.write)
+    pub fn read<R: Read>(reader: R) -> Result<Replay> {
+        let mut lines = BufReader::new(reader).lines();
+        let mut next = |key: &str| -> Result<String> {
+            let line = lines
+                .next()
+                .ok_or_else(|| EmceeError::from(format!("replay file is missing `{}`", key)))??;
+            if key.is_empty() {
+                return Ok(line);
+            }
+            let prefix = format!("{} ", key);
+            if line == key {
+                Ok(String::new())
+            } else if let Some(rest) = line.strip_prefix(prefix.as_str()) {
+                Ok(rest.to_string())
+            } else {
+                Err(format!("expected `{}` in replay file, found `{}`", key, line).into())
+            }
+        };
+
+        if next("")? != MAGIC {
+            return Err("not an emcee replay file".into());
+        }
+        let seed = next("seed")?
+            .split_whitespace()
+            .map(|word| word.parse())
+            .collect::<::std::result::Result<_, _>>()
+            .map_err(|_| EmceeError::from("invalid seed in replay file"))?;
+        let config_hash = u64::from_str_radix(&next("config")?, 16)
+            .map_err(|_| EmceeError::from("invalid configuration hash in replay file"))?;
+        let niterations = next("niterations")?
+            .parse()
+            .map_err(|_| EmceeError::from("invalid number of iterations in replay file"))?;
+
+        let mut p0 = Vec::new();
+        while let Ok(walker) = next("walker") {
+            let values = walker
+                .split_whitespace()
+                .map(|word| u64::from_str_radix(word, 16).map(f64::from_bits))
+                .collect::<::std::result::Result<Vec<_>, _>>()
+                .map_err(|_| EmceeError::from("invalid walker position in replay file"))?;
+            p0.push(Guess::new(&values));
+        }
+
+        Ok(Replay {
+            seed,
+            config_hash,
+            niterations,
+            p0,
+        })
+    }
+
+    /// Write the replay to a file
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        self.write(File::create(path)?)
+    }
+
+    /// Read a replay from a file
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Replay> {
+        Replay::read(File::open(path)?)
+    }
+}
+
+impl<'a, T: Prob + Sync + Send + 'a> EnsembleSampler<'a, T> {
+    /// Run the sampler reproducibly, returning a record which can repeat the run
+    ///
+    /// The sampler is [`reset`](#method.reset), which reseeds it, and then run for
+    /// `niterations` iterations from `p0`. The returned [`Replay`](replay/struct.Replay.html)
+    /// can be saved and later passed to [`replay`](#method.replay) to repeat the run exactly,
+    /// e.g. to debug a problem in the model which only appears after many iterations.
+    ///
+    /// Errors are handled by returning a [`Result`](errors/type.Result.html) which contains
+    /// [`EmceeError::InvalidInputs`](errors/enum.EmceeError.html) if the sampler has not been
+    /// [`seed`](#method.seed)ed, or any error from the run.
+    pub fn run_recorded(&mut self, p0: &[Guess], niterations: usize) -> Result<(Step, Replay)> {
+        let seed = match self.seed {
+            Some(ref seed) => seed.clone(),
+            None => {
+                return Err(EmceeError::InvalidInputs(
+                    "the sampler must be seeded to record a replay".into(),
+                ))
+            }
+        };
+
+        self.reset();
+        let state = self.run_mcmc(p0, niterations)?;
+        let replay = Replay {
+            seed,
+            config_hash: self.config_hash(),
+            niterations,
+            p0: p0.to_vec(),
+        };
+        Ok((state, replay))
+    }
+
+    /// Repeat a run recorded by [`run_recorded`](#method.run_recorded)
+    ///
+    /// The sampler is seeded from the replay and [`reset`](#method.reset) before running, so
+    /// the chain is bit-identical to the recorded run.
+    ///
+    /// Errors are handled by returning a [`Result`](errors/type.Result.html) which contains
+    /// [`EmceeError::InvalidInputs`](errors/enum.EmceeError.html) if the sampler is not
+    /// configured the same way as the recorded one (number of walkers and parameters, moves and
+    /// their weights and tuning, thinning, discarded iterations and steps per iteration,
+    /// parameter groups, scaling and timeout), or any error from the run.
+    pub fn replay(&mut self, replay: &Replay) -> Result<Step> {
+        if replay.config_hash != self.config_hash() {
+            return Err(EmceeError::InvalidInputs(
+                "the sampler configuration does not match the replay".into(),
+            ));
+        }
+
+        self.seed(&replay.seed);
+        self.reset();
+        self.run_mcmc(&replay.p0, replay.niterations)
+    }
+
+    /// FNV-1a hash of the configuration which affects the sequence of samples
+    fn config_hash(&self) -> u64 {
+        let mut description = format!(
+            "{} {} {} {} {} {:?}",
+            self.nwalkers, self.dim, self.thin, self.grouped, self.autoscale, self.timeout
+        );
+        if self.shuffle_walkers {
+            description.push_str(" shuffled");
+        }
+        if self.discard > 0 {
+            description.push_str(&format!(" discard {}", self.discard));
+        }
+        if self.steps_per_store != 1 {
+            description.push_str(&format!(" steps {}", self.steps_per_store));
+        }
+        for (&(ref m, weight), tuning) in self.moves.iter().zip(&self.configured_tuning) {
+            description.push_str(&format!(
+                " {}:{:016x}:{:?}",
+                m.name(),
+                weight.to_bits(),
+                tuning.map(f64::to_bits)
+            ));
+        }
+
+        description.bytes().fold(0xcbf2_9ce4_8422_2325, |hash, byte| {
+            (hash ^ u64::from(byte)).wrapping_mul(0x0100_0000_01b3)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use moves::StretchMove;
+    use testkit::CorrelatedGaussian;
+
+    fn initial() -> Vec<Guess> {
+        (0..8)
+            .map(|i| Guess::new(&[0.1 * i as f64 + 0.01, 1.0 / (i + 3) as f64]))
+            .collect()
+    }
+
+    #[test]
+    fn test_round_trip_is_exact() {
+        let replay = Replay {
+            seed: vec![1, 2, 3],
+            config_hash: 0xdead_beef,
+            niterations: 10,
+            p0: initial(),
+        };
+        let mut bytes = Vec::new();
+        replay.write(&mut bytes).unwrap();
+        assert_eq!(Replay::read(&bytes[..]).unwrap(), replay);
+        assert!(Replay::read(&b"something else\n"[..]).is_err());
+    }
+
+    #[test]
+    fn test_replay_reproduces_run() {
+        let model = CorrelatedGaussian::standard(2);
+        let mut sampler = EnsembleSampler::new(8, 2, &model).unwrap();
+        assert!(sampler.run_recorded(&initial(), 10).is_err());
+
+        sampler.seed(&[11]);
+        let (state, replay) = sampler.run_recorded(&initial(), 50).unwrap();
+        let flatchain = sampler.flatchain().unwrap();
+
+        let mut bytes = Vec::new();
+        replay.write(&mut bytes).unwrap();
+        let replay = Replay::read(&bytes[..]).unwrap();
+
+        let mut other = EnsembleSampler::new(8, 2, &model).unwrap();
+        let replayed = other.replay(&replay).unwrap();
+        assert_eq!(*replayed.lnprob, *state.lnprob);
+        for (a, b) in other.flatchain().unwrap().iter().zip(&flatchain) {
+            assert_eq!(a.values, b.values);
+        }
+
+        let mut different = EnsembleSampler::new(8, 2, &model).unwrap();
+        different
+            .set_moves(vec![(Box::new(StretchMove::new(3.0)), 1.0)])
+            .unwrap();
+        assert!(different.replay(&replay).is_err());
+    }
+}
@@ -0,0 +1,116 @@
+//! Retrying transient failures of the model
+
+use std::thread;
+use std::time::Duration;
+
+/// How often, and how patiently, to retry an evaluation of the model which failed
+///
+/// Used through the sampler's [`retry`](struct.EnsembleSampler.html#structfield.retry) field for
+/// models which report transient failures from
+/// [`Prob::try_lnprob`](trait.Prob.html#method.try_lnprob), e.g. a dropped connection to a
+/// likelihood server or a crashed subprocess. A failed evaluation is repeated up to
+/// `max_retries` times, waiting `backoff` before the first retry and `multiplier` times longer
+/// before each further one. The default makes no retries.
+///
+/// ```rust
+/// # use emcee::RetryPolicy;
+/// # use std::time::Duration;
+/// // Wait 10 ms, 20 ms, then 40 ms before giving up
+/// let policy = RetryPolicy::new(3, Duration::from_millis(10));
+/// assert_eq!(policy.delay(2), Duration::from_millis(40));
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RetryPolicy {
+    /// The number of times a failed evaluation is repeated
+    pub max_retries: usize,
+
+    /// The wait before the first retry
+    pub backoff: Duration,
+
+    /// The factor by which the wait grows after every retry (default 2)
+    pub multiplier: f64,
+}
+
+impl RetryPolicy {
+    /// Retry up to `max_retries` times, with waits starting at `backoff` and doubling
+    pub fn new(max_retries: usize, backoff: Duration) -> RetryPolicy {
+        RetryPolicy {
+            max_retries,
+            backoff,
+            multiplier: 2.0,
+        }
+    }
+
+    /// The wait before retry number `retry`, counting from zero
+    ///
+    /// Waits too long for a [`Duration`](https://doc.rust-lang.org/std/time/struct.Duration.html)
+    /// saturate at `Duration::MAX`.
+    pub fn delay(&self, retry: usize) -> Duration {
+        let seconds = self.backoff.as_secs_f64() * self.multiplier.powf(retry as f64);
+        if seconds > 0.0 {
+            Duration::try_from_secs_f64(seconds).unwrap_or(Duration::MAX)
+        } else {
+            Duration::from_secs(0)
+        }
+    }
+
+    /// Call `f` until it succeeds or the retries are exhausted, returning the last result and
+    /// the number of retries made
+    pub(crate) fn run<F>(&self, mut f: F) -> (Result<f64, String>, usize)
+    where
+        F: FnMut() -> Result<f64, String>,
+    {
+        let mut result = f();
+        let mut retries = 0;
+        while result.is_err() && retries < self.max_retries {
+            thread::sleep(self.delay(retries));
+            retries += 1;
+            result = f();
+        }
+        (result, retries)
+    }
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        RetryPolicy::new(0, Duration::from_millis(100))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_retries() {
+        let policy = RetryPolicy::new(3, Duration::from_millis(1));
+        assert_eq!(policy.delay(0), Duration::from_millis(1));
+        assert_eq!(policy.delay(3), Duration::from_millis(8));
+
+        let mut calls = 0;
+        let (result, retries) = policy.run(|| {
+            calls += 1;
+            if calls < 3 {
+                Err("connection reset".into())
+            } else {
+                Ok(-1.5)
+            }
+        });
+        assert_eq!(result, Ok(-1.5));
+        assert_eq!(retries, 2);
+
+        let (result, retries) = policy.run(|| Err("connection refused".into()));
+        assert_eq!(result, Err("connection refused".to_string()));
+        assert_eq!(retries, 3);
+
+        let (result, retries) = RetryPolicy::default().run(|| Err("down".into()));
+        assert!(result.is_err());
+        assert_eq!(retries, 0);
+
+        // Long waits saturate instead of overflowing
+        let patient = RetryPolicy::new(70, Duration::from_millis(100));
+        assert_eq!(patient.delay(68), Duration::MAX);
+        assert_eq!(patient.delay(usize::MAX), Duration::MAX);
+        assert_eq!(RetryPolicy::new(3, Duration::MAX).delay(1), Duration::MAX);
+    }
+}
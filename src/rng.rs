@@ -0,0 +1,63 @@
+//! The sampler's random number generator, which counts what it has drawn
+
+use rand::Rng;
+
+/// Wraps the generator used by the sampler, counting the 64-bit words drawn from it
+///
+/// Every draw takes exactly one word from the wrapped generator, whatever its word size, so a
+/// seeded generator is brought back to any point of its stream by reseeding it and
+/// [`skip`](#method.skip)ping the number of words drawn. This is what lets a checkpoint
+/// record the random state of a seeded sampler.
+pub(crate) struct SamplerRng {
+    inner: Box<dyn Rng>,
+    ndraws: u64,
+}
+
+impl SamplerRng {
+    pub(crate) fn new(inner: Box<dyn Rng>) -> SamplerRng {
+        SamplerRng { inner, ndraws: 0 }
+    }
+
+    /// The number of words drawn since the generator was created
+    pub(crate) fn ndraws(&self) -> u64 {
+        self.ndraws
+    }
+
+    /// Discard the next `n` words
+    pub(crate) fn skip(&mut self, n: u64) {
+        for _ in 0..n {
+            self.next_u64();
+        }
+    }
+}
+
+impl Rng for SamplerRng {
+    fn next_u32(&mut self) -> u32 {
+        self.next_u64() as u32
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        self.ndraws += 1;
+        self.inner.next_u64()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::{SeedableRng, StdRng};
+
+    #[test]
+    fn test_skip() {
+        let mut a = SamplerRng::new(Box::new(StdRng::from_seed(&[1, 2][..])));
+        let _: Vec<f64> = (0..10).map(|_| a.gen()).collect();
+        let _ = a.next_u32();
+        assert_eq!(a.ndraws(), 11);
+
+        let mut b = SamplerRng::new(Box::new(StdRng::from_seed(&[1, 2][..])));
+        b.skip(a.ndraws());
+        assert_eq!(b.ndraws(), 11);
+        assert_eq!(a.next_u64(), b.next_u64());
+        assert_eq!(a.gen::<f64>(), b.gen::<f64>());
+    }
+}
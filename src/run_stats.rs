@@ -0,0 +1,60 @@
+//! Accounting of the cost of evaluating the model
+
+use std::time::Duration;
+
+/// Counts of model evaluations and the time spent in them
+///
+/// Returned by [`EnsembleSampler::run_stats`](struct.EnsembleSampler.html#method.run_stats).
+/// Together with an estimate of the number of effective samples (see
+/// [`autocorr`](autocorr/index.html)) this gives the computational cost per effective sample,
+/// e.g. to compare move configurations.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct RunStats {
+    /// The number of times the model was evaluated; evaluations of cheaper approximations are
+    /// not included
+    pub nevaluations: usize,
+
+    /// The number of proposals which were identical to the walker's current position, whose
+    /// log probability was reused instead of evaluating the model again
+    pub ncache_hits: usize,
+
+    /// The number of proposals rejected because they were out of bounds, i.e. failed
+    /// [`Prob::validate`](trait.Prob.html#method.validate) or evaluated to `-inf`
+    pub nrejected_bounds: usize,
+
+    /// The number of evaluations which exceeded the sampler's
+    /// [`timeout`](struct.EnsembleSampler.html#structfield.timeout)
+    pub ntimeouts: usize,
+
+    /// The number of times a failed evaluation was repeated under the sampler's
+    /// [`retry`](struct.EnsembleSampler.html#structfield.retry) policy
+    pub nretries: usize,
+
+    /// The number of evaluations which still failed once the retries were exhausted; their
+    /// proposals are rejected
+    pub nfailures: usize,
+
+    /// The number of evaluations which returned NaN or positive infinity and were treated as
+    /// `-inf` under [`NanPolicy::TreatAsNegInf`](enum.NanPolicy.html), rejecting the proposal
+    pub nnonfinite: usize,
+
+    /// The number of proposals rejected by one of the model's cheaper
+    /// [approximations](trait.Prob.html#method.napproximations), without evaluating the full
+    /// model
+    pub nscreened: usize,
+
+    /// The total time spent evaluating the full model, summed over all evaluations; with a
+    /// threaded sampler this can exceed the wall clock time of the run
+    pub evaluation_time: Duration,
+}
+
+impl RunStats {
+    /// The mean time taken by one evaluation of the model
+    pub fn mean_evaluation_time(&self) -> Option<Duration> {
+        if self.nevaluations == 0 {
+            None
+        } else {
+            Some(self.evaluation_time / self.nevaluations as u32)
+        }
+    }
+}
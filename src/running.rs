@@ -0,0 +1,349 @@
+//! Statistics which are updated as the chain is stored
+
+use float::Float;
+use guess::Guess;
+use stores::Chain;
+
+/// Running mean and variance of a series, using Welford's algorithm
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub(crate) struct Welford {
+    count: usize,
+    mean: f64,
+    m2: f64,
+}
+
+impl Welford {
+    pub(crate) fn add(&mut self, x: f64) {
+        self.count += 1;
+        let delta = x - self.mean;
+        self.mean += delta / self.count as f64;
+        self.m2 += delta * (x - self.mean);
+    }
+
+    /// Remove a value which was previously added
+    pub(crate) fn remove(&mut self, x: f64) {
+        assert!(self.count > 0);
+        if self.count == 1 {
+            *self = Welford::default();
+            return;
+        }
+
+        let mean = (self.count as f64 * self.mean - x) / (self.count - 1) as f64;
+        self.m2 -= (x - mean) * (x - self.mean);
+        self.mean = mean;
+        self.count -= 1;
+    }
+
+    /// Combine two accumulators (Chan et al.)
+    pub(crate) fn merge(&self, other: &Welford) -> Welford {
+        if self.count == 0 {
+            return *other;
+        }
+        if other.count == 0 {
+            return *self;
+        }
+
+        let count = self.count + other.count;
+        let delta = other.mean - self.mean;
+        Welford {
+            count,
+            mean: self.mean + delta * other.count as f64 / count as f64,
+            m2: self.m2
+                + other.m2
+                + delta * delta * (self.count as f64 * other.count as f64) / count as f64,
+        }
+    }
+
+    pub(crate) fn count(&self) -> usize {
+        self.count
+    }
+
+    pub(crate) fn mean(&self) -> f64 {
+        self.mean
+    }
+
+    /// The unbiased sample variance
+    pub(crate) fn variance(&self) -> f64 {
+        if self.count < 2 {
+            f64::NAN
+        } else {
+            self.m2 / (self.count - 1) as f64
+        }
+    }
+}
+
+/// Per-walker summary statistics, updated every time an iteration is stored
+///
+/// Each walker's samples are split into a first and second half, and the running mean and
+/// variance of each half is kept up to date as the chain grows. When a new iteration is stored
+/// at most one earlier sample per walker moves from the second half to the first, so the cost of
+/// an update is `O(nwalkers * nparams)` regardless of the length of the chain. The statistics
+/// are available from
+/// [`EnsembleSampler::running_stats`](struct.EnsembleSampler.html#method.running_stats).
+#[derive(Debug, Clone)]
+pub struct RunningStats {
+    nparams: usize,
+    nwalkers: usize,
+    niterations: usize,
+    first: Vec<Welford>,
+    second: Vec<Welford>,
+}
+
+impl RunningStats {
+    pub(crate) fn new(nparams: usize, nwalkers: usize) -> RunningStats {
+        RunningStats {
+            nparams,
+            nwalkers,
+            niterations: 0,
+            first: vec![Welford::default(); nparams * nwalkers],
+            second: vec![Welford::default(); nparams * nwalkers],
+        }
+    }
+
+    /// Include the iteration which has just been stored at `iteration_idx` in the chain
+    pub(crate) fn push<F: Float>(&mut self, chain: &Chain<F>, iteration_idx: usize) {
+        assert_eq!(iteration_idx, self.niterations, "iterations must be pushed in order");
+
+        let old_half = self.niterations / 2;
+        self.niterations += 1;
+        let moves_split = self.niterations / 2 > old_half;
+
+        for walker in 0..self.nwalkers {
+            for param in 0..self.nparams {
+                let idx = walker * self.nparams + param;
+                self.second[idx].add(chain.get(param, walker, iteration_idx).to_f64());
+                if moves_split {
+                    let value = chain.get(param, walker, old_half).to_f64();
+                    self.second[idx].remove(value);
+                    self.first[idx].add(value);
+                }
+            }
+        }
+    }
+
+    /// The number of stored iterations included in the statistics
+    pub fn niterations(&self) -> usize {
+        self.niterations
+    }
+
+    /// The mean of each parameter over all walkers and stored iterations
+    pub fn mean(&self) -> Vec<f64> {
+        self.combined().iter().map(|acc| acc.mean()).collect()
+    }
+
+    /// The unbiased variance of each parameter over all walkers and stored iterations
+    pub fn variance(&self) -> Vec<f64> {
+        self.combined().iter().map(|acc| acc.variance()).collect()
+    }
+
+    /// The split Gelman-Rubin statistic of each parameter
+    ///
+    /// Each walker's samples are split in half, giving `2 * nwalkers` sequences, and the
+    /// potential scale reduction factor is computed from the between- and within-sequence
+    /// variances. Values close to 1 indicate convergence. At least four stored iterations are
+    /// required, otherwise the results are NaN.
+    pub fn split_rhat(&self) -> Vec<f64> {
+        (0..self.nparams)
+            .map(|param| {
+                let halves: Vec<&Welford> = (0..self.nwalkers)
+                    .flat_map(|walker| {
+                        let idx = walker * self.nparams + param;
+                        vec![&self.first[idx], &self.second[idx]]
+                    })
+                    .collect();
+                rhat(&halves)
+            })
+            .collect()
+    }
+
+    fn combined(&self) -> Vec<Welford> {
+        (0..self.nparams)
+            .map(|param| {
+                (0..self.nwalkers).fold(Welford::default(), |acc, walker| {
+                    let idx = walker * self.nparams + param;
+                    acc.merge(&self.first[idx]).merge(&self.second[idx])
+                })
+            })
+            .collect()
+    }
+}
+
+/// Running mean and variance of each parameter and of the log probability over every sample
+///
+/// Unlike [`RunningStats`](struct.RunningStats.html) these do not need the chain to be stored,
+/// and reading them costs `O(nparams)`, so monitoring code can display the current estimates
+/// at any time. They are updated with every iteration which would be stored (taking
+/// [`thin`](struct.EnsembleSampler.html#structfield.thin) into account), and accumulate over
+/// runs, including any burn-in, until
+/// [`EnsembleSampler::clear_online_stats`](struct.EnsembleSampler.html#method.clear_online_stats)
+/// or [`reset`](struct.EnsembleSampler.html#method.reset). They are available from
+/// [`EnsembleSampler::online_stats`](struct.EnsembleSampler.html#method.online_stats).
+#[derive(Debug, Clone)]
+pub struct OnlineStats {
+    params: Vec<Welford>,
+    lnprob: Welford,
+}
+
+impl OnlineStats {
+    pub(crate) fn new(nparams: usize) -> OnlineStats {
+        OnlineStats {
+            params: vec![Welford::default(); nparams],
+            lnprob: Welford::default(),
+        }
+    }
+
+    /// Include the positions and log probabilities of every walker at one iteration
+    pub(crate) fn push(&mut self, pos: &[Guess], lnprob: &[f64]) {
+        for guess in pos {
+            for (acc, value) in self.params.iter_mut().zip(&guess.values) {
+                acc.add(*value);
+            }
+        }
+        for value in lnprob {
+            self.lnprob.add(*value);
+        }
+    }
+
+    /// The number of samples included, i.e. walkers times iterations
+    pub fn count(&self) -> usize {
+        self.lnprob.count()
+    }
+
+    /// The mean of each parameter, or NaN if there are no samples
+    pub fn mean(&self) -> Vec<f64> {
+        self.params.iter().map(|acc| self.checked_mean(acc)).collect()
+    }
+
+    /// The unbiased variance of each parameter, or NaN if there are fewer than two samples
+    pub fn variance(&self) -> Vec<f64> {
+        self.params.iter().map(|acc| acc.variance()).collect()
+    }
+
+    /// The mean log probability, or NaN if there are no samples
+    pub fn mean_lnprob(&self) -> f64 {
+        self.checked_mean(&self.lnprob)
+    }
+
+    /// The unbiased variance of the log probability, or NaN if there are fewer than two samples
+    pub fn variance_lnprob(&self) -> f64 {
+        self.lnprob.variance()
+    }
+
+    fn checked_mean(&self, acc: &Welford) -> f64 {
+        if acc.count() == 0 {
+            f64::NAN
+        } else {
+            acc.mean()
+        }
+    }
+}
+
+/// Gelman-Rubin statistic from per-sequence accumulators
+pub(crate) fn rhat(sequences: &[&Welford]) -> f64 {
+    let m = sequences.len();
+    let n = sequences.iter().map(|acc| acc.count()).min().unwrap_or(0);
+    if m < 2 || n < 2 {
+        return f64::NAN;
+    }
+
+    let n = n as f64;
+    let grand_mean = sequences.iter().map(|acc| acc.mean()).sum::<f64>() / m as f64;
+    let b_over_n = sequences
+        .iter()
+        .map(|acc| (acc.mean() - grand_mean).powi(2))
+        .sum::<f64>() / (m - 1) as f64;
+    let w = sequences.iter().map(|acc| acc.variance()).sum::<f64>() / m as f64;
+
+    let var_plus = (n - 1.0) / n * w + b_over_n;
+    (var_plus / w).sqrt()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn direct(values: &[f64]) -> (f64, f64) {
+        let n = values.len() as f64;
+        let mean = values.iter().sum::<f64>() / n;
+        let var = values.iter().map(|x| (x - mean).powi(2)).sum::<f64>() / (n - 1.0);
+        (mean, var)
+    }
+
+    #[test]
+    fn test_welford_add_remove_merge() {
+        let values = [1.0, 4.0, 2.5, -3.0, 8.0, 0.5];
+        let mut acc = Welford::default();
+        for value in &values {
+            acc.add(*value);
+        }
+        let (mean, var) = direct(&values);
+        assert_approx_eq!(acc.mean(), mean);
+        assert_approx_eq!(acc.variance(), var);
+
+        acc.remove(1.0);
+        acc.remove(4.0);
+        let (mean, var) = direct(&values[2..]);
+        assert_approx_eq!(acc.mean(), mean);
+        assert_approx_eq!(acc.variance(), var);
+
+        let mut head = Welford::default();
+        head.add(1.0);
+        head.add(4.0);
+        let merged = head.merge(&acc);
+        let (mean, var) = direct(&values);
+        assert_eq!(merged.count(), values.len());
+        assert_approx_eq!(merged.mean(), mean);
+        assert_approx_eq!(merged.variance(), var);
+    }
+
+    #[test]
+    fn test_running_stats_match_full_scan() {
+        let (nparams, nwalkers, niterations) = (2, 3, 11);
+        let mut chain = Chain::new(nparams, nwalkers, niterations);
+        let mut stats = RunningStats::new(nparams, nwalkers);
+        for iter in 0..niterations {
+            for walker in 0..nwalkers {
+                let x = (iter * 7 + walker * 3) as f64 % 5.0;
+                chain.set_params(walker, iter, &[x, x * x - iter as f64]);
+            }
+            stats.push(&chain, iter);
+        }
+
+        // Recompute the split statistic from scratch
+        let half = niterations / 2;
+        for param in 0..nparams {
+            let mut halves = Vec::new();
+            for walker in 0..nwalkers {
+                for &(start, end) in &[(0, half), (half, niterations)] {
+                    let mut acc = Welford::default();
+                    for iter in start..end {
+                        acc.add(chain.get(param, walker, iter));
+                    }
+                    halves.push(acc);
+                }
+            }
+            let refs: Vec<&Welford> = halves.iter().collect();
+            assert_approx_eq!(stats.split_rhat()[param], rhat(&refs));
+
+            let all: Vec<f64> = chain
+                .flatchain()
+                .iter()
+                .map(|guess| guess[param])
+                .collect();
+            let (mean, var) = direct(&all);
+            assert_approx_eq!(stats.mean()[param], mean);
+            assert_approx_eq!(stats.variance()[param], var);
+        }
+    }
+
+    #[test]
+    fn test_rhat_identical_sequences() {
+        let mut a = Welford::default();
+        for value in &[1.0, 2.0, 3.0, 4.0] {
+            a.add(*value);
+        }
+        let value = rhat(&[&a, &a, &a]);
+        assert!(value < 1.0);
+        assert_approx_eq!(value, (0.75f64).sqrt());
+    }
+}
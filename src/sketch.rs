@@ -0,0 +1,193 @@
+//! Streaming quantile estimates
+
+use std::f64::consts::PI;
+
+/// Approximate quantiles of a stream of values in bounded memory (Dunning's merging t-digest)
+///
+/// The values are summarised by at most a few times `compression` weighted centroids, which are
+/// small near the extremes of the distribution and larger near the median, so tail quantiles
+/// such as the bounds of a 95% credible interval stay accurate. New values are buffered and
+/// merged in batches, so adding a value costs amortised `O(log compression)`.
+///
+/// The sampler keeps one digest per parameter when
+/// [`quantile_sketch`](struct.EnsembleSampler.html#structfield.quantile_sketch) is set, see
+/// [`EnsembleSampler::quantile_sketches`](struct.EnsembleSampler.html#method.quantile_sketches).
+#[derive(Debug, Clone)]
+pub struct TDigest {
+    compression: f64,
+    centroids: Vec<(f64, f64)>,
+    buffer: Vec<f64>,
+    count: usize,
+    min: f64,
+    max: f64,
+}
+
+impl TDigest {
+    /// Create an empty digest
+    ///
+    /// Larger values of `compression` give more accurate quantiles at the cost of more memory;
+    /// 100 gives relative errors in the tail quantiles of a fraction of a percent.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `compression` is less than 1.
+    pub fn new(compression: f64) -> TDigest {
+        assert!(compression >= 1.0, "the compression must be at least 1");
+        TDigest {
+            compression,
+            centroids: Vec::new(),
+            buffer: Vec::with_capacity(Self::buffer_size(compression)),
+            count: 0,
+            min: f64::INFINITY,
+            max: -f64::INFINITY,
+        }
+    }
+
+    /// Add a value
+    pub fn add(&mut self, value: f64) {
+        self.buffer.push(value);
+        self.count += 1;
+        self.min = self.min.min(value);
+        self.max = self.max.max(value);
+        if self.buffer.len() >= Self::buffer_size(self.compression) {
+            self.centroids = self.merged();
+            self.buffer.clear();
+        }
+    }
+
+    /// The number of values added
+    pub fn count(&self) -> usize {
+        self.count
+    }
+
+    /// Estimate the `q` quantile, for `q` between 0 and 1, or NaN if no values were added
+    pub fn quantile(&self, q: f64) -> f64 {
+        let centroids = self.merged();
+        if centroids.is_empty() || q.is_nan() {
+            return f64::NAN;
+        }
+        let q = q.clamp(0.0, 1.0);
+        if centroids.len() == 1 {
+            return centroids[0].0;
+        }
+
+        // Each centroid's mean is placed at the middle of its weight, and the quantile is
+        // interpolated linearly between neighbouring centroids, or the extreme values
+        let target = q * self.count as f64;
+        let (first_mean, first_weight) = centroids[0];
+        if target < first_weight / 2.0 {
+            return self.min + (first_mean - self.min) * target / (first_weight / 2.0);
+        }
+
+        let mut cumulative = 0f64;
+        for pair in centroids.windows(2) {
+            let (left_mean, left_weight) = pair[0];
+            let (right_mean, right_weight) = pair[1];
+            let left_centre = cumulative + left_weight / 2.0;
+            let right_centre = cumulative + left_weight + right_weight / 2.0;
+            if target < right_centre {
+                let fraction = (target - left_centre) / (right_centre - left_centre);
+                return left_mean + (right_mean - left_mean) * fraction;
+            }
+            cumulative += left_weight;
+        }
+
+        let (last_mean, last_weight) = centroids[centroids.len() - 1];
+        let remaining = self.count as f64 - target;
+        self.max - (self.max - last_mean) * remaining / (last_weight / 2.0)
+    }
+
+    /// Estimate the median
+    pub fn median(&self) -> f64 {
+        self.quantile(0.5)
+    }
+
+    /// Estimate the central interval containing a fraction `level` of the values, e.g. 0.95
+    pub fn credible_interval(&self, level: f64) -> (f64, f64) {
+        let tail = (1.0 - level) / 2.0;
+        (self.quantile(tail), self.quantile(1.0 - tail))
+    }
+
+    fn buffer_size(compression: f64) -> usize {
+        (5.0 * compression).ceil() as usize
+    }
+
+    /// The centroids with any buffered values merged in
+    fn merged(&self) -> Vec<(f64, f64)> {
+        let mut all = self.centroids.clone();
+        all.extend(self.buffer.iter().map(|&value| (value, 1.0)));
+        if all.is_empty() {
+            return all;
+        }
+        all.sort_by(|a, b| a.0.partial_cmp(&b.0).unwrap_or(::std::cmp::Ordering::Equal));
+
+        // Merge neighbours while the merged centroid spans at most one unit of the scale
+        // function k(q) = compression / (2 pi) * asin(2q - 1)
+        let total = self.count as f64;
+        let compression = self.compression;
+        let scale = |q: f64| compression / (2.0 * PI) * (2.0 * q - 1.0).clamp(-1.0, 1.0).asin();
+        let mut out = Vec::with_capacity(all.len());
+        let mut before = 0f64;
+        let mut current = all[0];
+        for &(mean, weight) in &all[1..] {
+            let merged_weight = current.1 + weight;
+            if scale((before + merged_weight) / total) - scale(before / total) <= 1.0 {
+                current = (
+                    (current.0 * current.1 + mean * weight) / merged_weight,
+                    merged_weight,
+                );
+            } else {
+                before += current.1;
+                out.push(current);
+                current = (mean, weight);
+            }
+        }
+        out.push(current);
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::{Rng, SeedableRng, StdRng};
+
+    #[test]
+    fn test_uniform_quantiles() {
+        let mut digest = TDigest::new(100.0);
+        assert!(digest.median().is_nan());
+
+        let mut rng = StdRng::from_seed(&[1]);
+        let mut values: Vec<f64> = (0..100_000).map(|_| rng.gen::<f64>()).collect();
+        for value in &values {
+            digest.add(*value);
+        }
+        assert_eq!(digest.count(), values.len());
+        assert!(digest.centroids.len() < 1000);
+
+        values.sort_by(|a, b| a.partial_cmp(b).unwrap());
+        for &q in &[0.001, 0.025, 0.16, 0.5, 0.84, 0.975, 0.999] {
+            let exact = values[(q * values.len() as f64) as usize];
+            assert_approx_eq!(digest.quantile(q), exact, 2e-3);
+        }
+        assert_eq!(digest.quantile(0.0), values[0]);
+        assert_eq!(digest.quantile(1.0), values[values.len() - 1]);
+
+        let (low, high) = digest.credible_interval(0.9);
+        assert_approx_eq!(low, 0.05, 5e-3);
+        assert_approx_eq!(high, 0.95, 5e-3);
+    }
+
+    #[test]
+    fn test_small_streams_are_exact() {
+        let mut digest = TDigest::new(100.0);
+        digest.add(3.0);
+        assert_eq!(digest.median(), 3.0);
+        for value in &[1.0, 2.0, 4.0, 5.0] {
+            digest.add(*value);
+        }
+        assert_eq!(digest.median(), 3.0);
+        assert_eq!(digest.quantile(0.0), 1.0);
+        assert_eq!(digest.quantile(1.0), 5.0);
+    }
+}
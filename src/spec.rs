@@ -0,0 +1,347 @@
+//! Declared structure of the parameter vector
+
+use errors::{EmceeError, Result};
+use moves::Move;
+use stores::Chain;
+
+/// Whether a block of parameters describes the whole population or one group
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BlockLevel {
+    /// Hyperparameters shared by every group, e.g. a population mean and scale
+    Population,
+
+    /// The parameters of a single group, e.g. one star in a cluster
+    Group,
+}
+
+/// A named block of parameters in a [`ParamSpec`](struct.ParamSpec.html)
+#[derive(Debug, Clone, PartialEq)]
+pub struct ParamBlock {
+    /// The name of the block
+    pub name: String,
+
+    /// The level of the block in the hierarchy
+    pub level: BlockLevel,
+
+    /// The indices of the block's parameters in the parameter vector
+    pub indices: Vec<usize>,
+}
+
+/// The layout of a hierarchical model's parameters as population and group blocks
+///
+/// Each parameter belongs to at most one block. Group blocks usually all have the same layout,
+/// e.g. a mass and a radius per star, so the `j`th parameter of every group can be compared
+/// with [`group_marginals`](#method.group_marginals). The blocks can be updated in turn, each
+/// by its own move, with
+/// [`EnsembleSampler::set_block_moves`](struct.EnsembleSampler.html#method.set_block_moves),
+/// which is usually much more efficient than updating all parameters at once when there are
+/// many groups, as the acceptance rate of an ensemble move falls with the dimension.
+///
+/// ```rust
+/// # use emcee::ParamSpec;
+/// // A population mean and scale, followed by a mass and radius for each of three stars
+/// let mut spec = ParamSpec::new(8);
+/// spec.add_population("population", &[0, 1]).unwrap();
+/// spec.add_groups("star", 2, 3, 2).unwrap();
+///
+/// assert_eq!(spec.block("star[1]").unwrap().indices, vec![4, 5]);
+/// assert_eq!(spec.groups().count(), 3);
+/// ```
+#[derive(Debug, Clone)]
+pub struct ParamSpec {
+    nparams: usize,
+    blocks: Vec<ParamBlock>,
+}
+
+impl ParamSpec {
+    /// Create a specification for `nparams` parameters, initially without any blocks
+    pub fn new(nparams: usize) -> ParamSpec {
+        ParamSpec {
+            nparams,
+            blocks: Vec::new(),
+        }
+    }
+
+    /// The total number of parameters
+    pub fn nparams(&self) -> usize {
+        self.nparams
+    }
+
+    /// Declare a block of population-level parameters
+    ///
+    /// Errors are handled by returning a [`Result`](errors/type.Result.html) which contains
+    /// [`EmceeError::InvalidInputs`](errors/enum.EmceeError.html) if the block is empty, the
+    /// name is already used, or an index is out of range or already belongs to a block.
+    pub fn add_population(&mut self, name: &str, indices: &[usize]) -> Result<&mut Self> {
+        self.add_block(name, BlockLevel::Population, indices)
+    }
+
+    /// Declare the parameters of one group
+    ///
+    /// Errors are handled by returning a [`Result`](errors/type.Result.html) which contains
+    /// [`EmceeError::InvalidInputs`](errors/enum.EmceeError.html) if the block is empty, the
+    /// name is already used, or an index is out of range or already belongs to a block.
+    pub fn add_group(&mut self, name: &str, indices: &[usize]) -> Result<&mut Self> {
+        self.add_block(name, BlockLevel::Group, indices)
+    }
+
+    /// Declare `ngroups` groups of `size` consecutive parameters each, starting at index
+    /// `first`, named `prefix[0]`, `prefix[1]` and so on
+    ///
+    /// Errors are handled by returning a [`Result`](errors/type.Result.html) which contains
+    /// [`EmceeError::InvalidInputs`](errors/enum.EmceeError.html) under the same conditions as
+    /// [`add_group`](#method.add_group), in which case no groups are added.
+    pub fn add_groups(
+        &mut self,
+        prefix: &str,
+        first: usize,
+        ngroups: usize,
+        size: usize,
+    ) -> Result<&mut Self> {
+        let mut spec = self.clone();
+        for group in 0..ngroups {
+            let start = first + group * size;
+            let indices: Vec<usize> = (start..start + size).collect();
+            spec.add_group(&format!("{}[{}]", prefix, group), &indices)?;
+        }
+        *self = spec;
+        Ok(self)
+    }
+
+    /// Every block, in the order they were declared
+    pub fn blocks(&self) -> &[ParamBlock] {
+        &self.blocks
+    }
+
+    /// The block called `name`
+    pub fn block(&self, name: &str) -> Option<&ParamBlock> {
+        self.blocks.iter().find(|block| block.name == name)
+    }
+
+    /// The population-level blocks
+    pub fn population(&self) -> impl Iterator<Item = &ParamBlock> {
+        self.blocks
+            .iter()
+            .filter(|block| block.level == BlockLevel::Population)
+    }
+
+    /// The group-level blocks
+    pub fn groups(&self) -> impl Iterator<Item = &ParamBlock> {
+        self.blocks
+            .iter()
+            .filter(|block| block.level == BlockLevel::Group)
+    }
+
+    /// One move per block, created by `make`, and one for the parameters outside any block
+    ///
+    /// The result is accepted by
+    /// [`EnsembleSampler::set_groups`](struct.EnsembleSampler.html#method.set_groups). The
+    /// parameters outside any block are passed to `make` as a population block called
+    /// `"unassigned"`.
+    pub fn block_moves<F>(&self, mut make: F) -> Vec<(Vec<usize>, Box<dyn Move>)>
+    where
+        F: FnMut(&ParamBlock) -> Box<dyn Move>,
+    {
+        let mut moves: Vec<(Vec<usize>, Box<dyn Move>)> = self.blocks
+            .iter()
+            .map(|block| (block.indices.clone(), make(block)))
+            .collect();
+
+        let unassigned: Vec<usize> = (0..self.nparams)
+            .filter(|idx| !self.blocks.iter().any(|block| block.indices.contains(idx)))
+            .collect();
+        if !unassigned.is_empty() {
+            let block = ParamBlock {
+                name: "unassigned".into(),
+                level: BlockLevel::Population,
+                indices: unassigned,
+            };
+            moves.push((block.indices.clone(), make(&block)));
+        }
+        moves
+    }
+
+    /// The parameters of the block called `name`, from a chain of the full parameter vector
+    ///
+    /// Errors are handled by returning a [`Result`](errors/type.Result.html) which contains
+    /// [`EmceeError::InvalidInputs`](errors/enum.EmceeError.html) if there is no such block, or
+    /// the chain does not have [`nparams`](#method.nparams) parameters.
+    pub fn marginal(&self, chain: &Chain, name: &str) -> Result<Chain> {
+        self.check_chain(chain)?;
+        let block = self.block(name).ok_or_else(|| {
+            EmceeError::InvalidInputs(format!("there is no parameter block called {}", name))
+        })?;
+        Ok(chain.select_params(&block.indices))
+    }
+
+    /// The flattened samples of the `param`th parameter of every group, with the group names
+    ///
+    /// For example, with a mass and radius per star, `group_marginals(&chain, 0)` gives the
+    /// posterior samples of the mass of each star, ready to be summarised or plotted together.
+    ///
+    /// Errors are handled by returning a [`Result`](errors/type.Result.html) which contains
+    /// [`EmceeError::InvalidInputs`](errors/enum.EmceeError.html) if a group has no `param`th
+    /// parameter, or the chain does not have [`nparams`](#method.nparams) parameters.
+    pub fn group_marginals(&self, chain: &Chain, param: usize) -> Result<Vec<(String, Vec<f64>)>> {
+        self.check_chain(chain)?;
+        self.groups()
+            .map(|block| {
+                let idx = *block.indices.get(param).ok_or_else(|| {
+                    EmceeError::InvalidInputs(format!(
+                        "group {} has no parameter {}",
+                        block.name, param
+                    ))
+                })?;
+                let samples = chain
+                    .select_params(&[idx])
+                    .flatchain()
+                    .iter()
+                    .map(|guess| guess[0])
+                    .collect();
+                Ok((block.name.clone(), samples))
+            })
+            .collect()
+    }
+
+    fn add_block(&mut self, name: &str, level: BlockLevel, indices: &[usize]) -> Result<&mut Self> {
+        if indices.is_empty() {
+            return Err(EmceeError::InvalidInputs(format!("block {} is empty", name)));
+        }
+        if self.block(name).is_some() {
+            return Err(EmceeError::InvalidInputs(format!(
+                "a block called {} already exists",
+                name
+            )));
+        }
+        for (i, &idx) in indices.iter().enumerate() {
+            let taken = self.blocks.iter().any(|block| block.indices.contains(&idx));
+            if idx >= self.nparams || taken || indices[..i].contains(&idx) {
+                return Err(EmceeError::InvalidInputs(format!(
+                    "parameter {} is out of range or belongs to more than one block",
+                    idx
+                )));
+            }
+        }
+
+        self.blocks.push(ParamBlock {
+            name: name.into(),
+            level,
+            indices: indices.to_vec(),
+        });
+        Ok(self)
+    }
+
+    fn check_chain(&self, chain: &Chain) -> Result<()> {
+        if chain.nparams != self.nparams {
+            return Err(EmceeError::InvalidInputs(format!(
+                "the chain has {} parameters but the specification has {}",
+                chain.nparams, self.nparams
+            )));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use guess::Guess;
+    use moves::StretchMove;
+    use {EnsembleSampler, Prob};
+
+    /// Group means `x_i ~ N(mu, 1)` with one noisy observation each, and a flat prior on `mu`
+    struct Hierarchical {
+        observations: Vec<f64>,
+    }
+
+    impl Prob for Hierarchical {
+        fn lnlike(&self, params: &Guess) -> f64 {
+            let mu = params[0];
+            self.observations
+                .iter()
+                .zip(&params.values[1..])
+                .map(|(y, x)| -0.5 * (x - mu).powi(2) - 0.5 * (y - x).powi(2))
+                .sum()
+        }
+
+        fn lnprior(&self, _params: &Guess) -> f64 {
+            0.0
+        }
+    }
+
+    #[test]
+    fn test_blocks() {
+        let mut spec = ParamSpec::new(8);
+        spec.add_population("population", &[0, 1]).unwrap();
+        spec.add_groups("star", 2, 3, 2).unwrap();
+        assert_eq!(spec.blocks().len(), 4);
+        assert_eq!(spec.population().count(), 1);
+        assert_eq!(spec.block("star[2]").unwrap().indices, vec![6, 7]);
+        assert_eq!(spec.block("star[0]").unwrap().level, BlockLevel::Group);
+        assert!(spec.block("star[3]").is_none());
+
+        assert!(spec.add_group("star[0]", &[7]).is_err());
+        assert!(spec.add_group("extra", &[1]).is_err());
+        assert!(spec.add_group("extra", &[8]).is_err());
+        assert!(spec.add_group("extra", &[]).is_err());
+        assert!(spec.add_groups("more", 6, 2, 1).is_err());
+        assert_eq!(spec.blocks().len(), 4);
+
+        let moves = spec.block_moves(|_| Box::new(StretchMove::default()));
+        assert_eq!(moves.len(), 4);
+
+        let mut spec = ParamSpec::new(4);
+        spec.add_group("a", &[2, 0]).unwrap();
+        let moves = spec.block_moves(|block| {
+            assert_eq!(block.name == "unassigned", block.indices == vec![1, 3]);
+            Box::new(StretchMove::default())
+        });
+        assert_eq!(moves.len(), 2);
+    }
+
+    #[test]
+    fn test_hierarchical_sampling() {
+        let observations = vec![-2.0, 0.0, 1.0, 5.0];
+        let model = Hierarchical {
+            observations: observations.clone(),
+        };
+        let mut spec = ParamSpec::new(5);
+        spec.add_population("mu", &[0]).unwrap();
+        spec.add_groups("x", 1, 4, 1).unwrap();
+
+        let nwalkers = 16;
+        let mut sampler = EnsembleSampler::new(nwalkers, 5, &model).unwrap();
+        sampler.seed(&[1]);
+        sampler
+            .set_block_moves(&spec, |_| Box::new(StretchMove::default()))
+            .unwrap();
+        assert_eq!(sampler.move_acceptance().len(), 5);
+        let p0: Vec<Guess> = (0..nwalkers)
+            .map(|i| Guess {
+                values: (0..5).map(|j| 0.1 * ((i * (j + 1)) % 7) as f64).collect(),
+            })
+            .collect();
+        sampler.run_mcmc(&p0, 3000).unwrap();
+        let chain = sampler.chain().unwrap().split_at(500).1;
+
+        // mu has a flat prior, so its posterior mean is the mean of the observations, and
+        // each group mean is shrunk halfway towards it
+        let mu = spec.marginal(&chain, "mu").unwrap();
+        assert_eq!(mu.nparams, 1);
+        let samples = mu.flatchain();
+        let mean_mu = samples.iter().map(|guess| guess[0]).sum::<f64>() / samples.len() as f64;
+        assert_approx_eq!(mean_mu, 1.0, 0.2);
+
+        let marginals = spec.group_marginals(&chain, 0).unwrap();
+        assert_eq!(marginals.len(), 4);
+        for ((name, samples), (i, y)) in marginals.iter().zip(observations.iter().enumerate()) {
+            assert_eq!(*name, format!("x[{}]", i));
+            let mean = samples.iter().sum::<f64>() / samples.len() as f64;
+            assert_approx_eq!(mean, (y + 1.0) / 2.0, 0.25);
+        }
+
+        assert!(spec.group_marginals(&chain, 1).is_err());
+        assert!(spec.marginal(&chain, "sigma").is_err());
+        assert!(spec.marginal(&Chain::new(3, 1, 1), "mu").is_err());
+    }
+}
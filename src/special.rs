@@ -0,0 +1,116 @@
+//! Special functions
+
+use std::f64::consts::{PI, SQRT_2};
+
+use math;
+
+/// Standard normal cumulative distribution function
+pub(crate) fn normal_cdf(x: f64) -> f64 {
+    0.5 * erfc(-x / SQRT_2)
+}
+
+/// Natural log of the standard normal cumulative distribution function, accurate far into the
+/// lower tail
+pub(crate) fn ln_normal_cdf(x: f64) -> f64 {
+    if x < 0.0 {
+        ln_erfc(-x / SQRT_2) - 2f64.ln()
+    } else {
+        (-0.5 * erfc(x / SQRT_2)).ln_1p()
+    }
+}
+
+/// Natural log of the standard normal probability of the interval from `a` to `b`
+///
+/// Intervals in the upper tail are reflected into the lower tail, where the difference of the
+/// CDFs can be taken in log space without cancellation.
+pub(crate) fn ln_normal_interval(a: f64, b: f64) -> f64 {
+    let (a, b) = if a > 0.0 { (-b, -a) } else { (a, b) };
+    math::logsubexp(ln_normal_cdf(b), ln_normal_cdf(a))
+}
+
+/// Natural log of the gamma function for `x > 0`, with a relative error of about 1e-15
+/// (Lanczos approximation with g = 7)
+pub(crate) fn ln_gamma(x: f64) -> f64 {
+    const COEFFICIENTS: [f64; 9] = [
+        0.999_999_999_999_809_9,
+        676.520_368_121_885_1,
+        -1_259.139_216_722_402_8,
+        771.323_428_777_653_1,
+        -176.615_029_162_140_6,
+        12.507_343_278_686_905,
+        -0.138_571_095_265_720_12,
+        9.984_369_578_019_572e-6,
+        1.505_632_735_149_311_6e-7,
+    ];
+
+    if x < 0.5 {
+        // Reflection formula, as the approximation is only accurate for x >= 1/2
+        return (PI / (PI * x).sin()).ln() - ln_gamma(1.0 - x);
+    }
+    let x = x - 1.0;
+    let series = COEFFICIENTS[1..]
+        .iter()
+        .enumerate()
+        .fold(COEFFICIENTS[0], |sum, (i, c)| sum + c / (x + (i + 1) as f64));
+    let t = x + 7.5;
+    0.5 * (2.0 * PI).ln() + (x + 0.5) * t.ln() - t + series.ln()
+}
+
+/// Complementary error function, with a relative error below 1.2e-7 (Numerical Recipes)
+fn erfc(x: f64) -> f64 {
+    let value = ln_erfc(x.abs()).exp();
+    if x >= 0.0 {
+        value
+    } else {
+        2.0 - value
+    }
+}
+
+/// Natural log of the complementary error function for `z >= 0`
+fn ln_erfc(z: f64) -> f64 {
+    let t = 1.0 / (1.0 + 0.5 * z);
+    let poly = -z * z - 1.265_512_23
+        + t * (1.000_023_68
+            + t * (0.374_091_96
+                + t * (0.096_784_18
+                    + t * (-0.186_288_06
+                        + t * (0.278_868_07
+                            + t * (-1.135_203_98
+                                + t * (1.488_515_87 + t * (-0.822_152_23 + t * 0.170_872_77))))))));
+    t.ln() + poly
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_normal_cdf() {
+        assert_approx_eq!(normal_cdf(0.0), 0.5, 1e-7);
+        assert_approx_eq!(normal_cdf(1.0), 0.841_344_746, 1e-6);
+        assert_approx_eq!(normal_cdf(-1.96), 0.024_997_895, 1e-6);
+
+        assert_approx_eq!(ln_normal_cdf(-1.96), 0.024_997_895f64.ln(), 1e-6);
+        assert_approx_eq!(ln_normal_cdf(1.0), 0.841_344_746f64.ln(), 1e-6);
+        // The log of Phi(-40) = 3.655893540915e-350, which underflows as a plain f64
+        assert_approx_eq!(ln_normal_cdf(-40.0), -804.608_442_013_754, 1e-5);
+        assert_eq!(ln_normal_cdf(40.0), 0.0);
+
+        assert_approx_eq!(ln_normal_interval(-1.0, 1.0), 0.682_689_492f64.ln(), 1e-6);
+        // Phi(31) - Phi(30) = Phi(-30) - Phi(-31), far below the spacing of doubles near 1
+        assert_approx_eq!(
+            ln_normal_interval(30.0, 31.0),
+            ln_normal_cdf(-30.0),
+            1e-6
+        );
+    }
+
+    #[test]
+    fn test_ln_gamma() {
+        assert_approx_eq!(ln_gamma(1.0), 0.0, 1e-14);
+        assert_approx_eq!(ln_gamma(0.5), PI.sqrt().ln(), 1e-14);
+        assert_approx_eq!(ln_gamma(10.0), 362_880f64.ln(), 1e-12);
+        assert_approx_eq!(ln_gamma(0.1), 2.252_712_651_734_206, 1e-13);
+        assert_approx_eq!(ln_gamma(100.5), 361.435_540_467_778, 1e-10);
+    }
+}
@@ -0,0 +1,518 @@
+//! Stepping-stone estimation of the marginal likelihood
+
+use std::mem;
+
+use autocorr;
+use control::AcceptanceTarget;
+use errors::{EmceeError, Result};
+use guess::Guess;
+use moves::Move;
+use stores::{Chain, TemperedChains};
+use tempered::Tempered;
+use {stored_positions, EnsembleSampler, Prob};
+
+/// Estimate the marginal likelihood (evidence) of a model with the stepping-stone method
+///
+/// The evidence is written as a product of ratios between the normalising constants of power
+/// posteriors `prior * likelihood^beta` on a ladder of inverse temperatures
+/// `0 = beta_0 < beta_1 < ... < beta_K = 1`. For each rung `k < K` the sampler is run at
+/// `beta_k` and the ratio to the next rung is estimated from the likelihood of its samples,
+/// `r_k = mean(likelihood^(beta_(k+1) - beta_k))` (Xie et al. 2011, Syst. Biol. 60, 150). Each
+/// rung starts from the final positions of the previous one, and the first from the given
+/// initial positions, which need not be drawn from the prior.
+///
+/// The standard error combines the variance of each ratio, using the number of effective
+/// samples from the integrated autocorrelation time of the likelihood values at that rung.
+///
+/// With [`store_chains`](#structfield.store_chains) set, the samples of every rung are kept,
+/// and the sampler is also run at `beta = 1` so the posterior itself is available. With
+/// [`adapt`](#structfield.adapt) set, the moves are tuned separately at every rung.
+///
+/// ```rust
+/// # use emcee::{Guess, Prob, SteppingStone};
+/// struct Model;
+///
+/// impl Prob for Model {
+///     fn lnlike(&self, params: &Guess) -> f64 {
+///         -0.5 * params[0] * params[0]
+///     }
+///
+///     fn lnprior(&self, params: &Guess) -> f64 {
+///         // uniform on [-5, 5]
+///         if params[0].abs() < 5.0 { -(10f64).ln() } else { -::std::f64::INFINITY }
+///     }
+/// }
+///
+/// let model = Model;
+/// let p0: Vec<Guess> = (0..16).map(|i| Guess::new(&[0.5 * i as f64 - 4.0])).collect();
+///
+/// let mut stepping_stone = SteppingStone::new(16, 1, &model).unwrap();
+/// let evidence = stepping_stone
+///     .run(&p0, &SteppingStone::<Model>::schedule(10).unwrap())
+///     .unwrap();
+/// // the exact value is ln(sqrt(2 pi) / 10)
+/// let exact = (2.0 * std::f64::consts::PI).sqrt().ln() - (10f64).ln();
+/// assert!((evidence.ln_z - exact).abs() < 0.5);
+/// ```
+pub struct SteppingStone<'a, T: Prob + Sync + Send + 'a> {
+    model: &'a T,
+    nwalkers: usize,
+    dim: usize,
+    n_threads: Option<usize>,
+    seed: Option<Vec<usize>>,
+    moves: Option<Vec<(Box<dyn Move>, f64)>>,
+    tuning: Vec<(f64, Vec<Option<f64>>)>,
+
+    /// The number of iterations discarded at the start of each rung (default 100)
+    pub nburn: usize,
+
+    /// The number of iterations used for the estimate at each rung (default 500)
+    pub nsteps: usize,
+
+    /// Keep the samples and log likelihood values of every rung in
+    /// [`SteppingStoneEvidence::chains`](struct.SteppingStoneEvidence.html#structfield.chains)
+    /// (default false)
+    ///
+    /// The sampler is then also run at the final rung, `beta = 1`, for the same number of
+    /// iterations, so the cold chain can be analysed like any other posterior sample. As in
+    /// the chain of an [`EnsembleSampler`](struct.EnsembleSampler.html), the samples are the
+    /// positions given by [`Prob::stored_position`](trait.Prob.html#method.stored_position).
+    pub store_chains: bool,
+
+    /// Adapt the tuning of the moves during the burn-in of each rung, as in
+    /// [`EnsembleSampler::run_warmup`](struct.EnsembleSampler.html#method.run_warmup)
+    /// (default `None`)
+    ///
+    /// Hot rungs, whose distributions are close to the prior, usually want much bolder
+    /// proposals than the posterior, so every rung keeps its own tuning rather than sharing
+    /// one. The first run at a temperature starts from the tuning adapted at the rung below,
+    /// and later runs at the same temperature continue from what was learned there, which is
+    /// available from [`tuning`](#method.tuning).
+    pub adapt: Option<AcceptanceTarget>,
+}
+
+/// The result of [`SteppingStone::run`](struct.SteppingStone.html#method.run)
+#[derive(Debug, Clone)]
+pub struct SteppingStoneEvidence {
+    /// The natural logarithm of the estimated evidence
+    pub ln_z: f64,
+
+    /// The standard error of `ln_z`
+    pub ln_z_error: f64,
+
+    /// The estimated log ratio between each rung and the next, which sum to `ln_z`
+    pub ln_ratios: Vec<f64>,
+
+    /// The mean log likelihood of the samples at each rung, e.g. for a thermodynamic
+    /// integration cross-check
+    pub mean_lnlike: Vec<f64>,
+
+    /// The samples of every rung, including `beta = 1`, if
+    /// [`store_chains`](struct.SteppingStone.html#structfield.store_chains) was set
+    pub chains: Option<TemperedChains>,
+}
+
+impl<'a, T: Prob + Sync + Send + 'a> SteppingStone<'a, T> {
+    /// Create an estimator using `nwalkers` walkers
+    ///
+    /// Errors are handled by returning a [`Result`](errors/type.Result.html) which contains
+    /// [`EmceeError::InvalidInputs`](errors/enum.EmceeError.html) for the same reasons as
+    /// [`EnsembleSampler::new`](struct.EnsembleSampler.html#method.new).
+    pub fn new(nwalkers: usize, dim: usize, model: &'a T) -> Result<Self> {
+        EnsembleSampler::new(nwalkers, dim, model)?;
+        Ok(SteppingStone {
+            model,
+            nwalkers,
+            dim,
+            n_threads: None,
+            seed: None,
+            moves: None,
+            tuning: Vec::new(),
+            nburn: 100,
+            nsteps: 500,
+            store_chains: false,
+            adapt: None,
+        })
+    }
+
+    /// Create an estimator whose sampler evaluates the walkers on a pool of `n_threads` threads
+    ///
+    /// Errors are handled by returning a [`Result`](errors/type.Result.html) which contains
+    /// [`EmceeError::InvalidInputs`](errors/enum.EmceeError.html) for the same reasons as
+    /// [`EnsembleSampler::new`](struct.EnsembleSampler.html#method.new).
+    pub fn threaded(nwalkers: usize, dim: usize, model: &'a T, n_threads: usize) -> Result<Self> {
+        let mut stepping_stone = SteppingStone::new(nwalkers, dim, model)?;
+        stepping_stone.n_threads = Some(n_threads);
+        Ok(stepping_stone)
+    }
+
+    /// Seed the random number stream of the sampler, see
+    /// [`EnsembleSampler::seed`](struct.EnsembleSampler.html#method.seed)
+    pub fn seed(&mut self, seed: &[usize]) {
+        self.seed = Some(seed.to_vec());
+    }
+
+    /// Replace the moves used at each rung, see
+    /// [`EnsembleSampler::set_moves`](struct.EnsembleSampler.html#method.set_moves)
+    ///
+    /// Any tuning adapted at each rung is forgotten.
+    ///
+    /// Errors are handled by returning a [`Result`](errors/type.Result.html) which contains
+    /// [`EmceeError::InvalidInputs`](errors/enum.EmceeError.html) for the same reasons as
+    /// [`EnsembleSampler::set_moves`](struct.EnsembleSampler.html#method.set_moves).
+    pub fn set_moves(&mut self, moves: Vec<(Box<dyn Move>, f64)>) -> Result<&mut Self> {
+        let mut check = EnsembleSampler::new(self.nwalkers, self.dim, self.model)?;
+        check.set_moves(moves)?;
+        self.moves = Some(mem::take(&mut check.moves));
+        self.tuning.clear();
+        Ok(self)
+    }
+
+    /// The tuning parameter of each move adapted at inverse temperature `beta`, or `None` if
+    /// no run has adapted the moves at that temperature
+    ///
+    /// Moves without a [tuning parameter](moves/trait.Move.html#method.tuning) have `None`.
+    pub fn tuning(&self, beta: f64) -> Option<&[Option<f64>]> {
+        self.tuning
+            .iter()
+            .find(|&&(b, _)| b == beta)
+            .map(|(_, tuning)| tuning.as_slice())
+    }
+
+    /// A ladder of `nrungs` inverse temperatures from 0 to 1, `beta_k = (k / (nrungs - 1))^(1 /
+    /// 0.3)`
+    ///
+    /// These are the quantiles of a Beta(0.3, 1) distribution recommended by Xie et al., which
+    /// place most rungs at small `beta` where the power posterior changes fastest.
+    ///
+    /// Errors are handled by returning a [`Result`](errors/type.Result.html) which contains
+    /// [`EmceeError::InvalidInputs`](errors/enum.EmceeError.html) if `nrungs` is less than 2.
+    pub fn schedule(nrungs: usize) -> Result<Vec<f64>> {
+        if nrungs < 2 {
+            return Err(EmceeError::InvalidInputs(
+                "a ladder needs at least two rungs".into(),
+            ));
+        }
+        Ok((0..nrungs)
+            .map(|k| (k as f64 / (nrungs - 1) as f64).powf(1.0 / 0.3))
+            .collect())
+    }
+
+    /// Run the sampler at every rung of `schedule` but the last, and combine the likelihood
+    /// values into an estimate of the evidence
+    ///
+    /// `schedule` must increase strictly from 0 to 1, e.g. [`schedule`](#method.schedule).
+    ///
+    /// Errors are handled by returning a [`Result`](errors/type.Result.html) which contains
+    /// [`EmceeError::InvalidInputs`](errors/enum.EmceeError.html) if `p0` does not contain one
+    /// position per walker, the schedule is invalid or [`nsteps`](#structfield.nsteps) is zero,
+    /// or any error from the sampler.
+    pub fn run(&mut self, p0: &[Guess], schedule: &[f64]) -> Result<SteppingStoneEvidence> {
+        if p0.len() != self.nwalkers {
+            return Err(EmceeError::InvalidInputs(format!(
+                "expected {} initial positions, got {}",
+                self.nwalkers,
+                p0.len()
+            )));
+        }
+        if schedule.len() < 2 || schedule[0] != 0.0 || schedule[schedule.len() - 1] != 1.0
+            || schedule.windows(2).any(|pair| pair[1] <= pair[0] || pair[1].is_nan())
+        {
+            return Err(EmceeError::InvalidInputs(
+                "the schedule must increase strictly from 0 to 1".into(),
+            ));
+        }
+        if self.nsteps == 0 {
+            return Err(EmceeError::InvalidInputs(
+                "at least one step per rung is required".into(),
+            ));
+        }
+
+        let tempered = Tempered::new(self.model);
+        let mut sampler = match self.n_threads {
+            Some(n_threads) => {
+                EnsembleSampler::threaded(self.nwalkers, self.dim, &tempered, n_threads)?
+            }
+            None => EnsembleSampler::new(self.nwalkers, self.dim, &tempered)?,
+        };
+        sampler.storechain = false;
+        if let Some(ref seed) = self.seed {
+            sampler.seed(seed);
+        }
+        let custom_moves = self.moves.take();
+        let restore = custom_moves.is_some();
+        if let Some(moves) = custom_moves {
+            sampler.set_moves(moves)?;
+        }
+
+        let result = self.climb(&tempered, &mut sampler, p0, schedule);
+
+        if restore {
+            sampler.reset();
+            self.moves = Some(mem::take(&mut sampler.moves));
+        }
+        result
+    }
+
+    /// Sample each rung in turn and combine the ratios
+    fn climb(
+        &mut self,
+        tempered: &Tempered<'a, T>,
+        sampler: &mut EnsembleSampler<Tempered<'a, T>>,
+        p0: &[Guess],
+        schedule: &[f64],
+    ) -> Result<SteppingStoneEvidence> {
+        let mut pos = p0.to_vec();
+        let mut ln_ratios = Vec::with_capacity(schedule.len() - 1);
+        let mut mean_lnlike = Vec::with_capacity(schedule.len() - 1);
+        let mut variance = 0f64;
+        let mut chains = if self.store_chains {
+            Some(TemperedChains::default())
+        } else {
+            None
+        };
+
+        for pair in schedule.windows(2) {
+            let (beta, delta) = (pair[0], pair[1] - pair[0]);
+            let lnlike = self.sample_rung(tempered, sampler, &mut pos, beta, chains.as_mut())?;
+
+            let values: Vec<f64> = lnlike
+                .iter()
+                .flat_map(|series| series.iter().cloned())
+                .collect();
+            let n = values.len() as f64;
+            let max = values.iter().cloned().fold(-f64::INFINITY, f64::max);
+            let weights: Vec<f64> = values
+                .iter()
+                .map(|value| (delta * (value - max)).exp())
+                .collect();
+            let mean_weight = weights.iter().sum::<f64>() / n;
+            let weight_variance = weights
+                .iter()
+                .map(|w| (w - mean_weight).powi(2))
+                .sum::<f64>() / (n - 1.0).max(1.0);
+
+            let tau = autocorr::integrated_time(&lnlike, 5.0).max(1.0);
+            let neff = n / tau;
+
+            ln_ratios.push(delta * max + mean_weight.ln());
+            mean_lnlike.push(values.iter().sum::<f64>() / n);
+            variance += weight_variance / (neff * mean_weight * mean_weight);
+        }
+
+        if chains.is_some() {
+            self.sample_rung(tempered, sampler, &mut pos, 1.0, chains.as_mut())?;
+        }
+
+        Ok(SteppingStoneEvidence {
+            ln_z: ln_ratios.iter().sum(),
+            ln_z_error: variance.sqrt(),
+            ln_ratios,
+            mean_lnlike,
+            chains,
+        })
+    }
+
+    /// Run the sampler at `beta`, starting from and updating `pos`, and return the log
+    /// likelihood of each walker at each retained iteration, storing the samples in `chains`
+    fn sample_rung(
+        &mut self,
+        tempered: &Tempered<'a, T>,
+        sampler: &mut EnsembleSampler<Tempered<'a, T>>,
+        pos: &mut Vec<Guess>,
+        beta: f64,
+        chains: Option<&mut TemperedChains>,
+    ) -> Result<Vec<Vec<f64>>> {
+        let model = self.model;
+        tempered.set_beta(beta);
+
+        // With adaptation the burn-in is a warmup of the moves at this temperature
+        let nburn = match self.adapt {
+            Some(ref control) if self.nburn > 0 => {
+                if let Some(tuning) = self.tuning(beta) {
+                    for (&mut (ref mut m, _), tuning) in sampler.moves.iter_mut().zip(tuning) {
+                        if let Some(tuning) = *tuning {
+                            m.set_tuning(tuning);
+                        }
+                    }
+                }
+                let state = sampler.run_warmup(pos, self.nburn, control)?;
+                *pos = state.pos.to_vec();
+
+                let tuning = sampler.moves.iter().map(|(m, _)| m.tuning()).collect();
+                match self.tuning.iter_mut().find(|&&mut (b, _)| b == beta) {
+                    Some(stored) => stored.1 = tuning,
+                    None => self.tuning.push((beta, tuning)),
+                }
+                0
+            }
+            _ => self.nburn,
+        };
+
+        // The tempered log probability already contains the log likelihood, except at infinite
+        // temperature
+        let mut lnlike = vec![Vec::with_capacity(self.nsteps); self.nwalkers];
+        let mut chain = chains
+            .as_ref()
+            .map(|_| Chain::new(self.dim, self.nwalkers, self.nsteps));
+        let state = sampler.sample(pos, nburn + self.nsteps, |step| {
+            if step.iteration < nburn {
+                return;
+            }
+            let positions = step.pos.iter().zip(step.lnprob.iter());
+            for (walker, (guess, lnprob)) in positions.enumerate() {
+                let value = if beta == 0.0 {
+                    model.lnlike(guess)
+                } else {
+                    (lnprob - model.lnprior(guess)) / beta
+                };
+                lnlike[walker].push(value);
+            }
+            if let Some(chain) = chain.as_mut() {
+                for (walker, guess) in stored_positions(model, &step.pos).iter().enumerate() {
+                    chain.set_params(walker, step.iteration - nburn, &guess.values);
+                }
+            }
+        })?;
+        *pos = state.pos.to_vec();
+
+        if let (Some(chains), Some(chain)) = (chains, chain) {
+            let flat = (0..self.nsteps)
+                .flat_map(|iter| lnlike.iter().map(move |series| series[iter]))
+                .collect();
+            chains.push(beta, chain, flat);
+        }
+        Ok(lnlike)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use testkit::BoxedGaussian;
+
+    /// Gaussian likelihood with standard deviation 0.5 and a uniform prior on [-10, 10] in each
+    /// parameter
+    fn model() -> BoxedGaussian {
+        BoxedGaussian {
+            sigma: 0.5,
+            ..BoxedGaussian::default()
+        }
+    }
+
+    fn initial() -> Vec<Guess> {
+        (0..16)
+            .map(|i| Guess::new(&[1.1 * i as f64 - 8.0, 9.0 - 1.05 * i as f64]))
+            .collect()
+    }
+
+    #[test]
+    fn test_evidence_of_gaussian() {
+        let model = model();
+        let mut stepping_stone = SteppingStone::new(16, 2, &model).unwrap();
+        stepping_stone.seed(&[1]);
+        let schedule = SteppingStone::<BoxedGaussian>::schedule(16).unwrap();
+        let evidence = stepping_stone.run(&initial(), &schedule).unwrap();
+
+        let expected = model.ln_evidence();
+        assert_eq!(evidence.ln_ratios.len(), 15);
+        assert!(evidence.ln_z_error > 0.0);
+        assert!(
+            (evidence.ln_z - expected).abs() < 3.0 * evidence.ln_z_error + 0.05,
+            "{} +/- {}, expected {}",
+            evidence.ln_z,
+            evidence.ln_z_error,
+            expected
+        );
+
+        // The samples concentrate on the likelihood peak as beta increases
+        assert!(evidence.mean_lnlike[0] < evidence.mean_lnlike[14]);
+    }
+
+    #[test]
+    fn test_stored_chains() {
+        let model = model();
+        let mut stepping_stone = SteppingStone::new(16, 2, &model).unwrap();
+        stepping_stone.seed(&[2]);
+        stepping_stone.nsteps = 300;
+        let schedule = SteppingStone::<BoxedGaussian>::schedule(5).unwrap();
+        assert!(stepping_stone.run(&initial(), &schedule).unwrap().chains.is_none());
+
+        stepping_stone.store_chains = true;
+        let evidence = stepping_stone.run(&initial(), &schedule).unwrap();
+        let chains = evidence.chains.unwrap();
+        assert_eq!(chains.ntemps(), 5);
+        assert_eq!(chains.betas(), &schedule[..]);
+        for k in 0..4 {
+            assert_eq!(chains.chain(k).niterations, 300);
+            let lnlike = chains.lnlike(k);
+            assert_eq!(lnlike.len(), 300 * 16);
+            assert_approx_eq!(
+                lnlike.iter().sum::<f64>() / lnlike.len() as f64,
+                evidence.mean_lnlike[k],
+                1e-10
+            );
+            for (guess, value) in chains.chain(k).flatchain().iter().zip(lnlike).take(50) {
+                assert_approx_eq!(*value, model.lnlike(guess), 1e-10);
+            }
+        }
+
+        // The cold chain samples the posterior, a Gaussian with variance 0.25
+        let cold = chains.cold_chain().unwrap();
+        let samples = cold.flatchain();
+        let variance = samples.iter().map(|guess| guess[0] * guess[0]).sum::<f64>()
+            / samples.len() as f64;
+        assert_approx_eq!(variance, 0.25, 0.05);
+        assert_eq!(chains.cold_lnlike().unwrap().len(), 300 * 16);
+    }
+
+    #[test]
+    fn test_tuning_per_rung() {
+        use moves::GaussianMove;
+
+        let model = model();
+        let mut stepping_stone = SteppingStone::new(16, 2, &model).unwrap();
+        stepping_stone.seed(&[3]);
+        stepping_stone.nburn = 200;
+        stepping_stone.nsteps = 200;
+        stepping_stone
+            .set_moves(vec![(Box::new(GaussianMove::isotropic(0.01).unwrap()), 1.0)])
+            .unwrap();
+        let schedule = SteppingStone::<BoxedGaussian>::schedule(5).unwrap();
+        stepping_stone.run(&initial(), &schedule).unwrap();
+        assert!(stepping_stone.tuning(0.0).is_none());
+
+        stepping_stone.adapt = Some(AcceptanceTarget::default());
+        stepping_stone.run(&initial(), &schedule).unwrap();
+        let scale =
+            |stepping_stone: &SteppingStone<BoxedGaussian>, beta| stepping_stone.tuning(beta).unwrap()[0];
+
+        // The prior is much wider than the posterior, so the hottest rung takes bolder steps than
+        // the coldest sampled one
+        let hot = scale(&stepping_stone, 0.0).unwrap();
+        assert!(hot > 2.0 * scale(&stepping_stone, schedule[3]).unwrap());
+        assert!(stepping_stone.tuning(1.0).is_none());
+
+        // Later runs continue from the tuning learned at each temperature
+        stepping_stone.run(&initial(), &schedule).unwrap();
+        assert!((scale(&stepping_stone, 0.0).unwrap() / hot).ln().abs() < 1.0);
+
+        stepping_stone
+            .set_moves(vec![(Box::new(GaussianMove::isotropic(0.01).unwrap()), 1.0)])
+            .unwrap();
+        assert!(stepping_stone.tuning(0.0).is_none());
+    }
+
+    #[test]
+    fn test_invalid_inputs() {
+        let model = model();
+        let mut stepping_stone = SteppingStone::new(16, 2, &model).unwrap();
+        let p0 = initial();
+        assert!(stepping_stone.run(&p0[..4], &[0.0, 1.0]).is_err());
+        assert!(stepping_stone.run(&p0, &[0.0, 0.5]).is_err());
+        assert!(stepping_stone.run(&p0, &[0.0, 0.7, 0.3, 1.0]).is_err());
+        stepping_stone.nsteps = 0;
+        assert!(stepping_stone.run(&p0, &[0.0, 1.0]).is_err());
+        assert!(SteppingStone::<BoxedGaussian>::schedule(1).is_err());
+    }
+}
@@ -1,88 +1,1038 @@
+use autocorr;
+use errors::{EmceeError, Result};
+use float::Float;
 use guess::Guess;
+use linalg;
+use math;
+use parallel;
+use rand::Rng;
+use std::iter::StepBy;
+use std::ops::Range;
+use std::sync::Arc;
 
-#[derive(Debug, Default)]
-pub struct Chain {
-    data: Vec<f64>,
+/// The number of iterations held by each block of a chain's storage
+const CHUNK_ITERATIONS: usize = 256;
+
+/// The stored positions of every walker at every stored iteration
+///
+/// Returned by [`EnsembleSampler::chain`](struct.EnsembleSampler.html#method.chain).
+///
+/// The values are held in blocks of a fixed number of iterations, so a chain grows one
+/// iteration at a time without copying what it already holds, and the sampler only allocates
+/// the iterations it has actually stored. Use
+/// [`reserve_iterations`](#method.reserve_iterations) to allocate ahead of time instead.
+///
+/// The values are `f64` by default. Storage, access and resizing are available for any
+/// [`Float`](trait.Float.html) type, e.g. `Chain<f32>` to halve the memory of a long run, and
+/// [`to_precision`](#method.to_precision) converts between them; the analysis methods need
+/// `f64` values.
+#[derive(Debug, Default, Clone)]
+pub struct Chain<F: Float = f64> {
+    chunks: Vec<Vec<F>>,
+    pub(crate) names: Option<Arc<[String]>>,
+
+    /// The number of parameters per walker
     pub nparams: usize,
+
+    /// The number of walkers
     pub nwalkers: usize,
+
+    /// The number of stored iterations
     pub niterations: usize,
 }
 
 impl Chain {
+    /// Create a chain of the given size, initialised to zero
     pub fn new(nparams: usize, nwalkers: usize, niterations: usize) -> Chain {
-        Chain {
+        Chain::zeros(nparams, nwalkers, niterations)
+    }
+}
+
+impl<F: Float> Chain<F> {
+    /// Create a chain of the given size holding values of type `F`, initialised to zero
+    ///
+    /// [`new`](#method.new) creates the usual `f64` chain; use e.g.
+    /// `Chain::<f32>::zeros(nparams, nwalkers, niterations)` for half the memory.
+    pub fn zeros(nparams: usize, nwalkers: usize, niterations: usize) -> Chain<F> {
+        let mut chain = Chain {
             nparams: nparams,
             nwalkers: nwalkers,
-            niterations: niterations,
-            data: vec![0f64; nparams * nwalkers * niterations],
-        }
+            niterations: 0,
+            chunks: Vec::new(),
+            names: None,
+        };
+        chain.reserve_iterations(niterations);
+        chain.extend(niterations);
+        chain
     }
 
-    pub fn set(&mut self, param_idx: usize, walker_idx: usize, iteration_idx: usize, value: f64) {
+    /// Set a single parameter value
+    pub fn set(&mut self, param_idx: usize, walker_idx: usize, iteration_idx: usize, value: F) {
         assert!(param_idx < self.nparams);
         assert!(walker_idx < self.nwalkers);
         assert!(iteration_idx < self.niterations);
 
-        let idx = self.index(param_idx, walker_idx, iteration_idx);
+        let (chunk, offset) = self.locate(self.index(param_idx, walker_idx, iteration_idx));
 
-        self.data[idx] = value;
+        self.chunks[chunk][offset] = value;
     }
 
-    pub fn get(&self, param_idx: usize, walker_idx: usize, iteration_idx: usize) -> f64 {
+    /// Get a single parameter value
+    pub fn get(&self, param_idx: usize, walker_idx: usize, iteration_idx: usize) -> F {
         assert!(param_idx < self.nparams);
         assert!(walker_idx < self.nwalkers);
         assert!(iteration_idx < self.niterations);
 
-        let idx = self.index(param_idx, walker_idx, iteration_idx);
+        let (chunk, offset) = self.locate(self.index(param_idx, walker_idx, iteration_idx));
 
-        self.data[idx]
+        self.chunks[chunk][offset]
     }
 
-    pub fn set_params(&mut self, walker_idx: usize, iteration_idx: usize, newdata: &[f64]) {
+    /// Get the position of every walker at one iteration, as a matrix with one row per walker
+    /// and one column per parameter
+    ///
+    /// This is the state of the ensemble at that point of the run, e.g. for one frame of an
+    /// animation of the walkers, or to start another run or analysis from that time slice by
+    /// passing each row to [`Guess::new`](struct.Guess.html#method.new).
+    ///
+    /// # Panics
+    ///
+    /// Panics if `iteration_idx >= niterations`.
+    pub fn positions_at(&self, iteration_idx: usize) -> Vec<Vec<F>> {
+        assert!(
+            iteration_idx < self.niterations,
+            "iteration {} is past the end of the chain ({} iterations)",
+            iteration_idx,
+            self.niterations
+        );
+        (0..self.nwalkers)
+            .map(|walker| {
+                (0..self.nparams)
+                    .map(|param| self.get(param, walker, iteration_idx))
+                    .collect()
+            })
+            .collect()
+    }
+
+    /// Set all parameter values of one walker at one iteration
+    pub fn set_params(&mut self, walker_idx: usize, iteration_idx: usize, newdata: &[F]) {
         assert_eq!(newdata.len(), self.nparams);
         for (idx, value) in newdata.iter().enumerate() {
             self.set(idx, walker_idx, iteration_idx, *value);
         }
     }
 
-    pub fn flatchain(&self) -> Vec<Guess> {
-        let mut out = Vec::with_capacity(self.niterations * self.nwalkers);
-        let mut buffer = vec![0f64; self.nparams];
-        for iter in 0..self.niterations {
+    /// Grow the chain by `additional` iterations, initialised to zero
+    pub fn extend(&mut self, additional: usize) {
+        let end = (self.niterations + additional) * self.stride();
+        let mut nvalues = self.niterations * self.stride();
+        while nvalues < end {
+            let (chunk, offset) = self.locate(nvalues);
+            if chunk == self.chunks.len() {
+                self.chunks.push(Vec::new());
+            }
+            let n = (self.chunk_len() - offset).min(end - nvalues);
+            self.chunks[chunk].resize(offset + n, F::default());
+            nvalues += n;
+        }
+        self.niterations += additional;
+    }
+
+    /// Allocate storage for at least `additional` more iterations
+    ///
+    /// The chain itself is unchanged, but growing it by up to `additional` iterations with
+    /// [`extend`](#method.extend) will not allocate, e.g. to fail early rather than part way
+    /// through a long run if the memory is not available.
+    pub fn reserve_iterations(&mut self, additional: usize) {
+        let end = (self.niterations + additional) * self.stride();
+        let mut nvalues = self.niterations * self.stride();
+        while nvalues < end {
+            let (chunk, offset) = self.locate(nvalues);
+            if chunk == self.chunks.len() {
+                self.chunks.push(Vec::new());
+            }
+            let n = (self.chunk_len() - offset).min(end - nvalues);
+            let storage = &mut self.chunks[chunk];
+            if storage.capacity() < offset + n {
+                let len = storage.len();
+                storage.reserve_exact(offset + n - len);
+            }
+            nvalues += n;
+        }
+    }
+
+    /// Keep only the first `niterations` iterations
+    ///
+    /// Has no effect if the chain is already shorter, e.g. to drop the part of a run after it
+    /// diverged.
+    pub fn truncate(&mut self, niterations: usize) {
+        if niterations < self.niterations {
+            self.niterations = niterations;
+            let (chunk, offset) = self.locate(niterations * self.stride());
+            self.chunks.truncate(chunk + 1);
+            if let Some(storage) = self.chunks.get_mut(chunk) {
+                storage.truncate(offset);
+            }
+        }
+    }
+
+    /// Move every other one of the first `nfilled` iterations to the front of the chain, keeping
+    /// iterations `0, 2, 4, ...`, and return how many were kept
+    ///
+    /// The size of the chain is unchanged, so the freed iterations can be filled again.
+    pub(crate) fn keep_even_iterations(&mut self, nfilled: usize) -> usize {
+        let stride = self.stride();
+        let nkept = nfilled.div_ceil(2);
+        for k in 1..nkept {
+            let (from, from_offset) = self.locate(2 * k * stride);
+            let (to, to_offset) = self.locate(k * stride);
+            if from == to {
+                self.chunks[from].copy_within(from_offset..from_offset + stride, to_offset);
+            } else {
+                let (head, tail) = self.chunks.split_at_mut(from);
+                head[to][to_offset..to_offset + stride]
+                    .copy_from_slice(&tail[0][from_offset..from_offset + stride]);
+            }
+        }
+        nkept
+    }
+
+    /// Copy the chain into two chains, containing the iterations before `iteration_idx` and from
+    /// `iteration_idx` onwards
+    ///
+    /// This is useful to keep e.g. burn-in and production samples as separate objects.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `iteration_idx > niterations`.
+    pub fn split_at(&self, iteration_idx: usize) -> (Chain<F>, Chain<F>) {
+        assert!(
+            iteration_idx <= self.niterations,
+            "split index {} is past the end of the chain ({} iterations)",
+            iteration_idx,
+            self.niterations
+        );
+
+        let part = |start: usize, end: usize| {
+            let mut chain = Chain::<F>::zeros(self.nparams, self.nwalkers, end - start);
+            chain.names = self.names.clone();
+            let stride = self.stride();
+            for (i, iteration) in (start..end).enumerate() {
+                let (chunk, offset) = self.locate(iteration * stride);
+                let (to, to_offset) = chain.locate(i * stride);
+                chain.chunks[to][to_offset..to_offset + stride]
+                    .copy_from_slice(&self.chunks[chunk][offset..offset + stride]);
+            }
+            chain
+        };
+        (
+            part(0, iteration_idx),
+            part(iteration_idx, self.niterations),
+        )
+    }
+
+    /// The names of the parameters, if the chain has them
+    ///
+    /// Chains of [derived quantities](#method.map_derived) are named, as is the chain stored by
+    /// the sampler once
+    /// [`EnsembleSampler::set_param_names`](struct.EnsembleSampler.html#method.set_param_names)
+    /// has been called.
+    pub fn param_names(&self) -> Option<&[String]> {
+        self.names.as_deref()
+    }
+
+    /// Name the parameters, e.g. `&["mass", "radius"]`
+    ///
+    /// The names are carried by chains split or selected from this one and by exports such as
+    /// [`write_csv`](#method.write_csv), and the samples returned by e.g.
+    /// [`flatchain`](#method.flatchain) can be read by name with
+    /// [`Guess::get`](struct.Guess.html#method.get).
+    ///
+    /// Errors are handled by returning a [`Result`](errors/type.Result.html) which contains
+    /// [`EmceeError::InvalidInputs`](errors/enum.EmceeError.html) if the number of names is not
+    /// `nparams`.
+    pub fn set_param_names(&mut self, names: &[&str]) -> Result<&mut Self> {
+        if names.len() != self.nparams {
+            return Err(EmceeError::InvalidInputs(format!(
+                "{} names were given for {} parameters",
+                names.len(),
+                self.nparams
+            )));
+        }
+        self.names = Some(names.iter().map(|name| name.to_string()).collect());
+        Ok(self)
+    }
+
+    /// The index of the parameter called `name`, if the chain has one
+    pub fn param_index(&self, name: &str) -> Option<usize> {
+        self.names.as_ref()?.iter().position(|candidate| candidate == name)
+    }
+
+    /// Copy the chain into another floating point type, e.g. `f32` to halve its memory
+    ///
+    /// Each value is rounded to the nearest value of the new type.
+    pub fn to_precision<G: Float>(&self) -> Chain<G> {
+        Chain {
+            chunks: self
+                .chunks
+                .iter()
+                .map(|chunk| chunk.iter().map(|value| G::from_f64(value.to_f64())).collect())
+                .collect(),
+            names: self.names.clone(),
+            nparams: self.nparams,
+            nwalkers: self.nwalkers,
+            niterations: self.niterations,
+        }
+    }
+}
+
+impl Chain {
+    /// Compute a chain of derived quantities from every sample, e.g. a mass from a sampled
+    /// density and radius
+    ///
+    /// `derive` maps the parameters of one sample to the values of the quantities called
+    /// `names`. The result is an ordinary chain with the same walkers and iterations, so
+    /// summaries, autocorrelation times and everything else available for the sampled
+    /// parameters can be computed for the derived quantities as well.
+    ///
+    /// Errors are handled by returning a [`Result`](errors/type.Result.html) which contains
+    /// [`EmceeError::InvalidInputs`](errors/enum.EmceeError.html) if `derive` returns a number of
+    /// values other than the number of names.
+    pub fn map_derived<F>(&self, names: &[&str], mut derive: F) -> Result<Chain>
+    where
+        F: FnMut(&Guess) -> Vec<f64>,
+    {
+        let mut out = Chain::new(names.len(), self.nwalkers, self.niterations);
+        out.names = Some(names.iter().map(|name| name.to_string()).collect());
+        for iteration in 0..self.niterations {
             for walker in 0..self.nwalkers {
-                for (i, value) in buffer.iter_mut().enumerate() {
-                    *value = self.get(i, walker, iter);
+                let index = iteration * self.nwalkers + walker;
+                let values = derive(&self.sample(index));
+                if values.len() != names.len() {
+                    return Err(EmceeError::InvalidInputs(format!(
+                        "{} derived values were computed for {} names",
+                        values.len(),
+                        names.len()
+                    )));
                 }
+                out.set_params(walker, iteration, &values);
+            }
+        }
+        Ok(out)
+    }
+
+    /// Compute the posterior of linear combinations of the parameters
+    ///
+    /// Each row of `combinations` is a vector `v` with one coefficient per parameter, and the
+    /// result holds the chain of `v · θ` for every row, named `combination[0]`,
+    /// `combination[1]` and so on, together with its mean and covariance. This gives the
+    /// constraints in a rotated parameter basis, e.g. along the principal axes of a degeneracy,
+    /// directly from the samples. Discard the burn-in first, e.g. with
+    /// [`split_at`](#method.split_at).
+    ///
+    /// Errors are handled by returning a [`Result`](errors/type.Result.html) which contains
+    /// [`EmceeError::InvalidInputs`](errors/enum.EmceeError.html) if there are no combinations,
+    /// a row does not have one coefficient per parameter, or the chain is empty.
+    pub fn project(&self, combinations: &[Vec<f64>]) -> Result<Projection> {
+        if combinations.is_empty() {
+            return Err(EmceeError::InvalidInputs("no combinations were given".into()));
+        }
+        if let Some(row) = combinations.iter().find(|row| row.len() != self.nparams) {
+            return Err(EmceeError::InvalidInputs(format!(
+                "a combination has {} coefficients, the chain has {} parameters",
+                row.len(),
+                self.nparams
+            )));
+        }
+        if self.niterations == 0 || self.nwalkers == 0 {
+            return Err(EmceeError::InvalidInputs("the chain is empty".into()));
+        }
+
+        let names: Vec<String> = (0..combinations.len())
+            .map(|i| format!("combination[{}]", i))
+            .collect();
+        let names: Vec<&str> = names.iter().map(|name| name.as_str()).collect();
+        let chain = self.map_derived(&names, |guess| {
+            combinations
+                .iter()
+                .map(|row| row.iter().zip(&guess.values).map(|(v, x)| v * x).sum())
+                .collect()
+        })?;
+        let (mean, covariance) = linalg::mean_covariance(&chain.flatchain());
+        Ok(Projection {
+            chain,
+            mean,
+            covariance,
+        })
+    }
+
+    /// Compute the principal components of the posterior
+    ///
+    /// The components are the eigenvectors of the parameter covariance over every stored
+    /// sample, ordered from the largest variance to the smallest. The last components are the
+    /// best-constrained directions in parameter space, and the first ones, when much wider than
+    /// the rest, are degeneracies of the model. Pass
+    /// [`eigenvectors`](struct.PrincipalComponents.html#structfield.eigenvectors) to
+    /// [`project`](#method.project) for the chain in the rotated basis. Discard the burn-in
+    /// first, e.g. with [`split_at`](#method.split_at).
+    ///
+    /// Errors are handled by returning a [`Result`](errors/type.Result.html) which contains
+    /// [`EmceeError::InvalidInputs`](errors/enum.EmceeError.html) if the chain holds fewer than
+    /// two samples.
+    pub fn principal_components(&self) -> Result<PrincipalComponents> {
+        if self.niterations * self.nwalkers < 2 {
+            return Err(EmceeError::InvalidInputs(
+                "at least two samples are needed for principal components".into(),
+            ));
+        }
+
+        let (mean, covariance) = linalg::mean_covariance(&self.flatchain());
+        let (eigenvalues, eigenvectors) = linalg::symmetric_eigen(&covariance);
+        Ok(PrincipalComponents {
+            mean,
+            // Rounding can leave the variance of a degenerate direction slightly negative
+            eigenvalues: eigenvalues.iter().map(|value| value.max(0.0)).collect(),
+            eigenvectors,
+        })
+    }
+
+    /// Estimate the integrated autocorrelation time of each parameter
+    ///
+    /// The autocorrelation functions of the walkers of a large chain are computed on every
+    /// available core.
+    pub fn integrated_time(&self, c: f64) -> Vec<f64> {
+        let nthreads = parallel::nthreads(self.nvalues_stored());
+        (0..self.nparams)
+            .map(|param| {
+                let walkers: Vec<Vec<f64>> = parallel::map(self.nwalkers, nthreads, |walker| {
+                    (0..self.niterations)
+                        .map(|iter| self.get(param, walker, iter))
+                        .collect()
+                });
+                autocorr::integrated_time_parallel(&walkers, c, nthreads)
+            })
+            .collect()
+    }
+
+    /// Estimate the integrated autocorrelation time of each parameter, checking that the chain
+    /// is long enough for the estimates to be trusted
+    ///
+    /// This follows `get_autocorr_time` in Python `emcee`: the autocorrelation function of each
+    /// walker is computed with an FFT and averaged over the walkers, and the sum is truncated at
+    /// the smallest window `M` with `M >= c * tau(M)` (`emcee` uses `c = 5`). The estimates are
+    /// only returned if the chain is at least `tol` times longer than every one of them (`emcee`
+    /// uses `tol = 50`); use [`integrated_time`](#method.integrated_time) to get them
+    /// regardless.
+    ///
+    /// Errors are handled by returning a [`Result`](errors/type.Result.html) which contains
+    /// [`EmceeError::InvalidInputs`](errors/enum.EmceeError.html) if `c` or `tol` is not
+    /// positive, the chain is empty, or it is shorter than `tol` autocorrelation times for any
+    /// parameter, in which case the message lists the estimates.
+    pub fn autocorr_time(&self, c: f64, tol: f64) -> Result<Vec<f64>> {
+        if c.is_nan() || c <= 0.0 || tol.is_nan() || tol <= 0.0 {
+            return Err(EmceeError::InvalidInputs(
+                "the window factor and tolerance must be positive".into(),
+            ));
+        }
+
+        if self.niterations == 0 {
+            return Err(EmceeError::InvalidInputs("the chain is empty".into()));
+        }
+
+        let tau = self.integrated_time(c);
+        let nshort = tau
+            .iter()
+            .filter(|&&t| t.is_nan() || tol * t > self.niterations as f64)
+            .count();
+        if nshort > 0 {
+            return Err(EmceeError::InvalidInputs(format!(
+                "the chain is shorter than {} times the integrated autocorrelation time for {} \
+                 parameter(s); N/{} = {:.1}, tau: {:?}",
+                tol,
+                nshort,
+                tol,
+                self.niterations as f64 / tol,
+                tau
+            )));
+        }
+
+        Ok(tau)
+    }
+
+    /// Return the samples in iteration order, then walker order
+    ///
+    /// The samples of a large chain are gathered on every available core.
+    pub fn flatchain(&self) -> Vec<Guess> {
+        let nthreads = parallel::nthreads(self.nvalues_stored());
+        let iterations = parallel::map(self.niterations, nthreads, |iter| {
+            (0..self.nwalkers)
+                .map(|walker| Guess {
+                    values: (0..self.nparams)
+                        .map(|param| self.get(param, walker, iter))
+                        .collect(),
+                })
+                .collect::<Vec<_>>()
+        });
+
+        let mut out = Vec::with_capacity(self.niterations * self.nwalkers);
+        for samples in iterations {
+            out.extend(samples);
+        }
+        out
+    }
+
+    /// The number of values held by the stored iterations
+    fn nvalues_stored(&self) -> usize {
+        self.niterations * self.nwalkers * self.nparams
+    }
+
+    /// Return the samples after the first `discard` iterations, keeping every `thin`th
+    /// iteration, with the semantics of `get_chain` in Python `emcee`
+    ///
+    /// The kept iterations are `discard + thin - 1`, `discard + 2 * thin - 1`, ..., exactly as
+    /// in `emcee`, so indices carry over when porting analysis code. With `flat` the samples
+    /// are returned as one list in the order of [`flatchain`](#method.flatchain), like an
+    /// `(nsteps * nwalkers, ndim)` array; otherwise they are grouped by iteration, like an
+    /// `(nsteps, nwalkers, ndim)` array. Discarding the whole chain gives no samples.
+    ///
+    /// Errors are handled by returning a [`Result`](errors/type.Result.html) which contains
+    /// [`EmceeError::InvalidInputs`](errors/enum.EmceeError.html) if `thin` is zero.
+    pub fn get_chain(&self, flat: bool, thin: usize, discard: usize) -> Result<ChainSamples> {
+        let iterations = kept_iterations(self.niterations, discard, thin)?;
+        let position = |iter, walker| Guess {
+            values: (0..self.nparams)
+                .map(|param| self.get(param, walker, iter))
+                .collect(),
+        };
+        Ok(if flat {
+            ChainSamples::Flat(
+                iterations
+                    .flat_map(|iter| (0..self.nwalkers).map(move |walker| (iter, walker)))
+                    .map(|(iter, walker)| position(iter, walker))
+                    .collect(),
+            )
+        } else {
+            ChainSamples::Shaped(
+                iterations
+                    .map(|iter| (0..self.nwalkers).map(|walker| position(iter, walker)).collect())
+                    .collect(),
+            )
+        })
+    }
+
+    /// Return the samples after the first `discard` iterations, keeping every `thin`th
+    /// iteration, in the order of [`flatchain`](#method.flatchain)
+    ///
+    /// This drops the burn-in and thins the chain in one go, keeping the same iterations as
+    /// [`get_chain`](#method.get_chain).
+    ///
+    /// Errors are handled by returning a [`Result`](errors/type.Result.html) which contains
+    /// [`EmceeError::InvalidInputs`](errors/enum.EmceeError.html) if `thin` is zero.
+    pub fn flatchain_thinned(&self, discard: usize, thin: usize) -> Result<Vec<Guess>> {
+        Ok(self.get_chain(true, thin, discard)?.into_flat())
+    }
+
+    /// Return at most `max_samples` samples spread uniformly over the chain, for quick-look plots
+    ///
+    /// Whole iterations are kept at a fixed stride, counted back from the last iteration so the
+    /// latest state is always included, together with every walker; if one iteration has more
+    /// than `max_samples` walkers, the walkers are strided as well. The cost depends only on the
+    /// size of the thumbnail, so e.g. 10 000 samples can be redrawn instantly in a dashboard
+    /// however long the run gets. Samples are in the same order as
+    /// [`flatchain`](#method.flatchain).
+    pub fn thumbnail(&self, max_samples: usize) -> Vec<Guess> {
+        let (iterations, walkers) =
+            thumbnail_indices(self.niterations, self.nwalkers, max_samples);
+        let mut out = Vec::with_capacity(iterations.len() * walkers.len());
+        for &iter in &iterations {
+            for &walker in &walkers {
                 out.push(Guess {
-                    values: buffer.clone(),
+                    values: (0..self.nparams)
+                        .map(|param| self.get(param, walker, iter))
+                        .collect(),
                 });
             }
         }
         out
     }
 
+    /// Draw `n` equally weighted posterior samples, with replacement
+    ///
+    /// Every stored sample is equally likely, so the result is a sample set of a fixed size for
+    /// propagating the posterior uncertainty through a downstream Monte Carlo calculation,
+    /// whatever the length of the run. Discard the burn-in first, e.g. with
+    /// [`split_at`](#method.split_at).
+    ///
+    /// Errors are handled by returning a [`Result`](errors/type.Result.html) which contains
+    /// [`EmceeError::InvalidInputs`](errors/enum.EmceeError.html) if the chain is empty.
+    pub fn resample<R: Rng>(&self, n: usize, rng: &mut R) -> Result<Vec<Guess>> {
+        let nsamples = self.niterations * self.nwalkers;
+        if nsamples == 0 {
+            return Err(EmceeError::InvalidInputs("the chain is empty".into()));
+        }
+        Ok((0..n)
+            .map(|_| self.sample(rng.gen_range(0, nsamples)))
+            .collect())
+    }
+
+    /// Draw `n` equally weighted samples from the samples of the chain weighted by
+    /// `log_weights`, with replacement
+    ///
+    /// There is one log weight per sample, in the order of [`flatchain`](#method.flatchain), and
+    /// each sample is drawn with a probability proportional to its weight. This turns weighted
+    /// samples into an ordinary sample set, e.g. after importance reweighting the chain to a
+    /// different prior or dataset. Samples with a weight of `-inf` are never drawn.
+    ///
+    /// Errors are handled by returning a [`Result`](errors/type.Result.html) which contains
+    /// [`EmceeError::InvalidInputs`](errors/enum.EmceeError.html) if the number of weights does
+    /// not match the number of samples, any weight is NaN or `+inf`, or every weight is zero.
+    pub fn resample_weighted<R: Rng>(
+        &self,
+        n: usize,
+        log_weights: &[f64],
+        rng: &mut R,
+    ) -> Result<Vec<Guess>> {
+        let nsamples = self.niterations * self.nwalkers;
+        if log_weights.len() != nsamples {
+            return Err(EmceeError::InvalidInputs(format!(
+                "expected {} weights, one per sample, got {}",
+                nsamples,
+                log_weights.len()
+            )));
+        }
+        if log_weights.iter().any(|w| w.is_nan() || *w == f64::INFINITY) {
+            return Err(EmceeError::InvalidInputs(
+                "the weights must not be NaN or infinite".into(),
+            ));
+        }
+        if math::logsumexp(log_weights) == -f64::INFINITY {
+            return Err(EmceeError::InvalidInputs("every weight is zero".into()));
+        }
+
+        let mut total = 0f64;
+        let cumulative: Vec<f64> = math::softmax(log_weights)
+            .iter()
+            .map(|w| {
+                total += w;
+                total
+            })
+            .collect();
+        // Rounding can put a draw past the last cumulative weight, which belongs to the last
+        // sample with a non-zero weight
+        let last = log_weights
+            .iter()
+            .rposition(|w| *w > -f64::INFINITY)
+            .unwrap_or(nsamples - 1);
+        Ok((0..n)
+            .map(|_| {
+                let u = rng.gen::<f64>() * total;
+                let idx = cumulative.partition_point(|&c| c <= u);
+                self.sample(idx.min(last))
+            })
+            .collect())
+    }
+
+    /// Restrict the chain to the given walkers, without copying the samples
+    ///
+    /// Walker `i` of the view is walker `walkers[i]` of the chain, e.g. to analyse only the
+    /// walkers which are not stuck in a low probability region.
+    ///
+    /// # Panics
+    ///
+    /// Panics if any index is not less than `nwalkers`.
+    pub fn select_walkers(&self, walkers: &[usize]) -> ChainView<'_> {
+        for &walker in walkers {
+            assert!(
+                walker < self.nwalkers,
+                "walker {} is out of range for a chain of {} walkers",
+                walker,
+                self.nwalkers
+            );
+        }
+        ChainView {
+            chain: self,
+            walkers: walkers.to_vec(),
+        }
+    }
+
+    /// Copy out the parameters at the indices `params`, in that order
+    ///
+    /// Parameter `i` of the result is parameter `params[i]` of the chain, e.g. to analyse one
+    /// [block](struct.ParamSpec.html) of a hierarchical model.
+    ///
+    /// # Panics
+    ///
+    /// Panics if any index is not less than `nparams`.
+    pub fn select_params(&self, params: &[usize]) -> Chain {
+        let mut out = Chain::new(params.len(), self.nwalkers, self.niterations);
+        if let Some(ref names) = self.names {
+            out.names = Some(params.iter().map(|&param| names[param].clone()).collect());
+        }
+        for iteration in 0..self.niterations {
+            for walker in 0..self.nwalkers {
+                for (i, &param) in params.iter().enumerate() {
+                    out.set(i, walker, iteration, self.get(param, walker, iteration));
+                }
+            }
+        }
+        out
+    }
+
+    /// The sample at `index` in the order of [`flatchain`](#method.flatchain)
+    fn sample(&self, index: usize) -> Guess {
+        let (iter, walker) = (index / self.nwalkers, index % self.nwalkers);
+        Guess {
+            values: (0..self.nparams)
+                .map(|param| self.get(param, walker, iter))
+                .collect(),
+        }
+    }
+}
+
+impl<F: Float> Chain<F> {
     fn index(&self, param_idx: usize, walker_idx: usize, iteration_idx: usize) -> usize {
         (iteration_idx * self.nwalkers * self.nparams) + (walker_idx * self.nparams) + param_idx
     }
+
+    /// The number of values stored for each iteration
+    fn stride(&self) -> usize {
+        self.nparams * self.nwalkers
+    }
+
+    /// The number of values held by each block of storage
+    fn chunk_len(&self) -> usize {
+        (CHUNK_ITERATIONS * self.stride()).max(1)
+    }
+
+    /// The block of storage and the offset within it of the value at `index`
+    fn locate(&self, index: usize) -> (usize, usize) {
+        (index / self.chunk_len(), index % self.chunk_len())
+    }
+
+    /// The number of values currently stored
+    #[cfg(test)]
+    fn nvalues(&self) -> usize {
+        self.chunks.iter().map(|chunk| chunk.len()).sum()
+    }
+}
+
+/// The posterior of linear combinations of the parameters
+///
+/// Returned by [`Chain::project`](struct.Chain.html#method.project).
+#[derive(Debug, Clone)]
+pub struct Projection {
+    /// The samples of the combinations, one parameter per combination
+    pub chain: Chain,
+
+    /// The posterior mean of each combination
+    pub mean: Vec<f64>,
+
+    /// The posterior covariance of the combinations
+    pub covariance: Vec<Vec<f64>>,
+}
+
+/// The principal components of the posterior
+///
+/// Returned by [`Chain::principal_components`](struct.Chain.html#method.principal_components).
+#[derive(Debug, Clone)]
+pub struct PrincipalComponents {
+    /// The posterior mean of each parameter
+    pub mean: Vec<f64>,
+
+    /// The posterior variance along each component, in decreasing order
+    pub eigenvalues: Vec<f64>,
+
+    /// The unit vector of each component in parameter space, one row per eigenvalue
+    pub eigenvectors: Vec<Vec<f64>>,
+}
+
+impl PrincipalComponents {
+    /// The fraction of the total posterior variance along each component
+    pub fn explained_variance_ratio(&self) -> Vec<f64> {
+        let total: f64 = self.eigenvalues.iter().sum();
+        self.eigenvalues.iter().map(|value| value / total).collect()
+    }
 }
 
+/// Samples returned by [`Chain::get_chain`](struct.Chain.html#method.get_chain)
+#[derive(Debug, Clone, PartialEq)]
+pub enum ChainSamples {
+    /// The samples of each kept iteration, indexed as `[iteration][walker]`
+    Shaped(Vec<Vec<Guess>>),
+
+    /// All samples in iteration order, then walker order
+    Flat(Vec<Guess>),
+}
+
+impl ChainSamples {
+    /// The total number of samples
+    pub fn len(&self) -> usize {
+        match *self {
+            ChainSamples::Shaped(ref iterations) => iterations.iter().map(Vec::len).sum(),
+            ChainSamples::Flat(ref samples) => samples.len(),
+        }
+    }
+
+    /// Whether there are no samples
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Flatten the samples into iteration order, then walker order
+    pub fn into_flat(self) -> Vec<Guess> {
+        match self {
+            ChainSamples::Shaped(iterations) => iterations.into_iter().flatten().collect(),
+            ChainSamples::Flat(samples) => samples,
+        }
+    }
+}
+
+/// A subset of the walkers of a [`Chain`](struct.Chain.html)
+///
+/// Created by [`Chain::select_walkers`](struct.Chain.html#method.select_walkers). Walkers are
+/// numbered in the order they were selected.
+#[derive(Debug, Clone)]
+pub struct ChainView<'a> {
+    chain: &'a Chain,
+    walkers: Vec<usize>,
+}
+
+impl<'a> ChainView<'a> {
+    /// The number of parameters per walker
+    pub fn nparams(&self) -> usize {
+        self.chain.nparams
+    }
+
+    /// The number of selected walkers
+    pub fn nwalkers(&self) -> usize {
+        self.walkers.len()
+    }
+
+    /// The number of stored iterations
+    pub fn niterations(&self) -> usize {
+        self.chain.niterations
+    }
+
+    /// The indices in the underlying chain of the selected walkers
+    pub fn walkers(&self) -> &[usize] {
+        &self.walkers
+    }
+
+    /// Get a single parameter value of selected walker `walker_idx`
+    pub fn get(&self, param_idx: usize, walker_idx: usize, iteration_idx: usize) -> f64 {
+        self.chain.get(param_idx, self.walkers[walker_idx], iteration_idx)
+    }
+
+    /// Estimate the integrated autocorrelation time of each parameter over the selected walkers
+    pub fn integrated_time(&self, c: f64) -> Vec<f64> {
+        let nthreads = parallel::nthreads(self.nparams() * self.nwalkers() * self.niterations());
+        (0..self.nparams())
+            .map(|param| {
+                let walkers: Vec<Vec<f64>> = parallel::map(self.nwalkers(), nthreads, |walker| {
+                    (0..self.niterations())
+                        .map(|iter| self.get(param, walker, iter))
+                        .collect()
+                });
+                autocorr::integrated_time_parallel(&walkers, c, nthreads)
+            })
+            .collect()
+    }
+
+    /// Return the samples of the selected walkers in iteration order, then walker order
+    pub fn flatchain(&self) -> Vec<Guess> {
+        let mut out = Vec::with_capacity(self.niterations() * self.nwalkers());
+        for iter in 0..self.niterations() {
+            for walker in 0..self.nwalkers() {
+                out.push(Guess {
+                    values: (0..self.nparams())
+                        .map(|param| self.get(param, walker, iter))
+                        .collect(),
+                });
+            }
+        }
+        out
+    }
+
+    /// Copy the selected walkers into a new chain
+    pub fn to_chain(&self) -> Chain {
+        let mut chain = Chain::new(self.nparams(), self.nwalkers(), self.niterations());
+        chain.names = self.chain.names.clone();
+        for iter in 0..self.niterations() {
+            for walker in 0..self.nwalkers() {
+                for param in 0..self.nparams() {
+                    chain.set(param, walker, iter, self.get(param, walker, iter));
+                }
+            }
+        }
+        chain
+    }
+}
+
+/// The chains of a tempered run, one per inverse temperature
+///
+/// Returned in
+/// [`SteppingStoneEvidence::chains`](struct.SteppingStoneEvidence.html#structfield.chains).
+/// Each temperature has its own [`Chain`](struct.Chain.html) and the log likelihood of every
+/// sample, in the order of [`Chain::flatchain`](struct.Chain.html#method.flatchain). The
+/// temperatures are indexed in the order they were sampled.
+#[derive(Debug, Clone, Default)]
+pub struct TemperedChains {
+    betas: Vec<f64>,
+    chains: Vec<Chain>,
+    lnlike: Vec<Vec<f64>>,
+}
+
+impl TemperedChains {
+    /// The number of temperatures
+    pub fn ntemps(&self) -> usize {
+        self.betas.len()
+    }
+
+    /// The inverse temperature of each chain
+    pub fn betas(&self) -> &[f64] {
+        &self.betas
+    }
+
+    /// The chain at temperature index `temp`
+    ///
+    /// # Panics
+    ///
+    /// Panics if `temp` is not less than [`ntemps`](#method.ntemps).
+    pub fn chain(&self, temp: usize) -> &Chain {
+        &self.chains[temp]
+    }
+
+    /// The log likelihood of every sample of the chain at temperature index `temp`
+    ///
+    /// # Panics
+    ///
+    /// Panics if `temp` is not less than [`ntemps`](#method.ntemps).
+    pub fn lnlike(&self, temp: usize) -> &[f64] {
+        &self.lnlike[temp]
+    }
+
+    /// The chain at `beta = 1`, which samples the posterior, if it was stored
+    pub fn cold_chain(&self) -> Option<&Chain> {
+        self.cold_index().map(|temp| self.chain(temp))
+    }
+
+    /// The log likelihood of every sample of the [`cold_chain`](#method.cold_chain)
+    pub fn cold_lnlike(&self) -> Option<&[f64]> {
+        self.cold_index().map(|temp| self.lnlike(temp))
+    }
+
+    pub(crate) fn push(&mut self, beta: f64, chain: Chain, lnlike: Vec<f64>) {
+        assert_eq!(lnlike.len(), chain.nwalkers * chain.niterations);
+        self.betas.push(beta);
+        self.chains.push(chain);
+        self.lnlike.push(lnlike);
+    }
+
+    fn cold_index(&self) -> Option<usize> {
+        self.betas.iter().rposition(|&beta| beta == 1.0)
+    }
+}
+
+/// The iterations and walkers kept in a thumbnail of at most `max_samples` samples
+pub(crate) fn thumbnail_indices(
+    niterations: usize,
+    nwalkers: usize,
+    max_samples: usize,
+) -> (Vec<usize>, Vec<usize>) {
+    if niterations == 0 || nwalkers == 0 || max_samples == 0 {
+        return (Vec::new(), Vec::new());
+    }
+
+    let walkers: Vec<usize> = (0..nwalkers)
+        .step_by(nwalkers.div_ceil(max_samples))
+        .collect();
+    let per_iteration = max_samples / walkers.len();
+    let mut iterations: Vec<usize> = (0..niterations)
+        .rev()
+        .step_by(niterations.div_ceil(per_iteration))
+        .collect();
+    iterations.reverse();
+    (iterations, walkers)
+}
+
+/// The iterations kept after discarding the first `discard` of `niterations` and thinning by
+/// `thin`, as in [`Chain::get_chain`](struct.Chain.html#method.get_chain)
+pub(crate) fn kept_iterations(
+    niterations: usize,
+    discard: usize,
+    thin: usize,
+) -> Result<StepBy<Range<usize>>> {
+    if thin == 0 {
+        return Err(EmceeError::InvalidInputs("thin must be positive".into()));
+    }
+    Ok((discard + thin - 1..niterations).step_by(thin))
+}
+
+/// Draw `nwalkers` starting positions from a previous run
+///
+/// The positions are drawn without replacement from the samples of `chain` after the first
+/// `discard` iterations, so a follow-up run (e.g. with slightly different data) starts from an
+/// approximate posterior sample and needs much less burn-in than starting from a small ball.
+///
+/// Errors are handled by returning a [`Result`](errors/type.Result.html) which contains
+/// [`EmceeError::InvalidInputs`](errors/enum.EmceeError.html) if fewer than `nwalkers` samples
+/// remain after discarding.
+pub fn initial_from_chain<R: Rng>(
+    chain: &Chain,
+    nwalkers: usize,
+    discard: usize,
+    rng: &mut R,
+) -> Result<Vec<Guess>> {
+    let niterations = chain.niterations.saturating_sub(discard);
+    let nsamples = niterations * chain.nwalkers;
+    if nsamples < nwalkers {
+        return Err(EmceeError::InvalidInputs(format!(
+            "only {} samples remain after discarding {} iterations, {} are required",
+            nsamples, discard, nwalkers
+        )));
+    }
+
+    // Partial Fisher-Yates shuffle of the sample indices
+    let mut indices: Vec<usize> = (0..nsamples).collect();
+    Ok((0..nwalkers)
+        .map(|i| {
+            let j = rng.gen_range(i, nsamples);
+            indices.swap(i, j);
+            chain.sample(discard * chain.nwalkers + indices[i])
+        })
+        .collect())
+}
+
+/// The log probability of every walker at every stored iteration
+///
+/// Returned by [`EnsembleSampler::probstore`](struct.EnsembleSampler.html#method.probstore).
 #[derive(Debug, Default)]
-pub struct ProbStore {
-    data: Vec<f64>,
+pub struct ProbStore<F: Float = f64> {
+    data: Vec<F>,
     nwalkers: usize,
     niterations: usize,
 }
 
 impl ProbStore {
+    /// Create a store of the given size, initialised to zero
     pub fn new(nwalkers: usize, niterations: usize) -> ProbStore {
+        ProbStore::zeros(nwalkers, niterations)
+    }
+}
+
+impl<F: Float> ProbStore<F> {
+    /// Create a store of the given size holding values of type `F`, initialised to zero, as
+    /// for [`Chain::zeros`](struct.Chain.html#method.zeros)
+    pub fn zeros(nwalkers: usize, niterations: usize) -> ProbStore<F> {
         ProbStore {
             nwalkers: nwalkers,
             niterations: niterations,
-            data: vec![0f64; nwalkers * niterations],
+            data: vec![F::default(); nwalkers * niterations],
         }
     }
 
-    pub fn set(&mut self, walker_idx: usize, iteration_idx: usize, value: f64) {
+    /// Set the log probability of a walker at one iteration
+    pub fn set(&mut self, walker_idx: usize, iteration_idx: usize, value: F) {
         assert!(walker_idx < self.nwalkers);
         assert!(
             iteration_idx < self.niterations,
@@ -96,7 +1046,8 @@ impl ProbStore {
         self.data[idx] = value;
     }
 
-    pub fn get(&self, walker_idx: usize, iteration_idx: usize) -> f64{
+    /// Get the log probability of a walker at one iteration
+    pub fn get(&self, walker_idx: usize, iteration_idx: usize) -> F {
         assert!(walker_idx < self.nwalkers);
         assert!(
             iteration_idx < self.niterations,
@@ -110,17 +1061,115 @@ impl ProbStore {
         self.data[idx]
     }
 
-    pub fn set_probs(&mut self, iteration_idx: usize, newdata: &[f64]) {
+    /// Set the log probability of every walker at one iteration
+    pub fn set_probs(&mut self, iteration_idx: usize, newdata: &[F]) {
         assert_eq!(newdata.len(), self.nwalkers);
         for (idx, value) in newdata.iter().enumerate() {
             self.set(idx, iteration_idx, *value);
         }
     }
 
+    /// The number of stored iterations
+    pub fn niterations(&self) -> usize {
+        self.niterations
+    }
+
+    /// Grow the store by `additional` iterations, initialised to zero
+    pub fn extend(&mut self, additional: usize) {
+        self.niterations += additional;
+        self.data.resize(self.nwalkers * self.niterations, F::default());
+    }
+
+    /// Keep only the first `niterations` iterations
+    pub fn truncate(&mut self, niterations: usize) {
+        if niterations < self.niterations {
+            self.niterations = niterations;
+            self.data.truncate(self.nwalkers * niterations);
+        }
+    }
+
+    /// Move every other one of the first `nfilled` iterations to the front of the store, as for
+    /// the chain
+    pub fn keep_even_iterations(&mut self, nfilled: usize) -> usize {
+        let nkept = nfilled.div_ceil(2);
+        for k in 1..nkept {
+            let start = 2 * k * self.nwalkers;
+            self.data.copy_within(start..start + self.nwalkers, k * self.nwalkers);
+        }
+        nkept
+    }
+
+    /// Copy the store into another floating point type, as for
+    /// [`Chain::to_precision`](struct.Chain.html#method.to_precision)
+    pub fn to_precision<G: Float>(&self) -> ProbStore<G> {
+        ProbStore {
+            data: self.data.iter().map(|value| G::from_f64(value.to_f64())).collect(),
+            nwalkers: self.nwalkers,
+            niterations: self.niterations,
+        }
+    }
+
     fn index(&self, walker_idx: usize, iteration_idx: usize) -> usize {
         (iteration_idx * self.nwalkers) + walker_idx
     }
+}
+
+impl ProbStore {
+    /// The largest log probability of any walker over the iterations in `range`
+    ///
+    /// NaN values are ignored, and `None` is returned if there are no other values in the
+    /// range. Panics if the range extends past the stored iterations.
+    pub fn max_over(&self, range: Range<usize>) -> Option<f64> {
+        self.argmax_over(range).map(|(walker, iter)| self.get(walker, iter))
+    }
+
+    /// The mean log probability of the walkers over the iterations in `range`, or `None` if
+    /// the range is empty
+    ///
+    /// Panics if the range extends past the stored iterations.
+    pub fn mean_over(&self, range: Range<usize>) -> Option<f64> {
+        let values = self.window(range);
+        if values.is_empty() {
+            None
+        } else {
+            Some(values.iter().sum::<f64>() / values.len() as f64)
+        }
+    }
+
+    /// The walker and iteration of the largest log probability over the iterations in
+    /// `range`, as from [`max_over`](#method.max_over)
+    ///
+    /// The earliest iteration, then the lowest walker, is returned if there is a tie.
+    pub fn argmax_over(&self, range: Range<usize>) -> Option<(usize, usize)> {
+        let start = range.start;
+        let values = self.window(range);
+        let mut best: Option<usize> = None;
+        for (idx, &value) in values.iter().enumerate() {
+            if !value.is_nan() && best.is_none_or(|best| value > values[best]) {
+                best = Some(idx);
+            }
+        }
+        best.map(|idx| (idx % self.nwalkers, start + idx / self.nwalkers))
+    }
 
+    /// The walker and iteration of the largest log probability in the store
+    pub fn argmax(&self) -> Option<(usize, usize)> {
+        self.argmax_over(0..self.niterations)
+    }
+
+    /// The values of the iterations in `range`, iteration by iteration
+    fn window(&self, range: Range<usize>) -> &[f64] {
+        assert!(
+            range.start <= range.end && range.end <= self.niterations,
+            "iteration range {:?}, number of iterations stored: {}",
+            range,
+            self.niterations
+        );
+        &self.data[range.start * self.nwalkers..range.end * self.nwalkers]
+    }
+
+    /// The log probability of every sample, in the order of
+    /// [`Chain::flatchain`](struct.Chain.html#method.flatchain)
     pub fn flatprob(&self) -> Vec<f64> {
         let mut out = Vec::with_capacity(self.niterations * self.nwalkers);
         for iter in 0..self.niterations {
@@ -142,7 +1191,7 @@ mod test {
         let nwalkers = 10;
         let niterations = 1000;
         let mut chain = Chain::new(nparams, nwalkers, niterations);
-        assert_eq!(chain.data.len(), nparams * nwalkers * niterations);
+        assert_eq!(chain.nvalues(), nparams * nwalkers * niterations);
 
         assert_eq!(chain.index(0, 0, 0), 0);
         assert_eq!(chain.index(1, 0, 0), 1);
@@ -153,7 +1202,7 @@ mod test {
         assert_eq!(chain.index(0, 0, 1), 20);
 
         chain.set(0, 1, 0, 2.0f64);
-        assert_eq!(chain.data[2], 2.0f64);
+        assert_eq!(chain.chunks[0][2], 2.0f64);
         assert_eq!(chain.get(0, 1, 0), 2.0f64);
 
         let newdata = vec![5.0f64, 100.0f64];
@@ -163,6 +1212,268 @@ mod test {
         assert_eq!(chain.get(1, 1, 250), 100.0f64);
     }
 
+    #[test]
+    fn test_chain_growth() {
+        let (nparams, nwalkers) = (2, 3);
+        let niterations = 2 * CHUNK_ITERATIONS + 10;
+        let mut chain = Chain::new(nparams, nwalkers, 0);
+        chain.reserve_iterations(CHUNK_ITERATIONS + 1);
+        assert_eq!(chain.niterations, 0);
+        assert_eq!(chain.nvalues(), 0);
+        assert_eq!(chain.chunks.len(), 2);
+        assert!(chain.chunks[0].capacity() >= CHUNK_ITERATIONS * nparams * nwalkers);
+
+        for iter in 0..niterations {
+            chain.extend(1);
+            for walker in 0..nwalkers {
+                chain.set_params(walker, iter, &[iter as f64, walker as f64]);
+            }
+        }
+        assert_eq!(chain.chunks.len(), 3);
+        assert_eq!(chain.nvalues(), niterations * nparams * nwalkers);
+
+        let (head, tail) = chain.split_at(CHUNK_ITERATIONS + 5);
+        assert_eq!(head.get(0, 2, CHUNK_ITERATIONS + 4), (CHUNK_ITERATIONS + 4) as f64);
+        assert_eq!(tail.get(0, 1, 0), (CHUNK_ITERATIONS + 5) as f64);
+        assert_eq!(tail.niterations, CHUNK_ITERATIONS + 5);
+
+        // Halving moves iterations between blocks
+        let nkept = chain.keep_even_iterations(niterations);
+        assert_eq!(nkept, niterations / 2);
+        for iter in 0..nkept {
+            assert_eq!(chain.get(0, 1, iter), 2.0 * iter as f64);
+            assert_eq!(chain.get(1, 1, iter), 1.0);
+        }
+
+        chain.truncate(nkept);
+        assert_eq!(chain.chunks.len(), 2);
+        assert_eq!(chain.nvalues(), nkept * nparams * nwalkers);
+    }
+
+    #[test]
+    fn test_resample() {
+        use rand::{SeedableRng, StdRng};
+
+        let mut chain = Chain::new(1, 2, 3);
+        for iter in 0..3 {
+            for walker in 0..2 {
+                chain.set(0, walker, iter, (2 * iter + walker) as f64);
+            }
+        }
+        let mut rng = StdRng::from_seed(&[1, 2, 3, 4]);
+
+        let draws = chain.resample(6000, &mut rng).unwrap();
+        assert_eq!(draws.len(), 6000);
+        let mut counts = vec![0; 6];
+        for guess in &draws {
+            counts[guess[0] as usize] += 1;
+        }
+        assert!(counts.iter().all(|&c| c > 850 && c < 1150), "{:?}", counts);
+
+        // Only samples 1 and 4 have weight, in the ratio 1:3
+        let mut log_weights = vec![-f64::INFINITY; 6];
+        log_weights[1] = 0.0;
+        log_weights[4] = 3f64.ln();
+        let draws = chain.resample_weighted(4000, &log_weights, &mut rng).unwrap();
+        let nfour = draws.iter().filter(|guess| guess[0] == 4.0).count();
+        assert!(draws.iter().all(|guess| guess[0] == 1.0 || guess[0] == 4.0));
+        assert!(nfour > 2850 && nfour < 3150, "{}", nfour);
+
+        assert!(Chain::new(1, 2, 0).resample(1, &mut rng).is_err());
+        assert!(chain.resample_weighted(1, &[0.0; 5], &mut rng).is_err());
+        assert!(chain.resample_weighted(1, &[-f64::INFINITY; 6], &mut rng).is_err());
+        assert!(chain.resample_weighted(1, &[f64::NAN; 6], &mut rng).is_err());
+    }
+
+    #[test]
+    fn test_map_derived() {
+        let mut chain = Chain::new(2, 3, 4);
+        for iter in 0..4 {
+            for walker in 0..3 {
+                chain.set_params(walker, iter, &[iter as f64 + 1.0, walker as f64 + 1.0]);
+            }
+        }
+        assert!(chain.param_names().is_none());
+
+        let derived = chain
+            .map_derived(&["product", "sum", "ratio"], |guess| {
+                vec![guess[0] * guess[1], guess[0] + guess[1], guess[0] / guess[1]]
+            })
+            .unwrap();
+        assert_eq!(derived.nparams, 3);
+        assert_eq!(derived.nwalkers, 3);
+        assert_eq!(derived.niterations, 4);
+        assert_eq!(derived.param_names().unwrap(), &["product", "sum", "ratio"]);
+        assert_eq!(derived.get(0, 2, 3), 12.0);
+        assert_eq!(derived.get(1, 1, 0), 3.0);
+        assert_eq!(derived.get(2, 1, 2), 1.5);
+
+        // The derived chain supports the usual analysis
+        assert_eq!(derived.flatchain().len(), 12);
+        let (_, tail) = derived.split_at(2);
+        assert_eq!(tail.param_names().unwrap()[1], "sum");
+        let ratio = derived.select_params(&[2]);
+        assert_eq!(ratio.param_names().unwrap(), &["ratio"]);
+        assert_eq!(ratio.get(0, 1, 2), 1.5);
+
+        assert!(chain.map_derived(&["a", "b"], |guess| vec![guess[0]]).is_err());
+    }
+
+    #[test]
+    fn test_param_names() {
+        let mut chain = Chain::new(2, 3, 4);
+        for iter in 0..4 {
+            for walker in 0..3 {
+                chain.set_params(walker, iter, &[iter as f64, walker as f64]);
+            }
+        }
+        assert!(chain.set_param_names(&["mass"]).is_err());
+        assert_eq!(chain.param_index("mass"), None);
+
+        chain.set_param_names(&["mass", "radius"]).unwrap();
+        assert_eq!(chain.param_names().unwrap(), &["mass", "radius"]);
+        assert_eq!(chain.param_index("radius"), Some(1));
+
+        let names = chain.param_names().unwrap();
+        let flat = chain.flatchain();
+        assert_eq!(flat[7].get(names, "mass"), Some(2.0));
+        assert_eq!(flat[7].get(names, "radius"), Some(1.0));
+        let view = chain.select_walkers(&[2]);
+        assert_eq!(view.flatchain()[3].get(names, "radius"), Some(2.0));
+        assert_eq!(view.to_chain().param_names(), chain.param_names());
+
+        use rand::{SeedableRng, StdRng};
+        let mut rng = StdRng::from_seed(&[1, 2, 3, 4]);
+        let p0 = initial_from_chain(&chain, 3, 1, &mut rng).unwrap();
+        assert!(p0.iter().all(|guess| guess.get(names, "mass").unwrap() >= 1.0));
+        assert_eq!(chain.select_params(&[1]).param_names().unwrap(), &["radius"]);
+    }
+
+    #[test]
+    fn test_project() {
+        let mut chain = Chain::new(2, 2, 50);
+        for iter in 0..50 {
+            for walker in 0..2 {
+                let t = (iter * 2 + walker) as f64;
+                chain.set_params(walker, iter, &[t.sin(), 2.0 * t.cos() + 0.5 * t.sin()]);
+            }
+        }
+        let samples = chain.flatchain();
+        let (mean, cov) = linalg::mean_covariance(&samples);
+
+        let combinations = vec![vec![1.0, 1.0], vec![1.0, -2.0], vec![0.0, 3.0]];
+        let projection = chain.project(&combinations).unwrap();
+        assert_eq!(projection.chain.nparams, 3);
+        assert_eq!(projection.chain.niterations, 50);
+        assert_eq!(projection.chain.param_names().unwrap()[1], "combination[1]");
+        let expected = chain.get(0, 1, 7) - 2.0 * chain.get(1, 1, 7);
+        assert_approx_eq!(projection.chain.get(1, 1, 7), expected);
+
+        // The moments are V mu and V Sigma V^T
+        for (i, v) in combinations.iter().enumerate() {
+            assert_approx_eq!(projection.mean[i], v[0] * mean[0] + v[1] * mean[1]);
+            for (j, w) in combinations.iter().enumerate() {
+                let expected = (0..2)
+                    .map(|k| (0..2).map(|l| v[k] * cov[k][l] * w[l]).sum::<f64>())
+                    .sum::<f64>();
+                assert_approx_eq!(projection.covariance[i][j], expected);
+            }
+        }
+
+        assert!(chain.project(&[]).is_err());
+        assert!(chain.project(&[vec![1.0]]).is_err());
+        assert!(Chain::new(2, 2, 0).project(&[vec![1.0, 0.0]]).is_err());
+    }
+
+    #[test]
+    fn test_principal_components() {
+        // Walkers spread along (1, 1) with a little scatter across it
+        let mut chain = Chain::new(2, 4, 100);
+        for iter in 0..100 {
+            for walker in 0..4 {
+                let t = (iter * 4 + walker) as f64;
+                let (along, across) = (3.0 * t.sin(), 0.1 * (1.7 * t).cos());
+                chain.set_params(walker, iter, &[1.0 + along + across, -1.0 + along - across]);
+            }
+        }
+
+        let pca = chain.principal_components().unwrap();
+        let (mean, cov) = linalg::mean_covariance(&chain.flatchain());
+        assert_eq!(pca.mean, mean);
+        assert!(pca.eigenvalues[0] > 100.0 * pca.eigenvalues[1]);
+        assert_approx_eq!(pca.eigenvalues[0] + pca.eigenvalues[1], cov[0][0] + cov[1][1]);
+        let half = 0.5f64.sqrt();
+        assert_approx_eq!(pca.eigenvectors[0][0], half, 1e-3);
+        assert_approx_eq!(pca.eigenvectors[0][1], half, 1e-3);
+        assert_approx_eq!(pca.eigenvectors[1][0].abs(), half, 1e-3);
+        let ratio = pca.explained_variance_ratio();
+        assert_approx_eq!(ratio[0] + ratio[1], 1.0);
+
+        // The components are uncorrelated in the rotated basis
+        let projection = chain.project(&pca.eigenvectors).unwrap();
+        assert_approx_eq!(projection.covariance[0][0], pca.eigenvalues[0]);
+        assert_approx_eq!(projection.covariance[0][1], 0.0);
+
+        assert!(Chain::new(2, 1, 1).principal_components().is_err());
+    }
+
+    #[test]
+    fn test_parallel_statistics() {
+        // Large enough to be split between threads
+        let (nparams, nwalkers, niterations) = (2, 64, 2100);
+        let mut chain = Chain::new(nparams, nwalkers, niterations);
+        let mut x = 0.3f64;
+        for iter in 0..niterations {
+            for walker in 0..nwalkers {
+                x = (3.9 * x * (1.0 - x)).max(1e-3);
+                chain.set_params(walker, iter, &[x, (iter + walker) as f64 * 0.01 + x]);
+            }
+        }
+
+        // The results are those of the serial computations, bit for bit
+        let flat = chain.flatchain();
+        assert_eq!(flat.len(), nwalkers * niterations);
+        assert_eq!(flat[5 * nwalkers + 7][1], chain.get(1, 7, 5));
+        assert_eq!(flat.last().unwrap()[0], chain.get(0, nwalkers - 1, niterations - 1));
+
+        let tau = chain.integrated_time(5.0);
+        for (param, &tau) in tau.iter().enumerate() {
+            let walkers: Vec<Vec<f64>> = (0..nwalkers)
+                .map(|walker| (0..niterations).map(|iter| chain.get(param, walker, iter)).collect())
+                .collect();
+            assert_eq!(tau, autocorr::integrated_time(&walkers, 5.0));
+            assert_eq!(
+                autocorr::integrated_time_parallel(&walkers, 5.0, 3),
+                autocorr::integrated_time(&walkers, 5.0)
+            );
+        }
+        let view = chain.select_walkers(&(0..nwalkers).collect::<Vec<_>>());
+        assert_eq!(view.integrated_time(5.0), chain.integrated_time(5.0));
+    }
+
+    #[test]
+    fn test_positions_at() {
+        let mut chain = Chain::new(3, 4, 300);
+        for iter in 0..300 {
+            for walker in 0..4 {
+                let base = (iter * 10 + walker) as f64;
+                chain.set_params(walker, iter, &[base, base + 0.1, base + 0.2]);
+            }
+        }
+
+        // Past the end of the first block of storage
+        let positions = chain.positions_at(271);
+        assert_eq!(positions.len(), 4);
+        assert_eq!(positions[2], vec![2712.0, 2712.1, 2712.2]);
+        assert_eq!(positions[0], chain.flatchain()[271 * 4].values);
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_positions_past_end() {
+        Chain::new(2, 4, 10).positions_at(10);
+    }
+
     #[test]
     fn test_probstore() {
         let nwalkers = 4;
@@ -186,6 +1497,293 @@ mod test {
         assert_eq!(store_get(&store, 3, 250), 20.0f64);
     }
 
+    #[test]
+    fn test_extend() {
+        let mut chain = Chain::new(2, 3, 1);
+        chain.set_params(2, 0, &[1.0, 2.0]);
+        chain.extend(4);
+        assert_eq!(chain.niterations, 5);
+        assert_eq!(chain.get(1, 2, 0), 2.0);
+        chain.set_params(2, 4, &[3.0, 4.0]);
+        assert_eq!(chain.get(0, 2, 4), 3.0);
+
+        let mut store = ProbStore::new(3, 1);
+        store.set(1, 0, 5.0);
+        store.extend(2);
+        assert_eq!(store.get(1, 0), 5.0);
+        store.set(1, 2, 6.0);
+        assert_eq!(store.flatprob().len(), 9);
+    }
+
+    #[test]
+    fn test_truncate_and_split() {
+        let mut chain = Chain::new(2, 3, 6);
+        for iter in 0..6 {
+            for walker in 0..3 {
+                chain.set_params(walker, iter, &[iter as f64, walker as f64]);
+            }
+        }
+
+        let (head, tail) = chain.split_at(4);
+        assert_eq!(head.niterations, 4);
+        assert_eq!(tail.niterations, 2);
+        assert_eq!(head.get(0, 2, 3), 3.0);
+        assert_eq!(tail.get(0, 1, 0), 4.0);
+        assert_eq!(tail.get(1, 1, 1), 1.0);
+        assert_eq!(head.flatchain().len() + tail.flatchain().len(), 18);
+
+        let (empty, all) = chain.split_at(0);
+        assert_eq!(empty.niterations, 0);
+        assert_eq!(all.niterations, 6);
+
+        chain.truncate(10);
+        assert_eq!(chain.niterations, 6);
+        chain.truncate(2);
+        assert_eq!(chain.niterations, 2);
+        assert_eq!(chain.nvalues(), 12);
+        assert_eq!(chain.get(0, 0, 1), 1.0);
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_split_past_end() {
+        Chain::new(2, 3, 6).split_at(7);
+    }
+
+    #[test]
+    fn test_select_walkers() {
+        let mut chain = Chain::new(2, 4, 3);
+        for iter in 0..3 {
+            for walker in 0..4 {
+                chain.set_params(walker, iter, &[iter as f64, walker as f64]);
+            }
+        }
+
+        let view = chain.select_walkers(&[3, 1]);
+        assert_eq!(view.nwalkers(), 2);
+        assert_eq!(view.niterations(), 3);
+        assert_eq!(view.walkers(), &[3, 1]);
+        assert_eq!(view.get(1, 0, 2), 3.0);
+        assert_eq!(view.get(0, 1, 2), 2.0);
+
+        let flat = view.flatchain();
+        assert_eq!(flat.len(), 6);
+        assert_eq!(flat[1].values, vec![0.0, 1.0]);
+        assert_eq!(view.to_chain().flatchain(), flat);
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_select_missing_walker() {
+        Chain::new(2, 3, 6).select_walkers(&[0, 3]);
+    }
+
+    #[test]
+    fn test_keep_even_iterations() {
+        let mut chain = Chain::new(2, 3, 6);
+        let mut probstore = ProbStore::new(3, 6);
+        for iter in 0..5 {
+            for walker in 0..3 {
+                chain.set_params(walker, iter, &[iter as f64, walker as f64]);
+                probstore.set(walker, iter, iter as f64);
+            }
+        }
+
+        assert_eq!(chain.keep_even_iterations(5), 3);
+        assert_eq!(probstore.keep_even_iterations(5), 3);
+        assert_eq!(chain.niterations, 6);
+        for (k, iter) in [0.0, 2.0, 4.0].iter().enumerate() {
+            assert_eq!(chain.get(0, 2, k), *iter);
+            assert_eq!(chain.get(1, 2, k), 2.0);
+            assert_eq!(probstore.get(1, k), *iter);
+        }
+
+        probstore.truncate(3);
+        assert_eq!(probstore.flatprob().len(), 9);
+    }
+
+    #[test]
+    fn test_get_chain() {
+        let mut chain = Chain::new(2, 3, 10);
+        for iter in 0..10 {
+            for walker in 0..3 {
+                chain.set_params(walker, iter, &[iter as f64, walker as f64]);
+            }
+        }
+
+        assert_eq!(
+            chain.get_chain(true, 1, 0).unwrap(),
+            ChainSamples::Flat(chain.flatchain())
+        );
+
+        // Iterations 5 and 8, as chain[discard + thin - 1::thin] in emcee
+        let shaped = match chain.get_chain(false, 3, 3).unwrap() {
+            ChainSamples::Shaped(shaped) => shaped,
+            other => panic!("expected shaped samples, got {:?}", other),
+        };
+        assert_eq!(shaped.len(), 2);
+        assert_eq!(shaped[0].len(), 3);
+        assert_eq!(shaped[0][2].values, vec![5.0, 2.0]);
+        assert_eq!(shaped[1][0].values, vec![8.0, 0.0]);
+
+        let flat = chain.get_chain(true, 3, 3).unwrap();
+        assert_eq!(flat.len(), 6);
+        assert_eq!(flat, ChainSamples::Flat(ChainSamples::Shaped(shaped).into_flat()));
+
+        assert!(chain.get_chain(false, 1, 10).unwrap().is_empty());
+        assert!(chain.get_chain(true, 1, 20).unwrap().is_empty());
+        assert!(chain.get_chain(true, 0, 0).is_err());
+
+        let thinned = chain.flatchain_thinned(3, 3).unwrap();
+        assert_eq!(thinned, flat.into_flat());
+        assert_eq!(chain.flatchain_thinned(0, 1).unwrap(), chain.flatchain());
+        assert!(chain.flatchain_thinned(0, 0).is_err());
+    }
+
+    #[test]
+    fn test_thumbnail() {
+        let mut chain = Chain::new(2, 4, 1000);
+        for iter in 0..1000 {
+            for walker in 0..4 {
+                chain.set_params(walker, iter, &[iter as f64, walker as f64]);
+            }
+        }
+
+        let thumbnail = chain.thumbnail(100);
+        assert_eq!(thumbnail.len(), 100);
+        assert_eq!(thumbnail[0].values, vec![39.0, 0.0]);
+        assert_eq!(thumbnail[99].values, vec![999.0, 3.0]);
+        assert_eq!(thumbnail[4][0] - thumbnail[0][0], 40.0);
+
+        // Everything fits
+        assert_eq!(chain.thumbnail(10000), chain.flatchain());
+
+        // Fewer samples than walkers
+        let thumbnail = chain.thumbnail(3);
+        assert_eq!(thumbnail.len(), 2);
+        assert_eq!(thumbnail[0].values, vec![999.0, 0.0]);
+        assert_eq!(thumbnail[1].values, vec![999.0, 2.0]);
+
+        assert!(chain.thumbnail(0).is_empty());
+        assert!(Chain::new(2, 4, 0).thumbnail(100).is_empty());
+    }
+
+    #[test]
+    fn test_initial_from_chain() {
+        use rand::{SeedableRng, StdRng};
+
+        let mut chain = Chain::new(2, 4, 10);
+        for iter in 0..10 {
+            for walker in 0..4 {
+                chain.set_params(walker, iter, &[iter as f64, walker as f64]);
+            }
+        }
+
+        let mut rng = StdRng::from_seed(&[1, 2, 3, 4]);
+        let pos = initial_from_chain(&chain, 8, 5, &mut rng).unwrap();
+        assert_eq!(pos.len(), 8);
+        assert!(pos.iter().all(|guess| guess[0] >= 5.0));
+
+        // No sample is drawn twice
+        let mut keys: Vec<(usize, usize)> = pos.iter()
+            .map(|guess| (guess[0] as usize, guess[1] as usize))
+            .collect();
+        keys.sort();
+        keys.dedup();
+        assert_eq!(keys.len(), 8);
+
+        assert_eq!(initial_from_chain(&chain, 20, 5, &mut rng).unwrap().len(), 20);
+        assert!(initial_from_chain(&chain, 21, 5, &mut rng).is_err());
+        assert!(initial_from_chain(&chain, 1, 10, &mut rng).is_err());
+    }
+
+    #[test]
+    fn test_autocorr_time() {
+        use rand::{SeedableRng, StdRng};
+        use rand::distributions::{IndependentSample, Normal};
+
+        // Two AR(1) parameters with autocorrelation times (1 + rho) / (1 - rho) of 1 and 9
+        let (nwalkers, niterations) = (16, 2000);
+        let mut chain = Chain::new(2, nwalkers, niterations);
+        let mut rng = StdRng::from_seed(&[1, 2, 3, 4]);
+        let normal = Normal::new(0.0, 1.0);
+        for walker in 0..nwalkers {
+            let mut value = [0f64; 2];
+            for iter in 0..niterations {
+                value[0] = normal.ind_sample(&mut rng);
+                value[1] = 0.8 * value[1] + normal.ind_sample(&mut rng);
+                chain.set_params(walker, iter, &value);
+            }
+        }
+
+        let tau = chain.autocorr_time(5.0, 50.0).unwrap();
+        assert_eq!(tau, chain.integrated_time(5.0));
+        assert_approx_eq!(tau[0], 1.0, 0.2);
+        assert_approx_eq!(tau[1], 9.0, 1.5);
+
+        // 2000 iterations are not 500 autocorrelation times of the second parameter
+        assert!(chain.autocorr_time(5.0, 500.0).is_err());
+        let (short, _) = chain.split_at(100);
+        assert!(short.autocorr_time(5.0, 50.0).is_err());
+        assert!(Chain::new(2, 4, 0).autocorr_time(5.0, 50.0).is_err());
+        assert!(chain.autocorr_time(0.0, 50.0).is_err());
+        assert!(chain.autocorr_time(5.0, f64::NAN).is_err());
+    }
+
+    #[test]
+    fn test_precision() {
+        let mut chain = Chain::<f32>::zeros(2, 3, 300);
+        chain.set_params(1, 299, &[0.1, -2.5]);
+        chain.set_param_names(&["a", "b"]).unwrap();
+        let (head, tail) = chain.split_at(100);
+        assert_eq!((head.niterations, tail.niterations), (100, 200));
+        assert_eq!(tail.get(0, 1, 199), 0.1f32);
+        assert_eq!(chain.nvalues(), 1800);
+
+        let wide: Chain = chain.to_precision();
+        assert_eq!(wide.get(0, 1, 299), f64::from(0.1f32));
+        assert_eq!(wide.get(1, 1, 299), -2.5);
+        assert_eq!(wide.param_index("b"), Some(1));
+        assert_eq!(wide.to_precision::<f32>().positions_at(299), chain.positions_at(299));
+
+        let mut probstore = ProbStore::<f32>::zeros(3, 2);
+        probstore.set_probs(1, &[-1.0, -0.5, -1e-9]);
+        let wide: ProbStore = probstore.to_precision();
+        assert_eq!(wide.max_over(1..2), Some(-1e-9f32 as f64));
+
+        let guess = Guess::new(&[0.1, 2.0]).to_precision::<f32>();
+        assert_eq!(guess[0], 0.1f32);
+        assert_eq!(guess.to_precision::<f64>()[1], 2.0);
+    }
+
+    #[test]
+    fn test_probstore_ranges() {
+        let mut store = ProbStore::new(3, 4);
+        store.set_probs(0, &[-5.0, -4.0, -6.0]);
+        store.set_probs(1, &[-3.0, f64::NAN, -1.0]);
+        store.set_probs(2, &[-2.0, -1.0, -7.0]);
+        store.set_probs(3, &[f64::NEG_INFINITY, -8.0, -9.0]);
+
+        assert_eq!(store.max_over(0..1), Some(-4.0));
+        assert_eq!(store.argmax_over(0..1), Some((1, 0)));
+        assert_eq!(store.argmax(), Some((2, 1)));
+        assert_eq!(store.argmax_over(2..4), Some((1, 2)));
+        assert_eq!(store.max_over(2..4), Some(-1.0));
+        assert_eq!(store.mean_over(2..3), Some(-10.0 / 3.0));
+        assert_eq!(store.mean_over(2..4), Some(f64::NEG_INFINITY));
+        assert_eq!(store.mean_over(1..1), None);
+        assert_eq!(store.argmax_over(4..4), None);
+
+        store.set_probs(3, &[f64::NAN; 3]);
+        assert_eq!(store.max_over(3..4), None);
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_probstore_range_past_end() {
+        ProbStore::new(3, 4).mean_over(2..5);
+    }
+
     fn store_get(store: &ProbStore, walker_idx: usize, iteration_idx: usize) -> f64 {
         assert!(walker_idx < store.nwalkers);
         assert!(iteration_idx < store.niterations);
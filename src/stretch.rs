@@ -5,6 +5,8 @@ pub struct Stretch {
     pub q: Vec<Guess>,
     pub newlnprob: Vec<f64>,
     pub accept: Vec<bool>,
+    pub newapprox: Vec<Vec<f64>>,
+    pub sources: Vec<usize>,
 }
 
 impl Stretch {
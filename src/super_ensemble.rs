@@ -0,0 +1,215 @@
+//! Several independent ensembles sharing one model
+
+use errors::{EmceeError, Result};
+use guess::Guess;
+use running::{rhat, Welford};
+use {EnsembleSampler, Prob, Step};
+
+/// `K` independent ensembles run side by side on the same model
+///
+/// Each ensemble is a complete [`EnsembleSampler`](struct.EnsembleSampler.html) with its own
+/// random number stream and its own stored chain. Because the ensembles never exchange walkers,
+/// comparing them gives a between-chain convergence check
+/// ([`rhat`](#method.rhat)) which a single ensemble cannot provide, as its walkers are coupled
+/// through the moves.
+///
+/// The individual samplers are available through [`samplers`](#method.samplers) and
+/// [`samplers_mut`](#method.samplers_mut), e.g. to read their chains or configure their moves.
+pub struct SuperEnsemble<'a, T: Prob + Sync + Send + 'a> {
+    samplers: Vec<EnsembleSampler<'a, T>>,
+}
+
+impl<'a, T: Prob + Sync + Send + 'a> SuperEnsemble<'a, T> {
+    /// Create `nensembles` ensembles of `nwalkers` walkers each
+    ///
+    /// Errors are handled by returning a [`Result`](errors/type.Result.html) which contains
+    /// [`EmceeError::InvalidInputs`](errors/enum.EmceeError.html) if `nensembles` is zero, or
+    /// for the same reasons as [`EnsembleSampler::new`](struct.EnsembleSampler.html#method.new).
+    pub fn new(nensembles: usize, nwalkers: usize, dim: usize, lnprob: &'a T) -> Result<Self> {
+        if nensembles == 0 {
+            return Err(EmceeError::InvalidInputs(
+                "at least one ensemble is required".into(),
+            ));
+        }
+
+        let samplers = (0..nensembles)
+            .map(|_| EnsembleSampler::new(nwalkers, dim, lnprob))
+            .collect::<Result<Vec<_>>>()?;
+        Ok(SuperEnsemble { samplers })
+    }
+
+    /// Create `nensembles` ensembles which each evaluate their walkers on a pool of `n_threads`
+    /// threads
+    ///
+    /// Errors are handled by returning a [`Result`](errors/type.Result.html) which contains
+    /// [`EmceeError::InvalidInputs`](errors/enum.EmceeError.html) if `nensembles` is zero, or
+    /// for the same reasons as
+    /// [`EnsembleSampler::threaded`](struct.EnsembleSampler.html#method.threaded).
+    pub fn threaded(
+        nensembles: usize,
+        nwalkers: usize,
+        dim: usize,
+        lnprob: &'a T,
+        n_threads: usize,
+    ) -> Result<Self> {
+        if nensembles == 0 {
+            return Err(EmceeError::InvalidInputs(
+                "at least one ensemble is required".into(),
+            ));
+        }
+
+        let samplers = (0..nensembles)
+            .map(|_| EnsembleSampler::threaded(nwalkers, dim, lnprob, n_threads))
+            .collect::<Result<Vec<_>>>()?;
+        Ok(SuperEnsemble { samplers })
+    }
+
+    /// Seed every ensemble reproducibly
+    ///
+    /// Ensemble `k` is seeded with `seed` followed by `k`, so the streams are distinct but the
+    /// whole run is determined by `seed`.
+    pub fn seed(&mut self, seed: &[usize]) {
+        for (k, sampler) in self.samplers.iter_mut().enumerate() {
+            let mut stream = seed.to_vec();
+            stream.push(k);
+            sampler.seed(&stream);
+        }
+    }
+
+    /// The number of ensembles
+    pub fn nensembles(&self) -> usize {
+        self.samplers.len()
+    }
+
+    /// The individual samplers, one per ensemble
+    pub fn samplers(&self) -> &[EnsembleSampler<'a, T>] {
+        &self.samplers
+    }
+
+    /// Mutable access to the individual samplers, one per ensemble
+    pub fn samplers_mut(&mut self) -> &mut [EnsembleSampler<'a, T>] {
+        &mut self.samplers
+    }
+
+    /// Run every ensemble for `niterations` iterations
+    ///
+    /// `p0` contains the starting positions of each ensemble in turn. The ensembles are run one
+    /// after the other, so for expensive models create them with
+    /// [`threaded`](#method.threaded) to evaluate the walkers of each ensemble in parallel. The
+    /// final state of each ensemble is returned.
+    ///
+    /// Errors are handled by returning a [`Result`](errors/type.Result.html) which contains
+    /// [`EmceeError::InvalidInputs`](errors/enum.EmceeError.html) if `p0` does not contain one
+    /// set of positions per ensemble, or any error from running the ensembles.
+    pub fn run_mcmc(&mut self, p0: &[Vec<Guess>], niterations: usize) -> Result<Vec<Step>> {
+        if p0.len() != self.samplers.len() {
+            return Err(EmceeError::InvalidInputs(format!(
+                "expected initial positions for {} ensembles, got {}",
+                self.samplers.len(),
+                p0.len()
+            )));
+        }
+
+        self.samplers
+            .iter_mut()
+            .zip(p0)
+            .map(|(sampler, p0)| sampler.run_mcmc(p0, niterations))
+            .collect()
+    }
+
+    /// The Gelman-Rubin statistic of each parameter, comparing the ensembles
+    ///
+    /// Every ensemble's stored samples (all walkers and iterations) are treated as one sequence.
+    /// Values close to 1 indicate that the ensembles have converged to the same distribution.
+    /// Returns `None` unless every ensemble has a stored chain, and the results are NaN for
+    /// fewer than two ensembles.
+    pub fn rhat(&self) -> Option<Vec<f64>> {
+        let chains = self.samplers
+            .iter()
+            .map(|sampler| sampler.flatchain())
+            .collect::<Option<Vec<_>>>()?;
+        let dim = self.samplers[0].dim;
+
+        let stats: Vec<Vec<Welford>> = chains
+            .iter()
+            .map(|chain| {
+                let mut acc = vec![Welford::default(); dim];
+                for guess in chain {
+                    for (acc, value) in acc.iter_mut().zip(&guess.values) {
+                        acc.add(*value);
+                    }
+                }
+                acc
+            })
+            .collect();
+
+        Some(
+            (0..dim)
+                .map(|param| {
+                    let sequences: Vec<&Welford> = stats.iter().map(|acc| &acc[param]).collect();
+                    rhat(&sequences)
+                })
+                .collect(),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use testkit::CorrelatedGaussian;
+
+    fn initial(nensembles: usize, nwalkers: usize) -> Vec<Vec<Guess>> {
+        (0..nensembles)
+            .map(|k| {
+                (0..nwalkers)
+                    .map(|i| Guess::new(&[0.1 * i as f64 - 0.4, 0.05 * (i + k) as f64]))
+                    .collect()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_independent_ensembles() {
+        let model = CorrelatedGaussian::standard(2);
+        let mut ensembles = SuperEnsemble::new(3, 8, 2, &model).unwrap();
+        ensembles.seed(&[1, 2, 3]);
+        let states = ensembles.run_mcmc(&initial(3, 8), 500).unwrap();
+        assert_eq!(states.len(), 3);
+        assert_ne!(states[0].pos[0].values, states[1].pos[0].values);
+
+        for sampler in ensembles.samplers() {
+            assert_eq!(sampler.flatchain().unwrap().len(), 500 * 8);
+        }
+
+        let rhat = ensembles.rhat().unwrap();
+        assert_eq!(rhat.len(), 2);
+        for value in rhat {
+            assert!(value < 1.2, "rhat = {}", value);
+        }
+    }
+
+    #[test]
+    fn test_seeding_is_reproducible() {
+        let model = CorrelatedGaussian::standard(2);
+        let run = || {
+            let mut ensembles = SuperEnsemble::new(2, 8, 2, &model).unwrap();
+            ensembles.seed(&[7]);
+            ensembles.run_mcmc(&initial(2, 8), 20).unwrap()
+        };
+        let (a, b) = (run(), run());
+        for (a, b) in a.iter().zip(&b) {
+            assert_eq!(*a.lnprob, *b.lnprob);
+        }
+    }
+
+    #[test]
+    fn test_invalid_inputs() {
+        let model = CorrelatedGaussian::standard(2);
+        assert!(SuperEnsemble::new(0, 8, 2, &model).is_err());
+        assert!(SuperEnsemble::threaded(0, 8, 2, &model, 2).is_err());
+        let mut ensembles = SuperEnsemble::new(2, 8, 2, &model).unwrap();
+        assert!(ensembles.rhat().is_none());
+        assert!(ensembles.run_mcmc(&initial(1, 8), 10).is_err());
+    }
+}
@@ -0,0 +1,506 @@
+//! Gaussian-process surrogates of expensive models
+//!
+//! Enabled with the `surrogate` feature. Every evaluation of the model made while sampling is
+//! kept in the chain and log probability store, and for a model which takes minutes per
+//! evaluation those pairs are too valuable to waste. [`Surrogate`](struct.Surrogate.html) fits
+//! a Gaussian process to them, and [`SurrogateLnProb`](struct.SurrogateLnProb.html) offers the
+//! fit to the sampler as an [approximation](../trait.Prob.html#method.napproximations), so that
+//! delayed acceptance screens out most poor proposals without running the model while the
+//! samples still follow the full posterior.
+//!
+//! The process has a constant mean, the mean of the training values, and a squared exponential
+//! kernel acting on the parameters standardised by the spread of the training points. Its
+//! prediction is exact at the training points (up to the `noise` term) and returns to the mean
+//! far away from them, where the screening then passes proposals on to the full model. Fitting
+//! costs `O(n^3)` in the number of training points, so the history is thinned to at most
+//! [`max_points`](struct.SurrogateFit.html#structfield.max_points) distinct positions.
+//!
+//! ```rust,ignore
+//! // Explore with the full model, then continue with the surrogate screening proposals
+//! let mut sampler = EnsembleSampler::new(nwalkers, ndim, &model)?;
+//! let state = sampler.run_mcmc(&p0, 200)?;
+//! let surrogate = sampler.fit_surrogate(&SurrogateFit::default())?;
+//!
+//! let screened = SurrogateLnProb::new(model, surrogate);
+//! let mut sampler = EnsembleSampler::new(nwalkers, ndim, &screened)?;
+//! sampler.run_mcmc(&state.pos, 10000)?;
+//! ```
+
+use std::sync::RwLock;
+use std::time::Instant;
+
+use scoped_threadpool::Pool;
+
+use errors::{EmceeError, Result};
+use guess::Guess;
+use linalg::{self, Matrix};
+use prob::Prob;
+use stores::{Chain, ProbStore};
+use EnsembleSampler;
+
+/// Settings for fitting a [`Surrogate`](struct.Surrogate.html)
+#[derive(Debug, Clone)]
+pub struct SurrogateFit {
+    /// The largest number of training points; longer histories are thinned evenly
+    /// (default 500)
+    pub max_points: usize,
+
+    /// The length scale of the kernel, in units of the standard deviation of the training
+    /// points in each parameter (default 0.5)
+    pub length_scale: f64,
+
+    /// The variance of the noise added to the kernel matrix, as a fraction of the variance of
+    /// the training values, which keeps the fit well conditioned (default 1e-6)
+    pub noise: f64,
+}
+
+impl Default for SurrogateFit {
+    fn default() -> Self {
+        SurrogateFit {
+            max_points: 500,
+            length_scale: 0.5,
+            noise: 1e-6,
+        }
+    }
+}
+
+/// A Gaussian process fitted to evaluations of the log posterior probability
+#[derive(Debug, Clone)]
+pub struct Surrogate {
+    shift: Vec<f64>,
+    scale: Vec<f64>,
+    points: Matrix,
+    weights: Vec<f64>,
+    mean: f64,
+    amplitude: f64,
+    length_scale: f64,
+}
+
+impl Surrogate {
+    /// Fit a surrogate to the log probabilities `lnprob` at the positions `points`
+    ///
+    /// Positions which are repeated, e.g. by rejected proposals, are used once, and those with
+    /// a log probability which is not finite are skipped.
+    ///
+    /// Errors are handled by returning a [`Result`](../errors/type.Result.html) which contains
+    /// [`EmceeError::InvalidInputs`](../errors/enum.EmceeError.html) if there is not one log
+    /// probability per position, the positions have different numbers of parameters, fewer
+    /// than two distinct positions with finite values remain, any setting is not positive, or
+    /// the kernel matrix cannot be factorised.
+    pub fn fit(points: &[Guess], lnprob: &[f64], settings: &SurrogateFit) -> Result<Surrogate> {
+        if points.len() != lnprob.len() {
+            return Err(EmceeError::InvalidInputs(format!(
+                "{} log probabilities were given for {} positions",
+                lnprob.len(),
+                points.len()
+            )));
+        }
+        if settings.max_points < 2 || !(settings.length_scale > 0.0 && settings.noise > 0.0) {
+            return Err(EmceeError::InvalidInputs(
+                "the surrogate needs at least two points and a positive length scale and noise"
+                    .into(),
+            ));
+        }
+        let ndim = points.first().map_or(0, |guess| guess.values.len());
+        if points.iter().any(|guess| guess.values.len() != ndim) {
+            return Err(EmceeError::InvalidInputs(
+                "the positions have different numbers of parameters".into(),
+            ));
+        }
+
+        let (x, y) = training_set(points, lnprob, settings.max_points);
+        if x.len() < 2 {
+            return Err(EmceeError::InvalidInputs(format!(
+                "{} distinct positions with a finite log probability, at least 2 are required",
+                x.len()
+            )));
+        }
+
+        let n = x.len() as f64;
+        let shift: Vec<f64> = (0..ndim)
+            .map(|i| x.iter().map(|row| row[i]).sum::<f64>() / n)
+            .collect();
+        let scale: Vec<f64> = (0..ndim)
+            .map(|i| {
+                let var = x.iter().map(|row| (row[i] - shift[i]).powi(2)).sum::<f64>() / n;
+                if var > 0.0 {
+                    var.sqrt()
+                } else {
+                    1.0
+                }
+            })
+            .collect();
+        let points: Matrix = x
+            .iter()
+            .map(|row| standardise(row, &shift, &scale))
+            .collect();
+
+        let mean = y.iter().sum::<f64>() / n;
+        let variance = y.iter().map(|value| (value - mean).powi(2)).sum::<f64>() / n;
+        let amplitude = if variance > 0.0 { variance } else { 1.0 };
+
+        let mut surrogate = Surrogate {
+            shift,
+            scale,
+            points,
+            weights: Vec::new(),
+            mean,
+            amplitude,
+            length_scale: settings.length_scale,
+        };
+        let mut k: Matrix = surrogate
+            .points
+            .iter()
+            .map(|a| surrogate.points.iter().map(|b| surrogate.kernel(a, b)).collect())
+            .collect();
+        for (i, row) in k.iter_mut().enumerate() {
+            row[i] += settings.noise * amplitude;
+        }
+        let l = linalg::cholesky(&k).ok_or_else(|| {
+            EmceeError::InvalidInputs(
+                "the kernel matrix is not positive definite, try a larger noise".into(),
+            )
+        })?;
+
+        // Solve (K + noise) w = y - mean with the two triangular factors
+        let residuals: Vec<f64> = y.iter().map(|value| value - mean).collect();
+        let z = linalg::lower_solve(&l, &residuals);
+        let mut weights = vec![0f64; z.len()];
+        for i in (0..z.len()).rev() {
+            let sum: f64 = (i + 1..z.len()).map(|j| l[j][i] * weights[j]).sum();
+            weights[i] = (z[i] - sum) / l[i][i];
+        }
+        surrogate.weights = weights;
+        Ok(surrogate)
+    }
+
+    /// Fit a surrogate to every sample of a chain and the matching log probabilities, as
+    /// with [`fit`](#method.fit)
+    pub fn from_chain(
+        chain: &Chain,
+        probstore: &ProbStore,
+        settings: &SurrogateFit,
+    ) -> Result<Surrogate> {
+        Surrogate::fit(&chain.flatchain(), &probstore.flatprob(), settings)
+    }
+
+    /// The number of training points
+    pub fn npoints(&self) -> usize {
+        self.points.len()
+    }
+
+    /// The predicted log posterior probability at `params`
+    pub fn predict(&self, params: &Guess) -> f64 {
+        let x = standardise(&params.values, &self.shift, &self.scale);
+        let correction: f64 = self
+            .points
+            .iter()
+            .zip(&self.weights)
+            .map(|(point, weight)| weight * self.kernel(&x, point))
+            .sum();
+        self.mean + correction
+    }
+
+    fn kernel(&self, a: &[f64], b: &[f64]) -> f64 {
+        let distance2: f64 = a.iter().zip(b).map(|(a, b)| (a - b).powi(2)).sum();
+        self.amplitude * (-0.5 * distance2 / (self.length_scale * self.length_scale)).exp()
+    }
+}
+
+/// The distinct positions with finite values, thinned evenly to at most `max_points`
+fn training_set(points: &[Guess], lnprob: &[f64], max_points: usize) -> (Matrix, Vec<f64>) {
+    let mut order: Vec<usize> = (0..points.len())
+        .filter(|&i| lnprob[i].is_finite())
+        .collect();
+    let bits = |i: usize| -> Vec<u64> { points[i].values.iter().map(|x| x.to_bits()).collect() };
+    order.sort_by_key(|&i| bits(i));
+    order.dedup_by_key(|i| bits(*i));
+    order.sort_unstable();
+
+    let stride = order.len().div_ceil(max_points).max(1);
+    order
+        .into_iter()
+        .step_by(stride)
+        .map(|i| (points[i].values.clone(), lnprob[i]))
+        .unzip()
+}
+
+fn standardise(values: &[f64], shift: &[f64], scale: &[f64]) -> Vec<f64> {
+    values
+        .iter()
+        .zip(shift.iter().zip(scale))
+        .map(|(x, (shift, scale))| (x - shift) / scale)
+        .collect()
+}
+
+/// A model screened by a [`Surrogate`](struct.Surrogate.html) of itself
+///
+/// The surrogate is the cheapest [approximation](../trait.Prob.html#method.napproximations),
+/// followed by any of the model's own, so the sampler evaluates the model only for proposals
+/// which the surrogate finds promising. Every other method, including batching and the
+/// [stored positions](../trait.Prob.html#method.stored_position), is passed on to the model,
+/// and the surrogate predicts at the sampled positions. The surrogate can be replaced between
+/// runs with [`set_surrogate`](#method.set_surrogate), e.g. after refitting it to a longer
+/// history, while the sampler still borrows this model.
+pub struct SurrogateLnProb<T: Prob> {
+    model: T,
+    surrogate: RwLock<Surrogate>,
+}
+
+impl<T: Prob> SurrogateLnProb<T> {
+    /// Screen `model` with `surrogate`
+    pub fn new(model: T, surrogate: Surrogate) -> SurrogateLnProb<T> {
+        SurrogateLnProb {
+            model,
+            surrogate: RwLock::new(surrogate),
+        }
+    }
+
+    /// The screened model
+    pub fn model(&self) -> &T {
+        &self.model
+    }
+
+    /// Replace the surrogate, e.g. with one fitted to a longer run
+    pub fn set_surrogate(&self, surrogate: Surrogate) {
+        *self.surrogate.write().expect("surrogate lock poisoned") = surrogate;
+    }
+}
+
+impl<T: Prob> Prob for SurrogateLnProb<T> {
+    fn lnlike(&self, params: &Guess) -> f64 {
+        self.model.lnlike(params)
+    }
+
+    fn lnprior(&self, params: &Guess) -> f64 {
+        self.model.lnprior(params)
+    }
+
+    fn validate(&self, params: &Guess) -> ::std::result::Result<(), String> {
+        self.model.validate(params)
+    }
+
+    fn lnprob(&self, params: &Guess) -> f64 {
+        self.model.lnprob(params)
+    }
+
+    fn try_lnprob(&self, params: &Guess) -> ::std::result::Result<f64, String> {
+        self.model.try_lnprob(params)
+    }
+
+    fn lnprob_with_deadline(&self, params: &Guess, deadline: Instant) -> Option<f64> {
+        self.model.lnprob_with_deadline(params, deadline)
+    }
+
+    fn lnprob_in_pool(&self, params: &Guess, pool: &mut Pool) -> f64 {
+        self.model.lnprob_in_pool(params, pool)
+    }
+
+    fn is_batched(&self) -> bool {
+        self.model.is_batched()
+    }
+
+    fn lnprob_batch(&self, positions: &[Guess], out: &mut [f64]) {
+        self.model.lnprob_batch(positions, out)
+    }
+
+    fn napproximations(&self) -> usize {
+        1 + self.model.napproximations()
+    }
+
+    /// The surrogate's prediction where the prior allows the position, so the approximation is
+    /// finite wherever the full posterior is, then the model's own approximations
+    fn lnprob_approximation(&self, params: &Guess, level: usize) -> f64 {
+        if level > 0 {
+            return self.model.lnprob_approximation(params, level - 1);
+        }
+        if !self.model.lnprior(params).is_finite() {
+            return -f64::INFINITY;
+        }
+        self.surrogate
+            .read()
+            .expect("surrogate lock poisoned")
+            .predict(params)
+    }
+
+    fn stored_position(&self, params: &Guess) -> Option<Guess> {
+        self.model.stored_position(params)
+    }
+
+    fn sampled_position(&self, stored: &Guess) -> Option<Guess> {
+        self.model.sampled_position(stored)
+    }
+}
+
+impl<'a, T: Prob + Sync + Send + 'a> EnsembleSampler<'a, T> {
+    /// Fit a [`Surrogate`](surrogate/struct.Surrogate.html) to the stored chain and its log
+    /// probabilities
+    ///
+    /// The surrogate is fitted in the sampled coordinates, mapping the stored chain back with
+    /// [`Prob::sampled_position`](trait.Prob.html#method.sampled_position), so it predicts the
+    /// log probabilities the sampler sees.
+    ///
+    /// Errors are handled by returning a [`Result`](errors/type.Result.html) which contains
+    /// [`EmceeError::InvalidInputs`](errors/enum.EmceeError.html) if no chain is stored, or for
+    /// the same reasons as [`Surrogate::fit`](surrogate/struct.Surrogate.html#method.fit).
+    pub fn fit_surrogate(&self, settings: &SurrogateFit) -> Result<Surrogate> {
+        match (self.chain.as_ref(), self.probstore.as_ref()) {
+            (Some(chain), Some(probstore)) => {
+                let lnprob = self.lnprob;
+                let points: Vec<Guess> = chain
+                    .flatchain()
+                    .into_iter()
+                    .map(|guess| lnprob.sampled_position(&guess).unwrap_or(guess))
+                    .collect();
+                Surrogate::fit(&points, &probstore.flatprob(), settings)
+            }
+            _ => Err(EmceeError::InvalidInputs(
+                "the sampler is not storing the chain".into(),
+            )),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use {with_data, Batched, BatchedProb, Cascade, LogTransform, Transformed};
+
+    /// Correlated Gaussian in two parameters, within a box
+    struct Gaussian;
+
+    impl Prob for Gaussian {
+        fn lnlike(&self, params: &Guess) -> f64 {
+            let (x, y) = (params[0], params[1]);
+            -0.5 * (x * x - 1.2 * x * y + y * y) / 0.64
+        }
+
+        fn lnprior(&self, params: &Guess) -> f64 {
+            if params.values.iter().all(|x| x.abs() < 10.0) {
+                0.0
+            } else {
+                -f64::INFINITY
+            }
+        }
+    }
+
+    fn initial() -> Vec<Guess> {
+        (0..16)
+            .map(|i| Guess::new(&[0.1 * i as f64 - 0.8, 0.05 * ((7 * i) % 16) as f64 - 0.4]))
+            .collect()
+    }
+
+    #[test]
+    fn test_fit() {
+        let points: Vec<Guess> = (0..200)
+            .map(|i| Guess::new(&[(i % 20) as f64 * 0.2 - 2.0, (i / 20) as f64 * 0.4 - 2.0]))
+            .collect();
+        let lnprob: Vec<f64> = points.iter().map(|guess| Gaussian.lnprob(guess)).collect();
+        let surrogate = Surrogate::fit(&points, &lnprob, &SurrogateFit::default()).unwrap();
+        assert_eq!(surrogate.npoints(), 200);
+
+        // Exact at the training points, and close in between
+        assert_approx_eq!(surrogate.predict(&points[57]), lnprob[57], 1e-3);
+        for guess in &[Guess::new(&[0.1, -0.3]), Guess::new(&[-1.1, 0.5])] {
+            assert_approx_eq!(surrogate.predict(guess), Gaussian.lnprob(guess), 0.05);
+        }
+
+        // Repeated and non-finite positions are dropped, and long histories thinned
+        let mut repeated = points.clone();
+        repeated.extend(points.iter().cloned());
+        let mut values = lnprob.clone();
+        values.extend(lnprob.iter().cloned());
+        values[3] = -f64::INFINITY;
+        let settings = SurrogateFit {
+            max_points: 50,
+            ..SurrogateFit::default()
+        };
+        assert_eq!(Surrogate::fit(&repeated, &values, &settings).unwrap().npoints(), 50);
+
+        assert!(Surrogate::fit(&points, &lnprob[1..], &settings).is_err());
+        assert!(Surrogate::fit(&points[..1], &lnprob[..1], &settings).is_err());
+        let settings = SurrogateFit {
+            length_scale: 0.0,
+            ..SurrogateFit::default()
+        };
+        assert!(Surrogate::fit(&points, &lnprob, &settings).is_err());
+    }
+
+    #[test]
+    fn test_surrogate_screening() {
+        let mut sampler = EnsembleSampler::new(16, 2, &Gaussian).unwrap();
+        sampler.seed(&[2]);
+        let state = sampler.run_mcmc(&initial(), 100).unwrap();
+        let surrogate = sampler.fit_surrogate(&SurrogateFit::default()).unwrap();
+
+        let model = SurrogateLnProb::new(Gaussian, surrogate);
+        assert_eq!(model.napproximations(), 1);
+        assert_eq!(model.lnprob_approximation(&Guess::new(&[20.0, 0.0]), 0), -f64::INFINITY);
+
+        let mut sampler = EnsembleSampler::new(16, 2, &model).unwrap();
+        sampler.seed(&[3]);
+        sampler.run_mcmc(&state.pos, 3000).unwrap();
+
+        let flatchain = sampler.flatchain().unwrap();
+        let n = flatchain.len() as f64;
+        for param in 0..2 {
+            let mean = flatchain.iter().map(|guess| guess[param]).sum::<f64>() / n;
+            let var = flatchain.iter().map(|guess| (guess[param] - mean).powi(2)).sum::<f64>() / n;
+            assert!(mean.abs() < 0.1, "mean = {}", mean);
+            assert!((var - 1.0).abs() < 0.15, "var = {}", var);
+        }
+        assert!(sampler.run_stats().nscreened > 0);
+
+        // The surrogate can be refitted while the sampler borrows the model
+        let refitted = sampler.fit_surrogate(&SurrogateFit::default()).unwrap();
+        model.set_surrogate(refitted);
+        sampler.run_mcmc(&initial(), 10).unwrap();
+
+        let unrun = EnsembleSampler::new(16, 2, &Gaussian).unwrap();
+        assert!(unrun.fit_surrogate(&SurrogateFit::default()).is_err());
+    }
+
+    struct Square;
+
+    impl BatchedProb for Square {
+        fn lnprob_batch(&self, positions: &[Guess], out: &mut [f64]) {
+            for (params, out) in positions.iter().zip(out.iter_mut()) {
+                *out = -params[0] * params[0];
+            }
+        }
+    }
+
+    #[test]
+    fn test_forwarding() {
+        let coarse = with_data((), |params: &Guess, _: &()| -params[0]);
+        let full = with_data((), |params: &Guess, _: &()| -2.0 * params[0]);
+        let cascade = Cascade::new(vec![Box::new(coarse), Box::new(full)]).unwrap();
+        let mut model = Transformed::new(cascade);
+        model.transform(0, LogTransform::default()).unwrap();
+
+        // Fitted in the sampled coordinates, where the chain is stored physically
+        let mut sampler = EnsembleSampler::new(8, 1, &model).unwrap();
+        sampler.seed(&[4]);
+        let p0: Vec<Guess> = (0..8).map(|i| Guess::new(&[0.1 * i as f64 - 0.4])).collect();
+        let state = sampler.run_mcmc(&p0, 20).unwrap();
+        let surrogate = sampler.fit_surrogate(&SurrogateFit::default()).unwrap();
+        assert_approx_eq!(surrogate.predict(&state.pos[3]), state.lnprob[3], 1e-3);
+
+        let screened = SurrogateLnProb::new(model, surrogate.clone());
+        let y = Guess::new(&[0.5]);
+        let x = 0.5f64.exp();
+        assert_eq!(screened.napproximations(), 2);
+        assert_eq!(screened.lnprob_approximation(&y, 0), surrogate.predict(&y));
+        assert_approx_eq!(screened.lnprob_approximation(&y, 1), -x + 0.5);
+        assert_approx_eq!(screened.lnprob(&y), -2.0 * x + 0.5);
+        let stored = screened.stored_position(&y).unwrap();
+        assert_approx_eq!(stored[0], x);
+        assert_approx_eq!(screened.sampled_position(&stored).unwrap()[0], 0.5);
+
+        let screened = SurrogateLnProb::new(Batched::new(Square), surrogate);
+        assert!(screened.is_batched());
+        let mut out = [0.0; 2];
+        screened.lnprob_batch(&[Guess::new(&[2.0]), Guess::new(&[-1.0])], &mut out);
+        assert_eq!(out, [-4.0, -1.0]);
+    }
+}
@@ -0,0 +1,58 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use guess::Guess;
+use prob::Prob;
+
+/// A model with its likelihood raised to the power `beta`
+///
+/// The power can be changed while a sampler holds a reference to the model, so one sampler,
+/// with its moves and thread pool, can be reused at every temperature of a schedule.
+pub struct Tempered<'a, T: 'a> {
+    model: &'a T,
+    beta: AtomicU64,
+}
+
+impl<'a, T: Prob + 'a> Tempered<'a, T> {
+    pub fn new(model: &'a T) -> Tempered<'a, T> {
+        Tempered {
+            model,
+            beta: AtomicU64::new(1f64.to_bits()),
+        }
+    }
+
+    pub fn beta(&self) -> f64 {
+        f64::from_bits(self.beta.load(Ordering::SeqCst))
+    }
+
+    pub fn set_beta(&self, beta: f64) {
+        self.beta.store(beta.to_bits(), Ordering::SeqCst);
+    }
+}
+
+impl<'a, T: Prob + 'a> Prob for Tempered<'a, T> {
+    fn lnlike(&self, params: &Guess) -> f64 {
+        // Avoid 0 * -inf at infinite temperature, where only the prior matters
+        let beta = self.beta();
+        if beta == 0.0 {
+            0.0
+        } else {
+            beta * self.model.lnlike(params)
+        }
+    }
+
+    fn lnprior(&self, params: &Guess) -> f64 {
+        self.model.lnprior(params)
+    }
+
+    fn validate(&self, params: &Guess) -> ::std::result::Result<(), String> {
+        self.model.validate(params)
+    }
+
+    fn stored_position(&self, params: &Guess) -> Option<Guess> {
+        self.model.stored_position(params)
+    }
+
+    fn sampled_position(&self, stored: &Guess) -> Option<Guess> {
+        self.model.sampled_position(stored)
+    }
+}
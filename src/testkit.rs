@@ -0,0 +1,759 @@
+//! Tools for checking custom moves
+//!
+//! A correct [`Move`](../moves/trait.Move.html) leaves the target distribution invariant. This
+//! module provides standard [`Target`](trait.Target.html)s whose moments and marginal
+//! distributions are known exactly, a driver which samples a target with a given move, and
+//! statistical checks comparing the samples with the truth:
+//!
+//! ```rust
+//! # use emcee::moves::StretchMove;
+//! # use emcee::testkit::{self, CorrelatedGaussian};
+//! let target = CorrelatedGaussian::new(
+//!     vec![1.0, -2.0],
+//!     vec![vec![1.0, 0.8], vec![0.8, 2.0]],
+//! ).unwrap();
+//! let run = testkit::run_move(&target, Box::new(StretchMove::default()), 16, 4000, &[1]).unwrap();
+//! testkit::assert_moments(&run, &target, 4.0);
+//! testkit::assert_marginals(&run, &target, 10, 4.0);
+//! ```
+//!
+//! The checks account for the autocorrelation of the chain through the effective number of
+//! samples, but they are statistical: a correct move fails a check with a small probability,
+//! controlled by the `nsigma` argument, and a subtly wrong move may need long runs to be
+//! detected. A move which neglects its acceptance factors usually fails on targets with several
+//! parameters.
+
+use std::f64::consts::PI;
+
+use rand::{SeedableRng, StdRng};
+use rand::distributions::{IndependentSample, Normal};
+
+use errors::{EmceeError, Result};
+use guess::Guess;
+use linalg::{self, Matrix};
+use math;
+use moves::Move;
+use special::normal_cdf;
+use {Chain, EnsembleSampler, Prob};
+
+/// A target distribution with known moments
+pub trait Target: Prob {
+    /// The number of parameters
+    fn dim(&self) -> usize;
+
+    /// The exact mean of each parameter
+    fn mean(&self) -> Vec<f64>;
+
+    /// The exact covariance matrix
+    fn covariance(&self) -> Vec<Vec<f64>>;
+
+    /// The cumulative distribution function of the marginal distribution of `param`, if it is
+    /// known in closed form
+    fn marginal_cdf(&self, param: usize, x: f64) -> Option<f64>;
+}
+
+/// Multivariate Gaussian with a full covariance matrix
+#[derive(Debug, Clone)]
+pub struct CorrelatedGaussian {
+    mean: Vec<f64>,
+    cov: Matrix,
+    l: Matrix,
+}
+
+impl CorrelatedGaussian {
+    /// Create a Gaussian with the given mean and covariance
+    ///
+    /// Errors are handled by returning a [`Result`](../errors/type.Result.html) which contains
+    /// [`EmceeError::InvalidInputs`](../errors/enum.EmceeError.html) if the covariance is not
+    /// square, does not match the mean, or is not positive definite.
+    pub fn new(mean: Vec<f64>, cov: Vec<Vec<f64>>) -> Result<CorrelatedGaussian> {
+        if cov.len() != mean.len() || cov.iter().any(|row| row.len() != mean.len()) {
+            return Err(EmceeError::InvalidInputs(
+                "the covariance must be square and match the mean".into(),
+            ));
+        }
+        let l = linalg::cholesky(&cov).ok_or_else(|| {
+            EmceeError::InvalidInputs("the covariance must be positive definite".into())
+        })?;
+        Ok(CorrelatedGaussian { mean, cov, l })
+    }
+
+    /// The standard normal in `dim` dimensions, with zero mean and identity covariance
+    pub fn standard(dim: usize) -> CorrelatedGaussian {
+        let identity: Vec<Vec<f64>> = (0..dim)
+            .map(|i| (0..dim).map(|j| if i == j { 1.0 } else { 0.0 }).collect())
+            .collect();
+        CorrelatedGaussian::new(vec![0.0; dim], identity)
+            .expect("the identity is positive definite")
+    }
+}
+
+impl Prob for CorrelatedGaussian {
+    fn lnlike(&self, params: &Guess) -> f64 {
+        let delta: Vec<f64> = params
+            .values
+            .iter()
+            .zip(&self.mean)
+            .map(|(x, mu)| x - mu)
+            .collect();
+        -0.5 * linalg::lower_solve(&self.l, &delta)
+            .iter()
+            .map(|z| z * z)
+            .sum::<f64>()
+    }
+
+    fn lnprior(&self, _params: &Guess) -> f64 {
+        0.0
+    }
+}
+
+impl Target for CorrelatedGaussian {
+    fn dim(&self) -> usize {
+        self.mean.len()
+    }
+
+    fn mean(&self) -> Vec<f64> {
+        self.mean.clone()
+    }
+
+    fn covariance(&self) -> Vec<Vec<f64>> {
+        self.cov.clone()
+    }
+
+    fn marginal_cdf(&self, param: usize, x: f64) -> Option<f64> {
+        Some(normal_cdf((x - self.mean[param]) / self.cov[param][param].sqrt()))
+    }
+}
+
+/// The two dimensional Rosenbrock density `exp(-((a - x)^2 + b (y - x^2)^2) / scale)`
+///
+/// The curved, narrow ridge is a standard stress test for samplers. The marginal distribution
+/// of `x` is Gaussian with mean `a` and variance `scale / 2`, and `y` given `x` is Gaussian
+/// with mean `x^2` and variance `scale / (2 b)`.
+#[derive(Debug, Clone)]
+pub struct Rosenbrock {
+    /// Location of the ridge
+    pub a: f64,
+
+    /// Steepness of the ridge
+    pub b: f64,
+
+    /// Overall scale of the log density
+    pub scale: f64,
+}
+
+impl Default for Rosenbrock {
+    /// The version used in the Python `emcee` tests, with `a = 1`, `b = 100` and `scale = 20`
+    fn default() -> Self {
+        Rosenbrock {
+            a: 1.0,
+            b: 100.0,
+            scale: 20.0,
+        }
+    }
+}
+
+impl Prob for Rosenbrock {
+    fn lnlike(&self, params: &Guess) -> f64 {
+        let (x, y) = (params[0], params[1]);
+        -((self.a - x).powi(2) + self.b * (y - x * x).powi(2)) / self.scale
+    }
+
+    fn lnprior(&self, _params: &Guess) -> f64 {
+        0.0
+    }
+}
+
+impl Target for Rosenbrock {
+    fn dim(&self) -> usize {
+        2
+    }
+
+    fn mean(&self) -> Vec<f64> {
+        let var = self.scale / 2.0;
+        vec![self.a, self.a * self.a + var]
+    }
+
+    fn covariance(&self) -> Vec<Vec<f64>> {
+        let var = self.scale / 2.0;
+        let cov_xy = 2.0 * self.a * var;
+        let var_y = 4.0 * self.a * self.a * var + 2.0 * var * var + self.scale / (2.0 * self.b);
+        vec![vec![var, cov_xy], vec![cov_xy, var_y]]
+    }
+
+    fn marginal_cdf(&self, param: usize, x: f64) -> Option<f64> {
+        if param == 0 {
+            Some(normal_cdf((x - self.a) / (self.scale / 2.0).sqrt()))
+        } else {
+            None
+        }
+    }
+}
+
+/// Neal's funnel: `v ~ N(0, scale^2)` and `x_i ~ N(0, e^v)` for the other `dim - 1` parameters
+///
+/// The scale of the `x_i` changes by orders of magnitude along `v`, which no single affine
+/// transformation can remove, so this is typical of the difficulty of hierarchical models.
+/// `v` is the first parameter.
+#[derive(Debug, Clone)]
+pub struct Funnel {
+    /// The total number of parameters, including `v`
+    pub dim: usize,
+
+    /// The standard deviation of `v`
+    pub scale: f64,
+}
+
+impl Default for Funnel {
+    /// Neal's original version, with ten parameters and `scale = 3`
+    fn default() -> Self {
+        Funnel {
+            dim: 10,
+            scale: 3.0,
+        }
+    }
+}
+
+impl Prob for Funnel {
+    fn lnlike(&self, params: &Guess) -> f64 {
+        let v = params[0];
+        let sum_squares: f64 = params.values[1..].iter().map(|x| x * x).sum();
+        -0.5 * (v / self.scale).powi(2) - 0.5 * sum_squares * (-v).exp()
+            - 0.5 * (self.dim - 1) as f64 * v
+    }
+
+    fn lnprior(&self, _params: &Guess) -> f64 {
+        0.0
+    }
+}
+
+impl Target for Funnel {
+    fn dim(&self) -> usize {
+        self.dim
+    }
+
+    fn mean(&self) -> Vec<f64> {
+        vec![0.0; self.dim]
+    }
+
+    fn covariance(&self) -> Vec<Vec<f64>> {
+        // The variance of each x_i is E[e^v] = e^(scale^2 / 2)
+        let var_x = (self.scale * self.scale / 2.0).exp();
+        (0..self.dim)
+            .map(|i| {
+                (0..self.dim)
+                    .map(|j| match (i, j) {
+                        (0, 0) => self.scale * self.scale,
+                        _ if i == j => var_x,
+                        _ => 0.0,
+                    })
+                    .collect()
+            })
+            .collect()
+    }
+
+    fn marginal_cdf(&self, param: usize, x: f64) -> Option<f64> {
+        if param == 0 {
+            Some(normal_cdf(x / self.scale))
+        } else {
+            None
+        }
+    }
+}
+
+/// Mixture of isotropic Gaussians
+#[derive(Debug, Clone)]
+pub struct GaussianMixture {
+    components: Vec<(f64, Vec<f64>, f64)>,
+}
+
+impl GaussianMixture {
+    /// Create a mixture from `(weight, mean, standard deviation)` components
+    ///
+    /// The weights are normalised to sum to one.
+    ///
+    /// Errors are handled by returning a [`Result`](../errors/type.Result.html) which contains
+    /// [`EmceeError::InvalidInputs`](../errors/enum.EmceeError.html) if there are no components,
+    /// the means have different lengths, or a weight or standard deviation is not positive.
+    pub fn new(components: Vec<(f64, Vec<f64>, f64)>) -> Result<GaussianMixture> {
+        let dim = match components.first() {
+            Some(component) => component.1.len(),
+            None => {
+                return Err(EmceeError::InvalidInputs(
+                    "the mixture needs at least one component".into(),
+                ))
+            }
+        };
+        if components
+            .iter()
+            .any(|&(weight, ref mean, sigma)| mean.len() != dim || !(weight > 0.0 && sigma > 0.0))
+        {
+            return Err(EmceeError::InvalidInputs(
+                "components must have positive weights and widths, and means of equal length"
+                    .into(),
+            ));
+        }
+
+        let total: f64 = components.iter().map(|&(weight, _, _)| weight).sum();
+        Ok(GaussianMixture {
+            components: components
+                .into_iter()
+                .map(|(weight, mean, sigma)| (weight / total, mean, sigma))
+                .collect(),
+        })
+    }
+}
+
+impl Prob for GaussianMixture {
+    fn lnlike(&self, params: &Guess) -> f64 {
+        let dim = params.values.len() as f64;
+        let terms: Vec<f64> = self.components
+            .iter()
+            .map(|&(weight, ref mean, sigma)| {
+                let r2: f64 = params
+                    .values
+                    .iter()
+                    .zip(mean)
+                    .map(|(x, mu)| (x - mu).powi(2))
+                    .sum();
+                weight.ln() - dim * sigma.ln() - 0.5 * r2 / (sigma * sigma)
+            })
+            .collect();
+        math::logsumexp(&terms)
+    }
+
+    fn lnprior(&self, _params: &Guess) -> f64 {
+        0.0
+    }
+}
+
+impl Target for GaussianMixture {
+    fn dim(&self) -> usize {
+        self.components[0].1.len()
+    }
+
+    fn mean(&self) -> Vec<f64> {
+        (0..self.dim())
+            .map(|i| {
+                self.components
+                    .iter()
+                    .map(|&(weight, ref mean, _)| weight * mean[i])
+                    .sum()
+            })
+            .collect()
+    }
+
+    fn covariance(&self) -> Vec<Vec<f64>> {
+        let mean = self.mean();
+        (0..self.dim())
+            .map(|i| {
+                (0..self.dim())
+                    .map(|j| {
+                        let second: f64 = self.components
+                            .iter()
+                            .map(|&(weight, ref mu, sigma)| {
+                                let diag = if i == j { sigma * sigma } else { 0.0 };
+                                weight * (diag + mu[i] * mu[j])
+                            })
+                            .sum();
+                        second - mean[i] * mean[j]
+                    })
+                    .collect()
+            })
+            .collect()
+    }
+
+    fn marginal_cdf(&self, param: usize, x: f64) -> Option<f64> {
+        Some(
+            self.components
+                .iter()
+                .map(|&(weight, ref mean, sigma)| weight * normal_cdf((x - mean[param]) / sigma))
+                .sum(),
+        )
+    }
+}
+
+/// Isotropic Gaussian likelihood centred on the origin with a uniform prior on a box
+///
+/// The likelihood has standard deviation `sigma` in each parameter and is not normalised, and
+/// the prior is uniform on `[-half_width, half_width]` in each parameter. The posterior is a
+/// truncated Gaussian and the evidence is known in closed form, which makes this the standard
+/// check for evidence and information estimates.
+#[derive(Debug, Clone)]
+pub struct BoxedGaussian {
+    /// The number of parameters
+    pub dim: usize,
+
+    /// The standard deviation of the likelihood
+    pub sigma: f64,
+
+    /// Half the width of the prior box
+    pub half_width: f64,
+}
+
+impl BoxedGaussian {
+    /// The natural log of the evidence, the likelihood integrated over the prior
+    pub fn ln_evidence(&self) -> f64 {
+        let mass = 2.0 * normal_cdf(self.half_width / self.sigma) - 1.0;
+        self.dim as f64 * ((2.0 * PI).sqrt() * self.sigma * mass / (2.0 * self.half_width)).ln()
+    }
+}
+
+impl Default for BoxedGaussian {
+    /// A unit Gaussian in two parameters with a prior on `[-10, 10]`
+    fn default() -> Self {
+        BoxedGaussian {
+            dim: 2,
+            sigma: 1.0,
+            half_width: 10.0,
+        }
+    }
+}
+
+impl Prob for BoxedGaussian {
+    fn lnlike(&self, params: &Guess) -> f64 {
+        -0.5 * params
+            .values
+            .iter()
+            .map(|x| (x / self.sigma).powi(2))
+            .sum::<f64>()
+    }
+
+    fn lnprior(&self, params: &Guess) -> f64 {
+        if params.values.iter().all(|x| x.abs() < self.half_width) {
+            -(params.values.len() as f64) * (2.0 * self.half_width).ln()
+        } else {
+            -f64::INFINITY
+        }
+    }
+}
+
+impl Target for BoxedGaussian {
+    fn dim(&self) -> usize {
+        self.dim
+    }
+
+    fn mean(&self) -> Vec<f64> {
+        vec![0.0; self.dim]
+    }
+
+    fn covariance(&self) -> Vec<Vec<f64>> {
+        // Variance of a normal truncated to [-a, a] in units of sigma
+        let a = self.half_width / self.sigma;
+        let density = (-0.5 * a * a).exp() / (2.0 * PI).sqrt();
+        let var = self.sigma * self.sigma
+            * (1.0 - 2.0 * a * density / (2.0 * normal_cdf(a) - 1.0));
+        (0..self.dim)
+            .map(|i| (0..self.dim).map(|j| if i == j { var } else { 0.0 }).collect())
+            .collect()
+    }
+
+    fn marginal_cdf(&self, _param: usize, x: f64) -> Option<f64> {
+        let x = x.max(-self.half_width).min(self.half_width);
+        let lower = normal_cdf(-self.half_width / self.sigma);
+        Some((normal_cdf(x / self.sigma) - lower) / (1.0 - 2.0 * lower))
+    }
+}
+
+/// Samples drawn by [`run_move`](fn.run_move.html)
+#[derive(Debug, Clone)]
+pub struct TestRun {
+    /// The samples, after discarding the first half of the run
+    pub samples: Vec<Guess>,
+
+    /// The effective number of independent samples, from the integrated autocorrelation time
+    pub neff: f64,
+}
+
+/// Sample `target` using only the move `m`
+///
+/// The walkers start from a Gaussian with the target's mean and covariance, the sampler is
+/// seeded with `seed` and run for `niterations` iterations, and the first half of the run is
+/// discarded.
+///
+/// Errors are handled by returning a [`Result`](../errors/type.Result.html) which contains any
+/// error from creating or running the sampler.
+pub fn run_move<P: Target>(
+    target: &P,
+    m: Box<dyn Move>,
+    nwalkers: usize,
+    niterations: usize,
+    seed: &[usize],
+) -> Result<TestRun> {
+    let p0 = initial_ensemble(target, nwalkers, seed)?;
+    let mut sampler = EnsembleSampler::new(nwalkers, target.dim(), target)?;
+    sampler.seed(seed);
+    sampler.set_moves(vec![(m, 1.0)])?;
+    sampler.run_mcmc(&p0, niterations)?;
+
+    let chain = sampler
+        .chain()
+        .ok_or_else(|| EmceeError::from("the chain was not stored"))?;
+    let (_, production) = chain.split_at(chain.niterations / 2);
+    let neff = production.niterations as f64 * nwalkers as f64 / max_integrated_time(&production);
+    let samples = production.flatchain();
+    Ok(TestRun { samples, neff })
+}
+
+/// Draw `nwalkers` starting positions from a Gaussian with the target's mean and covariance
+pub(crate) fn initial_ensemble<P: Target>(
+    target: &P,
+    nwalkers: usize,
+    seed: &[usize],
+) -> Result<Vec<Guess>> {
+    let mean = target.mean();
+    let l = linalg::cholesky(&target.covariance()).ok_or_else(|| {
+        EmceeError::InvalidInputs("the target covariance must be positive definite".into())
+    })?;
+
+    let mut rng = StdRng::from_seed(seed);
+    let normal = Normal::new(0.0, 1.0);
+    Ok((0..nwalkers)
+        .map(|_| {
+            let z: Vec<f64> = (0..mean.len()).map(|_| normal.ind_sample(&mut rng)).collect();
+            Guess {
+                values: linalg::lower_mul(&l, &z)
+                    .iter()
+                    .zip(&mean)
+                    .map(|(x, mu)| x + mu)
+                    .collect(),
+            }
+        })
+        .collect())
+}
+
+/// The largest integrated autocorrelation time of any parameter, and at least 1
+pub(crate) fn max_integrated_time(chain: &Chain) -> f64 {
+    chain
+        .integrated_time(5.0)
+        .iter()
+        .cloned()
+        .fold(1f64, f64::max)
+}
+
+/// Check the sample means and variances against the target
+///
+/// Each mean must be within `nsigma` standard errors of the truth, and each variance within
+/// `nsigma` times the standard error of a Gaussian variance estimate, using the effective number
+/// of samples. Returns a description of the first failure.
+pub fn check_moments<P: Target>(
+    run: &TestRun,
+    target: &P,
+    nsigma: f64,
+) -> ::std::result::Result<(), String> {
+    let n = run.samples.len() as f64;
+    let mean = target.mean();
+    let cov = target.covariance();
+    for param in 0..target.dim() {
+        let sample_mean = run.samples.iter().map(|guess| guess[param]).sum::<f64>() / n;
+        let sample_var = run.samples
+            .iter()
+            .map(|guess| (guess[param] - sample_mean).powi(2))
+            .sum::<f64>() / (n - 1.0);
+        let var = cov[param][param];
+
+        let mean_error = (var / run.neff).sqrt();
+        if (sample_mean - mean[param]).abs() > nsigma * mean_error {
+            return Err(format!(
+                "mean of parameter {} is {}, expected {} +/- {}",
+                param, sample_mean, mean[param], mean_error
+            ));
+        }
+
+        let var_error = var * (2.0 / run.neff).sqrt();
+        if (sample_var - var).abs() > nsigma * var_error {
+            return Err(format!(
+                "variance of parameter {} is {}, expected {} +/- {}",
+                param, sample_var, var, var_error
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// Panic unless [`check_moments`](fn.check_moments.html) passes
+pub fn assert_moments<P: Target>(run: &TestRun, target: &P, nsigma: f64) {
+    if let Err(msg) = check_moments(run, target, nsigma) {
+        panic!("{}", msg);
+    }
+}
+
+/// Chi-square test of each marginal distribution with a known CDF against the target
+///
+/// The samples of each parameter are binned into `nbins` equal-width bins spanning their range,
+/// with the outer bins extended to infinity, and the counts are compared with the expected
+/// counts from the marginal CDF. The counts are scaled to the effective number of samples, and
+/// the statistic must be below `dof + nsigma * sqrt(2 dof)`. Returns a description of the first
+/// failure.
+pub fn check_marginals<P: Target>(
+    run: &TestRun,
+    target: &P,
+    nbins: usize,
+    nsigma: f64,
+) -> ::std::result::Result<(), String> {
+    assert!(nbins > 1, "at least two bins are required");
+    let n = run.samples.len() as f64;
+    let weight = run.neff / n;
+
+    for param in 0..target.dim() {
+        if target.marginal_cdf(param, 0.0).is_none() {
+            continue;
+        }
+
+        let values: Vec<f64> = run.samples.iter().map(|guess| guess[param]).collect();
+        let min = values.iter().cloned().fold(f64::INFINITY, f64::min);
+        let max = values.iter().cloned().fold(-f64::INFINITY, f64::max);
+        let width = (max - min) / nbins as f64;
+
+        let mut observed = vec![0f64; nbins];
+        for value in &values {
+            let bin = (((value - min) / width) as usize).min(nbins - 1);
+            observed[bin] += 1.0;
+        }
+
+        let cdf = |x: f64| target.marginal_cdf(param, x).unwrap_or(0.0);
+        let mut chi2 = 0f64;
+        for (bin, count) in observed.iter().enumerate() {
+            let lower = if bin == 0 { 0.0 } else { cdf(min + bin as f64 * width) };
+            let upper = if bin == nbins - 1 {
+                1.0
+            } else {
+                cdf(min + (bin + 1) as f64 * width)
+            };
+            let expected = (upper - lower) * run.neff;
+            if expected > 0.0 {
+                chi2 += (count * weight - expected).powi(2) / expected;
+            }
+        }
+
+        let dof = (nbins - 1) as f64;
+        let limit = dof + nsigma * (2.0 * dof).sqrt();
+        if chi2 > limit {
+            return Err(format!(
+                "marginal distribution of parameter {} has chi-square {} for {} degrees of \
+                 freedom, limit {}",
+                param, chi2, dof, limit
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// Panic unless [`check_marginals`](fn.check_marginals.html) passes
+pub fn assert_marginals<P: Target>(run: &TestRun, target: &P, nbins: usize, nsigma: f64) {
+    if let Err(msg) = check_marginals(run, target, nbins, nsigma) {
+        panic!("{}", msg);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use moves::{Proposal, StretchMove};
+    use rand::Rng;
+
+    /// A stretch move which forgets its acceptance factor
+    struct BrokenStretch(StretchMove);
+
+    impl Move for BrokenStretch {
+        fn name(&self) -> &str {
+            "broken"
+        }
+
+        fn propose(&mut self, s: &[Guess], c: &[Guess], rng: &mut dyn Rng) -> Proposal {
+            let mut proposal = self.0.propose(s, c, rng);
+            for factor in &mut proposal.factors {
+                *factor = 0.0;
+            }
+            proposal
+        }
+    }
+
+    fn gaussian(dim: usize) -> CorrelatedGaussian {
+        let cov = (0..dim)
+            .map(|i| {
+                (0..dim)
+                    .map(|j| if i == j { 1.0 + i as f64 } else { 0.5 })
+                    .collect()
+            })
+            .collect();
+        CorrelatedGaussian::new((0..dim).map(|i| i as f64).collect(), cov).unwrap()
+    }
+
+    #[test]
+    fn test_target_moments() {
+        let target = Rosenbrock::default();
+        let cov = target.covariance();
+        assert_eq!(target.mean(), vec![1.0, 11.0]);
+        assert_approx_eq!(cov[0][1], 20.0);
+
+        let mixture =
+            GaussianMixture::new(vec![(1.0, vec![-1.0, 0.0], 1.0), (3.0, vec![1.0, 0.0], 1.0)])
+                .unwrap();
+        assert_approx_eq!(mixture.mean()[0], 0.5);
+        // 1 + E[mu^2] - E[mu]^2
+        assert_approx_eq!(mixture.covariance()[0][0], 1.75);
+        assert_approx_eq!(mixture.marginal_cdf(1, 0.0).unwrap(), 0.5, 1e-7);
+
+        let funnel = Funnel::default();
+        assert_eq!(funnel.mean(), vec![0.0; 10]);
+        assert_approx_eq!(funnel.covariance()[0][0], 9.0);
+        assert_approx_eq!(funnel.covariance()[3][3], 4.5f64.exp());
+        assert_eq!(funnel.covariance()[0][3], 0.0);
+        assert_approx_eq!(funnel.marginal_cdf(0, 0.0).unwrap(), 0.5, 1e-7);
+        assert_eq!(funnel.marginal_cdf(1, 0.0), None);
+
+        let boxed = BoxedGaussian {
+            dim: 3,
+            sigma: 2.0,
+            half_width: 2.0,
+        };
+        assert_eq!(boxed.mean(), vec![0.0; 3]);
+        // Truncated at one standard deviation
+        assert_approx_eq!(boxed.covariance()[1][1], 4.0 * 0.2911, 1e-3);
+        assert_eq!(boxed.covariance()[0][1], 0.0);
+        assert_approx_eq!(boxed.marginal_cdf(0, -3.0).unwrap(), 0.0);
+        assert_approx_eq!(boxed.marginal_cdf(0, 0.0).unwrap(), 0.5, 1e-7);
+        assert_approx_eq!(boxed.marginal_cdf(0, 3.0).unwrap(), 1.0);
+        assert_approx_eq!(
+            BoxedGaussian::default().ln_evidence(),
+            (2.0 * PI).ln() - 2.0 * 20f64.ln(),
+            1e-10
+        );
+        assert_eq!(boxed.lnprior(&Guess::new(&[0.0, 2.5, 0.0])), -f64::INFINITY);
+
+        assert!(GaussianMixture::new(Vec::new()).is_err());
+        let indefinite = vec![vec![1.0, 2.0], vec![2.0, 1.0]];
+        assert!(CorrelatedGaussian::new(vec![0.0, 0.0], indefinite).is_err());
+    }
+
+    #[test]
+    fn test_stretch_move_passes() {
+        let target = gaussian(3);
+        let run = run_move(&target, Box::new(StretchMove::default()), 16, 4000, &[1]).unwrap();
+        assert!(run.neff > 100.0, "neff = {}", run.neff);
+        assert!(run.neff < run.samples.len() as f64);
+        assert_moments(&run, &target, 4.0);
+        assert_marginals(&run, &target, 10, 4.0);
+
+        let mixture =
+            GaussianMixture::new(vec![(1.0, vec![-1.0, 0.0], 1.0), (1.0, vec![1.0, 0.0], 1.0)])
+                .unwrap();
+        let run = run_move(&mixture, Box::new(StretchMove::default()), 16, 4000, &[2]).unwrap();
+        assert_moments(&run, &mixture, 4.0);
+        assert_marginals(&run, &mixture, 10, 4.0);
+    }
+
+    #[test]
+    fn test_broken_move_fails() {
+        let target = gaussian(6);
+        let run = run_move(
+            &target,
+            Box::new(BrokenStretch(StretchMove::default())),
+            16,
+            4000,
+            &[3],
+        ).unwrap();
+        assert!(check_moments(&run, &target, 4.0).is_err());
+    }
+}
@@ -0,0 +1,485 @@
+//! Sampling bounded parameters in unconstrained coordinates
+
+use std::time::Instant;
+
+use errors::{EmceeError, Result};
+use guess::Guess;
+use prob::Prob;
+use Pool;
+
+/// A one-to-one map from the real line onto the support of a parameter
+///
+/// The sampler works with the unconstrained value `y`, and the model is evaluated at the
+/// physical value `x = to_physical(y)`. See [`Transformed`](struct.Transformed.html).
+pub trait Transform: Send + Sync {
+    /// The physical value of the unconstrained value `y`
+    fn to_physical(&self, y: f64) -> f64;
+
+    /// The unconstrained value of the physical value `x`, or NaN outside the support
+    fn to_unconstrained(&self, x: f64) -> f64;
+
+    /// The natural logarithm of `|dx / dy|` at the unconstrained value `y`
+    fn ln_jacobian(&self, y: f64) -> f64;
+}
+
+/// Maps the real line onto `(lower, inf)`, with `x = lower + exp(y)`
+///
+/// The default has a lower bound of zero, for positive parameters such as scales and rates.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct LogTransform {
+    lower: f64,
+}
+
+impl LogTransform {
+    /// A transform onto the values above `lower`
+    ///
+    /// Errors are handled by returning a [`Result`](errors/type.Result.html) which contains
+    /// [`EmceeError::InvalidInputs`](errors/enum.EmceeError.html) if `lower` is not finite.
+    pub fn new(lower: f64) -> Result<LogTransform> {
+        if !lower.is_finite() {
+            return Err(EmceeError::InvalidInputs(format!(
+                "a log transform needs a finite lower bound, not {}",
+                lower
+            )));
+        }
+        Ok(LogTransform { lower })
+    }
+}
+
+impl Transform for LogTransform {
+    fn to_physical(&self, y: f64) -> f64 {
+        self.lower + y.exp()
+    }
+
+    fn to_unconstrained(&self, x: f64) -> f64 {
+        if x > self.lower {
+            (x - self.lower).ln()
+        } else {
+            f64::NAN
+        }
+    }
+
+    fn ln_jacobian(&self, y: f64) -> f64 {
+        y
+    }
+}
+
+/// Maps the real line onto `(lo, hi)` with the logistic function,
+/// `x = lo + (hi - lo) / (1 + exp(-y))`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LogitTransform {
+    lo: f64,
+    hi: f64,
+}
+
+impl LogitTransform {
+    /// A transform onto the values between `lo` and `hi`
+    ///
+    /// Errors are handled by returning a [`Result`](errors/type.Result.html) which contains
+    /// [`EmceeError::InvalidInputs`](errors/enum.EmceeError.html) if either bound is not
+    /// finite or `lo` is not below `hi`.
+    pub fn new(lo: f64, hi: f64) -> Result<LogitTransform> {
+        if !(lo.is_finite() && hi.is_finite() && lo < hi) {
+            return Err(EmceeError::InvalidInputs(format!(
+                "a logit transform needs finite bounds with lo < hi, not [{}, {}]",
+                lo, hi
+            )));
+        }
+        Ok(LogitTransform { lo, hi })
+    }
+}
+
+impl Transform for LogitTransform {
+    fn to_physical(&self, y: f64) -> f64 {
+        // The logistic function, without overflow
+        let sigmoid = if y >= 0.0 {
+            1.0 / (1.0 + (-y).exp())
+        } else {
+            y.exp() / (1.0 + y.exp())
+        };
+        self.lo + (self.hi - self.lo) * sigmoid
+    }
+
+    fn to_unconstrained(&self, x: f64) -> f64 {
+        if x > self.lo && x < self.hi {
+            let u = (x - self.lo) / (self.hi - self.lo);
+            u.ln() - (-u).ln_1p()
+        } else {
+            f64::NAN
+        }
+    }
+
+    fn ln_jacobian(&self, y: f64) -> f64 {
+        // ln(hi - lo) + ln(sigmoid(y)) + ln(sigmoid(-y))
+        (self.hi - self.lo).ln() - y.abs() - 2.0 * (-y.abs()).exp().ln_1p()
+    }
+}
+
+/// Sample a model with bounded parameters in unconstrained coordinates
+///
+/// Walkers near a hard bound waste many proposals outside it, and the affine-invariant moves
+/// adapt poorly to a posterior piled up against one. Giving such parameters a
+/// [`Transform`](trait.Transform.html) with [`transform`](#method.transform) makes the sampler
+/// work with an unconstrained value instead, e.g. the log of a scale with a
+/// [`LogTransform`](struct.LogTransform.html), or the logit of a fraction with a
+/// [`LogitTransform`](struct.LogitTransform.html). The model itself is unchanged: it is always
+/// evaluated at the physical parameters, and the log Jacobian of the transforms is added to
+/// the prior, so the sampled distribution is exactly the model's posterior. Approximations,
+/// batching, retries, timeouts and the thread pool of the model are kept, each with the log
+/// Jacobian added.
+///
+/// Start the walkers from [`to_unconstrained`](#method.to_unconstrained) positions. The
+/// sampler stores the chain, and computes its running statistics, at the physical positions,
+/// through [`Prob::stored_position`](trait.Prob.html#method.stored_position) and its inverse
+/// [`Prob::sampled_position`](trait.Prob.html#method.sampled_position), while the positions
+/// of each [`Step`](struct.Step.html) are the sampled ones; convert those with
+/// [`to_physical`](#method.to_physical).
+///
+/// ```rust
+/// # use emcee::{with_data, EnsembleSampler, Guess, LogTransform, LogitTransform, Transformed};
+/// // A scale and a fraction
+/// let model = with_data((), |params: &Guess, _: &()| {
+///     let (scale, fraction) = (params[0], params[1]);
+///     -scale + 3.0 * fraction.ln() + (1.0 - fraction).ln()
+/// });
+/// let mut model = Transformed::new(model);
+/// model.transform(0, LogTransform::default()).unwrap();
+/// model.transform(1, LogitTransform::new(0.0, 1.0).unwrap()).unwrap();
+///
+/// let p0 = model.to_unconstrained(&Guess::new(&[1.0, 0.5])).create_initial_guess(8);
+/// let mut sampler = EnsembleSampler::new(8, 2, &model).unwrap();
+/// sampler.run_mcmc(&p0, 100).unwrap();
+/// assert!(sampler.flatchain().unwrap().iter().all(|guess| guess[0] > 0.0));
+/// ```
+///
+/// # Panics
+///
+/// Evaluating the model panics if a transformed parameter is beyond the end of the position.
+pub struct Transformed<T> {
+    model: T,
+    transforms: Vec<(usize, Box<dyn Transform>)>,
+}
+
+impl<T: Prob> Transformed<T> {
+    /// Wrap a model, initially sampling every parameter as it is
+    pub fn new(model: T) -> Transformed<T> {
+        Transformed {
+            model,
+            transforms: Vec::new(),
+        }
+    }
+
+    /// Sample the parameter at index `param` through `transform`
+    ///
+    /// Errors are handled by returning a [`Result`](errors/type.Result.html) which contains
+    /// [`EmceeError::InvalidInputs`](errors/enum.EmceeError.html) if the parameter already has a
+    /// transform.
+    pub fn transform<U: Transform + 'static>(
+        &mut self,
+        param: usize,
+        transform: U,
+    ) -> Result<&mut Self> {
+        if self.transforms.iter().any(|&(idx, _)| idx == param) {
+            return Err(EmceeError::InvalidInputs(format!(
+                "parameter {} is already transformed",
+                param
+            )));
+        }
+        self.transforms.push((param, Box::new(transform)));
+        Ok(self)
+    }
+
+    /// The wrapped model
+    pub fn model(&self) -> &T {
+        &self.model
+    }
+
+    /// Convert a sampled position to the model's physical parameters
+    pub fn to_physical(&self, params: &Guess) -> Guess {
+        let mut values = params.values.clone();
+        for &(idx, ref transform) in &self.transforms {
+            values[idx] = transform.to_physical(params[idx]);
+        }
+        Guess { values }
+    }
+
+    /// Convert a position in the model's physical parameters to the unconstrained coordinates
+    /// which are sampled, e.g. to build the initial positions of the walkers
+    ///
+    /// Parameters outside the support of their transform are set to NaN.
+    pub fn to_unconstrained(&self, params: &Guess) -> Guess {
+        let mut values = params.values.clone();
+        for &(idx, ref transform) in &self.transforms {
+            values[idx] = transform.to_unconstrained(params[idx]);
+        }
+        Guess { values }
+    }
+
+    /// The log Jacobian of the map from sampled to physical parameters
+    fn ln_jacobian(&self, params: &Guess) -> f64 {
+        self.transforms
+            .iter()
+            .map(|&(idx, ref transform)| transform.ln_jacobian(params[idx]))
+            .sum()
+    }
+
+    /// The physical position and the log Jacobian, or `None` if the Jacobian vanishes so the
+    /// model need not be evaluated
+    fn physical(&self, params: &Guess) -> Option<(Guess, f64)> {
+        let ln_jacobian = self.ln_jacobian(params);
+        if ln_jacobian == -f64::INFINITY {
+            None
+        } else {
+            Some((self.to_physical(params), ln_jacobian))
+        }
+    }
+}
+
+impl<T: Prob> Prob for Transformed<T> {
+    fn lnlike(&self, params: &Guess) -> f64 {
+        self.model.lnlike(&self.to_physical(params))
+    }
+
+    fn lnprior(&self, params: &Guess) -> f64 {
+        let lnprior = self.model.lnprior(&self.to_physical(params));
+        if lnprior == -f64::INFINITY {
+            lnprior
+        } else {
+            lnprior + self.ln_jacobian(params)
+        }
+    }
+
+    fn validate(&self, params: &Guess) -> ::std::result::Result<(), String> {
+        self.model.validate(&self.to_physical(params))
+    }
+
+    fn lnprob(&self, params: &Guess) -> f64 {
+        match self.physical(params) {
+            Some((physical, ln_jacobian)) => self.model.lnprob(&physical) + ln_jacobian,
+            None => -f64::INFINITY,
+        }
+    }
+
+    fn try_lnprob(&self, params: &Guess) -> ::std::result::Result<f64, String> {
+        match self.physical(params) {
+            Some((physical, ln_jacobian)) => {
+                self.model.try_lnprob(&physical).map(|lnprob| lnprob + ln_jacobian)
+            }
+            None => Ok(-f64::INFINITY),
+        }
+    }
+
+    fn lnprob_with_deadline(&self, params: &Guess, deadline: Instant) -> Option<f64> {
+        match self.physical(params) {
+            Some((physical, ln_jacobian)) => self
+                .model
+                .lnprob_with_deadline(&physical, deadline)
+                .map(|lnprob| lnprob + ln_jacobian),
+            None => Some(-f64::INFINITY),
+        }
+    }
+
+    fn lnprob_in_pool(&self, params: &Guess, pool: &mut Pool) -> f64 {
+        match self.physical(params) {
+            Some((physical, ln_jacobian)) => {
+                self.model.lnprob_in_pool(&physical, pool) + ln_jacobian
+            }
+            None => -f64::INFINITY,
+        }
+    }
+
+    fn is_batched(&self) -> bool {
+        self.model.is_batched()
+    }
+
+    fn lnprob_batch(&self, positions: &[Guess], out: &mut [f64]) {
+        let physical: Vec<Guess> = positions
+            .iter()
+            .map(|params| self.to_physical(params))
+            .collect();
+        self.model.lnprob_batch(&physical, out);
+        for (params, out) in positions.iter().zip(out.iter_mut()) {
+            *out += self.ln_jacobian(params);
+        }
+    }
+
+    fn napproximations(&self) -> usize {
+        self.model.napproximations()
+    }
+
+    fn lnprob_approximation(&self, params: &Guess, level: usize) -> f64 {
+        match self.physical(params) {
+            Some((physical, ln_jacobian)) => {
+                self.model.lnprob_approximation(&physical, level) + ln_jacobian
+            }
+            None => -f64::INFINITY,
+        }
+    }
+
+    fn stored_position(&self, params: &Guess) -> Option<Guess> {
+        let physical = self.to_physical(params);
+        Some(self.model.stored_position(&physical).unwrap_or(physical))
+    }
+
+    fn sampled_position(&self, stored: &Guess) -> Option<Guess> {
+        let physical = self.model.sampled_position(stored);
+        Some(self.to_unconstrained(physical.as_ref().unwrap_or(stored)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use {with_data, Batched, BatchedProb, Cascade, EnsembleSampler, SteppingStone};
+
+    #[test]
+    fn test_transforms() {
+        let log = LogTransform::new(1.0).unwrap();
+        let logit = LogitTransform::new(-1.0, 3.0).unwrap();
+        for &y in &[-5.0, -2.0, 0.0, 0.7, 5.0] {
+            assert_approx_eq!(log.to_unconstrained(log.to_physical(y)), y, 1e-9);
+            assert_approx_eq!(logit.to_unconstrained(logit.to_physical(y)), y, 1e-9);
+
+            // Against a central difference of the physical value
+            let h = 1e-6;
+            let slope = (logit.to_physical(y + h) - logit.to_physical(y - h)) / (2.0 * h);
+            assert_approx_eq!(logit.ln_jacobian(y), slope.ln(), 1e-6);
+        }
+        assert_approx_eq!(logit.to_physical(0.0), 1.0);
+        assert!(logit.ln_jacobian(1e3).is_finite());
+        assert!(log.to_unconstrained(0.5).is_nan());
+        assert!(logit.to_unconstrained(3.0).is_nan());
+        assert!(LogTransform::new(f64::INFINITY).is_err());
+        assert!(LogitTransform::new(1.0, 1.0).is_err());
+
+        // Gamma(2, 1) on the scale, Beta(4, 2) on the fraction
+        let model = with_data((), |params: &Guess, _: &()| {
+            let (scale, fraction) = (params[0], params[1]);
+            if scale <= 0.0 || fraction <= 0.0 || fraction >= 1.0 {
+                return -f64::INFINITY;
+            }
+            scale.ln() - scale + 3.0 * fraction.ln() + (1.0 - fraction).ln()
+        });
+        let mut model = Transformed::new(model);
+        model.transform(0, LogTransform::default()).unwrap();
+        model.transform(1, LogitTransform::new(0.0, 1.0).unwrap()).unwrap();
+        assert!(model.transform(1, LogTransform::default()).is_err());
+
+        let p0 = model.to_unconstrained(&Guess::new(&[1.5, 0.6]));
+        assert_approx_eq!(model.to_physical(&p0)[1], 0.6);
+        let mut sampler = EnsembleSampler::new(16, 2, &model).unwrap();
+        sampler.seed(&[7]);
+        sampler.run_mcmc(&p0.create_initial_guess(16), 2000).unwrap();
+
+        // The chain is stored in the physical parameters, and has their moments
+        let flatchain = sampler.flatchain().unwrap();
+        assert!(flatchain
+            .iter()
+            .all(|guess| guess[0] > 0.0 && guess[1] > 0.0 && guess[1] < 1.0));
+        let n = flatchain.len() as f64;
+        let mean_scale = flatchain.iter().map(|guess| guess[0]).sum::<f64>() / n;
+        let mean_fraction = flatchain.iter().map(|guess| guess[1]).sum::<f64>() / n;
+        assert_approx_eq!(mean_scale, 2.0, 0.15);
+        assert_approx_eq!(mean_fraction, 2.0 / 3.0, 0.02);
+    }
+
+    struct Square;
+
+    impl BatchedProb for Square {
+        fn lnprob_batch(&self, positions: &[Guess], out: &mut [f64]) {
+            for (params, out) in positions.iter().zip(out.iter_mut()) {
+                *out = -params[0] * params[0];
+            }
+        }
+    }
+
+    #[test]
+    fn test_forwarding() {
+        let y = Guess::new(&[0.5]);
+        let x = 0.5f64.exp();
+
+        let coarse = with_data((), |params: &Guess, _: &()| -params[0]);
+        let full = with_data((), |params: &Guess, _: &()| -2.0 * params[0]);
+        let cascade = Cascade::new(vec![Box::new(coarse), Box::new(full)]).unwrap();
+        let mut model = Transformed::new(cascade);
+        model.transform(0, LogTransform::default()).unwrap();
+        assert_eq!(model.napproximations(), 1);
+        assert_approx_eq!(model.lnprob_approximation(&y, 0), -x + 0.5);
+        assert_approx_eq!(model.lnprob(&y), -2.0 * x + 0.5);
+        assert_approx_eq!(model.try_lnprob(&y).unwrap(), -2.0 * x + 0.5);
+        let stored = model.stored_position(&y).unwrap();
+        assert_approx_eq!(stored[0], x);
+        assert_approx_eq!(model.sampled_position(&stored).unwrap()[0], 0.5);
+
+        let mut model = Transformed::new(Batched::new(Square));
+        model.transform(0, LogTransform::default()).unwrap();
+        assert!(model.is_batched());
+        let mut out = [0.0; 2];
+        model.lnprob_batch(&[y.clone(), Guess::new(&[0.0])], &mut out);
+        assert_approx_eq!(out[0], -x * x + 0.5);
+        assert_approx_eq!(out[1], -1.0);
+        assert_approx_eq!(model.lnprob(&y), out[0]);
+    }
+
+    /// Exponential prior on a scale, with a Gaussian likelihood centred on 1
+    struct Scale;
+
+    impl Prob for Scale {
+        fn lnlike(&self, params: &Guess) -> f64 {
+            -2.0 * (params[0] - 1.0).powi(2)
+        }
+
+        fn lnprior(&self, params: &Guess) -> f64 {
+            if params[0] > 0.0 {
+                -params[0]
+            } else {
+                -f64::INFINITY
+            }
+        }
+    }
+
+    #[test]
+    fn test_stored_positions() {
+        let mut model = Transformed::new(Scale);
+        model.transform(0, LogTransform::default()).unwrap();
+        let p0: Vec<Guess> = (0..8)
+            .map(|i| model.to_unconstrained(&Guess::new(&[0.5 + 0.1 * i as f64])))
+            .collect();
+
+        // The blocks of a chunked run hold the same physical positions as the stored chain
+        let mut sampler = EnsembleSampler::new(8, 1, &model).unwrap();
+        sampler.seed(&[3]);
+        sampler.run_mcmc(&p0, 20).unwrap();
+        let chain = sampler.chain().unwrap().clone();
+        sampler.reset();
+        let mut blocks = Vec::new();
+        sampler.run_in_chunks(&p0, 20, 8, |block| blocks.push(block)).unwrap();
+        for iteration in 0..20 {
+            for walker in 0..8 {
+                let block = &blocks[iteration / 8];
+                assert_eq!(block.get(0, walker, iteration % 8), chain.get(0, walker, iteration));
+            }
+        }
+
+        // A whitening fitted to the chain is in the sampled coordinates
+        sampler.set_whitening_from_chain(&chain).unwrap();
+        let samples = chain.flatchain();
+        let mean = samples.iter().map(|guess| guess[0].ln()).sum::<f64>() / samples.len() as f64;
+        assert_approx_eq!(sampler.whitening().unwrap().0[0], mean, 1e-10);
+
+        // The rungs of a stepping-stone run are stored in the physical parameters
+        let mut stepping_stone = SteppingStone::new(8, 1, &model).unwrap();
+        stepping_stone.seed(&[4]);
+        stepping_stone.nburn = 20;
+        stepping_stone.nsteps = 20;
+        stepping_stone.store_chains = true;
+        let evidence = stepping_stone.run(&p0, &[0.0, 0.5, 1.0]).unwrap();
+        let chains = evidence.chains.unwrap();
+        for k in 0..3 {
+            let samples = chains.chain(k).flatchain();
+            for (guess, value) in samples.iter().zip(chains.lnlike(k)) {
+                assert_approx_eq!(*value, Scale.lnlike(guess), 1e-10);
+            }
+        }
+    }
+}
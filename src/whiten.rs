@@ -0,0 +1,239 @@
+//! Proposals in parameter space whitened by a posterior covariance
+
+use errors::{EmceeError, Result};
+use guess::Guess;
+use linalg::{self, Matrix};
+use {Chain, EnsembleSampler, Prob, Step};
+
+/// The affine map `z = L^-1 (x - mean)`, with `L L^T` the covariance, under which the moves
+/// operate
+#[derive(Debug, Clone)]
+pub(crate) struct Whitening {
+    mean: Vec<f64>,
+    covariance: Matrix,
+    l: Matrix,
+}
+
+impl Whitening {
+    /// Map parameters to the whitened space
+    pub(crate) fn whiten(&self, values: &[f64]) -> Vec<f64> {
+        let delta: Vec<f64> = values.iter().zip(&self.mean).map(|(x, m)| x - m).collect();
+        linalg::lower_solve(&self.l, &delta)
+    }
+
+    /// Map whitened values back to parameters
+    pub(crate) fn unwhiten(&self, values: &[f64]) -> Vec<f64> {
+        linalg::lower_mul(&self.l, values)
+            .iter()
+            .zip(&self.mean)
+            .map(|(z, m)| z + m)
+            .collect()
+    }
+}
+
+impl<'a, T: Prob + Sync + Send + 'a> EnsembleSampler<'a, T> {
+    /// Let the moves operate on parameters whitened by a mean and covariance, e.g. those of
+    /// the posterior from a pilot run
+    ///
+    /// Every proposal is made in terms of `z = L^-1 (x - mean)`, where `L` is the Cholesky
+    /// factor of `covariance`, so a posterior close to the given one looks like a unit
+    /// Gaussian to the moves. The affine-invariant moves are unaffected, but moves with fixed
+    /// step sizes, or which update subsets of the parameters, mix far better on strongly
+    /// correlated or poorly scaled problems. The map is linear, so no acceptance correction is
+    /// required. The whitening takes the place of [`autoscale`](#structfield.autoscale), and
+    /// is part of the configuration, so it is kept by [`reset`](#method.reset) until
+    /// [`clear_whitening`](#method.clear_whitening).
+    ///
+    /// Errors are handled by returning a [`Result`](errors/type.Result.html) which contains
+    /// [`EmceeError::InvalidInputs`](errors/enum.EmceeError.html) if `mean` and `covariance` do
+    /// not have one entry per parameter, or `covariance` is not positive definite.
+    pub fn set_whitening(&mut self, mean: &[f64], covariance: &[Vec<f64>]) -> Result<&mut Self> {
+        if mean.len() != self.dim
+            || covariance.len() != self.dim
+            || covariance.iter().any(|row| row.len() != self.dim)
+        {
+            return Err(EmceeError::InvalidInputs(format!(
+                "the whitening needs a mean and covariance of dimension {}",
+                self.dim
+            )));
+        }
+        let l = linalg::cholesky(covariance).ok_or_else(|| {
+            EmceeError::InvalidInputs("the covariance is not positive definite".into())
+        })?;
+
+        self.whitening = Some(Whitening {
+            mean: mean.to_vec(),
+            covariance: covariance.to_vec(),
+            l,
+        });
+        Ok(self)
+    }
+
+    /// Whiten the proposals with the mean and covariance of every sample in `chain`
+    ///
+    /// See [`set_whitening`](#method.set_whitening). Discard the burn-in from the chain first,
+    /// e.g. with [`Chain::split_at`](struct.Chain.html#method.split_at). Like the sampler's own
+    /// chain, `chain` holds stored positions, which are mapped back to the sampled coordinates
+    /// with [`Prob::sampled_position`](trait.Prob.html#method.sampled_position).
+    ///
+    /// Errors are handled by returning a [`Result`](errors/type.Result.html) which contains
+    /// [`EmceeError::InvalidInputs`](errors/enum.EmceeError.html) if the chain has the wrong
+    /// number of parameters, or too few independent samples for a positive definite covariance.
+    pub fn set_whitening_from_chain(&mut self, chain: &Chain) -> Result<&mut Self> {
+        if chain.niterations * chain.nwalkers <= chain.nparams {
+            return Err(EmceeError::InvalidInputs(format!(
+                "{} samples cannot determine the covariance of {} parameters",
+                chain.niterations * chain.nwalkers,
+                chain.nparams
+            )));
+        }
+        // The moves operate on the sampled coordinates, while the chain holds stored positions
+        let lnprob = self.lnprob;
+        let samples: Vec<Guess> = chain
+            .flatchain()
+            .into_iter()
+            .map(|guess| lnprob.sampled_position(&guess).unwrap_or(guess))
+            .collect();
+        let (mean, covariance) = linalg::mean_covariance(&samples);
+        self.set_whitening(&mean, &covariance)
+    }
+
+    /// Stop whitening the proposals
+    pub fn clear_whitening(&mut self) -> &mut Self {
+        self.whitening = None;
+        self
+    }
+
+    /// Return the mean and covariance which whiten the proposals, if set
+    pub fn whitening(&self) -> Option<(&[f64], &[Vec<f64>])> {
+        self.whitening
+            .as_ref()
+            .map(|w| (w.mean.as_slice(), w.covariance.as_slice()))
+    }
+
+    /// Run a pilot of `npilot` iterations, then `niterations` more with the proposals whitened
+    /// by the posterior covariance of the pilot
+    ///
+    /// The mean and covariance are taken from the second half of the stored pilot chain, so
+    /// its burn-in is left out. The sampler is then [`reset`](#method.reset), keeping the
+    /// whitening, and continues from the final positions of the pilot without evaluating them
+    /// again, so the chain and statistics afterwards are those of the whitened run alone.
+    ///
+    /// Errors are handled by returning a [`Result`](errors/type.Result.html) which contains
+    /// [`EmceeError::InvalidInputs`](errors/enum.EmceeError.html) if the chain is not stored, or
+    /// the pilot does not store enough iterations for the covariance, or any error from either
+    /// run.
+    pub fn run_whitened(
+        &mut self,
+        p0: &[Guess],
+        npilot: usize,
+        niterations: usize,
+    ) -> Result<Step> {
+        if !self.storechain {
+            return Err(EmceeError::InvalidInputs(
+                "the pilot run needs storechain to estimate the covariance".into(),
+            ));
+        }
+
+        let pilot = self.run_mcmc(p0, npilot)?;
+        {
+            let chain = self.chain().expect("the pilot chain is stored");
+            let (_, tail) = chain.split_at(chain.niterations / 2);
+            self.set_whitening_from_chain(&tail)?;
+        }
+
+        self.reset();
+        let pos = pilot.pos.clone();
+        self.set_initial_state(pilot);
+        self.run_mcmc(&pos, niterations)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use moves::{Move, StretchMove, SubspaceMove};
+    use rand::{SeedableRng, StdRng};
+    use testkit::CorrelatedGaussian;
+
+    #[test]
+    fn test_whitening() {
+        let w = Whitening {
+            mean: vec![1.0, -2.0],
+            covariance: vec![vec![4.0, 1.0], vec![1.0, 2.0]],
+            l: linalg::cholesky(&[vec![4.0, 1.0], vec![1.0, 2.0]]).unwrap(),
+        };
+        let z = w.whiten(&[3.0, 0.5]);
+        let x = w.unwhiten(&z);
+        assert_approx_eq!(x[0], 3.0);
+        assert_approx_eq!(x[1], 0.5);
+        assert_eq!(w.whiten(&[1.0, -2.0]), vec![0.0, 0.0]);
+    }
+
+    #[test]
+    fn test_run_whitened() {
+        // Strongly correlated and badly scaled: moves along one parameter at a time only take
+        // small steps along the long axis unless the space is whitened
+        let covariance = vec![vec![1e4, 99.0], vec![99.0, 1.0]];
+        let model = CorrelatedGaussian::new(vec![0.0, 0.0], covariance.clone()).unwrap();
+        let nwalkers = 16;
+        let mut rng = StdRng::from_seed(&[5, 6, 7]);
+        let p0 = Guess::new(&[0.0, 0.0]).create_initial_guess_with_rng(nwalkers, &mut rng);
+
+        let run = |whiten: bool| {
+            let mut sampler = EnsembleSampler::new(nwalkers, 2, &model).unwrap();
+            sampler.seed(&[3]);
+            let moves: Vec<(Box<dyn Move>, f64)> = (0..2)
+                .map(|i| {
+                    let inner = Box::new(StretchMove::default());
+                    (Box::new(SubspaceMove::new(vec![i], inner)) as Box<dyn Move>, 1.0)
+                })
+                .collect();
+            sampler.set_moves(moves).unwrap();
+            assert!(sampler.whitening().is_none());
+            if whiten {
+                sampler.run_whitened(&p0, 1000, 1000).unwrap();
+            } else {
+                sampler.run_mcmc(&p0, 1000).unwrap();
+            }
+            let chain = sampler.chain().unwrap().clone();
+            (sampler.whitening().map(|(_, cov)| cov.to_vec()), chain)
+        };
+
+        let (plain_cov, plain) = run(false);
+        assert!(plain_cov.is_none());
+        let (cov, whitened) = run(true);
+
+        // The follow-up run alone is stored, and the whitening survives the reset
+        assert_eq!(whitened.niterations, 1000);
+        let cov = cov.unwrap();
+        assert!(cov[0][0] > 1e3 && cov[1][1] > 0.1 && cov[0][1] > 0.0);
+
+        let (_, sampled) = linalg::mean_covariance(&whitened.flatchain());
+        assert!(sampled[0][0] > 5e3 && sampled[0][0] < 2e4, "variance {}", sampled[0][0]);
+        let tau = |chain: &Chain| chain.integrated_time(5.0)[0];
+        assert!(tau(&whitened) < 0.5 * tau(&plain));
+    }
+
+    #[test]
+    fn test_set_whitening() {
+        let model = CorrelatedGaussian::new(vec![0.0, 0.0], vec![vec![1.0, 0.0], vec![0.0, 1.0]])
+            .unwrap();
+        let mut sampler = EnsembleSampler::new(8, 2, &model).unwrap();
+        let covariance = vec![vec![2.0, 0.5], vec![0.5, 1.0]];
+        sampler.set_whitening(&[1.0, 2.0], &covariance).unwrap();
+        sampler.reset();
+        assert_eq!(sampler.whitening().unwrap().0, &[1.0, 2.0]);
+
+        assert!(sampler.set_whitening(&[0.0], &covariance).is_err());
+        let singular = vec![vec![1.0, 1.0], vec![1.0, 1.0]];
+        assert!(sampler.set_whitening(&[0.0, 0.0], &singular).is_err());
+        assert!(sampler.set_whitening_from_chain(&Chain::new(2, 1, 2)).is_err());
+        sampler.clear_whitening();
+        assert!(sampler.whitening().is_none());
+
+        let p0 = Guess::new(&[0.0, 0.0]).create_initial_guess(8);
+        sampler.storechain = false;
+        assert!(sampler.run_whitened(&p0, 10, 10).is_err());
+    }
+}